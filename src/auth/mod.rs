@@ -0,0 +1,12 @@
+//! Password authentication.
+//!
+//! Passwords are stored as [`scram::ScramSecret`]s. PostgreSQL clients log in with a SCRAM-SHA-256 exchange, MySQL
+//! clients send the password in clear text over TLS, where it is checked against the same secret.
+
+pub mod crypto;
+pub mod scram;
+
+/// A password for the superuser when none is configured.
+pub fn generate_password() -> String {
+    crypto::base64_encode(&crypto::random_bytes::<18>())
+}
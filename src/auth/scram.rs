@@ -0,0 +1,201 @@
+//! Server side of SCRAM-SHA-256 (RFC 5802, RFC 7677).
+//!
+//! The server only keeps a [`ScramSecret`] derived from the password. The client proves it knows the password
+//! without sending it, and the server proves it knows the secret in return. Passwords are used as given, without
+//! the SASLprep normalization.
+
+use crate::auth::crypto::{
+    base64_decode, base64_encode, constant_time_eq, hmac_sha256, pbkdf2_sha256, random_bytes, sha256,
+};
+use std::fmt::{Display, Formatter};
+
+pub const MECHANISM: &str = "SCRAM-SHA-256";
+
+pub const DEFAULT_ITERATIONS: u32 = 4096;
+
+/// What the server stores instead of the password.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScramSecret {
+    pub iterations: u32,
+    pub salt: Vec<u8>,
+    pub stored_key: [u8; 32],
+    pub server_key: [u8; 32],
+}
+
+impl ScramSecret {
+    /// Derives the secret of `password` with a new random salt.
+    pub fn new(password: &str) -> Self {
+        ScramSecret::with_salt(password, &random_bytes::<16>(), DEFAULT_ITERATIONS)
+    }
+
+    pub fn with_salt(password: &str, salt: &[u8], iterations: u32) -> Self {
+        let salted = pbkdf2_sha256(password.as_bytes(), salt, iterations);
+        ScramSecret {
+            iterations,
+            salt: salt.to_vec(),
+            stored_key: sha256(&hmac_sha256(&salted, b"Client Key")),
+            server_key: hmac_sha256(&salted, b"Server Key"),
+        }
+    }
+
+    /// Checks a password sent in clear text.
+    pub fn verify(&self, password: &str) -> bool {
+        let secret = ScramSecret::with_salt(password, &self.salt, self.iterations);
+        constant_time_eq(&secret.stored_key, &self.stored_key)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScramError {
+    Malformed(String),
+    /// The client does not know the password.
+    InvalidProof,
+}
+
+impl Display for ScramError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ScramError::Malformed(message) => write!(f, "malformed SCRAM message: {message}"),
+            ScramError::InvalidProof => write!(f, "invalid SCRAM proof"),
+        }
+    }
+}
+
+/// State of an exchange between the server-first and the client-final message.
+#[derive(Debug)]
+pub struct ScramServer {
+    secret: ScramSecret,
+    /// Sent back by the client, base64 encoded, in its final message.
+    gs2_header: String,
+    client_first_bare: String,
+    server_first: String,
+    nonce: String,
+}
+
+impl ScramServer {
+    /// Answers the client-first message with the server-first message.
+    ///
+    /// Users without a password or that do not exist get `None` as their secret. The exchange then goes on with a
+    /// made up one and fails at the end, so that clients cannot tell which users exist.
+    pub fn start(secret: Option<&ScramSecret>, client_first: &[u8]) -> Result<(Self, String), ScramError> {
+        let server_nonce = base64_encode(&random_bytes::<18>());
+        let secret = match secret {
+            Some(secret) => secret.clone(),
+            None => ScramSecret::with_salt(&server_nonce, &random_bytes::<16>(), DEFAULT_ITERATIONS),
+        };
+        ScramServer::start_with_nonce(secret, client_first, &server_nonce)
+    }
+
+    fn start_with_nonce(secret: ScramSecret, client_first: &[u8], server_nonce: &str) -> Result<(Self, String), ScramError> {
+        let client_first = text(client_first)?;
+
+        // gs2-header: channel binding flag and authorization identity.
+        let mut parts = client_first.splitn(3, ',');
+        let (Some(binding), Some(authzid), Some(bare)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(malformed("missing GS2 header"));
+        };
+        match binding {
+            "n" | "y" => {}
+            _ if binding.starts_with("p=") => return Err(malformed("channel binding is not supported")),
+            _ => return Err(malformed("invalid channel binding flag")),
+        }
+
+        let client_nonce = attribute(bare, 'r').ok_or_else(|| malformed("missing nonce"))?;
+        let nonce = format!("{client_nonce}{server_nonce}");
+        let server_first = format!("r={nonce},s={0},i={1}", base64_encode(&secret.salt), secret.iterations);
+
+        let server = ScramServer {
+            secret,
+            gs2_header: format!("{binding},{authzid},"),
+            client_first_bare: bare.to_string(),
+            server_first: server_first.clone(),
+            nonce,
+        };
+        Ok((server, server_first))
+    }
+
+    /// Checks the proof of the client-final message and answers with the server-final message.
+    pub fn finish(&self, client_final: &[u8]) -> Result<String, ScramError> {
+        let client_final = text(client_final)?;
+        let Some((without_proof, proof)) = client_final.rsplit_once(",p=") else {
+            return Err(malformed("missing proof"));
+        };
+
+        if attribute(without_proof, 'c') != Some(&base64_encode(self.gs2_header.as_bytes())) {
+            return Err(malformed("channel binding does not match"));
+        }
+        if attribute(without_proof, 'r') != Some(&self.nonce) {
+            return Err(malformed("nonce does not match"));
+        }
+        let proof = base64_decode(proof)
+            .filter(|proof| proof.len() == 32)
+            .ok_or_else(|| malformed("invalid proof"))?;
+
+        let auth_message = format!("{0},{1},{without_proof}", self.client_first_bare, self.server_first);
+        let client_signature = hmac_sha256(&self.secret.stored_key, auth_message.as_bytes());
+        let client_key: Vec<u8> = proof.iter().zip(client_signature).map(|(p, s)| p ^ s).collect();
+        if !constant_time_eq(&sha256(&client_key), &self.secret.stored_key) {
+            return Err(ScramError::InvalidProof);
+        }
+
+        let server_signature = hmac_sha256(&self.secret.server_key, auth_message.as_bytes());
+        Ok(format!("v={0}", base64_encode(&server_signature)))
+    }
+}
+
+fn text(message: &[u8]) -> Result<&str, ScramError> {
+    std::str::from_utf8(message).map_err(|_| malformed("not UTF-8"))
+}
+
+/// Value of the `name=value` attribute in a comma separated message.
+fn attribute(message: &str, name: char) -> Option<&str> {
+    message
+        .split(',')
+        .find_map(|attr| attr.strip_prefix(name).and_then(|attr| attr.strip_prefix('=')))
+}
+
+fn malformed(message: &str) -> ScramError {
+    ScramError::Malformed(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_rfc7677_exchange() {
+        let salt = base64_decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap();
+        let secret = ScramSecret::with_salt("pencil", &salt, 4096);
+        assert!(secret.verify("pencil"));
+        assert!(!secret.verify("pen"));
+
+        let (server, server_first) =
+            ScramServer::start_with_nonce(secret, b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO", "%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0")
+                .unwrap();
+        assert_eq!(
+            server_first,
+            "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096"
+        );
+
+        let client_final = "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+                            p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=";
+        assert_eq!(
+            server.finish(client_final.as_bytes()).unwrap(),
+            "v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4="
+        );
+
+        let forged = client_final.replace("p=dHzb", "p=dHzc");
+        assert_eq!(server.finish(forged.as_bytes()), Err(ScramError::InvalidProof));
+    }
+
+    #[test]
+    fn test_unknown_user() {
+        let (server, _) = ScramServer::start(None, b"n,,n=,r=abc").unwrap();
+        let nonce = server.nonce.clone();
+        let client_final = format!("c=biws,r={nonce},p={0}", base64_encode(&[0; 32]));
+        assert_eq!(server.finish(client_final.as_bytes()), Err(ScramError::InvalidProof));
+
+        assert!(ScramServer::start(None, b"p=tls-server-end-point,,n=,r=abc").is_err());
+    }
+}
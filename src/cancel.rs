@@ -0,0 +1,211 @@
+//! Cooperative cancellation of running statements.
+//!
+//! Statements are never interrupted from the outside. The parser and the executor call [`Checkpoint::check`] at
+//! points where stopping is safe and unwind with an error once the session's [`CancelFlag`] was raised or the
+//! statement ran past its deadline.
+//!
+//! Clients cancel through a [`CancelRegistry`]: every session registers its flag under an id and a random secret
+//! which it hands to the client, who presents both on a separate connection to raise it.
+
+use crate::auth::crypto::random_bytes;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const CANCELLED: u8 = 1;
+const TERMINATED: u8 = 2;
+
+/// Raised from another connection to cancel whatever the session is running, or by the server to end it.
+#[derive(Clone, Debug, Default)]
+pub struct CancelFlag(Arc<AtomicU8>);
+
+impl CancelFlag {
+    pub fn new() -> Self {
+        CancelFlag::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.fetch_or(CANCELLED, Ordering::Relaxed);
+    }
+
+    /// Cancels the statement running now and every one after it, for the server to shut down.
+    pub fn terminate(&self) {
+        self.0.fetch_or(TERMINATED, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed) != 0
+    }
+
+    /// Clears a request that arrived while nothing was running, so it does not cancel the next statement.
+    pub fn reset(&self) {
+        self.0.fetch_and(!CANCELLED, Ordering::Relaxed);
+    }
+}
+
+/// Why a statement stopped at a checkpoint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CancelReason {
+    UserRequest,
+    StatementTimeout,
+    Shutdown,
+}
+
+/// Cancellation state of one statement.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    flag: CancelFlag,
+    deadline: Option<Instant>,
+}
+
+impl Checkpoint {
+    /// Starts the clock of a statement that may run for at most `timeout`.
+    pub fn new(flag: CancelFlag, timeout: Option<Duration>) -> Self {
+        Checkpoint {
+            flag,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+        }
+    }
+
+    /// A checkpoint that always passes.
+    pub fn none() -> Self {
+        Checkpoint::new(CancelFlag::new(), None)
+    }
+
+    pub fn check(&self) -> Result<(), CancelReason> {
+        match self.flag.0.load(Ordering::Relaxed) {
+            0 => {}
+            flags if flags & TERMINATED != 0 => return Err(CancelReason::Shutdown),
+            _ => return Err(CancelReason::UserRequest),
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(CancelReason::StatementTimeout),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Cancel flags of the running sessions, by session id.
+#[derive(Debug, Default)]
+pub struct CancelRegistry {
+    sessions: Mutex<Sessions>,
+}
+
+#[derive(Debug, Default)]
+struct Sessions {
+    last_id: u32,
+    keys: HashMap<u32, (u32, CancelFlag)>,
+}
+
+impl CancelRegistry {
+    pub fn new() -> Self {
+        CancelRegistry::default()
+    }
+
+    /// Gives the session an id that no other running session has.
+    pub fn register(&self) -> Registration<'_> {
+        // The secret is all it takes to cancel, it must not be guessed.
+        let secret = u32::from_le_bytes(random_bytes());
+        let flag = CancelFlag::new();
+
+        let mut sessions = self.sessions.lock().unwrap();
+        let mut id = sessions.last_id;
+        loop {
+            id = id.wrapping_add(1);
+            if id != 0 && !sessions.keys.contains_key(&id) {
+                break;
+            }
+        }
+        sessions.last_id = id;
+        sessions.keys.insert(id, (secret, flag.clone()));
+
+        Registration {
+            registry: self,
+            id,
+            secret,
+            flag,
+        }
+    }
+
+    /// Terminates whatever the sessions are running, and will run.
+    pub fn terminate_all(&self) {
+        for (_, flag) in self.sessions.lock().unwrap().keys.values() {
+            flag.terminate();
+        }
+    }
+
+    /// Raises the flag of session `id` when `secret` is its secret, returns whether it did.
+    pub fn cancel(&self, id: u32, secret: u32) -> bool {
+        match self.sessions.lock().unwrap().keys.get(&id) {
+            Some((key, flag)) if *key == secret => {
+                flag.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Entry of a session in the [`CancelRegistry`], removed when dropped.
+#[derive(Debug)]
+pub struct Registration<'a> {
+    registry: &'a CancelRegistry,
+    pub id: u32,
+    pub secret: u32,
+    pub flag: CancelFlag,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.registry.sessions.lock().unwrap().keys.remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_checkpoint() {
+        let flag = CancelFlag::new();
+        let checkpoint = Checkpoint::new(flag.clone(), None);
+        assert_eq!(checkpoint.check(), Ok(()));
+
+        flag.cancel();
+        assert_eq!(checkpoint.check(), Err(CancelReason::UserRequest));
+        flag.reset();
+        assert_eq!(checkpoint.check(), Ok(()));
+
+        let checkpoint = Checkpoint::new(flag.clone(), Some(Duration::ZERO));
+        assert_eq!(checkpoint.check(), Err(CancelReason::StatementTimeout));
+
+        // Unlike requests, shutdown outlasts the statement.
+        flag.cancel();
+        flag.terminate();
+        flag.reset();
+        assert_eq!(checkpoint.check(), Err(CancelReason::Shutdown));
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = CancelRegistry::new();
+        let first = registry.register();
+        let second = registry.register();
+        assert_ne!(first.id, second.id);
+
+        assert!(!registry.cancel(first.id, first.secret.wrapping_add(1)));
+        assert!(!first.flag.is_cancelled());
+        assert!(registry.cancel(first.id, first.secret));
+        assert!(first.flag.is_cancelled());
+        assert!(!second.flag.is_cancelled());
+
+        registry.terminate_all();
+        assert!(second.flag.is_cancelled());
+
+        let (id, secret) = (second.id, second.secret);
+        drop(second);
+        assert!(!registry.cancel(id, secret));
+    }
+}
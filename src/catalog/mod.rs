@@ -0,0 +1,443 @@
+//! Roles and the privileges granted to them.
+//!
+//! A role may log in, in which case it is a user, and may be a member of other roles, whose privileges it then
+//! holds as well. Privileges granted to `PUBLIC` are held by every role. Superusers hold every privilege.
+//!
+//! Tables may have an owner, which alone changes their definition along with its members and the superusers.
+//! Those without one, like the system tables, belong to the superusers.
+
+use crate::auth::scram::ScramSecret;
+use crate::executor::{ExecError, SqlState};
+use crate::parser::ast::{PrivilegeKind, RoleOption};
+use crate::stats::STAT_STATEMENTS_TABLE;
+use crate::storage::lock::LOCKS_TABLE;
+use crate::storage::vacuum::VACUUM_TABLE;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::RwLock;
+
+/// Name of the pseudo-role every role is a member of.
+pub const PUBLIC: &str = "public";
+
+/// Schema objects are created in.
+pub const DEFAULT_SCHEMA: &str = "public";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Role {
+    pub name: String,
+    pub superuser: bool,
+    pub login: bool,
+    pub password: Option<ScramSecret>,
+    /// Roles this one was granted, directly.
+    pub member_of: BTreeSet<String>,
+}
+
+/// What a privilege is granted on.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Object {
+    Table(String),
+    /// A column of a table.
+    Column(String, String),
+    Schema(String),
+}
+
+impl Object {
+    /// Kind of object as worded in error messages.
+    fn kind(&self) -> &'static str {
+        match self {
+            Object::Table(_) => "table",
+            Object::Column(..) => "column",
+            Object::Schema(_) => "schema",
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Object::Table(name) | Object::Schema(name) => name,
+            Object::Column(_, name) => name,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    roles: HashMap<String, Role>,
+    /// Whether the grantee may grant the privilege on, by grantee, object and privilege.
+    grants: BTreeMap<(String, Object, PrivilegeKind), bool>,
+    /// Owners of tables, by table.
+    owners: HashMap<String, String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Catalog {
+    state: RwLock<State>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Catalog::default()
+    }
+
+    /// A catalog with the superuser every other role is created by, and public access to the statistics.
+    pub fn bootstrap(superuser: &str, password: Option<&str>) -> Self {
+        let catalog = Catalog::new();
+        {
+            let mut state = catalog.state.write().unwrap();
+            state.roles.insert(
+                superuser.to_string(),
+                Role {
+                    name: superuser.to_string(),
+                    superuser: true,
+                    login: true,
+                    password: password.map(ScramSecret::new),
+                    member_of: BTreeSet::new(),
+                },
+            );
+            for table in [STAT_STATEMENTS_TABLE, LOCKS_TABLE, VACUUM_TABLE] {
+                state.grants.insert(
+                    (PUBLIC.to_string(), Object::Table(table.to_string()), PrivilegeKind::Select),
+                    false,
+                );
+            }
+            for privilege in [PrivilegeKind::Usage, PrivilegeKind::Create] {
+                state.grants.insert(
+                    (PUBLIC.to_string(), Object::Schema(DEFAULT_SCHEMA.to_string()), privilege),
+                    false,
+                );
+            }
+        }
+        catalog
+    }
+
+    pub fn role(&self, name: &str) -> Option<Role> {
+        self.state.read().unwrap().roles.get(name).cloned()
+    }
+
+    pub fn create_role(&self, name: &str, options: &[RoleOption]) -> Result<(), ExecError> {
+        if name == PUBLIC {
+            return Err(ExecError::new(
+                SqlState::RESERVED_NAME,
+                format!("role name \"{name}\" is reserved"),
+            ));
+        }
+
+        let mut state = self.state.write().unwrap();
+        if state.roles.contains_key(name) {
+            return Err(ExecError::new(
+                SqlState::DUPLICATE_OBJECT,
+                format!("role \"{name}\" already exists"),
+            ));
+        }
+
+        let mut role = Role {
+            name: name.to_string(),
+            superuser: false,
+            login: false,
+            password: None,
+            member_of: BTreeSet::new(),
+        };
+        apply_options(&mut role, options);
+        state.roles.insert(name.to_string(), role);
+
+        Ok(())
+    }
+
+    pub fn alter_role(&self, name: &str, options: &[RoleOption]) -> Result<(), ExecError> {
+        let mut state = self.state.write().unwrap();
+        let role = state.roles.get_mut(name).ok_or_else(|| undefined_role(name))?;
+        apply_options(role, options);
+        Ok(())
+    }
+
+    /// Drops a role along with its privileges and memberships, returns whether it existed.
+    pub fn drop_role(&self, name: &str) -> bool {
+        let mut state = self.state.write().unwrap();
+        if state.roles.remove(name).is_none() {
+            return false;
+        }
+
+        state.grants.retain(|(grantee, _, _), _| grantee != name);
+        state.owners.retain(|_, owner| owner != name);
+        for role in state.roles.values_mut() {
+            role.member_of.remove(name);
+        }
+        true
+    }
+
+    pub fn grant(
+        &self,
+        grantee: &str,
+        object: Object,
+        privilege: PrivilegeKind,
+        grant_option: bool,
+    ) -> Result<(), ExecError> {
+        let mut state = self.state.write().unwrap();
+        state.check_grantee(grantee)?;
+
+        let held = state.grants.entry((grantee.to_string(), object, privilege)).or_default();
+        *held |= grant_option;
+        Ok(())
+    }
+
+    /// Takes the privilege away, or only the right to grant it on when `grant_option` is set.
+    pub fn revoke(
+        &self,
+        grantee: &str,
+        object: Object,
+        privilege: PrivilegeKind,
+        grant_option: bool,
+    ) -> Result<(), ExecError> {
+        let mut state = self.state.write().unwrap();
+        state.check_grantee(grantee)?;
+
+        let key = (grantee.to_string(), object, privilege);
+        if grant_option {
+            if let Some(held) = state.grants.get_mut(&key) {
+                *held = false;
+            }
+        } else {
+            state.grants.remove(&key);
+        }
+        Ok(())
+    }
+
+    /// Makes `member` a member of `role`.
+    pub fn grant_role(&self, role: &str, member: &str) -> Result<(), ExecError> {
+        let mut state = self.state.write().unwrap();
+        if !state.roles.contains_key(role) {
+            return Err(undefined_role(role));
+        }
+        if !state.roles.contains_key(member) {
+            return Err(undefined_role(member));
+        }
+        if state.memberships(role).contains(member) {
+            return Err(ExecError::new(
+                SqlState::INVALID_GRANT_OPERATION,
+                format!("role \"{role}\" is a member of role \"{member}\""),
+            ));
+        }
+
+        state.roles.get_mut(member).unwrap().member_of.insert(role.to_string());
+        Ok(())
+    }
+
+    pub fn revoke_role(&self, role: &str, member: &str) -> Result<(), ExecError> {
+        let mut state = self.state.write().unwrap();
+        if !state.roles.contains_key(role) {
+            return Err(undefined_role(role));
+        }
+        let member = state.roles.get_mut(member).ok_or_else(|| undefined_role(member))?;
+        member.member_of.remove(role);
+        Ok(())
+    }
+
+    pub fn set_owner(&self, table: &str, owner: &str) -> Result<(), ExecError> {
+        let mut state = self.state.write().unwrap();
+        if !state.roles.contains_key(owner) {
+            return Err(undefined_role(owner));
+        }
+        state.owners.insert(table.to_string(), owner.to_string());
+        Ok(())
+    }
+
+    /// Fails unless `user` is a superuser, or the owner of the table or a member of it.
+    pub fn check_owner(&self, user: &str, table: &str) -> Result<(), ExecError> {
+        let state = self.state.read().unwrap();
+        let superuser = state.roles.get(user).is_some_and(|role| role.superuser);
+        let owner = state
+            .owners
+            .get(table)
+            .is_some_and(|owner| state.memberships(user).contains(owner));
+        match superuser || owner {
+            true => Ok(()),
+            false => Err(ExecError::new(
+                SqlState::INSUFFICIENT_PRIVILEGE,
+                format!("must be owner of table {table}"),
+            )),
+        }
+    }
+
+    pub fn is_superuser(&self, name: &str) -> bool {
+        self.state.read().unwrap().roles.get(name).is_some_and(|role| role.superuser)
+    }
+
+    /// Whether `user` holds the privilege on the object, itself, through a role it is a member of or through
+    /// `PUBLIC`.
+    pub fn has_privilege(&self, user: &str, object: &Object, privilege: PrivilegeKind) -> bool {
+        self.held(user, object, privilege).is_some()
+    }
+
+    /// Whether `user` holds the privilege and may grant it on.
+    pub fn has_grant_option(&self, user: &str, object: &Object, privilege: PrivilegeKind) -> bool {
+        self.held(user, object, privilege) == Some(true)
+    }
+
+    fn held(&self, user: &str, object: &Object, privilege: PrivilegeKind) -> Option<bool> {
+        let state = self.state.read().unwrap();
+        if state.roles.get(user).is_some_and(|role| role.superuser) {
+            return Some(true);
+        }
+
+        let mut roles = state.memberships(user);
+        roles.insert(PUBLIC.to_string());
+        roles
+            .iter()
+            .filter_map(|role| state.grants.get(&(role.clone(), object.clone(), privilege)))
+            .copied()
+            .reduce(|a, b| a || b)
+    }
+
+    /// Fails unless `user` holds the privilege on the table, or on all of `columns` when there are any.
+    pub fn check_table(&self, user: &str, table: &str, privilege: PrivilegeKind, columns: &[&str]) -> Result<(), ExecError> {
+        let object = Object::Table(table.to_string());
+        if self.has_privilege(user, &object, privilege) {
+            return Ok(());
+        }
+        let on_columns = !columns.is_empty()
+            && columns
+                .iter()
+                .all(|column| self.has_privilege(user, &Object::Column(table.to_string(), column.to_string()), privilege));
+        match on_columns {
+            true => Ok(()),
+            false => Err(permission_denied(&object)),
+        }
+    }
+
+    /// Fails unless `user` holds the privilege on the object.
+    pub fn check(&self, user: &str, object: &Object, privilege: PrivilegeKind) -> Result<(), ExecError> {
+        match self.has_privilege(user, object, privilege) {
+            true => Ok(()),
+            false => Err(permission_denied(object)),
+        }
+    }
+}
+
+impl State {
+    /// The role and every role it is a member of, directly or not.
+    fn memberships(&self, name: &str) -> BTreeSet<String> {
+        let mut found = BTreeSet::new();
+        let mut pending = vec![name.to_string()];
+        while let Some(name) = pending.pop() {
+            if let Some(role) = self.roles.get(&name) {
+                pending.extend(role.member_of.iter().filter(|role| !found.contains(*role)).cloned());
+            }
+            found.insert(name);
+        }
+        found
+    }
+
+    fn check_grantee(&self, grantee: &str) -> Result<(), ExecError> {
+        match grantee == PUBLIC || self.roles.contains_key(grantee) {
+            true => Ok(()),
+            false => Err(undefined_role(grantee)),
+        }
+    }
+}
+
+fn apply_options(role: &mut Role, options: &[RoleOption]) {
+    for option in options {
+        match option {
+            RoleOption::Superuser(superuser) => role.superuser = *superuser,
+            RoleOption::Login(login) => role.login = *login,
+            RoleOption::Password(password) => role.password = password.map(ScramSecret::new),
+        }
+    }
+}
+
+pub fn undefined_role(name: &str) -> ExecError {
+    ExecError::new(SqlState::UNDEFINED_OBJECT, format!("role \"{name}\" does not exist"))
+}
+
+pub fn permission_denied(object: &Object) -> ExecError {
+    ExecError::new(
+        SqlState::INSUFFICIENT_PRIVILEGE,
+        format!("permission denied for {0} {1}", object.kind(), object.name()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn table(name: &str) -> Object {
+        Object::Table(name.to_string())
+    }
+
+    #[test]
+    fn test_roles() {
+        let catalog = Catalog::bootstrap("rdb", Some("secret"));
+        assert!(catalog.role("rdb").unwrap().password.unwrap().verify("secret"));
+
+        catalog.create_role("alice", &[RoleOption::Login(true)]).unwrap();
+        assert_eq!(
+            catalog.create_role("alice", &[]).unwrap_err().code,
+            SqlState::DUPLICATE_OBJECT
+        );
+        assert_eq!(catalog.create_role("public", &[]).unwrap_err().code, SqlState::RESERVED_NAME);
+
+        catalog
+            .alter_role("alice", &[RoleOption::Password(Some("pw")), RoleOption::Login(false)])
+            .unwrap();
+        let alice = catalog.role("alice").unwrap();
+        assert!(!alice.login && alice.password.unwrap().verify("pw"));
+        assert_eq!(catalog.alter_role("bob", &[]).unwrap_err().code, SqlState::UNDEFINED_OBJECT);
+
+        assert!(catalog.drop_role("alice"));
+        assert!(!catalog.drop_role("alice"));
+    }
+
+    #[test]
+    fn test_privileges() {
+        let catalog = Catalog::bootstrap("rdb", None);
+        catalog.create_role("alice", &[]).unwrap();
+        catalog.create_role("readers", &[]).unwrap();
+
+        assert!(catalog.has_privilege("rdb", &table("cats"), PrivilegeKind::Delete));
+        assert!(catalog.has_privilege("alice", &table(STAT_STATEMENTS_TABLE), PrivilegeKind::Select));
+        assert!(!catalog.has_privilege("alice", &table("cats"), PrivilegeKind::Select));
+
+        // Through membership, with the grant option only where it was given.
+        catalog.grant("readers", table("cats"), PrivilegeKind::Select, true).unwrap();
+        catalog.grant_role("readers", "alice").unwrap();
+        assert!(catalog.has_grant_option("alice", &table("cats"), PrivilegeKind::Select));
+        catalog.revoke("readers", table("cats"), PrivilegeKind::Select, true).unwrap();
+        assert!(catalog.has_privilege("alice", &table("cats"), PrivilegeKind::Select));
+        assert!(!catalog.has_grant_option("alice", &table("cats"), PrivilegeKind::Select));
+
+        assert_eq!(
+            catalog.grant_role("alice", "readers").unwrap_err().code,
+            SqlState::INVALID_GRANT_OPERATION
+        );
+        catalog.revoke_role("readers", "alice").unwrap();
+        assert!(!catalog.has_privilege("alice", &table("cats"), PrivilegeKind::Select));
+
+        // Column privileges only cover statements that stay within the columns.
+        let column = Object::Column("cats".to_string(), "name".to_string());
+        catalog.grant("alice", column, PrivilegeKind::Update, false).unwrap();
+        assert!(catalog.check_table("alice", "cats", PrivilegeKind::Update, &["name"]).is_ok());
+        let err = catalog
+            .check_table("alice", "cats", PrivilegeKind::Update, &["name", "age"])
+            .unwrap_err();
+        assert_eq!(err.message, "permission denied for table cats");
+
+        // Ownership goes to members, not to those holding privileges.
+        assert!(catalog.check_owner("rdb", "cats").is_ok());
+        assert!(catalog.check_owner("alice", "cats").is_err());
+        catalog.set_owner("cats", "readers").unwrap();
+        catalog.grant_role("readers", "alice").unwrap();
+        assert!(catalog.check_owner("alice", "cats").is_ok());
+        assert!(catalog.drop_role("readers"));
+        assert_eq!(
+            catalog.check_owner("alice", "cats").unwrap_err().message,
+            "must be owner of table cats"
+        );
+
+        assert_eq!(
+            catalog
+                .grant("bob", table("cats"), PrivilegeKind::Select, false)
+                .unwrap_err()
+                .code,
+            SqlState::UNDEFINED_OBJECT
+        );
+    }
+}
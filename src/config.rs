@@ -0,0 +1,841 @@
+//! Server configuration.
+//!
+//! Settings come from the defaults, a TOML file, `RDB_*` environment variables and command line flags, each source
+//! overriding the ones before it. Every setting has a dotted key, `server.port` is `port` in the `[server]` table
+//! of the file, the `RDB_SERVER_PORT` environment variable and `--set server.port=<value>` on the command line.
+
+use crate::protocol::codec::MAX_FRAME_LEN;
+use crate::stats::STAT_STATEMENTS_MAX;
+use crate::storage::buffer::EvictionPolicyKind;
+use crate::storage::lock::DEADLOCK_CHECK_INTERVAL;
+use crate::storage::vacuum::{VACUUM_INTERVAL, VACUUM_THRESHOLD};
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+Usage: rdb [options]
+
+Options:
+  --config <path>             Read settings from a TOML file, also RDB_CONFIG
+  --listen-address <addr>     Address to listen on (server.listen_address)
+  --port <port>               PostgreSQL protocol port (server.port)
+  --mysql-port <port>         Also speak the MySQL protocol on this port (server.mysql_port)
+  --data-directory <path>     Where data is stored (storage.data_directory)
+  --max-connections <n>       Connections served at once (server.max_connections)
+  --log-level <level>         error, warn, info, debug or trace (log.level)
+  --auth-method <method>      trust or scram-sha-256 (auth.method)
+  --tls-certificate <path>    Offer TLS with this PEM certificate chain (tls.certificate)
+  --tls-key <path>            PEM private key of the certificate (tls.key)
+  --set <key>=<value>         Set any setting by its key
+  --print-config              Print the effective configuration and exit
+  --help                      Print this message and exit";
+
+/// Settings that can be set, by key.
+const KEYS: &[&str] = &[
+    "server.listen_address",
+    "server.port",
+    "server.mysql_port",
+    "server.max_connections",
+    "storage.data_directory",
+    "memory.buffer_pool_size",
+    "memory.max_message_size",
+    "memory.eviction_policy",
+    "log.level",
+    "wal.fsync",
+    "wal.segment_size",
+    "wal.checkpoint_interval",
+    "timeouts.statement",
+    "timeouts.idle_session",
+    "timeouts.authentication",
+    "timeouts.shutdown",
+    "timeouts.lock",
+    "timeouts.deadlock",
+    "vacuum.interval",
+    "vacuum.threshold",
+    "stats.max_statements",
+    "auth.method",
+    "auth.superuser",
+    "auth.password",
+    "tls.certificate",
+    "tls.key",
+    "tls.required",
+];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    /// The configuration file is not valid TOML.
+    Parse(PathBuf, String),
+    UnknownKey(String),
+    InvalidValue {
+        key: String,
+        message: String,
+    },
+    /// A command line argument that is not understood.
+    Usage(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "could not read {0}: {err}", path.display()),
+            ConfigError::Parse(path, message) => write!(f, "invalid configuration file {0}: {message}", path.display()),
+            ConfigError::UnknownKey(key) => write!(f, "unknown setting: {key}"),
+            ConfigError::InvalidValue { key, message } => write!(f, "invalid value for {key}: {message}"),
+            ConfigError::Usage(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+/// How clients prove who they are.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AuthMethod {
+    /// Any client may connect as any role that can log in.
+    Trust,
+    ScramSha256,
+}
+
+impl AuthMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthMethod::Trust => "trust",
+            AuthMethod::ScramSha256 => "scram-sha-256",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerConfig {
+    pub listen_address: IpAddr,
+    pub port: u16,
+    /// The MySQL protocol is only served when a port is set.
+    pub mysql_port: Option<u16>,
+    pub max_connections: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StorageConfig {
+    pub data_directory: PathBuf,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryConfig {
+    pub buffer_pool_size: u64,
+    /// Largest message accepted from a client.
+    pub max_message_size: usize,
+    pub eviction_policy: EvictionPolicyKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogConfig {
+    pub level: LogLevel,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WalConfig {
+    /// Whether commits wait for the log to reach the disk.
+    pub fsync: bool,
+    pub segment_size: u64,
+    pub checkpoint_interval: Duration,
+}
+
+/// `None` disables a timeout.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeoutConfig {
+    pub statement: Option<Duration>,
+    pub idle_session: Option<Duration>,
+    /// How long a client may take to start up and authenticate.
+    pub authentication: Option<Duration>,
+    /// How long shutdown waits for open connections.
+    pub shutdown: Duration,
+    /// How long a statement waits for a lock.
+    pub lock: Option<Duration>,
+    /// How often sessions waiting for each other's locks are looked for.
+    pub deadlock: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VacuumConfig {
+    /// How often tables are looked at, `None` leaves them to `VACUUM`.
+    pub interval: Option<Duration>,
+    /// Versions transactions write to a table before it is vacuumed.
+    pub threshold: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatsConfig {
+    /// Query shapes `rdb_stat_statements` tracks, the least called make room for new ones.
+    pub max_statements: usize,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct AuthConfig {
+    pub method: AuthMethod,
+    /// Role created at startup, which creates every other role.
+    pub superuser: String,
+    /// Password of the superuser, one is generated when there is none.
+    pub password: Option<String>,
+}
+
+/// Leaves the password out.
+impl std::fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field("method", &self.method)
+            .field("superuser", &self.superuser)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// TLS is offered to clients when a certificate and its key are set.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TlsConfig {
+    /// PEM file with the server certificate followed by the rest of its chain.
+    pub certificate: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    /// Turns away clients that do not switch to TLS.
+    pub required: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub storage: StorageConfig,
+    pub memory: MemoryConfig,
+    pub log: LogConfig,
+    pub wal: WalConfig,
+    pub timeouts: TimeoutConfig,
+    pub vacuum: VacuumConfig,
+    pub stats: StatsConfig,
+    pub auth: AuthConfig,
+    pub tls: TlsConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            server: ServerConfig {
+                listen_address: IpAddr::from([127, 0, 0, 1]),
+                port: 6543,
+                mysql_port: None,
+                max_connections: 100,
+            },
+            storage: StorageConfig {
+                data_directory: PathBuf::from("data"),
+            },
+            memory: MemoryConfig {
+                buffer_pool_size: 128 * MIB,
+                max_message_size: MAX_FRAME_LEN,
+                eviction_policy: EvictionPolicyKind::Clock,
+            },
+            log: LogConfig { level: LogLevel::Info },
+            wal: WalConfig {
+                fsync: true,
+                segment_size: 16 * MIB,
+                checkpoint_interval: Duration::from_secs(300),
+            },
+            timeouts: TimeoutConfig {
+                statement: None,
+                idle_session: None,
+                authentication: Some(Duration::from_secs(60)),
+                shutdown: Duration::from_secs(30),
+                lock: None,
+                deadlock: DEADLOCK_CHECK_INTERVAL,
+            },
+            vacuum: VacuumConfig {
+                interval: Some(VACUUM_INTERVAL),
+                threshold: VACUUM_THRESHOLD,
+            },
+            stats: StatsConfig {
+                max_statements: STAT_STATEMENTS_MAX,
+            },
+            auth: AuthConfig {
+                method: AuthMethod::ScramSha256,
+                superuser: "rdb".to_string(),
+                password: None,
+            },
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+const GIB: u64 = 1024 * MIB;
+
+/// What the command line asks for.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Args {
+    pub config_file: Option<PathBuf>,
+    pub print_config: bool,
+    pub help: bool,
+    /// Settings in the order they were given.
+    pub overrides: Vec<(String, String)>,
+}
+
+impl Args {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, ConfigError> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+                _ => (arg, None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| ConfigError::Usage(format!("{flag} requires a value")))
+            };
+
+            match flag.as_str() {
+                "--config" => parsed.config_file = Some(PathBuf::from(value()?)),
+                "--print-config" => parsed.print_config = true,
+                "--help" | "-h" => parsed.help = true,
+                "--set" => {
+                    let setting = value()?;
+                    let (key, value) = setting
+                        .split_once('=')
+                        .ok_or_else(|| ConfigError::Usage(format!("--set expects <key>=<value>, got {setting}")))?;
+                    parsed.overrides.push((key.to_string(), value.to_string()));
+                }
+                "--listen-address" | "--port" | "--mysql-port" | "--max-connections" => {
+                    let key = format!("server.{0}", flag[2..].replace('-', "_"));
+                    parsed.overrides.push((key, value()?));
+                }
+                "--data-directory" => parsed.overrides.push(("storage.data_directory".to_string(), value()?)),
+                "--log-level" => parsed.overrides.push(("log.level".to_string(), value()?)),
+                "--auth-method" => parsed.overrides.push(("auth.method".to_string(), value()?)),
+                "--tls-certificate" => parsed.overrides.push(("tls.certificate".to_string(), value()?)),
+                "--tls-key" => parsed.overrides.push(("tls.key".to_string(), value()?)),
+                _ => return Err(ConfigError::Usage(format!("unknown argument: {flag}"))),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+impl Config {
+    /// Builds the configuration from the defaults, the configuration file, the environment and the command line.
+    pub fn load<E>(args: &Args, env: E) -> Result<Self, ConfigError>
+    where
+        E: IntoIterator<Item = (String, String)>,
+    {
+        let env: Vec<(String, String)> = env.into_iter().filter(|(name, _)| name.starts_with("RDB_")).collect();
+        let mut config = Config::default();
+
+        let file = args.config_file.clone().or_else(|| {
+            env.iter()
+                .find(|(name, _)| name == "RDB_CONFIG")
+                .map(|(_, path)| PathBuf::from(path))
+        });
+        if let Some(path) = file {
+            let text = std::fs::read_to_string(&path).map_err(|err| ConfigError::Io(path.clone(), err))?;
+            config.apply_toml(&path, &text)?;
+        }
+
+        for key in KEYS {
+            let name = format!("RDB_{0}", key.replace('.', "_").to_uppercase());
+            if let Some((_, value)) = env.iter().find(|(env_name, _)| *env_name == name) {
+                config.set(key, value)?;
+            }
+        }
+
+        for (key, value) in &args.overrides {
+            config.set(key, value)?;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_toml(&mut self, path: &std::path::Path, text: &str) -> Result<(), ConfigError> {
+        let table: toml::Table = text
+            .parse()
+            .map_err(|err: toml::de::Error| ConfigError::Parse(path.to_path_buf(), err.message().to_string()))?;
+
+        for (section, settings) in table {
+            let toml::Value::Table(settings) = settings else {
+                return Err(ConfigError::UnknownKey(section));
+            };
+
+            for (name, value) in settings {
+                let key = format!("{section}.{name}");
+                let value = match value {
+                    toml::Value::String(s) => s,
+                    toml::Value::Integer(_) | toml::Value::Boolean(_) => value.to_string(),
+                    _ => {
+                        return Err(ConfigError::InvalidValue {
+                            key,
+                            message: format!("expected a string, integer or boolean, got {0}", value.type_str()),
+                        });
+                    }
+                };
+                self.set(&key, &value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets a setting from its text form.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = |message: String| ConfigError::InvalidValue {
+            key: key.to_string(),
+            message,
+        };
+        let value = value.trim();
+
+        match key {
+            "server.listen_address" => {
+                self.server.listen_address = value.parse().map_err(|_| invalid(format!("not an IP address: {value}")))?
+            }
+            "server.port" => self.server.port = parse_number(value).map_err(invalid)?,
+            "server.mysql_port" => {
+                self.server.mysql_port = match value {
+                    "" | "off" => None,
+                    value => Some(parse_number(value).map_err(invalid)?),
+                }
+            }
+            "server.max_connections" => self.server.max_connections = parse_number(value).map_err(invalid)?,
+            "storage.data_directory" => self.storage.data_directory = PathBuf::from(value),
+            "memory.buffer_pool_size" => self.memory.buffer_pool_size = parse_size(value).map_err(invalid)?,
+            "memory.max_message_size" => {
+                self.memory.max_message_size = usize::try_from(parse_size(value).map_err(invalid)?)
+                    .map_err(|_| invalid(format!("{value} is too large")))?
+            }
+            "memory.eviction_policy" => {
+                self.memory.eviction_policy = match value.to_lowercase().as_str() {
+                    "clock" => EvictionPolicyKind::Clock,
+                    policy => match policy.strip_prefix("lru-").map(str::parse) {
+                        Some(Ok(k)) if k > 0 => EvictionPolicyKind::LruK(k),
+                        _ => return Err(invalid(format!("expected clock or lru-<k>, got {value}"))),
+                    },
+                }
+            }
+            "log.level" => {
+                self.log.level = match value.to_lowercase().as_str() {
+                    "error" => LogLevel::Error,
+                    "warn" | "warning" => LogLevel::Warn,
+                    "info" => LogLevel::Info,
+                    "debug" => LogLevel::Debug,
+                    "trace" => LogLevel::Trace,
+                    _ => return Err(invalid(format!("unknown log level: {value}"))),
+                }
+            }
+            "wal.fsync" => self.wal.fsync = parse_bool(value).map_err(invalid)?,
+            "wal.segment_size" => self.wal.segment_size = parse_size(value).map_err(invalid)?,
+            "wal.checkpoint_interval" => self.wal.checkpoint_interval = parse_duration(value).map_err(invalid)?,
+            "timeouts.statement" => {
+                self.timeouts.statement = Some(parse_duration(value).map_err(invalid)?).filter(|d| !d.is_zero())
+            }
+            "timeouts.idle_session" => {
+                self.timeouts.idle_session = Some(parse_duration(value).map_err(invalid)?).filter(|d| !d.is_zero())
+            }
+            "timeouts.authentication" => {
+                self.timeouts.authentication = Some(parse_duration(value).map_err(invalid)?).filter(|d| !d.is_zero())
+            }
+            "timeouts.shutdown" => self.timeouts.shutdown = parse_duration(value).map_err(invalid)?,
+            "timeouts.lock" => self.timeouts.lock = Some(parse_duration(value).map_err(invalid)?).filter(|d| !d.is_zero()),
+            "timeouts.deadlock" => match parse_duration(value).map_err(invalid)? {
+                interval if interval.is_zero() => return Err(invalid("must be positive".to_string())),
+                interval => self.timeouts.deadlock = interval,
+            },
+            "vacuum.interval" => {
+                self.vacuum.interval = Some(parse_duration(value).map_err(invalid)?).filter(|d| !d.is_zero())
+            }
+            "vacuum.threshold" => self.vacuum.threshold = parse_number(value).map_err(invalid)?,
+            "stats.max_statements" => match parse_number(value).map_err(invalid)? {
+                0 => return Err(invalid("must be positive".to_string())),
+                max => self.stats.max_statements = max,
+            },
+            "auth.method" => {
+                self.auth.method = match value.to_lowercase().as_str() {
+                    "trust" => AuthMethod::Trust,
+                    "scram-sha-256" => AuthMethod::ScramSha256,
+                    _ => return Err(invalid(format!("unknown authentication method: {value}"))),
+                }
+            }
+            "auth.superuser" => self.auth.superuser = value.to_string(),
+            "auth.password" => self.auth.password = Some(value.to_string()).filter(|password| !password.is_empty()),
+            "tls.certificate" => self.tls.certificate = Some(PathBuf::from(value)).filter(|_| !value.is_empty()),
+            "tls.key" => self.tls.key = Some(PathBuf::from(value)).filter(|_| !value.is_empty()),
+            "tls.required" => self.tls.required = parse_bool(value).map_err(invalid)?,
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+
+        Ok(())
+    }
+
+    /// Checks the settings that are only wrong in combination or outside of their sensible range.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |key: &str, message: &str| {
+            Err(ConfigError::InvalidValue {
+                key: key.to_string(),
+                message: message.to_string(),
+            })
+        };
+
+        if self.server.mysql_port == Some(self.server.port) {
+            return invalid("server.mysql_port", "must differ from server.port");
+        }
+        if self.server.max_connections == 0 {
+            return invalid("server.max_connections", "must be at least 1");
+        }
+        if self.storage.data_directory.as_os_str().is_empty() {
+            return invalid("storage.data_directory", "must not be empty");
+        }
+        if self.memory.buffer_pool_size < MIB {
+            return invalid("memory.buffer_pool_size", "must be at least 1MiB");
+        }
+        if !(KIB..=GIB).contains(&(self.memory.max_message_size as u64)) {
+            return invalid("memory.max_message_size", "must be between 1KiB and 1GiB");
+        }
+        if self.wal.segment_size < MIB || !self.wal.segment_size.is_power_of_two() {
+            return invalid("wal.segment_size", "must be a power of two of at least 1MiB");
+        }
+        if self.wal.checkpoint_interval < Duration::from_secs(1) {
+            return invalid("wal.checkpoint_interval", "must be at least 1s");
+        }
+        if self.auth.superuser.is_empty() || self.auth.superuser == "public" {
+            return invalid("auth.superuser", "must be a role name other than public");
+        }
+        if self.tls.certificate.is_some() != self.tls.key.is_some() {
+            return invalid("tls.key", "must be set together with tls.certificate");
+        }
+        if self.tls.required && self.tls.certificate.is_none() {
+            return invalid("tls.required", "needs tls.certificate and tls.key");
+        }
+
+        Ok(())
+    }
+}
+
+/// The configuration as a TOML file that loads back to the same settings.
+impl Display for Config {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        writeln!(f, "[server]")?;
+        writeln!(f, "listen_address = \"{0}\"", self.server.listen_address)?;
+        writeln!(f, "port = {0}", self.server.port)?;
+        match self.server.mysql_port {
+            Some(port) => writeln!(f, "mysql_port = {port}")?,
+            None => writeln!(f, "mysql_port = \"off\"")?,
+        }
+        writeln!(f, "max_connections = {0}", self.server.max_connections)?;
+
+        writeln!(f, "\n[storage]")?;
+        writeln!(
+            f,
+            "data_directory = {0}",
+            toml::Value::from(self.storage.data_directory.display().to_string())
+        )?;
+
+        writeln!(f, "\n[memory]")?;
+        writeln!(f, "buffer_pool_size = \"{0}\"", format_size(self.memory.buffer_pool_size))?;
+        writeln!(
+            f,
+            "max_message_size = \"{0}\"",
+            format_size(self.memory.max_message_size as u64)
+        )?;
+        writeln!(f, "eviction_policy = \"{0}\"", self.memory.eviction_policy)?;
+
+        writeln!(f, "\n[log]")?;
+        writeln!(f, "level = \"{0}\"", self.log.level.as_str())?;
+
+        writeln!(f, "\n[wal]")?;
+        writeln!(f, "fsync = {0}", self.wal.fsync)?;
+        writeln!(f, "segment_size = \"{0}\"", format_size(self.wal.segment_size))?;
+        writeln!(
+            f,
+            "checkpoint_interval = \"{0}\"",
+            format_duration(self.wal.checkpoint_interval)
+        )?;
+
+        writeln!(f, "\n[timeouts]")?;
+        let optional = |timeout: Option<Duration>| timeout.map_or("0".to_string(), format_duration);
+        writeln!(f, "statement = \"{0}\"", optional(self.timeouts.statement))?;
+        writeln!(f, "idle_session = \"{0}\"", optional(self.timeouts.idle_session))?;
+        writeln!(f, "authentication = \"{0}\"", optional(self.timeouts.authentication))?;
+        writeln!(f, "shutdown = \"{0}\"", format_duration(self.timeouts.shutdown))?;
+        writeln!(f, "lock = \"{0}\"", optional(self.timeouts.lock))?;
+        writeln!(f, "deadlock = \"{0}\"", format_duration(self.timeouts.deadlock))?;
+
+        writeln!(f, "\n[vacuum]")?;
+        writeln!(f, "interval = \"{0}\"", optional(self.vacuum.interval))?;
+        writeln!(f, "threshold = {0}", self.vacuum.threshold)?;
+
+        writeln!(f, "\n[stats]")?;
+        writeln!(f, "max_statements = {0}", self.stats.max_statements)?;
+
+        // The password stays out of printed configurations.
+        writeln!(f, "\n[auth]")?;
+        writeln!(f, "method = \"{0}\"", self.auth.method.as_str())?;
+        writeln!(f, "superuser = {0}", toml::Value::from(self.auth.superuser.clone()))?;
+
+        writeln!(f, "\n[tls]")?;
+        let path = |path: &Option<PathBuf>| {
+            toml::Value::from(path.as_ref().map_or(String::new(), |path| path.display().to_string()))
+        };
+        writeln!(f, "certificate = {0}", path(&self.tls.certificate))?;
+        writeln!(f, "key = {0}", path(&self.tls.key))?;
+        write!(f, "required = {0}", self.tls.required)
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "true" | "on" | "1" => Ok(true),
+        "false" | "off" | "0" => Ok(false),
+        _ => Err(format!("expected a boolean, got {value}")),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("expected a number in range, got {value}"))
+}
+
+/// Splits `64MiB` into `64` and `MiB`.
+fn split_unit(value: &str) -> (&str, &str) {
+    let end = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    (&value[..end], value[end..].trim())
+}
+
+/// Bytes, with an optional `kB`, `MB` or `GB` unit. Units are powers of 1024 with or without the `i`.
+fn parse_size(value: &str) -> Result<u64, String> {
+    let (number, unit) = split_unit(value);
+    let multiplier = match unit.to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => KIB,
+        "m" | "mb" | "mib" => MIB,
+        "g" | "gb" | "gib" => GIB,
+        _ => return Err(format!("unknown size unit: {unit}")),
+    };
+
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("expected a size like 64MiB, got {value}"))
+}
+
+fn format_size(size: u64) -> String {
+    match size {
+        0 => "0".to_string(),
+        size if size % GIB == 0 => format!("{0}GiB", size / GIB),
+        size if size % MIB == 0 => format!("{0}MiB", size / MIB),
+        size if size % KIB == 0 => format!("{0}KiB", size / KIB),
+        size => size.to_string(),
+    }
+}
+
+/// A duration with a `ms`, `s`, `min`, `h` or `d` unit, milliseconds without one.
+pub(crate) fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = split_unit(value);
+    let millis = match unit {
+        "" | "ms" => 1,
+        "s" => 1000,
+        "min" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        "d" => 24 * 60 * 60 * 1000,
+        _ => return Err(format!("unknown time unit: {unit}")),
+    };
+
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(millis))
+        .map(Duration::from_millis)
+        .ok_or_else(|| format!("expected a duration like 30s, got {value}"))
+}
+
+fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    match millis {
+        0 => "0".to_string(),
+        n if n % (60 * 60 * 1000) == 0 => format!("{0}h", n / (60 * 60 * 1000)),
+        n if n % (60 * 1000) == 0 => format!("{0}min", n / (60 * 1000)),
+        n if n % 1000 == 0 => format!("{0}s", n / 1000),
+        n => format!("{n}ms"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Args {
+        Args::parse(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_units() {
+        assert_eq!(parse_size("64MiB"), Ok(64 * MIB));
+        assert_eq!(parse_size("8 kB"), Ok(8 * KIB));
+        assert_eq!(parse_size("100"), Ok(100));
+        assert!(parse_size("1TB").is_err());
+        assert!(parse_size("MB").is_err());
+
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("1500"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("5min"), Ok(Duration::from_secs(300)));
+        assert!(parse_duration("-1s").is_err());
+
+        assert_eq!(format_size(3 * GIB), "3GiB");
+        assert_eq!(format_duration(Duration::from_millis(90_000)), "90s");
+    }
+
+    #[test]
+    fn test_args() {
+        let parsed = args(&["--port", "7000", "--set=log.level=debug", "--print-config"]);
+        assert!(parsed.print_config);
+        assert_eq!(
+            parsed.overrides,
+            vec![
+                ("server.port".to_string(), "7000".to_string()),
+                ("log.level".to_string(), "debug".to_string())
+            ]
+        );
+
+        assert!(matches!(Args::parse(["--port".to_string()]), Err(ConfigError::Usage(_))));
+        assert!(matches!(Args::parse(["--verbose".to_string()]), Err(ConfigError::Usage(_))));
+    }
+
+    #[test]
+    fn test_precedence() {
+        let path = std::env::temp_dir().join(format!("rdb-config-{0}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[server]\nport = 7000\nmax_connections = 10\n\n[memory]\nbuffer_pool_size = \"1GiB\"\n",
+        )
+        .unwrap();
+
+        let cli = args(&["--max-connections", "20"]);
+        let vars = env(&[
+            ("RDB_CONFIG", path.to_str().unwrap()),
+            ("RDB_SERVER_MAX_CONNECTIONS", "15"),
+            ("RDB_TIMEOUTS_STATEMENT", "2s"),
+            ("HOME", "/root"),
+        ]);
+        let config = Config::load(&cli, vars).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.server.port, 7000);
+        assert_eq!(config.server.max_connections, 20);
+        assert_eq!(config.memory.buffer_pool_size, GIB);
+        assert_eq!(config.timeouts.statement, Some(Duration::from_secs(2)));
+        assert_eq!(config.log.level, LogLevel::Info);
+        assert_eq!(config.auth.method, AuthMethod::ScramSha256);
+    }
+
+    #[test]
+    fn test_errors() {
+        let load = |cli: &[&str]| Config::load(&args(cli), vec![]).unwrap_err().to_string();
+
+        assert_eq!(load(&["--set", "server.colour=red"]), "unknown setting: server.colour");
+        assert_eq!(
+            load(&["--port", "70000"]),
+            "invalid value for server.port: expected a number in range, got 70000"
+        );
+        assert_eq!(
+            load(&["--mysql-port", "6543"]),
+            "invalid value for server.mysql_port: must differ from server.port"
+        );
+        assert_eq!(
+            load(&["--set", "wal.segment_size=3MiB"]),
+            "invalid value for wal.segment_size: must be a power of two of at least 1MiB"
+        );
+        assert_eq!(
+            load(&["--set", "memory.eviction_policy=lru-0"]),
+            "invalid value for memory.eviction_policy: expected clock or lru-<k>, got lru-0"
+        );
+        assert_eq!(
+            load(&["--set", "stats.max_statements=0"]),
+            "invalid value for stats.max_statements: must be positive"
+        );
+        assert!(load(&["--config", "/nonexistent/rdb.toml"]).starts_with("could not read"));
+        assert_eq!(
+            load(&["--tls-certificate", "server.crt"]),
+            "invalid value for tls.key: must be set together with tls.certificate"
+        );
+        assert_eq!(
+            load(&["--set", "tls.required=true"]),
+            "invalid value for tls.required: needs tls.certificate and tls.key"
+        );
+
+        let mut config = Config::default();
+        let path = PathBuf::from("rdb.toml");
+        assert!(matches!(
+            config.apply_toml(&path, "port = 1"),
+            Err(ConfigError::UnknownKey(_))
+        ));
+        assert!(matches!(config.apply_toml(&path, "[server"), Err(ConfigError::Parse(..))));
+    }
+
+    #[test]
+    fn test_print_round_trip() {
+        let mut config = Config::default();
+        config.set("server.mysql_port", "3306").unwrap();
+        config.set("storage.data_directory", "/var/lib/rdb \"main\"").unwrap();
+        config.set("timeouts.idle_session", "10min").unwrap();
+        config.set("timeouts.authentication", "0").unwrap();
+        config.set("timeouts.lock", "5s").unwrap();
+        config.set("timeouts.deadlock", "250ms").unwrap();
+        config.set("vacuum.interval", "0").unwrap();
+        config.set("vacuum.threshold", "1000").unwrap();
+        config.set("stats.max_statements", "100").unwrap();
+        config.set("memory.max_message_size", "1000").unwrap();
+        config.set("memory.eviction_policy", "LRU-3").unwrap();
+        config.set("auth.method", "trust").unwrap();
+        config.set("tls.certificate", "/etc/rdb/server.crt").unwrap();
+        config.set("tls.key", "/etc/rdb/server.key").unwrap();
+        config.set("tls.required", "on").unwrap();
+
+        let mut loaded = Config::default();
+        loaded.apply_toml(&PathBuf::from("printed"), &config.to_string()).unwrap();
+        assert_eq!(loaded, config);
+
+        let mut loaded = config.clone();
+        loaded
+            .apply_toml(&PathBuf::from("printed"), &Config::default().to_string())
+            .unwrap();
+        assert_eq!(loaded, Config::default());
+
+        config.set("auth.password", "hunter2").unwrap();
+        assert!(!config.to_string().contains("hunter2"));
+        assert!(!format!("{config:?}").contains("hunter2"));
+    }
+}
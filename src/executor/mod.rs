@@ -0,0 +1,1809 @@
+pub mod prepared;
+pub mod privilege;
+pub mod result;
+
+use crate::cancel::{CancelFlag, CancelReason, CancelRegistry, Checkpoint, Registration};
+use crate::catalog::{Catalog, DEFAULT_SCHEMA, Object, PUBLIC, permission_denied, undefined_role};
+use crate::config::parse_duration;
+use crate::executor::privilege::{Requirement, requirements};
+use crate::executor::result::{Column, ResultSet};
+use crate::parser::ast::{
+    AlterRoleStmt, BinaryOperator, CreateIndexStmt, DropIndexStmt, DropRoleStmt, ExprKind, FromItemKind, GrantKind,
+    GrantObject, GrantStmt, Grantee, IsolationLevel, LockStrength, PrivilegeKind, RoleOption, SelectItemKind, SelectStmt,
+    SetStmt, StatementKind,
+};
+use crate::parser::dialect::{Dialect, GenericDialect};
+use crate::parser::fingerprint::Fingerprint;
+use crate::parser::split::split_statements;
+use crate::parser::token::{DataKind, LiteralKind};
+use crate::parser::{ParseError, ParseErrorKind, Parser};
+use crate::stats::{STAT_STATEMENTS_COLUMNS, STAT_STATEMENTS_TABLE, StatementStats};
+use crate::storage::lock::{DEADLOCK_CHECK_INTERVAL, LOCKS_COLUMNS, LOCKS_TABLE, LockManager, LockMode, LockTarget};
+use crate::storage::mvcc::{Transaction, TransactionManager};
+use crate::storage::table::Table;
+use crate::storage::vacuum::{VACUUM_COLUMNS, VACUUM_INTERVAL, VACUUM_TABLE, VACUUM_THRESHOLD, Vacuum};
+use crate::storage::{RowId, StorageError};
+use crate::value::Value;
+use std::fmt::{Debug, Display, Formatter};
+use std::ops::Range;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::runtime::RuntimeFlavor;
+use tokio::task::block_in_place;
+
+/// What to do with the rest of a batch once one of its statements failed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BatchMode {
+    StopOnError,
+    Continue,
+}
+
+/// SQLSTATE error code, as reported to clients.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SqlState([u8; 5]);
+
+impl SqlState {
+    pub const ACTIVE_SQL_TRANSACTION: SqlState = SqlState(*b"25001");
+    pub const ADMIN_SHUTDOWN: SqlState = SqlState(*b"57P01");
+    pub const CHARACTER_NOT_IN_REPERTOIRE: SqlState = SqlState(*b"22021");
+    pub const DEADLOCK_DETECTED: SqlState = SqlState(*b"40P01");
+    pub const DUPLICATE_CURSOR: SqlState = SqlState(*b"42P03");
+    pub const DUPLICATE_OBJECT: SqlState = SqlState(*b"42710");
+    pub const DUPLICATE_PREPARED_STATEMENT: SqlState = SqlState(*b"42P05");
+    pub const DUPLICATE_TABLE: SqlState = SqlState(*b"42P07");
+    pub const FEATURE_NOT_SUPPORTED: SqlState = SqlState(*b"0A000");
+    pub const IDLE_SESSION_TIMEOUT: SqlState = SqlState(*b"57P05");
+    pub const IN_FAILED_SQL_TRANSACTION: SqlState = SqlState(*b"25P02");
+    pub const INSUFFICIENT_PRIVILEGE: SqlState = SqlState(*b"42501");
+    pub const INTERNAL_ERROR: SqlState = SqlState(*b"XX000");
+    pub const INVALID_AUTHORIZATION_SPECIFICATION: SqlState = SqlState(*b"28000");
+    pub const INVALID_BINARY_REPRESENTATION: SqlState = SqlState(*b"22P03");
+    pub const INVALID_CURSOR_NAME: SqlState = SqlState(*b"34000");
+    pub const INVALID_GRANT_OPERATION: SqlState = SqlState(*b"0LP01");
+    pub const INVALID_PARAMETER_VALUE: SqlState = SqlState(*b"22023");
+    pub const INVALID_PASSWORD: SqlState = SqlState(*b"28P01");
+    pub const INVALID_SCHEMA_NAME: SqlState = SqlState(*b"3F000");
+    pub const INVALID_SQL_STATEMENT_NAME: SqlState = SqlState(*b"26000");
+    pub const INVALID_TEXT_REPRESENTATION: SqlState = SqlState(*b"22P02");
+    pub const LOCK_NOT_AVAILABLE: SqlState = SqlState(*b"55P03");
+    pub const OBJECT_IN_USE: SqlState = SqlState(*b"55006");
+    pub const PROTOCOL_VIOLATION: SqlState = SqlState(*b"08P01");
+    pub const QUERY_CANCELED: SqlState = SqlState(*b"57014");
+    pub const RESERVED_NAME: SqlState = SqlState(*b"42939");
+    pub const SERIALIZATION_FAILURE: SqlState = SqlState(*b"40001");
+    pub const STATEMENT_TOO_COMPLEX: SqlState = SqlState(*b"54001");
+    pub const SYNTAX_ERROR: SqlState = SqlState(*b"42601");
+    pub const TOO_MANY_CONNECTIONS: SqlState = SqlState(*b"53300");
+    pub const UNDEFINED_COLUMN: SqlState = SqlState(*b"42703");
+    pub const UNDEFINED_OBJECT: SqlState = SqlState(*b"42704");
+    pub const UNDEFINED_TABLE: SqlState = SqlState(*b"42P01");
+    pub const UNIQUE_VIOLATION: SqlState = SqlState(*b"23505");
+    pub const WARNING: SqlState = SqlState(*b"01000");
+    pub const WRONG_OBJECT_TYPE: SqlState = SqlState(*b"42809");
+
+    /// Code from its five character representation.
+    pub fn from_code(code: &str) -> Option<SqlState> {
+        let code: [u8; 5] = code.as_bytes().try_into().ok()?;
+        code.iter().all(|c| c.is_ascii_alphanumeric()).then_some(SqlState(code))
+    }
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.0).unwrap()
+    }
+}
+
+impl Debug for SqlState {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "SqlState({})", self.as_str())
+    }
+}
+
+impl Display for SqlState {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecError {
+    pub code: SqlState,
+    /// Byte offset in the submitted text, when the error can be tied to one.
+    pub pos: Option<usize>,
+    pub message: String,
+}
+
+impl ExecError {
+    pub fn new(code: SqlState, message: String) -> Self {
+        ExecError {
+            code,
+            pos: None,
+            message,
+        }
+    }
+}
+
+/// Transaction state of a session, carried from one batch to the next.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TransactionStatus {
+    #[default]
+    Idle,
+    InTransaction,
+    /// A statement failed inside a transaction, everything but `COMMIT` and `ROLLBACK` is rejected.
+    Failed,
+}
+
+/// What the executor keeps about a session from one statement to the next.
+#[derive(Clone, Debug, Default)]
+pub struct SessionState {
+    /// Id of the session in the cancel registry, which its locks are held under.
+    pub id: u32,
+    /// Role the session runs as, empty until the client authenticated.
+    pub user: String,
+    pub txn: TransactionStatus,
+    /// The transaction `BEGIN` started, until it ends.
+    pub transaction: Option<Arc<Transaction>>,
+    /// Statements running for longer are canceled, `None` lets them run. Changed with `SET statement_timeout`.
+    pub statement_timeout: Option<Duration>,
+    /// What `SET statement_timeout = DEFAULT` goes back to.
+    pub default_statement_timeout: Option<Duration>,
+    /// How long a statement waits for a lock, `None` waits for as long as it takes. Changed with `SET lock_timeout`.
+    pub lock_timeout: Option<Duration>,
+    pub default_lock_timeout: Option<Duration>,
+    /// Raised to cancel the running statement.
+    pub cancel: CancelFlag,
+}
+
+impl SessionState {
+    pub fn new(id: u32, statement_timeout: Option<Duration>, cancel: CancelFlag) -> Self {
+        SessionState {
+            id,
+            user: String::new(),
+            txn: TransactionStatus::Idle,
+            transaction: None,
+            statement_timeout,
+            default_statement_timeout: statement_timeout,
+            lock_timeout: None,
+            default_lock_timeout: None,
+            cancel,
+        }
+    }
+
+    pub fn with_lock_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.lock_timeout = timeout;
+        self.default_lock_timeout = timeout;
+        self
+    }
+
+    /// Starts the clock of the next statement.
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint::new(self.cancel.clone(), self.statement_timeout)
+    }
+}
+
+/// Outcome of one statement of a batch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatementResult {
+    /// Byte range of the statement in the batch.
+    pub range: Range<usize>,
+    pub result: Result<ResultSet, ExecError>,
+    /// Transaction status right after the statement.
+    pub txn: TransactionStatus,
+}
+
+/// Runs statements on the system tables and on the tables registered with its [`Vacuum`]. SQL does not create or
+/// store tables yet, `CREATE TABLE`, `INSERT`, `UPDATE` and `DELETE` are not supported, so only code embedding the
+/// executor has tables to register.
+pub struct Executor {
+    stats: Arc<StatementStats>,
+    catalog: Catalog,
+    sessions: CancelRegistry,
+    transactions: TransactionManager,
+    locks: LockManager,
+    vacuum: Vacuum,
+}
+
+impl Executor {
+    pub fn new(stats: Arc<StatementStats>, catalog: Catalog) -> Self {
+        let transactions = TransactionManager::new();
+        Executor {
+            stats,
+            catalog,
+            sessions: CancelRegistry::new(),
+            vacuum: Vacuum::new(transactions.clone(), Some(VACUUM_INTERVAL), VACUUM_THRESHOLD),
+            transactions,
+            locks: LockManager::new(DEADLOCK_CHECK_INTERVAL),
+        }
+    }
+
+    /// Takes locks from `locks`, which looks for deadlocks as often as it was configured to.
+    pub fn with_locks(mut self, locks: LockManager) -> Self {
+        self.locks = locks;
+        self
+    }
+
+    /// Vacuums tables in the background every `interval`, or never with `None`, once transactions wrote
+    /// `threshold` versions to them.
+    pub fn with_vacuum(mut self, interval: Option<Duration>, threshold: u64) -> Self {
+        self.vacuum = Vacuum::new(self.transactions.clone(), interval, threshold);
+        self
+    }
+
+    /// Roles sessions authenticate as.
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+
+    pub fn transactions(&self) -> &TransactionManager {
+        &self.transactions
+    }
+
+    pub fn locks(&self) -> &LockManager {
+        &self.locks
+    }
+
+    /// Tables `VACUUM` and the background vacuum know of.
+    pub fn vacuum(&self) -> &Vacuum {
+        &self.vacuum
+    }
+
+    /// Gives a new session the id and secret its client cancels it with.
+    pub fn register_session(&self) -> Registration<'_> {
+        self.sessions.register()
+    }
+
+    /// Cancels the statement session `id` is running, if `secret` is the session's.
+    pub fn cancel(&self, id: u32, secret: u32) -> bool {
+        self.sessions.cancel(id, secret)
+    }
+
+    /// Cancels the statements every session is running, and those they would run after, as the server shuts down.
+    pub fn terminate_sessions(&self) {
+        self.sessions.terminate_all();
+    }
+
+    /// Rolls back the transaction of a session that goes away and releases its locks.
+    pub fn end_session(&self, session: &mut SessionState) {
+        if let Some(transaction) = session.transaction.take() {
+            transaction.rollback();
+        }
+        session.txn = TransactionStatus::Idle;
+        self.locks.release_all(session.id);
+    }
+
+    /// Splits the batch into statements and runs them in order. Every statement is parsed on its own so a syntax
+    /// error only fails the statement it is in, and has its own statement timeout.
+    pub fn execute_batch(&self, sql: &[u8], mode: BatchMode, session: &mut SessionState) -> Vec<StatementResult> {
+        self.execute_batch_with_dialect(sql, &GenericDialect, mode, session)
+    }
+
+    pub fn execute_batch_with_dialect(
+        &self,
+        sql: &[u8],
+        dialect: &dyn Dialect,
+        mode: BatchMode,
+        session: &mut SessionState,
+    ) -> Vec<StatementResult> {
+        let mut results = Vec::new();
+        // A request that came in before the batch was meant for an earlier one.
+        session.cancel.reset();
+
+        // The lexer works on bytes but hands out text.
+        if let Err(err) = std::str::from_utf8(sql) {
+            let err = ExecError {
+                code: SqlState::CHARACTER_NOT_IN_REPERTOIRE,
+                pos: Some(err.valid_up_to()),
+                message: "invalid byte sequence for encoding \"UTF8\"".to_string(),
+            };
+            if session.txn == TransactionStatus::InTransaction {
+                session.txn = TransactionStatus::Failed;
+            }
+            results.push(StatementResult {
+                range: 0..sql.len(),
+                result: Err(err),
+                txn: session.txn,
+            });
+            return results;
+        }
+
+        for range in split_statements(sql, dialect) {
+            let checkpoint = session.checkpoint();
+            let result = self.execute_sql(&sql[range.clone()], range.start, dialect, &checkpoint, session);
+            let failed = result.is_err();
+            if failed && session.txn == TransactionStatus::InTransaction {
+                session.txn = TransactionStatus::Failed;
+            }
+            results.push(StatementResult {
+                range,
+                result,
+                txn: session.txn,
+            });
+
+            if failed && mode == BatchMode::StopOnError {
+                break;
+            }
+        }
+
+        results
+    }
+
+    fn execute_sql(
+        &self,
+        sql: &[u8],
+        offset: usize,
+        dialect: &dyn Dialect,
+        checkpoint: &Checkpoint,
+        session: &mut SessionState,
+    ) -> Result<ResultSet, ExecError> {
+        let mut parser = Parser::with_dialect(sql, dialect).with_checkpoint(checkpoint);
+        let ast = parser.parse().map_err(|err| match checkpoint.check() {
+            // The parser gave up because of the checkpoint.
+            Err(reason) => canceled(reason),
+            Ok(()) => parse_error(err, offset),
+        })?;
+
+        let mut output = ResultSet::command("EMPTY");
+        for stmt in &ast.stmts {
+            output = self.execute_with_checkpoint(stmt, checkpoint, session)?;
+        }
+
+        Ok(output)
+    }
+
+    /// Runs one statement, keeping track of the transaction it runs in.
+    pub fn execute(&self, stmt: &StatementKind, session: &mut SessionState) -> Result<ResultSet, ExecError> {
+        session.cancel.reset();
+        self.execute_with_checkpoint(stmt, &session.checkpoint(), session)
+    }
+
+    fn execute_with_checkpoint(
+        &self,
+        stmt: &StatementKind,
+        checkpoint: &Checkpoint,
+        session: &mut SessionState,
+    ) -> Result<ResultSet, ExecError> {
+        checkpoint.check().map_err(canceled)?;
+
+        let start = Instant::now();
+        let txn = session.txn;
+        let output = match (stmt, txn) {
+            (StatementKind::Begin(isolation), TransactionStatus::Idle) => {
+                session.txn = TransactionStatus::InTransaction;
+                let transaction = self.transactions.begin(isolation.unwrap_or_default());
+                session.transaction = Some(Arc::new(transaction));
+                Ok(ResultSet::command("BEGIN"))
+            }
+            (StatementKind::Begin(_), _) => {
+                Ok(ResultSet::command("BEGIN").with_notice("there is already a transaction in progress".to_string()))
+            }
+            (StatementKind::Commit, TransactionStatus::Failed) => {
+                // A failed transaction can only be rolled back.
+                session.txn = TransactionStatus::Idle;
+                if let Some(transaction) = session.transaction.take() {
+                    transaction.rollback();
+                }
+                Ok(ResultSet::command("ROLLBACK"))
+            }
+            (StatementKind::Commit | StatementKind::Rollback, TransactionStatus::Idle) => self
+                .execute_stmt(stmt, checkpoint, session)
+                .map(|result| result.with_notice("there is no transaction in progress".to_string())),
+            (StatementKind::Commit | StatementKind::Rollback, _) => {
+                session.txn = TransactionStatus::Idle;
+                let ended = match (stmt, session.transaction.take()) {
+                    (StatementKind::Commit, Some(transaction)) => transaction.commit().map_err(storage_error),
+                    (_, transaction) => {
+                        transaction.inspect(|transaction| transaction.rollback());
+                        Ok(())
+                    }
+                };
+                ended.and_then(|()| self.execute_stmt(stmt, checkpoint, session))
+            }
+            (_, TransactionStatus::Failed) => Err(ExecError::new(
+                SqlState::IN_FAILED_SQL_TRANSACTION,
+                "current transaction is aborted, commands ignored until end of transaction block".to_string(),
+            )),
+            _ => {
+                if let Some(transaction) = &session.transaction {
+                    transaction.next_statement();
+                }
+                self.execute_stmt(stmt, checkpoint, session)
+            }
+        };
+
+        match &output {
+            Ok(output) => self.stats.record(
+                &Fingerprint::of_stmt(stmt),
+                start.elapsed(),
+                output.rows.len() as u64 + output.affected_rows,
+            ),
+            Err(_) if session.txn == TransactionStatus::InTransaction => session.txn = TransactionStatus::Failed,
+            Err(_) => {}
+        }
+        // Locks are held until the end of the transaction, which is the statement outside of one.
+        if session.transaction.is_none() {
+            self.locks.release_all(session.id);
+        }
+
+        output
+    }
+
+    /// Columns of the rows the statement returns, `None` when it returns no rows.
+    pub fn describe(&self, stmt: &StatementKind) -> Result<Option<Vec<Column>>, ExecError> {
+        match stmt {
+            StatementKind::Select(select) => match select_table(select)? {
+                Some(table) => match self.vacuum.table(table) {
+                    Some(found) => Ok(Some(selected_columns(&found.read().unwrap(), select)?.1)),
+                    None => system_columns(table).map(Some).ok_or_else(|| undefined_table(table)),
+                },
+                None => Err(not_supported("SELECT without a table")),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    fn execute_stmt(
+        &self,
+        stmt: &StatementKind,
+        checkpoint: &Checkpoint,
+        session: &mut SessionState,
+    ) -> Result<ResultSet, ExecError> {
+        self.authorize(stmt, &session.user)?;
+
+        match stmt {
+            StatementKind::Select(select) => match select_table(select)? {
+                Some(table) if self.table_exists(table) => {
+                    // `FOR UPDATE` and `FOR SHARE` lock the rows returned, one by one.
+                    let locking = select.locking_clause;
+                    let mode = match locking.map(|clause| clause.strength) {
+                        None | Some(LockStrength::Share) => LockMode::IntentionShared,
+                        Some(LockStrength::Update) => LockMode::IntentionExclusive,
+                    };
+                    let nowait = locking.is_some_and(|clause| clause.nowait);
+                    let found = self.vacuum.table(table);
+                    if found.is_none() && locking.is_some() {
+                        return Err(not_supported("locking the rows of a system table"));
+                    }
+                    self.lock(LockTarget::Table(table.to_string()), mode, nowait, checkpoint, session)?;
+                    if let Some(found) = found {
+                        return self.select_rows(table, &found.read().unwrap(), select, checkpoint, session);
+                    }
+
+                    let rows = match table {
+                        STAT_STATEMENTS_TABLE => self.stats.rows(),
+                        VACUUM_TABLE => self.vacuum.rows(),
+                        _ => self.locks.rows(),
+                    };
+                    for _ in &rows {
+                        checkpoint.check().map_err(canceled)?;
+                    }
+                    Ok(ResultSet::query(system_columns(table).unwrap(), rows))
+                }
+                Some(table) => Err(undefined_table(table)),
+                None => Err(not_supported("SELECT without a table")),
+            },
+            StatementKind::Begin(_) => Ok(ResultSet::command("BEGIN")),
+            StatementKind::Commit => Ok(ResultSet::command("COMMIT")),
+            StatementKind::Rollback => Ok(ResultSet::command("ROLLBACK")),
+            StatementKind::Block(_) => Err(not_supported("Block statement")),
+            StatementKind::Update(_) => Err(not_supported("UPDATE")),
+            StatementKind::Insert(_) => Err(not_supported("INSERT")),
+            StatementKind::Delete(_) => Err(not_supported("DELETE")),
+            StatementKind::CreateTable(_) => Err(not_supported("CREATE TABLE")),
+            StatementKind::CreateIndex(create) => self.create_index(create, checkpoint, session),
+            StatementKind::DropIndex(drop) => self.drop_indexes(drop, checkpoint, session),
+            StatementKind::Set(set) => set_parameter(set, session),
+            StatementKind::CreateRole(create) => {
+                self.require_superuser(&session.user, "create role")?;
+                self.catalog.create_role(create.name, &create.options)?;
+                Ok(ResultSet::command("CREATE ROLE"))
+            }
+            StatementKind::AlterRole(alter) => self.alter_role(alter, &session.user),
+            StatementKind::DropRole(drop) => self.drop_role(drop, &session.user),
+            StatementKind::Grant(grant) => self.grant(grant, &session.user, false),
+            StatementKind::Revoke(grant) => self.grant(grant, &session.user, true),
+            StatementKind::Vacuum(table) => {
+                let table = table.as_ref().map(|table| table.dataset.unwrap_or_default());
+                self.vacuum_tables(table, checkpoint, session)
+            }
+        }
+    }
+
+    /// Fails unless `user` holds the privileges the statement needs on objects that exist. Statements on objects
+    /// that do not exist fail on those instead.
+    fn authorize(&self, stmt: &StatementKind, user: &str) -> Result<(), ExecError> {
+        for requirement in requirements(stmt) {
+            match requirement {
+                Requirement::Table {
+                    table,
+                    privilege,
+                    columns,
+                } if self.table_exists(table) => self.catalog.check_table(user, table, privilege, &columns)?,
+                Requirement::Schema { schema, privilege } if schema == DEFAULT_SCHEMA => {
+                    self.catalog.check(user, &Object::Schema(schema.to_string()), privilege)?
+                }
+                Requirement::Owner { table } if self.table_exists(table) => self.catalog.check_owner(user, table)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Locks the target for the session, failing at once with `nowait`.
+    fn lock(
+        &self,
+        target: LockTarget,
+        mode: LockMode,
+        nowait: bool,
+        checkpoint: &Checkpoint,
+        session: &SessionState,
+    ) -> Result<(), ExecError> {
+        let interrupted = || checkpoint.check().is_err();
+        let mut locked = self.locks.lock(session.id, &target, mode, Some(Duration::ZERO), &interrupted);
+        if !nowait && matches!(locked, Err(StorageError::LockNotAvailable(_))) {
+            // Waits may be long, the other sessions on the worker thread go on meanwhile.
+            let wait = || self.locks.lock(session.id, &target, mode, session.lock_timeout, &interrupted);
+            locked = match tokio::runtime::Handle::try_current() {
+                Ok(runtime) if runtime.runtime_flavor() == RuntimeFlavor::MultiThread => block_in_place(wait),
+                _ => wait(),
+            };
+        }
+        locked.map_err(|err| match checkpoint.check() {
+            // The wait was interrupted by the checkpoint.
+            Err(reason) => canceled(reason),
+            Ok(()) => storage_error(err),
+        })
+    }
+
+    /// Rows of a table the vacuum knows of, found through an index when the `WHERE` clause compares the key
+    /// columns of one with constants. Locks each of them under `FOR UPDATE` or `FOR SHARE`.
+    fn select_rows(
+        &self,
+        name: &str,
+        table: &Table,
+        select: &SelectStmt,
+        checkpoint: &Checkpoint,
+        session: &SessionState,
+    ) -> Result<ResultSet, ExecError> {
+        let (selected, columns) = selected_columns(table, select)?;
+        let mut conditions = Vec::new();
+        if let Some(where_clause) = &select.where_clause {
+            for (column, literal) in equalities(&where_clause.expr)? {
+                let i = column_position(table, column)?;
+                let data_type = &table.schema().columns()[i].data_type;
+                let value = literal_value(literal, data_type).ok_or_else(|| {
+                    not_supported(&format!(
+                        "comparing column \"{column}\" of type {data_type} with a constant of another type"
+                    ))
+                })?;
+                conditions.push((i, value));
+            }
+        }
+
+        // Outside of a transaction the statement reads in one of its own.
+        let own;
+        let txn = match &session.transaction {
+            Some(transaction) => transaction.as_ref(),
+            None => {
+                own = self.transactions.begin(IsolationLevel::default());
+                &own
+            }
+        };
+        let keyed: Vec<usize> = conditions.iter().map(|(i, _)| *i).collect();
+        let rows: Vec<(RowId, Vec<Value>)> = match table.equality_index(&keyed) {
+            // Nothing equals NULL.
+            _ if conditions.iter().any(|(_, value)| value.is_null()) => Vec::new(),
+            Some(index) => {
+                let key: Vec<Value> = index
+                    .columns()
+                    .iter()
+                    .map(|column| conditions.iter().find(|(i, _)| i == column).unwrap().1.clone())
+                    .collect();
+                table.lookup_in(txn, index, &key).map_err(storage_error)?
+            }
+            None => table.scan_in(txn).collect::<Result<_, _>>().map_err(storage_error)?,
+        };
+
+        let locking = select.locking_clause.map(|clause| {
+            let mode = match clause.strength {
+                LockStrength::Share => LockMode::Shared,
+                LockStrength::Update => LockMode::Exclusive,
+            };
+            (mode, clause.nowait)
+        });
+        let mut result = Vec::new();
+        for (id, row) in rows {
+            checkpoint.check().map_err(canceled)?;
+            // The index may only cover some of the conditions.
+            if !conditions.iter().all(|(i, value)| row[*i] == *value) {
+                continue;
+            }
+            if let Some((mode, nowait)) = locking {
+                self.lock(LockTarget::Row(name.to_string(), id), mode, nowait, checkpoint, session)?;
+            }
+            result.push(selected.iter().map(|i| row[*i].clone()).collect());
+        }
+        Ok(ResultSet::query(columns, result))
+    }
+
+    /// `CREATE INDEX` on a table the vacuum knows of, system tables cannot be indexed.
+    fn create_index(
+        &self,
+        create: &CreateIndexStmt,
+        checkpoint: &Checkpoint,
+        session: &SessionState,
+    ) -> Result<ResultSet, ExecError> {
+        let name = create.table.dataset.unwrap_or_default();
+        let found = self.vacuum.table(name);
+        if found.is_none() && !is_system_table(name) {
+            return Err(undefined_table(name));
+        }
+        // Like for a table, writes wait while the index is built.
+        self.lock(
+            LockTarget::Table(name.to_string()),
+            LockMode::Shared,
+            false,
+            checkpoint,
+            session,
+        )?;
+        let Some(table) = found else {
+            return Err(ExecError::new(
+                SqlState::WRONG_OBJECT_TYPE,
+                format!("\"{name}\" is not a table"),
+            ));
+        };
+
+        // Index names are unique among all tables.
+        if self.index_table(create.name).is_some() {
+            let message = format!("relation \"{0}\" already exists", create.name);
+            return match create.if_not_exists {
+                true => Ok(ResultSet::command("CREATE INDEX").with_notice(format!("{message}, skipping"))),
+                false => Err(ExecError::new(SqlState::DUPLICATE_TABLE, message)),
+            };
+        }
+        let mut table = table.write().unwrap();
+        let columns = create
+            .columns
+            .iter()
+            .map(|column| {
+                let position = table.schema().columns().iter().position(|schema| schema.name == *column);
+                position
+                    .ok_or_else(|| ExecError::new(SqlState::UNDEFINED_COLUMN, format!("column \"{column}\" does not exist")))
+            })
+            .collect::<Result<Vec<usize>, ExecError>>()?;
+        table
+            .create_index(create.name, create.method, columns, create.unique)
+            .map_err(storage_error)?;
+        Ok(ResultSet::command("CREATE INDEX"))
+    }
+
+    /// `DROP INDEX`, which drops none of the indexes unless all of them exist or `IF EXISTS` is given.
+    fn drop_indexes(
+        &self,
+        drop: &DropIndexStmt,
+        checkpoint: &Checkpoint,
+        session: &SessionState,
+    ) -> Result<ResultSet, ExecError> {
+        let mut result = ResultSet::command("DROP INDEX");
+        let mut found = Vec::new();
+        for name in &drop.names {
+            match self.index_table(name) {
+                Some(table) => found.push((*name, table)),
+                None if drop.if_exists => {
+                    result = result.with_notice(format!("index \"{name}\" does not exist, skipping"));
+                }
+                None => {
+                    return Err(ExecError::new(
+                        SqlState::UNDEFINED_OBJECT,
+                        format!("index \"{name}\" does not exist"),
+                    ));
+                }
+            }
+        }
+
+        for (_, (table, _)) in &found {
+            self.catalog.check_owner(&session.user, table)?;
+        }
+        for (name, (table, indexed)) in found {
+            // Nothing may use the index while it goes away.
+            self.lock(LockTarget::Table(table), LockMode::Exclusive, false, checkpoint, session)?;
+            indexed.write().unwrap().drop_index(name).map_err(storage_error)?;
+        }
+        Ok(result)
+    }
+
+    /// The table with the index, by name.
+    fn index_table(&self, index: &str) -> Option<(String, Arc<RwLock<Table>>)> {
+        self.vacuum
+            .tables()
+            .into_iter()
+            .find(|(_, table)| table.read().unwrap().index(index).is_some())
+    }
+
+    /// `VACUUM` of a table, or of every table without one. Each table gets a notice of what was removed from it.
+    fn vacuum_tables(
+        &self,
+        table: Option<&str>,
+        checkpoint: &Checkpoint,
+        session: &SessionState,
+    ) -> Result<ResultSet, ExecError> {
+        if session.transaction.is_some() {
+            return Err(ExecError::new(
+                SqlState::ACTIVE_SQL_TRANSACTION,
+                "VACUUM cannot run inside a transaction block".to_string(),
+            ));
+        }
+        self.require_superuser(&session.user, "vacuum")?;
+        let tables = match table {
+            None => self.vacuum.tables(),
+            // Their rows have no versions.
+            Some(table) if is_system_table(table) => {
+                return Ok(ResultSet::command("VACUUM")
+                    .with_notice(format!("skipping \"{table}\" --- cannot vacuum system tables")));
+            }
+            Some(table) => match self.vacuum.table(table) {
+                Some(found) => vec![(table.to_string(), found)],
+                None => return Err(undefined_table(table)),
+            },
+        };
+
+        let mut result = ResultSet::command("VACUUM");
+        for (name, table) in tables {
+            checkpoint.check().map_err(canceled)?;
+            // Like writes, it waits while an index is built.
+            self.lock(
+                LockTarget::Table(name.clone()),
+                LockMode::IntentionExclusive,
+                false,
+                checkpoint,
+                session,
+            )?;
+            let table = table.read().unwrap();
+            let notice = match self.vacuum.run(&name, &table, false).map_err(storage_error)? {
+                Some(stats) => format!(
+                    "\"{name}\": removed {0} dead row versions and {1} index entries, compacted {2} of {3} pages",
+                    stats.versions, stats.index_entries, stats.compacted, stats.pages
+                ),
+                None => format!("skipping \"{name}\" --- it is already being vacuumed"),
+            };
+            result = result.with_notice(notice);
+        }
+        Ok(result)
+    }
+
+    /// Whether the table is a system table or one the vacuum knows of.
+    fn table_exists(&self, table: &str) -> bool {
+        is_system_table(table) || self.vacuum.table(table).is_some()
+    }
+
+    fn require_superuser(&self, user: &str, action: &str) -> Result<(), ExecError> {
+        match self.catalog.is_superuser(user) {
+            true => Ok(()),
+            false => Err(ExecError::new(
+                SqlState::INSUFFICIENT_PRIVILEGE,
+                format!("permission denied to {action}"),
+            )),
+        }
+    }
+
+    fn alter_role(&self, alter: &AlterRoleStmt, user: &str) -> Result<ResultSet, ExecError> {
+        // Users may change their own password.
+        let own_password =
+            alter.name == user && alter.options.iter().all(|option| matches!(option, RoleOption::Password(_)));
+        if !own_password {
+            self.require_superuser(user, "alter role")?;
+        }
+        self.catalog.alter_role(alter.name, &alter.options)?;
+        Ok(ResultSet::command("ALTER ROLE"))
+    }
+
+    fn drop_role(&self, drop: &DropRoleStmt, user: &str) -> Result<ResultSet, ExecError> {
+        self.require_superuser(user, "drop role")?;
+        if drop.names.contains(&user) {
+            return Err(ExecError::new(
+                SqlState::OBJECT_IN_USE,
+                "current user cannot be dropped".to_string(),
+            ));
+        }
+        if !drop.if_exists
+            && let Some(name) = drop.names.iter().find(|name| self.catalog.role(name).is_none())
+        {
+            return Err(undefined_role(name));
+        }
+
+        let mut result = ResultSet::command("DROP ROLE");
+        for name in &drop.names {
+            if !self.catalog.drop_role(name) {
+                result = result.with_notice(format!("role \"{name}\" does not exist, skipping"));
+            }
+        }
+        Ok(result)
+    }
+
+    /// `GRANT` or, with `revoke`, `REVOKE`. Privileges on objects are granted by those holding them with the grant
+    /// option, membership in roles only by superusers.
+    fn grant(&self, grant: &GrantStmt, user: &str, revoke: bool) -> Result<ResultSet, ExecError> {
+        let command = if revoke { "REVOKE" } else { "GRANT" };
+        let grantees: Vec<&str> = grant
+            .grantees
+            .iter()
+            .map(|grantee| match grantee {
+                Grantee::Public => PUBLIC,
+                Grantee::Role(role) => role,
+            })
+            .collect();
+
+        let (privileges, object) = match &grant.kind {
+            GrantKind::Privileges { privileges, object } => (privileges, object),
+            GrantKind::Roles(roles) => {
+                for role in roles {
+                    self.require_superuser(user, &format!("grant role \"{role}\""))?;
+                    for grantee in &grantees {
+                        match revoke {
+                            true => self.catalog.revoke_role(role, grantee)?,
+                            false => self.catalog.grant_role(role, grantee)?,
+                        }
+                    }
+                }
+                return Ok(ResultSet::command(command));
+            }
+        };
+
+        // Everything is checked before anything is granted.
+        let mut grants = Vec::new();
+        let (kind, all): (&str, &[PrivilegeKind]) = match object {
+            GrantObject::Tables(_) => (
+                "table",
+                &[
+                    PrivilegeKind::Select,
+                    PrivilegeKind::Insert,
+                    PrivilegeKind::Update,
+                    PrivilegeKind::Delete,
+                ],
+            ),
+            GrantObject::Schemas(_) => ("schema", &[PrivilegeKind::Usage, PrivilegeKind::Create]),
+        };
+        let items: Vec<(PrivilegeKind, &[&str])> = match privileges {
+            Some(items) => items.iter().map(|item| (item.privilege, item.columns.as_slice())).collect(),
+            None => all.iter().map(|privilege| (*privilege, &[][..])).collect(),
+        };
+        for (privilege, columns) in items {
+            let column_privilege = matches!(
+                privilege,
+                PrivilegeKind::Select | PrivilegeKind::Insert | PrivilegeKind::Update
+            );
+            if !all.contains(&privilege) || (!columns.is_empty() && !column_privilege) {
+                let kind = if columns.is_empty() { kind } else { "column" };
+                return Err(ExecError::new(
+                    SqlState::INVALID_GRANT_OPERATION,
+                    format!("invalid privilege type {0} for {kind}", privilege.as_str()),
+                ));
+            }
+
+            let objects: Vec<Object> = match object {
+                GrantObject::Tables(tables) => {
+                    let mut objects = Vec::new();
+                    for table in tables {
+                        let table = table.dataset.unwrap_or_default();
+                        if !self.table_exists(table) {
+                            return Err(undefined_table(table));
+                        }
+                        match columns.is_empty() {
+                            true => objects.push(Object::Table(table.to_string())),
+                            false => objects.extend(
+                                columns
+                                    .iter()
+                                    .map(|column| Object::Column(table.to_string(), column.to_string())),
+                            ),
+                        }
+                    }
+                    objects
+                }
+                GrantObject::Schemas(schemas) => {
+                    let mut objects = Vec::new();
+                    for schema in schemas {
+                        if *schema != DEFAULT_SCHEMA {
+                            return Err(ExecError::new(
+                                SqlState::INVALID_SCHEMA_NAME,
+                                format!("schema \"{schema}\" does not exist"),
+                            ));
+                        }
+                        objects.push(Object::Schema(schema.to_string()));
+                    }
+                    objects
+                }
+            };
+
+            for object in objects {
+                let grantable = match &object {
+                    Object::Column(table, _) => {
+                        self.catalog.has_grant_option(user, &Object::Table(table.clone()), privilege)
+                            || self.catalog.has_grant_option(user, &object, privilege)
+                    }
+                    _ => self.catalog.has_grant_option(user, &object, privilege),
+                };
+                if !grantable {
+                    return Err(permission_denied(&object));
+                }
+                grants.push((object, privilege));
+            }
+        }
+
+        for grantee in &grantees {
+            for (object, privilege) in &grants {
+                match revoke {
+                    true => self.catalog.revoke(grantee, object.clone(), *privilege, grant.grant_option)?,
+                    false => self.catalog.grant(grantee, object.clone(), *privilege, grant.grant_option)?,
+                }
+            }
+        }
+        Ok(ResultSet::command(command))
+    }
+}
+
+fn is_system_table(table: &str) -> bool {
+    table == STAT_STATEMENTS_TABLE || table == LOCKS_TABLE || table == VACUUM_TABLE
+}
+
+/// `SET name = value` for the settings a session can change.
+fn set_parameter(set: &SetStmt, session: &mut SessionState) -> Result<ResultSet, ExecError> {
+    let (setting, default) = match set.name.to_lowercase().as_str() {
+        "statement_timeout" => (&mut session.statement_timeout, session.default_statement_timeout),
+        "lock_timeout" => (&mut session.lock_timeout, session.default_lock_timeout),
+        _ => {
+            return Err(ExecError::new(
+                SqlState::UNDEFINED_OBJECT,
+                format!("unrecognized configuration parameter \"{0}\"", set.name),
+            ));
+        }
+    };
+
+    let timeout = match &set.value {
+        None => default,
+        Some(value) => {
+            let invalid = || {
+                ExecError::new(
+                    SqlState::INVALID_PARAMETER_VALUE,
+                    format!("invalid value for parameter \"{0}\"", set.name),
+                )
+            };
+            // Numbers are milliseconds, strings may carry a unit.
+            let timeout = match value {
+                ExprKind::Literal(LiteralKind::Numeric(n)) if *n >= 0.0 && n.fract() == 0.0 => {
+                    Duration::from_millis(*n as u64)
+                }
+                ExprKind::Literal(LiteralKind::String(s)) => parse_duration(s.trim()).map_err(|_| invalid())?,
+                _ => return Err(invalid()),
+            };
+            Some(timeout).filter(|timeout| !timeout.is_zero())
+        }
+    };
+    *setting = timeout;
+
+    Ok(ResultSet::command("SET"))
+}
+
+fn canceled(reason: CancelReason) -> ExecError {
+    let (code, message) = match reason {
+        CancelReason::UserRequest => (SqlState::QUERY_CANCELED, "canceling statement due to user request"),
+        CancelReason::StatementTimeout => (SqlState::QUERY_CANCELED, "canceling statement due to statement timeout"),
+        CancelReason::Shutdown => (
+            SqlState::ADMIN_SHUTDOWN,
+            "terminating connection due to administrator command",
+        ),
+    };
+    ExecError::new(code, message.to_string())
+}
+
+/// Columns of a system table.
+fn system_columns(table: &str) -> Option<Vec<Column>> {
+    let columns: &[(&str, DataKind)] = match table {
+        STAT_STATEMENTS_TABLE => &STAT_STATEMENTS_COLUMNS,
+        LOCKS_TABLE => &LOCKS_COLUMNS,
+        VACUUM_TABLE => &VACUUM_COLUMNS,
+        _ => return None,
+    };
+    Some(
+        columns
+            .iter()
+            .map(|(name, data_type)| Column::new(name, data_type.clone()))
+            .collect(),
+    )
+}
+
+/// The table the query reads, `None` without one. Queries of several tables, or joins, are not supported yet.
+fn select_table<'a>(select: &SelectStmt<'a>) -> Result<Option<&'a str>, ExecError> {
+    match select.from_clause.from.as_slice() {
+        [] => Ok(None),
+        [FromItemKind::Dataset(dataset)] => Ok(dataset.dataset),
+        [FromItemKind::Join(_)] => Err(not_supported("JOIN")),
+        _ => Err(not_supported("SELECT from more than one table")),
+    }
+}
+
+fn parse_error(err: ParseError, offset: usize) -> ExecError {
+    let code = match err.kind {
+        ParseErrorKind::Syntax => SqlState::SYNTAX_ERROR,
+        ParseErrorKind::TooComplex => SqlState::STATEMENT_TOO_COMPLEX,
+    };
+    ExecError {
+        code,
+        pos: Some(offset + err.pos),
+        message: err.message,
+    }
+}
+
+/// Positions of the columns a query selects from the table, and their description.
+fn selected_columns(table: &Table, select: &SelectStmt) -> Result<(Vec<usize>, Vec<Column>), ExecError> {
+    let mut selected = Vec::new();
+    for item in &select.select_clause.selected {
+        match item {
+            SelectItemKind::All => selected.extend(0..table.schema().columns().len()),
+            SelectItemKind::Identifier(column) => selected.push(column_position(table, column.obj.unwrap_or_default())?),
+            SelectItemKind::Expr(_) => return Err(not_supported("an expression in the select list")),
+        }
+    }
+    let schema = table.schema().columns();
+    let columns = selected
+        .iter()
+        .map(|i| Column::new(&schema[*i].name, schema[*i].data_type.clone()))
+        .collect();
+    Ok((selected, columns))
+}
+
+fn column_position(table: &Table, column: &str) -> Result<usize, ExecError> {
+    let position = table.schema().columns().iter().position(|schema| schema.name == column);
+    position.ok_or_else(|| ExecError::new(SqlState::UNDEFINED_COLUMN, format!("column \"{column}\" does not exist")))
+}
+
+/// The columns a condition compares with constants, which it has to be made of with `AND`.
+fn equalities<'e, 'a>(expr: &'e ExprKind<'a>) -> Result<Vec<(&'a str, &'e LiteralKind<'a>)>, ExecError> {
+    match expr {
+        ExprKind::Binary(left, BinaryOperator::And, right) => {
+            let mut conditions = equalities(left)?;
+            conditions.extend(equalities(right)?);
+            Ok(conditions)
+        }
+        ExprKind::Binary(left, BinaryOperator::Equal, right) => match (left.as_ref(), right.as_ref()) {
+            (ExprKind::Identifier(column), ExprKind::Literal(literal))
+            | (ExprKind::Literal(literal), ExprKind::Identifier(column)) => {
+                Ok(vec![(column.obj.unwrap_or_default(), literal)])
+            }
+            _ => Err(not_supported("a condition other than a column equal to a constant")),
+        },
+        _ => Err(not_supported("a condition other than a column equal to a constant")),
+    }
+}
+
+/// The value of a column of `data_type` that equals the literal, `None` when there is none.
+fn literal_value(literal: &LiteralKind, data_type: &DataKind) -> Option<Value> {
+    match (literal, data_type) {
+        (LiteralKind::Null, _) => Some(Value::Null),
+        (
+            LiteralKind::Numeric(n),
+            DataKind::TinyInt(_)
+            | DataKind::SmallInt(_)
+            | DataKind::MediumInt(_)
+            | DataKind::Integer(_)
+            | DataKind::BigInt(_)
+            | DataKind::Bit(_),
+        ) if n.fract() == 0.0 => Some(Value::Int(*n as i64)),
+        (LiteralKind::Numeric(n), DataKind::Float(..) | DataKind::Double(..)) => Some(Value::Float(*n)),
+        (
+            LiteralKind::String(s),
+            DataKind::Char(_)
+            | DataKind::VarChar(_)
+            | DataKind::TinyText
+            | DataKind::Text(_)
+            | DataKind::MediumText(_)
+            | DataKind::LongText(_),
+        ) => Some(Value::Text(s.to_string())),
+        _ => None,
+    }
+}
+
+fn undefined_table(table: &str) -> ExecError {
+    ExecError::new(SqlState::UNDEFINED_TABLE, format!("table \"{table}\" does not exist"))
+}
+
+fn storage_error(err: StorageError) -> ExecError {
+    let code = match err {
+        StorageError::SerializationFailure(_) => SqlState::SERIALIZATION_FAILURE,
+        StorageError::Deadlock(_) => SqlState::DEADLOCK_DETECTED,
+        StorageError::LockNotAvailable(_) => SqlState::LOCK_NOT_AVAILABLE,
+        StorageError::DuplicateKey(_) => SqlState::UNIQUE_VIOLATION,
+        _ => SqlState::INTERNAL_ERROR,
+    };
+    ExecError::new(code, err.to_string())
+}
+
+fn not_supported(what: &str) -> ExecError {
+    ExecError::new(SqlState::FEATURE_NOT_SUPPORTED, format!("{what} is not supported yet"))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parser::ast::IsolationLevel;
+    use crate::parser::token::DataKind;
+    use crate::storage::buffer::{BufferPool, EvictionPolicyKind};
+    use crate::storage::file::FileManager;
+    use crate::storage::row::{ColumnSchema, Schema};
+    use crate::storage::testing::TempDir;
+    use crate::value::Value;
+
+    fn executor() -> Executor {
+        Executor::new(Arc::new(StatementStats::new()), Catalog::bootstrap("rdb", None))
+    }
+
+    /// A table `cats` of ids and names that the executor knows of, with rows 1 tom and 2 kit.
+    fn cats(executor: &Executor, dir: &TempDir) -> Arc<RwLock<Table>> {
+        let pool = BufferPool::new(
+            Arc::new(FileManager::open(dir.path()).unwrap()),
+            16,
+            EvictionPolicyKind::Clock,
+        );
+        let schema = Schema::new(vec![
+            ColumnSchema::new("id", DataKind::Integer(None), false),
+            ColumnSchema::new("name", DataKind::Text(None), true),
+        ])
+        .unwrap();
+        let table = Arc::new(RwLock::new(Table::create(pool, schema).unwrap()));
+        executor.vacuum().register("cats", &table);
+        let txn = executor.transactions().begin(IsolationLevel::ReadCommitted);
+        for (id, name) in [(1, "tom"), (2, "kit")] {
+            let row = [Value::Int(id), Value::Text(name.to_string())];
+            table.read().unwrap().insert_in(&txn, &row).unwrap();
+        }
+        txn.commit().unwrap();
+        table
+    }
+
+    /// A session of the superuser.
+    fn session() -> SessionState {
+        SessionState {
+            user: "rdb".to_string(),
+            ..SessionState::default()
+        }
+    }
+
+    #[test]
+    fn test_batch_results() {
+        let sql = b"COMMIT; ROLLBACK; SELECT * FROM rdb_stat_statements";
+        let results = executor().execute_batch(sql, BatchMode::StopOnError, &mut session());
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].range, 0..6);
+        assert_eq!(
+            results[0].result,
+            Ok(ResultSet::command("COMMIT").with_notice("there is no transaction in progress".to_string()))
+        );
+        assert_eq!(results[1].result.as_ref().unwrap().command, "ROLLBACK");
+
+        let stats = results[2].result.as_ref().unwrap();
+        assert_eq!((stats.command, stats.rows.len()), ("SELECT", 2));
+        assert_eq!(stats.columns[1], Column::new("query", DataKind::Text(None)));
+        assert!(stats.rows.iter().any(|row| row[1] == Value::Text("COMMIT".to_string())));
+    }
+
+    #[test]
+    fn test_stop_on_error() {
+        let sql = b"COMMIT; SELECT FROM; COMMIT";
+        let results = executor().execute_batch(sql, BatchMode::StopOnError, &mut session());
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].result.is_ok());
+
+        let err = results[1].result.clone().unwrap_err();
+        assert_eq!(err.pos, Some(15));
+    }
+
+    #[test]
+    fn test_statement_too_complex() {
+        let sql = format!("SELECT {0}1{1} FROM t", "(".repeat(100_000), ")".repeat(100_000));
+        let results = executor().execute_batch(sql.as_bytes(), BatchMode::StopOnError, &mut session());
+
+        let err = results[0].result.clone().unwrap_err();
+        assert_eq!(err.code, SqlState::STATEMENT_TOO_COMPLEX);
+    }
+
+    #[test]
+    fn test_continue_on_error() {
+        let sql = b"COMMIT; SELECT * FROM cats; COMMIT";
+        let results = executor().execute_batch(sql, BatchMode::Continue, &mut session());
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            results[1].result,
+            Err(ExecError::new(
+                SqlState::UNDEFINED_TABLE,
+                "table \"cats\" does not exist".to_string()
+            ))
+        );
+        assert!(results[2].result.is_ok());
+    }
+
+    #[test]
+    fn test_indexes() {
+        let sql = b"CREATE INDEX i ON rdb_stat_statements (calls); CREATE UNIQUE INDEX i ON cats (name); \
+                    DROP INDEX i; DROP INDEX IF EXISTS i";
+        let results = executor().execute_batch(sql, BatchMode::Continue, &mut session());
+
+        let codes: Vec<_> = results[..3]
+            .iter()
+            .map(|result| result.result.clone().unwrap_err().code)
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                SqlState::WRONG_OBJECT_TYPE,
+                SqlState::UNDEFINED_TABLE,
+                SqlState::UNDEFINED_OBJECT
+            ]
+        );
+        assert_eq!(
+            results[3].result,
+            Ok(ResultSet::command("DROP INDEX").with_notice("index \"i\" does not exist, skipping".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_table_indexes() {
+        let executor = executor();
+        let dir = TempDir::new();
+        let table = cats(&executor, &dir);
+        let run = |sql: &str| {
+            let results = executor.execute_batch(sql.as_bytes(), BatchMode::StopOnError, &mut session());
+            results.last().unwrap().result.clone().map_err(|err| (err.code, err.message))
+        };
+
+        assert_eq!(
+            run("CREATE UNIQUE INDEX cats_id ON cats (id); CREATE INDEX cats_name ON cats USING hash (name)"),
+            Ok(ResultSet::command("CREATE INDEX"))
+        );
+        let names: Vec<String> = table
+            .read()
+            .unwrap()
+            .indexes()
+            .iter()
+            .map(|index| index.name().to_string())
+            .collect();
+        assert_eq!(names, ["cats_id", "cats_name"]);
+
+        // The indexes hold the rows that were there, and those written since.
+        let txn = executor.transactions().begin(IsolationLevel::ReadCommitted);
+        let row = vec![Value::Int(3), Value::Text("tom".to_string())];
+        table.read().unwrap().insert_in(&txn, &row).unwrap();
+        let found = |index: &str, key: Value| {
+            let table = table.read().unwrap();
+            let rows = table.lookup_in(&txn, table.index(index).unwrap(), &[key]).unwrap();
+            rows.into_iter().map(|(_, row)| row[0].clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(found("cats_id", Value::Int(2)), [Value::Int(2)]);
+        assert_eq!(
+            found("cats_name", Value::Text("tom".to_string())),
+            [Value::Int(1), Value::Int(3)]
+        );
+        txn.commit().unwrap();
+
+        assert_eq!(
+            run("CREATE INDEX cats_id ON cats (name)"),
+            Err((SqlState::DUPLICATE_TABLE, "relation \"cats_id\" already exists".to_string()))
+        );
+        assert_eq!(
+            run("CREATE INDEX IF NOT EXISTS cats_id ON cats (name)").unwrap().notices,
+            ["relation \"cats_id\" already exists, skipping"]
+        );
+        assert_eq!(run("CREATE INDEX i ON cats (age)").unwrap_err().0, SqlState::UNDEFINED_COLUMN);
+        assert_eq!(
+            run("CREATE UNIQUE INDEX i ON cats (name)").unwrap_err().0,
+            SqlState::UNIQUE_VIOLATION
+        );
+        assert_eq!(table.read().unwrap().indexes().len(), 2);
+
+        // None of the indexes is dropped unless all of them exist.
+        assert_eq!(
+            run("DROP INDEX cats_id, i"),
+            Err((SqlState::UNDEFINED_OBJECT, "index \"i\" does not exist".to_string()))
+        );
+        assert_eq!(table.read().unwrap().indexes().len(), 2);
+        assert_eq!(
+            run("DROP INDEX IF EXISTS cats_id, i, cats_name").unwrap().notices,
+            ["index \"i\" does not exist, skipping"]
+        );
+        assert!(table.read().unwrap().indexes().is_empty());
+        assert!(executor.locks().rows().is_empty());
+    }
+
+    #[test]
+    fn test_index_ownership() {
+        let executor = executor();
+        let dir = TempDir::new();
+        let table = cats(&executor, &dir);
+        let mut admin = session();
+        let mut alice = SessionState {
+            user: "alice".to_string(),
+            ..SessionState::default()
+        };
+        let run = |sql: &str, session: &mut SessionState| {
+            let results = executor.execute_batch(sql.as_bytes(), BatchMode::StopOnError, session);
+            results.last().unwrap().result.clone().map_err(|err| (err.code, err.message))
+        };
+        let denied = Err((SqlState::INSUFFICIENT_PRIVILEGE, "must be owner of table cats".to_string()));
+        run("CREATE USER alice; GRANT SELECT ON cats TO alice", &mut admin).unwrap();
+
+        // Privileges on the table are not enough, it takes its owner.
+        assert_eq!(run("CREATE INDEX cats_id ON cats (id)", &mut alice), denied);
+        run("CREATE INDEX cats_id ON cats (id)", &mut admin).unwrap();
+        assert_eq!(run("DROP INDEX cats_id", &mut alice), denied);
+        assert_eq!(table.read().unwrap().indexes().len(), 1);
+
+        executor.catalog().set_owner("cats", "alice").unwrap();
+        run("DROP INDEX cats_id; CREATE INDEX cats_name ON cats (name)", &mut alice).unwrap();
+        assert_eq!(table.read().unwrap().index("cats_name").unwrap().columns(), [1]);
+    }
+
+    #[test]
+    fn test_select_through_index() {
+        let executor = executor();
+        let dir = TempDir::new();
+        let table = cats(&executor, &dir);
+        let run = |sql: &str| {
+            let results = executor.execute_batch(sql.as_bytes(), BatchMode::StopOnError, &mut session());
+            results.last().unwrap().result.clone().map_err(|err| (err.code, err.message))
+        };
+        let scans = |index: &str| table.read().unwrap().index(index).unwrap().scans();
+        run("CREATE INDEX cats_id ON cats (id); CREATE INDEX cats_name ON cats USING hash (name)").unwrap();
+
+        let found = run("SELECT name, id FROM cats WHERE name = 'kit'").unwrap();
+        assert_eq!(
+            found.columns.iter().map(|column| column.name.as_str()).collect::<Vec<_>>(),
+            ["name", "id"]
+        );
+        assert_eq!(found.rows, [vec![Value::Text("kit".to_string()), Value::Int(2)]]);
+        assert_eq!((scans("cats_id"), scans("cats_name")), (0, 1));
+
+        // The hash index is preferred, the other condition filters what it finds.
+        assert!(
+            run("SELECT * FROM cats WHERE 1 = id AND name = 'kit'")
+                .unwrap()
+                .rows
+                .is_empty()
+        );
+        assert_eq!((scans("cats_id"), scans("cats_name")), (0, 2));
+        assert_eq!(run("SELECT * FROM cats WHERE id = 1").unwrap().rows.len(), 1);
+        assert_eq!((scans("cats_id"), scans("cats_name")), (1, 2));
+
+        // Without an index on the columns the table is scanned.
+        run("DROP INDEX cats_name").unwrap();
+        assert_eq!(run("SELECT * FROM cats WHERE name = 'tom'").unwrap().rows.len(), 1);
+        assert_eq!(run("SELECT * FROM cats").unwrap().rows.len(), 2);
+        assert_eq!(scans("cats_id"), 1);
+
+        assert_eq!(run("SELECT * FROM cats WHERE id = NULL").unwrap().rows.len(), 0);
+        assert_eq!(run("SELECT age FROM cats").unwrap_err().0, SqlState::UNDEFINED_COLUMN);
+        assert_eq!(
+            run("SELECT * FROM cats WHERE id = 'tom'").unwrap_err().0,
+            SqlState::FEATURE_NOT_SUPPORTED
+        );
+        assert!(executor.locks().rows().is_empty());
+    }
+
+    #[test]
+    fn test_table_privileges() {
+        let executor = executor();
+        let dir = TempDir::new();
+        let _table = cats(&executor, &dir);
+        let mut admin = session();
+        let mut alice = SessionState {
+            user: "alice".to_string(),
+            ..SessionState::default()
+        };
+        let run = |sql: &str, session: &mut SessionState| {
+            let results = executor.execute_batch(sql.as_bytes(), BatchMode::StopOnError, session);
+            results.last().unwrap().result.clone().map_err(|err| (err.code, err.message))
+        };
+        run("CREATE USER alice", &mut admin).unwrap();
+
+        // Unlike the system tables, nobody reads the others without a grant.
+        assert_eq!(
+            run("SELECT * FROM cats", &mut alice),
+            Err((
+                SqlState::INSUFFICIENT_PRIVILEGE,
+                "permission denied for table cats".to_string()
+            ))
+        );
+        run("GRANT SELECT (name) ON cats TO alice", &mut admin).unwrap();
+        assert_eq!(run("SELECT name FROM cats", &mut alice).unwrap().rows.len(), 2);
+        assert_eq!(
+            run("SELECT * FROM cats WHERE id = 1", &mut alice).unwrap_err().0,
+            SqlState::INSUFFICIENT_PRIVILEGE
+        );
+        run("GRANT SELECT ON cats TO alice", &mut admin).unwrap();
+        assert_eq!(run("SELECT * FROM cats WHERE id = 1", &mut alice).unwrap().rows.len(), 1);
+        run("REVOKE SELECT ON cats FROM alice", &mut admin).unwrap();
+        assert_eq!(
+            run("SELECT id FROM cats", &mut alice).unwrap_err().0,
+            SqlState::INSUFFICIENT_PRIVILEGE
+        );
+    }
+
+    #[test]
+    fn test_several_tables() {
+        let executor = executor();
+        let dir = TempDir::new();
+        let _table = cats(&executor, &dir);
+        let mut session = session();
+        let run = |sql: &str, session: &mut SessionState| {
+            let results = executor.execute_batch(sql.as_bytes(), BatchMode::StopOnError, session);
+            results.last().unwrap().result.clone().map_err(|err| (err.code, err.message))
+        };
+
+        // Rather than rows of the first one only.
+        for sql in ["SELECT * FROM cats, dogs", "SELECT * FROM rdb_locks, cats"] {
+            assert_eq!(
+                run(sql, &mut session),
+                Err((
+                    SqlState::FEATURE_NOT_SUPPORTED,
+                    "SELECT from more than one table is not supported yet".to_string()
+                ))
+            );
+        }
+        assert_eq!(run("SELECT * FROM cats", &mut session).unwrap().rows.len(), 2);
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let mut session = SessionState {
+            txn: TransactionStatus::InTransaction,
+            ..session()
+        };
+        let results = executor().execute_batch(b"SELECT 'caf\xe9' FROM t", BatchMode::Continue, &mut session);
+
+        assert_eq!(results.len(), 1);
+        let err = results[0].result.clone().unwrap_err();
+        assert_eq!((err.code, err.pos), (SqlState::CHARACTER_NOT_IN_REPERTOIRE, Some(11)));
+        assert_eq!(session.txn, TransactionStatus::Failed);
+    }
+
+    #[test]
+    fn test_records_stats() {
+        let stats = Arc::new(StatementStats::new());
+        let executor = Executor::new(stats.clone(), Catalog::bootstrap("rdb", None));
+
+        executor.execute_batch(b"COMMIT; commit; ROLLBACK", BatchMode::Continue, &mut session());
+
+        let entries = stats.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].query.as_str(), entries[0].calls), ("COMMIT", 2));
+    }
+
+    #[test]
+    fn test_transaction_status() {
+        let executor = executor();
+        let mut session = SessionState::default();
+
+        executor.execute_batch(b"BEGIN", BatchMode::StopOnError, &mut session);
+        assert_eq!(session.txn, TransactionStatus::InTransaction);
+
+        executor.execute_batch(b"SELECT * FROM cats", BatchMode::StopOnError, &mut session);
+        assert_eq!(session.txn, TransactionStatus::Failed);
+
+        let sql = b"SELECT * FROM rdb_stat_statements";
+        let results = executor.execute_batch(sql, BatchMode::StopOnError, &mut session);
+        let err = results[0].result.clone().unwrap_err();
+        assert_eq!(err.code, SqlState::IN_FAILED_SQL_TRANSACTION);
+
+        let results = executor.execute_batch(b"COMMIT", BatchMode::StopOnError, &mut session);
+        assert_eq!(results[0].result, Ok(ResultSet::command("ROLLBACK")));
+        assert_eq!(session.txn, TransactionStatus::Idle);
+        assert!(session.transaction.is_none());
+    }
+
+    #[test]
+    fn test_isolation_levels() {
+        let executor = executor();
+        let mut session = SessionState::default();
+
+        let sql = b"BEGIN ISOLATION LEVEL SERIALIZABLE; BEGIN ISOLATION LEVEL READ COMMITTED";
+        let results = executor.execute_batch(sql, BatchMode::StopOnError, &mut session);
+        assert_eq!(results.len(), 2);
+        let transaction = session.transaction.clone().unwrap();
+        assert_eq!(transaction.isolation(), IsolationLevel::Serializable);
+        assert_eq!(executor.transactions().running(), 1);
+        // It only reads, so it gets no id.
+        assert_eq!(transaction.xid(), None);
+
+        executor.execute_batch(b"COMMIT", BatchMode::StopOnError, &mut session);
+        assert_eq!(executor.transactions().running(), 0);
+        executor.execute_batch(b"BEGIN; ROLLBACK", BatchMode::StopOnError, &mut session);
+        assert_eq!(executor.transactions().running(), 0);
+
+        // A session that goes away rolls back its transaction.
+        executor.execute_batch(b"BEGIN", BatchMode::StopOnError, &mut session);
+        assert_eq!(
+            session.transaction.as_ref().unwrap().isolation(),
+            IsolationLevel::ReadCommitted
+        );
+        assert_eq!(executor.transactions().running(), 1);
+        drop((session, transaction));
+        assert_eq!(executor.transactions().running(), 0);
+    }
+
+    #[test]
+    fn test_statement_timeout() {
+        let executor = executor();
+        let mut session = SessionState::new(1, Some(Duration::from_secs(60)), CancelFlag::new());
+
+        let sql = b"SET statement_timeout = '1ms'; SET statement_timeout TO 0; SET statement_timeout = DEFAULT";
+        let results = executor.execute_batch(sql, BatchMode::StopOnError, &mut session);
+        assert_eq!(results.len(), 3);
+        assert_eq!(session.statement_timeout, Some(Duration::from_secs(60)));
+
+        executor.execute_batch(b"SET statement_timeout = 1", BatchMode::StopOnError, &mut session);
+        assert_eq!(session.statement_timeout, Some(Duration::from_millis(1)));
+
+        // Runs out before the first checkpoint.
+        session.statement_timeout = Some(Duration::from_nanos(1));
+        let results = executor.execute_batch(b"COMMIT", BatchMode::StopOnError, &mut session);
+        let err = results[0].result.clone().unwrap_err();
+        assert_eq!(err.code, SqlState::QUERY_CANCELED);
+        assert_eq!(err.message, "canceling statement due to statement timeout");
+
+        session.statement_timeout = None;
+        let results = executor.execute_batch(b"SET search_path = public", BatchMode::StopOnError, &mut session);
+        assert_eq!(results[0].result.clone().unwrap_err().code, SqlState::UNDEFINED_OBJECT);
+        let results = executor.execute_batch(b"SET statement_timeout = 'soon'", BatchMode::StopOnError, &mut session);
+        assert_eq!(results[0].result.clone().unwrap_err().code, SqlState::INVALID_PARAMETER_VALUE);
+    }
+
+    #[test]
+    fn test_locks() {
+        let executor = executor();
+        let dir = TempDir::new();
+        let _table = cats(&executor, &dir);
+        let run = |sql: &str, session: &mut SessionState| {
+            let results = executor.execute_batch(sql.as_bytes(), BatchMode::StopOnError, session);
+            results.last().unwrap().result.clone().map_err(|err| (err.code, err.message))
+        };
+        let mut a = SessionState { id: 1, ..session() };
+        let mut b = SessionState { id: 2, ..session() };
+
+        // Rows selected for update stay locked until the transaction ends, and can still be read.
+        run("BEGIN; SELECT * FROM cats WHERE id = 1 FOR UPDATE", &mut a).unwrap();
+        let read = run("SELECT name FROM cats", &mut b).unwrap();
+        assert_eq!(read.rows.len(), 2);
+        let locks = run("SELECT * FROM rdb_locks", &mut b).unwrap();
+        let held = |session: i64, kind: &str| {
+            let lock = locks.rows.iter().find(|row| {
+                row[0] == Value::Int(session)
+                    && row[1] == Value::Text(kind.to_string())
+                    && row[2] == Value::Text("cats".to_string())
+            });
+            lock.map(|row| row[4].to_string())
+        };
+        assert_eq!(held(1, "table").as_deref(), Some("IX"));
+        assert_eq!(held(1, "row").as_deref(), Some("X"));
+        // The locks of statements outside of transactions go with them.
+        assert_eq!(held(2, "table"), None);
+
+        // Other rows can be locked, the locked one cannot.
+        run("SELECT * FROM cats WHERE id = 2 FOR UPDATE", &mut b).unwrap();
+        let err = run("SELECT * FROM cats WHERE id = 1 FOR SHARE NOWAIT", &mut b).unwrap_err();
+        assert_eq!(err.0, SqlState::LOCK_NOT_AVAILABLE);
+        assert!(err.1.starts_with("could not obtain lock on row "), "{}", err.1);
+        let err = run("SET lock_timeout = 20; SELECT * FROM cats FOR UPDATE", &mut b).unwrap_err();
+        assert_eq!(err.0, SqlState::LOCK_NOT_AVAILABLE);
+        run("COMMIT", &mut a).unwrap();
+        run("SELECT * FROM cats FOR UPDATE", &mut b).unwrap();
+        assert!(executor.locks().rows().is_empty());
+
+        // Rows of system tables are not locked.
+        assert_eq!(
+            run("SELECT * FROM rdb_locks FOR UPDATE", &mut b).unwrap_err().0,
+            SqlState::FEATURE_NOT_SUPPORTED
+        );
+
+        // Sessions locking each other's rows, the one that asked last fails.
+        b.lock_timeout = None;
+        run("BEGIN; SELECT * FROM cats WHERE id = 1 FOR UPDATE", &mut a).unwrap();
+        run("BEGIN; SELECT * FROM cats WHERE id = 2 FOR UPDATE", &mut b).unwrap();
+        std::thread::scope(|scope| {
+            let first = scope.spawn(|| run("SELECT * FROM cats WHERE id = 2 FOR SHARE", &mut a));
+            while executor.locks().rows().iter().all(|row| row[5] == Value::Bool(true)) {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            let err = run("SELECT * FROM cats WHERE id = 1 FOR SHARE", &mut b).unwrap_err();
+            assert_eq!(err.0, SqlState::DEADLOCK_DETECTED);
+            assert!(
+                err.1.starts_with("deadlock detected: session 1 waits for S lock on row ")
+                    && err.1.contains("of table \"cats\", blocked by session 2"),
+                "{}",
+                err.1
+            );
+            executor.end_session(&mut b);
+            first.join().unwrap().unwrap();
+        });
+        run("ROLLBACK", &mut a).unwrap();
+        assert!(executor.locks().rows().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_lock_waits_leave_workers() {
+        let executor = Arc::new(executor());
+        let dir = TempDir::new();
+        let _table = cats(&executor, &dir);
+        let run = |executor: &Executor, sql: &str, session: &mut SessionState| {
+            let results = executor.execute_batch(sql.as_bytes(), BatchMode::StopOnError, session);
+            results.last().unwrap().result.clone().map(|_| ()).map_err(|err| err.code)
+        };
+        let mut holder = SessionState { id: 1, ..session() };
+        run(&executor, "BEGIN; SELECT * FROM cats FOR UPDATE", &mut holder).unwrap();
+
+        // More sessions wait than there are worker threads, yet the holder still gets to commit.
+        let waiters: Vec<_> = (2..6)
+            .map(|id| {
+                let executor = executor.clone();
+                tokio::spawn(async move {
+                    let mut session = SessionState {
+                        id,
+                        lock_timeout: Some(Duration::from_secs(2)),
+                        ..session()
+                    };
+                    run(&executor, "SELECT * FROM cats FOR SHARE", &mut session)
+                })
+            })
+            .collect();
+        let releaser = tokio::spawn({
+            let executor = executor.clone();
+            async move {
+                let start = Instant::now();
+                let waiting = || executor.locks().rows().into_iter().filter(|row| row[5] == Value::Bool(false));
+                while waiting().count() < 4 && start.elapsed() < Duration::from_secs(10) {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+                run(&executor, "COMMIT", &mut holder)
+            }
+        });
+
+        releaser.await.unwrap().unwrap();
+        for waiter in waiters {
+            assert_eq!(waiter.await.unwrap(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_vacuum() {
+        let executor = executor();
+        let dir = TempDir::new();
+        let pool = BufferPool::new(
+            Arc::new(FileManager::open(dir.path()).unwrap()),
+            16,
+            EvictionPolicyKind::Clock,
+        );
+        let schema = Schema::new(vec![ColumnSchema::new("id", DataKind::Integer(None), false)]).unwrap();
+        let table = Arc::new(RwLock::new(Table::create(pool, schema).unwrap()));
+        executor.vacuum().register("cats", &table);
+        let txn = executor.transactions().begin(IsolationLevel::ReadCommitted);
+        let id = table.read().unwrap().insert_in(&txn, &[Value::Int(1)]).unwrap();
+        table.read().unwrap().delete_in(&txn, id).unwrap();
+        txn.commit().unwrap();
+
+        let run = |sql: &str, session: &mut SessionState| {
+            let results = executor.execute_batch(sql.as_bytes(), BatchMode::StopOnError, session);
+            results.last().unwrap().result.clone().map_err(|err| (err.code, err.message))
+        };
+        let mut session = session();
+        let vacuumed = run("VACUUM cats", &mut session).unwrap();
+        assert_eq!(
+            vacuumed.notices,
+            vec!["\"cats\": removed 1 dead row versions and 0 index entries, compacted 1 of 1 pages".to_string()]
+        );
+        assert_eq!(table.read().unwrap().scan().count(), 0);
+        assert_eq!(run("VACUUM", &mut session).unwrap().notices.len(), 1);
+        assert_eq!(
+            run("VACUUM rdb_locks", &mut session).unwrap().notices,
+            vec!["skipping \"rdb_locks\" --- cannot vacuum system tables".to_string()]
+        );
+        assert_eq!(run("VACUUM dogs", &mut session).unwrap_err().0, SqlState::UNDEFINED_TABLE);
+        assert_eq!(
+            run("BEGIN; VACUUM cats", &mut session),
+            Err((
+                SqlState::ACTIVE_SQL_TRANSACTION,
+                "VACUUM cannot run inside a transaction block".to_string()
+            ))
+        );
+        run("ROLLBACK", &mut session).unwrap();
+
+        // Anybody can watch the progress of vacuums, only superusers start them.
+        run("CREATE ROLE alice LOGIN", &mut session).unwrap();
+        let mut alice = SessionState {
+            user: "alice".to_string(),
+            ..SessionState::default()
+        };
+        let progress = run("SELECT * FROM rdb_stat_progress_vacuum", &mut alice).unwrap();
+        assert_eq!(progress.columns[1], Column::new("phase", DataKind::Text(None)));
+        assert!(progress.rows.is_empty());
+        assert_eq!(run("VACUUM", &mut alice).unwrap_err().0, SqlState::INSUFFICIENT_PRIVILEGE);
+
+        drop(table);
+        assert!(run("VACUUM", &mut session).unwrap().notices.is_empty());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let executor = executor();
+        let registration = executor.register_session();
+        let mut session = SessionState::new(registration.id, None, registration.flag.clone());
+
+        // Only stops statements that are running, checked here at the first checkpoint of the parser.
+        let sql = b"SELECT * FROM rdb_stat_statements";
+        let flag = registration.flag.clone();
+        let checkpoint = Checkpoint::new(flag.clone(), None);
+        assert!(executor.cancel(registration.id, registration.secret));
+        let err = executor
+            .execute_sql(sql, 0, &GenericDialect, &checkpoint, &mut session)
+            .unwrap_err();
+        assert_eq!(err.code, SqlState::QUERY_CANCELED);
+        assert_eq!(err.message, "canceling statement due to user request");
+
+        // A request from before the batch is forgotten.
+        let results = executor.execute_batch(sql, BatchMode::StopOnError, &mut session);
+        assert!(results[0].result.is_ok());
+    }
+
+    #[test]
+    fn test_privileges() {
+        let stats = Arc::new(StatementStats::new());
+        let executor = Executor::new(stats.clone(), Catalog::bootstrap("rdb", None));
+        let mut admin = session();
+        let mut alice = SessionState {
+            user: "alice".to_string(),
+            ..SessionState::default()
+        };
+        let run = |sql: &str, session: &mut SessionState| {
+            let results = executor.execute_batch(sql.as_bytes(), BatchMode::StopOnError, session);
+            results.last().unwrap().result.clone().map_err(|err| (err.code, err.message))
+        };
+        let denied = |message: &str| Err((SqlState::INSUFFICIENT_PRIVILEGE, message.to_string()));
+
+        run("CREATE USER alice PASSWORD 'hunter2'", &mut admin).unwrap();
+        assert!(stats.entries().iter().all(|entry| !entry.query.contains("hunter2")));
+
+        // Everyone reads the statistics until the privilege is revoked from PUBLIC.
+        run("SELECT * FROM rdb_stat_statements", &mut alice).unwrap();
+        run("REVOKE SELECT ON rdb_stat_statements FROM PUBLIC", &mut admin).unwrap();
+        assert_eq!(
+            run("SELECT * FROM rdb_stat_statements", &mut alice),
+            denied("permission denied for table rdb_stat_statements")
+        );
+
+        run("GRANT SELECT (query, calls) ON rdb_stat_statements TO alice", &mut admin).unwrap();
+        run("SELECT query, calls FROM rdb_stat_statements WHERE calls > 1", &mut alice).unwrap();
+        assert!(run("SELECT query, rows FROM rdb_stat_statements", &mut alice).is_err());
+        assert_eq!(
+            run("GRANT SELECT (query) ON rdb_stat_statements TO PUBLIC", &mut alice),
+            denied("permission denied for column query")
+        );
+
+        assert_eq!(run("CREATE ROLE bob", &mut alice), denied("permission denied to create role"));
+        assert_eq!(
+            run("ALTER ROLE alice SUPERUSER", &mut alice),
+            denied("permission denied to alter role")
+        );
+        run("ALTER USER alice PASSWORD 'swordfish'", &mut alice).unwrap();
+
+        let code = |result: Result<ResultSet, (SqlState, String)>| result.unwrap_err().0;
+        assert_eq!(
+            code(run("GRANT SELECT ON cats TO alice", &mut admin)),
+            SqlState::UNDEFINED_TABLE
+        );
+        assert_eq!(
+            code(run("GRANT USAGE ON rdb_stat_statements TO alice", &mut admin)),
+            SqlState::INVALID_GRANT_OPERATION
+        );
+        assert_eq!(code(run("DROP ROLE rdb", &mut admin)), SqlState::OBJECT_IN_USE);
+        assert_eq!(code(run("DROP ROLE bob", &mut admin)), SqlState::UNDEFINED_OBJECT);
+        let dropped = run("DROP ROLE IF EXISTS alice, bob", &mut admin).unwrap();
+        assert_eq!(dropped.notices, vec!["role \"bob\" does not exist, skipping".to_string()]);
+    }
+}
@@ -0,0 +1,234 @@
+//! Statements parsed once and executed many times with different parameters.
+
+use crate::executor::{ExecError, SqlState, parse_error};
+use crate::parser::Parser;
+use crate::parser::ast::{AST, ExprKind, StatementKind};
+use crate::parser::fingerprint::max_placeholder;
+use crate::parser::token::LiteralKind;
+use crate::parser::visitor::{Fold, fold_expr};
+use self_cell::self_cell;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+self_cell!(
+    /// SQL text together with the tree parsed from it, which borrows the text.
+    struct ParsedSql {
+        owner: String,
+
+        #[covariant]
+        dependent: AST,
+    }
+
+    impl {Debug}
+);
+
+/// Value bound to a placeholder.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParamValue {
+    Null,
+    Text(String),
+    Numeric(f64),
+}
+
+impl ParamValue {
+    fn as_literal(&self) -> LiteralKind<'_> {
+        match self {
+            ParamValue::Null => LiteralKind::Null,
+            ParamValue::Text(text) => LiteralKind::String(text),
+            ParamValue::Numeric(n) => LiteralKind::Numeric(*n),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PreparedStatement {
+    parsed: ParsedSql,
+    param_count: usize,
+}
+
+impl PreparedStatement {
+    /// Parses `sql`, which may hold at most one statement.
+    pub fn parse(sql: String) -> Result<Self, ExecError> {
+        let parsed = ParsedSql::try_new(sql, |sql| {
+            Parser::new(sql.as_bytes()).parse().map_err(|err| parse_error(err, 0))
+        })?;
+
+        let stmts = &parsed.borrow_dependent().stmts;
+        if stmts.len() > 1 {
+            return Err(ExecError::new(
+                SqlState::SYNTAX_ERROR,
+                "cannot insert multiple commands into a prepared statement".to_string(),
+            ));
+        }
+
+        let param_count = stmts.first().map_or(0, max_placeholder);
+        Ok(PreparedStatement { parsed, param_count })
+    }
+
+    pub fn sql(&self) -> &str {
+        self.parsed.borrow_owner()
+    }
+
+    /// The statement, `None` when the text was empty.
+    pub fn stmt(&self) -> Option<&StatementKind<'_>> {
+        self.parsed.borrow_dependent().stmts.first()
+    }
+
+    /// Number of values [`PreparedStatement::bind`] expects.
+    pub fn param_count(&self) -> usize {
+        self.param_count
+    }
+
+    /// The statement with every placeholder replaced by its value.
+    pub fn bind<'s>(&'s self, params: &'s [ParamValue]) -> Result<Option<StatementKind<'s>>, ExecError> {
+        if params.len() != self.param_count {
+            return Err(ExecError::new(
+                SqlState::PROTOCOL_VIOLATION,
+                format!(
+                    "bind message supplies {0} parameters, but prepared statement requires {1}",
+                    params.len(),
+                    self.param_count
+                ),
+            ));
+        }
+
+        let literals: Vec<LiteralKind<'s>> = params.iter().map(ParamValue::as_literal).collect();
+        Ok(self.stmt().map(|stmt| Binder { params: &literals }.fold_stmt(stmt.clone())))
+    }
+}
+
+struct Binder<'p, 's> {
+    params: &'p [LiteralKind<'s>],
+}
+
+impl<'s> Fold<'s> for Binder<'_, 's> {
+    fn fold_expr(&mut self, expr: ExprKind<'s>) -> ExprKind<'s> {
+        match expr {
+            // Positions were checked against the parameter count when binding.
+            ExprKind::Placeholder(n) => ExprKind::Literal(self.params[n - 1].clone()),
+            _ => fold_expr(self, expr),
+        }
+    }
+}
+
+/// Recently prepared statements keyed by their text, so a statement prepared again is not parsed again.
+#[derive(Debug)]
+pub struct StatementCache {
+    capacity: usize,
+    entries: HashMap<String, Arc<PreparedStatement>>,
+    /// Least recently used first.
+    order: VecDeque<String>,
+    hits: u64,
+}
+
+impl StatementCache {
+    pub fn new(capacity: usize) -> Self {
+        StatementCache {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            hits: 0,
+        }
+    }
+
+    pub fn prepare(&mut self, sql: &str) -> Result<Arc<PreparedStatement>, ExecError> {
+        if let Some(stmt) = self.entries.get(sql) {
+            self.hits += 1;
+            let stmt = stmt.clone();
+            if let Some(i) = self.order.iter().position(|s| s == sql) {
+                let key = self.order.remove(i).unwrap();
+                self.order.push_back(key);
+            }
+            return Ok(stmt);
+        }
+
+        let stmt = Arc::new(PreparedStatement::parse(sql.to_string())?);
+        if self.capacity > 0 {
+            if self.entries.len() == self.capacity
+                && let Some(evicted) = self.order.pop_front()
+            {
+                self.entries.remove(&evicted);
+            }
+            self.entries.insert(sql.to_string(), stmt.clone());
+            self.order.push_back(sql.to_string());
+        }
+
+        Ok(stmt)
+    }
+
+    /// Number of [`StatementCache::prepare`] calls answered without parsing.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parser::ast::WhereClause;
+    use crate::parser::ast::{BinaryOperator, ObjectReference};
+
+    #[test]
+    fn test_bind() {
+        let stmt = PreparedStatement::parse("SELECT * FROM cats WHERE name = $1 AND age > $2".to_string()).unwrap();
+        assert_eq!(stmt.param_count(), 2);
+
+        let params = vec![ParamValue::Text("tom".to_string()), ParamValue::Numeric(3.0)];
+        let bound = stmt.bind(&params).unwrap().unwrap();
+
+        let StatementKind::Select(select) = bound else {
+            panic!("expected a select");
+        };
+        let Some(WhereClause {
+            expr: ExprKind::Binary(left, BinaryOperator::And, right),
+        }) = select.where_clause
+        else {
+            panic!("expected a conjunction");
+        };
+        assert_eq!(
+            *left,
+            ExprKind::Binary(
+                Box::new(ExprKind::Identifier(ObjectReference::new("name"))),
+                BinaryOperator::Equal,
+                Box::new(ExprKind::Literal(LiteralKind::String("tom")))
+            )
+        );
+        assert!(matches!(*right, ExprKind::Binary(_, _, value) if *value == ExprKind::Literal(LiteralKind::Numeric(3.0))));
+
+        let err = stmt.bind(&params[..1]).unwrap_err();
+        assert_eq!(err.code, SqlState::PROTOCOL_VIOLATION);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = PreparedStatement::parse("COMMIT; ROLLBACK".to_string()).unwrap_err();
+        assert_eq!(err.message, "cannot insert multiple commands into a prepared statement");
+
+        let err = PreparedStatement::parse("SELECT FROM".to_string()).unwrap_err();
+        assert_eq!(err.code, SqlState::SYNTAX_ERROR);
+
+        assert!(PreparedStatement::parse(String::new()).unwrap().stmt().is_none());
+    }
+
+    #[test]
+    fn test_cache() {
+        let mut cache = StatementCache::new(2);
+
+        let first = cache.prepare("COMMIT").unwrap();
+        let again = cache.prepare("COMMIT").unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+        assert_eq!(cache.hits(), 1);
+
+        cache.prepare("ROLLBACK").unwrap();
+        cache.prepare("COMMIT").unwrap();
+        // Evicts ROLLBACK, the least recently used.
+        cache.prepare("BEGIN").unwrap();
+        cache.prepare("COMMIT").unwrap();
+        assert_eq!(cache.hits(), 3);
+        cache.prepare("ROLLBACK").unwrap();
+        assert_eq!(cache.hits(), 3);
+
+        assert!(cache.prepare("SELECT FROM").is_err());
+    }
+}
@@ -0,0 +1,193 @@
+//! Privileges a statement needs on the objects it reads and writes.
+
+use crate::catalog::DEFAULT_SCHEMA;
+use crate::parser::ast::{ExprKind, FromItemKind, ObjectReference, PrivilegeKind, SelectItemKind, StatementKind};
+use crate::parser::visitor::{Visitor, walk_select_clause};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Requirement<'a> {
+    /// The privilege on every one of `columns`, or on the table as a whole when there are none.
+    Table {
+        table: &'a str,
+        privilege: PrivilegeKind,
+        columns: Vec<&'a str>,
+    },
+    Schema {
+        schema: &'a str,
+        privilege: PrivilegeKind,
+    },
+    /// Being the owner of the table.
+    Owner {
+        table: &'a str,
+    },
+}
+
+/// What the statement needs to run. Statements that only change the session or the roles need nothing here, the
+/// executor checks those itself, as it does for `DROP INDEX` once it found the tables of the indexes.
+pub fn requirements<'a>(stmt: &StatementKind<'a>) -> Vec<Requirement<'a>> {
+    let mut required = Vec::new();
+    let mut table = |table: Option<&'a str>, privilege, columns| {
+        if let Some(table) = table {
+            required.push(Requirement::Table {
+                table,
+                privilege,
+                columns,
+            });
+        }
+    };
+
+    match stmt {
+        StatementKind::Select(select) => {
+            let datasets: Vec<&str> = select
+                .from_clause
+                .from
+                .iter()
+                .filter_map(|item| match item {
+                    FromItemKind::Dataset(dataset) => dataset.dataset,
+                    FromItemKind::Join(_) => None,
+                })
+                .collect();
+            // `*` reads every column, which takes the privilege on the table. So do columns of several tables,
+            // which are not told apart.
+            let every_column = select.select_clause.selected.contains(&SelectItemKind::All) || datasets.len() > 1;
+            let columns = match every_column {
+                true => Vec::new(),
+                false => {
+                    let mut columns = ColumnCollector::default();
+                    walk_select_clause(&mut columns, &select.select_clause);
+                    if let Some(clause) = &select.where_clause {
+                        columns.visit_expr(&clause.expr);
+                    }
+                    columns.0
+                }
+            };
+            for dataset in datasets {
+                table(Some(dataset), PrivilegeKind::Select, columns.clone());
+            }
+        }
+        StatementKind::Insert(insert) => {
+            table(insert.table.dataset, PrivilegeKind::Insert, insert.columns.clone());
+            let read = ColumnCollector::of(insert.values.iter().flatten());
+            if !read.is_empty() {
+                table(insert.table.dataset, PrivilegeKind::Select, read);
+            }
+        }
+        StatementKind::Update(update) => {
+            let written = update.assignments.iter().map(|assignment| assignment.column).collect();
+            table(update.table.dataset, PrivilegeKind::Update, written);
+            let values = update.assignments.iter().map(|assignment| &assignment.value);
+            let read = ColumnCollector::of(values.chain(update.where_clause.iter().map(|clause| &clause.expr)));
+            if !read.is_empty() {
+                table(update.table.dataset, PrivilegeKind::Select, read);
+            }
+        }
+        StatementKind::Delete(delete) => {
+            table(delete.table.dataset, PrivilegeKind::Delete, Vec::new());
+            let read = ColumnCollector::of(delete.where_clause.iter().map(|clause| &clause.expr));
+            if !read.is_empty() {
+                table(delete.table.dataset, PrivilegeKind::Select, read);
+            }
+        }
+        StatementKind::CreateTable(create) => required.push(Requirement::Schema {
+            schema: create.table.schema.unwrap_or(DEFAULT_SCHEMA),
+            privilege: PrivilegeKind::Create,
+        }),
+        StatementKind::CreateIndex(create) => {
+            if let Some(table) = create.table.dataset {
+                required.push(Requirement::Owner { table });
+            }
+        }
+        _ => {}
+    }
+
+    required
+}
+
+/// Names of the columns an expression refers to, each once.
+#[derive(Default)]
+struct ColumnCollector<'a>(Vec<&'a str>);
+
+impl<'a> ColumnCollector<'a> {
+    fn of<'b>(exprs: impl Iterator<Item = &'b ExprKind<'a>>) -> Vec<&'a str>
+    where
+        'a: 'b,
+    {
+        let mut columns = ColumnCollector::default();
+        for expr in exprs {
+            columns.visit_expr(expr);
+        }
+        columns.0
+    }
+}
+
+impl<'a> Visitor<'a> for ColumnCollector<'a> {
+    fn visit_object_reference(&mut self, obj: &ObjectReference<'a>) {
+        if let Some(column) = obj.obj
+            && !self.0.contains(&column)
+        {
+            self.0.push(column);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parser::Parser;
+
+    fn required(sql: &str) -> Vec<Requirement<'_>> {
+        let ast = Parser::new(sql.as_bytes()).parse().unwrap();
+        requirements(&ast.stmts[0])
+    }
+
+    fn on_table<'a>(table: &'a str, privilege: PrivilegeKind, columns: &[&'a str]) -> Requirement<'a> {
+        Requirement::Table {
+            table,
+            privilege,
+            columns: columns.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_requirements() {
+        assert_eq!(
+            required("SELECT name FROM cats WHERE age > 2 AND name <> 'tom'"),
+            vec![on_table("cats", PrivilegeKind::Select, &["name", "age"])]
+        );
+        assert_eq!(
+            required("SELECT * FROM cats"),
+            vec![on_table("cats", PrivilegeKind::Select, &[])]
+        );
+        assert_eq!(
+            required("SELECT name FROM cats, dogs"),
+            vec![
+                on_table("cats", PrivilegeKind::Select, &[]),
+                on_table("dogs", PrivilegeKind::Select, &[])
+            ]
+        );
+        assert_eq!(
+            required("UPDATE cats SET age = age + 1 WHERE name = 'tom'"),
+            vec![
+                on_table("cats", PrivilegeKind::Update, &["age"]),
+                on_table("cats", PrivilegeKind::Select, &["age", "name"])
+            ]
+        );
+        assert_eq!(
+            required("DELETE FROM cats"),
+            vec![on_table("cats", PrivilegeKind::Delete, &[])]
+        );
+        assert_eq!(
+            required("CREATE TABLE cats (name TEXT)"),
+            vec![Requirement::Schema {
+                schema: "public",
+                privilege: PrivilegeKind::Create
+            }]
+        );
+        assert_eq!(
+            required("CREATE INDEX cats_name ON cats (name)"),
+            vec![Requirement::Owner { table: "cats" }]
+        );
+        assert_eq!(required("BEGIN"), vec![]);
+    }
+}
@@ -0,0 +1,62 @@
+//! Results of executed statements.
+
+use crate::parser::token::DataKind;
+use crate::value::Value;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub data_type: DataKind,
+}
+
+impl Column {
+    pub fn new(name: &str, data_type: DataKind) -> Self {
+        Column {
+            name: name.to_string(),
+            data_type,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResultSet {
+    /// Statement that produced the result, e.g. `SELECT` or `CREATE TABLE`.
+    pub command: &'static str,
+    /// Empty when the statement returns no rows.
+    pub columns: Vec<Column>,
+    pub rows: Vec<Vec<Value>>,
+    /// Rows inserted, updated or deleted.
+    pub affected_rows: u64,
+    /// Messages for the client that are not errors.
+    pub notices: Vec<String>,
+}
+
+impl ResultSet {
+    /// Result of a statement that returns no rows.
+    pub fn command(command: &'static str) -> Self {
+        ResultSet {
+            command,
+            columns: Vec::new(),
+            rows: Vec::new(),
+            affected_rows: 0,
+            notices: Vec::new(),
+        }
+    }
+
+    pub fn query(columns: Vec<Column>, rows: Vec<Vec<Value>>) -> Self {
+        ResultSet {
+            columns,
+            rows,
+            ..ResultSet::command("SELECT")
+        }
+    }
+
+    pub fn with_notice(mut self, notice: String) -> Self {
+        self.notices.push(notice);
+        self
+    }
+
+    pub fn returns_rows(&self) -> bool {
+        !self.columns.is_empty()
+    }
+}
@@ -0,0 +1,13 @@
+pub mod auth;
+pub mod cancel;
+pub mod catalog;
+pub mod config;
+pub mod executor;
+pub mod log;
+pub mod parser;
+pub mod protocol;
+pub mod server;
+pub mod stats;
+pub mod storage;
+pub mod tls;
+pub mod value;
@@ -1,48 +1,179 @@
-use crate::parser::Parser;
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpListener,
-};
-
-pub mod parser;
+use rdb::auth::generate_password;
+use rdb::catalog::Catalog;
+use rdb::config::{Args, AuthMethod, Config, USAGE};
+use rdb::executor::Executor;
+use rdb::server::Server;
+use rdb::stats::StatementStats;
+use rdb::storage::buffer::BufferPool;
+use rdb::storage::file::FileManager;
+use rdb::storage::lock::LockManager;
+use rdb::storage::page::PAGE_SIZE;
+use rdb::storage::wal::Wal;
+use rdb::{error, info, warn};
+use std::process::ExitCode;
+use std::sync::Arc;
 
 #[tokio::main]
-async fn main() {
-    println!("Starting database...");
-    let listener = TcpListener::bind("127.0.0.1:6543")
-        .await
-        .expect("could not bind to port 6543");
+async fn main() -> ExitCode {
+    let config = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) if args.help => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Ok(args) => match Config::load(&args, std::env::vars()) {
+            Ok(config) if args.print_config => {
+                println!("{config}");
+                return ExitCode::SUCCESS;
+            }
+            Ok(config) => Arc::new(config),
+            Err(err) => {
+                eprintln!("rdb: {err}");
+                return ExitCode::FAILURE;
+            }
+        },
+        Err(err) => {
+            eprintln!("rdb: {err}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    rdb::log::set_level(config.log.level);
 
-    loop {
-        match listener.accept().await {
-            Ok((mut socket, addr)) => {
-                tokio::spawn(async move {
-                    println!("Recieved connection: {}.", addr.ip());
+    info!("starting database");
+    let auth = &config.auth;
+    let password = match (&auth.password, auth.method) {
+        (Some(password), _) => Some(password.clone()),
+        (None, AuthMethod::ScramSha256) => {
+            let password = generate_password();
+            warn!("no password configured, {0} logs in with password {password}", auth.superuser);
+            Some(password)
+        }
+        (None, AuthMethod::Trust) => None,
+    };
+    let tls = match rdb::tls::acceptor(&config.tls) {
+        Ok(tls) => tls,
+        Err(err) => {
+            error!("could not set up TLS: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let tls_enabled = tls.is_some();
 
-                    let mut buffer = [0u8; 1024];
+    let files = match FileManager::open(config.storage.data_directory.join("base")) {
+        Ok(files) => Arc::new(files),
+        Err(err) => {
+            error!(
+                "could not open data directory {0}: {err}",
+                config.storage.data_directory.display()
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    let frames = (config.memory.buffer_pool_size / PAGE_SIZE as u64) as usize;
+    let pool = BufferPool::new(files, frames, config.memory.eviction_policy);
+    info!("buffer pool of {frames} pages, {0} eviction", config.memory.eviction_policy);
 
-                    let size = socket.peek(&mut buffer).await.expect("could not get size of message.");
+    let wal = match Wal::open(config.storage.data_directory.join("wal"), &config.wal) {
+        Ok(wal) => Arc::new(wal),
+        Err(err) => {
+            error!("could not open the write-ahead log: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    pool.set_wal(wal.clone());
+    match wal.recover(&pool) {
+        Ok(recovery) => info!(
+            "recovered from the write-ahead log, {0} changes redone, {1} transactions rolled back",
+            recovery.redone,
+            recovery.rolled_back.len()
+        ),
+        Err(err) => {
+            error!("could not recover from the write-ahead log: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let catalog = Catalog::bootstrap(&auth.superuser, password.as_deref());
+    let locks = LockManager::new(config.timeouts.deadlock);
+    let executor = Arc::new(
+        Executor::new(Arc::new(StatementStats::with_max(config.stats.max_statements)), catalog)
+            .with_locks(locks)
+            .with_vacuum(config.vacuum.interval, config.vacuum.threshold),
+    );
+    executor.transactions().set_wal(wal.clone());
+    // Nothing stores tables yet, there are none to open and register.
+    info!("tables cannot be created yet, only the system tables can be queried");
+    let checkpoints = tokio::spawn(checkpoint_periodically(wal.clone(), pool.clone()));
+    let server = match Server::bind(config.clone(), executor).await {
+        Ok(server) => server.with_tls(tls),
+        Err(err) => {
+            error!("could not listen on {0}: {err}", config.server.listen_address);
+            return ExitCode::FAILURE;
+        }
+    };
 
-                    let mut payload = vec![0u8; size];
-                    socket.read_exact(&mut payload).await.unwrap();
+    if let Ok(addr) = server.postgres_addr() {
+        info!("listening for PostgreSQL clients on {addr}");
+    }
+    if let Some(Ok(addr)) = server.mysql_addr() {
+        info!("listening for MySQL clients on {addr}");
+    }
+    match (tls_enabled, config.tls.required) {
+        (true, true) => info!("TLS is required"),
+        (true, false) => info!("TLS is offered, plain text connections are accepted"),
+        (false, _) => {}
+    }
+    if !tls_enabled && config.server.mysql_port.is_some() && auth.method == AuthMethod::ScramSha256 {
+        warn!("TLS is not configured, MySQL clients cannot log in with a password");
+    }
 
-                    println!("Recieved: {}", String::from_utf8(payload.clone()).unwrap());
+    server.run(shutdown_signal()).await;
+    checkpoints.abort();
+    if let Err(err) = wal.checkpoint(&pool) {
+        error!("could not write buffered pages: {err}");
+        return ExitCode::FAILURE;
+    }
+    info!("database stopped");
+    ExitCode::SUCCESS
+}
 
-                    match process_request(&payload) {
-                        Ok(res) => socket.write(&res.into_bytes()).await,
-                        Err(err) => socket.write(&err.into_bytes()).await,
-                    }
-                });
-            }
-            Err(err) => println!("Error: {}", err),
+/// Checkpoints the log as often as it was configured to, so that recovery has less to redo and the log is cut short.
+async fn checkpoint_periodically(wal: Arc<Wal>, pool: BufferPool) {
+    loop {
+        tokio::time::sleep(wal.checkpoint_interval()).await;
+        let (wal, pool) = (wal.clone(), pool.clone());
+        match tokio::task::spawn_blocking(move || wal.checkpoint(&pool)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => warn!("could not checkpoint: {err}"),
+            Err(err) => warn!("could not checkpoint: {err}"),
         }
     }
 }
 
-fn process_request(payload: &[u8]) -> Result<String, String> {
-    let mut parser = Parser::new(payload);
-    match parser.parse() {
-        Ok(ast) => Ok("SUCCESS".to_string()),
-        Err(err) => Err(format!("[ERROR] Position: {0}, Message: {1}", err.pos, err.message)),
+/// Completes on SIGINT or SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                warn!("could not listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => {
+            if let Err(err) = result {
+                warn!("could not listen for SIGINT: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+        _ = terminate => {}
     }
 }
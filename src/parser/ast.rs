@@ -1,3 +1,6 @@
+use crate::parser::token::{DataKind, LiteralKind};
+
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct AST<'a> {
     pub stmts: Vec<StatementKind<'a>>,
 }
@@ -20,20 +23,79 @@ pub enum StatementKind<'a> {
     Insert(InsertStmt<'a>),
     Delete(DeleteStmt<'a>),
     CreateTable(CreateTableStmt<'a>),
+    CreateIndex(CreateIndexStmt<'a>),
+    DropIndex(DropIndexStmt<'a>),
+    Set(SetStmt<'a>),
+    CreateRole(CreateRoleStmt<'a>),
+    AlterRole(AlterRoleStmt<'a>),
+    DropRole(DropRoleStmt<'a>),
+    /// `BEGIN`, with the isolation level it asks for.
+    Begin(Option<IsolationLevel>),
     Commit,
     Rollback,
-    Grant,
-    Revoke,
+    Grant(GrantStmt<'a>),
+    Revoke(GrantStmt<'a>),
+    /// `VACUUM`, of one table or of every table without one.
+    Vacuum(Option<DatasetReference<'a>>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprKind<'a> {
+    Identifier(ObjectReference<'a>),
+    Literal(LiteralKind<'a>),
+    /// Positional parameter, `$1` is `Placeholder(1)`.
+    Placeholder(usize),
+    Unary(UnaryOperator, Box<ExprKind<'a>>),
+    Binary(Box<ExprKind<'a>>, BinaryOperator, Box<ExprKind<'a>>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOperator {
+    Not,
+    Plus,
+    Minus,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanEq,
+    GreaterThan,
+    GreaterThanEq,
+    Like,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
 }
 
-pub enum ExprKind {
-    Identifier,
-    Literal,
+impl BinaryOperator {
+    /// Binding power of the operator, higher binds tighter.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::Equal
+            | BinaryOperator::NotEqual
+            | BinaryOperator::LessThan
+            | BinaryOperator::LessThanEq
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::GreaterThanEq
+            | BinaryOperator::Like => 4,
+            BinaryOperator::Add | BinaryOperator::Subtract => 5,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => 6,
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct BlockStmt<'a> {
-    stmts: Vec<StatementKind<'a>>,
+    pub stmts: Vec<StatementKind<'a>>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -57,15 +119,25 @@ pub struct ObjectReference<'a> {
     pub obj: Option<&'a str>,
 }
 
+impl<'a> ObjectReference<'a> {
+    pub fn new(obj: &'a str) -> Self {
+        ObjectReference {
+            dataset: None,
+            obj: Some(obj),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct SelectStmt<'a> {
     pub select_clause: SelectClause<'a>,
     pub from_clause: FromClause<'a>,
-    pub where_clause: Option<WhereClause>,
+    pub where_clause: Option<WhereClause<'a>>,
     pub group_by_clause: Option<GroupByClause>,
     pub having_clause: Option<HavingClause>,
     pub order_by_clause: Option<OrderByClause>,
-    pub limit_clause: Option<LimitClause>,
+    pub limit_clause: Option<LimitClause<'a>>,
+    pub locking_clause: Option<LockingClause>,
 }
 
 impl<'a> SelectStmt<'a> {
@@ -78,28 +150,235 @@ impl<'a> SelectStmt<'a> {
             having_clause: None,
             order_by_clause: None,
             limit_clause: None,
+            locking_clause: None,
         }
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct UpdateStmt<'a> {
-    table: &'a str,
+    pub table: DatasetReference<'a>,
+    pub assignments: Vec<Assignment<'a>>,
+    pub where_clause: Option<WhereClause<'a>>,
+}
+
+/// `column = value` in the SET clause of an update.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Assignment<'a> {
+    pub column: &'a str,
+    pub value: ExprKind<'a>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct InsertStmt<'a> {
-    table: &'a str,
+    pub table: DatasetReference<'a>,
+    /// Target columns, empty when the values follow the table's column order.
+    pub columns: Vec<&'a str>,
+    pub values: Vec<Vec<ExprKind<'a>>>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct DeleteStmt<'a> {
-    table: &'a str,
+    pub table: DatasetReference<'a>,
+    pub where_clause: Option<WhereClause<'a>>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct CreateTableStmt<'a> {
-    table: &'a str,
+    pub table: DatasetReference<'a>,
+    pub if_not_exists: bool,
+    pub columns: Vec<ColumnDef<'a>>,
+    pub constraints: Vec<TableConstraintKind<'a>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreateIndexStmt<'a> {
+    pub name: &'a str,
+    pub table: DatasetReference<'a>,
+    pub method: IndexMethod,
+    pub columns: Vec<&'a str>,
+    pub unique: bool,
+    pub if_not_exists: bool,
+}
+
+/// How an index stores its keys, given by `USING` and a b-tree when it is not.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum IndexMethod {
+    #[default]
+    BTree,
+    /// Only finds keys equal to a value.
+    Hash,
+}
+
+impl IndexMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IndexMethod::BTree => "BTREE",
+            IndexMethod::Hash => "HASH",
+        }
+    }
+}
+
+/// How much a transaction sees of what concurrent ones commit, given by `BEGIN ISOLATION LEVEL`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum IsolationLevel {
+    /// Every statement sees what was committed before it started.
+    #[default]
+    ReadCommitted,
+    /// Every statement sees what was committed before the first one started.
+    RepeatableRead,
+    /// Like `RepeatableRead`, and fails transactions whose outcome no serial order of them would give.
+    Serializable,
+}
+
+impl IsolationLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DropIndexStmt<'a> {
+    pub names: Vec<&'a str>,
+    pub if_exists: bool,
+}
+
+/// `SET name = value` on a session setting.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetStmt<'a> {
+    pub name: &'a str,
+    /// `None` for `DEFAULT`, which restores the configured value.
+    pub value: Option<ExprKind<'a>>,
+}
+
+/// `CREATE ROLE` or `CREATE USER`, which is a role that may log in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreateRoleStmt<'a> {
+    pub name: &'a str,
+    pub options: Vec<RoleOption<'a>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlterRoleStmt<'a> {
+    pub name: &'a str,
+    pub options: Vec<RoleOption<'a>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DropRoleStmt<'a> {
+    pub names: Vec<&'a str>,
+    pub if_exists: bool,
+}
+
+/// Attribute of a role, the last one of a kind wins.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RoleOption<'a> {
+    Superuser(bool),
+    Login(bool),
+    /// `PASSWORD NULL` removes the password.
+    Password(Option<&'a str>),
+}
+
+/// `GRANT` or `REVOKE`. Both give what is granted and to whom, they only differ in the optional clause.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GrantStmt<'a> {
+    pub kind: GrantKind<'a>,
+    pub grantees: Vec<Grantee<'a>>,
+    /// `WITH GRANT OPTION` on `GRANT`, `GRANT OPTION FOR` on `REVOKE`.
+    pub grant_option: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GrantKind<'a> {
+    /// Privileges on objects, `None` for `ALL PRIVILEGES`.
+    Privileges {
+        privileges: Option<Vec<PrivilegeItem<'a>>>,
+        object: GrantObject<'a>,
+    },
+    /// Membership in roles.
+    Roles(Vec<&'a str>),
+}
+
+/// A privilege, limited to some columns of a table when `columns` is not empty.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrivilegeItem<'a> {
+    pub privilege: PrivilegeKind,
+    pub columns: Vec<&'a str>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PrivilegeKind {
+    Select,
+    Insert,
+    Update,
+    Delete,
+    /// Looking up objects in a schema.
+    Usage,
+    /// Creating objects in a schema.
+    Create,
+}
+
+impl PrivilegeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrivilegeKind::Select => "SELECT",
+            PrivilegeKind::Insert => "INSERT",
+            PrivilegeKind::Update => "UPDATE",
+            PrivilegeKind::Delete => "DELETE",
+            PrivilegeKind::Usage => "USAGE",
+            PrivilegeKind::Create => "CREATE",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GrantObject<'a> {
+    Tables(Vec<DatasetReference<'a>>),
+    Schemas(Vec<&'a str>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Grantee<'a> {
+    /// Every role, present and future.
+    Public,
+    Role(&'a str),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnDef<'a> {
+    pub name: &'a str,
+    pub data_type: DataKind,
+    pub constraints: Vec<ColumnConstraintKind<'a>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColumnConstraintKind<'a> {
+    Null,
+    NotNull,
+    Default(ExprKind<'a>),
+    PrimaryKey,
+    Unique,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TableConstraintKind<'a> {
+    PrimaryKey {
+        name: Option<&'a str>,
+        columns: Vec<&'a str>,
+    },
+    Unique {
+        name: Option<&'a str>,
+        columns: Vec<&'a str>,
+    },
+    /// Non unique index declared inline, `KEY name (columns)` or `INDEX name (columns)`.
+    Index {
+        name: Option<&'a str>,
+        columns: Vec<&'a str>,
+    },
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -119,9 +398,10 @@ impl<'a> SelectClause<'a> {
 pub enum SelectItemKind<'a> {
     All,
     Identifier(ObjectReference<'a>),
+    Expr(ExprKind<'a>),
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct FromClause<'a> {
     pub from: Vec<FromItemKind<'a>>,
 }
@@ -152,7 +432,9 @@ pub enum FromItemKind<'a> {
 pub struct JoinClause {}
 
 #[derive(Clone, Debug, PartialEq)]
-pub struct WhereClause {}
+pub struct WhereClause<'a> {
+    pub expr: ExprKind<'a>,
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct GroupByClause {}
@@ -163,5 +445,22 @@ pub struct HavingClause {}
 #[derive(Clone, Debug, PartialEq)]
 pub struct OrderByClause {}
 
+/// Row limit of a query, written `LIMIT n` or `TOP n` depending on the dialect.
 #[derive(Clone, Debug, PartialEq)]
-pub struct LimitClause {}
+pub struct LimitClause<'a> {
+    pub count: ExprKind<'a>,
+}
+
+/// `FOR UPDATE` or `FOR SHARE`, locking the rows a query returns until its transaction ends. With `NOWAIT` the
+/// query fails rather than wait for a lock.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LockingClause {
+    pub strength: LockStrength,
+    pub nowait: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LockStrength {
+    Update,
+    Share,
+}
@@ -0,0 +1,218 @@
+//! SQL dialects.
+//!
+//! A [`Dialect`] decides which words are keywords, how identifiers are quoted, how string literals escape their
+//! delimiter and which vendor specific clauses the parser accepts.
+
+use crate::parser::lexer::match_kw;
+use crate::parser::token::KeywordKind;
+use std::fmt::{Display, Formatter};
+
+/// Clauses that only some dialects accept.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Clause {
+    /// `SELECT ... LIMIT n`
+    Limit,
+    /// `SELECT TOP n ...`
+    Top,
+    /// `WHERE ROWNUM <= n`
+    Rownum,
+}
+
+impl Display for Clause {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Clause::Limit => write!(f, "LIMIT"),
+            Clause::Top => write!(f, "TOP"),
+            Clause::Rownum => write!(f, "ROWNUM"),
+        }
+    }
+}
+
+pub trait Dialect {
+    fn name(&self) -> &'static str;
+
+    /// Keyword for the word, `None` when it is lexed as an identifier.
+    fn keyword(&self, word: &str) -> Option<KeywordKind>;
+
+    /// Closing delimiter when `open` starts a quoted identifier.
+    fn identifier_quote(&self, open: u8) -> Option<u8>;
+
+    /// Whether `open` delimits string literals, it always does for `'`.
+    fn string_quote(&self, open: u8) -> bool;
+
+    /// Whether a backslash escapes the delimiter of a string literal, a doubled delimiter is always accepted.
+    fn supports_backslash_escapes(&self) -> bool;
+
+    fn supports_clause(&self, clause: Clause) -> bool;
+}
+
+/// Permissive dialect accepting the union of the supported vendor syntaxes. Brackets are left to
+/// [`MsSqlDialect`] since they are also used as punctuation.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GenericDialect;
+
+impl Dialect for GenericDialect {
+    fn name(&self) -> &'static str {
+        "generic"
+    }
+
+    fn keyword(&self, word: &str) -> Option<KeywordKind> {
+        match_kw(word)
+    }
+
+    fn identifier_quote(&self, open: u8) -> Option<u8> {
+        match open {
+            b'"' => Some(b'"'),
+            b'`' => Some(b'`'),
+            _ => None,
+        }
+    }
+
+    fn string_quote(&self, open: u8) -> bool {
+        open == b'\''
+    }
+
+    fn supports_backslash_escapes(&self) -> bool {
+        true
+    }
+
+    fn supports_clause(&self, _clause: Clause) -> bool {
+        true
+    }
+}
+
+/// Strict ANSI SQL, vendor keywords are plain identifiers and vendor clauses are rejected.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AnsiDialect;
+
+impl Dialect for AnsiDialect {
+    fn name(&self) -> &'static str {
+        "ANSI"
+    }
+
+    fn keyword(&self, word: &str) -> Option<KeywordKind> {
+        match match_kw(word) {
+            Some(KeywordKind::Backup | KeywordKind::Exec | KeywordKind::Rownum | KeywordKind::Top) => None,
+            kw => kw,
+        }
+    }
+
+    fn identifier_quote(&self, open: u8) -> Option<u8> {
+        match open {
+            b'"' => Some(b'"'),
+            _ => None,
+        }
+    }
+
+    fn string_quote(&self, open: u8) -> bool {
+        open == b'\''
+    }
+
+    fn supports_backslash_escapes(&self) -> bool {
+        false
+    }
+
+    fn supports_clause(&self, _clause: Clause) -> bool {
+        false
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MySqlDialect;
+
+impl Dialect for MySqlDialect {
+    fn name(&self) -> &'static str {
+        "MySQL"
+    }
+
+    fn keyword(&self, word: &str) -> Option<KeywordKind> {
+        match match_kw(word) {
+            Some(KeywordKind::Exec | KeywordKind::Rownum | KeywordKind::Top) => None,
+            kw => kw,
+        }
+    }
+
+    fn identifier_quote(&self, open: u8) -> Option<u8> {
+        match open {
+            b'`' => Some(b'`'),
+            _ => None,
+        }
+    }
+
+    fn string_quote(&self, open: u8) -> bool {
+        matches!(open, b'\'' | b'"')
+    }
+
+    fn supports_backslash_escapes(&self) -> bool {
+        true
+    }
+
+    fn supports_clause(&self, clause: Clause) -> bool {
+        clause == Clause::Limit
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MsSqlDialect;
+
+impl Dialect for MsSqlDialect {
+    fn name(&self) -> &'static str {
+        "T-SQL"
+    }
+
+    fn keyword(&self, word: &str) -> Option<KeywordKind> {
+        match match_kw(word) {
+            Some(KeywordKind::Limit | KeywordKind::Rownum) => None,
+            kw => kw,
+        }
+    }
+
+    fn identifier_quote(&self, open: u8) -> Option<u8> {
+        match open {
+            b'"' => Some(b'"'),
+            b'[' => Some(b']'),
+            _ => None,
+        }
+    }
+
+    fn string_quote(&self, open: u8) -> bool {
+        open == b'\''
+    }
+
+    fn supports_backslash_escapes(&self) -> bool {
+        false
+    }
+
+    fn supports_clause(&self, clause: Clause) -> bool {
+        clause == Clause::Top
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_ansi_keywords() {
+        assert_eq!(AnsiDialect.keyword("SELECT"), Some(KeywordKind::Select));
+        assert_eq!(AnsiDialect.keyword("top"), None);
+        assert_eq!(GenericDialect.keyword("top"), Some(KeywordKind::Top));
+    }
+
+    #[test]
+    fn test_identifier_quotes() {
+        assert_eq!(AnsiDialect.identifier_quote(b'"'), Some(b'"'));
+        assert_eq!(AnsiDialect.identifier_quote(b'`'), None);
+        assert_eq!(GenericDialect.identifier_quote(b'['), None);
+        assert_eq!(MsSqlDialect.identifier_quote(b'['), Some(b']'));
+    }
+
+    #[test]
+    fn test_string_quotes() {
+        assert!(MySqlDialect.string_quote(b'"'));
+        assert!(MySqlDialect.string_quote(b'\''));
+        assert!(!GenericDialect.string_quote(b'"'));
+        assert!(!AnsiDialect.string_quote(b'`'));
+    }
+}
@@ -0,0 +1,601 @@
+//! Query fingerprinting.
+//!
+//! Two statements that only differ by their literal values or by the case of their identifiers share a
+//! fingerprint, which lets the server aggregate statistics per query shape rather than per query text.
+
+use crate::parser::ast::*;
+use crate::parser::token::LiteralKind;
+use crate::parser::visitor::{Fold, Visitor, walk_expr};
+use std::fmt::Write;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fingerprint {
+    pub id: u64,
+    pub text: String,
+}
+
+impl Fingerprint {
+    pub fn of_stmt(stmt: &StatementKind) -> Self {
+        let normalized = normalize_stmt(stmt.clone());
+
+        let mut printer = Printer { out: String::new() };
+        printer.visit_stmt(&normalized);
+
+        Fingerprint::from_text(printer.out)
+    }
+
+    pub fn of_ast(ast: &AST) -> Self {
+        let text = ast
+            .stmts
+            .iter()
+            .map(|stmt| Fingerprint::of_stmt(stmt).text)
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        Fingerprint::from_text(text)
+    }
+
+    fn from_text(text: String) -> Self {
+        Fingerprint {
+            id: fnv1a(text.as_bytes()),
+            text,
+        }
+    }
+}
+
+/// Replaces every literal of the statement with a positional placeholder. Numbering continues after any
+/// placeholder already present so the result never reuses a parameter position.
+pub fn normalize_stmt(stmt: StatementKind) -> StatementKind {
+    let next = max_placeholder(&stmt);
+    Normalizer { next }.fold_stmt(stmt)
+}
+
+/// Highest parameter position used by the statement, 0 when it has none.
+pub fn max_placeholder(stmt: &StatementKind) -> usize {
+    let mut max = MaxPlaceholder(0);
+    max.visit_stmt(stmt);
+    max.0
+}
+
+pub fn normalize(ast: AST) -> AST {
+    AST {
+        stmts: ast.stmts.into_iter().map(normalize_stmt).collect(),
+    }
+}
+
+struct MaxPlaceholder(usize);
+
+impl<'a> Visitor<'a> for MaxPlaceholder {
+    fn visit_expr(&mut self, expr: &ExprKind<'a>) {
+        if let ExprKind::Placeholder(n) = expr {
+            self.0 = self.0.max(*n);
+        }
+        walk_expr(self, expr);
+    }
+}
+
+struct Normalizer {
+    next: usize,
+}
+
+impl<'a> Fold<'a> for Normalizer {
+    fn fold_literal(&mut self, literal: LiteralKind<'a>) -> ExprKind<'a> {
+        // NULL is part of the query shape, `x = NULL` and `x = 1` behave differently.
+        if literal == LiteralKind::Null {
+            return ExprKind::Literal(literal);
+        }
+
+        self.next += 1;
+        ExprKind::Placeholder(self.next)
+    }
+}
+
+/// Renders statements with upper case keywords, lower case identifiers and single spaces between tokens.
+struct Printer {
+    out: String,
+}
+
+impl Printer {
+    fn comma_separated<T>(&mut self, items: &[T], mut f: impl FnMut(&mut Self, &T)) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            f(self, item);
+        }
+    }
+
+    /// Passwords are never printed, the fingerprint text ends up in the statistics.
+    fn role_options(&mut self, options: &[RoleOption]) {
+        for option in options {
+            self.out.push_str(match option {
+                RoleOption::Superuser(true) => " SUPERUSER",
+                RoleOption::Superuser(false) => " NOSUPERUSER",
+                RoleOption::Login(true) => " LOGIN",
+                RoleOption::Login(false) => " NOLOGIN",
+                RoleOption::Password(Some(_)) => " PASSWORD <redacted>",
+                RoleOption::Password(None) => " PASSWORD NULL",
+            });
+        }
+    }
+
+    fn grant_kind(&mut self, kind: &GrantKind) {
+        match kind {
+            GrantKind::Privileges { privileges, object } => {
+                match privileges {
+                    Some(privileges) => self.comma_separated(privileges, |p, item| {
+                        p.out.push_str(item.privilege.as_str());
+                        if !item.columns.is_empty() {
+                            p.out.push_str(" (");
+                            p.comma_separated(&item.columns, |p, column| p.visit_identifier(column));
+                            p.out.push(')');
+                        }
+                    }),
+                    None => self.out.push_str("ALL PRIVILEGES"),
+                }
+                match object {
+                    GrantObject::Tables(tables) => {
+                        self.out.push_str(" ON TABLE ");
+                        self.comma_separated(tables, |p, table| p.visit_dataset_reference(table));
+                    }
+                    GrantObject::Schemas(schemas) => {
+                        self.out.push_str(" ON SCHEMA ");
+                        self.comma_separated(schemas, |p, schema| p.visit_identifier(schema));
+                    }
+                }
+            }
+            GrantKind::Roles(roles) => self.comma_separated(roles, |p, role| p.visit_identifier(role)),
+        }
+    }
+
+    fn grantees(&mut self, grantees: &[Grantee]) {
+        self.comma_separated(grantees, |p, grantee| match grantee {
+            Grantee::Public => p.out.push_str("PUBLIC"),
+            Grantee::Role(role) => p.visit_identifier(role),
+        });
+    }
+
+    fn nested_expr(&mut self, expr: &ExprKind, parenthesize: bool) {
+        if parenthesize {
+            self.out.push('(');
+            self.visit_expr(expr);
+            self.out.push(')');
+        } else {
+            self.visit_expr(expr);
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Printer {
+    fn visit_stmt(&mut self, stmt: &StatementKind<'a>) {
+        match stmt {
+            StatementKind::Block(block) => self.visit_block_stmt(block),
+            StatementKind::Select(select) => self.visit_select_stmt(select),
+            StatementKind::Update(update) => self.visit_update_stmt(update),
+            StatementKind::Insert(insert) => self.visit_insert_stmt(insert),
+            StatementKind::Delete(delete) => self.visit_delete_stmt(delete),
+            StatementKind::CreateTable(create) => self.visit_create_table_stmt(create),
+            StatementKind::CreateIndex(create) => {
+                self.out.push_str("CREATE ");
+                if create.unique {
+                    self.out.push_str("UNIQUE ");
+                }
+                self.out.push_str("INDEX ");
+                if create.if_not_exists {
+                    self.out.push_str("IF NOT EXISTS ");
+                }
+                self.visit_identifier(create.name);
+                self.out.push_str(" ON ");
+                self.visit_dataset_reference(&create.table);
+                if create.method != IndexMethod::default() {
+                    self.out.push_str(" USING ");
+                    self.out.push_str(create.method.as_str());
+                }
+                self.out.push_str(" (");
+                self.comma_separated(&create.columns, |p, column| p.visit_identifier(column));
+                self.out.push(')');
+            }
+            StatementKind::DropIndex(drop) => {
+                self.out.push_str("DROP INDEX ");
+                if drop.if_exists {
+                    self.out.push_str("IF EXISTS ");
+                }
+                self.comma_separated(&drop.names, |p, name| p.visit_identifier(name));
+            }
+            StatementKind::Set(set) => self.visit_set_stmt(set),
+            StatementKind::CreateRole(create) => {
+                self.out.push_str("CREATE ROLE ");
+                self.visit_identifier(create.name);
+                self.role_options(&create.options);
+            }
+            StatementKind::AlterRole(alter) => {
+                self.out.push_str("ALTER ROLE ");
+                self.visit_identifier(alter.name);
+                self.role_options(&alter.options);
+            }
+            StatementKind::DropRole(drop) => {
+                self.out.push_str("DROP ROLE ");
+                if drop.if_exists {
+                    self.out.push_str("IF EXISTS ");
+                }
+                self.comma_separated(&drop.names, |p, name| p.visit_identifier(name));
+            }
+            StatementKind::Begin(isolation) => {
+                self.out.push_str("BEGIN");
+                if let Some(isolation) = isolation {
+                    self.out.push_str(" ISOLATION LEVEL ");
+                    self.out.push_str(isolation.as_str());
+                }
+            }
+            StatementKind::Commit => self.out.push_str("COMMIT"),
+            StatementKind::Rollback => self.out.push_str("ROLLBACK"),
+            StatementKind::Vacuum(table) => {
+                self.out.push_str("VACUUM");
+                if let Some(table) = table {
+                    self.out.push(' ');
+                    self.visit_dataset_reference(table);
+                }
+            }
+            StatementKind::Grant(grant) => {
+                self.out.push_str("GRANT ");
+                self.grant_kind(&grant.kind);
+                self.out.push_str(" TO ");
+                self.grantees(&grant.grantees);
+                if grant.grant_option {
+                    self.out.push_str(" WITH GRANT OPTION");
+                }
+            }
+            StatementKind::Revoke(grant) => {
+                self.out.push_str("REVOKE ");
+                if grant.grant_option {
+                    self.out.push_str("GRANT OPTION FOR ");
+                }
+                self.grant_kind(&grant.kind);
+                self.out.push_str(" FROM ");
+                self.grantees(&grant.grantees);
+            }
+        }
+    }
+
+    fn visit_update_stmt(&mut self, update: &UpdateStmt<'a>) {
+        self.out.push_str("UPDATE ");
+        self.visit_dataset_reference(&update.table);
+
+        self.out.push_str(" SET ");
+        self.comma_separated(&update.assignments, |p, assignment| {
+            p.visit_identifier(assignment.column);
+            p.out.push_str(" = ");
+            p.visit_expr(&assignment.value);
+        });
+
+        if let Some(clause) = &update.where_clause {
+            self.out.push_str(" WHERE ");
+            self.visit_expr(&clause.expr);
+        }
+    }
+
+    fn visit_insert_stmt(&mut self, insert: &InsertStmt<'a>) {
+        self.out.push_str("INSERT INTO ");
+        self.visit_dataset_reference(&insert.table);
+
+        if !insert.columns.is_empty() {
+            self.out.push_str(" (");
+            self.comma_separated(&insert.columns, |p, column| p.visit_identifier(column));
+            self.out.push(')');
+        }
+
+        self.out.push_str(" VALUES ");
+        self.comma_separated(&insert.values, |p, row| {
+            p.out.push('(');
+            p.comma_separated(row, |p, expr| p.visit_expr(expr));
+            p.out.push(')');
+        });
+    }
+
+    fn visit_delete_stmt(&mut self, delete: &DeleteStmt<'a>) {
+        self.out.push_str("DELETE FROM ");
+        self.visit_dataset_reference(&delete.table);
+
+        if let Some(clause) = &delete.where_clause {
+            self.out.push_str(" WHERE ");
+            self.visit_expr(&clause.expr);
+        }
+    }
+
+    fn visit_create_table_stmt(&mut self, create: &CreateTableStmt<'a>) {
+        self.out.push_str("CREATE TABLE ");
+        if create.if_not_exists {
+            self.out.push_str("IF NOT EXISTS ");
+        }
+        self.visit_dataset_reference(&create.table);
+
+        self.out.push_str(" (");
+        self.comma_separated(&create.columns, |p, column| p.visit_column_def(column));
+        for constraint in &create.constraints {
+            self.out.push_str(", ");
+            self.visit_table_constraint(constraint);
+        }
+        self.out.push(')');
+    }
+
+    fn visit_set_stmt(&mut self, set: &SetStmt<'a>) {
+        self.out.push_str("SET ");
+        self.visit_identifier(set.name);
+        match &set.value {
+            Some(value) => {
+                self.out.push_str(" = ");
+                self.visit_expr(value);
+            }
+            None => self.out.push_str(" = DEFAULT"),
+        }
+    }
+
+    fn visit_column_def(&mut self, column: &ColumnDef<'a>) {
+        self.visit_identifier(column.name);
+        let _ = write!(self.out, " {0}", column.data_type);
+
+        for constraint in &column.constraints {
+            match constraint {
+                ColumnConstraintKind::Null => self.out.push_str(" NULL"),
+                ColumnConstraintKind::NotNull => self.out.push_str(" NOT NULL"),
+                ColumnConstraintKind::Default(expr) => {
+                    self.out.push_str(" DEFAULT ");
+                    self.visit_expr(expr);
+                }
+                ColumnConstraintKind::PrimaryKey => self.out.push_str(" PRIMARY KEY"),
+                ColumnConstraintKind::Unique => self.out.push_str(" UNIQUE"),
+            }
+        }
+    }
+
+    fn visit_table_constraint(&mut self, constraint: &TableConstraintKind<'a>) {
+        let (keyword, name, columns) = match constraint {
+            TableConstraintKind::PrimaryKey { name, columns } => ("PRIMARY KEY", name, columns),
+            TableConstraintKind::Unique { name, columns } => ("UNIQUE", name, columns),
+            TableConstraintKind::Index { name, columns } => ("INDEX", name, columns),
+        };
+
+        self.out.push_str(keyword);
+        if let Some(name) = name {
+            self.out.push(' ');
+            self.visit_identifier(name);
+        }
+        self.out.push_str(" (");
+        self.comma_separated(columns, |p, column| p.visit_identifier(column));
+        self.out.push(')');
+    }
+
+    fn visit_block_stmt(&mut self, block: &BlockStmt<'a>) {
+        self.out.push_str("BEGIN");
+        for stmt in &block.stmts {
+            self.out.push(' ');
+            self.visit_stmt(stmt);
+            self.out.push(';');
+        }
+        self.out.push_str(" END");
+    }
+
+    fn visit_select_stmt(&mut self, select: &SelectStmt<'a>) {
+        self.out.push_str("SELECT ");
+        self.comma_separated(&select.select_clause.selected, |p, item| p.visit_select_item(item));
+
+        self.out.push_str(" FROM ");
+        self.comma_separated(&select.from_clause.from, |p, item| p.visit_from_item(item));
+
+        if let Some(clause) = &select.where_clause {
+            self.out.push_str(" WHERE ");
+            self.visit_expr(&clause.expr);
+        }
+
+        if let Some(clause) = &select.limit_clause {
+            self.out.push_str(" LIMIT ");
+            self.visit_expr(&clause.count);
+        }
+
+        if let Some(clause) = &select.locking_clause {
+            self.out.push_str(match clause.strength {
+                LockStrength::Update => " FOR UPDATE",
+                LockStrength::Share => " FOR SHARE",
+            });
+            if clause.nowait {
+                self.out.push_str(" NOWAIT");
+            }
+        }
+    }
+
+    fn visit_select_item(&mut self, item: &SelectItemKind<'a>) {
+        match item {
+            SelectItemKind::All => self.out.push('*'),
+            SelectItemKind::Identifier(obj) => self.visit_object_reference(obj),
+            SelectItemKind::Expr(expr) => self.visit_expr(expr),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &ExprKind<'a>) {
+        match expr {
+            ExprKind::Identifier(obj) => self.visit_object_reference(obj),
+            ExprKind::Literal(literal) => self.visit_literal(literal),
+            ExprKind::Placeholder(n) => {
+                let _ = write!(self.out, "${n}");
+            }
+            ExprKind::Unary(op, operand) => {
+                self.out.push_str(match op {
+                    UnaryOperator::Not => "NOT ",
+                    UnaryOperator::Plus => "+",
+                    UnaryOperator::Minus => "-",
+                });
+                let parenthesize = match (op, operand.as_ref()) {
+                    (UnaryOperator::Not, ExprKind::Binary(_, inner, _)) => {
+                        inner.precedence() <= BinaryOperator::And.precedence()
+                    }
+                    (_, ExprKind::Binary(..)) => true,
+                    _ => false,
+                };
+                self.nested_expr(operand, parenthesize);
+            }
+            ExprKind::Binary(left, op, right) => {
+                let left_parens =
+                    matches!(left.as_ref(), ExprKind::Binary(_, inner, _) if inner.precedence() < op.precedence());
+                let right_parens =
+                    matches!(right.as_ref(), ExprKind::Binary(_, inner, _) if inner.precedence() <= op.precedence());
+
+                self.nested_expr(left, left_parens);
+                self.out.push_str(match op {
+                    BinaryOperator::Or => " OR ",
+                    BinaryOperator::And => " AND ",
+                    BinaryOperator::Equal => " = ",
+                    BinaryOperator::NotEqual => " <> ",
+                    BinaryOperator::LessThan => " < ",
+                    BinaryOperator::LessThanEq => " <= ",
+                    BinaryOperator::GreaterThan => " > ",
+                    BinaryOperator::GreaterThanEq => " >= ",
+                    BinaryOperator::Like => " LIKE ",
+                    BinaryOperator::Add => " + ",
+                    BinaryOperator::Subtract => " - ",
+                    BinaryOperator::Multiply => " * ",
+                    BinaryOperator::Divide => " / ",
+                    BinaryOperator::Modulo => " % ",
+                });
+                self.nested_expr(right, right_parens);
+            }
+        }
+    }
+
+    fn visit_literal(&mut self, literal: &LiteralKind<'a>) {
+        match literal {
+            LiteralKind::String(s) => {
+                let _ = write!(self.out, "'{s}'");
+            }
+            LiteralKind::Numeric(n) => {
+                let _ = write!(self.out, "{n}");
+            }
+            LiteralKind::Null => self.out.push_str("NULL"),
+        }
+    }
+
+    fn visit_dataset_reference(&mut self, dataset: &DatasetReference<'a>) {
+        if let Some(schema) = dataset.schema {
+            self.visit_identifier(schema);
+            self.out.push('.');
+        }
+        if let Some(name) = dataset.dataset {
+            self.visit_identifier(name);
+        }
+    }
+
+    fn visit_object_reference(&mut self, obj: &ObjectReference<'a>) {
+        if let Some(dataset) = &obj.dataset {
+            self.visit_dataset_reference(dataset);
+            self.out.push('.');
+        }
+        if let Some(name) = obj.obj {
+            self.visit_identifier(name);
+        }
+    }
+
+    fn visit_identifier(&mut self, ident: &'a str) {
+        self.out.push_str(&ident.to_lowercase());
+    }
+}
+
+/// 64-bit FNV-1a, chosen over `DefaultHasher` because its output is stable across builds and releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    bytes
+        .iter()
+        .fold(OFFSET_BASIS, |hash, byte| (hash ^ *byte as u64).wrapping_mul(PRIME))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parser::Parser;
+
+    fn fingerprint(sql: &[u8]) -> Fingerprint {
+        let mut p = Parser::new(sql);
+        let ast = p.parse().unwrap();
+        Fingerprint::of_ast(&ast)
+    }
+
+    #[test]
+    fn test_normalized_text() {
+        let fp = fingerprint(b"select Name, weight from Cats where age > 2 and name = 'tom'");
+
+        assert_eq!(fp.text, "SELECT name, weight FROM cats WHERE age > $1 AND name = $2");
+
+        let fp = fingerprint(b"select * from cats limit 1 for share nowait");
+        assert_eq!(fp.text, "SELECT * FROM cats LIMIT $1 FOR SHARE NOWAIT");
+    }
+
+    #[test]
+    fn test_same_shape_same_fingerprint() {
+        let a = fingerprint(b"SELECT * FROM cats WHERE age > 2");
+        let b = fingerprint(b"select *   from CATS\nwhere AGE > 14.5;");
+        let c = fingerprint(b"SELECT * FROM cats WHERE age < 2");
+
+        assert_eq!(a, b);
+        assert_ne!(a.id, c.id);
+    }
+
+    #[test]
+    fn test_parentheses_preserved() {
+        let fp = fingerprint(b"SELECT * FROM cats WHERE (age > 2 OR age < 1) AND NOT (a = 1 OR b = 2)");
+
+        assert_eq!(
+            fp.text,
+            "SELECT * FROM cats WHERE (age > $1 OR age < $2) AND NOT (a = $3 OR b = $4)"
+        );
+    }
+
+    #[test]
+    fn test_existing_placeholders_not_reused() {
+        let stmt = StatementKind::Select(SelectStmt {
+            where_clause: Some(WhereClause {
+                expr: ExprKind::Binary(
+                    Box::new(ExprKind::Placeholder(1)),
+                    BinaryOperator::Equal,
+                    Box::new(ExprKind::Literal(LiteralKind::Numeric(1.0))),
+                ),
+            }),
+            ..SelectStmt::new(SelectClause::all(), FromClause::table("cats"))
+        });
+
+        assert_eq!(Fingerprint::of_stmt(&stmt).text, "SELECT * FROM cats WHERE $1 = $2");
+    }
+
+    #[test]
+    fn test_create_table() {
+        let fp = fingerprint(b"CREATE TABLE Cats (id INT PRIMARY KEY, name VARCHAR(20) DEFAULT 'tom', KEY by_name (name))");
+
+        assert_eq!(
+            fp.text,
+            "CREATE TABLE cats (id INTEGER PRIMARY KEY, name VARCHAR(20) DEFAULT $1, INDEX by_name (name))"
+        );
+
+        let fp = fingerprint(b"create unique index By_Name on cats using hash (name)");
+        assert_eq!(fp.text, "CREATE UNIQUE INDEX by_name ON cats USING HASH (name)");
+
+        assert_eq!(fingerprint(b"vacuum Cats").text, "VACUUM cats");
+    }
+
+    #[test]
+    fn test_dml() {
+        let fp = fingerprint(b"INSERT INTO cats (name, age) VALUES ('tom', 3), ('kit', 1)");
+        assert_eq!(fp.text, "INSERT INTO cats (name, age) VALUES ($1, $2), ($3, $4)");
+
+        let fp = fingerprint(b"UPDATE cats SET age = age + 1 WHERE name = 'tom'");
+        assert_eq!(fp.text, "UPDATE cats SET age = age + $1 WHERE name = $2");
+
+        let fp = fingerprint(b"DELETE FROM cats WHERE age > 20");
+        assert_eq!(fp.text, "DELETE FROM cats WHERE age > $1");
+    }
+
+    #[test]
+    fn test_stable_id() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+}
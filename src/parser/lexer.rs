@@ -1,9 +1,11 @@
+use crate::parser::dialect::{Dialect, GenericDialect};
 use crate::parser::token::*;
 use std::cell::Cell;
 
 pub struct Lexer<'a> {
     data: &'a [u8],
     cursor: Cell<usize>,
+    dialect: &'a dyn Dialect,
 }
 
 #[derive(Debug)]
@@ -20,9 +22,14 @@ impl LexerError {
 
 impl<'a> Lexer<'a> {
     pub fn new(data: &'a [u8]) -> Self {
+        Lexer::with_dialect(data, &GenericDialect)
+    }
+
+    pub fn with_dialect(data: &'a [u8], dialect: &'a dyn Dialect) -> Self {
         Self {
             data,
             cursor: Cell::new(0),
+            dialect,
         }
     }
 
@@ -36,18 +43,61 @@ impl<'a> Lexer<'a> {
             let peek = self.data[pos];
 
             let token = match peek {
-                b'\'' => Ok(Token::new(self.lex_string_literal(), pos)),
-                b'a'..=b'z' | b'A'..=b'Z' => Ok(Token::new(self.lex_identifier_or_kw(), pos)),
+                _ if self.dialect.identifier_quote(peek).is_some() => self.lex_quoted_identifier(),
+                _ if self.dialect.string_quote(peek) => self.lex_string_literal(),
+                b'a'..=b'z' | b'A'..=b'Z' | b'_' => Ok(Token::new(self.lex_identifier_or_kw(), pos)),
                 b'0'..=b'9' => Ok(Token::new(self.lex_numerical_literal(), pos)),
-                b'*' => {
-                    if self.data[pos + 1] == b'=' {
+                b'$' if self.peek_byte(1).is_some_and(|b| b.is_ascii_digit()) => self.lex_placeholder(),
+                b'*' => Ok(Token::new(
+                    self.lex_operator(PuncKind::Star, Some(PuncKind::MultiplyAssign)),
+                    pos,
+                )),
+                b'+' => Ok(Token::new(self.lex_operator(PuncKind::Add, Some(PuncKind::AddAssign)), pos)),
+                b'-' if self.peek_byte(1) == Some(b'-') => Ok(Token::new(self.lex_single_comment(), pos)),
+                b'/' if self.peek_byte(1) == Some(b'*') => self.lex_multi_comment(),
+                b'-' => Ok(Token::new(
+                    self.lex_operator(PuncKind::Subtract, Some(PuncKind::SubtractAssign)),
+                    pos,
+                )),
+                b'/' => Ok(Token::new(
+                    self.lex_operator(PuncKind::Divide, Some(PuncKind::DivideAssign)),
+                    pos,
+                )),
+                b'%' => Ok(Token::new(
+                    self.lex_operator(PuncKind::Modulo, Some(PuncKind::ModuloAssign)),
+                    pos,
+                )),
+                b'&' => Ok(Token::new(
+                    self.lex_operator(PuncKind::BitwiseAnd, Some(PuncKind::BitwiseAndAssign)),
+                    pos,
+                )),
+                b'|' => Ok(Token::new(
+                    self.lex_operator(PuncKind::BitwiseOr, Some(PuncKind::BitwiseOrAssign)),
+                    pos,
+                )),
+                b'^' => Ok(Token::new(
+                    self.lex_operator(PuncKind::BitwiseXor, Some(PuncKind::BitwiseXorAssign)),
+                    pos,
+                )),
+                b'>' => Ok(Token::new(
+                    self.lex_operator(PuncKind::GreaterThan, Some(PuncKind::GreaterThanEq)),
+                    pos,
+                )),
+                b'<' => {
+                    if self.peek_byte(1) == Some(b'>') {
                         self.cursor.set(pos + 2);
-                        Ok(Token::new(TokenKind::Punc(PuncKind::MultiplyAssign), pos))
+                        Ok(Token::new(TokenKind::Punc(PuncKind::NotEqual), pos))
                     } else {
-                        self.cursor.set(pos + 1);
-                        Ok(Token::new(TokenKind::Punc(PuncKind::Star), pos))
+                        Ok(Token::new(
+                            self.lex_operator(PuncKind::LessThan, Some(PuncKind::LessThanEq)),
+                            pos,
+                        ))
                     }
                 }
+                b'!' if self.peek_byte(1) == Some(b'=') => {
+                    self.cursor.set(pos + 2);
+                    Ok(Token::new(TokenKind::Punc(PuncKind::NotEqual), pos))
+                }
                 _ => self.lex_single_chars(),
             };
 
@@ -91,6 +141,24 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Peeks the token following the next one.
+    pub fn peek_second(&self) -> Result<Token<'a>, LexerError> {
+        let start = self.cursor.get();
+        let second = self.next().and_then(|_| self.next());
+        self.cursor.set(start);
+        second
+    }
+
+    /// Source text of the word starting at `pos`, used to recover the spelling of keywords and data types.
+    pub fn word_at(&self, pos: usize) -> &'a str {
+        let mut end = pos;
+        while end < self.data.len() && (self.data[end].is_ascii_alphanumeric() || self.data[end] == b'_') {
+            end += 1;
+        }
+
+        std::str::from_utf8(&self.data[pos..end]).expect("word should be utf-8")
+    }
+
     pub fn eat(&self, check: TokenKind) -> bool {
         if self.is_end() {
             return false;
@@ -128,33 +196,99 @@ impl<'a> Lexer<'a> {
             b';' => Ok(Token::new(TokenKind::Punc(PuncKind::SemiColon), pos)),
             b':' => Ok(Token::new(TokenKind::Punc(PuncKind::Colon), pos)),
             b'=' => Ok(Token::new(TokenKind::Punc(PuncKind::Equal), pos)),
+            b'.' => Ok(Token::new(TokenKind::Punc(PuncKind::Period), pos)),
             _ => Err(LexerError {
                 message: "unknown artifact".to_string(),
-                pos: pos.into(),
+                pos,
             }),
         }
     }
 
-    fn lex_string_literal(&self) -> TokenKind<'a> {
-        let start = self.cursor.get() + 1;
+    /// Lexes a literal delimited by one of the string quotes of the dialect. A doubled quote is an escaped quote and
+    /// so is a backslash followed by a quote when the dialect allows it, both are kept verbatim in the literal.
+    fn lex_string_literal(&self) -> Result<Token<'a>, LexerError> {
+        let open = self.cursor.get();
+        let quote = self.data[open];
+        let start = open + 1;
         let mut pos = start;
-        while self.data[pos] != b'\'' || self.data[pos - 1] == b'\\' {
-            pos += 1;
+        loop {
+            match self.data.get(pos) {
+                None => return Err(LexerError::new("unterminated string literal".to_string(), open)),
+                Some(b'\\') if self.dialect.supports_backslash_escapes() => pos += 2,
+                Some(&c) if c == quote && self.data.get(pos + 1) == Some(&quote) => pos += 2,
+                Some(&c) if c == quote => break,
+                Some(_) => pos += 1,
+            }
         }
 
         let slice = &self.data[start..pos];
-        pos += 1;
+        self.cursor.set(pos + 1);
+
+        let word = std::str::from_utf8(slice).expect("word should be utf8");
+        Ok(Token::new(TokenKind::Literal(LiteralKind::String(word)), open))
+    }
+
+    fn lex_single_comment(&self) -> TokenKind<'a> {
+        let start = self.cursor.get() + 2;
+        let mut pos = start;
+        while pos < self.data.len() && self.data[pos] != b'\n' {
+            pos += 1;
+        }
 
         self.cursor.set(pos);
 
-        let word = std::str::from_utf8(slice).expect("word should be utf8");
-        TokenKind::Literal(LiteralKind::String(word))
+        let text = std::str::from_utf8(&self.data[start..pos]).expect("comment should be utf-8");
+        TokenKind::Comment(CommentKind::Single(text))
+    }
+
+    fn lex_multi_comment(&self) -> Result<Token<'a>, LexerError> {
+        let open = self.cursor.get();
+        let start = open + 2;
+        let end = match self.data[start..].windows(2).position(|w| w == b"*/") {
+            Some(len) => start + len,
+            None => return Err(LexerError::new("unterminated comment".to_string(), open)),
+        };
+
+        self.cursor.set(end + 2);
+
+        let text = std::str::from_utf8(&self.data[start..end]).expect("comment should be utf-8");
+        Ok(Token::new(TokenKind::Comment(CommentKind::Multi(text)), open))
+    }
+
+    /// Lexes an identifier between the quotes of the dialect, it is never a keyword.
+    fn lex_quoted_identifier(&self) -> Result<Token<'a>, LexerError> {
+        let open = self.cursor.get();
+        let close = self
+            .dialect
+            .identifier_quote(self.data[open])
+            .expect("should be an identifier quote");
+
+        let start = open + 1;
+        let mut pos = start;
+        loop {
+            match self.data.get(pos) {
+                None => return Err(LexerError::new("unterminated quoted identifier".to_string(), open)),
+                Some(&c) if c == close && self.data.get(pos + 1) == Some(&close) => pos += 2,
+                Some(&c) if c == close => break,
+                Some(_) => pos += 1,
+            }
+        }
+
+        if pos == start {
+            return Err(LexerError::new("zero-length quoted identifier".to_string(), open));
+        }
+
+        let slice = &self.data[start..pos];
+        self.cursor.set(pos + 1);
+
+        let word = std::str::from_utf8(slice).expect("word should be utf-8");
+        Ok(Token::new(TokenKind::Identifier(word), open))
     }
 
     fn lex_identifier_or_kw(&self) -> TokenKind<'a> {
         let start = self.cursor.get();
         let mut pos = start;
-        while pos < self.data.len() && self.data[pos].is_ascii_alphabetic() {
+        while pos < self.data.len() && (self.data[pos].is_ascii_alphanumeric() || self.data[pos] == b'_') {
             pos += 1;
         }
 
@@ -163,24 +297,103 @@ impl<'a> Lexer<'a> {
         let slice = &self.data[start..pos];
         let word = std::str::from_utf8(slice).expect("word should be utf-8");
 
-        match match_kw(word) {
-            Some(kw) => TokenKind::Keyword(kw),
+        if let Some(kw) = self.dialect.keyword(word) {
+            return TokenKind::Keyword(kw);
+        }
+
+        match match_data(word) {
+            Some(data) => TokenKind::Data(data),
             None => TokenKind::Identifier(word),
         }
     }
 
     fn lex_numerical_literal(&self) -> TokenKind<'a> {
-        todo!();
+        let start = self.cursor.get();
+        let mut pos = start;
+        while pos < self.data.len() && self.data[pos].is_ascii_digit() {
+            pos += 1;
+        }
+
+        // Fractional part, only when the period is followed by a digit.
+        if pos + 1 < self.data.len() && self.data[pos] == b'.' && self.data[pos + 1].is_ascii_digit() {
+            pos += 1;
+            while pos < self.data.len() && self.data[pos].is_ascii_digit() {
+                pos += 1;
+            }
+        }
+
+        self.cursor.set(pos);
+
+        let slice = &self.data[start..pos];
+        let word = std::str::from_utf8(slice).expect("number should be utf-8");
+        TokenKind::Literal(LiteralKind::Numeric(word.parse().expect("number should be valid")))
+    }
+
+    fn lex_placeholder(&self) -> Result<Token<'a>, LexerError> {
+        let start = self.cursor.get();
+        let mut pos = start + 1;
+        while pos < self.data.len() && self.data[pos].is_ascii_digit() {
+            pos += 1;
+        }
+
+        self.cursor.set(pos);
+
+        let digits = std::str::from_utf8(&self.data[start + 1..pos]).expect("digits should be utf-8");
+        match digits.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(Token::new(TokenKind::Placeholder(n), start)),
+            _ => Err(LexerError::new(format!("invalid parameter number: ${digits}"), start)),
+        }
+    }
+
+    /// Lexes a single character operator, or its two character form when followed by `=`.
+    fn lex_operator(&self, single: PuncKind, assign: Option<PuncKind>) -> TokenKind<'a> {
+        let pos = self.cursor.get();
+        match assign {
+            Some(assign) if self.peek_byte(1) == Some(b'=') => {
+                self.cursor.set(pos + 2);
+                TokenKind::Punc(assign)
+            }
+            _ => {
+                self.cursor.set(pos + 1);
+                TokenKind::Punc(single)
+            }
+        }
+    }
+
+    fn peek_byte(&self, offset: usize) -> Option<u8> {
+        self.data.get(self.cursor.get() + offset).copied()
     }
 }
 
 fn match_data(word: &str) -> Option<DataKind> {
     match word.to_lowercase().as_str() {
+        "char" => Some(DataKind::Char(None)),
+        "varchar" => Some(DataKind::VarChar(None)),
+        "binary" => Some(DataKind::Binary(None)),
+        "varbinary" => Some(DataKind::VarBinary(None)),
+        "tinyblob" => Some(DataKind::TinyBlob),
+        "tinytext" => Some(DataKind::TinyText),
+        "text" => Some(DataKind::Text(None)),
+        "blob" => Some(DataKind::Blob(None)),
+        "mediumtext" => Some(DataKind::MediumText(None)),
+        "mediumblob" => Some(DataKind::MediumBlob(None)),
+        "longtext" => Some(DataKind::LongText(None)),
+        "longblob" => Some(DataKind::LongBlob(None)),
+        "bit" => Some(DataKind::Bit(None)),
+        "tinyint" => Some(DataKind::TinyInt(None)),
+        "bool" | "boolean" => Some(DataKind::Bool),
+        "smallint" => Some(DataKind::SmallInt(None)),
+        "mediumint" => Some(DataKind::MediumInt(None)),
+        "int" | "integer" => Some(DataKind::Integer(None)),
+        "bigint" => Some(DataKind::BigInt(None)),
+        "float" => Some(DataKind::Float(None, None)),
+        "double" => Some(DataKind::Double(None, None)),
+        "decimal" | "numeric" => Some(DataKind::Decimal(None, None)),
         _ => None,
     }
 }
 
-fn match_kw(word: &str) -> Option<KeywordKind> {
+pub(crate) fn match_kw(word: &str) -> Option<KeywordKind> {
     match word.to_lowercase().as_str() {
         "add" => Some(KeywordKind::Add),
         "all" => Some(KeywordKind::All),
@@ -207,9 +420,11 @@ fn match_kw(word: &str) -> Option<KeywordKind> {
         "else" => Some(KeywordKind::Else),
         "exec" => Some(KeywordKind::Exec),
         "exists" => Some(KeywordKind::Exists),
+        "for" => Some(KeywordKind::For),
         "foreign" => Some(KeywordKind::Foreign),
         "from" => Some(KeywordKind::From),
         "full" => Some(KeywordKind::Full),
+        "grant" => Some(KeywordKind::Grant),
         "group" => Some(KeywordKind::Group),
         "having" => Some(KeywordKind::Having),
         "if" => Some(KeywordKind::If),
@@ -224,31 +439,49 @@ fn match_kw(word: &str) -> Option<KeywordKind> {
         "left" => Some(KeywordKind::Left),
         "like" => Some(KeywordKind::Like),
         "limit" => Some(KeywordKind::Limit),
+        "login" => Some(KeywordKind::Login),
+        "nologin" => Some(KeywordKind::NoLogin),
+        "nosuperuser" => Some(KeywordKind::NoSuperuser),
         "not" => Some(KeywordKind::Not),
         "null" => Some(KeywordKind::Null),
+        "on" => Some(KeywordKind::On),
+        "option" => Some(KeywordKind::Option),
         "or" => Some(KeywordKind::Or),
         "order" => Some(KeywordKind::Order),
         "outer" => Some(KeywordKind::Outer),
+        "password" => Some(KeywordKind::Password),
         "primary" => Some(KeywordKind::Primary),
+        "privileges" => Some(KeywordKind::Privileges),
         "procedure" => Some(KeywordKind::Procedure),
+        "revoke" => Some(KeywordKind::Revoke),
         "right" => Some(KeywordKind::Right),
+        "role" => Some(KeywordKind::Role),
         "rollback" => Some(KeywordKind::Rollback),
         "rownum" => Some(KeywordKind::Rownum),
+        "schema" => Some(KeywordKind::Schema),
         "select" => Some(KeywordKind::Select),
         "set" => Some(KeywordKind::Set),
         "some" => Some(KeywordKind::Some),
+        "superuser" => Some(KeywordKind::Superuser),
         "table" => Some(KeywordKind::Table),
         "then" => Some(KeywordKind::Then),
+        "to" => Some(KeywordKind::To),
         "top" => Some(KeywordKind::Top),
         "transaction" => Some(KeywordKind::Transaction),
         "truncate" => Some(KeywordKind::Truncate),
         "union" => Some(KeywordKind::Union),
         "unique" => Some(KeywordKind::Unique),
         "update" => Some(KeywordKind::Update),
+        "usage" => Some(KeywordKind::Usage),
+        "user" => Some(KeywordKind::User),
+        "using" => Some(KeywordKind::Using),
+        "vacuum" => Some(KeywordKind::Vacuum),
         "values" => Some(KeywordKind::Values),
         "view" => Some(KeywordKind::View),
         "when" => Some(KeywordKind::When),
         "where" => Some(KeywordKind::Where),
+        "with" => Some(KeywordKind::With),
+        "work" => Some(KeywordKind::Work),
         _ => None,
     }
 }
@@ -263,6 +496,7 @@ fn is_significant(kind: &TokenKind) -> bool {
 #[cfg(test)]
 mod tests {
     use super::Lexer;
+    use crate::parser::dialect::{AnsiDialect, MsSqlDialect, MySqlDialect};
 
     use crate::parser::token::*;
 
@@ -299,6 +533,70 @@ mod tests {
         assert_eq!(TokenKind::Eof, l.next().unwrap().kind);
     }
 
+    #[test]
+    fn test_identifier() {
+        let l = Lexer::new(b"rdb_stat_statements _tmp col2");
+
+        assert_eq!(TokenKind::Identifier("rdb_stat_statements"), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Identifier("_tmp"), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Identifier("col2"), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Eof, l.next().unwrap().kind);
+    }
+
+    #[test]
+    fn test_data() {
+        let l = Lexer::new(b"VARCHAR(255) int Decimal");
+
+        assert_eq!(TokenKind::Data(DataKind::VarChar(None)), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Punc(PuncKind::LParen), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Literal(LiteralKind::Numeric(255.0)), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Punc(PuncKind::RParen), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Data(DataKind::Integer(None)), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Data(DataKind::Decimal(None, None)), l.next().unwrap().kind);
+        assert_eq!(l.word_at(13), "int");
+    }
+
+    #[test]
+    fn test_numeric_literal() {
+        let l = Lexer::new(b"42 3.25 7.");
+
+        assert_eq!(TokenKind::Literal(LiteralKind::Numeric(42.0)), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Literal(LiteralKind::Numeric(3.25)), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Literal(LiteralKind::Numeric(7.0)), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Punc(PuncKind::Period), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Eof, l.next().unwrap().kind);
+    }
+
+    #[test]
+    fn test_placeholder() {
+        let l = Lexer::new(b"$1 $12");
+
+        assert_eq!(TokenKind::Placeholder(1), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Placeholder(12), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Eof, l.next().unwrap().kind);
+
+        assert!(Lexer::new(b"$0").next().is_err());
+    }
+
+    #[test]
+    fn test_operators() {
+        let l = Lexer::new(b"< <= <> > >= != + += - / % *");
+
+        assert_eq!(TokenKind::Punc(PuncKind::LessThan), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Punc(PuncKind::LessThanEq), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Punc(PuncKind::NotEqual), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Punc(PuncKind::GreaterThan), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Punc(PuncKind::GreaterThanEq), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Punc(PuncKind::NotEqual), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Punc(PuncKind::Add), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Punc(PuncKind::AddAssign), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Punc(PuncKind::Subtract), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Punc(PuncKind::Divide), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Punc(PuncKind::Modulo), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Punc(PuncKind::Star), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Eof, l.next().unwrap().kind);
+    }
+
     #[test]
     fn test_string_literal() {
         let l = Lexer::new(b"UPDATE dog SET color = 'golden' WHERE breed = 'golden retriever';");
@@ -319,4 +617,57 @@ mod tests {
         assert_eq!(TokenKind::Punc(PuncKind::SemiColon), l.next().unwrap().kind);
         assert_eq!(TokenKind::Eof, l.next().unwrap().kind);
     }
+
+    #[test]
+    fn test_comments() {
+        let l = Lexer::new(b"select -- the weight\n weight /* of\n dogs */ - 1 --");
+
+        assert_eq!(TokenKind::Keyword(KeywordKind::Select), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Identifier("weight"), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Punc(PuncKind::Subtract), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Literal(LiteralKind::Numeric(1.0)), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Eof, l.next().unwrap().kind);
+
+        let l = Lexer::new(b"/* open");
+        assert_eq!(l.next().unwrap_err().message, "unterminated comment");
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let l = Lexer::new(br"'it''s' 'it\'s'");
+        assert_eq!(TokenKind::Literal(LiteralKind::String("it''s")), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Literal(LiteralKind::String(r"it\'s")), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Eof, l.next().unwrap().kind);
+
+        let l = Lexer::with_dialect(br"'C:\' 'x'", &AnsiDialect);
+        assert_eq!(TokenKind::Literal(LiteralKind::String(r"C:\")), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Literal(LiteralKind::String("x")), l.next().unwrap().kind);
+
+        let l = Lexer::new(b"'open");
+        assert_eq!(l.next().unwrap_err().message, "unterminated string literal");
+
+        let l = Lexer::with_dialect(br#""it""s" "it\"s" "it's" `x`"#, &MySqlDialect);
+        assert_eq!(TokenKind::Literal(LiteralKind::String(r#"it""s"#)), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Literal(LiteralKind::String(r#"it\"s"#)), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Literal(LiteralKind::String("it's")), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Identifier("x"), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Eof, l.next().unwrap().kind);
+    }
+
+    #[test]
+    fn test_quoted_identifier() {
+        let l = Lexer::new(b"\"select\" `from` \"a\"\"b\"");
+        assert_eq!(TokenKind::Identifier("select"), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Identifier("from"), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Identifier("a\"\"b"), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Eof, l.next().unwrap().kind);
+
+        let l = Lexer::with_dialect(b"[order by]", &MsSqlDialect);
+        assert_eq!(TokenKind::Identifier("order by"), l.next().unwrap().kind);
+
+        let l = Lexer::with_dialect(b"\"top\" top `x`", &AnsiDialect);
+        assert_eq!(TokenKind::Identifier("top"), l.next().unwrap().kind);
+        assert_eq!(TokenKind::Identifier("top"), l.next().unwrap().kind);
+        assert!(l.next().is_err());
+    }
 }
@@ -1,56 +1,105 @@
+use crate::cancel::Checkpoint;
 use crate::parser::ast::{
-    AST, DatasetReference, ExprKind, FromClause, FromItemKind, SelectClause, SelectItemKind, SelectStmt, StatementKind,
+    AST, AlterRoleStmt, Assignment, BinaryOperator, ColumnConstraintKind, ColumnDef, CreateIndexStmt, CreateRoleStmt,
+    CreateTableStmt, DatasetReference, DeleteStmt, DropIndexStmt, DropRoleStmt, ExprKind, FromClause, FromItemKind,
+    GrantKind, GrantObject, GrantStmt, Grantee, IndexMethod, InsertStmt, IsolationLevel, LimitClause, LockStrength,
+    LockingClause, ObjectReference, PrivilegeItem, PrivilegeKind, RoleOption, SelectClause, SelectItemKind, SelectStmt,
+    SetStmt, StatementKind, TableConstraintKind, UnaryOperator, UpdateStmt, WhereClause,
 };
+use crate::parser::dialect::{Clause, Dialect, GenericDialect};
 use crate::parser::lexer::{Lexer, LexerError};
-use crate::parser::token::{KeywordKind, PuncKind, TokenKind, TokenKind::Keyword};
-use std::cell::RefCell;
+use crate::parser::token::{DataKind, KeywordKind, LiteralKind, PuncKind, Token, TokenKind, TokenKind::Keyword};
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
 pub mod ast;
-mod lexer;
+pub mod dialect;
+pub mod fingerprint;
+pub mod lexer;
+pub mod split;
 pub mod token;
+pub mod visitor;
+
+/// How deep expressions may nest, deeper ones are rejected before parsing them overflows the stack.
+pub const MAX_EXPR_DEPTH: usize = 256;
 
 pub struct Parser<'a> {
     lexer: Rc<RefCell<Lexer<'a>>>,
+    dialect: &'a dyn Dialect,
+    checkpoint: Option<&'a Checkpoint>,
+    /// Expressions being parsed around the current one.
+    depth: Cell<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ParseErrorKind {
+    #[default]
+    Syntax,
+    /// Expressions nest deeper than [`MAX_EXPR_DEPTH`].
+    TooComplex,
 }
 
 #[derive(Clone, Debug)]
 pub struct ParseError {
+    pub kind: ParseErrorKind,
     pub pos: usize,
     pub message: String,
 }
 
 impl ParseError {
     fn new(message: String, pos: usize) -> Self {
-        ParseError { message, pos }
+        ParseError {
+            kind: ParseErrorKind::Syntax,
+            message,
+            pos,
+        }
+    }
+
+    fn too_complex(pos: usize) -> Self {
+        ParseError {
+            kind: ParseErrorKind::TooComplex,
+            message: "statement is too complex".to_string(),
+            pos,
+        }
     }
 }
 
-impl Into<String> for ParseError {
-    fn into(self) -> String {
-        format!("ERROR[Position:{0}]: '{1}'", self.pos, self.message)
+impl From<ParseError> for String {
+    fn from(err: ParseError) -> Self {
+        format!("ERROR[Position:{0}]: '{1}'", err.pos, err.message)
     }
 }
 
 impl From<LexerError> for ParseError {
     fn from(err: LexerError) -> Self {
-        ParseError {
-            message: err.message,
-            pos: err.pos,
-        }
+        ParseError::new(err.message, err.pos)
     }
 }
 
 impl<'a> Parser<'a> {
     pub fn new(data: &'a [u8]) -> Self {
+        Parser::with_dialect(data, &GenericDialect)
+    }
+
+    pub fn with_dialect(data: &'a [u8], dialect: &'a dyn Dialect) -> Self {
         Parser {
-            lexer: Rc::new(RefCell::new(Lexer::new(data))),
+            lexer: Rc::new(RefCell::new(Lexer::with_dialect(data, dialect))),
+            dialect,
+            checkpoint: None,
+            depth: Cell::new(0),
         }
     }
 
-    pub fn parse(&'a mut self) -> Result<AST<'a>, ParseError> {
+    /// Gives up with an error once `checkpoint` no longer passes.
+    pub fn with_checkpoint(mut self, checkpoint: &'a Checkpoint) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    pub fn parse(&mut self) -> Result<AST<'a>, ParseError> {
         let mut ast = AST::new();
         loop {
+            self.check_cancel()?;
             match self.parse_stmt() {
                 Ok(Some(stmt)) => ast.append_stmt(stmt),
                 Ok(None) => break,
@@ -60,18 +109,29 @@ impl<'a> Parser<'a> {
         Ok(ast)
     }
 
-    fn parse_stmt(&'a self) -> Result<Option<StatementKind<'a>>, ParseError> {
+    fn parse_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
         let l = self.lexer.borrow();
 
+        // Empty statements
+        while l.eat(TokenKind::Punc(PuncKind::SemiColon)) {}
+
         match l.next() {
             Ok(token) => match token.kind.clone() {
                 Keyword(kw) => match kw {
+                    KeywordKind::Alter => self.parse_alter_stmt(),
+                    KeywordKind::Begin => self.parse_begin_stmt(),
                     KeywordKind::Commit => self.parse_commit_stmt(),
                     KeywordKind::Create => self.parse_create_stmt(),
                     KeywordKind::Delete => self.parse_delete_stmt(),
+                    KeywordKind::Drop => self.parse_drop_stmt(),
+                    KeywordKind::Grant => self.parse_grant_stmt(),
+                    KeywordKind::Insert => self.parse_insert_stmt(),
+                    KeywordKind::Revoke => self.parse_revoke_stmt(),
                     KeywordKind::Rollback => self.parse_rollback_stmt(),
                     KeywordKind::Select => self.parse_select_stmt(),
+                    KeywordKind::Set => self.parse_set_stmt(),
                     KeywordKind::Update => self.parse_update_stmt(),
+                    KeywordKind::Vacuum => self.parse_vacuum_stmt(),
                     _ => Err(ParseError::new(
                         format!("Unexpected keyword token: {0}", token.kind),
                         token.pos,
@@ -84,9 +144,25 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_select_stmt(&'a self) -> Result<Option<StatementKind<'a>>, ParseError> {
+    fn parse_select_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
         let l = self.lexer.borrow();
 
+        // Top clause
+        let mut limit_clause = None;
+        let t = l.peek()?;
+        if t.kind == TokenKind::Keyword(KeywordKind::Top)
+            && matches!(
+                l.peek_second()?.kind,
+                TokenKind::Literal(LiteralKind::Numeric(_)) | TokenKind::Punc(PuncKind::LParen)
+            )
+        {
+            self.check_clause(Clause::Top, t.pos)?;
+            l.bump();
+            limit_clause = Some(LimitClause {
+                count: self.parse_primary_expr()?,
+            });
+        }
+
         // Select clause
         let select_clause = self.parse_select_clause()?;
 
@@ -94,9 +170,10 @@ impl<'a> Parser<'a> {
         l.expect(TokenKind::Keyword(KeywordKind::From))?;
         let from_clause = self.parse_from_clause()?;
 
-        let select = SelectStmt::new(select_clause, from_clause);
+        let mut select = SelectStmt::new(select_clause, from_clause);
 
         // Where clause
+        select.where_clause = self.parse_where_clause()?;
 
         // GroupBy clause
 
@@ -105,47 +182,94 @@ impl<'a> Parser<'a> {
         // OrderBy clause
 
         // Limit clause
+        let t = l.peek()?;
+        if t.kind == TokenKind::Keyword(KeywordKind::Limit) {
+            self.check_clause(Clause::Limit, t.pos)?;
+            if limit_clause.is_some() {
+                return Err(ParseError::new("LIMIT cannot be combined with TOP".to_string(), t.pos));
+            }
+            l.bump();
+            limit_clause = Some(LimitClause {
+                count: self.parse_expr()?,
+            });
+        }
+        select.limit_clause = limit_clause;
+
+        // Locking clause
+        if l.eat(TokenKind::Keyword(KeywordKind::For)) {
+            let strength = match l.eat(TokenKind::Keyword(KeywordKind::Update)) {
+                true => LockStrength::Update,
+                false => {
+                    self.parse_word("share")?;
+                    LockStrength::Share
+                }
+            };
+            let t = l.peek()?;
+            let nowait = self.identifier_of(&t).is_some_and(|word| word.eq_ignore_ascii_case("nowait"));
+            if nowait {
+                l.bump();
+            }
+            select.locking_clause = Some(LockingClause { strength, nowait });
+        }
+
+        self.parse_eol()?;
 
         Ok(Some(StatementKind::Select(select)))
     }
 
-    fn parse_select_clause(&'a self) -> Result<SelectClause<'a>, ParseError> {
+    fn parse_select_clause(&self) -> Result<SelectClause<'a>, ParseError> {
         let l = self.lexer.borrow();
 
-        match l.next() {
-            Ok(t) => match t.kind {
-                TokenKind::Punc(PuncKind::Star) => Ok(SelectClause::all()),
-                TokenKind::Punc(PuncKind::LParen) => {
-                    let expr = self.parse_select_list();
-                    Ok(SelectClause { selected: expr })
-                }
-                _ => Err(ParseError::new("Unexpected token".to_string(), t.pos)),
-            },
-            Err(err) => Err(err.into()),
+        if l.eat(TokenKind::Punc(PuncKind::Star)) {
+            return Ok(SelectClause::all());
         }
+
+        Ok(SelectClause {
+            selected: self.parse_select_list()?,
+        })
     }
 
-    fn parse_select_list(&'a self) -> Vec<SelectItemKind<'a>> {
-        todo!();
+    fn parse_select_list(&self) -> Result<Vec<SelectItemKind<'a>>, ParseError> {
+        let l = self.lexer.borrow();
+
+        let mut selected = Vec::new();
+        loop {
+            let item = match self.parse_expr()? {
+                ExprKind::Identifier(obj) => SelectItemKind::Identifier(obj),
+                expr => SelectItemKind::Expr(expr),
+            };
+            selected.push(item);
+
+            if !l.eat(TokenKind::Punc(PuncKind::Comma)) {
+                break;
+            }
+        }
+
+        Ok(selected)
     }
 
-    fn parse_from_clause(&'a self) -> Result<FromClause<'a>, ParseError> {
+    fn parse_from_clause(&self) -> Result<FromClause<'a>, ParseError> {
         let l = self.lexer.borrow();
 
         let mut from_clause = FromClause::new();
 
         loop {
-            match l.next() {
-                Ok(t) => match t.kind {
-                    TokenKind::Identifier(id) => from_clause.from.push(FromItemKind::Dataset(DatasetReference::new(id))),
-                    TokenKind::Keyword(KeywordKind::Join) => {
-                        // There will be left, right, inner etc...
-                        todo!();
-                    }
-                    _ => break,
-                },
-                Err(err) => return Err(err.into()),
-            };
+            let t = l.peek()?;
+            if let Some(id) = self.identifier_of(&t) {
+                l.bump();
+                from_clause.from.push(FromItemKind::Dataset(DatasetReference::new(id)));
+                if !l.eat(TokenKind::Punc(PuncKind::Comma)) {
+                    break;
+                }
+                continue;
+            }
+
+            match t.kind {
+                TokenKind::Keyword(KeywordKind::Join) => {
+                    return Err(ParseError::new("JOIN is not supported yet".to_string(), t.pos));
+                }
+                _ => break,
+            }
         }
 
         if from_clause.is_empty() {
@@ -155,38 +279,817 @@ impl<'a> Parser<'a> {
         Ok(from_clause)
     }
 
-    fn parse_commit_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
+    fn parse_expr(&self) -> Result<ExprKind<'a>, ParseError> {
+        self.check_cancel()?;
+        self.parse_binary_expr(0)
+    }
+
+    /// Precedence climbing over binary operators that bind tighter than `min_precedence`.
+    fn parse_binary_expr(&self, min_precedence: u8) -> Result<ExprKind<'a>, ParseError> {
+        let l = self.lexer.borrow();
+
+        let mut left = self.parse_unary_expr()?;
+
+        loop {
+            let op = match binary_operator(&l.peek()?.kind) {
+                Some(op) if op.precedence() > min_precedence => op,
+                _ => break,
+            };
+            l.bump();
+
+            let right = self.parse_binary_expr(op.precedence())?;
+            left = ExprKind::Binary(Box::new(left), op, Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_unary_expr(&self) -> Result<ExprKind<'a>, ParseError> {
+        let depth = self.depth.get();
+        if depth == MAX_EXPR_DEPTH {
+            return Err(ParseError::too_complex(self.lexer.borrow().position()));
+        }
+        self.depth.set(depth + 1);
+        let expr = self.parse_prefix_expr();
+        self.depth.set(depth);
+        expr
+    }
+
+    fn parse_prefix_expr(&self) -> Result<ExprKind<'a>, ParseError> {
+        let l = self.lexer.borrow();
+
+        // NOT binds looser than comparisons, sign binds tighter than everything.
+        if l.eat(TokenKind::Keyword(KeywordKind::Not)) {
+            let operand = self.parse_binary_expr(BinaryOperator::And.precedence())?;
+            return Ok(ExprKind::Unary(UnaryOperator::Not, Box::new(operand)));
+        }
+        if l.eat(TokenKind::Punc(PuncKind::Subtract)) {
+            return Ok(ExprKind::Unary(UnaryOperator::Minus, Box::new(self.parse_unary_expr()?)));
+        }
+        if l.eat(TokenKind::Punc(PuncKind::Add)) {
+            return Ok(ExprKind::Unary(UnaryOperator::Plus, Box::new(self.parse_unary_expr()?)));
+        }
+
+        self.parse_primary_expr()
+    }
+
+    fn parse_primary_expr(&self) -> Result<ExprKind<'a>, ParseError> {
+        let l = self.lexer.borrow();
+
+        let t = l.next()?;
+        match t.kind {
+            TokenKind::Literal(literal) => Ok(ExprKind::Literal(literal)),
+            TokenKind::Keyword(KeywordKind::Null) => Ok(ExprKind::Literal(LiteralKind::Null)),
+            TokenKind::Placeholder(n) => Ok(ExprKind::Placeholder(n)),
+            TokenKind::Keyword(KeywordKind::Rownum) => {
+                self.check_clause(Clause::Rownum, t.pos)?;
+                Ok(ExprKind::Identifier(ObjectReference::new("rownum")))
+            }
+            _ if self.identifier_of(&t).is_some() => {
+                let id = self.identifier_of(&t).unwrap();
+                Ok(ExprKind::Identifier(self.parse_object_reference(id)?))
+            }
+            TokenKind::Punc(PuncKind::LParen) => {
+                let expr = self.parse_expr()?;
+                l.expect(TokenKind::Punc(PuncKind::RParen))?;
+                Ok(expr)
+            }
+            _ => Err(ParseError::new(format!("Unexpected token: {0}", t.kind), t.pos)),
+        }
+    }
+
+    /// Parses the remainder of a possibly qualified name, `obj`, `dataset.obj` or `schema.dataset.obj`.
+    fn parse_object_reference(&self, first: &'a str) -> Result<ObjectReference<'a>, ParseError> {
+        let l = self.lexer.borrow();
+
+        let mut parts = vec![first];
+        while parts.len() < 3 && l.eat(TokenKind::Punc(PuncKind::Period)) {
+            parts.push(self.parse_identifier()?);
+        }
+
+        Ok(match parts[..] {
+            [obj] => ObjectReference::new(obj),
+            [dataset, obj] => ObjectReference {
+                dataset: Some(DatasetReference::new(dataset)),
+                obj: Some(obj),
+            },
+            [schema, dataset, obj] => ObjectReference {
+                dataset: Some(DatasetReference {
+                    schema: Some(schema),
+                    dataset: Some(dataset),
+                }),
+                obj: Some(obj),
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    fn parse_begin_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
+        self.parse_transaction_noise();
+        let isolation = self.parse_isolation_level()?;
+        self.parse_eol()?;
+
+        Ok(Some(StatementKind::Begin(isolation)))
+    }
+
+    /// Optional `ISOLATION LEVEL {SERIALIZABLE | REPEATABLE READ | READ COMMITTED | READ UNCOMMITTED}`.
+    fn parse_isolation_level(&self) -> Result<Option<IsolationLevel>, ParseError> {
         let l = self.lexer.borrow();
 
-        l.eat(TokenKind::Keyword(KeywordKind::Work));
+        let t = l.peek()?;
+        if !self
+            .identifier_of(&t)
+            .is_some_and(|word| word.eq_ignore_ascii_case("isolation"))
+        {
+            return Ok(None);
+        }
+        l.bump();
+        self.parse_word("level")?;
+        let pos = l.peek()?.pos;
+        let level = match self.parse_identifier()?.to_lowercase().as_str() {
+            "serializable" => IsolationLevel::Serializable,
+            "repeatable" => {
+                self.parse_word("read")?;
+                IsolationLevel::RepeatableRead
+            }
+            "read" => {
+                let pos = l.peek()?.pos;
+                match self.parse_identifier()?.to_lowercase().as_str() {
+                    // Never seeing uncommitted changes is allowed at that level too.
+                    "committed" | "uncommitted" => IsolationLevel::ReadCommitted,
+                    other => return Err(ParseError::new(format!("Unknown isolation level: read {other}"), pos)),
+                }
+            }
+            other => return Err(ParseError::new(format!("Unknown isolation level: {other}"), pos)),
+        };
+        Ok(Some(level))
+    }
+
+    /// An identifier that has to be `word`, which is no keyword.
+    fn parse_word(&self, word: &str) -> Result<(), ParseError> {
+        let pos = self.lexer.borrow().peek()?.pos;
+        match self.parse_identifier()? {
+            found if found.eq_ignore_ascii_case(word) => Ok(()),
+            found => Err(ParseError::new(
+                format!("Expected {0}, found: {found}", word.to_uppercase()),
+                pos,
+            )),
+        }
+    }
+
+    fn parse_commit_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
+        self.parse_transaction_noise();
         self.parse_eol()?;
 
         Ok(Some(StatementKind::Commit))
     }
 
     fn parse_rollback_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
+        self.parse_transaction_noise();
+        self.parse_eol()?;
+
+        Ok(Some(StatementKind::Rollback))
+    }
+
+    /// `VACUUM [table]`
+    fn parse_vacuum_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
         let l = self.lexer.borrow();
 
-        l.eat(TokenKind::Keyword(KeywordKind::Work));
+        let t = l.peek()?;
+        let table = match t.kind {
+            TokenKind::Eof | TokenKind::Punc(PuncKind::SemiColon) => None,
+            _ => Some(self.parse_dataset_reference()?),
+        };
         self.parse_eol()?;
 
-        Ok(Some(StatementKind::Rollback))
+        Ok(Some(StatementKind::Vacuum(table)))
+    }
+
+    /// Optional `WORK` or `TRANSACTION` after `BEGIN`, `COMMIT` and `ROLLBACK`.
+    fn parse_transaction_noise(&self) {
+        let l = self.lexer.borrow();
+
+        if !l.eat(TokenKind::Keyword(KeywordKind::Work)) {
+            l.eat(TokenKind::Keyword(KeywordKind::Transaction));
+        }
+    }
+
+    /// `SET name {= | TO} {value | DEFAULT}`
+    fn parse_set_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
+        let l = self.lexer.borrow();
+
+        let name = self.parse_identifier()?;
+        if !l.eat(TokenKind::Keyword(KeywordKind::To)) {
+            l.expect(TokenKind::Punc(PuncKind::Equal))?;
+        }
+        let value = match l.eat(TokenKind::Keyword(KeywordKind::Default)) {
+            true => None,
+            false => Some(self.parse_expr()?),
+        };
+        self.parse_eol()?;
+
+        Ok(Some(StatementKind::Set(SetStmt { name, value })))
     }
 
     fn parse_delete_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
-        todo!();
+        let l = self.lexer.borrow();
+
+        l.expect(TokenKind::Keyword(KeywordKind::From))?;
+        let table = self.parse_dataset_reference()?;
+        let where_clause = self.parse_where_clause()?;
+        self.parse_eol()?;
+
+        Ok(Some(StatementKind::Delete(DeleteStmt { table, where_clause })))
     }
 
     fn parse_update_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
-        todo!();
+        let l = self.lexer.borrow();
+
+        let table = self.parse_dataset_reference()?;
+
+        l.expect(TokenKind::Keyword(KeywordKind::Set))?;
+        let mut assignments = Vec::new();
+        loop {
+            let column = self.parse_identifier()?;
+            l.expect(TokenKind::Punc(PuncKind::Equal))?;
+            assignments.push(Assignment {
+                column,
+                value: self.parse_expr()?,
+            });
+
+            if !l.eat(TokenKind::Punc(PuncKind::Comma)) {
+                break;
+            }
+        }
+
+        let where_clause = self.parse_where_clause()?;
+        self.parse_eol()?;
+
+        Ok(Some(StatementKind::Update(UpdateStmt {
+            table,
+            assignments,
+            where_clause,
+        })))
+    }
+
+    fn parse_insert_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
+        let l = self.lexer.borrow();
+
+        l.expect(TokenKind::Keyword(KeywordKind::Into))?;
+        let table = self.parse_dataset_reference()?;
+
+        let mut columns = Vec::new();
+        if l.peek()?.kind == TokenKind::Punc(PuncKind::LParen) {
+            columns = self.parse_column_list()?;
+        }
+
+        l.expect(TokenKind::Keyword(KeywordKind::Values))?;
+        let mut values = Vec::new();
+        loop {
+            let t = l.peek()?;
+            l.expect(TokenKind::Punc(PuncKind::LParen))?;
+            let mut row = vec![self.parse_expr()?];
+            while l.eat(TokenKind::Punc(PuncKind::Comma)) {
+                row.push(self.parse_expr()?);
+            }
+            l.expect(TokenKind::Punc(PuncKind::RParen))?;
+
+            if !columns.is_empty() && row.len() != columns.len() {
+                return Err(ParseError::new(
+                    format!("Expected {0} values, found {1}", columns.len(), row.len()),
+                    t.pos,
+                ));
+            }
+            values.push(row);
+
+            if !l.eat(TokenKind::Punc(PuncKind::Comma)) {
+                break;
+            }
+        }
+
+        self.parse_eol()?;
+
+        Ok(Some(StatementKind::Insert(InsertStmt { table, columns, values })))
+    }
+
+    fn parse_where_clause(&self) -> Result<Option<WhereClause<'a>>, ParseError> {
+        let l = self.lexer.borrow();
+
+        if !l.eat(TokenKind::Keyword(KeywordKind::Where)) {
+            return Ok(None);
+        }
+
+        Ok(Some(WhereClause {
+            expr: self.parse_expr()?,
+        }))
     }
 
     fn parse_create_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
-        todo!();
+        let l = self.lexer.borrow();
+
+        let t = l.next()?;
+        match t.kind {
+            TokenKind::Keyword(KeywordKind::Table) => self.parse_create_table_stmt(),
+            TokenKind::Keyword(KeywordKind::Index) => self.parse_create_index_stmt(false),
+            TokenKind::Keyword(KeywordKind::Unique) => {
+                l.expect(TokenKind::Keyword(KeywordKind::Index))?;
+                self.parse_create_index_stmt(true)
+            }
+            TokenKind::Keyword(KeywordKind::Role) => self.parse_create_role_stmt(false),
+            TokenKind::Keyword(KeywordKind::User) => self.parse_create_role_stmt(true),
+            _ => Err(ParseError::new(format!("Unexpected token: {0}", t.kind), t.pos)),
+        }
     }
 
-    fn parse_list_literal(&self) -> Result<Option<ExprKind>, ParseError> {
-        todo!();
+    fn parse_create_table_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
+        let l = self.lexer.borrow();
+
+        let mut if_not_exists = false;
+        if l.eat(TokenKind::Keyword(KeywordKind::If)) {
+            l.expect(TokenKind::Keyword(KeywordKind::Not))?;
+            l.expect(TokenKind::Keyword(KeywordKind::Exists))?;
+            if_not_exists = true;
+        }
+
+        let table = self.parse_dataset_reference()?;
+
+        let mut create = CreateTableStmt {
+            table,
+            if_not_exists,
+            columns: Vec::new(),
+            constraints: Vec::new(),
+        };
+
+        l.expect(TokenKind::Punc(PuncKind::LParen))?;
+        loop {
+            match self.parse_table_constraint()? {
+                Some(constraint) => create.constraints.push(constraint),
+                None => create.columns.push(self.parse_column_def()?),
+            }
+
+            if !l.eat(TokenKind::Punc(PuncKind::Comma)) {
+                break;
+            }
+        }
+        l.expect(TokenKind::Punc(PuncKind::RParen))?;
+
+        if create.columns.is_empty() {
+            return Err(ParseError::new(
+                "Table must have at least one column".to_string(),
+                l.position(),
+            ));
+        }
+
+        self.parse_eol()?;
+
+        Ok(Some(StatementKind::CreateTable(create)))
+    }
+
+    /// `CREATE [UNIQUE] INDEX [IF NOT EXISTS] name ON table [USING {BTREE | HASH}] (column, ...)`
+    fn parse_create_index_stmt(&self, unique: bool) -> Result<Option<StatementKind<'a>>, ParseError> {
+        let l = self.lexer.borrow();
+
+        let mut if_not_exists = false;
+        if l.eat(TokenKind::Keyword(KeywordKind::If)) {
+            l.expect(TokenKind::Keyword(KeywordKind::Not))?;
+            l.expect(TokenKind::Keyword(KeywordKind::Exists))?;
+            if_not_exists = true;
+        }
+        let name = self.parse_identifier()?;
+        l.expect(TokenKind::Keyword(KeywordKind::On))?;
+        let table = self.parse_dataset_reference()?;
+        let mut method = IndexMethod::default();
+        if l.eat(TokenKind::Keyword(KeywordKind::Using)) {
+            let pos = l.peek()?.pos;
+            method = match self.parse_identifier()?.to_lowercase().as_str() {
+                "btree" => IndexMethod::BTree,
+                "hash" => IndexMethod::Hash,
+                other => return Err(ParseError::new(format!("Unknown index method: {other}"), pos)),
+            };
+        }
+        let columns = self.parse_column_list()?;
+        self.parse_eol()?;
+
+        Ok(Some(StatementKind::CreateIndex(CreateIndexStmt {
+            name,
+            table,
+            method,
+            columns,
+            unique,
+            if_not_exists,
+        })))
+    }
+
+    /// `CREATE {ROLE | USER} name [WITH] options`, users may log in unless told otherwise.
+    fn parse_create_role_stmt(&self, user: bool) -> Result<Option<StatementKind<'a>>, ParseError> {
+        let name = self.parse_identifier()?;
+        let mut options = if user { vec![RoleOption::Login(true)] } else { Vec::new() };
+        options.extend(self.parse_role_options()?);
+        self.parse_eol()?;
+
+        Ok(Some(StatementKind::CreateRole(CreateRoleStmt { name, options })))
+    }
+
+    /// `ALTER {ROLE | USER} name [WITH] options`
+    fn parse_alter_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
+        let l = self.lexer.borrow();
+
+        if !l.eat(TokenKind::Keyword(KeywordKind::Role)) {
+            l.expect(TokenKind::Keyword(KeywordKind::User))?;
+        }
+        let name = self.parse_identifier()?;
+        let options = self.parse_role_options()?;
+        self.parse_eol()?;
+
+        Ok(Some(StatementKind::AlterRole(AlterRoleStmt { name, options })))
+    }
+
+    /// `DROP {ROLE | USER | INDEX} [IF EXISTS] name, ...`
+    fn parse_drop_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
+        let l = self.lexer.borrow();
+
+        let index = l.eat(TokenKind::Keyword(KeywordKind::Index));
+        if !index && !l.eat(TokenKind::Keyword(KeywordKind::Role)) {
+            l.expect(TokenKind::Keyword(KeywordKind::User))?;
+        }
+        let mut if_exists = false;
+        if l.eat(TokenKind::Keyword(KeywordKind::If)) {
+            l.expect(TokenKind::Keyword(KeywordKind::Exists))?;
+            if_exists = true;
+        }
+        let names = self.parse_identifier_list()?;
+        self.parse_eol()?;
+
+        if index {
+            return Ok(Some(StatementKind::DropIndex(DropIndexStmt { names, if_exists })));
+        }
+        Ok(Some(StatementKind::DropRole(DropRoleStmt { names, if_exists })))
+    }
+
+    fn parse_role_options(&self) -> Result<Vec<RoleOption<'a>>, ParseError> {
+        let l = self.lexer.borrow();
+
+        l.eat(TokenKind::Keyword(KeywordKind::With));
+        let mut options = Vec::new();
+        loop {
+            let option = match l.peek()?.kind {
+                TokenKind::Keyword(KeywordKind::Superuser) => RoleOption::Superuser(true),
+                TokenKind::Keyword(KeywordKind::NoSuperuser) => RoleOption::Superuser(false),
+                TokenKind::Keyword(KeywordKind::Login) => RoleOption::Login(true),
+                TokenKind::Keyword(KeywordKind::NoLogin) => RoleOption::Login(false),
+                TokenKind::Keyword(KeywordKind::Password) => {
+                    l.bump();
+                    let t = l.next()?;
+                    options.push(match t.kind {
+                        TokenKind::Literal(LiteralKind::String(password)) => RoleOption::Password(Some(password)),
+                        TokenKind::Keyword(KeywordKind::Null) => RoleOption::Password(None),
+                        _ => return Err(ParseError::new(format!("Expected password, found: {0}", t.kind), t.pos)),
+                    });
+                    continue;
+                }
+                _ => break,
+            };
+            l.bump();
+            options.push(option);
+        }
+
+        Ok(options)
+    }
+
+    /// `GRANT what TO grantees [WITH GRANT OPTION]`
+    fn parse_grant_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
+        let l = self.lexer.borrow();
+
+        let kind = self.parse_grant_kind()?;
+        l.expect(TokenKind::Keyword(KeywordKind::To))?;
+        let grantees = self.parse_grantees()?;
+
+        let grant_option = l.eat(TokenKind::Keyword(KeywordKind::With));
+        if grant_option {
+            l.expect(TokenKind::Keyword(KeywordKind::Grant))?;
+            l.expect(TokenKind::Keyword(KeywordKind::Option))?;
+        }
+        self.parse_eol()?;
+
+        Ok(Some(StatementKind::Grant(GrantStmt {
+            kind,
+            grantees,
+            grant_option,
+        })))
+    }
+
+    /// `REVOKE [GRANT OPTION FOR] what FROM grantees`
+    fn parse_revoke_stmt(&self) -> Result<Option<StatementKind<'a>>, ParseError> {
+        let l = self.lexer.borrow();
+
+        let grant_option = l.peek()?.kind == TokenKind::Keyword(KeywordKind::Grant);
+        if grant_option {
+            l.bump();
+            l.expect(TokenKind::Keyword(KeywordKind::Option))?;
+            l.expect(TokenKind::Keyword(KeywordKind::For))?;
+        }
+
+        let kind = self.parse_grant_kind()?;
+        l.expect(TokenKind::Keyword(KeywordKind::From))?;
+        let grantees = self.parse_grantees()?;
+        self.parse_eol()?;
+
+        Ok(Some(StatementKind::Revoke(GrantStmt {
+            kind,
+            grantees,
+            grant_option,
+        })))
+    }
+
+    /// Either privileges followed by `ON` and the objects they are on, or the names of roles.
+    fn parse_grant_kind(&self) -> Result<GrantKind<'a>, ParseError> {
+        let l = self.lexer.borrow();
+
+        let t = l.peek()?;
+        let privileges = if l.eat(TokenKind::Keyword(KeywordKind::All)) {
+            l.eat(TokenKind::Keyword(KeywordKind::Privileges));
+            None
+        } else {
+            let mut items = Vec::new();
+            loop {
+                let t = l.next()?;
+                let privilege = match t.kind {
+                    TokenKind::Keyword(KeywordKind::Select) => Ok(PrivilegeKind::Select),
+                    TokenKind::Keyword(KeywordKind::Insert) => Ok(PrivilegeKind::Insert),
+                    TokenKind::Keyword(KeywordKind::Update) => Ok(PrivilegeKind::Update),
+                    TokenKind::Keyword(KeywordKind::Delete) => Ok(PrivilegeKind::Delete),
+                    TokenKind::Keyword(KeywordKind::Create) => Ok(PrivilegeKind::Create),
+                    TokenKind::Keyword(KeywordKind::Usage) => Ok(PrivilegeKind::Usage),
+                    _ => match self.identifier_of(&t) {
+                        Some(role) => Err(role),
+                        None => return Err(ParseError::new(format!("Unexpected token: {0}", t.kind), t.pos)),
+                    },
+                };
+                let columns = match l.peek()?.kind {
+                    TokenKind::Punc(PuncKind::LParen) => self.parse_column_list()?,
+                    _ => Vec::new(),
+                };
+                items.push((privilege, columns, t.pos));
+
+                if !l.eat(TokenKind::Punc(PuncKind::Comma)) {
+                    break;
+                }
+            }
+
+            // Without `ON` the names are roles.
+            if l.peek()?.kind != TokenKind::Keyword(KeywordKind::On) {
+                let mut roles = Vec::new();
+                for (item, columns, pos) in items {
+                    match item {
+                        Err(role) if columns.is_empty() => roles.push(role),
+                        _ => return Err(ParseError::new("Expected role name".to_string(), pos)),
+                    }
+                }
+                return Ok(GrantKind::Roles(roles));
+            }
+
+            let mut privileges = Vec::new();
+            for (item, columns, pos) in items {
+                match item {
+                    Ok(privilege) => privileges.push(PrivilegeItem { privilege, columns }),
+                    Err(name) => return Err(ParseError::new(format!("Unknown privilege: {name}"), pos)),
+                }
+            }
+            Some(privileges)
+        };
+
+        if l.peek()?.kind != TokenKind::Keyword(KeywordKind::On) {
+            return Err(ParseError::new("Expected ON after ALL PRIVILEGES".to_string(), t.pos));
+        }
+        l.bump();
+
+        let object = if l.eat(TokenKind::Keyword(KeywordKind::Schema)) {
+            GrantObject::Schemas(self.parse_identifier_list()?)
+        } else {
+            l.eat(TokenKind::Keyword(KeywordKind::Table));
+            let mut tables = vec![self.parse_dataset_reference()?];
+            while l.eat(TokenKind::Punc(PuncKind::Comma)) {
+                tables.push(self.parse_dataset_reference()?);
+            }
+            GrantObject::Tables(tables)
+        };
+
+        Ok(GrantKind::Privileges { privileges, object })
+    }
+
+    fn parse_grantees(&self) -> Result<Vec<Grantee<'a>>, ParseError> {
+        Ok(self
+            .parse_identifier_list()?
+            .into_iter()
+            .map(|name| match name.eq_ignore_ascii_case("public") {
+                true => Grantee::Public,
+                false => Grantee::Role(name),
+            })
+            .collect())
+    }
+
+    fn parse_identifier_list(&self) -> Result<Vec<&'a str>, ParseError> {
+        let l = self.lexer.borrow();
+
+        let mut names = vec![self.parse_identifier()?];
+        while l.eat(TokenKind::Punc(PuncKind::Comma)) {
+            names.push(self.parse_identifier()?);
+        }
+        Ok(names)
+    }
+
+    fn parse_column_def(&self) -> Result<ColumnDef<'a>, ParseError> {
+        let l = self.lexer.borrow();
+
+        let name = self.parse_identifier()?;
+        let data_type = self.parse_data_type()?;
+
+        let mut constraints = Vec::new();
+        loop {
+            let t = l.peek()?;
+            let constraint = match t.kind {
+                TokenKind::Keyword(KeywordKind::Null) => {
+                    l.bump();
+                    ColumnConstraintKind::Null
+                }
+                TokenKind::Keyword(KeywordKind::Not) => {
+                    l.bump();
+                    l.expect(TokenKind::Keyword(KeywordKind::Null))?;
+                    ColumnConstraintKind::NotNull
+                }
+                TokenKind::Keyword(KeywordKind::Default) => {
+                    l.bump();
+                    ColumnConstraintKind::Default(self.parse_unary_expr()?)
+                }
+                TokenKind::Keyword(KeywordKind::Primary) => {
+                    l.bump();
+                    l.expect(TokenKind::Keyword(KeywordKind::Key))?;
+                    ColumnConstraintKind::PrimaryKey
+                }
+                TokenKind::Keyword(KeywordKind::Unique) => {
+                    l.bump();
+                    l.eat(TokenKind::Keyword(KeywordKind::Key));
+                    ColumnConstraintKind::Unique
+                }
+                _ => break,
+            };
+            constraints.push(constraint);
+        }
+
+        Ok(ColumnDef {
+            name,
+            data_type,
+            constraints,
+        })
+    }
+
+    /// Parses a table level constraint, `None` when the next element is a column definition.
+    fn parse_table_constraint(&self) -> Result<Option<TableConstraintKind<'a>>, ParseError> {
+        let l = self.lexer.borrow();
+
+        let mut name = None;
+        if l.eat(TokenKind::Keyword(KeywordKind::Constraint)) {
+            name = Some(self.parse_identifier()?);
+        }
+
+        let t = l.peek()?;
+        let constraint = match t.kind {
+            TokenKind::Keyword(KeywordKind::Primary) => {
+                l.bump();
+                l.expect(TokenKind::Keyword(KeywordKind::Key))?;
+                TableConstraintKind::PrimaryKey {
+                    name,
+                    columns: self.parse_column_list()?,
+                }
+            }
+            TokenKind::Keyword(KeywordKind::Unique) => {
+                l.bump();
+                if !l.eat(TokenKind::Keyword(KeywordKind::Key)) {
+                    l.eat(TokenKind::Keyword(KeywordKind::Index));
+                }
+                if name.is_none() && l.peek()?.kind != TokenKind::Punc(PuncKind::LParen) {
+                    name = Some(self.parse_identifier()?);
+                }
+                TableConstraintKind::Unique {
+                    name,
+                    columns: self.parse_column_list()?,
+                }
+            }
+            // `KEY name (columns)` declares an index, `key INTEGER` is a column named key.
+            TokenKind::Keyword(KeywordKind::Key | KeywordKind::Index)
+                if !matches!(l.peek_second()?.kind, TokenKind::Data(_)) =>
+            {
+                l.bump();
+                if l.peek()?.kind != TokenKind::Punc(PuncKind::LParen) {
+                    name = Some(self.parse_identifier()?);
+                }
+                TableConstraintKind::Index {
+                    name,
+                    columns: self.parse_column_list()?,
+                }
+            }
+            _ if name.is_some() => {
+                return Err(ParseError::new(format!("Unexpected token: {0}", t.kind), t.pos));
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(constraint))
+    }
+
+    fn parse_column_list(&self) -> Result<Vec<&'a str>, ParseError> {
+        let l = self.lexer.borrow();
+
+        l.expect(TokenKind::Punc(PuncKind::LParen))?;
+        let mut columns = vec![self.parse_identifier()?];
+        while l.eat(TokenKind::Punc(PuncKind::Comma)) {
+            columns.push(self.parse_identifier()?);
+        }
+        l.expect(TokenKind::Punc(PuncKind::RParen))?;
+
+        Ok(columns)
+    }
+
+    fn parse_data_type(&self) -> Result<DataKind, ParseError> {
+        let l = self.lexer.borrow();
+
+        let t = l.next()?;
+        let data = match t.kind {
+            TokenKind::Data(data) => data,
+            _ => return Err(ParseError::new(format!("Expected data type, found: {0}", t.kind), t.pos)),
+        };
+
+        let mut params = Vec::new();
+        if l.eat(TokenKind::Punc(PuncKind::LParen)) {
+            loop {
+                let t = l.next()?;
+                match t.kind {
+                    TokenKind::Literal(LiteralKind::Numeric(n)) if n.fract() == 0.0 && n >= 0.0 => {
+                        params.push((n as u64, t.pos))
+                    }
+                    _ => return Err(ParseError::new(format!("Invalid type parameter: {0}", t.kind), t.pos)),
+                }
+                if !l.eat(TokenKind::Punc(PuncKind::Comma)) {
+                    break;
+                }
+            }
+            l.expect(TokenKind::Punc(PuncKind::RParen))?;
+        }
+
+        data_with_params(data, &params, t.pos)
+    }
+
+    fn parse_dataset_reference(&self) -> Result<DatasetReference<'a>, ParseError> {
+        let l = self.lexer.borrow();
+
+        let first = self.parse_identifier()?;
+        if l.eat(TokenKind::Punc(PuncKind::Period)) {
+            return Ok(DatasetReference {
+                schema: Some(first),
+                dataset: Some(self.parse_identifier()?),
+            });
+        }
+
+        Ok(DatasetReference::new(first))
+    }
+
+    fn parse_identifier(&self) -> Result<&'a str, ParseError> {
+        let l = self.lexer.borrow();
+
+        let t = l.next()?;
+        match self.identifier_of(&t) {
+            Some(id) => Ok(id),
+            None => Err(ParseError::new(format!("Expected identifier, found: {0}", t.kind), t.pos)),
+        }
+    }
+
+    /// Identifier named by the token. Besides plain identifiers this accepts non-reserved keywords and data type
+    /// names, which are only meaningful in specific positions.
+    fn identifier_of(&self, t: &Token<'a>) -> Option<&'a str> {
+        match t.kind {
+            TokenKind::Identifier(id) => Some(id),
+            TokenKind::Keyword(ref kw) if !kw.is_reserved() => Some(self.lexer.borrow().word_at(t.pos)),
+            TokenKind::Data(_) => Some(self.lexer.borrow().word_at(t.pos)),
+            _ => None,
+        }
+    }
+
+    fn check_clause(&self, clause: Clause, pos: usize) -> Result<(), ParseError> {
+        if self.dialect.supports_clause(clause) {
+            return Ok(());
+        }
+
+        Err(ParseError::new(
+            format!("{clause} is not supported by the {0} dialect", self.dialect.name()),
+            pos,
+        ))
+    }
+
+    fn check_cancel(&self) -> Result<(), ParseError> {
+        match self.checkpoint.map(Checkpoint::check) {
+            Some(Err(_)) => Err(ParseError::new("canceled".to_string(), self.lexer.borrow().position())),
+            _ => Ok(()),
+        }
     }
 
     fn parse_eol(&self) -> Result<(), ParseError> {
@@ -208,10 +1111,81 @@ impl<'a> Parser<'a> {
     }
 }
 
+/// Applies the parenthesized parameters of a data type, e.g. the length of `VARCHAR(255)`.
+fn data_with_params(data: DataKind, params: &[(u64, usize)], pos: usize) -> Result<DataKind, ParseError> {
+    fn param<T: TryFrom<u64>>(param: Option<&(u64, usize)>) -> Result<Option<T>, ParseError> {
+        match param {
+            Some(&(value, pos)) => match T::try_from(value) {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(ParseError::new(format!("Type parameter out of range: {value}"), pos)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    let max = match data {
+        DataKind::TinyBlob | DataKind::TinyText | DataKind::Bool => 0,
+        DataKind::Float(..) | DataKind::Double(..) | DataKind::Decimal(..) => 2,
+        _ => 1,
+    };
+    if params.len() > max {
+        return Err(ParseError::new(format!("{data} takes at most {max} type parameter(s)"), pos));
+    }
+
+    let first = params.first();
+    let second = params.get(1);
+    Ok(match data {
+        DataKind::Char(_) => DataKind::Char(param(first)?),
+        DataKind::VarChar(_) => DataKind::VarChar(param(first)?),
+        DataKind::Binary(_) => DataKind::Binary(param(first)?),
+        DataKind::VarBinary(_) => DataKind::VarBinary(param(first)?),
+        DataKind::Text(_) => DataKind::Text(param(first)?),
+        DataKind::Blob(_) => DataKind::Blob(param(first)?),
+        DataKind::MediumText(_) => DataKind::MediumText(param(first)?),
+        DataKind::MediumBlob(_) => DataKind::MediumBlob(param(first)?),
+        DataKind::LongText(_) => DataKind::LongText(param(first)?),
+        DataKind::LongBlob(_) => DataKind::LongBlob(param(first)?),
+        DataKind::Bit(_) => DataKind::Bit(param(first)?),
+        DataKind::TinyInt(_) => DataKind::TinyInt(param(first)?),
+        DataKind::SmallInt(_) => DataKind::SmallInt(param(first)?),
+        DataKind::MediumInt(_) => DataKind::MediumInt(param(first)?),
+        DataKind::Integer(_) => DataKind::Integer(param(first)?),
+        DataKind::BigInt(_) => DataKind::BigInt(param(first)?),
+        DataKind::Float(..) => DataKind::Float(param(first)?, param(second)?),
+        DataKind::Double(..) => DataKind::Double(param(first)?, param(second)?),
+        DataKind::Decimal(..) => DataKind::Decimal(param(first)?, param(second)?),
+        DataKind::TinyBlob | DataKind::TinyText | DataKind::Bool => data,
+    })
+}
+
+fn binary_operator(kind: &TokenKind) -> Option<BinaryOperator> {
+    match kind {
+        Keyword(KeywordKind::Or) => Some(BinaryOperator::Or),
+        Keyword(KeywordKind::And) => Some(BinaryOperator::And),
+        Keyword(KeywordKind::Like) => Some(BinaryOperator::Like),
+        TokenKind::Punc(punc) => match punc {
+            PuncKind::Equal => Some(BinaryOperator::Equal),
+            PuncKind::NotEqual => Some(BinaryOperator::NotEqual),
+            PuncKind::LessThan => Some(BinaryOperator::LessThan),
+            PuncKind::LessThanEq => Some(BinaryOperator::LessThanEq),
+            PuncKind::GreaterThan => Some(BinaryOperator::GreaterThan),
+            PuncKind::GreaterThanEq => Some(BinaryOperator::GreaterThanEq),
+            PuncKind::Add => Some(BinaryOperator::Add),
+            PuncKind::Subtract => Some(BinaryOperator::Subtract),
+            PuncKind::Star => Some(BinaryOperator::Multiply),
+            PuncKind::Divide => Some(BinaryOperator::Divide),
+            PuncKind::Modulo => Some(BinaryOperator::Modulo),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::parser::dialect::{AnsiDialect, MsSqlDialect, MySqlDialect};
 
     #[test]
     fn test_commit() {
@@ -233,6 +1207,37 @@ mod tests {
         assert_eq!(ast.stmts[0], StatementKind::Rollback);
     }
 
+    #[test]
+    fn test_begin() {
+        let mut p = Parser::new(b"BEGIN; begin work; BEGIN TRANSACTION; COMMIT TRANSACTION");
+        let ast = p.parse().unwrap();
+
+        assert_eq!(
+            ast.stmts,
+            vec![
+                StatementKind::Begin(None),
+                StatementKind::Begin(None),
+                StatementKind::Begin(None),
+                StatementKind::Commit
+            ]
+        );
+
+        let mut p = Parser::new(
+            b"BEGIN ISOLATION LEVEL SERIALIZABLE; begin transaction isolation level repeatable read; \
+              BEGIN ISOLATION LEVEL READ UNCOMMITTED",
+        );
+        assert_eq!(
+            p.parse().unwrap().stmts,
+            vec![
+                StatementKind::Begin(Some(IsolationLevel::Serializable)),
+                StatementKind::Begin(Some(IsolationLevel::RepeatableRead)),
+                StatementKind::Begin(Some(IsolationLevel::ReadCommitted)),
+            ]
+        );
+        assert!(Parser::new(b"BEGIN ISOLATION LEVEL SNAPSHOT").parse().is_err());
+        assert!(Parser::new(b"BEGIN ISOLATION SERIALIZABLE").parse().is_err());
+    }
+
     #[test]
     fn test_select() {
         let mut p = Parser::new(b"SELECT * from cats");
@@ -245,4 +1250,571 @@ mod tests {
 
         assert_eq!(ast.stmts[0], StatementKind::Select(select));
     }
+
+    #[test]
+    fn test_select_list() {
+        let mut p = Parser::new(b"SELECT name, cats.weight from cats;");
+        let ast = p.parse().unwrap();
+
+        let select = SelectStmt::new(
+            SelectClause {
+                selected: vec![
+                    SelectItemKind::Identifier(ObjectReference::new("name")),
+                    SelectItemKind::Identifier(ObjectReference {
+                        dataset: Some(DatasetReference::new("cats")),
+                        obj: Some("weight"),
+                    }),
+                ],
+            },
+            FromClause::table("cats"),
+        );
+
+        assert_eq!(ast.stmts, vec![StatementKind::Select(select)]);
+    }
+
+    #[test]
+    fn test_join_rejected() {
+        for sql in [&b"SELECT a FROM JOIN"[..], b"SELECT a FROM t, JOIN u"] {
+            let err = Parser::new(sql).parse().unwrap_err();
+            assert_eq!(err.message, "JOIN is not supported yet");
+        }
+    }
+
+    #[test]
+    fn test_expression_depth() {
+        let nested = |open: &str, depth: usize| {
+            let close = if open == "(" { ")".repeat(depth) } else { String::new() };
+            format!("SELECT {0}1{close} FROM t", open.repeat(depth))
+        };
+        assert!(Parser::new(nested("(", MAX_EXPR_DEPTH - 1).as_bytes()).parse().is_ok());
+
+        // Deeper nesting fails cleanly instead of overflowing the stack of a worker thread.
+        let worker = std::thread::Builder::new().stack_size(2 << 20).spawn(move || {
+            for open in ["(", "NOT ", "- "] {
+                let sql = nested(open, 100_000);
+                let err = Parser::new(sql.as_bytes()).parse().unwrap_err();
+                assert_eq!(err.kind, ParseErrorKind::TooComplex, "{open}");
+            }
+        });
+        worker.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_select_where() {
+        let mut p = Parser::new(b"SELECT * FROM cats WHERE age > 2 + 1 AND NOT name = 'tom' OR weight <= 4.5");
+        let ast = p.parse().unwrap();
+
+        let ident = |name| Box::new(ExprKind::Identifier(ObjectReference::new(name)));
+        let num = |n| Box::new(ExprKind::Literal(LiteralKind::Numeric(n)));
+
+        let age = ExprKind::Binary(
+            ident("age"),
+            BinaryOperator::GreaterThan,
+            Box::new(ExprKind::Binary(num(2.0), BinaryOperator::Add, num(1.0))),
+        );
+        let name = ExprKind::Unary(
+            UnaryOperator::Not,
+            Box::new(ExprKind::Binary(
+                ident("name"),
+                BinaryOperator::Equal,
+                Box::new(ExprKind::Literal(LiteralKind::String("tom"))),
+            )),
+        );
+        let weight = ExprKind::Binary(ident("weight"), BinaryOperator::LessThanEq, num(4.5));
+
+        let mut select = SelectStmt::new(SelectClause::all(), FromClause::table("cats"));
+        select.where_clause = Some(WhereClause {
+            expr: ExprKind::Binary(
+                Box::new(ExprKind::Binary(Box::new(age), BinaryOperator::And, Box::new(name))),
+                BinaryOperator::Or,
+                Box::new(weight),
+            ),
+        });
+
+        assert_eq!(ast.stmts, vec![StatementKind::Select(select)]);
+    }
+
+    #[test]
+    fn test_limit_and_top() {
+        let mut expected = SelectStmt::new(SelectClause::all(), FromClause::table("cats"));
+        expected.limit_clause = Some(LimitClause {
+            count: ExprKind::Literal(LiteralKind::Numeric(5.0)),
+        });
+        let expected = vec![StatementKind::Select(expected)];
+
+        let mut p = Parser::new(b"SELECT * FROM cats LIMIT 5");
+        assert_eq!(p.parse().unwrap().stmts, expected);
+
+        let mut p = Parser::with_dialect(b"SELECT * FROM cats LIMIT 5", &MySqlDialect);
+        assert_eq!(p.parse().unwrap().stmts, expected);
+
+        let mut p = Parser::with_dialect(b"SELECT TOP 5 * FROM [cats]", &MsSqlDialect);
+        assert_eq!(p.parse().unwrap().stmts, expected);
+
+        let mut p = Parser::new(b"SELECT TOP 5 * FROM cats LIMIT 5");
+        assert_eq!(p.parse().unwrap_err().message, "LIMIT cannot be combined with TOP");
+    }
+
+    #[test]
+    fn test_locking_clause() {
+        let mut p = Parser::new(b"SELECT * FROM cats LIMIT 5 FOR UPDATE; select * from cats for share nowait");
+        let stmts = p.parse().unwrap().stmts;
+        let StatementKind::Select(select) = &stmts[0] else {
+            panic!("not a select");
+        };
+        assert!(select.limit_clause.is_some());
+        assert_eq!(
+            select.locking_clause,
+            Some(LockingClause {
+                strength: LockStrength::Update,
+                nowait: false
+            })
+        );
+        let mut expected = SelectStmt::new(SelectClause::all(), FromClause::table("cats"));
+        expected.locking_clause = Some(LockingClause {
+            strength: LockStrength::Share,
+            nowait: true,
+        });
+        assert_eq!(stmts[1], StatementKind::Select(expected));
+
+        let mut p = Parser::new(b"SELECT * FROM cats FOR KEY SHARE");
+        assert_eq!(p.parse().unwrap_err().message, "Expected SHARE, found: KEY");
+    }
+
+    #[test]
+    fn test_ansi_rejects_vendor_clauses() {
+        let mut p = Parser::with_dialect(b"SELECT * FROM cats LIMIT 5", &AnsiDialect);
+        let err = p.parse().unwrap_err();
+        assert_eq!(err.message, "LIMIT is not supported by the ANSI dialect");
+        assert_eq!(err.pos, 19);
+
+        let mut p = Parser::with_dialect(b"SELECT * FROM cats LIMIT 5", &MsSqlDialect);
+        assert!(p.parse().is_err());
+
+        // Vendor keywords are plain identifiers in ANSI.
+        let mut p = Parser::with_dialect(b"SELECT top, rownum FROM \"select\"", &AnsiDialect);
+        let select = SelectStmt::new(
+            SelectClause {
+                selected: vec![
+                    SelectItemKind::Identifier(ObjectReference::new("top")),
+                    SelectItemKind::Identifier(ObjectReference::new("rownum")),
+                ],
+            },
+            FromClause::table("select"),
+        );
+        assert_eq!(p.parse().unwrap().stmts, vec![StatementKind::Select(select)]);
+    }
+
+    #[test]
+    fn test_mysql_double_quoted_strings() {
+        let mut p = Parser::with_dialect(b"INSERT INTO cats VALUES (\"tom\")", &MySqlDialect);
+        let StatementKind::Insert(insert) = &p.parse().unwrap().stmts[0] else {
+            panic!("expected an insert");
+        };
+        assert_eq!(insert.values, vec![vec![ExprKind::Literal(LiteralKind::String("tom"))]]);
+    }
+
+    #[test]
+    fn test_rownum() {
+        let mut p = Parser::new(b"SELECT * FROM cats WHERE ROWNUM <= 10");
+        assert!(p.parse().is_ok());
+
+        let mut p = Parser::with_dialect(b"SELECT * FROM cats WHERE ROWNUM <= 10", &MySqlDialect);
+        assert!(p.parse().is_ok());
+    }
+
+    /// Schemas and queries from real applications that use keywords as column or table names.
+    const NON_RESERVED_CORPUS: [&str; 8] = [
+        // WordPress options table
+        "CREATE TABLE wp_options (
+            option_id BIGINT(20) NOT NULL,
+            option_name VARCHAR(191) NOT NULL DEFAULT '',
+            option_value LONGTEXT NOT NULL,
+            autoload VARCHAR(20) NOT NULL DEFAULT 'yes',
+            PRIMARY KEY (option_id),
+            UNIQUE KEY option_name (option_name)
+        )",
+        // Key/value settings store
+        "CREATE TABLE IF NOT EXISTS settings (
+            key VARCHAR(64) PRIMARY KEY,
+            value TEXT,
+            default BOOL DEFAULT 0
+        );",
+        // Ordered menu entries laid out on a grid
+        "CREATE TABLE menu_items (
+            id INTEGER NOT NULL,
+            index INTEGER NOT NULL,
+            column SMALLINT,
+            top INTEGER,
+            text TEXT NULL,
+            KEY idx_index (index),
+            INDEX (column)
+        )",
+        // Audit log keyed by transaction and view
+        "CREATE TABLE audit.log (transaction BIGINT, view VARCHAR(32), procedure TEXT, database VARCHAR(64))",
+        "SELECT key, value FROM settings WHERE key = 'theme'",
+        "SELECT index, column, top FROM menu_items WHERE top > 10 AND column <> 2",
+        "SELECT top, key FROM settings",
+        "SELECT log.transaction, text FROM log WHERE view = 'home'",
+    ];
+
+    #[test]
+    fn test_non_reserved_corpus() {
+        for sql in NON_RESERVED_CORPUS {
+            let mut p = Parser::new(sql.as_bytes());
+            if let Err(err) = p.parse() {
+                panic!("failed to parse {sql}: {0} at {1}", err.message, err.pos);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reserved_keywords_rejected() {
+        let mut p = Parser::new(b"SELECT from FROM settings");
+        assert!(p.parse().is_err());
+
+        let mut p = Parser::new(b"CREATE TABLE t (select INTEGER)");
+        assert_eq!(p.parse().unwrap_err().message, "Expected identifier, found: Keyword(Select)");
+    }
+
+    #[test]
+    fn test_create_table() {
+        let mut p = Parser::new(NON_RESERVED_CORPUS[2].as_bytes());
+        let ast = p.parse().unwrap();
+
+        let column = |name, data_type, constraints| ColumnDef {
+            name,
+            data_type,
+            constraints,
+        };
+        let create = CreateTableStmt {
+            table: DatasetReference::new("menu_items"),
+            if_not_exists: false,
+            columns: vec![
+                column("id", DataKind::Integer(None), vec![ColumnConstraintKind::NotNull]),
+                column("index", DataKind::Integer(None), vec![ColumnConstraintKind::NotNull]),
+                column("column", DataKind::SmallInt(None), vec![]),
+                column("top", DataKind::Integer(None), vec![]),
+                column("text", DataKind::Text(None), vec![ColumnConstraintKind::Null]),
+            ],
+            constraints: vec![
+                TableConstraintKind::Index {
+                    name: Some("idx_index"),
+                    columns: vec!["index"],
+                },
+                TableConstraintKind::Index {
+                    name: None,
+                    columns: vec!["column"],
+                },
+            ],
+        };
+
+        assert_eq!(ast.stmts, vec![StatementKind::CreateTable(create)]);
+    }
+
+    #[test]
+    fn test_data_type_params() {
+        let mut p = Parser::new(b"CREATE TABLE t (a DECIMAL(10, 2), b VARCHAR(255), c CHAR)");
+        let ast = p.parse().unwrap();
+
+        let StatementKind::CreateTable(create) = &ast.stmts[0] else {
+            panic!("expected create table");
+        };
+        let types: Vec<&DataKind> = create.columns.iter().map(|c| &c.data_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                &DataKind::Decimal(Some(10), Some(2)),
+                &DataKind::VarChar(Some(255)),
+                &DataKind::Char(None)
+            ]
+        );
+
+        let mut p = Parser::new(b"CREATE TABLE t (a CHAR(300))");
+        assert_eq!(p.parse().unwrap_err().message, "Type parameter out of range: 300");
+
+        let mut p = Parser::new(b"CREATE TABLE t (a BOOL(1))");
+        assert_eq!(p.parse().unwrap_err().message, "BOOL takes at most 0 type parameter(s)");
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut p = Parser::new(b"INSERT INTO cats (name, age) VALUES ('tom', 3), ('kit', NULL);");
+        let ast = p.parse().unwrap();
+
+        let insert = InsertStmt {
+            table: DatasetReference::new("cats"),
+            columns: vec!["name", "age"],
+            values: vec![
+                vec![
+                    ExprKind::Literal(LiteralKind::String("tom")),
+                    ExprKind::Literal(LiteralKind::Numeric(3.0)),
+                ],
+                vec![
+                    ExprKind::Literal(LiteralKind::String("kit")),
+                    ExprKind::Literal(LiteralKind::Null),
+                ],
+            ],
+        };
+        assert_eq!(ast.stmts, vec![StatementKind::Insert(insert)]);
+
+        let mut p = Parser::new(b"INSERT INTO cats (name, age) VALUES ('tom')");
+        assert_eq!(p.parse().unwrap_err().message, "Expected 2 values, found 1");
+    }
+
+    #[test]
+    fn test_placeholders() {
+        let mut p = Parser::new(b"UPDATE cats SET age = $2 WHERE name = $1");
+        let ast = p.parse().unwrap();
+
+        let update = UpdateStmt {
+            table: DatasetReference::new("cats"),
+            assignments: vec![Assignment {
+                column: "age",
+                value: ExprKind::Placeholder(2),
+            }],
+            where_clause: Some(WhereClause {
+                expr: ExprKind::Binary(
+                    Box::new(ExprKind::Identifier(ObjectReference::new("name"))),
+                    BinaryOperator::Equal,
+                    Box::new(ExprKind::Placeholder(1)),
+                ),
+            }),
+        };
+        assert_eq!(ast.stmts, vec![StatementKind::Update(update)]);
+    }
+
+    #[test]
+    fn test_update_and_delete() {
+        let mut p = Parser::new(b"UPDATE cats SET age = 4, name = 'tom' WHERE age = 3; DELETE FROM cats");
+        let ast = p.parse().unwrap();
+
+        let age_is_3 = WhereClause {
+            expr: ExprKind::Binary(
+                Box::new(ExprKind::Identifier(ObjectReference::new("age"))),
+                BinaryOperator::Equal,
+                Box::new(ExprKind::Literal(LiteralKind::Numeric(3.0))),
+            ),
+        };
+        let update = UpdateStmt {
+            table: DatasetReference::new("cats"),
+            assignments: vec![
+                Assignment {
+                    column: "age",
+                    value: ExprKind::Literal(LiteralKind::Numeric(4.0)),
+                },
+                Assignment {
+                    column: "name",
+                    value: ExprKind::Literal(LiteralKind::String("tom")),
+                },
+            ],
+            where_clause: Some(age_is_3),
+        };
+        let delete = DeleteStmt {
+            table: DatasetReference::new("cats"),
+            where_clause: None,
+        };
+
+        assert_eq!(ast.stmts, vec![StatementKind::Update(update), StatementKind::Delete(delete)]);
+    }
+
+    #[test]
+    fn test_set() {
+        let mut p = Parser::new(b"SET statement_timeout = '5s'; set statement_timeout to default");
+        let ast = p.parse().unwrap();
+
+        assert_eq!(
+            ast.stmts,
+            vec![
+                StatementKind::Set(SetStmt {
+                    name: "statement_timeout",
+                    value: Some(ExprKind::Literal(LiteralKind::String("5s"))),
+                }),
+                StatementKind::Set(SetStmt {
+                    name: "statement_timeout",
+                    value: None,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_roles() {
+        let mut p = Parser::new(
+            b"CREATE USER alice WITH PASSWORD 'pw' NOSUPERUSER; ALTER ROLE alice NOLOGIN PASSWORD NULL; \
+              DROP ROLE IF EXISTS alice, bob",
+        );
+        let ast = p.parse().unwrap();
+
+        assert_eq!(
+            ast.stmts,
+            vec![
+                StatementKind::CreateRole(CreateRoleStmt {
+                    name: "alice",
+                    options: vec![
+                        RoleOption::Login(true),
+                        RoleOption::Password(Some("pw")),
+                        RoleOption::Superuser(false)
+                    ],
+                }),
+                StatementKind::AlterRole(AlterRoleStmt {
+                    name: "alice",
+                    options: vec![RoleOption::Login(false), RoleOption::Password(None)],
+                }),
+                StatementKind::DropRole(DropRoleStmt {
+                    names: vec!["alice", "bob"],
+                    if_exists: true,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_indexes() {
+        let mut p = Parser::new(
+            b"CREATE UNIQUE INDEX IF NOT EXISTS cats_name ON public.cats (name, age); \
+              CREATE INDEX cats_age ON cats USING hash (age); \
+              DROP INDEX IF EXISTS cats_name, cats_age",
+        );
+        let ast = p.parse().unwrap();
+
+        assert_eq!(
+            ast.stmts,
+            vec![
+                StatementKind::CreateIndex(CreateIndexStmt {
+                    name: "cats_name",
+                    table: DatasetReference {
+                        schema: Some("public"),
+                        dataset: Some("cats"),
+                    },
+                    method: IndexMethod::BTree,
+                    columns: vec!["name", "age"],
+                    unique: true,
+                    if_not_exists: true,
+                }),
+                StatementKind::CreateIndex(CreateIndexStmt {
+                    name: "cats_age",
+                    table: DatasetReference::new("cats"),
+                    method: IndexMethod::Hash,
+                    columns: vec!["age"],
+                    unique: false,
+                    if_not_exists: false,
+                }),
+                StatementKind::DropIndex(DropIndexStmt {
+                    names: vec!["cats_name", "cats_age"],
+                    if_exists: true,
+                }),
+            ]
+        );
+        assert!(Parser::new(b"CREATE UNIQUE TABLE cats (id INT)").parse().is_err());
+        assert!(Parser::new(b"CREATE INDEX cats_age ON cats ()").parse().is_err());
+        assert!(
+            Parser::new(b"CREATE INDEX cats_age ON cats USING gist (age)")
+                .parse()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_vacuum() {
+        let mut p = Parser::new(b"VACUUM; vacuum public.cats; VACUUM vacuum");
+        let ast = p.parse().unwrap();
+
+        assert_eq!(
+            ast.stmts,
+            vec![
+                StatementKind::Vacuum(None),
+                StatementKind::Vacuum(Some(DatasetReference {
+                    schema: Some("public"),
+                    dataset: Some("cats"),
+                })),
+                StatementKind::Vacuum(Some(DatasetReference::new("vacuum"))),
+            ]
+        );
+        assert!(Parser::new(b"VACUUM cats, dogs").parse().is_err());
+    }
+
+    #[test]
+    fn test_grant() {
+        let mut p = Parser::new(
+            b"GRANT SELECT, UPDATE (name, age) ON TABLE cats, dogs TO alice, PUBLIC WITH GRANT OPTION; \
+              REVOKE GRANT OPTION FOR ALL PRIVILEGES ON SCHEMA public FROM alice; GRANT readers TO alice",
+        );
+        let ast = p.parse().unwrap();
+
+        assert_eq!(
+            ast.stmts,
+            vec![
+                StatementKind::Grant(GrantStmt {
+                    kind: GrantKind::Privileges {
+                        privileges: Some(vec![
+                            PrivilegeItem {
+                                privilege: PrivilegeKind::Select,
+                                columns: vec![],
+                            },
+                            PrivilegeItem {
+                                privilege: PrivilegeKind::Update,
+                                columns: vec!["name", "age"],
+                            },
+                        ]),
+                        object: GrantObject::Tables(vec![DatasetReference::new("cats"), DatasetReference::new("dogs")]),
+                    },
+                    grantees: vec![Grantee::Role("alice"), Grantee::Public],
+                    grant_option: true,
+                }),
+                StatementKind::Revoke(GrantStmt {
+                    kind: GrantKind::Privileges {
+                        privileges: None,
+                        object: GrantObject::Schemas(vec!["public"]),
+                    },
+                    grantees: vec![Grantee::Role("alice")],
+                    grant_option: true,
+                }),
+                StatementKind::Grant(GrantStmt {
+                    kind: GrantKind::Roles(vec!["readers"]),
+                    grantees: vec![Grantee::Role("alice")],
+                    grant_option: false,
+                }),
+            ]
+        );
+
+        for sql in [
+            "GRANT SELECT TO alice",
+            "GRANT ALL TO alice",
+            "GRANT frobnicate ON cats TO alice",
+        ] {
+            assert!(Parser::new(sql.as_bytes()).parse().is_err(), "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let checkpoint = Checkpoint::none();
+        let mut p = Parser::new(b"SELECT a FROM t").with_checkpoint(&checkpoint);
+        assert!(p.parse().is_ok());
+
+        let flag = crate::cancel::CancelFlag::new();
+        flag.cancel();
+        let checkpoint = Checkpoint::new(flag, None);
+        let mut p = Parser::new(b"SELECT a FROM t").with_checkpoint(&checkpoint);
+        assert_eq!(p.parse().unwrap_err().message, "canceled");
+    }
+
+    #[test]
+    fn test_statement_termination() {
+        let mut p = Parser::new(b";SELECT * FROM cats;; COMMIT; ROLLBACK WORK; INSERT INTO cats VALUES (1);");
+        assert_eq!(p.parse().unwrap().stmts.len(), 4);
+
+        let inputs: [&[u8]; 5] = [
+            b"SELECT * FROM cats SELECT * FROM dogs",
+            b"COMMIT ROLLBACK",
+            b"DELETE FROM cats COMMIT",
+            b"INSERT INTO cats VALUES (1) COMMIT",
+            b"CREATE TABLE cats (id INT) COMMIT",
+        ];
+        for input in inputs {
+            let mut p = Parser::new(input);
+            assert_eq!(p.parse().unwrap_err().message, "missing ';'");
+        }
+    }
 }
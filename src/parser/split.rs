@@ -0,0 +1,126 @@
+//! Statement splitting without a full parse.
+//!
+//! Finds the `;` separating statements while skipping over string literals, quoted identifiers and comments, so a
+//! batch can be executed, logged or reported on statement by statement even when some statements do not parse.
+
+use crate::parser::dialect::Dialect;
+use std::ops::Range;
+
+/// Byte ranges of the statements in `data`. Ranges exclude the terminating `;` and surrounding whitespace and
+/// comments, empty statements are skipped.
+pub fn split_statements(data: &[u8], dialect: &dyn Dialect) -> Vec<Range<usize>> {
+    let mut stmts = Vec::new();
+    // Range of significant bytes of the statement being scanned.
+    let mut current: Option<Range<usize>> = None;
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let start = pos;
+        match data[pos] {
+            b';' => {
+                stmts.extend(current.take());
+                pos += 1;
+                continue;
+            }
+            b if b.is_ascii_whitespace() => {
+                pos += 1;
+                continue;
+            }
+            b'-' if data.get(pos + 1) == Some(&b'-') => {
+                while pos < data.len() && data[pos] != b'\n' {
+                    pos += 1;
+                }
+                continue;
+            }
+            b'/' if data.get(pos + 1) == Some(&b'*') => {
+                pos = match data[pos + 2..].windows(2).position(|w| w == b"*/") {
+                    Some(len) => pos + 2 + len + 2,
+                    None => data.len(),
+                };
+                continue;
+            }
+            b'\'' => pos = skip_quoted(data, pos, b'\'', dialect.supports_backslash_escapes()),
+            open => match dialect.identifier_quote(open) {
+                Some(close) => pos = skip_quoted(data, pos, close, false),
+                None => pos += 1,
+            },
+        }
+
+        match &mut current {
+            Some(range) => range.end = pos,
+            None => current = Some(start..pos),
+        }
+    }
+
+    stmts.extend(current);
+    stmts
+}
+
+/// Position after the closing delimiter of the quoted text opened at `open`, or the end of the data when it is
+/// unterminated. A doubled delimiter is an escaped one.
+fn skip_quoted(data: &[u8], open: usize, close: u8, backslash_escapes: bool) -> usize {
+    let mut pos = open + 1;
+    while pos < data.len() {
+        match data[pos] {
+            b'\\' if backslash_escapes => pos += 2,
+            c if c == close && data.get(pos + 1) == Some(&close) => pos += 2,
+            c if c == close => return pos + 1,
+            _ => pos += 1,
+        }
+    }
+
+    data.len()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parser::dialect::{AnsiDialect, GenericDialect};
+
+    fn split<'a>(data: &'a [u8], dialect: &dyn Dialect) -> Vec<&'a str> {
+        split_statements(data, dialect)
+            .into_iter()
+            .map(|range| std::str::from_utf8(&data[range]).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_split() {
+        let data = b"SELECT * FROM cats; COMMIT;\n\n  ROLLBACK";
+        assert_eq!(split(data, &GenericDialect), vec!["SELECT * FROM cats", "COMMIT", "ROLLBACK"]);
+        assert_eq!(split_statements(data, &GenericDialect)[1], 20..26);
+    }
+
+    #[test]
+    fn test_skips_empty_and_comment_only() {
+        let data = b";; -- nothing here\n /* or; here */ ; SELECT 1 FROM t -- trailing; comment\n;";
+        assert_eq!(split(data, &GenericDialect), vec!["SELECT 1 FROM t"]);
+    }
+
+    #[test]
+    fn test_respects_quotes() {
+        let data = b"INSERT INTO t VALUES ('a;b', 'it''s;'); SELECT \"weird;name\" FROM t";
+        assert_eq!(
+            split(data, &GenericDialect),
+            vec!["INSERT INTO t VALUES ('a;b', 'it''s;')", "SELECT \"weird;name\" FROM t"]
+        );
+    }
+
+    #[test]
+    fn test_backslash_escapes_follow_dialect() {
+        let data = br"SELECT 'a\'; b' FROM t; SELECT 2 FROM t";
+        assert_eq!(split(data, &GenericDialect).len(), 2);
+        // Without backslash escapes the literal ends at the quote after the backslash.
+        assert_eq!(split(data, &AnsiDialect), vec![r"SELECT 'a\'", r"b' FROM t; SELECT 2 FROM t"]);
+    }
+
+    #[test]
+    fn test_unterminated() {
+        let data = b"SELECT 1 FROM t; SELECT 'open; SELECT 2";
+        assert_eq!(
+            split(data, &GenericDialect),
+            vec!["SELECT 1 FROM t", "SELECT 'open; SELECT 2"]
+        );
+    }
+}
@@ -18,6 +18,8 @@ pub enum TokenKind<'a> {
     Data(DataKind),
     Keyword(KeywordKind),
     Literal(LiteralKind<'a>),
+    /// `$n`
+    Placeholder(usize),
     Punc(PuncKind),
     Comment(CommentKind<'a>),
     LineTerminator(LineTerminatorKind),
@@ -57,10 +59,49 @@ pub enum DataKind {
     Decimal(Option<u8>, Option<u8>),
 }
 
+impl Display for DataKind {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        fn params(f: &mut Formatter, name: &str, params: &[Option<u64>]) -> Result {
+            write!(f, "{name}")?;
+            let params: Vec<String> = params.iter().flatten().map(|p| p.to_string()).collect();
+            if !params.is_empty() {
+                write!(f, "({0})", params.join(", "))?;
+            }
+            Ok(())
+        }
+
+        match *self {
+            DataKind::Char(n) => params(f, "CHAR", &[n.map(u64::from)]),
+            DataKind::VarChar(n) => params(f, "VARCHAR", &[n.map(u64::from)]),
+            DataKind::Binary(n) => params(f, "BINARY", &[n.map(u64::from)]),
+            DataKind::VarBinary(n) => params(f, "VARBINARY", &[n.map(u64::from)]),
+            DataKind::TinyBlob => write!(f, "TINYBLOB"),
+            DataKind::TinyText => write!(f, "TINYTEXT"),
+            DataKind::Text(n) => params(f, "TEXT", &[n.map(u64::from)]),
+            DataKind::Blob(n) => params(f, "BLOB", &[n.map(u64::from)]),
+            DataKind::MediumText(n) => params(f, "MEDIUMTEXT", &[n.map(u64::from)]),
+            DataKind::MediumBlob(n) => params(f, "MEDIUMBLOB", &[n.map(u64::from)]),
+            DataKind::LongText(n) => params(f, "LONGTEXT", &[n]),
+            DataKind::LongBlob(n) => params(f, "LONGBLOB", &[n]),
+            DataKind::Bit(n) => params(f, "BIT", &[n.map(u64::from)]),
+            DataKind::TinyInt(n) => params(f, "TINYINT", &[n.map(u64::from)]),
+            DataKind::Bool => write!(f, "BOOL"),
+            DataKind::SmallInt(n) => params(f, "SMALLINT", &[n.map(u64::from)]),
+            DataKind::MediumInt(n) => params(f, "MEDIUMINT", &[n.map(u64::from)]),
+            DataKind::Integer(n) => params(f, "INTEGER", &[n.map(u64::from)]),
+            DataKind::BigInt(n) => params(f, "BIGINT", &[n.map(u64::from)]),
+            DataKind::Float(p, s) => params(f, "FLOAT", &[p.map(u64::from), s.map(u64::from)]),
+            DataKind::Double(p, s) => params(f, "DOUBLE", &[p.map(u64::from), s.map(u64::from)]),
+            DataKind::Decimal(p, s) => params(f, "DECIMAL", &[p.map(u64::from), s.map(u64::from)]),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum LiteralKind<'a> {
     String(&'a str),
     Numeric(f64),
+    Null,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -90,9 +131,11 @@ pub enum KeywordKind {
     Else,
     Exec,
     Exists,
+    For,
     Foreign,
     From,
     Full,
+    Grant,
     Group,
     Having,
     If,
@@ -107,34 +150,88 @@ pub enum KeywordKind {
     Left,
     Like,
     Limit,
+    Login,
+    NoLogin,
+    NoSuperuser,
     Not,
     Null,
+    On,
+    Option,
     Or,
     Order,
     Outer,
+    Password,
     Primary,
+    Privileges,
     Procedure,
+    Revoke,
     Right,
+    Role,
     Rollback,
     Rownum,
+    Schema,
     Select,
     Set,
     Some,
+    Superuser,
     Table,
     Then,
+    To,
     Top,
     Transaction,
     Truncate,
     Union,
     Unique,
     Update,
+    Usage,
+    User,
+    Using,
+    Vacuum,
     Values,
     View,
     When,
     Where,
+    With,
     Work,
 }
 
+impl KeywordKind {
+    /// Reserved keywords can never be used as unquoted identifiers. Non-reserved ones are keywords only in the
+    /// positions that give them a meaning and identifiers everywhere else, e.g. a column named `key`.
+    pub fn is_reserved(&self) -> bool {
+        !matches!(
+            self,
+            KeywordKind::Add
+                | KeywordKind::Backup
+                | KeywordKind::Column
+                | KeywordKind::Database
+                | KeywordKind::Default
+                | KeywordKind::Exec
+                | KeywordKind::Index
+                | KeywordKind::Key
+                | KeywordKind::Login
+                | KeywordKind::NoLogin
+                | KeywordKind::NoSuperuser
+                | KeywordKind::Option
+                | KeywordKind::Password
+                | KeywordKind::Privileges
+                | KeywordKind::Procedure
+                | KeywordKind::Role
+                | KeywordKind::Rownum
+                | KeywordKind::Schema
+                | KeywordKind::Superuser
+                | KeywordKind::Top
+                | KeywordKind::Transaction
+                | KeywordKind::Truncate
+                | KeywordKind::Usage
+                | KeywordKind::User
+                | KeywordKind::Vacuum
+                | KeywordKind::View
+                | KeywordKind::Work
+        )
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum PuncKind {
     /// (
@@ -217,7 +314,7 @@ pub enum LineTerminatorKind {
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum WhitespaceKind {
-    ///  
+    /// ' '
     Space,
     /// \t
     HorizontalTab,
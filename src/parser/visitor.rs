@@ -0,0 +1,1037 @@
+//! Traversal of the syntax tree.
+//!
+//! [`Visitor`] walks a tree by reference, [`VisitorMut`] walks it by mutable reference so nodes can be edited in
+//! place, and [`Fold`] consumes a tree and rebuilds it. Every method has a default implementation that recurses into
+//! the node's children through the matching `walk_*` / `fold_*` function, so a pass only overrides the nodes it cares
+//! about and calls back into the walker when it wants to keep descending.
+
+use crate::parser::ast::*;
+use crate::parser::token::LiteralKind;
+
+pub trait Visitor<'a> {
+    fn visit_ast(&mut self, ast: &AST<'a>) {
+        walk_ast(self, ast);
+    }
+
+    fn visit_stmt(&mut self, stmt: &StatementKind<'a>) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_block_stmt(&mut self, block: &BlockStmt<'a>) {
+        walk_block_stmt(self, block);
+    }
+
+    fn visit_select_stmt(&mut self, select: &SelectStmt<'a>) {
+        walk_select_stmt(self, select);
+    }
+
+    fn visit_update_stmt(&mut self, update: &UpdateStmt<'a>) {
+        walk_update_stmt(self, update);
+    }
+
+    fn visit_insert_stmt(&mut self, insert: &InsertStmt<'a>) {
+        walk_insert_stmt(self, insert);
+    }
+
+    fn visit_delete_stmt(&mut self, delete: &DeleteStmt<'a>) {
+        walk_delete_stmt(self, delete);
+    }
+
+    fn visit_create_table_stmt(&mut self, create: &CreateTableStmt<'a>) {
+        walk_create_table_stmt(self, create);
+    }
+
+    fn visit_set_stmt(&mut self, set: &SetStmt<'a>) {
+        walk_set_stmt(self, set);
+    }
+
+    fn visit_grant_stmt(&mut self, grant: &GrantStmt<'a>) {
+        walk_grant_stmt(self, grant);
+    }
+
+    fn visit_column_def(&mut self, column: &ColumnDef<'a>) {
+        walk_column_def(self, column);
+    }
+
+    fn visit_table_constraint(&mut self, constraint: &TableConstraintKind<'a>) {
+        walk_table_constraint(self, constraint);
+    }
+
+    fn visit_select_clause(&mut self, clause: &SelectClause<'a>) {
+        walk_select_clause(self, clause);
+    }
+
+    fn visit_select_item(&mut self, item: &SelectItemKind<'a>) {
+        walk_select_item(self, item);
+    }
+
+    fn visit_from_clause(&mut self, clause: &FromClause<'a>) {
+        walk_from_clause(self, clause);
+    }
+
+    fn visit_from_item(&mut self, item: &FromItemKind<'a>) {
+        walk_from_item(self, item);
+    }
+
+    fn visit_join_clause(&mut self, _clause: &JoinClause) {}
+
+    fn visit_where_clause(&mut self, clause: &WhereClause<'a>) {
+        walk_where_clause(self, clause);
+    }
+
+    fn visit_group_by_clause(&mut self, _clause: &GroupByClause) {}
+
+    fn visit_having_clause(&mut self, _clause: &HavingClause) {}
+
+    fn visit_order_by_clause(&mut self, _clause: &OrderByClause) {}
+
+    fn visit_limit_clause(&mut self, clause: &LimitClause<'a>) {
+        walk_limit_clause(self, clause);
+    }
+
+    fn visit_expr(&mut self, expr: &ExprKind<'a>) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_literal(&mut self, _literal: &LiteralKind<'a>) {}
+
+    fn visit_dataset_reference(&mut self, dataset: &DatasetReference<'a>) {
+        walk_dataset_reference(self, dataset);
+    }
+
+    fn visit_object_reference(&mut self, obj: &ObjectReference<'a>) {
+        walk_object_reference(self, obj);
+    }
+
+    fn visit_identifier(&mut self, _ident: &'a str) {}
+}
+
+pub fn walk_ast<'a, V: Visitor<'a> + ?Sized>(v: &mut V, ast: &AST<'a>) {
+    for stmt in &ast.stmts {
+        v.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<'a, V: Visitor<'a> + ?Sized>(v: &mut V, stmt: &StatementKind<'a>) {
+    match stmt {
+        StatementKind::Block(block) => v.visit_block_stmt(block),
+        StatementKind::Select(select) => v.visit_select_stmt(select),
+        StatementKind::Update(update) => v.visit_update_stmt(update),
+        StatementKind::Insert(insert) => v.visit_insert_stmt(insert),
+        StatementKind::Delete(delete) => v.visit_delete_stmt(delete),
+        StatementKind::CreateTable(create) => v.visit_create_table_stmt(create),
+        StatementKind::CreateIndex(create) => v.visit_dataset_reference(&create.table),
+        StatementKind::Vacuum(Some(table)) => v.visit_dataset_reference(table),
+        StatementKind::Set(set) => v.visit_set_stmt(set),
+        StatementKind::Grant(grant) | StatementKind::Revoke(grant) => v.visit_grant_stmt(grant),
+        StatementKind::CreateRole(_)
+        | StatementKind::AlterRole(_)
+        | StatementKind::DropRole(_)
+        | StatementKind::DropIndex(_)
+        | StatementKind::Begin(_)
+        | StatementKind::Commit
+        | StatementKind::Rollback
+        | StatementKind::Vacuum(None) => {}
+    }
+}
+
+pub fn walk_block_stmt<'a, V: Visitor<'a> + ?Sized>(v: &mut V, block: &BlockStmt<'a>) {
+    for stmt in &block.stmts {
+        v.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_select_stmt<'a, V: Visitor<'a> + ?Sized>(v: &mut V, select: &SelectStmt<'a>) {
+    v.visit_select_clause(&select.select_clause);
+    v.visit_from_clause(&select.from_clause);
+    if let Some(clause) = &select.where_clause {
+        v.visit_where_clause(clause);
+    }
+    if let Some(clause) = &select.group_by_clause {
+        v.visit_group_by_clause(clause);
+    }
+    if let Some(clause) = &select.having_clause {
+        v.visit_having_clause(clause);
+    }
+    if let Some(clause) = &select.order_by_clause {
+        v.visit_order_by_clause(clause);
+    }
+    if let Some(clause) = &select.limit_clause {
+        v.visit_limit_clause(clause);
+    }
+}
+
+pub fn walk_update_stmt<'a, V: Visitor<'a> + ?Sized>(v: &mut V, update: &UpdateStmt<'a>) {
+    v.visit_dataset_reference(&update.table);
+    for assignment in &update.assignments {
+        v.visit_identifier(assignment.column);
+        v.visit_expr(&assignment.value);
+    }
+    if let Some(clause) = &update.where_clause {
+        v.visit_where_clause(clause);
+    }
+}
+
+pub fn walk_insert_stmt<'a, V: Visitor<'a> + ?Sized>(v: &mut V, insert: &InsertStmt<'a>) {
+    v.visit_dataset_reference(&insert.table);
+    for column in &insert.columns {
+        v.visit_identifier(column);
+    }
+    for row in &insert.values {
+        for expr in row {
+            v.visit_expr(expr);
+        }
+    }
+}
+
+pub fn walk_delete_stmt<'a, V: Visitor<'a> + ?Sized>(v: &mut V, delete: &DeleteStmt<'a>) {
+    v.visit_dataset_reference(&delete.table);
+    if let Some(clause) = &delete.where_clause {
+        v.visit_where_clause(clause);
+    }
+}
+
+pub fn walk_create_table_stmt<'a, V: Visitor<'a> + ?Sized>(v: &mut V, create: &CreateTableStmt<'a>) {
+    v.visit_dataset_reference(&create.table);
+    for column in &create.columns {
+        v.visit_column_def(column);
+    }
+    for constraint in &create.constraints {
+        v.visit_table_constraint(constraint);
+    }
+}
+
+pub fn walk_set_stmt<'a, V: Visitor<'a> + ?Sized>(v: &mut V, set: &SetStmt<'a>) {
+    v.visit_identifier(set.name);
+    if let Some(value) = &set.value {
+        v.visit_expr(value);
+    }
+}
+
+pub fn walk_grant_stmt<'a, V: Visitor<'a> + ?Sized>(v: &mut V, grant: &GrantStmt<'a>) {
+    if let GrantKind::Privileges {
+        object: GrantObject::Tables(tables),
+        ..
+    } = &grant.kind
+    {
+        for table in tables {
+            v.visit_dataset_reference(table);
+        }
+    }
+}
+
+pub fn walk_column_def<'a, V: Visitor<'a> + ?Sized>(v: &mut V, column: &ColumnDef<'a>) {
+    v.visit_identifier(column.name);
+    for constraint in &column.constraints {
+        if let ColumnConstraintKind::Default(expr) = constraint {
+            v.visit_expr(expr);
+        }
+    }
+}
+
+pub fn walk_table_constraint<'a, V: Visitor<'a> + ?Sized>(v: &mut V, constraint: &TableConstraintKind<'a>) {
+    let (TableConstraintKind::PrimaryKey { name, columns }
+    | TableConstraintKind::Unique { name, columns }
+    | TableConstraintKind::Index { name, columns }) = constraint;
+
+    if let Some(name) = name {
+        v.visit_identifier(name);
+    }
+    for column in columns {
+        v.visit_identifier(column);
+    }
+}
+
+pub fn walk_select_clause<'a, V: Visitor<'a> + ?Sized>(v: &mut V, clause: &SelectClause<'a>) {
+    for item in &clause.selected {
+        v.visit_select_item(item);
+    }
+}
+
+pub fn walk_select_item<'a, V: Visitor<'a> + ?Sized>(v: &mut V, item: &SelectItemKind<'a>) {
+    match item {
+        SelectItemKind::All => {}
+        SelectItemKind::Identifier(obj) => v.visit_object_reference(obj),
+        SelectItemKind::Expr(expr) => v.visit_expr(expr),
+    }
+}
+
+pub fn walk_from_clause<'a, V: Visitor<'a> + ?Sized>(v: &mut V, clause: &FromClause<'a>) {
+    for item in &clause.from {
+        v.visit_from_item(item);
+    }
+}
+
+pub fn walk_from_item<'a, V: Visitor<'a> + ?Sized>(v: &mut V, item: &FromItemKind<'a>) {
+    match item {
+        FromItemKind::Dataset(dataset) => v.visit_dataset_reference(dataset),
+        FromItemKind::Join(join) => v.visit_join_clause(join),
+    }
+}
+
+pub fn walk_where_clause<'a, V: Visitor<'a> + ?Sized>(v: &mut V, clause: &WhereClause<'a>) {
+    v.visit_expr(&clause.expr);
+}
+
+pub fn walk_limit_clause<'a, V: Visitor<'a> + ?Sized>(v: &mut V, clause: &LimitClause<'a>) {
+    v.visit_expr(&clause.count);
+}
+
+pub fn walk_expr<'a, V: Visitor<'a> + ?Sized>(v: &mut V, expr: &ExprKind<'a>) {
+    match expr {
+        ExprKind::Identifier(obj) => v.visit_object_reference(obj),
+        ExprKind::Literal(literal) => v.visit_literal(literal),
+        ExprKind::Placeholder(_) => {}
+        ExprKind::Unary(_, operand) => v.visit_expr(operand),
+        ExprKind::Binary(left, _, right) => {
+            v.visit_expr(left);
+            v.visit_expr(right);
+        }
+    }
+}
+
+pub fn walk_dataset_reference<'a, V: Visitor<'a> + ?Sized>(v: &mut V, dataset: &DatasetReference<'a>) {
+    if let Some(schema) = dataset.schema {
+        v.visit_identifier(schema);
+    }
+    if let Some(name) = dataset.dataset {
+        v.visit_identifier(name);
+    }
+}
+
+pub fn walk_object_reference<'a, V: Visitor<'a> + ?Sized>(v: &mut V, obj: &ObjectReference<'a>) {
+    if let Some(dataset) = &obj.dataset {
+        v.visit_dataset_reference(dataset);
+    }
+    if let Some(name) = obj.obj {
+        v.visit_identifier(name);
+    }
+}
+
+pub trait VisitorMut<'a> {
+    fn visit_ast_mut(&mut self, ast: &mut AST<'a>) {
+        walk_ast_mut(self, ast);
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut StatementKind<'a>) {
+        walk_stmt_mut(self, stmt);
+    }
+
+    fn visit_block_stmt_mut(&mut self, block: &mut BlockStmt<'a>) {
+        walk_block_stmt_mut(self, block);
+    }
+
+    fn visit_select_stmt_mut(&mut self, select: &mut SelectStmt<'a>) {
+        walk_select_stmt_mut(self, select);
+    }
+
+    fn visit_update_stmt_mut(&mut self, update: &mut UpdateStmt<'a>) {
+        walk_update_stmt_mut(self, update);
+    }
+
+    fn visit_insert_stmt_mut(&mut self, insert: &mut InsertStmt<'a>) {
+        walk_insert_stmt_mut(self, insert);
+    }
+
+    fn visit_delete_stmt_mut(&mut self, delete: &mut DeleteStmt<'a>) {
+        walk_delete_stmt_mut(self, delete);
+    }
+
+    fn visit_create_table_stmt_mut(&mut self, create: &mut CreateTableStmt<'a>) {
+        walk_create_table_stmt_mut(self, create);
+    }
+
+    fn visit_set_stmt_mut(&mut self, set: &mut SetStmt<'a>) {
+        walk_set_stmt_mut(self, set);
+    }
+
+    fn visit_grant_stmt_mut(&mut self, grant: &mut GrantStmt<'a>) {
+        walk_grant_stmt_mut(self, grant);
+    }
+
+    fn visit_column_def_mut(&mut self, column: &mut ColumnDef<'a>) {
+        walk_column_def_mut(self, column);
+    }
+
+    fn visit_table_constraint_mut(&mut self, constraint: &mut TableConstraintKind<'a>) {
+        walk_table_constraint_mut(self, constraint);
+    }
+
+    fn visit_select_clause_mut(&mut self, clause: &mut SelectClause<'a>) {
+        walk_select_clause_mut(self, clause);
+    }
+
+    fn visit_select_item_mut(&mut self, item: &mut SelectItemKind<'a>) {
+        walk_select_item_mut(self, item);
+    }
+
+    fn visit_from_clause_mut(&mut self, clause: &mut FromClause<'a>) {
+        walk_from_clause_mut(self, clause);
+    }
+
+    fn visit_from_item_mut(&mut self, item: &mut FromItemKind<'a>) {
+        walk_from_item_mut(self, item);
+    }
+
+    fn visit_join_clause_mut(&mut self, _clause: &mut JoinClause) {}
+
+    fn visit_where_clause_mut(&mut self, clause: &mut WhereClause<'a>) {
+        walk_where_clause_mut(self, clause);
+    }
+
+    fn visit_group_by_clause_mut(&mut self, _clause: &mut GroupByClause) {}
+
+    fn visit_having_clause_mut(&mut self, _clause: &mut HavingClause) {}
+
+    fn visit_order_by_clause_mut(&mut self, _clause: &mut OrderByClause) {}
+
+    fn visit_limit_clause_mut(&mut self, clause: &mut LimitClause<'a>) {
+        walk_limit_clause_mut(self, clause);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut ExprKind<'a>) {
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_literal_mut(&mut self, _literal: &mut LiteralKind<'a>) {}
+
+    fn visit_dataset_reference_mut(&mut self, dataset: &mut DatasetReference<'a>) {
+        walk_dataset_reference_mut(self, dataset);
+    }
+
+    fn visit_object_reference_mut(&mut self, obj: &mut ObjectReference<'a>) {
+        walk_object_reference_mut(self, obj);
+    }
+
+    fn visit_identifier_mut(&mut self, _ident: &mut &'a str) {}
+}
+
+pub fn walk_ast_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, ast: &mut AST<'a>) {
+    for stmt in &mut ast.stmts {
+        v.visit_stmt_mut(stmt);
+    }
+}
+
+pub fn walk_stmt_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, stmt: &mut StatementKind<'a>) {
+    match stmt {
+        StatementKind::Block(block) => v.visit_block_stmt_mut(block),
+        StatementKind::Select(select) => v.visit_select_stmt_mut(select),
+        StatementKind::Update(update) => v.visit_update_stmt_mut(update),
+        StatementKind::Insert(insert) => v.visit_insert_stmt_mut(insert),
+        StatementKind::Delete(delete) => v.visit_delete_stmt_mut(delete),
+        StatementKind::CreateTable(create) => v.visit_create_table_stmt_mut(create),
+        StatementKind::CreateIndex(create) => v.visit_dataset_reference_mut(&mut create.table),
+        StatementKind::Vacuum(Some(table)) => v.visit_dataset_reference_mut(table),
+        StatementKind::Set(set) => v.visit_set_stmt_mut(set),
+        StatementKind::Grant(grant) | StatementKind::Revoke(grant) => v.visit_grant_stmt_mut(grant),
+        StatementKind::CreateRole(_)
+        | StatementKind::AlterRole(_)
+        | StatementKind::DropRole(_)
+        | StatementKind::DropIndex(_)
+        | StatementKind::Begin(_)
+        | StatementKind::Commit
+        | StatementKind::Rollback
+        | StatementKind::Vacuum(None) => {}
+    }
+}
+
+pub fn walk_block_stmt_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, block: &mut BlockStmt<'a>) {
+    for stmt in &mut block.stmts {
+        v.visit_stmt_mut(stmt);
+    }
+}
+
+pub fn walk_select_stmt_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, select: &mut SelectStmt<'a>) {
+    v.visit_select_clause_mut(&mut select.select_clause);
+    v.visit_from_clause_mut(&mut select.from_clause);
+    if let Some(clause) = &mut select.where_clause {
+        v.visit_where_clause_mut(clause);
+    }
+    if let Some(clause) = &mut select.group_by_clause {
+        v.visit_group_by_clause_mut(clause);
+    }
+    if let Some(clause) = &mut select.having_clause {
+        v.visit_having_clause_mut(clause);
+    }
+    if let Some(clause) = &mut select.order_by_clause {
+        v.visit_order_by_clause_mut(clause);
+    }
+    if let Some(clause) = &mut select.limit_clause {
+        v.visit_limit_clause_mut(clause);
+    }
+}
+
+pub fn walk_update_stmt_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, update: &mut UpdateStmt<'a>) {
+    v.visit_dataset_reference_mut(&mut update.table);
+    for assignment in &mut update.assignments {
+        v.visit_identifier_mut(&mut assignment.column);
+        v.visit_expr_mut(&mut assignment.value);
+    }
+    if let Some(clause) = &mut update.where_clause {
+        v.visit_where_clause_mut(clause);
+    }
+}
+
+pub fn walk_insert_stmt_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, insert: &mut InsertStmt<'a>) {
+    v.visit_dataset_reference_mut(&mut insert.table);
+    for column in &mut insert.columns {
+        v.visit_identifier_mut(column);
+    }
+    for row in &mut insert.values {
+        for expr in row {
+            v.visit_expr_mut(expr);
+        }
+    }
+}
+
+pub fn walk_delete_stmt_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, delete: &mut DeleteStmt<'a>) {
+    v.visit_dataset_reference_mut(&mut delete.table);
+    if let Some(clause) = &mut delete.where_clause {
+        v.visit_where_clause_mut(clause);
+    }
+}
+
+pub fn walk_create_table_stmt_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, create: &mut CreateTableStmt<'a>) {
+    v.visit_dataset_reference_mut(&mut create.table);
+    for column in &mut create.columns {
+        v.visit_column_def_mut(column);
+    }
+    for constraint in &mut create.constraints {
+        v.visit_table_constraint_mut(constraint);
+    }
+}
+
+pub fn walk_set_stmt_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, set: &mut SetStmt<'a>) {
+    v.visit_identifier_mut(&mut set.name);
+    if let Some(value) = &mut set.value {
+        v.visit_expr_mut(value);
+    }
+}
+
+pub fn walk_grant_stmt_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, grant: &mut GrantStmt<'a>) {
+    if let GrantKind::Privileges {
+        object: GrantObject::Tables(tables),
+        ..
+    } = &mut grant.kind
+    {
+        for table in tables {
+            v.visit_dataset_reference_mut(table);
+        }
+    }
+}
+
+pub fn walk_column_def_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, column: &mut ColumnDef<'a>) {
+    v.visit_identifier_mut(&mut column.name);
+    for constraint in &mut column.constraints {
+        if let ColumnConstraintKind::Default(expr) = constraint {
+            v.visit_expr_mut(expr);
+        }
+    }
+}
+
+pub fn walk_table_constraint_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, constraint: &mut TableConstraintKind<'a>) {
+    let (TableConstraintKind::PrimaryKey { name, columns }
+    | TableConstraintKind::Unique { name, columns }
+    | TableConstraintKind::Index { name, columns }) = constraint;
+
+    if let Some(name) = name {
+        v.visit_identifier_mut(name);
+    }
+    for column in columns {
+        v.visit_identifier_mut(column);
+    }
+}
+
+pub fn walk_select_clause_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, clause: &mut SelectClause<'a>) {
+    for item in &mut clause.selected {
+        v.visit_select_item_mut(item);
+    }
+}
+
+pub fn walk_select_item_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, item: &mut SelectItemKind<'a>) {
+    match item {
+        SelectItemKind::All => {}
+        SelectItemKind::Identifier(obj) => v.visit_object_reference_mut(obj),
+        SelectItemKind::Expr(expr) => v.visit_expr_mut(expr),
+    }
+}
+
+pub fn walk_from_clause_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, clause: &mut FromClause<'a>) {
+    for item in &mut clause.from {
+        v.visit_from_item_mut(item);
+    }
+}
+
+pub fn walk_from_item_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, item: &mut FromItemKind<'a>) {
+    match item {
+        FromItemKind::Dataset(dataset) => v.visit_dataset_reference_mut(dataset),
+        FromItemKind::Join(join) => v.visit_join_clause_mut(join),
+    }
+}
+
+pub fn walk_where_clause_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, clause: &mut WhereClause<'a>) {
+    v.visit_expr_mut(&mut clause.expr);
+}
+
+pub fn walk_limit_clause_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, clause: &mut LimitClause<'a>) {
+    v.visit_expr_mut(&mut clause.count);
+}
+
+pub fn walk_expr_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, expr: &mut ExprKind<'a>) {
+    match expr {
+        ExprKind::Identifier(obj) => v.visit_object_reference_mut(obj),
+        ExprKind::Literal(literal) => v.visit_literal_mut(literal),
+        ExprKind::Placeholder(_) => {}
+        ExprKind::Unary(_, operand) => v.visit_expr_mut(operand),
+        ExprKind::Binary(left, _, right) => {
+            v.visit_expr_mut(left);
+            v.visit_expr_mut(right);
+        }
+    }
+}
+
+pub fn walk_dataset_reference_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, dataset: &mut DatasetReference<'a>) {
+    if let Some(schema) = &mut dataset.schema {
+        v.visit_identifier_mut(schema);
+    }
+    if let Some(name) = &mut dataset.dataset {
+        v.visit_identifier_mut(name);
+    }
+}
+
+pub fn walk_object_reference_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, obj: &mut ObjectReference<'a>) {
+    if let Some(dataset) = &mut obj.dataset {
+        v.visit_dataset_reference_mut(dataset);
+    }
+    if let Some(name) = &mut obj.obj {
+        v.visit_identifier_mut(name);
+    }
+}
+
+pub trait Fold<'a> {
+    fn fold_ast(&mut self, ast: AST<'a>) -> AST<'a> {
+        fold_ast(self, ast)
+    }
+
+    fn fold_stmt(&mut self, stmt: StatementKind<'a>) -> StatementKind<'a> {
+        fold_stmt(self, stmt)
+    }
+
+    fn fold_block_stmt(&mut self, block: BlockStmt<'a>) -> BlockStmt<'a> {
+        fold_block_stmt(self, block)
+    }
+
+    fn fold_select_stmt(&mut self, select: SelectStmt<'a>) -> SelectStmt<'a> {
+        fold_select_stmt(self, select)
+    }
+
+    fn fold_update_stmt(&mut self, update: UpdateStmt<'a>) -> UpdateStmt<'a> {
+        fold_update_stmt(self, update)
+    }
+
+    fn fold_insert_stmt(&mut self, insert: InsertStmt<'a>) -> InsertStmt<'a> {
+        fold_insert_stmt(self, insert)
+    }
+
+    fn fold_delete_stmt(&mut self, delete: DeleteStmt<'a>) -> DeleteStmt<'a> {
+        fold_delete_stmt(self, delete)
+    }
+
+    fn fold_create_table_stmt(&mut self, create: CreateTableStmt<'a>) -> CreateTableStmt<'a> {
+        fold_create_table_stmt(self, create)
+    }
+
+    fn fold_set_stmt(&mut self, set: SetStmt<'a>) -> SetStmt<'a> {
+        fold_set_stmt(self, set)
+    }
+
+    fn fold_grant_stmt(&mut self, grant: GrantStmt<'a>) -> GrantStmt<'a> {
+        fold_grant_stmt(self, grant)
+    }
+
+    fn fold_column_def(&mut self, column: ColumnDef<'a>) -> ColumnDef<'a> {
+        fold_column_def(self, column)
+    }
+
+    fn fold_table_constraint(&mut self, constraint: TableConstraintKind<'a>) -> TableConstraintKind<'a> {
+        fold_table_constraint(self, constraint)
+    }
+
+    fn fold_select_clause(&mut self, clause: SelectClause<'a>) -> SelectClause<'a> {
+        fold_select_clause(self, clause)
+    }
+
+    fn fold_select_item(&mut self, item: SelectItemKind<'a>) -> SelectItemKind<'a> {
+        fold_select_item(self, item)
+    }
+
+    fn fold_from_clause(&mut self, clause: FromClause<'a>) -> FromClause<'a> {
+        fold_from_clause(self, clause)
+    }
+
+    fn fold_from_item(&mut self, item: FromItemKind<'a>) -> FromItemKind<'a> {
+        fold_from_item(self, item)
+    }
+
+    fn fold_join_clause(&mut self, clause: JoinClause) -> JoinClause {
+        clause
+    }
+
+    fn fold_where_clause(&mut self, clause: WhereClause<'a>) -> WhereClause<'a> {
+        fold_where_clause(self, clause)
+    }
+
+    fn fold_group_by_clause(&mut self, clause: GroupByClause) -> GroupByClause {
+        clause
+    }
+
+    fn fold_having_clause(&mut self, clause: HavingClause) -> HavingClause {
+        clause
+    }
+
+    fn fold_order_by_clause(&mut self, clause: OrderByClause) -> OrderByClause {
+        clause
+    }
+
+    fn fold_limit_clause(&mut self, clause: LimitClause<'a>) -> LimitClause<'a> {
+        fold_limit_clause(self, clause)
+    }
+
+    fn fold_expr(&mut self, expr: ExprKind<'a>) -> ExprKind<'a> {
+        fold_expr(self, expr)
+    }
+
+    fn fold_literal(&mut self, literal: LiteralKind<'a>) -> ExprKind<'a> {
+        ExprKind::Literal(literal)
+    }
+
+    fn fold_dataset_reference(&mut self, dataset: DatasetReference<'a>) -> DatasetReference<'a> {
+        fold_dataset_reference(self, dataset)
+    }
+
+    fn fold_object_reference(&mut self, obj: ObjectReference<'a>) -> ObjectReference<'a> {
+        fold_object_reference(self, obj)
+    }
+
+    fn fold_identifier(&mut self, ident: &'a str) -> &'a str {
+        ident
+    }
+}
+
+pub fn fold_ast<'a, F: Fold<'a> + ?Sized>(f: &mut F, ast: AST<'a>) -> AST<'a> {
+    AST {
+        stmts: ast.stmts.into_iter().map(|stmt| f.fold_stmt(stmt)).collect(),
+    }
+}
+
+pub fn fold_stmt<'a, F: Fold<'a> + ?Sized>(f: &mut F, stmt: StatementKind<'a>) -> StatementKind<'a> {
+    match stmt {
+        StatementKind::Block(block) => StatementKind::Block(f.fold_block_stmt(block)),
+        StatementKind::Select(select) => StatementKind::Select(f.fold_select_stmt(select)),
+        StatementKind::Update(update) => StatementKind::Update(f.fold_update_stmt(update)),
+        StatementKind::Insert(insert) => StatementKind::Insert(f.fold_insert_stmt(insert)),
+        StatementKind::Delete(delete) => StatementKind::Delete(f.fold_delete_stmt(delete)),
+        StatementKind::CreateTable(create) => StatementKind::CreateTable(f.fold_create_table_stmt(create)),
+        StatementKind::CreateIndex(create) => StatementKind::CreateIndex(CreateIndexStmt {
+            table: f.fold_dataset_reference(create.table),
+            ..create
+        }),
+        StatementKind::Set(set) => StatementKind::Set(f.fold_set_stmt(set)),
+        StatementKind::Grant(grant) => StatementKind::Grant(f.fold_grant_stmt(grant)),
+        StatementKind::Revoke(grant) => StatementKind::Revoke(f.fold_grant_stmt(grant)),
+        StatementKind::Vacuum(table) => StatementKind::Vacuum(table.map(|table| f.fold_dataset_reference(table))),
+        StatementKind::CreateRole(_)
+        | StatementKind::AlterRole(_)
+        | StatementKind::DropRole(_)
+        | StatementKind::DropIndex(_)
+        | StatementKind::Begin(_)
+        | StatementKind::Commit
+        | StatementKind::Rollback => stmt,
+    }
+}
+
+pub fn fold_block_stmt<'a, F: Fold<'a> + ?Sized>(f: &mut F, block: BlockStmt<'a>) -> BlockStmt<'a> {
+    BlockStmt {
+        stmts: block.stmts.into_iter().map(|stmt| f.fold_stmt(stmt)).collect(),
+    }
+}
+
+pub fn fold_select_stmt<'a, F: Fold<'a> + ?Sized>(f: &mut F, select: SelectStmt<'a>) -> SelectStmt<'a> {
+    SelectStmt {
+        select_clause: f.fold_select_clause(select.select_clause),
+        from_clause: f.fold_from_clause(select.from_clause),
+        where_clause: select.where_clause.map(|clause| f.fold_where_clause(clause)),
+        group_by_clause: select.group_by_clause.map(|clause| f.fold_group_by_clause(clause)),
+        having_clause: select.having_clause.map(|clause| f.fold_having_clause(clause)),
+        order_by_clause: select.order_by_clause.map(|clause| f.fold_order_by_clause(clause)),
+        limit_clause: select.limit_clause.map(|clause| f.fold_limit_clause(clause)),
+        locking_clause: select.locking_clause,
+    }
+}
+
+pub fn fold_update_stmt<'a, F: Fold<'a> + ?Sized>(f: &mut F, update: UpdateStmt<'a>) -> UpdateStmt<'a> {
+    UpdateStmt {
+        table: f.fold_dataset_reference(update.table),
+        assignments: update
+            .assignments
+            .into_iter()
+            .map(|assignment| Assignment {
+                column: f.fold_identifier(assignment.column),
+                value: f.fold_expr(assignment.value),
+            })
+            .collect(),
+        where_clause: update.where_clause.map(|clause| f.fold_where_clause(clause)),
+    }
+}
+
+pub fn fold_insert_stmt<'a, F: Fold<'a> + ?Sized>(f: &mut F, insert: InsertStmt<'a>) -> InsertStmt<'a> {
+    InsertStmt {
+        table: f.fold_dataset_reference(insert.table),
+        columns: insert.columns.into_iter().map(|column| f.fold_identifier(column)).collect(),
+        values: insert
+            .values
+            .into_iter()
+            .map(|row| row.into_iter().map(|expr| f.fold_expr(expr)).collect())
+            .collect(),
+    }
+}
+
+pub fn fold_delete_stmt<'a, F: Fold<'a> + ?Sized>(f: &mut F, delete: DeleteStmt<'a>) -> DeleteStmt<'a> {
+    DeleteStmt {
+        table: f.fold_dataset_reference(delete.table),
+        where_clause: delete.where_clause.map(|clause| f.fold_where_clause(clause)),
+    }
+}
+
+pub fn fold_create_table_stmt<'a, F: Fold<'a> + ?Sized>(f: &mut F, create: CreateTableStmt<'a>) -> CreateTableStmt<'a> {
+    CreateTableStmt {
+        table: f.fold_dataset_reference(create.table),
+        if_not_exists: create.if_not_exists,
+        columns: create.columns.into_iter().map(|column| f.fold_column_def(column)).collect(),
+        constraints: create
+            .constraints
+            .into_iter()
+            .map(|constraint| f.fold_table_constraint(constraint))
+            .collect(),
+    }
+}
+
+pub fn fold_set_stmt<'a, F: Fold<'a> + ?Sized>(f: &mut F, set: SetStmt<'a>) -> SetStmt<'a> {
+    SetStmt {
+        name: f.fold_identifier(set.name),
+        value: set.value.map(|value| f.fold_expr(value)),
+    }
+}
+
+pub fn fold_grant_stmt<'a, F: Fold<'a> + ?Sized>(f: &mut F, grant: GrantStmt<'a>) -> GrantStmt<'a> {
+    let kind = match grant.kind {
+        GrantKind::Privileges {
+            privileges,
+            object: GrantObject::Tables(tables),
+        } => GrantKind::Privileges {
+            privileges,
+            object: GrantObject::Tables(tables.into_iter().map(|table| f.fold_dataset_reference(table)).collect()),
+        },
+        kind => kind,
+    };
+    GrantStmt { kind, ..grant }
+}
+
+pub fn fold_column_def<'a, F: Fold<'a> + ?Sized>(f: &mut F, column: ColumnDef<'a>) -> ColumnDef<'a> {
+    ColumnDef {
+        name: f.fold_identifier(column.name),
+        data_type: column.data_type,
+        constraints: column
+            .constraints
+            .into_iter()
+            .map(|constraint| match constraint {
+                ColumnConstraintKind::Default(expr) => ColumnConstraintKind::Default(f.fold_expr(expr)),
+                constraint => constraint,
+            })
+            .collect(),
+    }
+}
+
+pub fn fold_table_constraint<'a, F: Fold<'a> + ?Sized>(
+    f: &mut F,
+    constraint: TableConstraintKind<'a>,
+) -> TableConstraintKind<'a> {
+    let mut fold_names = |name: Option<&'a str>, columns: Vec<&'a str>| {
+        (
+            name.map(|name| f.fold_identifier(name)),
+            columns.into_iter().map(|column| f.fold_identifier(column)).collect(),
+        )
+    };
+
+    match constraint {
+        TableConstraintKind::PrimaryKey { name, columns } => {
+            let (name, columns) = fold_names(name, columns);
+            TableConstraintKind::PrimaryKey { name, columns }
+        }
+        TableConstraintKind::Unique { name, columns } => {
+            let (name, columns) = fold_names(name, columns);
+            TableConstraintKind::Unique { name, columns }
+        }
+        TableConstraintKind::Index { name, columns } => {
+            let (name, columns) = fold_names(name, columns);
+            TableConstraintKind::Index { name, columns }
+        }
+    }
+}
+
+pub fn fold_select_clause<'a, F: Fold<'a> + ?Sized>(f: &mut F, clause: SelectClause<'a>) -> SelectClause<'a> {
+    SelectClause {
+        selected: clause.selected.into_iter().map(|item| f.fold_select_item(item)).collect(),
+    }
+}
+
+pub fn fold_select_item<'a, F: Fold<'a> + ?Sized>(f: &mut F, item: SelectItemKind<'a>) -> SelectItemKind<'a> {
+    match item {
+        SelectItemKind::All => SelectItemKind::All,
+        SelectItemKind::Identifier(obj) => SelectItemKind::Identifier(f.fold_object_reference(obj)),
+        SelectItemKind::Expr(expr) => SelectItemKind::Expr(f.fold_expr(expr)),
+    }
+}
+
+pub fn fold_from_clause<'a, F: Fold<'a> + ?Sized>(f: &mut F, clause: FromClause<'a>) -> FromClause<'a> {
+    FromClause {
+        from: clause.from.into_iter().map(|item| f.fold_from_item(item)).collect(),
+    }
+}
+
+pub fn fold_from_item<'a, F: Fold<'a> + ?Sized>(f: &mut F, item: FromItemKind<'a>) -> FromItemKind<'a> {
+    match item {
+        FromItemKind::Dataset(dataset) => FromItemKind::Dataset(f.fold_dataset_reference(dataset)),
+        FromItemKind::Join(join) => FromItemKind::Join(f.fold_join_clause(join)),
+    }
+}
+
+pub fn fold_where_clause<'a, F: Fold<'a> + ?Sized>(f: &mut F, clause: WhereClause<'a>) -> WhereClause<'a> {
+    WhereClause {
+        expr: f.fold_expr(clause.expr),
+    }
+}
+
+pub fn fold_limit_clause<'a, F: Fold<'a> + ?Sized>(f: &mut F, clause: LimitClause<'a>) -> LimitClause<'a> {
+    LimitClause {
+        count: f.fold_expr(clause.count),
+    }
+}
+
+pub fn fold_expr<'a, F: Fold<'a> + ?Sized>(f: &mut F, expr: ExprKind<'a>) -> ExprKind<'a> {
+    match expr {
+        ExprKind::Identifier(obj) => ExprKind::Identifier(f.fold_object_reference(obj)),
+        ExprKind::Literal(literal) => f.fold_literal(literal),
+        ExprKind::Placeholder(_) => expr,
+        ExprKind::Unary(op, operand) => ExprKind::Unary(op, Box::new(f.fold_expr(*operand))),
+        ExprKind::Binary(left, op, right) => {
+            ExprKind::Binary(Box::new(f.fold_expr(*left)), op, Box::new(f.fold_expr(*right)))
+        }
+    }
+}
+
+pub fn fold_dataset_reference<'a, F: Fold<'a> + ?Sized>(f: &mut F, dataset: DatasetReference<'a>) -> DatasetReference<'a> {
+    DatasetReference {
+        schema: dataset.schema.map(|schema| f.fold_identifier(schema)),
+        dataset: dataset.dataset.map(|name| f.fold_identifier(name)),
+    }
+}
+
+pub fn fold_object_reference<'a, F: Fold<'a> + ?Sized>(f: &mut F, obj: ObjectReference<'a>) -> ObjectReference<'a> {
+    ObjectReference {
+        dataset: obj.dataset.map(|dataset| f.fold_dataset_reference(dataset)),
+        obj: obj.obj.map(|name| f.fold_identifier(name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    struct TableCollector<'a> {
+        tables: Vec<&'a str>,
+    }
+
+    impl<'a> Visitor<'a> for TableCollector<'a> {
+        fn visit_dataset_reference(&mut self, dataset: &DatasetReference<'a>) {
+            if let Some(name) = dataset.dataset {
+                self.tables.push(name);
+            }
+        }
+    }
+
+    struct Renamer<'a> {
+        from: &'a str,
+        to: &'a str,
+    }
+
+    impl<'a> VisitorMut<'a> for Renamer<'a> {
+        fn visit_identifier_mut(&mut self, ident: &mut &'a str) {
+            if *ident == self.from {
+                *ident = self.to;
+            }
+        }
+    }
+
+    struct LiteralReplacer;
+
+    impl<'a> Fold<'a> for LiteralReplacer {
+        fn fold_literal(&mut self, _literal: LiteralKind<'a>) -> ExprKind<'a> {
+            ExprKind::Literal(LiteralKind::String("?"))
+        }
+    }
+
+    fn select_from(table: &str) -> StatementKind<'_> {
+        StatementKind::Select(SelectStmt::new(SelectClause::all(), FromClause::table(table)))
+    }
+
+    #[test]
+    fn test_collect_tables() {
+        let ast = AST {
+            stmts: vec![
+                select_from("cats"),
+                StatementKind::Commit,
+                StatementKind::Block(BlockStmt {
+                    stmts: vec![StatementKind::Delete(DeleteStmt {
+                        table: DatasetReference::new("dogs"),
+                        where_clause: None,
+                    })],
+                }),
+            ],
+        };
+
+        let mut collector = TableCollector { tables: Vec::new() };
+        collector.visit_ast(&ast);
+
+        assert_eq!(collector.tables, vec!["cats", "dogs"]);
+    }
+
+    #[test]
+    fn test_rename_identifiers() {
+        let mut ast = AST {
+            stmts: vec![select_from("cats"), select_from("birds")],
+        };
+
+        Renamer {
+            from: "cats",
+            to: "dogs",
+        }
+        .visit_ast_mut(&mut ast);
+
+        assert_eq!(ast.stmts[0], select_from("dogs"));
+        assert_eq!(ast.stmts[1], select_from("birds"));
+    }
+
+    #[test]
+    fn test_fold_literals() {
+        let expr = ExprKind::Literal(LiteralKind::Numeric(42.0));
+        assert_eq!(LiteralReplacer.fold_expr(expr), ExprKind::Literal(LiteralKind::String("?")));
+
+        let ident = ExprKind::Identifier(ObjectReference {
+            dataset: None,
+            obj: Some("weight"),
+        });
+        assert_eq!(LiteralReplacer.fold_expr(ident.clone()), ident);
+    }
+}
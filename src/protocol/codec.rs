@@ -0,0 +1,289 @@
+//! Message framing.
+//!
+//! Every message on the wire is a one byte type followed by a big endian `u32` length that counts itself and the
+//! body but not the type byte. The startup message of a connection has no type byte.
+
+use std::fmt::{Display, Formatter};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Default limit on the body of a frame accepted from a peer, see `memory.max_message_size`.
+pub const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    Io(std::io::Error),
+    /// The peer sent something that does not follow the protocol.
+    Malformed(String),
+}
+
+impl Display for ProtocolError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ProtocolError::Io(err) => write!(f, "{err}"),
+            ProtocolError::Malformed(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<std::io::Error> for ProtocolError {
+    fn from(err: std::io::Error) -> Self {
+        ProtocolError::Io(err)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub tag: u8,
+    pub body: Vec<u8>,
+}
+
+/// Reads the next frame, `None` when the peer closed the connection between frames.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R, max_len: usize) -> Result<Option<Frame>, ProtocolError> {
+    let tag = match reader.read_u8().await {
+        Ok(tag) => tag,
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let len = reader.read_u32().await? as usize;
+    let body = read_body(reader, len, max_len).await?;
+
+    Ok(Some(Frame { tag, body }))
+}
+
+/// Reads the body of an untyped frame, which only the first message of a connection is.
+pub async fn read_untyped_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    max_len: usize,
+) -> Result<Option<Vec<u8>>, ProtocolError> {
+    let len = match reader.read_u32().await {
+        Ok(len) => len as usize,
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    read_body(reader, len, max_len).await.map(Some)
+}
+
+async fn read_body<R: AsyncRead + Unpin>(reader: &mut R, len: usize, max_len: usize) -> Result<Vec<u8>, ProtocolError> {
+    if len < 4 {
+        return Err(ProtocolError::Malformed(format!("invalid frame length: {len}")));
+    }
+    if len - 4 > max_len {
+        return Err(ProtocolError::Malformed(format!(
+            "frame of {0} bytes exceeds the limit of {max_len}",
+            len - 4
+        )));
+    }
+
+    let mut body = vec![0u8; len - 4];
+    reader.read_exact(&mut body).await?;
+    Ok(body)
+}
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, tag: u8, body: &[u8]) -> Result<(), ProtocolError> {
+    writer.write_u8(tag).await?;
+    write_untyped_frame(writer, body).await
+}
+
+pub async fn write_untyped_frame<W: AsyncWrite + Unpin>(writer: &mut W, body: &[u8]) -> Result<(), ProtocolError> {
+    writer.write_u32(body.len() as u32 + 4).await?;
+    writer.write_all(body).await?;
+    Ok(())
+}
+
+/// Builds a message body.
+#[derive(Debug, Default)]
+pub struct BodyWriter {
+    buf: Vec<u8>,
+}
+
+impl BodyWriter {
+    pub fn new() -> Self {
+        BodyWriter::default()
+    }
+
+    pub fn put_u8(&mut self, value: u8) -> &mut Self {
+        self.buf.push(value);
+        self
+    }
+
+    pub fn put_u16(&mut self, value: u16) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn put_u32(&mut self, value: u32) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn put_i32(&mut self, value: i32) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn put_bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    /// Null terminated string.
+    pub fn put_cstr(&mut self, value: &str) -> &mut Self {
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+        self
+    }
+
+    pub fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf)
+    }
+}
+
+/// Reads the fields of a message body, failing on truncated bodies.
+#[derive(Debug)]
+pub struct BodyReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BodyReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        BodyReader { data, pos: 0 }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub fn get_bytes(&mut self, len: usize) -> Result<&'a [u8], ProtocolError> {
+        if self.remaining() < len {
+            return Err(ProtocolError::Malformed("message body is truncated".to_string()));
+        }
+
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn get_u8(&mut self) -> Result<u8, ProtocolError> {
+        Ok(self.get_bytes(1)?[0])
+    }
+
+    pub fn get_u16(&mut self) -> Result<u16, ProtocolError> {
+        Ok(u16::from_be_bytes(self.get_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn get_u32(&mut self) -> Result<u32, ProtocolError> {
+        Ok(u32::from_be_bytes(self.get_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn get_i32(&mut self) -> Result<i32, ProtocolError> {
+        Ok(i32::from_be_bytes(self.get_bytes(4)?.try_into().unwrap()))
+    }
+
+    /// Null terminated string, the terminator is consumed.
+    pub fn get_cstr(&mut self) -> Result<&'a str, ProtocolError> {
+        let len = self.data[self.pos..]
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| ProtocolError::Malformed("unterminated string in message body".to_string()))?;
+
+        let bytes = self.get_bytes(len + 1)?;
+        std::str::from_utf8(&bytes[..len]).map_err(|_| ProtocolError::Malformed("string is not valid UTF-8".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frame_round_trip() {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, b'Q', b"COMMIT").await.unwrap();
+        write_frame(&mut buf, b'X', b"").await.unwrap();
+        assert_eq!(&buf[..5], &[b'Q', 0, 0, 0, 10]);
+
+        let mut reader = buf.as_slice();
+        let frame = read_frame(&mut reader, MAX_FRAME_LEN).await.unwrap().unwrap();
+        assert_eq!(
+            frame,
+            Frame {
+                tag: b'Q',
+                body: b"COMMIT".to_vec()
+            }
+        );
+
+        let frame = read_frame(&mut reader, MAX_FRAME_LEN).await.unwrap().unwrap();
+        assert_eq!(frame, Frame { tag: b'X', body: vec![] });
+
+        assert!(read_frame(&mut reader, MAX_FRAME_LEN).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_untyped_frame() {
+        let mut buf = Vec::new();
+        write_untyped_frame(&mut buf, &[0, 3, 0, 0]).await.unwrap();
+        assert_eq!(buf, [0, 0, 0, 8, 0, 3, 0, 0]);
+
+        let mut reader = buf.as_slice();
+        let body = read_untyped_frame(&mut reader, MAX_FRAME_LEN).await.unwrap();
+        assert_eq!(body, Some(vec![0, 3, 0, 0]));
+    }
+
+    #[tokio::test]
+    async fn test_partial_segments() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        let query = "SELECT * FROM cats WHERE name = 'x'; ".repeat(100);
+
+        let mut buf = Vec::new();
+        write_frame(&mut buf, b'Q', query.as_bytes()).await.unwrap();
+
+        let writer = tokio::spawn(async move {
+            // Trickle the frame out a few bytes at a time.
+            for chunk in buf.chunks(7) {
+                client.write_all(chunk).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        });
+
+        let frame = read_frame(&mut server, MAX_FRAME_LEN).await.unwrap().unwrap();
+        assert_eq!(frame.body, query.as_bytes());
+        writer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rejects_bad_frames() {
+        let mut reader: &[u8] = &[b'Q', 0, 0, 0, 2];
+        assert!(matches!(
+            read_frame(&mut reader, MAX_FRAME_LEN).await,
+            Err(ProtocolError::Malformed(_))
+        ));
+
+        let mut reader: &[u8] = &[b'Q', 0, 0, 1, 0];
+        assert!(matches!(read_frame(&mut reader, 16).await, Err(ProtocolError::Malformed(_))));
+
+        // Connection closed in the middle of a frame.
+        let mut reader: &[u8] = &[b'Q', 0, 0, 0, 10, b'C'];
+        assert!(matches!(
+            read_frame(&mut reader, MAX_FRAME_LEN).await,
+            Err(ProtocolError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_body_reader() {
+        let body = BodyWriter::new().put_cstr("user").put_u16(2).put_i32(-1).finish();
+        let mut reader = BodyReader::new(&body);
+
+        assert_eq!(reader.get_cstr().unwrap(), "user");
+        assert_eq!(reader.get_u16().unwrap(), 2);
+        assert_eq!(reader.get_i32().unwrap(), -1);
+        assert_eq!(reader.remaining(), 0);
+        assert!(reader.get_u8().is_err());
+    }
+}
@@ -0,0 +1,5 @@
+//! Client/server wire protocols.
+
+pub mod codec;
+pub mod mysql;
+pub mod postgres;
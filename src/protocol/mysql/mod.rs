@@ -0,0 +1,643 @@
+//! MySQL client/server protocol, text protocol only.
+//!
+//! Every packet is a little endian 3 byte payload length followed by a sequence id, which starts at 0 with each
+//! command and increments with every packet of the exchange. Payloads of 2^24 - 1 bytes or more are split across
+//! several packets.
+
+pub mod session;
+
+use crate::executor::SqlState;
+use crate::executor::result::Column;
+use crate::parser::token::DataKind;
+use crate::protocol::codec::ProtocolError;
+use crate::value::Value;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Largest payload of a single packet, a packet this size is continued by the next one.
+const MAX_PACKET_LEN: usize = 0xff_ffff;
+
+// Capability flags.
+pub const CLIENT_LONG_PASSWORD: u32 = 0x0000_0001;
+pub const CLIENT_FOUND_ROWS: u32 = 0x0000_0002;
+pub const CLIENT_LONG_FLAG: u32 = 0x0000_0004;
+pub const CLIENT_CONNECT_WITH_DB: u32 = 0x0000_0008;
+pub const CLIENT_PROTOCOL_41: u32 = 0x0000_0200;
+/// Only offered when TLS is configured.
+pub const CLIENT_SSL: u32 = 0x0000_0800;
+pub const CLIENT_TRANSACTIONS: u32 = 0x0000_2000;
+pub const CLIENT_SECURE_CONNECTION: u32 = 0x0000_8000;
+pub const CLIENT_MULTI_STATEMENTS: u32 = 0x0001_0000;
+pub const CLIENT_MULTI_RESULTS: u32 = 0x0002_0000;
+pub const CLIENT_PLUGIN_AUTH: u32 = 0x0008_0000;
+pub const CLIENT_CONNECT_ATTRS: u32 = 0x0010_0000;
+pub const CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA: u32 = 0x0020_0000;
+
+/// Capabilities the server offers, clients without `CLIENT_PROTOCOL_41` are turned away.
+pub const SERVER_CAPABILITIES: u32 = CLIENT_LONG_PASSWORD
+    | CLIENT_FOUND_ROWS
+    | CLIENT_LONG_FLAG
+    | CLIENT_CONNECT_WITH_DB
+    | CLIENT_PROTOCOL_41
+    | CLIENT_TRANSACTIONS
+    | CLIENT_SECURE_CONNECTION
+    | CLIENT_MULTI_STATEMENTS
+    | CLIENT_MULTI_RESULTS
+    | CLIENT_PLUGIN_AUTH
+    | CLIENT_CONNECT_ATTRS
+    | CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA;
+
+// Status flags.
+pub const SERVER_STATUS_IN_TRANS: u16 = 0x0001;
+pub const SERVER_STATUS_AUTOCOMMIT: u16 = 0x0002;
+pub const SERVER_MORE_RESULTS_EXISTS: u16 = 0x0008;
+
+// Commands.
+pub const COM_QUIT: u8 = 0x01;
+pub const COM_INIT_DB: u8 = 0x02;
+pub const COM_QUERY: u8 = 0x03;
+pub const COM_PING: u8 = 0x0e;
+
+/// `utf8mb4_general_ci`
+pub const UTF8MB4_CHARSET: u8 = 45;
+/// Charset of columns that are not text.
+pub const BINARY_CHARSET: u8 = 63;
+
+// Column types.
+pub const MYSQL_TYPE_TINY: u8 = 0x01;
+pub const MYSQL_TYPE_SHORT: u8 = 0x02;
+pub const MYSQL_TYPE_LONG: u8 = 0x03;
+pub const MYSQL_TYPE_FLOAT: u8 = 0x04;
+pub const MYSQL_TYPE_DOUBLE: u8 = 0x05;
+pub const MYSQL_TYPE_LONGLONG: u8 = 0x08;
+pub const MYSQL_TYPE_INT24: u8 = 0x09;
+pub const MYSQL_TYPE_BIT: u8 = 0x10;
+pub const MYSQL_TYPE_NEWDECIMAL: u8 = 0xf6;
+pub const MYSQL_TYPE_BLOB: u8 = 0xfc;
+pub const MYSQL_TYPE_VAR_STRING: u8 = 0xfd;
+pub const MYSQL_TYPE_STRING: u8 = 0xfe;
+
+// Column flags.
+pub const BINARY_FLAG: u16 = 0x0080;
+pub const BLOB_FLAG: u16 = 0x0010;
+pub const NUM_FLAG: u16 = 0x8000;
+
+/// Offered in the handshake. Its fast path needs a hash of the password that the server does not keep, so
+/// passwords are always checked on its full path, where clients send them as they are and only over TLS.
+pub const AUTH_PLUGIN_NAME: &str = "caching_sha2_password";
+
+/// Sends the password as it is, accepted over TLS from clients that pick it.
+pub const CLEAR_PASSWORD_PLUGIN: &str = "mysql_clear_password";
+
+/// Sent in `AuthMoreData` by `caching_sha2_password` to ask the client for its password.
+pub const PERFORM_FULL_AUTHENTICATION: u8 = 0x04;
+
+/// Reads a whole payload, joining continuation packets, and returns it with the sequence id of its last packet.
+/// `None` when the peer closed the connection between packets.
+pub async fn read_packet<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    max_len: usize,
+) -> Result<Option<(u8, Vec<u8>)>, ProtocolError> {
+    let mut payload = Vec::new();
+    loop {
+        let mut header = [0u8; 4];
+        match reader.read_exact(&mut header).await {
+            Ok(_) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof && payload.is_empty() => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+
+        let len = u32::from_le_bytes([header[0], header[1], header[2], 0]) as usize;
+        if payload.len() + len > max_len {
+            return Err(ProtocolError::Malformed(format!(
+                "packet exceeds the limit of {max_len} bytes"
+            )));
+        }
+
+        let start = payload.len();
+        payload.resize(start + len, 0);
+        reader.read_exact(&mut payload[start..]).await?;
+
+        if len < MAX_PACKET_LEN {
+            return Ok(Some((header[3], payload)));
+        }
+    }
+}
+
+/// Writes a payload starting at sequence id `seq`, returns the sequence id of the next packet.
+pub async fn write_packet<W: AsyncWrite + Unpin>(writer: &mut W, mut seq: u8, payload: &[u8]) -> Result<u8, ProtocolError> {
+    let mut chunks = payload.chunks(MAX_PACKET_LEN).peekable();
+    loop {
+        let chunk = chunks.next().unwrap_or_default();
+        let len = (chunk.len() as u32).to_le_bytes();
+
+        writer.write_all(&[len[0], len[1], len[2], seq]).await?;
+        writer.write_all(chunk).await?;
+        seq = seq.wrapping_add(1);
+
+        // A payload that is a multiple of the maximum ends with an empty packet.
+        if chunks.peek().is_none() && chunk.len() < MAX_PACKET_LEN {
+            return Ok(seq);
+        }
+    }
+}
+
+/// Builds a little endian payload.
+#[derive(Debug, Default)]
+pub struct PacketWriter {
+    buf: Vec<u8>,
+}
+
+impl PacketWriter {
+    pub fn new() -> Self {
+        PacketWriter::default()
+    }
+
+    pub fn put_u8(&mut self, value: u8) -> &mut Self {
+        self.buf.push(value);
+        self
+    }
+
+    pub fn put_u16(&mut self, value: u16) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn put_u32(&mut self, value: u32) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn put_bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    /// Null terminated string.
+    pub fn put_cstr(&mut self, value: &str) -> &mut Self {
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+        self
+    }
+
+    /// Length encoded integer.
+    pub fn put_lenenc(&mut self, value: u64) -> &mut Self {
+        match value {
+            0..0xfb => self.put_u8(value as u8),
+            0xfb..0x1_0000 => self.put_u8(0xfc).put_u16(value as u16),
+            0x1_0000..0x100_0000 => self.put_u8(0xfd).put_bytes(&(value as u32).to_le_bytes()[..3]),
+            _ => self.put_u8(0xfe).put_bytes(&value.to_le_bytes()),
+        }
+    }
+
+    /// Length encoded string.
+    pub fn put_lenenc_bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.put_lenenc(value.len() as u64).put_bytes(value)
+    }
+
+    pub fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf)
+    }
+}
+
+/// Reads the fields of a little endian payload, failing on truncated payloads.
+#[derive(Debug)]
+pub struct PacketReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PacketReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        PacketReader { data, pos: 0 }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub fn get_bytes(&mut self, len: usize) -> Result<&'a [u8], ProtocolError> {
+        if self.remaining() < len {
+            return Err(ProtocolError::Malformed("packet is truncated".to_string()));
+        }
+
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn get_rest(&mut self) -> &'a [u8] {
+        let bytes = &self.data[self.pos..];
+        self.pos = self.data.len();
+        bytes
+    }
+
+    pub fn get_u8(&mut self) -> Result<u8, ProtocolError> {
+        Ok(self.get_bytes(1)?[0])
+    }
+
+    pub fn get_u16(&mut self) -> Result<u16, ProtocolError> {
+        Ok(u16::from_le_bytes(self.get_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn get_u32(&mut self) -> Result<u32, ProtocolError> {
+        Ok(u32::from_le_bytes(self.get_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn get_lenenc(&mut self) -> Result<u64, ProtocolError> {
+        match self.get_u8()? {
+            0xfc => Ok(self.get_u16()? as u64),
+            0xfd => {
+                let bytes = self.get_bytes(3)?;
+                Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]) as u64)
+            }
+            0xfe => Ok(u64::from_le_bytes(self.get_bytes(8)?.try_into().unwrap())),
+            0xfb | 0xff => Err(ProtocolError::Malformed("invalid length encoded integer".to_string())),
+            n => Ok(n as u64),
+        }
+    }
+
+    pub fn get_lenenc_bytes(&mut self) -> Result<&'a [u8], ProtocolError> {
+        let len = self.get_lenenc()? as usize;
+        self.get_bytes(len)
+    }
+
+    /// Null terminated string, the terminator is consumed.
+    pub fn get_cstr(&mut self) -> Result<&'a str, ProtocolError> {
+        let len = self.data[self.pos..]
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| ProtocolError::Malformed("unterminated string in packet".to_string()))?;
+
+        let bytes = self.get_bytes(len + 1)?;
+        std::str::from_utf8(&bytes[..len]).map_err(|_| ProtocolError::Malformed("string is not valid UTF-8".to_string()))
+    }
+}
+
+/// Initial packet of a connection, `Protocol::HandshakeV10`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Handshake {
+    pub server_version: String,
+    pub connection_id: u32,
+    /// 20 bytes the client scrambles its password with.
+    pub auth_data: [u8; 20],
+    pub capabilities: u32,
+    pub status: u16,
+}
+
+impl Handshake {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut packet = PacketWriter::new();
+        packet
+            .put_u8(10)
+            .put_cstr(&self.server_version)
+            .put_u32(self.connection_id)
+            .put_bytes(&self.auth_data[..8])
+            .put_u8(0)
+            .put_u16(self.capabilities as u16)
+            .put_u8(UTF8MB4_CHARSET)
+            .put_u16(self.status)
+            .put_u16((self.capabilities >> 16) as u16)
+            .put_u8(self.auth_data.len() as u8 + 1)
+            .put_bytes(&[0; 10])
+            .put_bytes(&self.auth_data[8..])
+            .put_u8(0)
+            .put_cstr(AUTH_PLUGIN_NAME);
+        packet.finish()
+    }
+}
+
+/// Whether the packet is a `Protocol::SSLRequest`, the start of a handshake response sent alone to switch to TLS.
+/// The full response follows once the connection is encrypted.
+pub fn is_ssl_request(payload: &[u8]) -> bool {
+    payload.len() == 32 && u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]) & CLIENT_SSL != 0
+}
+
+/// `Protocol::HandshakeResponse41`
+#[derive(Clone, Debug, PartialEq)]
+pub struct HandshakeResponse {
+    pub capabilities: u32,
+    pub max_packet_len: u32,
+    pub charset: u8,
+    pub user: String,
+    pub auth_response: Vec<u8>,
+    pub database: Option<String>,
+    pub auth_plugin: Option<String>,
+}
+
+impl HandshakeResponse {
+    pub fn decode(payload: &[u8]) -> Result<Self, ProtocolError> {
+        let mut packet = PacketReader::new(payload);
+        let capabilities = packet.get_u32()?;
+        if capabilities & CLIENT_PROTOCOL_41 == 0 {
+            return Err(ProtocolError::Malformed(
+                "clients older than 4.1 are not supported".to_string(),
+            ));
+        }
+
+        let max_packet_len = packet.get_u32()?;
+        let charset = packet.get_u8()?;
+        packet.get_bytes(23)?;
+        let user = packet.get_cstr()?.to_string();
+
+        let auth_response = if capabilities & CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA != 0 {
+            packet.get_lenenc_bytes()?
+        } else {
+            let len = packet.get_u8()? as usize;
+            packet.get_bytes(len)?
+        }
+        .to_vec();
+
+        let database = match capabilities & CLIENT_CONNECT_WITH_DB != 0 && packet.remaining() > 0 {
+            true => Some(packet.get_cstr()?.to_string()),
+            false => None,
+        };
+        let auth_plugin = match capabilities & CLIENT_PLUGIN_AUTH != 0 && packet.remaining() > 0 {
+            true => Some(packet.get_cstr()?.to_string()),
+            false => None,
+        };
+        // Connection attributes are not used.
+        packet.get_rest();
+
+        Ok(HandshakeResponse {
+            capabilities,
+            max_packet_len,
+            charset,
+            user,
+            auth_response,
+            database,
+            auth_plugin,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut packet = PacketWriter::new();
+        packet
+            .put_u32(self.capabilities)
+            .put_u32(self.max_packet_len)
+            .put_u8(self.charset)
+            .put_bytes(&[0; 23])
+            .put_cstr(&self.user);
+
+        if self.capabilities & CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA != 0 {
+            packet.put_lenenc_bytes(&self.auth_response);
+        } else {
+            packet.put_u8(self.auth_response.len() as u8).put_bytes(&self.auth_response);
+        }
+        if let Some(database) = &self.database {
+            packet.put_cstr(database);
+        }
+        if let Some(plugin) = &self.auth_plugin {
+            packet.put_cstr(plugin);
+        }
+        packet.finish()
+    }
+}
+
+pub fn ok_packet(affected_rows: u64, status: u16, warnings: u16) -> Vec<u8> {
+    PacketWriter::new()
+        .put_u8(0x00)
+        .put_lenenc(affected_rows)
+        .put_lenenc(0)
+        .put_u16(status)
+        .put_u16(warnings)
+        .finish()
+}
+
+pub fn eof_packet(status: u16, warnings: u16) -> Vec<u8> {
+    PacketWriter::new().put_u8(0xfe).put_u16(warnings).put_u16(status).finish()
+}
+
+/// Asks the client to authenticate again with another plugin.
+pub fn auth_switch_request(plugin: &str, data: &[u8]) -> Vec<u8> {
+    PacketWriter::new().put_u8(0xfe).put_cstr(plugin).put_bytes(data).finish()
+}
+
+/// Data of the authentication plugin, in the middle of its exchange.
+pub fn auth_more_data(data: &[u8]) -> Vec<u8> {
+    PacketWriter::new().put_u8(0x01).put_bytes(data).finish()
+}
+
+pub fn err_packet(code: u16, state: SqlState, message: &str) -> Vec<u8> {
+    PacketWriter::new()
+        .put_u8(0xff)
+        .put_u16(code)
+        .put_u8(b'#')
+        .put_bytes(state.as_str().as_bytes())
+        .put_bytes(message.as_bytes())
+        .finish()
+}
+
+/// Type, charset, maximum display length in bytes, flags and decimals of a column of the type.
+fn column_type(data_type: &DataKind) -> (u8, u8, u32, u16, u8) {
+    // Characters of utf8mb4 take up to 4 bytes.
+    let chars = |n: Option<u64>, default: u64| (n.unwrap_or(default) * 4).min(u32::MAX as u64) as u32;
+    let bytes = |n: Option<u64>, default: u64| n.unwrap_or(default).min(u32::MAX as u64) as u32;
+
+    match *data_type {
+        DataKind::Bool => (MYSQL_TYPE_TINY, BINARY_CHARSET, 1, NUM_FLAG, 0),
+        DataKind::TinyInt(_) => (MYSQL_TYPE_TINY, BINARY_CHARSET, 4, NUM_FLAG, 0),
+        DataKind::SmallInt(_) => (MYSQL_TYPE_SHORT, BINARY_CHARSET, 6, NUM_FLAG, 0),
+        DataKind::MediumInt(_) => (MYSQL_TYPE_INT24, BINARY_CHARSET, 9, NUM_FLAG, 0),
+        DataKind::Integer(_) => (MYSQL_TYPE_LONG, BINARY_CHARSET, 11, NUM_FLAG, 0),
+        DataKind::BigInt(_) => (MYSQL_TYPE_LONGLONG, BINARY_CHARSET, 20, NUM_FLAG, 0),
+        // 0x1f decimals means the number of digits is not fixed.
+        DataKind::Float(_, scale) => (MYSQL_TYPE_FLOAT, BINARY_CHARSET, 12, NUM_FLAG, scale.unwrap_or(0x1f)),
+        DataKind::Double(_, scale) => (MYSQL_TYPE_DOUBLE, BINARY_CHARSET, 22, NUM_FLAG, scale.unwrap_or(0x1f)),
+        DataKind::Decimal(precision, scale) => {
+            let len = precision.unwrap_or(10) as u32 + 2;
+            (MYSQL_TYPE_NEWDECIMAL, BINARY_CHARSET, len, NUM_FLAG, scale.unwrap_or(0))
+        }
+        DataKind::Bit(n) => (MYSQL_TYPE_BIT, BINARY_CHARSET, n.unwrap_or(1) as u32, 0, 0),
+        DataKind::Char(n) => (MYSQL_TYPE_STRING, UTF8MB4_CHARSET, chars(n.map(u64::from), 1), 0, 0),
+        DataKind::VarChar(n) => (MYSQL_TYPE_VAR_STRING, UTF8MB4_CHARSET, chars(n.map(u64::from), 65535), 0, 0),
+        DataKind::Binary(n) => (MYSQL_TYPE_STRING, BINARY_CHARSET, bytes(n.map(u64::from), 1), BINARY_FLAG, 0),
+        DataKind::VarBinary(n) => {
+            let len = bytes(n.map(u64::from), 65535);
+            (MYSQL_TYPE_VAR_STRING, BINARY_CHARSET, len, BINARY_FLAG, 0)
+        }
+        DataKind::TinyText => (MYSQL_TYPE_BLOB, UTF8MB4_CHARSET, chars(None, 255), BLOB_FLAG, 0),
+        DataKind::Text(n) => (MYSQL_TYPE_BLOB, UTF8MB4_CHARSET, chars(n.map(u64::from), 65535), BLOB_FLAG, 0),
+        DataKind::MediumText(n) => {
+            let len = chars(n.map(u64::from), 0xff_ffff);
+            (MYSQL_TYPE_BLOB, UTF8MB4_CHARSET, len, BLOB_FLAG, 0)
+        }
+        DataKind::LongText(n) => (MYSQL_TYPE_BLOB, UTF8MB4_CHARSET, chars(n, u32::MAX as u64), BLOB_FLAG, 0),
+        DataKind::TinyBlob => (MYSQL_TYPE_BLOB, BINARY_CHARSET, 255, BLOB_FLAG | BINARY_FLAG, 0),
+        DataKind::Blob(n) => {
+            let len = bytes(n.map(u64::from), 65535);
+            (MYSQL_TYPE_BLOB, BINARY_CHARSET, len, BLOB_FLAG | BINARY_FLAG, 0)
+        }
+        DataKind::MediumBlob(n) => {
+            let len = bytes(n.map(u64::from), 0xff_ffff);
+            (MYSQL_TYPE_BLOB, BINARY_CHARSET, len, BLOB_FLAG | BINARY_FLAG, 0)
+        }
+        DataKind::LongBlob(n) => {
+            let len = bytes(n, u32::MAX as u64);
+            (MYSQL_TYPE_BLOB, BINARY_CHARSET, len, BLOB_FLAG | BINARY_FLAG, 0)
+        }
+    }
+}
+
+/// `Protocol::ColumnDefinition41` of a column not backed by a table.
+pub fn column_definition(column: &Column) -> Vec<u8> {
+    let (column_type, charset, len, flags, decimals) = column_type(&column.data_type);
+    PacketWriter::new()
+        .put_lenenc_bytes(b"def")
+        .put_lenenc_bytes(b"")
+        .put_lenenc_bytes(b"")
+        .put_lenenc_bytes(b"")
+        .put_lenenc_bytes(column.name.as_bytes())
+        .put_lenenc_bytes(column.name.as_bytes())
+        .put_u8(0x0c)
+        .put_u16(charset as u16)
+        .put_u32(len)
+        .put_u8(column_type)
+        .put_u16(flags)
+        .put_u8(decimals)
+        .put_u16(0)
+        .finish()
+}
+
+/// Text protocol form of a value, `None` is NULL.
+pub fn text_value(value: &Value, data_type: &DataKind) -> Option<Vec<u8>> {
+    match (value, data_type) {
+        (Value::Null, _) => None,
+        (Value::Bool(b), _) => Some(vec![if *b { b'1' } else { b'0' }]),
+        // Bit values are sent as big endian bytes, not digits.
+        (Value::Int(n), DataKind::Bit(len)) => {
+            let len = (len.unwrap_or(1) as usize).div_ceil(8);
+            Some(n.to_be_bytes()[8 - len.min(8)..].to_vec())
+        }
+        (Value::Bytes(bytes), _) => Some(bytes.clone()),
+        (value, _) => Some(value.to_string().into_bytes()),
+    }
+}
+
+/// Row of a text result set.
+pub fn text_row(columns: &[Column], row: &[Value]) -> Vec<u8> {
+    let mut packet = PacketWriter::new();
+    for (value, column) in row.iter().zip(columns) {
+        match text_value(value, &column.data_type) {
+            Some(value) => packet.put_lenenc_bytes(&value),
+            None => packet.put_u8(0xfb),
+        };
+    }
+    packet.finish()
+}
+
+/// MySQL error number for a SQLSTATE.
+pub fn error_code(state: SqlState) -> u16 {
+    match state {
+        SqlState::SYNTAX_ERROR => 1064,
+        SqlState::UNDEFINED_TABLE => 1146,
+        SqlState::FEATURE_NOT_SUPPORTED => 1235,
+        SqlState::INVALID_AUTHORIZATION_SPECIFICATION | SqlState::INVALID_PASSWORD => 1045,
+        SqlState::INSUFFICIENT_PRIVILEGE => 1142,
+        SqlState::PROTOCOL_VIOLATION => 1047,
+        SqlState::ADMIN_SHUTDOWN => 1053,
+        SqlState::IDLE_SESSION_TIMEOUT => 4031,
+        SqlState::TOO_MANY_CONNECTIONS => 1040,
+        _ => 1105,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_packet_round_trip() {
+        let mut buf = Vec::new();
+        let next = write_packet(&mut buf, 3, b"\x03COMMIT").await.unwrap();
+        assert_eq!(next, 4);
+        assert_eq!(&buf[..4], &[7, 0, 0, 3]);
+
+        let mut reader = buf.as_slice();
+        assert_eq!(
+            read_packet(&mut reader, 1024).await.unwrap(),
+            Some((3, b"\x03COMMIT".to_vec()))
+        );
+        assert_eq!(read_packet(&mut reader, 1024).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_split_packets() {
+        let payload = vec![7u8; MAX_PACKET_LEN + 10];
+        let mut buf = Vec::new();
+        assert_eq!(write_packet(&mut buf, 0, &payload).await.unwrap(), 2);
+
+        let mut reader = buf.as_slice();
+        let (seq, read) = read_packet(&mut reader, usize::MAX).await.unwrap().unwrap();
+        assert_eq!(seq, 1);
+        assert_eq!(read, payload);
+
+        // Exactly the maximum is followed by an empty packet.
+        let mut buf = Vec::new();
+        assert_eq!(write_packet(&mut buf, 0, &payload[..MAX_PACKET_LEN]).await.unwrap(), 2);
+        assert_eq!(buf.len(), MAX_PACKET_LEN + 8);
+    }
+
+    #[test]
+    fn test_lenenc() {
+        for value in [0, 250, 251, 0xffff, 0x1_0000, 0xff_ffff, 0x100_0000, u64::MAX] {
+            let buf = PacketWriter::new().put_lenenc(value).finish();
+            assert_eq!(PacketReader::new(&buf).get_lenenc().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_handshake_response() {
+        let response = HandshakeResponse {
+            capabilities: CLIENT_PROTOCOL_41
+                | CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA
+                | CLIENT_CONNECT_WITH_DB
+                | CLIENT_PLUGIN_AUTH,
+            max_packet_len: 1 << 24,
+            charset: UTF8MB4_CHARSET,
+            user: "rdb".to_string(),
+            auth_response: vec![1, 2, 3],
+            database: Some("test".to_string()),
+            auth_plugin: Some(AUTH_PLUGIN_NAME.to_string()),
+        };
+        assert_eq!(HandshakeResponse::decode(&response.encode()).unwrap(), response);
+        assert!(!is_ssl_request(&response.encode()));
+
+        let ssl = HandshakeResponse {
+            capabilities: response.capabilities | CLIENT_SSL,
+            ..response.clone()
+        };
+        assert!(is_ssl_request(&ssl.encode()[..32]));
+        assert!(!is_ssl_request(&response.encode()[..32]));
+
+        let old = HandshakeResponse {
+            capabilities: CLIENT_LONG_PASSWORD,
+            ..response
+        };
+        assert!(HandshakeResponse::decode(&old.encode()).is_err());
+    }
+
+    #[test]
+    fn test_column_types() {
+        let definition = column_definition(&Column::new("n", DataKind::BigInt(None)));
+        // Charset, length, type, flags and decimals follow the 0x0c length of the fixed fields.
+        assert_eq!(
+            &definition[definition.len() - 12..][..8],
+            &[63, 0, 20, 0, 0, 0, MYSQL_TYPE_LONGLONG, 0]
+        );
+
+        let definition = column_definition(&Column::new("s", DataKind::VarChar(Some(10))));
+        assert_eq!(
+            &definition[definition.len() - 12..][..7],
+            &[45, 0, 40, 0, 0, 0, MYSQL_TYPE_VAR_STRING]
+        );
+
+        let columns = [
+            Column::new("b", DataKind::Bool),
+            Column::new("bits", DataKind::Bit(Some(12))),
+            Column::new("t", DataKind::Text(None)),
+        ];
+        let row = text_row(&columns, &[Value::Bool(true), Value::Int(0x0102), Value::Null]);
+        assert_eq!(row, [1, b'1', 2, 0x01, 0x02, 0xfb]);
+    }
+}
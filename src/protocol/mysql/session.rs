@@ -0,0 +1,683 @@
+//! Serves one MySQL client connection until it quits.
+
+use crate::auth::crypto::random_bytes;
+use crate::cancel::Registration;
+use crate::config::{AuthMethod, Config};
+use crate::executor::{BatchMode, ExecError, Executor, SessionState, SqlState, TransactionStatus};
+use crate::parser::dialect::MySqlDialect;
+use crate::parser::split::split_statements;
+use crate::protocol::codec::ProtocolError;
+use crate::protocol::mysql::*;
+use crate::server::{Interrupt, Shutdown, read_or_interrupt, startup_within};
+use crate::tls::MaybeTls;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_rustls::TlsAcceptor;
+
+/// Reported to clients, some of them adapt their behaviour to the server version.
+const SERVER_VERSION: &str = "8.0.0-rdb";
+
+pub struct Session<'a, S> {
+    stream: BufWriter<MaybeTls<S>>,
+    executor: &'a Executor,
+    config: &'a Config,
+    /// Offered to clients in the handshake.
+    tls: Option<TlsAcceptor>,
+    shutdown: Shutdown,
+    /// Connection slots, one is taken before the handshake.
+    permits: Option<Arc<Semaphore>>,
+    permit: Option<OwnedSemaphorePermit>,
+    /// Slot among the connections starting up, given back once the handshake is over.
+    startup_permit: Option<OwnedSemaphorePermit>,
+    /// Registered for its connection id, which is unique among running sessions.
+    registration: Registration<'a>,
+    capabilities: u32,
+    /// Sent in the handshake, and again when the client is asked to switch plugins.
+    scramble: [u8; 20],
+    state: SessionState,
+    /// Sequence id of the next packet sent.
+    seq: u8,
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> Session<'a, S> {
+    pub fn new(stream: S, executor: &'a Executor, config: &'a Config) -> Self {
+        let registration = executor.register_session();
+        let state = SessionState::new(registration.id, config.timeouts.statement, registration.flag.clone())
+            .with_lock_timeout(config.timeouts.lock);
+        Session {
+            stream: BufWriter::new(MaybeTls::Plain(stream)),
+            executor,
+            config,
+            tls: None,
+            shutdown: Shutdown::never(),
+            permits: None,
+            permit: None,
+            startup_permit: None,
+            registration,
+            capabilities: 0,
+            scramble: auth_data(),
+            state,
+            seq: 0,
+        }
+    }
+
+    pub fn with_tls(mut self, tls: Option<TlsAcceptor>) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Ends the session between commands once shutdown is requested.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Turns the client away before the handshake when no permit is left.
+    pub fn with_permits(mut self, permits: Arc<Semaphore>) -> Self {
+        self.permits = Some(permits);
+        self
+    }
+
+    pub fn with_startup_permit(mut self, permit: OwnedSemaphorePermit) -> Self {
+        self.startup_permit = Some(permit);
+        self
+    }
+
+    /// Answers commands until the client sends `COM_QUIT` or closes the connection.
+    pub async fn run(mut self) -> Result<(), ProtocolError> {
+        let timeout = self.config.timeouts.authentication;
+        let started = startup_within(self.handshake(), timeout).await;
+        self.startup_permit = None;
+        if !started? {
+            return Ok(());
+        }
+
+        loop {
+            let read = read_packet(self.stream.get_mut(), self.config.memory.max_message_size);
+            let packet = match read_or_interrupt(read, &mut self.shutdown, self.config.timeouts.idle_session).await {
+                Ok(packet) => packet?,
+                Err(interrupt) => {
+                    let err = match interrupt {
+                        Interrupt::Shutdown => {
+                            ExecError::new(SqlState::ADMIN_SHUTDOWN, "Server shutdown in progress".to_string())
+                        }
+                        Interrupt::IdleTimeout => ExecError::new(
+                            SqlState::IDLE_SESSION_TIMEOUT,
+                            "The client was disconnected by the server because of inactivity.".to_string(),
+                        ),
+                    };
+                    // Not an answer to a command, so it starts a new sequence.
+                    self.seq = 0;
+                    self.send_error(&err).await?;
+                    self.stream.flush().await?;
+                    return Ok(());
+                }
+            };
+            let Some((seq, payload)) = packet else {
+                return Ok(());
+            };
+            self.seq = seq.wrapping_add(1);
+
+            let Some((&command, body)) = payload.split_first() else {
+                return Err(ProtocolError::Malformed("empty command packet".to_string()));
+            };
+
+            match command {
+                COM_QUIT => return Ok(()),
+                // There is a single database, any of them is fine.
+                COM_INIT_DB | COM_PING => self.send(&ok_packet(0, self.status(), 0)).await?,
+                COM_QUERY => self.query(body).await?,
+                _ => {
+                    let err = ExecError::new(SqlState::PROTOCOL_VIOLATION, format!("unknown command: {command:#04x}"));
+                    self.send_error(&err).await?
+                }
+            }
+            self.stream.flush().await?;
+        }
+    }
+
+    /// Runs the connection phase, `false` when the client went away before completing it.
+    async fn handshake(&mut self) -> Result<bool, ProtocolError> {
+        if let Some(permits) = &self.permits {
+            match permits.clone().try_acquire_owned() {
+                Ok(permit) => self.permit = Some(permit),
+                Err(_) => {
+                    let err = ProtocolError::Malformed("Too many connections".to_string());
+                    return Err(self.handshake_failed(SqlState::TOO_MANY_CONNECTIONS, err).await);
+                }
+            }
+        }
+
+        let capabilities = match self.tls {
+            Some(_) => SERVER_CAPABILITIES | CLIENT_SSL,
+            None => SERVER_CAPABILITIES,
+        };
+        let handshake = Handshake {
+            server_version: SERVER_VERSION.to_string(),
+            connection_id: self.registration.id,
+            auth_data: self.scramble,
+            capabilities,
+            status: self.status(),
+        };
+        self.send(&handshake.encode()).await?;
+        self.stream.flush().await?;
+
+        let Some(mut payload) = self.read_handshake_packet().await? else {
+            return Ok(false);
+        };
+        if is_ssl_request(&payload) {
+            let Some(acceptor) = &self.tls else {
+                let err = ProtocolError::Malformed("TLS was requested but not offered".to_string());
+                return Err(self.handshake_failed(SqlState::PROTOCOL_VIOLATION, err).await);
+            };
+            let upgrade = self.stream.get_mut().upgrade(acceptor);
+            match read_or_interrupt(upgrade, &mut self.shutdown, self.config.timeouts.idle_session).await {
+                Ok(result) => result?,
+                Err(_) => return Ok(false),
+            }
+            // The full response follows encrypted.
+            let Some(response) = self.read_handshake_packet().await? else {
+                return Ok(false);
+            };
+            payload = response;
+        }
+
+        if self.config.tls.required && !self.stream.get_ref().is_tls() {
+            let err = ProtocolError::Malformed(
+                "Connections using insecure transport are prohibited while TLS is required.".to_string(),
+            );
+            return Err(self
+                .handshake_failed(SqlState::INVALID_AUTHORIZATION_SPECIFICATION, err)
+                .await);
+        }
+
+        let response = match HandshakeResponse::decode(&payload) {
+            Ok(response) => response,
+            Err(err) => return Err(self.handshake_failed(SqlState::PROTOCOL_VIOLATION, err).await),
+        };
+
+        self.capabilities = response.capabilities & capabilities;
+        if let Err(err) = self.authenticate(&response).await {
+            return match err {
+                Some(err) => {
+                    self.send_error(&err).await?;
+                    self.stream.flush().await?;
+                    Err(ProtocolError::Malformed(err.message))
+                }
+                None => Ok(false),
+            };
+        }
+        self.state.user = response.user;
+
+        self.send(&ok_packet(0, self.status(), 0)).await?;
+        self.stream.flush().await?;
+        Ok(true)
+    }
+
+    /// Payload of the next packet of the connection phase, `None` when the client went away.
+    async fn read_handshake_packet(&mut self) -> Result<Option<Vec<u8>>, ProtocolError> {
+        let read = read_packet(self.stream.get_mut(), self.config.memory.max_message_size);
+        let packet = match read_or_interrupt(read, &mut self.shutdown, self.config.timeouts.idle_session).await {
+            Ok(packet) => packet?,
+            Err(_) => None,
+        };
+        Ok(packet.map(|(seq, payload)| {
+            self.seq = seq.wrapping_add(1);
+            payload
+        }))
+    }
+
+    /// Reports an error that ends the connection phase, and returns it to close the connection with.
+    async fn handshake_failed(&mut self, state: SqlState, err: ProtocolError) -> ProtocolError {
+        // The client may already be gone, the original error is the interesting one.
+        if self
+            .send(&err_packet(error_code(state), state, &err.to_string()))
+            .await
+            .is_ok()
+        {
+            let _ = self.stream.flush().await;
+        }
+        err
+    }
+
+    /// Checks that the user may log in and knows its password, which the client is asked to send in clear text on
+    /// the full path of `caching_sha2_password`. Passwords are only taken over TLS. Fails with `None` when the client
+    /// went away in the middle.
+    async fn authenticate(&mut self, response: &HandshakeResponse) -> Result<(), Option<ExecError>> {
+        let user = &response.user;
+        let role = self.executor.catalog().role(user);
+
+        if self.config.auth.method == AuthMethod::ScramSha256 {
+            if !self.stream.get_ref().is_tls() {
+                return Err(Some(ExecError::new(
+                    SqlState::INVALID_AUTHORIZATION_SPECIFICATION,
+                    format!("Access denied for user '{user}': passwords are only accepted over TLS"),
+                )));
+            }
+            let password = match response.auth_plugin.as_deref() {
+                Some(CLEAR_PASSWORD_PLUGIN) => response.auth_response.clone(),
+                plugin => {
+                    if plugin != Some(AUTH_PLUGIN_NAME) {
+                        // What the client answers is scrambled, and of no use without a hash of the password.
+                        let scramble = [&self.scramble[..], &[0]].concat();
+                        self.send(&auth_switch_request(AUTH_PLUGIN_NAME, &scramble))
+                            .await
+                            .map_err(|_| None)?;
+                        self.read_auth_packet().await?;
+                    }
+                    self.send(&auth_more_data(&[PERFORM_FULL_AUTHENTICATION]))
+                        .await
+                        .map_err(|_| None)?;
+                    self.read_auth_packet().await?
+                }
+            };
+            let password = password.strip_suffix(&[0]).unwrap_or(&password);
+
+            let valid = role
+                .as_ref()
+                .and_then(|role| role.password.as_ref())
+                .zip(std::str::from_utf8(password).ok())
+                .is_some_and(|(secret, password)| secret.verify(password));
+            if !valid {
+                return Err(Some(ExecError::new(
+                    SqlState::INVALID_PASSWORD,
+                    format!("Access denied for user '{user}'"),
+                )));
+            }
+        }
+
+        match role {
+            Some(role) if role.login => Ok(()),
+            _ => Err(Some(ExecError::new(
+                SqlState::INVALID_AUTHORIZATION_SPECIFICATION,
+                format!("Access denied for user '{user}'"),
+            ))),
+        }
+    }
+
+    /// Flushes what was sent and reads the answer of the client, `None` when it went away.
+    async fn read_auth_packet(&mut self) -> Result<Vec<u8>, Option<ExecError>> {
+        self.stream.flush().await.map_err(|_| None)?;
+        let read = read_packet(self.stream.get_mut(), self.config.memory.max_message_size);
+        let packet = match read_or_interrupt(read, &mut self.shutdown, self.config.timeouts.idle_session).await {
+            Ok(Ok(packet)) => packet,
+            _ => None,
+        };
+        let (seq, payload) = packet.ok_or(None)?;
+        self.seq = seq.wrapping_add(1);
+        Ok(payload)
+    }
+
+    async fn query(&mut self, sql: &[u8]) -> Result<(), ProtocolError> {
+        let count = split_statements(sql, &MySqlDialect).len();
+        if count == 0 {
+            let err = ExecError::new(SqlState::SYNTAX_ERROR, "query was empty".to_string());
+            return self.send_error(&err).await;
+        }
+        if count > 1 && self.capabilities & CLIENT_MULTI_STATEMENTS == 0 {
+            let err = ExecError::new(
+                SqlState::SYNTAX_ERROR,
+                "multiple statements require CLIENT_MULTI_STATEMENTS".to_string(),
+            );
+            return self.send_error(&err).await;
+        }
+
+        let results = self
+            .executor
+            .execute_batch_with_dialect(sql, &MySqlDialect, BatchMode::StopOnError, &mut self.state);
+
+        let count = results.len();
+        for (i, stmt) in results.into_iter().enumerate() {
+            let status = status(stmt.txn) | if i + 1 < count { SERVER_MORE_RESULTS_EXISTS } else { 0 };
+            match stmt.result {
+                Ok(output) if !output.returns_rows() => {
+                    self.send(&ok_packet(output.affected_rows, status, output.notices.len() as u16))
+                        .await?
+                }
+                Ok(output) => {
+                    self.send(&PacketWriter::new().put_lenenc(output.columns.len() as u64).finish())
+                        .await?;
+                    for column in &output.columns {
+                        self.send(&column_definition(column)).await?;
+                    }
+                    self.send(&eof_packet(status & !SERVER_MORE_RESULTS_EXISTS, 0)).await?;
+
+                    for row in &output.rows {
+                        self.send(&text_row(&output.columns, row)).await?;
+                    }
+                    self.send(&eof_packet(status, output.notices.len() as u16)).await?;
+                }
+                // Stop on error, so an error is always the last result.
+                Err(err) => self.send_error(&err).await?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn status(&self) -> u16 {
+        status(self.state.txn)
+    }
+
+    async fn send(&mut self, payload: &[u8]) -> Result<(), ProtocolError> {
+        self.seq = write_packet(&mut self.stream, self.seq, payload).await?;
+        Ok(())
+    }
+
+    async fn send_error(&mut self, err: &ExecError) -> Result<(), ProtocolError> {
+        self.send(&err_packet(error_code(err.code), err.code, &err.message)).await
+    }
+}
+
+/// Whatever the session was in the middle of is rolled back once its client is gone.
+impl<S> Drop for Session<'_, S> {
+    fn drop(&mut self) {
+        self.executor.end_session(&mut self.state);
+    }
+}
+
+fn status(txn: TransactionStatus) -> u16 {
+    match txn {
+        TransactionStatus::Idle => SERVER_STATUS_AUTOCOMMIT,
+        TransactionStatus::InTransaction | TransactionStatus::Failed => SERVER_STATUS_IN_TRANS,
+    }
+}
+
+/// Scramble for the handshake. It only has to differ between connections, nothing is authenticated with it yet.
+fn auth_data() -> [u8; 20] {
+    // The scramble is sent null terminated, it must not contain zeros.
+    random_bytes::<20>().map(|b| b.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::catalog::Catalog;
+    use crate::protocol::codec::MAX_FRAME_LEN;
+    use crate::stats::StatementStats;
+    use std::sync::Arc;
+    use tokio::io::DuplexStream;
+    use tokio::task::JoinHandle;
+
+    /// Minimal client side of the protocol.
+    struct TestClient {
+        stream: DuplexStream,
+        server: JoinHandle<Result<(), ProtocolError>>,
+    }
+
+    impl TestClient {
+        async fn connect(capabilities: u32) -> Self {
+            let executor = Executor::new(Arc::new(StatementStats::new()), Catalog::bootstrap("rdb", None));
+            let mut config = Config::default();
+            config.auth.method = AuthMethod::Trust;
+            let (mut stream, server) = tokio::io::duplex(1024);
+            let server = tokio::spawn(async move { Session::new(server, &executor, &config).run().await });
+
+            let (seq, handshake) = read_packet(&mut stream, MAX_FRAME_LEN).await.unwrap().unwrap();
+            assert_eq!(seq, 0);
+            assert_eq!(handshake[0], 10);
+
+            let response = HandshakeResponse {
+                capabilities: CLIENT_PROTOCOL_41 | CLIENT_SECURE_CONNECTION | CLIENT_PLUGIN_AUTH | capabilities,
+                max_packet_len: 1 << 24,
+                charset: UTF8MB4_CHARSET,
+                user: "rdb".to_string(),
+                auth_response: vec![0; 20],
+                database: None,
+                auth_plugin: Some(AUTH_PLUGIN_NAME.to_string()),
+            };
+            write_packet(&mut stream, 1, &response.encode()).await.unwrap();
+
+            let mut client = TestClient { stream, server };
+            let (seq, ok) = client.receive().await;
+            assert_eq!((seq, ok[0]), (2, 0x00));
+            client
+        }
+
+        async fn receive(&mut self) -> (u8, Vec<u8>) {
+            read_packet(&mut self.stream, MAX_FRAME_LEN).await.unwrap().unwrap()
+        }
+
+        async fn command(&mut self, command: u8, body: &[u8]) {
+            let payload = [&[command], body].concat();
+            write_packet(&mut self.stream, 0, &payload).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_authentication() {
+        let (tls, certificate) = crate::tls::testing::config();
+        let config = Config {
+            tls: tls.clone(),
+            ..Config::default()
+        };
+        let response = |plugin: &str| HandshakeResponse {
+            capabilities: CLIENT_PROTOCOL_41 | CLIENT_SECURE_CONNECTION | CLIENT_PLUGIN_AUTH | CLIENT_SSL,
+            max_packet_len: 1 << 24,
+            charset: UTF8MB4_CHARSET,
+            user: "rdb".to_string(),
+            auth_response: vec![0; 32],
+            database: None,
+            auth_plugin: Some(plugin.to_string()),
+        };
+
+        // Without TLS the password is not even asked for.
+        let executor = Executor::new(Arc::new(StatementStats::new()), Catalog::bootstrap("rdb", Some("secret")));
+        let (mut stream, server) = tokio::io::duplex(1024);
+        let session = tokio::spawn({
+            let config = config.clone();
+            async move { Session::new(server, &executor, &config).run().await }
+        });
+        read_packet(&mut stream, MAX_FRAME_LEN).await.unwrap().unwrap();
+        write_packet(&mut stream, 1, &response(CLEAR_PASSWORD_PLUGIN).encode())
+            .await
+            .unwrap();
+        let (seq, reply) = read_packet(&mut stream, MAX_FRAME_LEN).await.unwrap().unwrap();
+        assert_eq!((seq, &reply[..3]), (2, &[0xff, 0x15, 0x04][..]));
+        assert!(session.await.unwrap().is_err());
+
+        for (plugin, password, expected) in [
+            (AUTH_PLUGIN_NAME, "secret", 0x00),
+            (AUTH_PLUGIN_NAME, "wrong", 0xff),
+            ("mysql_native_password", "secret", 0x00),
+        ] {
+            let executor = Executor::new(Arc::new(StatementStats::new()), Catalog::bootstrap("rdb", Some("secret")));
+            let acceptor = crate::tls::acceptor(&config.tls).unwrap();
+            let (mut stream, server) = tokio::io::duplex(4096);
+            let session = tokio::spawn({
+                let config = config.clone();
+                async move { Session::new(server, &executor, &config).with_tls(acceptor).run().await }
+            });
+            let (_, handshake) = read_packet(&mut stream, MAX_FRAME_LEN).await.unwrap().unwrap();
+            let mut reader = PacketReader::new(&handshake);
+            reader.get_u8().unwrap();
+            reader.get_cstr().unwrap();
+            reader.get_u32().unwrap();
+            let mut scramble = reader.get_bytes(8).unwrap().to_vec();
+            // A filler, the capabilities, charset and status, and a length and 10 reserved bytes.
+            reader.get_bytes(19).unwrap();
+            scramble.extend_from_slice(reader.get_bytes(12).unwrap());
+            let response = response(plugin);
+            write_packet(&mut stream, 1, &response.encode()[..32]).await.unwrap();
+            let mut stream = crate::tls::testing::connect(stream, &certificate).await.unwrap();
+            write_packet(&mut stream, 2, &response.encode()).await.unwrap();
+            stream.flush().await.unwrap();
+
+            // Other plugins are switched to the one offered, which then asks for the password.
+            let mut seq = 3;
+            if plugin != AUTH_PLUGIN_NAME {
+                let switch = read_packet(&mut stream, MAX_FRAME_LEN).await.unwrap().unwrap();
+                let expected = auth_switch_request(AUTH_PLUGIN_NAME, &[&scramble[..], &[0]].concat());
+                assert_eq!(switch, (seq, expected));
+                write_packet(&mut stream, seq + 1, &[0; 32]).await.unwrap();
+                stream.flush().await.unwrap();
+                seq += 2;
+            }
+            let more = read_packet(&mut stream, MAX_FRAME_LEN).await.unwrap().unwrap();
+            assert_eq!(more, (seq, auth_more_data(&[PERFORM_FULL_AUTHENTICATION])));
+
+            write_packet(&mut stream, seq + 1, &[password.as_bytes(), &[0]].concat())
+                .await
+                .unwrap();
+            stream.flush().await.unwrap();
+            let (seq, reply) = read_packet(&mut stream, MAX_FRAME_LEN).await.unwrap().unwrap();
+            assert_eq!((seq, reply[0]), (more.0 + 2, expected));
+            if expected == 0xff {
+                assert_eq!(&reply[1..3], &1045u16.to_le_bytes());
+                assert!(session.await.unwrap().is_err());
+            }
+        }
+
+        std::fs::remove_file(tls.certificate.unwrap()).unwrap();
+        std::fs::remove_file(tls.key.unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ping_and_quit() {
+        let mut client = TestClient::connect(0).await;
+
+        client.command(COM_PING, b"").await;
+        let (seq, ok) = client.receive().await;
+        assert_eq!(seq, 1);
+        assert_eq!(ok, ok_packet(0, SERVER_STATUS_AUTOCOMMIT, 0));
+
+        client.command(COM_QUIT, b"").await;
+        assert!(client.server.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_result_set() {
+        let mut client = TestClient::connect(0).await;
+
+        client.command(COM_QUERY, b"COMMIT").await;
+        client.receive().await;
+
+        client.command(COM_QUERY, b"SELECT * FROM rdb_stat_statements").await;
+        assert_eq!(client.receive().await, (1, vec![5]));
+        for seq in 2..7 {
+            let (s, column) = client.receive().await;
+            assert_eq!(s, seq);
+            assert_eq!(&column[..4], b"\x03def");
+        }
+        assert_eq!(client.receive().await.1, eof_packet(SERVER_STATUS_AUTOCOMMIT, 0));
+
+        let (_, row) = client.receive().await;
+        let mut reader = PacketReader::new(&row);
+        reader.get_lenenc_bytes().unwrap();
+        assert_eq!(reader.get_lenenc_bytes().unwrap(), b"COMMIT");
+        assert_eq!(client.receive().await, (9, eof_packet(SERVER_STATUS_AUTOCOMMIT, 0)));
+    }
+
+    #[tokio::test]
+    async fn test_errors() {
+        let mut client = TestClient::connect(0).await;
+
+        client.command(COM_QUERY, b"SELECT * FROM cats").await;
+        let (_, err) = client.receive().await;
+        assert_eq!(
+            err,
+            err_packet(1146, SqlState::UNDEFINED_TABLE, "table \"cats\" does not exist")
+        );
+
+        client.command(COM_QUERY, b"COMMIT; COMMIT").await;
+        let (_, err) = client.receive().await;
+        assert_eq!(&err[..3], &[0xff, 0x28, 0x04]);
+
+        client.command(0x1f, b"").await;
+        let (_, err) = client.receive().await;
+        assert_eq!(err[0], 0xff);
+    }
+
+    #[tokio::test]
+    async fn test_too_many_connections() {
+        let executor = Executor::new(Arc::new(StatementStats::new()), Catalog::bootstrap("rdb", None));
+        let config = Config::default();
+        let (mut stream, server) = tokio::io::duplex(1024);
+        let permits = Arc::new(Semaphore::new(0));
+        let server = tokio::spawn(async move { Session::new(server, &executor, &config).with_permits(permits).run().await });
+
+        // Turned away instead of the handshake.
+        let (seq, err) = read_packet(&mut stream, MAX_FRAME_LEN).await.unwrap().unwrap();
+        assert_eq!(seq, 0);
+        assert_eq!(err, err_packet(1040, SqlState::TOO_MANY_CONNECTIONS, "Too many connections"));
+        assert!(server.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_multi_statements() {
+        let mut client = TestClient::connect(CLIENT_MULTI_STATEMENTS).await;
+
+        client.command(COM_QUERY, b"BEGIN; COMMIT; SELECT 1 FROM cats").await;
+        assert_eq!(
+            client.receive().await.1,
+            ok_packet(0, SERVER_STATUS_IN_TRANS | SERVER_MORE_RESULTS_EXISTS, 0)
+        );
+        assert_eq!(
+            client.receive().await.1,
+            ok_packet(0, SERVER_STATUS_AUTOCOMMIT | SERVER_MORE_RESULTS_EXISTS, 0)
+        );
+        assert_eq!(client.receive().await.1[0], 0xff);
+    }
+
+    #[tokio::test]
+    async fn test_tls() {
+        let (tls, certificate) = crate::tls::testing::config();
+        let executor = Executor::new(Arc::new(StatementStats::new()), Catalog::bootstrap("rdb", None));
+        let mut config = Config::default();
+        config.auth.method = AuthMethod::Trust;
+        config.tls = tls.clone();
+        config.tls.required = true;
+        let acceptor = crate::tls::acceptor(&config.tls).unwrap();
+
+        let response = HandshakeResponse {
+            capabilities: CLIENT_PROTOCOL_41 | CLIENT_SECURE_CONNECTION | CLIENT_PLUGIN_AUTH | CLIENT_SSL,
+            max_packet_len: 1 << 24,
+            charset: UTF8MB4_CHARSET,
+            user: "rdb".to_string(),
+            auth_response: vec![0; 20],
+            database: None,
+            auth_plugin: Some(AUTH_PLUGIN_NAME.to_string()),
+        };
+
+        let (mut stream, server) = tokio::io::duplex(4096);
+        let session = tokio::spawn(async move {
+            let result = Session::new(server, &executor, &config).with_tls(acceptor).run().await;
+            (result, executor, config)
+        });
+
+        let (_, handshake) = read_packet(&mut stream, MAX_FRAME_LEN).await.unwrap().unwrap();
+        let mut reader = PacketReader::new(&handshake);
+        reader.get_u8().unwrap();
+        reader.get_cstr().unwrap();
+        // Connection id, the first part of the auth data and a filler precede the capabilities.
+        reader.get_bytes(13).unwrap();
+        assert_ne!(reader.get_u16().unwrap() as u32 & CLIENT_SSL, 0);
+
+        write_packet(&mut stream, 1, &response.encode()[..32]).await.unwrap();
+        let mut stream = crate::tls::testing::connect(stream, &certificate).await.unwrap();
+        write_packet(&mut stream, 2, &response.encode()).await.unwrap();
+        stream.flush().await.unwrap();
+        let (seq, ok) = read_packet(&mut stream, MAX_FRAME_LEN).await.unwrap().unwrap();
+        assert_eq!((seq, ok[0]), (3, 0x00));
+
+        write_packet(&mut stream, 0, &[COM_QUIT]).await.unwrap();
+        stream.flush().await.unwrap();
+        let (result, executor, config) = session.await.unwrap();
+        assert!(result.is_ok());
+
+        // Plain text is turned away.
+        let (mut stream, server) = tokio::io::duplex(4096);
+        let acceptor = crate::tls::acceptor(&config.tls).unwrap();
+        let session = tokio::spawn(async move { Session::new(server, &executor, &config).with_tls(acceptor).run().await });
+        read_packet(&mut stream, MAX_FRAME_LEN).await.unwrap().unwrap();
+        write_packet(&mut stream, 1, &response.encode()).await.unwrap();
+        let (_, err) = read_packet(&mut stream, MAX_FRAME_LEN).await.unwrap().unwrap();
+        assert_eq!(&err[..3], &[0xff, 0x15, 0x04]);
+        assert!(session.await.unwrap().is_err());
+
+        std::fs::remove_file(tls.certificate.unwrap()).unwrap();
+        std::fs::remove_file(tls.key.unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,798 @@
+//! PostgreSQL frontend/backend protocol, version 3.
+//!
+//! A connection starts with a [`StartupMessage`], after which every message is a typed frame. Each simple query is
+//! answered with the results of its statements followed by [`BackendMessage::ReadyForQuery`].
+
+pub mod session;
+pub mod types;
+
+use crate::executor::{SqlState, TransactionStatus};
+use crate::protocol::codec::{BodyReader, BodyWriter, Frame, ProtocolError};
+
+pub const PROTOCOL_VERSION: u32 = 3 << 16;
+const SSL_REQUEST_CODE: u32 = 80877103;
+const GSSENC_REQUEST_CODE: u32 = 80877104;
+const CANCEL_REQUEST_CODE: u32 = 80877102;
+
+// Type OIDs.
+pub const BOOL_OID: u32 = 16;
+pub const BYTEA_OID: u32 = 17;
+pub const INT8_OID: u32 = 20;
+pub const INT2_OID: u32 = 21;
+pub const INT4_OID: u32 = 23;
+pub const TEXT_OID: u32 = 25;
+pub const FLOAT4_OID: u32 = 700;
+pub const FLOAT8_OID: u32 = 701;
+pub const UNKNOWN_OID: u32 = 705;
+pub const BPCHAR_OID: u32 = 1042;
+pub const VARCHAR_OID: u32 = 1043;
+pub const BIT_OID: u32 = 1560;
+pub const NUMERIC_OID: u32 = 1700;
+
+/// First message of a connection.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StartupMessage {
+    Startup { version: u32, params: Vec<(String, String)> },
+    SslRequest,
+    GssEncRequest,
+    Cancel { process_id: u32, secret_key: u32 },
+}
+
+impl StartupMessage {
+    pub fn decode(data: &[u8]) -> Result<Self, ProtocolError> {
+        let mut body = BodyReader::new(data);
+        let msg = match body.get_u32()? {
+            SSL_REQUEST_CODE => StartupMessage::SslRequest,
+            GSSENC_REQUEST_CODE => StartupMessage::GssEncRequest,
+            CANCEL_REQUEST_CODE => StartupMessage::Cancel {
+                process_id: body.get_u32()?,
+                secret_key: body.get_u32()?,
+            },
+            version => {
+                let mut params = Vec::new();
+                loop {
+                    let name = body.get_cstr()?;
+                    if name.is_empty() {
+                        break;
+                    }
+                    params.push((name.to_string(), body.get_cstr()?.to_string()));
+                }
+                StartupMessage::Startup { version, params }
+            }
+        };
+
+        if body.remaining() > 0 {
+            return Err(ProtocolError::Malformed("trailing bytes in startup message".to_string()));
+        }
+
+        Ok(msg)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = BodyWriter::new();
+        match self {
+            StartupMessage::Startup { version, params } => {
+                body.put_u32(*version);
+                for (name, value) in params {
+                    body.put_cstr(name).put_cstr(value);
+                }
+                body.put_u8(0);
+            }
+            StartupMessage::SslRequest => {
+                body.put_u32(SSL_REQUEST_CODE);
+            }
+            StartupMessage::GssEncRequest => {
+                body.put_u32(GSSENC_REQUEST_CODE);
+            }
+            StartupMessage::Cancel { process_id, secret_key } => {
+                body.put_u32(CANCEL_REQUEST_CODE).put_u32(*process_id).put_u32(*secret_key);
+            }
+        }
+        body.finish()
+    }
+}
+
+/// What a `Describe` or `Close` message refers to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    Statement,
+    Portal,
+}
+
+impl Target {
+    fn decode(body: &mut BodyReader) -> Result<Self, ProtocolError> {
+        match body.get_u8()? {
+            b'S' => Ok(Target::Statement),
+            b'P' => Ok(Target::Portal),
+            kind => Err(ProtocolError::Malformed(format!("invalid target type: {0:?}", kind as char))),
+        }
+    }
+
+    fn encode(&self) -> u8 {
+        match self {
+            Target::Statement => b'S',
+            Target::Portal => b'P',
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrontendMessage {
+    /// `Q`, a batch of one or more statements.
+    Query(String),
+    /// `P`, prepares a statement, an empty name is the unnamed statement. Parameter types may be left out or 0.
+    Parse {
+        name: String,
+        query: String,
+        param_types: Vec<u32>,
+    },
+    /// `B`, binds parameters to a prepared statement, creating a portal.
+    Bind {
+        portal: String,
+        statement: String,
+        /// 0 for text and 1 for binary, either one code for every parameter or one per parameter.
+        param_formats: Vec<i16>,
+        params: Vec<Option<Vec<u8>>>,
+        result_formats: Vec<i16>,
+    },
+    /// `D`
+    Describe { target: Target, name: String },
+    /// `E`, runs a portal, returning at most `max_rows` rows when it is not 0.
+    Execute { portal: String, max_rows: u32 },
+    /// `C`
+    Close { target: Target, name: String },
+    /// `S`, ends an extended query cycle.
+    Sync,
+    /// `H`, asks for pending output to be sent.
+    Flush,
+    /// `X`, the client is closing the connection.
+    Terminate,
+    /// `p`, a password or a SASL message, which one depends on what the server asked for.
+    Password(Vec<u8>),
+}
+
+impl FrontendMessage {
+    pub fn decode(frame: &Frame) -> Result<Self, ProtocolError> {
+        let mut body = BodyReader::new(&frame.body);
+        let msg = match frame.tag {
+            b'Q' => FrontendMessage::Query(body.get_cstr()?.to_string()),
+            b'P' => {
+                let name = body.get_cstr()?.to_string();
+                let query = body.get_cstr()?.to_string();
+                let count = body.get_u16()?;
+                let param_types = (0..count).map(|_| body.get_u32()).collect::<Result<_, _>>()?;
+                FrontendMessage::Parse {
+                    name,
+                    query,
+                    param_types,
+                }
+            }
+            b'B' => {
+                let portal = body.get_cstr()?.to_string();
+                let statement = body.get_cstr()?.to_string();
+                let param_formats = decode_formats(&mut body)?;
+                let count = body.get_u16()?;
+                let mut params = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    params.push(match body.get_i32()? {
+                        -1 => None,
+                        len if len < 0 => {
+                            return Err(ProtocolError::Malformed(format!("invalid parameter length: {len}")));
+                        }
+                        len => Some(body.get_bytes(len as usize)?.to_vec()),
+                    });
+                }
+                let result_formats = decode_formats(&mut body)?;
+                FrontendMessage::Bind {
+                    portal,
+                    statement,
+                    param_formats,
+                    params,
+                    result_formats,
+                }
+            }
+            b'D' => FrontendMessage::Describe {
+                target: Target::decode(&mut body)?,
+                name: body.get_cstr()?.to_string(),
+            },
+            b'E' => FrontendMessage::Execute {
+                portal: body.get_cstr()?.to_string(),
+                max_rows: body.get_u32()?,
+            },
+            b'C' => FrontendMessage::Close {
+                target: Target::decode(&mut body)?,
+                name: body.get_cstr()?.to_string(),
+            },
+            b'S' => FrontendMessage::Sync,
+            b'H' => FrontendMessage::Flush,
+            b'X' => FrontendMessage::Terminate,
+            b'p' => FrontendMessage::Password(body.get_bytes(body.remaining())?.to_vec()),
+            tag => return Err(ProtocolError::Malformed(format!("unknown message type: {0:?}", tag as char))),
+        };
+
+        if body.remaining() > 0 {
+            return Err(ProtocolError::Malformed(format!(
+                "trailing bytes in {0:?} message",
+                frame.tag as char
+            )));
+        }
+
+        Ok(msg)
+    }
+
+    pub fn encode(&self) -> Frame {
+        let mut body = BodyWriter::new();
+        let tag = match self {
+            FrontendMessage::Query(sql) => {
+                body.put_cstr(sql);
+                b'Q'
+            }
+            FrontendMessage::Parse {
+                name,
+                query,
+                param_types,
+            } => {
+                body.put_cstr(name).put_cstr(query).put_u16(param_types.len() as u16);
+                for oid in param_types {
+                    body.put_u32(*oid);
+                }
+                b'P'
+            }
+            FrontendMessage::Bind {
+                portal,
+                statement,
+                param_formats,
+                params,
+                result_formats,
+            } => {
+                body.put_cstr(portal).put_cstr(statement);
+                encode_formats(&mut body, param_formats);
+                body.put_u16(params.len() as u16);
+                for param in params {
+                    match param {
+                        Some(value) => body.put_i32(value.len() as i32).put_bytes(value),
+                        None => body.put_i32(-1),
+                    };
+                }
+                encode_formats(&mut body, result_formats);
+                b'B'
+            }
+            FrontendMessage::Describe { target, name } => {
+                body.put_u8(target.encode()).put_cstr(name);
+                b'D'
+            }
+            FrontendMessage::Execute { portal, max_rows } => {
+                body.put_cstr(portal).put_u32(*max_rows);
+                b'E'
+            }
+            FrontendMessage::Close { target, name } => {
+                body.put_u8(target.encode()).put_cstr(name);
+                b'C'
+            }
+            FrontendMessage::Sync => b'S',
+            FrontendMessage::Flush => b'H',
+            FrontendMessage::Terminate => b'X',
+            FrontendMessage::Password(data) => {
+                body.put_bytes(data);
+                b'p'
+            }
+        };
+
+        Frame {
+            tag,
+            body: body.finish(),
+        }
+    }
+}
+
+/// First message of a SASL exchange, the body of a [`FrontendMessage::Password`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SaslInitialResponse {
+    pub mechanism: String,
+    /// `None` when the client sent no data with the mechanism.
+    pub data: Option<Vec<u8>>,
+}
+
+impl SaslInitialResponse {
+    pub fn decode(data: &[u8]) -> Result<Self, ProtocolError> {
+        let mut body = BodyReader::new(data);
+        let mechanism = body.get_cstr()?.to_string();
+        let data = match body.get_i32()? {
+            -1 => None,
+            len if len < 0 => return Err(ProtocolError::Malformed(format!("invalid SASL data length: {len}"))),
+            len => Some(body.get_bytes(len as usize)?.to_vec()),
+        };
+        if body.remaining() > 0 {
+            return Err(ProtocolError::Malformed("trailing bytes in SASL response".to_string()));
+        }
+        Ok(SaslInitialResponse { mechanism, data })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = BodyWriter::new();
+        body.put_cstr(&self.mechanism);
+        match &self.data {
+            Some(data) => body.put_i32(data.len() as i32).put_bytes(data),
+            None => body.put_i32(-1),
+        };
+        body.finish()
+    }
+}
+
+fn decode_formats(body: &mut BodyReader) -> Result<Vec<i16>, ProtocolError> {
+    let count = body.get_u16()?;
+    (0..count).map(|_| body.get_u16().map(|f| f as i16)).collect()
+}
+
+fn encode_formats(body: &mut BodyWriter, formats: &[i16]) {
+    body.put_u16(formats.len() as u16);
+    for format in formats {
+        body.put_u16(*format as u16);
+    }
+}
+
+/// Format of the `n`th value given the format codes of a `Bind` message.
+pub fn format_of(formats: &[i16], n: usize) -> i16 {
+    match formats {
+        [] => 0,
+        [format] => *format,
+        _ => formats.get(n).copied().unwrap_or(0),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldDescription {
+    pub name: String,
+    pub type_oid: u32,
+    /// Size of the type in bytes, -1 for variable length types.
+    pub type_len: i16,
+    pub type_modifier: i32,
+    /// 0 for text, 1 for binary.
+    pub format: i16,
+}
+
+impl FieldDescription {
+    pub fn text(name: &str) -> Self {
+        FieldDescription {
+            name: name.to_string(),
+            type_oid: TEXT_OID,
+            type_len: -1,
+            type_modifier: -1,
+            format: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error,
+    /// The connection is closed after the error is sent.
+    Fatal,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Warning => "WARNING",
+            Severity::Error => "ERROR",
+            Severity::Fatal => "FATAL",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErrorFields {
+    pub severity: Severity,
+    pub code: SqlState,
+    pub message: String,
+    /// 1-based character position in the query.
+    pub position: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BackendMessage {
+    /// `R`, the client is authenticated.
+    AuthenticationOk,
+    /// `R`, asks for a SASL exchange with one of the mechanisms.
+    AuthenticationSasl(Vec<String>),
+    /// `R`, a challenge of the SASL exchange.
+    AuthenticationSaslContinue(Vec<u8>),
+    /// `R`, the last message of a successful SASL exchange.
+    AuthenticationSaslFinal(Vec<u8>),
+    /// `v`, the newest minor version the server supports and the startup options it did not recognize.
+    NegotiateProtocolVersion { minor: u32, unrecognized: Vec<String> },
+    /// `S`, a run-time parameter the client should know about.
+    ParameterStatus { name: String, value: String },
+    /// `K`, the key the client cancels the session's statements with.
+    BackendKeyData { process_id: u32, secret_key: u32 },
+    /// `T`, columns of the rows that follow.
+    RowDescription(Vec<FieldDescription>),
+    /// `D`, values in text format, `None` is NULL.
+    DataRow(Vec<Option<Vec<u8>>>),
+    /// `1`
+    ParseComplete,
+    /// `2`
+    BindComplete,
+    /// `3`
+    CloseComplete,
+    /// `t`, types of the parameters of a prepared statement.
+    ParameterDescription(Vec<u32>),
+    /// `n`, the described statement or portal returns no rows.
+    NoData,
+    /// `s`, an `Execute` stopped at its row limit, the portal has more rows.
+    PortalSuspended,
+    /// `C`, a statement finished.
+    CommandComplete(String),
+    /// `I`, the query contained no statements.
+    EmptyQueryResponse,
+    /// `E`
+    ErrorResponse(ErrorFields),
+    /// `N`, a message that is not an error.
+    NoticeResponse(ErrorFields),
+    /// `Z`, the whole query has been processed.
+    ReadyForQuery(TransactionStatus),
+}
+
+impl BackendMessage {
+    pub fn encode(&self) -> Frame {
+        let mut body = BodyWriter::new();
+        let tag = match self {
+            BackendMessage::AuthenticationOk => {
+                body.put_u32(0);
+                b'R'
+            }
+            BackendMessage::AuthenticationSasl(mechanisms) => {
+                body.put_u32(10);
+                for mechanism in mechanisms {
+                    body.put_cstr(mechanism);
+                }
+                body.put_u8(0);
+                b'R'
+            }
+            BackendMessage::AuthenticationSaslContinue(data) => {
+                body.put_u32(11).put_bytes(data);
+                b'R'
+            }
+            BackendMessage::AuthenticationSaslFinal(data) => {
+                body.put_u32(12).put_bytes(data);
+                b'R'
+            }
+            BackendMessage::NegotiateProtocolVersion { minor, unrecognized } => {
+                body.put_u32(*minor).put_u32(unrecognized.len() as u32);
+                for option in unrecognized {
+                    body.put_cstr(option);
+                }
+                b'v'
+            }
+            BackendMessage::ParameterStatus { name, value } => {
+                body.put_cstr(name).put_cstr(value);
+                b'S'
+            }
+            BackendMessage::BackendKeyData { process_id, secret_key } => {
+                body.put_u32(*process_id).put_u32(*secret_key);
+                b'K'
+            }
+            BackendMessage::RowDescription(fields) => {
+                body.put_u16(fields.len() as u16);
+                for field in fields {
+                    // Not backed by a table column.
+                    body.put_cstr(&field.name)
+                        .put_u32(0)
+                        .put_u16(0)
+                        .put_u32(field.type_oid)
+                        .put_u16(field.type_len as u16)
+                        .put_i32(field.type_modifier)
+                        .put_u16(field.format as u16);
+                }
+                b'T'
+            }
+            BackendMessage::DataRow(values) => {
+                body.put_u16(values.len() as u16);
+                for value in values {
+                    match value {
+                        Some(value) => body.put_i32(value.len() as i32).put_bytes(value),
+                        None => body.put_i32(-1),
+                    };
+                }
+                b'D'
+            }
+            BackendMessage::ParseComplete => b'1',
+            BackendMessage::BindComplete => b'2',
+            BackendMessage::CloseComplete => b'3',
+            BackendMessage::ParameterDescription(types) => {
+                body.put_u16(types.len() as u16);
+                for oid in types {
+                    body.put_u32(*oid);
+                }
+                b't'
+            }
+            BackendMessage::NoData => b'n',
+            BackendMessage::PortalSuspended => b's',
+            BackendMessage::CommandComplete(tag) => {
+                body.put_cstr(tag);
+                b'C'
+            }
+            BackendMessage::EmptyQueryResponse => b'I',
+            BackendMessage::ErrorResponse(fields) | BackendMessage::NoticeResponse(fields) => {
+                body.put_u8(b'S').put_cstr(fields.severity.as_str());
+                body.put_u8(b'V').put_cstr(fields.severity.as_str());
+                body.put_u8(b'C').put_cstr(fields.code.as_str());
+                body.put_u8(b'M').put_cstr(&fields.message);
+                if let Some(position) = fields.position {
+                    body.put_u8(b'P').put_cstr(&position.to_string());
+                }
+                body.put_u8(0);
+                match self {
+                    BackendMessage::NoticeResponse(_) => b'N',
+                    _ => b'E',
+                }
+            }
+            BackendMessage::ReadyForQuery(status) => {
+                body.put_u8(match status {
+                    TransactionStatus::Idle => b'I',
+                    TransactionStatus::InTransaction => b'T',
+                    TransactionStatus::Failed => b'E',
+                });
+                b'Z'
+            }
+        };
+
+        Frame {
+            tag,
+            body: body.finish(),
+        }
+    }
+
+    /// Client side decoding, only messages the server sends are understood.
+    pub fn decode(frame: &Frame) -> Result<Self, ProtocolError> {
+        let mut body = BodyReader::new(&frame.body);
+        let msg = match frame.tag {
+            b'R' => match body.get_u32()? {
+                0 => BackendMessage::AuthenticationOk,
+                10 => {
+                    let mut mechanisms = Vec::new();
+                    loop {
+                        match body.get_cstr()? {
+                            "" => break,
+                            mechanism => mechanisms.push(mechanism.to_string()),
+                        }
+                    }
+                    BackendMessage::AuthenticationSasl(mechanisms)
+                }
+                11 => BackendMessage::AuthenticationSaslContinue(body.get_bytes(body.remaining())?.to_vec()),
+                12 => BackendMessage::AuthenticationSaslFinal(body.get_bytes(body.remaining())?.to_vec()),
+                kind => return Err(ProtocolError::Malformed(format!("unknown authentication request: {kind}"))),
+            },
+            b'v' => {
+                let minor = body.get_u32()?;
+                let count = body.get_u32()?;
+                let unrecognized = (0..count)
+                    .map(|_| body.get_cstr().map(str::to_string))
+                    .collect::<Result<_, _>>()?;
+                BackendMessage::NegotiateProtocolVersion { minor, unrecognized }
+            }
+            b'S' => BackendMessage::ParameterStatus {
+                name: body.get_cstr()?.to_string(),
+                value: body.get_cstr()?.to_string(),
+            },
+            b'K' => BackendMessage::BackendKeyData {
+                process_id: body.get_u32()?,
+                secret_key: body.get_u32()?,
+            },
+            b'T' => {
+                let count = body.get_u16()?;
+                let mut fields = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let name = body.get_cstr()?.to_string();
+                    body.get_u32()?;
+                    body.get_u16()?;
+                    let type_oid = body.get_u32()?;
+                    let type_len = body.get_u16()? as i16;
+                    let type_modifier = body.get_i32()?;
+                    let format = body.get_u16()? as i16;
+                    fields.push(FieldDescription {
+                        name,
+                        type_oid,
+                        type_len,
+                        type_modifier,
+                        format,
+                    });
+                }
+                BackendMessage::RowDescription(fields)
+            }
+            b'D' => {
+                let count = body.get_u16()?;
+                let mut values = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    values.push(match body.get_i32()? {
+                        -1 => None,
+                        len => Some(body.get_bytes(len as usize)?.to_vec()),
+                    });
+                }
+                BackendMessage::DataRow(values)
+            }
+            b'1' => BackendMessage::ParseComplete,
+            b'2' => BackendMessage::BindComplete,
+            b'3' => BackendMessage::CloseComplete,
+            b't' => {
+                let count = body.get_u16()?;
+                BackendMessage::ParameterDescription((0..count).map(|_| body.get_u32()).collect::<Result<_, _>>()?)
+            }
+            b'n' => BackendMessage::NoData,
+            b's' => BackendMessage::PortalSuspended,
+            b'C' => BackendMessage::CommandComplete(body.get_cstr()?.to_string()),
+            b'I' => BackendMessage::EmptyQueryResponse,
+            b'E' => BackendMessage::ErrorResponse(decode_error_fields(&mut body)?),
+            b'N' => BackendMessage::NoticeResponse(decode_error_fields(&mut body)?),
+            b'Z' => BackendMessage::ReadyForQuery(match body.get_u8()? {
+                b'I' => TransactionStatus::Idle,
+                b'T' => TransactionStatus::InTransaction,
+                b'E' => TransactionStatus::Failed,
+                status => return Err(ProtocolError::Malformed(format!("unknown transaction status: {status}"))),
+            }),
+            tag => return Err(ProtocolError::Malformed(format!("unknown message type: {0:?}", tag as char))),
+        };
+
+        if body.remaining() > 0 {
+            return Err(ProtocolError::Malformed(format!(
+                "trailing bytes in {0:?} message",
+                frame.tag as char
+            )));
+        }
+
+        Ok(msg)
+    }
+}
+
+fn decode_error_fields(body: &mut BodyReader) -> Result<ErrorFields, ProtocolError> {
+    let mut fields = ErrorFields {
+        severity: Severity::Error,
+        code: SqlState::INTERNAL_ERROR,
+        message: String::new(),
+        position: None,
+    };
+
+    loop {
+        match body.get_u8()? {
+            0 => return Ok(fields),
+            b'V' => {
+                fields.severity = match body.get_cstr()? {
+                    "FATAL" | "PANIC" => Severity::Fatal,
+                    "WARNING" | "NOTICE" => Severity::Warning,
+                    _ => Severity::Error,
+                }
+            }
+            b'C' => {
+                let code = body.get_cstr()?;
+                fields.code = SqlState::from_code(code)
+                    .ok_or_else(|| ProtocolError::Malformed(format!("invalid SQLSTATE: {code}")))?;
+            }
+            b'M' => fields.message = body.get_cstr()?.to_string(),
+            b'P' => fields.position = body.get_cstr()?.parse().ok(),
+            _ => {
+                body.get_cstr()?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_startup_round_trip() {
+        let msgs = vec![
+            StartupMessage::Startup {
+                version: PROTOCOL_VERSION,
+                params: vec![("user".to_string(), "rdb".to_string())],
+            },
+            StartupMessage::SslRequest,
+            StartupMessage::Cancel {
+                process_id: 7,
+                secret_key: 42,
+            },
+        ];
+
+        for msg in msgs {
+            assert_eq!(StartupMessage::decode(&msg.encode()).unwrap(), msg);
+        }
+    }
+
+    #[test]
+    fn test_backend_round_trip() {
+        let msgs = vec![
+            BackendMessage::AuthenticationOk,
+            BackendMessage::AuthenticationSasl(vec!["SCRAM-SHA-256".to_string()]),
+            BackendMessage::AuthenticationSaslContinue(b"r=abc,s=c2FsdA==,i=4096".to_vec()),
+            BackendMessage::AuthenticationSaslFinal(b"v=c2ln".to_vec()),
+            BackendMessage::NegotiateProtocolVersion {
+                minor: 0,
+                unrecognized: vec!["_pq_.compression".to_string()],
+            },
+            BackendMessage::ParameterStatus {
+                name: "server_encoding".to_string(),
+                value: "UTF8".to_string(),
+            },
+            BackendMessage::BackendKeyData {
+                process_id: 7,
+                secret_key: 42,
+            },
+            BackendMessage::RowDescription(vec![FieldDescription::text("a"), FieldDescription::text("b")]),
+            BackendMessage::DataRow(vec![Some(b"1".to_vec()), None]),
+            BackendMessage::ParseComplete,
+            BackendMessage::ParameterDescription(vec![TEXT_OID, 23]),
+            BackendMessage::NoData,
+            BackendMessage::PortalSuspended,
+            BackendMessage::CommandComplete("SELECT 1".to_string()),
+            BackendMessage::EmptyQueryResponse,
+            BackendMessage::ErrorResponse(ErrorFields {
+                severity: Severity::Fatal,
+                code: SqlState::PROTOCOL_VIOLATION,
+                message: "oops".to_string(),
+                position: Some(3),
+            }),
+            BackendMessage::ReadyForQuery(TransactionStatus::Failed),
+        ];
+
+        for msg in msgs {
+            assert_eq!(BackendMessage::decode(&msg.encode()).unwrap(), msg);
+        }
+    }
+
+    #[test]
+    fn test_frontend() {
+        let msgs = vec![
+            FrontendMessage::Query("COMMIT".to_string()),
+            FrontendMessage::Parse {
+                name: "s1".to_string(),
+                query: "SELECT * FROM t WHERE a = $1".to_string(),
+                param_types: vec![23],
+            },
+            FrontendMessage::Bind {
+                portal: String::new(),
+                statement: "s1".to_string(),
+                param_formats: vec![1],
+                params: vec![Some(vec![0, 0, 0, 7]), None],
+                result_formats: vec![],
+            },
+            FrontendMessage::Describe {
+                target: Target::Portal,
+                name: String::new(),
+            },
+            FrontendMessage::Execute {
+                portal: String::new(),
+                max_rows: 10,
+            },
+            FrontendMessage::Close {
+                target: Target::Statement,
+                name: "s1".to_string(),
+            },
+            FrontendMessage::Sync,
+            FrontendMessage::Flush,
+            FrontendMessage::Terminate,
+            FrontendMessage::Password(b"n,,n=,r=abc".to_vec()),
+        ];
+
+        for msg in msgs {
+            assert_eq!(FrontendMessage::decode(&msg.encode()).unwrap(), msg);
+        }
+
+        let sasl = SaslInitialResponse {
+            mechanism: "SCRAM-SHA-256".to_string(),
+            data: Some(b"n,,n=,r=abc".to_vec()),
+        };
+        assert_eq!(SaslInitialResponse::decode(&sasl.encode()).unwrap(), sasl);
+
+        assert!(FrontendMessage::decode(&Frame { tag: b'?', body: vec![] }).is_err());
+        assert!(
+            FrontendMessage::decode(&Frame {
+                tag: b'X',
+                body: vec![0]
+            })
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,1017 @@
+//! Serves one PostgreSQL client connection until it terminates.
+
+mod extended;
+
+use crate::auth::scram::{MECHANISM, ScramError, ScramServer};
+use crate::cancel::Registration;
+use crate::config::{AuthMethod, Config};
+use crate::executor::prepared::StatementCache;
+use crate::executor::{BatchMode, ExecError, Executor, SessionState, SqlState};
+use crate::parser::ast::{ExprKind, SetStmt, StatementKind};
+use crate::parser::token::LiteralKind;
+use crate::protocol::codec::{ProtocolError, read_frame, read_untyped_frame, write_frame};
+use crate::protocol::postgres::types::{command_tag, encode_row, field_description};
+use crate::protocol::postgres::{
+    BackendMessage, ErrorFields, FrontendMessage, PROTOCOL_VERSION, SaslInitialResponse, Severity, StartupMessage,
+};
+use crate::server::{Interrupt, Shutdown, read_or_interrupt, startup_within};
+use crate::tls::MaybeTls;
+use extended::{Portal, Statement};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_rustls::TlsAcceptor;
+
+/// Startup packets are small, anything bigger is not a PostgreSQL client.
+const MAX_STARTUP_LEN: usize = 10_000;
+
+/// Reported to clients, some of them adapt their behaviour to the server version.
+const SERVER_VERSION: &str = "14.0";
+
+/// Distinct statement texts a session keeps parsed.
+const STATEMENT_CACHE_CAPACITY: usize = 256;
+
+pub struct Session<'a, S> {
+    stream: BufWriter<MaybeTls<S>>,
+    executor: &'a Executor,
+    config: &'a Config,
+    /// Offered to clients that send an `SSLRequest`.
+    tls: Option<TlsAcceptor>,
+    shutdown: Shutdown,
+    /// Connection slots, one is taken at startup unless the connection only cancels a query.
+    permits: Option<Arc<Semaphore>>,
+    permit: Option<OwnedSemaphorePermit>,
+    /// Slot among the connections starting up, given back once the startup is over.
+    startup_permit: Option<OwnedSemaphorePermit>,
+    /// Key of the session in the executor's cancel registry.
+    registration: Registration<'a>,
+    state: SessionState,
+    /// Prepared statements by name, the unnamed statement has an empty name.
+    statements: HashMap<String, Statement>,
+    portals: HashMap<String, Portal>,
+    cache: StatementCache,
+    /// Set after an error in an extended query, messages are ignored until the next `Sync`.
+    skip_until_sync: bool,
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> Session<'a, S> {
+    pub fn new(stream: S, executor: &'a Executor, config: &'a Config) -> Self {
+        let registration = executor.register_session();
+        let state = SessionState::new(registration.id, config.timeouts.statement, registration.flag.clone())
+            .with_lock_timeout(config.timeouts.lock);
+        Session {
+            stream: BufWriter::new(MaybeTls::Plain(stream)),
+            executor,
+            config,
+            tls: None,
+            shutdown: Shutdown::never(),
+            permits: None,
+            permit: None,
+            startup_permit: None,
+            registration,
+            state,
+            statements: HashMap::new(),
+            portals: HashMap::new(),
+            cache: StatementCache::new(STATEMENT_CACHE_CAPACITY),
+            skip_until_sync: false,
+        }
+    }
+
+    pub fn with_tls(mut self, tls: Option<TlsAcceptor>) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Ends the session between messages once shutdown is requested.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Turns the client away at startup when no permit is left.
+    pub fn with_permits(mut self, permits: Arc<Semaphore>) -> Self {
+        self.permits = Some(permits);
+        self
+    }
+
+    pub fn with_startup_permit(mut self, permit: OwnedSemaphorePermit) -> Self {
+        self.startup_permit = Some(permit);
+        self
+    }
+
+    /// Answers queries until the client sends `Terminate` or closes the connection. A malformed message is
+    /// reported to the client before the connection is closed since the stream can no longer be trusted.
+    pub async fn run(mut self) -> Result<(), ProtocolError> {
+        let timeout = self.config.timeouts.authentication;
+        let started = startup_within(self.startup(), timeout).await;
+        self.startup_permit = None;
+        if !started? {
+            return Ok(());
+        }
+
+        loop {
+            let read = read_frame(self.stream.get_mut(), self.config.memory.max_message_size);
+            let frame = match read_or_interrupt(read, &mut self.shutdown, self.config.timeouts.idle_session).await {
+                Ok(frame) => frame?,
+                Err(Interrupt::Shutdown) => {
+                    let message = "terminating connection due to administrator command";
+                    return self.terminate(SqlState::ADMIN_SHUTDOWN, message).await;
+                }
+                Err(Interrupt::IdleTimeout) => {
+                    let message = "terminating connection due to idle-session timeout";
+                    return self.terminate(SqlState::IDLE_SESSION_TIMEOUT, message).await;
+                }
+            };
+            let Some(frame) = frame else {
+                return Ok(());
+            };
+
+            let msg = match FrontendMessage::decode(&frame) {
+                Ok(msg) => msg,
+                Err(err) => return Err(self.fatal(SqlState::PROTOCOL_VIOLATION, err).await),
+            };
+
+            let result = match msg {
+                FrontendMessage::Query(sql) => {
+                    self.query(&sql).await?;
+                    continue;
+                }
+                FrontendMessage::Sync => {
+                    self.skip_until_sync = false;
+                    self.portals.remove("");
+                    self.send(BackendMessage::ReadyForQuery(self.state.txn)).await?;
+                    self.stream.flush().await?;
+                    continue;
+                }
+                FrontendMessage::Flush => {
+                    self.stream.flush().await?;
+                    continue;
+                }
+                FrontendMessage::Terminate => return Ok(()),
+                FrontendMessage::Password(_) => {
+                    let err = ProtocolError::Malformed("unexpected password message".to_string());
+                    return Err(self.fatal(SqlState::PROTOCOL_VIOLATION, err).await);
+                }
+                _ if self.skip_until_sync => continue,
+                FrontendMessage::Parse {
+                    name,
+                    query,
+                    param_types,
+                } => self.parse(name, &query, param_types).map_err(|err| error_fields(&query, err)),
+                // Only parsing reports positions.
+                FrontendMessage::Bind {
+                    portal,
+                    statement,
+                    param_formats,
+                    params,
+                    result_formats,
+                } => self
+                    .bind(portal, &statement, &param_formats, params, result_formats)
+                    .map_err(|err| error_fields("", err)),
+                FrontendMessage::Describe { target, name } => {
+                    self.describe(target, &name).map_err(|err| error_fields("", err))
+                }
+                FrontendMessage::Execute { portal, max_rows } => {
+                    self.execute(&portal, max_rows).map_err(|err| error_fields("", err))
+                }
+                FrontendMessage::Close { target, name } => Ok(self.close(target, &name)),
+            };
+
+            match result {
+                Ok(msgs) => {
+                    for msg in msgs {
+                        self.send(msg).await?;
+                    }
+                }
+                Err(fields) => {
+                    self.send(BackendMessage::ErrorResponse(fields)).await?;
+                    self.skip_until_sync = true;
+                }
+            }
+        }
+    }
+
+    /// Runs the startup handshake, `false` when the client went away before completing it.
+    async fn startup(&mut self) -> Result<bool, ProtocolError> {
+        loop {
+            let read = read_untyped_frame(self.stream.get_mut(), MAX_STARTUP_LEN);
+            let data = match read_or_interrupt(read, &mut self.shutdown, self.config.timeouts.idle_session).await {
+                Ok(data) => data?,
+                // Not started up yet, there is nothing to tell the client.
+                Err(_) => None,
+            };
+            let Some(data) = data else {
+                return Ok(false);
+            };
+
+            let (version, params) = match StartupMessage::decode(&data) {
+                Ok(StartupMessage::Startup { version, params }) => (version, params),
+                Ok(StartupMessage::SslRequest) if self.tls.is_some() && !self.stream.get_ref().is_tls() => {
+                    self.stream.write_u8(b'S').await?;
+                    self.stream.flush().await?;
+                    // The startup message follows encrypted.
+                    let acceptor = self.tls.as_ref().expect("checked above");
+                    let upgrade = self.stream.get_mut().upgrade(acceptor);
+                    match read_or_interrupt(upgrade, &mut self.shutdown, self.config.timeouts.idle_session).await {
+                        Ok(result) => result?,
+                        Err(_) => return Ok(false),
+                    }
+                    continue;
+                }
+                Ok(StartupMessage::SslRequest | StartupMessage::GssEncRequest) => {
+                    // The client either gives up or retries in plain text.
+                    self.stream.write_u8(b'N').await?;
+                    self.stream.flush().await?;
+                    continue;
+                }
+                // Sent on a connection of its own, which closes without an answer either way.
+                Ok(StartupMessage::Cancel { process_id, secret_key }) => {
+                    self.executor.cancel(process_id, secret_key);
+                    return Ok(false);
+                }
+                Err(err) => return Err(self.fatal(SqlState::PROTOCOL_VIOLATION, err).await),
+            };
+
+            if let Some(permits) = &self.permits {
+                match permits.clone().try_acquire_owned() {
+                    Ok(permit) => self.permit = Some(permit),
+                    Err(_) => {
+                        let err = ProtocolError::Malformed("sorry, too many clients already".to_string());
+                        return Err(self.fatal(SqlState::TOO_MANY_CONNECTIONS, err).await);
+                    }
+                }
+            }
+
+            if version >> 16 != PROTOCOL_VERSION >> 16 {
+                let err = ProtocolError::Malformed(format!(
+                    "unsupported frontend protocol {0}.{1}: server supports 3.0",
+                    version >> 16,
+                    version & 0xffff
+                ));
+                return Err(self.fatal(SqlState::FEATURE_NOT_SUPPORTED, err).await);
+            }
+
+            if self.config.tls.required && !self.stream.get_ref().is_tls() {
+                let err = ProtocolError::Malformed("connection requires TLS, none was requested".to_string());
+                return Err(self.fatal(SqlState::INVALID_AUTHORIZATION_SPECIFICATION, err).await);
+            }
+
+            let Some((_, user)) = params.iter().find(|(name, _)| name == "user") else {
+                let err = ProtocolError::Malformed("no user name specified in startup packet".to_string());
+                return Err(self.fatal(SqlState::INVALID_AUTHORIZATION_SPECIFICATION, err).await);
+            };
+
+            // Protocol options are namespaced with `_pq_.`, none of them is supported.
+            let unrecognized: Vec<String> = params
+                .iter()
+                .filter(|(name, _)| name.starts_with("_pq_."))
+                .map(|(name, _)| name.clone())
+                .collect();
+            if version != PROTOCOL_VERSION || !unrecognized.is_empty() {
+                self.send(BackendMessage::NegotiateProtocolVersion { minor: 0, unrecognized })
+                    .await?;
+            }
+
+            if !self.authenticate(user).await? {
+                return Ok(false);
+            }
+            self.state.user = user.clone();
+
+            for (name, value) in startup_settings(&params) {
+                let set = StatementKind::Set(SetStmt {
+                    name,
+                    value: Some(ExprKind::Literal(LiteralKind::String(value))),
+                });
+                if let Err(err) = self.executor.execute(&set, &mut self.state) {
+                    let err = ProtocolError::Malformed(err.message);
+                    return Err(self.fatal(SqlState::INVALID_PARAMETER_VALUE, err).await);
+                }
+            }
+
+            self.send(BackendMessage::AuthenticationOk).await?;
+            for (name, value) in [
+                ("server_version", SERVER_VERSION),
+                ("server_encoding", "UTF8"),
+                ("client_encoding", "UTF8"),
+                ("DateStyle", "ISO, MDY"),
+                ("TimeZone", "UTC"),
+                ("integer_datetimes", "on"),
+                ("standard_conforming_strings", "on"),
+                ("session_authorization", user),
+            ] {
+                self.send(BackendMessage::ParameterStatus {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                })
+                .await?;
+            }
+            self.send(BackendMessage::BackendKeyData {
+                process_id: self.registration.id,
+                secret_key: self.registration.secret,
+            })
+            .await?;
+
+            self.send(BackendMessage::ReadyForQuery(self.state.txn)).await?;
+            self.stream.flush().await?;
+            return Ok(true);
+        }
+    }
+
+    /// Checks that `user` may log in and, unless every client is trusted, that the client knows its password.
+    /// `false` when the client went away in the middle.
+    async fn authenticate(&mut self, user: &str) -> Result<bool, ProtocolError> {
+        let role = self.executor.catalog().role(user);
+
+        if self.config.auth.method == AuthMethod::ScramSha256 {
+            self.send(BackendMessage::AuthenticationSasl(vec![MECHANISM.to_string()]))
+                .await?;
+            self.stream.flush().await?;
+
+            let Some(data) = self.read_password().await? else {
+                return Ok(false);
+            };
+            let initial = match SaslInitialResponse::decode(&data) {
+                Ok(initial) if initial.mechanism == MECHANISM => initial,
+                Ok(initial) => {
+                    let err = ProtocolError::Malformed(format!("unsupported SASL mechanism: {0}", initial.mechanism));
+                    return Err(self.fatal(SqlState::PROTOCOL_VIOLATION, err).await);
+                }
+                Err(err) => return Err(self.fatal(SqlState::PROTOCOL_VIOLATION, err).await),
+            };
+
+            // Unknown roles go through the exchange too, and fail at the end like a wrong password.
+            let secret = role.as_ref().and_then(|role| role.password.as_ref());
+            let (server, server_first) = match ScramServer::start(secret, &initial.data.unwrap_or_default()) {
+                Ok(started) => started,
+                Err(err) => return Err(self.scram_failed(user, err).await),
+            };
+            self.send(BackendMessage::AuthenticationSaslContinue(server_first.into_bytes()))
+                .await?;
+            self.stream.flush().await?;
+
+            let Some(data) = self.read_password().await? else {
+                return Ok(false);
+            };
+            match server.finish(&data) {
+                Ok(server_final) => {
+                    self.send(BackendMessage::AuthenticationSaslFinal(server_final.into_bytes()))
+                        .await?
+                }
+                Err(err) => return Err(self.scram_failed(user, err).await),
+            }
+        }
+
+        match role {
+            Some(role) if role.login => Ok(true),
+            Some(_) => {
+                let err = ProtocolError::Malformed(format!("role \"{user}\" is not permitted to log in"));
+                Err(self.fatal(SqlState::INVALID_AUTHORIZATION_SPECIFICATION, err).await)
+            }
+            None => {
+                let err = ProtocolError::Malformed(format!("role \"{user}\" does not exist"));
+                Err(self.fatal(SqlState::INVALID_AUTHORIZATION_SPECIFICATION, err).await)
+            }
+        }
+    }
+
+    /// Body of the next message, which must be a password message. `None` when the client went away.
+    async fn read_password(&mut self) -> Result<Option<Vec<u8>>, ProtocolError> {
+        let read = read_frame(self.stream.get_mut(), MAX_STARTUP_LEN);
+        let frame = match read_or_interrupt(read, &mut self.shutdown, self.config.timeouts.idle_session).await {
+            Ok(frame) => frame?,
+            Err(_) => None,
+        };
+        let Some(frame) = frame else {
+            return Ok(None);
+        };
+
+        match FrontendMessage::decode(&frame) {
+            Ok(FrontendMessage::Password(data)) => Ok(Some(data)),
+            Ok(_) => {
+                let err = ProtocolError::Malformed("expected a password message".to_string());
+                Err(self.fatal(SqlState::PROTOCOL_VIOLATION, err).await)
+            }
+            Err(err) => Err(self.fatal(SqlState::PROTOCOL_VIOLATION, err).await),
+        }
+    }
+
+    async fn scram_failed(&mut self, user: &str, err: ScramError) -> ProtocolError {
+        match err {
+            ScramError::InvalidProof => {
+                let err = ProtocolError::Malformed(format!("password authentication failed for user \"{user}\""));
+                self.fatal(SqlState::INVALID_PASSWORD, err).await
+            }
+            ScramError::Malformed(_) => {
+                self.fatal(SqlState::PROTOCOL_VIOLATION, ProtocolError::Malformed(err.to_string()))
+                    .await
+            }
+        }
+    }
+
+    async fn query(&mut self, sql: &str) -> Result<(), ProtocolError> {
+        // A simple query ends any unnamed statement or portal.
+        self.statements.remove("");
+        self.portals.remove("");
+
+        let results = self
+            .executor
+            .execute_batch(sql.as_bytes(), BatchMode::StopOnError, &mut self.state);
+
+        if results.is_empty() {
+            self.send(BackendMessage::EmptyQueryResponse).await?;
+        }
+
+        for stmt in results {
+            match stmt.result {
+                Ok(output) => {
+                    if output.returns_rows() {
+                        let fields = output.columns.iter().map(|c| field_description(c, 0)).collect();
+                        self.send(BackendMessage::RowDescription(fields)).await?;
+                    }
+                    for row in &output.rows {
+                        self.send(BackendMessage::DataRow(encode_row(&output.columns, row, &[])))
+                            .await?;
+                    }
+                    for notice in &output.notices {
+                        self.send(notice_response(notice)).await?;
+                    }
+                    self.send(BackendMessage::CommandComplete(command_tag(&output))).await?;
+                }
+                Err(err) => self.send(BackendMessage::ErrorResponse(error_fields(sql, err))).await?,
+            }
+        }
+
+        self.send(BackendMessage::ReadyForQuery(self.state.txn)).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    async fn send(&mut self, msg: BackendMessage) -> Result<(), ProtocolError> {
+        let frame = msg.encode();
+        write_frame(&mut self.stream, frame.tag, &frame.body).await
+    }
+
+    /// Reports an error that ends the connection and hands it back to be returned.
+    async fn fatal(&mut self, code: SqlState, err: ProtocolError) -> ProtocolError {
+        // The client may already be gone, the original error is the interesting one.
+        let _ = self.terminate(code, &err.to_string()).await;
+        err
+    }
+
+    /// Tells the client why the server is closing the connection.
+    async fn terminate(&mut self, code: SqlState, message: &str) -> Result<(), ProtocolError> {
+        let msg = BackendMessage::ErrorResponse(ErrorFields {
+            severity: Severity::Fatal,
+            code,
+            message: message.to_string(),
+            position: None,
+        });
+
+        self.send(msg).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+}
+
+/// Whatever the session was in the middle of is rolled back once its client is gone.
+impl<S> Drop for Session<'_, S> {
+    fn drop(&mut self) {
+        self.executor.end_session(&mut self.state);
+    }
+}
+
+/// Session settings given at startup, as parameters of their own or as `-c name=value` in `options`.
+fn startup_settings(params: &[(String, String)]) -> Vec<(&str, &str)> {
+    let mut settings = Vec::new();
+    for (name, value) in params {
+        match name.as_str() {
+            "statement_timeout" | "lock_timeout" => settings.push((name.as_str(), value.as_str())),
+            "options" => {
+                let mut words = value.split_whitespace();
+                while let Some(word) = words.next() {
+                    let setting = match word.strip_prefix("--") {
+                        Some(setting) => Some(setting),
+                        None if word == "-c" => words.next(),
+                        None => word.strip_prefix("-c"),
+                    };
+                    if let Some((name, value)) = setting.and_then(|setting| setting.split_once('=')) {
+                        settings.push((name, value));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    settings
+}
+
+fn notice_response(message: &str) -> BackendMessage {
+    BackendMessage::NoticeResponse(ErrorFields {
+        severity: Severity::Warning,
+        code: SqlState::WARNING,
+        message: message.to_string(),
+        position: None,
+    })
+}
+
+/// Converts the byte offset of the error to the 1-based character position clients expect.
+fn error_fields(sql: &str, err: ExecError) -> ErrorFields {
+    ErrorFields {
+        severity: Severity::Error,
+        code: err.code,
+        message: err.message,
+        position: err
+            .pos
+            .map(|pos| sql.get(..pos).map_or(pos, |prefix| prefix.chars().count()) + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::auth::crypto::{base64_decode, base64_encode, hmac_sha256, pbkdf2_sha256, sha256};
+    use crate::catalog::Catalog;
+    use crate::executor::TransactionStatus;
+    use crate::protocol::codec::MAX_FRAME_LEN;
+    use crate::protocol::postgres::{FieldDescription, INT4_OID, INT8_OID, Target};
+    use crate::stats::StatementStats;
+    use std::sync::Arc;
+    use tokio::io::DuplexStream;
+    use tokio::task::JoinHandle;
+
+    /// Minimal client side of the protocol.
+    struct TestClient {
+        stream: DuplexStream,
+        server: JoinHandle<Result<(), ProtocolError>>,
+    }
+
+    impl TestClient {
+        fn spawn() -> Self {
+            TestClient::spawn_with(trust())
+        }
+
+        fn spawn_with(config: Config) -> Self {
+            let executor = Executor::new(Arc::new(StatementStats::new()), Catalog::bootstrap("rdb", Some("secret")));
+            let (stream, server) = tokio::io::duplex(1024);
+            let server = tokio::spawn(async move { Session::new(server, &executor, &config).run().await });
+
+            TestClient { stream, server }
+        }
+
+        async fn connect() -> Self {
+            let mut client = TestClient::spawn();
+            client.startup(PROTOCOL_VERSION, &[("user", "rdb")]).await;
+
+            let msgs = client.receive().await;
+            assert_eq!(msgs[0], BackendMessage::AuthenticationOk);
+            assert_eq!(msgs.last(), Some(&BackendMessage::ReadyForQuery(TransactionStatus::Idle)));
+            client
+        }
+
+        async fn startup(&mut self, version: u32, params: &[(&str, &str)]) {
+            let params = params.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect();
+            let body = StartupMessage::Startup { version, params }.encode();
+            crate::protocol::codec::write_untyped_frame(&mut self.stream, &body)
+                .await
+                .unwrap();
+        }
+
+        async fn send(&mut self, msg: FrontendMessage) {
+            let frame = msg.encode();
+            write_frame(&mut self.stream, frame.tag, &frame.body).await.unwrap();
+        }
+
+        async fn receive_one(&mut self) -> BackendMessage {
+            let frame = read_frame(&mut self.stream, MAX_FRAME_LEN).await.unwrap().unwrap();
+            BackendMessage::decode(&frame).unwrap()
+        }
+
+        /// Messages up to and including the next `ReadyForQuery`.
+        async fn receive(&mut self) -> Vec<BackendMessage> {
+            let mut msgs = Vec::new();
+            loop {
+                let msg = self.receive_one().await;
+                let done = matches!(msg, BackendMessage::ReadyForQuery(_));
+                msgs.push(msg);
+                if done {
+                    return msgs;
+                }
+            }
+        }
+
+        async fn query(&mut self, sql: &str) -> Vec<BackendMessage> {
+            self.send(FrontendMessage::Query(sql.to_string())).await;
+            self.receive().await
+        }
+    }
+
+    fn trust() -> Config {
+        let mut config = Config::default();
+        config.auth.method = AuthMethod::Trust;
+        config
+    }
+
+    fn complete(tag: &str) -> BackendMessage {
+        BackendMessage::CommandComplete(tag.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_startup() {
+        let mut client = TestClient::spawn();
+        client.stream.write_all(&[0, 0, 0, 8]).await.unwrap();
+        client.stream.write_all(&StartupMessage::SslRequest.encode()).await.unwrap();
+
+        let mut response = [0u8; 1];
+        tokio::io::AsyncReadExt::read_exact(&mut client.stream, &mut response)
+            .await
+            .unwrap();
+        assert_eq!(&response, b"N");
+
+        client.startup(PROTOCOL_VERSION | 2, &[("user", "rdb")]).await;
+        let msgs = client.receive().await;
+        assert_eq!(
+            msgs[0],
+            BackendMessage::NegotiateProtocolVersion {
+                minor: 0,
+                unrecognized: vec![]
+            }
+        );
+        assert!(msgs.contains(&BackendMessage::ParameterStatus {
+            name: "session_authorization".to_string(),
+            value: "rdb".to_string()
+        }));
+        assert!(msgs.iter().any(|msg| matches!(msg, BackendMessage::BackendKeyData { .. })));
+
+        client.send(FrontendMessage::Terminate).await;
+        assert!(client.server.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_startup_errors() {
+        let mut client = TestClient::spawn();
+        client.startup(2 << 16, &[("user", "rdb")]).await;
+        match client.receive_one().await {
+            BackendMessage::ErrorResponse(err) => {
+                assert_eq!(err.severity, Severity::Fatal);
+                assert_eq!(err.code, SqlState::FEATURE_NOT_SUPPORTED);
+            }
+            msg => panic!("expected an error, got {msg:?}"),
+        }
+        assert!(client.server.await.unwrap().is_err());
+
+        let mut client = TestClient::spawn();
+        client.startup(PROTOCOL_VERSION, &[("database", "rdb")]).await;
+        match client.receive_one().await {
+            BackendMessage::ErrorResponse(err) => assert_eq!(err.code, SqlState::INVALID_AUTHORIZATION_SPECIFICATION),
+            msg => panic!("expected an error, got {msg:?}"),
+        }
+
+        let mut client = TestClient::spawn();
+        client
+            .startup(PROTOCOL_VERSION, &[("user", "rdb"), ("options", "-c statement_timeout=soon")])
+            .await;
+        match client.receive_one().await {
+            BackendMessage::ErrorResponse(err) => assert_eq!(err.code, SqlState::INVALID_PARAMETER_VALUE),
+            msg => panic!("expected an error, got {msg:?}"),
+        }
+
+        let mut client = TestClient::spawn();
+        client.startup(PROTOCOL_VERSION, &[("user", "nobody")]).await;
+        match client.receive_one().await {
+            BackendMessage::ErrorResponse(err) => assert_eq!(err.message, "role \"nobody\" does not exist"),
+            msg => panic!("expected an error, got {msg:?}"),
+        }
+    }
+
+    /// Runs the client side of a SCRAM-SHA-256 login, returns the message that followed the client's proof.
+    async fn scram_login(client: &mut TestClient, user: &str, password: &str) -> BackendMessage {
+        client.startup(PROTOCOL_VERSION, &[("user", user)]).await;
+        assert_eq!(
+            client.receive_one().await,
+            BackendMessage::AuthenticationSasl(vec![MECHANISM.to_string()])
+        );
+
+        let client_first_bare = "n=,r=fyko+d2lbbFgONRv9qkxdawL";
+        let initial = SaslInitialResponse {
+            mechanism: MECHANISM.to_string(),
+            data: Some(format!("n,,{client_first_bare}").into_bytes()),
+        };
+        client.send(FrontendMessage::Password(initial.encode())).await;
+        let BackendMessage::AuthenticationSaslContinue(server_first) = client.receive_one().await else {
+            panic!("expected a SASL challenge");
+        };
+        let server_first = String::from_utf8(server_first).unwrap();
+        let attribute = |name: &str| {
+            server_first
+                .split(',')
+                .find_map(|attr| attr.strip_prefix(name))
+                .unwrap()
+                .to_string()
+        };
+        let (nonce, salt, iterations) = (attribute("r="), attribute("s="), attribute("i="));
+
+        let salted = pbkdf2_sha256(
+            password.as_bytes(),
+            &base64_decode(&salt).unwrap(),
+            iterations.parse().unwrap(),
+        );
+        let client_key = hmac_sha256(&salted, b"Client Key");
+        let without_proof = format!("c=biws,r={nonce}");
+        let auth_message = format!("{client_first_bare},{server_first},{without_proof}");
+        let signature = hmac_sha256(&sha256(&client_key), auth_message.as_bytes());
+        let proof: Vec<u8> = client_key.iter().zip(signature).map(|(k, s)| k ^ s).collect();
+        let client_final = format!("{without_proof},p={0}", base64_encode(&proof));
+        client.send(FrontendMessage::Password(client_final.into_bytes())).await;
+
+        let msg = client.receive_one().await;
+        if let BackendMessage::AuthenticationSaslFinal(server_final) = &msg {
+            let server_signature = hmac_sha256(&hmac_sha256(&salted, b"Server Key"), auth_message.as_bytes());
+            assert_eq!(server_final, format!("v={0}", base64_encode(&server_signature)).as_bytes());
+        }
+        msg
+    }
+
+    #[tokio::test]
+    async fn test_scram_login() {
+        let mut client = TestClient::spawn_with(Config::default());
+        assert!(matches!(
+            scram_login(&mut client, "rdb", "secret").await,
+            BackendMessage::AuthenticationSaslFinal(_)
+        ));
+        let msgs = client.receive().await;
+        assert_eq!(msgs[0], BackendMessage::AuthenticationOk);
+
+        for user in ["rdb", "nobody"] {
+            let mut client = TestClient::spawn_with(Config::default());
+            match scram_login(&mut client, user, "wrong").await {
+                BackendMessage::ErrorResponse(err) => {
+                    assert_eq!(err.code, SqlState::INVALID_PASSWORD);
+                    assert_eq!(err.message, format!("password authentication failed for user \"{user}\""));
+                }
+                msg => panic!("expected an error, got {msg:?}"),
+            }
+            assert!(client.server.await.unwrap().is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_simple_query() {
+        let mut client = TestClient::connect().await;
+
+        assert_eq!(
+            client.query("COMMIT; ROLLBACK").await,
+            vec![
+                notice_response("there is no transaction in progress"),
+                complete("COMMIT"),
+                notice_response("there is no transaction in progress"),
+                complete("ROLLBACK"),
+                BackendMessage::ReadyForQuery(TransactionStatus::Idle)
+            ]
+        );
+
+        let msgs = client.query("SELECT * FROM rdb_stat_statements").await;
+        match &msgs[0] {
+            BackendMessage::RowDescription(fields) => {
+                assert_eq!(fields.len(), 5);
+                assert_eq!((fields[0].type_oid, fields[0].type_len), (INT8_OID, 8));
+                assert_eq!(fields[1], FieldDescription::text("query"));
+            }
+            msg => panic!("expected a row description, got {msg:?}"),
+        }
+        assert_eq!(msgs.len(), 1 + 2 + 2);
+        assert_eq!(msgs[3], complete("SELECT 2"));
+
+        assert_eq!(
+            client.query(" ; -- nothing").await,
+            vec![
+                BackendMessage::EmptyQueryResponse,
+                BackendMessage::ReadyForQuery(TransactionStatus::Idle)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_errors() {
+        let mut client = TestClient::connect().await;
+
+        // The notice of the COMMIT outside a transaction, its completion, the error and the ready.
+        let msgs = client.query("COMMIT; SELECT 'é' FROM; ROLLBACK").await;
+        assert_eq!(msgs.len(), 4);
+        assert_eq!(
+            msgs[2],
+            BackendMessage::ErrorResponse(ErrorFields {
+                severity: Severity::Error,
+                code: SqlState::SYNTAX_ERROR,
+                message: "Missing from clause".to_string(),
+                // Byte offset 24, 'é' is two bytes long.
+                position: Some(24),
+            })
+        );
+
+        let msgs = client.query("SELECT * FROM cats").await;
+        assert!(matches!(&msgs[0], BackendMessage::ErrorResponse(err) if err.code == SqlState::UNDEFINED_TABLE));
+    }
+
+    #[tokio::test]
+    async fn test_transaction_status() {
+        let mut client = TestClient::connect().await;
+
+        let msgs = client.query("BEGIN").await;
+        assert_eq!(
+            msgs.last(),
+            Some(&BackendMessage::ReadyForQuery(TransactionStatus::InTransaction))
+        );
+
+        let msgs = client.query("SELECT * FROM cats").await;
+        assert_eq!(msgs.last(), Some(&BackendMessage::ReadyForQuery(TransactionStatus::Failed)));
+
+        let msgs = client.query("ROLLBACK").await;
+        assert_eq!(
+            msgs,
+            vec![complete("ROLLBACK"), BackendMessage::ReadyForQuery(TransactionStatus::Idle)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_message_closes_connection() {
+        let mut client = TestClient::connect().await;
+
+        write_frame(&mut client.stream, b'?', b"").await.unwrap();
+        match client.receive_one().await {
+            BackendMessage::ErrorResponse(err) => assert_eq!(err.code, SqlState::PROTOCOL_VIOLATION),
+            msg => panic!("expected an error, got {msg:?}"),
+        }
+        assert!(matches!(client.server.await.unwrap(), Err(ProtocolError::Malformed(_))));
+    }
+
+    fn parse(name: &str, query: &str, param_types: Vec<u32>) -> FrontendMessage {
+        FrontendMessage::Parse {
+            name: name.to_string(),
+            query: query.to_string(),
+            param_types,
+        }
+    }
+
+    fn bind(portal: &str, statement: &str, params: Vec<Option<Vec<u8>>>, param_formats: Vec<i16>) -> FrontendMessage {
+        FrontendMessage::Bind {
+            portal: portal.to_string(),
+            statement: statement.to_string(),
+            param_formats,
+            params,
+            result_formats: vec![],
+        }
+    }
+
+    fn execute(portal: &str, max_rows: u32) -> FrontendMessage {
+        FrontendMessage::Execute {
+            portal: portal.to_string(),
+            max_rows,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extended_query() {
+        let mut client = TestClient::connect().await;
+        client.query("COMMIT; ROLLBACK").await;
+
+        let query = "SELECT * FROM rdb_stat_statements WHERE calls > $1";
+        client.send(parse("stats", query, vec![INT4_OID])).await;
+        client
+            .send(FrontendMessage::Describe {
+                target: Target::Statement,
+                name: "stats".to_string(),
+            })
+            .await;
+        client
+            .send(FrontendMessage::Bind {
+                portal: "p".to_string(),
+                statement: "stats".to_string(),
+                param_formats: vec![1],
+                params: vec![Some(vec![0, 0, 0, 0])],
+                result_formats: vec![1],
+            })
+            .await;
+        client.send(execute("p", 1)).await;
+        client.send(execute("p", 0)).await;
+        client.send(FrontendMessage::Sync).await;
+
+        let msgs = client.receive().await;
+        assert_eq!(msgs[0], BackendMessage::ParseComplete);
+        assert_eq!(msgs[1], BackendMessage::ParameterDescription(vec![INT4_OID]));
+        assert!(matches!(&msgs[2], BackendMessage::RowDescription(fields) if fields.len() == 5));
+        assert_eq!(msgs[3], BackendMessage::BindComplete);
+        // Binary results, calls is an int8.
+        assert!(matches!(&msgs[4], BackendMessage::DataRow(values) if values[2] == Some(1i64.to_be_bytes().to_vec())));
+        assert_eq!(msgs[5], BackendMessage::PortalSuspended);
+        assert!(matches!(msgs[6], BackendMessage::DataRow(_)));
+        assert_eq!(msgs[7], complete("SELECT 1"));
+        assert_eq!(msgs[8], BackendMessage::ReadyForQuery(TransactionStatus::Idle));
+
+        // The named statement outlives the sync, the unnamed portal does not.
+        client.send(bind("", "stats", vec![Some(b"1".to_vec())], vec![])).await;
+        client
+            .send(FrontendMessage::Describe {
+                target: Target::Portal,
+                name: String::new(),
+            })
+            .await;
+        client.send(execute("", 0)).await;
+        client.send(FrontendMessage::Sync).await;
+
+        let msgs = client.receive().await;
+        assert_eq!(msgs[0], BackendMessage::BindComplete);
+        assert!(matches!(&msgs[1], BackendMessage::RowDescription(fields) if fields[0].format == 0));
+        assert_eq!(msgs.last(), Some(&BackendMessage::ReadyForQuery(TransactionStatus::Idle)));
+    }
+
+    #[tokio::test]
+    async fn test_extended_commands() {
+        let mut client = TestClient::connect().await;
+
+        client.send(parse("", "BEGIN", vec![])).await;
+        client.send(bind("", "", vec![], vec![])).await;
+        client
+            .send(FrontendMessage::Describe {
+                target: Target::Portal,
+                name: String::new(),
+            })
+            .await;
+        client.send(execute("", 0)).await;
+        client
+            .send(FrontendMessage::Close {
+                target: Target::Statement,
+                name: String::new(),
+            })
+            .await;
+        client.send(FrontendMessage::Sync).await;
+
+        assert_eq!(
+            client.receive().await,
+            vec![
+                BackendMessage::ParseComplete,
+                BackendMessage::BindComplete,
+                BackendMessage::NoData,
+                complete("BEGIN"),
+                BackendMessage::CloseComplete,
+                BackendMessage::ReadyForQuery(TransactionStatus::InTransaction),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extended_errors_skip_until_sync() {
+        let mut client = TestClient::connect().await;
+
+        client.send(parse("", "SELECT FROM", vec![])).await;
+        client.send(bind("", "", vec![], vec![])).await;
+        client.send(execute("", 0)).await;
+        client.send(FrontendMessage::Sync).await;
+
+        let msgs = client.receive().await;
+        assert_eq!(msgs.len(), 2);
+        assert!(matches!(&msgs[0], BackendMessage::ErrorResponse(err) if err.code == SqlState::SYNTAX_ERROR));
+
+        client.send(bind("", "missing", vec![], vec![])).await;
+        client.send(FrontendMessage::Sync).await;
+        let msgs = client.receive().await;
+        assert!(matches!(&msgs[0], BackendMessage::ErrorResponse(err) if err.code == SqlState::INVALID_SQL_STATEMENT_NAME));
+
+        client.send(parse("s", "COMMIT", vec![])).await;
+        client.send(parse("s", "COMMIT", vec![])).await;
+        client.send(FrontendMessage::Sync).await;
+        let msgs = client.receive().await;
+        assert_eq!(msgs[0], BackendMessage::ParseComplete);
+        assert!(
+            matches!(&msgs[1], BackendMessage::ErrorResponse(err) if err.code == SqlState::DUPLICATE_PREPARED_STATEMENT)
+        );
+
+        // One parameter too many, and a malformed binary integer.
+        client.send(bind("", "s", vec![None], vec![])).await;
+        client.send(FrontendMessage::Sync).await;
+        let msgs = client.receive().await;
+        assert!(matches!(&msgs[0], BackendMessage::ErrorResponse(err) if err.code == SqlState::PROTOCOL_VIOLATION));
+
+        client.send(parse("n", "SELECT * FROM t WHERE a = $1", vec![INT4_OID])).await;
+        client.send(bind("", "n", vec![Some(vec![1, 2])], vec![1])).await;
+        client.send(FrontendMessage::Sync).await;
+        let msgs = client.receive().await;
+        assert!(
+            matches!(&msgs[1], BackendMessage::ErrorResponse(err) if err.code == SqlState::INVALID_BINARY_REPRESENTATION)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_statement_cache() {
+        let executor = Executor::new(Arc::new(StatementStats::new()), Catalog::bootstrap("rdb", None));
+        let (_client, server) = tokio::io::duplex(1024);
+        let config = trust();
+        let mut session = Session::new(server, &executor, &config);
+
+        session.parse(String::new(), "COMMIT", vec![]).unwrap();
+        session.parse(String::new(), "COMMIT", vec![]).unwrap();
+        session.parse("named".to_string(), "COMMIT", vec![]).unwrap();
+        assert_eq!(session.cache.hits(), 2);
+    }
+}
@@ -0,0 +1,271 @@
+//! Extended query protocol: prepared statements and portals.
+//!
+//! Every handler returns the messages answering its request, or the error that makes the session skip messages
+//! until the next `Sync`.
+
+use super::Session;
+use crate::executor::prepared::{ParamValue, PreparedStatement};
+use crate::executor::result::{Column, ResultSet};
+use crate::executor::{ExecError, SqlState};
+use crate::protocol::postgres::types::{command_tag, encode_row, field_description};
+use crate::protocol::postgres::*;
+use std::sync::Arc;
+
+/// A prepared statement as the client sees it.
+pub(super) struct Statement {
+    prepared: Arc<PreparedStatement>,
+    /// Types the client declared, 0 where it left the type unspecified.
+    param_types: Vec<u32>,
+}
+
+impl Statement {
+    /// Parameter types to report, unspecified ones are text.
+    fn param_types(&self) -> Vec<u32> {
+        (0..self.prepared.param_count())
+            .map(|i| match self.param_types.get(i) {
+                Some(0) | None => TEXT_OID,
+                Some(oid) => *oid,
+            })
+            .collect()
+    }
+}
+
+/// A bound statement ready for execution. It runs on the first `Execute`, later ones fetch the remaining rows.
+pub(super) struct Portal {
+    statement: Arc<PreparedStatement>,
+    params: Vec<ParamValue>,
+    result_formats: Vec<i16>,
+    output: Option<ResultSet>,
+    /// Rows of the output already sent.
+    sent: usize,
+}
+
+impl<S> Session<'_, S> {
+    pub(super) fn parse(
+        &mut self,
+        name: String,
+        query: &str,
+        param_types: Vec<u32>,
+    ) -> Result<Vec<BackendMessage>, ExecError> {
+        if !name.is_empty() && self.statements.contains_key(&name) {
+            return Err(ExecError::new(
+                SqlState::DUPLICATE_PREPARED_STATEMENT,
+                format!("prepared statement \"{name}\" already exists"),
+            ));
+        }
+
+        let prepared = self.cache.prepare(query)?;
+        self.statements.insert(name, Statement { prepared, param_types });
+        Ok(vec![BackendMessage::ParseComplete])
+    }
+
+    pub(super) fn bind(
+        &mut self,
+        portal: String,
+        statement: &str,
+        param_formats: &[i16],
+        params: Vec<Option<Vec<u8>>>,
+        result_formats: Vec<i16>,
+    ) -> Result<Vec<BackendMessage>, ExecError> {
+        if !portal.is_empty() && self.portals.contains_key(&portal) {
+            return Err(ExecError::new(
+                SqlState::DUPLICATE_CURSOR,
+                format!("portal \"{portal}\" already exists"),
+            ));
+        }
+
+        let stmt = self.statement(statement)?;
+        let types = stmt.param_types();
+        let params = params
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                decode_param(
+                    i,
+                    types.get(i).copied().unwrap_or(TEXT_OID),
+                    format_of(param_formats, i),
+                    value,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Checks the parameter count now rather than on the first `Execute`.
+        stmt.prepared.bind(&params)?;
+
+        let statement = stmt.prepared.clone();
+        self.portals.insert(
+            portal,
+            Portal {
+                statement,
+                params,
+                result_formats,
+                output: None,
+                sent: 0,
+            },
+        );
+        Ok(vec![BackendMessage::BindComplete])
+    }
+
+    pub(super) fn describe(&self, target: Target, name: &str) -> Result<Vec<BackendMessage>, ExecError> {
+        match target {
+            Target::Statement => {
+                let stmt = self.statement(name)?;
+                let columns = match stmt.prepared.stmt() {
+                    Some(s) => self.executor.describe(s)?,
+                    None => None,
+                };
+
+                Ok(vec![
+                    BackendMessage::ParameterDescription(stmt.param_types()),
+                    row_description(columns, &[]),
+                ])
+            }
+            Target::Portal => {
+                let portal = self.portal(name)?;
+                let columns = match &portal.output {
+                    Some(output) => Some(output.columns.clone()).filter(|c| !c.is_empty()),
+                    None => match portal.statement.bind(&portal.params)? {
+                        Some(stmt) => self.executor.describe(&stmt)?,
+                        None => None,
+                    },
+                };
+
+                Ok(vec![row_description(columns, &portal.result_formats)])
+            }
+        }
+    }
+
+    pub(super) fn execute(&mut self, name: &str, max_rows: u32) -> Result<Vec<BackendMessage>, ExecError> {
+        let portal = self
+            .portals
+            .get_mut(name)
+            .ok_or_else(|| ExecError::new(SqlState::INVALID_CURSOR_NAME, format!("portal \"{name}\" does not exist")))?;
+
+        if portal.output.is_none() {
+            let Some(stmt) = portal.statement.bind(&portal.params)? else {
+                return Ok(vec![BackendMessage::EmptyQueryResponse]);
+            };
+            portal.output = Some(self.executor.execute(&stmt, &mut self.state)?);
+        }
+
+        let output = portal.output.as_ref().unwrap();
+        let remaining = &output.rows[portal.sent..];
+        let count = match max_rows {
+            0 => remaining.len(),
+            n => remaining.len().min(n as usize),
+        };
+
+        let mut msgs: Vec<BackendMessage> = remaining[..count]
+            .iter()
+            .map(|row| BackendMessage::DataRow(encode_row(&output.columns, row, &portal.result_formats)))
+            .collect();
+        portal.sent += count;
+
+        if portal.sent < output.rows.len() {
+            msgs.push(BackendMessage::PortalSuspended);
+        } else if !output.returns_rows() {
+            msgs.extend(output.notices.iter().map(|notice| super::notice_response(notice)));
+            msgs.push(BackendMessage::CommandComplete(command_tag(output)));
+        } else {
+            // Counts the rows returned by this `Execute` only.
+            msgs.push(BackendMessage::CommandComplete(format!("SELECT {count}")));
+        }
+
+        Ok(msgs)
+    }
+
+    pub(super) fn close(&mut self, target: Target, name: &str) -> Vec<BackendMessage> {
+        // Closing something that does not exist is not an error.
+        match target {
+            Target::Statement => self.statements.remove(name).map(|_| ()),
+            Target::Portal => self.portals.remove(name).map(|_| ()),
+        };
+
+        vec![BackendMessage::CloseComplete]
+    }
+
+    fn statement(&self, name: &str) -> Result<&Statement, ExecError> {
+        self.statements.get(name).ok_or_else(|| {
+            ExecError::new(
+                SqlState::INVALID_SQL_STATEMENT_NAME,
+                format!("prepared statement \"{name}\" does not exist"),
+            )
+        })
+    }
+
+    fn portal(&self, name: &str) -> Result<&Portal, ExecError> {
+        self.portals
+            .get(name)
+            .ok_or_else(|| ExecError::new(SqlState::INVALID_CURSOR_NAME, format!("portal \"{name}\" does not exist")))
+    }
+}
+
+fn row_description(columns: Option<Vec<Column>>, formats: &[i16]) -> BackendMessage {
+    match columns {
+        Some(columns) => BackendMessage::RowDescription(
+            columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| field_description(column, format_of(formats, i)))
+                .collect(),
+        ),
+        None => BackendMessage::NoData,
+    }
+}
+
+/// Converts the `n`th parameter of a `Bind` message according to its declared type and format.
+fn decode_param(n: usize, oid: u32, format: i16, value: &Option<Vec<u8>>) -> Result<ParamValue, ExecError> {
+    let Some(value) = value else {
+        return Ok(ParamValue::Null);
+    };
+
+    let invalid_binary = || {
+        ExecError::new(
+            SqlState::INVALID_BINARY_REPRESENTATION,
+            format!("incorrect binary data format in bind parameter {0}", n + 1),
+        )
+    };
+
+    match (format, oid) {
+        (0, INT2_OID | INT4_OID | INT8_OID | FLOAT4_OID | FLOAT8_OID | NUMERIC_OID) => {
+            let text = std::str::from_utf8(value).unwrap_or_default();
+            text.trim().parse().map(ParamValue::Numeric).map_err(|_| {
+                ExecError::new(
+                    SqlState::INVALID_TEXT_REPRESENTATION,
+                    format!("invalid input syntax for type numeric: \"{text}\""),
+                )
+            })
+        }
+        (0, _) | (1, 0 | TEXT_OID | VARCHAR_OID | BPCHAR_OID | UNKNOWN_OID) => std::str::from_utf8(value)
+            .map(|text| ParamValue::Text(text.to_string()))
+            .map_err(|_| {
+                ExecError::new(
+                    SqlState::CHARACTER_NOT_IN_REPERTOIRE,
+                    "invalid byte sequence for encoding \"UTF8\"".to_string(),
+                )
+            }),
+        (1, INT2_OID) => Ok(ParamValue::Numeric(
+            i16::from_be_bytes(value[..].try_into().map_err(|_| invalid_binary())?) as f64,
+        )),
+        (1, INT4_OID) => Ok(ParamValue::Numeric(
+            i32::from_be_bytes(value[..].try_into().map_err(|_| invalid_binary())?) as f64,
+        )),
+        (1, INT8_OID) => Ok(ParamValue::Numeric(
+            i64::from_be_bytes(value[..].try_into().map_err(|_| invalid_binary())?) as f64,
+        )),
+        (1, FLOAT4_OID) => Ok(ParamValue::Numeric(
+            f32::from_be_bytes(value[..].try_into().map_err(|_| invalid_binary())?) as f64,
+        )),
+        (1, FLOAT8_OID) => Ok(ParamValue::Numeric(f64::from_be_bytes(
+            value[..].try_into().map_err(|_| invalid_binary())?,
+        ))),
+        (1, oid) => Err(ExecError::new(
+            SqlState::FEATURE_NOT_SUPPORTED,
+            format!("binary format is not supported for parameters of type {oid}"),
+        )),
+        (format, _) => Err(ExecError::new(
+            SqlState::PROTOCOL_VIOLATION,
+            format!("unsupported format code: {format}"),
+        )),
+    }
+}
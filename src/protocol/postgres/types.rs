@@ -0,0 +1,257 @@
+//! Mapping of column types and values to their PostgreSQL wire representations.
+
+use crate::executor::result::{Column, ResultSet};
+use crate::parser::token::DataKind;
+use crate::protocol::postgres::*;
+use crate::value::Value;
+
+pub fn type_oid(data_type: &DataKind) -> u32 {
+    match data_type {
+        DataKind::Bool => BOOL_OID,
+        DataKind::TinyInt(_) | DataKind::SmallInt(_) => INT2_OID,
+        DataKind::MediumInt(_) | DataKind::Integer(_) => INT4_OID,
+        DataKind::BigInt(_) => INT8_OID,
+        DataKind::Float(..) => FLOAT4_OID,
+        DataKind::Double(..) => FLOAT8_OID,
+        DataKind::Decimal(..) => NUMERIC_OID,
+        DataKind::Bit(_) => BIT_OID,
+        DataKind::Char(_) => BPCHAR_OID,
+        DataKind::VarChar(_) => VARCHAR_OID,
+        DataKind::TinyText | DataKind::Text(_) | DataKind::MediumText(_) | DataKind::LongText(_) => TEXT_OID,
+        DataKind::Binary(_)
+        | DataKind::VarBinary(_)
+        | DataKind::TinyBlob
+        | DataKind::Blob(_)
+        | DataKind::MediumBlob(_)
+        | DataKind::LongBlob(_) => BYTEA_OID,
+    }
+}
+
+/// Size in bytes of values of the type, -1 for variable length types.
+pub fn type_len(data_type: &DataKind) -> i16 {
+    match data_type {
+        DataKind::Bool => 1,
+        DataKind::TinyInt(_) | DataKind::SmallInt(_) => 2,
+        DataKind::MediumInt(_) | DataKind::Integer(_) | DataKind::Float(..) => 4,
+        DataKind::BigInt(_) | DataKind::Double(..) => 8,
+        _ => -1,
+    }
+}
+
+/// Type modifier the way PostgreSQL reports it, -1 when the type has none.
+fn type_modifier(data_type: &DataKind) -> i32 {
+    match *data_type {
+        // Character lengths include the 4 byte header of the value.
+        DataKind::Char(Some(n)) => n as i32 + 4,
+        DataKind::VarChar(Some(n)) => n as i32 + 4,
+        DataKind::Bit(Some(n)) => n as i32,
+        DataKind::Decimal(Some(precision), scale) => (((precision as i32) << 16) | scale.unwrap_or(0) as i32) + 4,
+        _ => -1,
+    }
+}
+
+pub fn field_description(column: &Column, format: i16) -> FieldDescription {
+    FieldDescription {
+        name: column.name.clone(),
+        type_oid: type_oid(&column.data_type),
+        type_len: type_len(&column.data_type),
+        type_modifier: type_modifier(&column.data_type),
+        format,
+    }
+}
+
+/// Encodes a row in the given result formats, NULLs are `None`.
+pub fn encode_row(columns: &[Column], row: &[Value], formats: &[i16]) -> Vec<Option<Vec<u8>>> {
+    row.iter()
+        .zip(columns)
+        .enumerate()
+        .map(|(i, (value, column))| match format_of(formats, i) {
+            1 => encode_binary(value, &column.data_type),
+            _ => encode_text(value, &column.data_type),
+        })
+        .collect()
+}
+
+pub fn encode_text(value: &Value, data_type: &DataKind) -> Option<Vec<u8>> {
+    let text = match (value, data_type) {
+        (Value::Null, _) => return None,
+        (Value::Bool(b), _) => if *b { "t" } else { "f" }.to_string(),
+        (Value::Int(n), DataKind::Bit(len)) => bit_string(*n, len.unwrap_or(1)),
+        (value, _) => value.to_string(),
+    };
+
+    Some(text.into_bytes())
+}
+
+pub fn encode_binary(value: &Value, data_type: &DataKind) -> Option<Vec<u8>> {
+    let bytes = match (value, type_oid(data_type)) {
+        (Value::Null, _) => return None,
+        (Value::Bool(b), _) => vec![*b as u8],
+        (Value::Int(n), INT2_OID) => (*n as i16).to_be_bytes().to_vec(),
+        (Value::Int(n), INT4_OID) => (*n as i32).to_be_bytes().to_vec(),
+        (Value::Float(n), FLOAT4_OID) => (*n as f32).to_be_bytes().to_vec(),
+        (Value::Float(n), FLOAT8_OID) => n.to_be_bytes().to_vec(),
+        (Value::Int(n), FLOAT8_OID) => (*n as f64).to_be_bytes().to_vec(),
+        (value, NUMERIC_OID) => encode_numeric(&value.to_string()),
+        (Value::Int(n), BIT_OID) => {
+            let len = match data_type {
+                DataKind::Bit(len) => len.unwrap_or(1),
+                _ => unreachable!(),
+            };
+            let mut bytes = (len as i32).to_be_bytes().to_vec();
+            let bits = bit_string(*n, len);
+            bytes.extend(bits.as_bytes().chunks(8).map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0u8, |byte, (i, bit)| byte | ((*bit == b'1') as u8) << (7 - i))
+            }));
+            bytes
+        }
+        (Value::Int(n), _) => n.to_be_bytes().to_vec(),
+        (Value::Float(n), _) => n.to_be_bytes().to_vec(),
+        (Value::Decimal(n), _) => encode_numeric(&n.to_string()),
+        // The binary form of text is the text itself.
+        (Value::Text(s), _) => s.as_bytes().to_vec(),
+        (Value::Bytes(bytes), _) => bytes.clone(),
+    };
+
+    Some(bytes)
+}
+
+/// The low `len` bits of `n`, most significant first.
+fn bit_string(n: i64, len: u8) -> String {
+    (0..len).rev().map(|i| if (n >> i) & 1 == 1 { '1' } else { '0' }).collect()
+}
+
+/// Binary `numeric` from its decimal text: digit count, weight of the first digit, sign and display scale, then
+/// the digits in base 10000.
+fn encode_numeric(text: &str) -> Vec<u8> {
+    const NUMERIC_POS: u16 = 0x0000;
+    const NUMERIC_NEG: u16 = 0x4000;
+    const NUMERIC_NAN: u16 = 0xc000;
+
+    let (sign, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => (NUMERIC_NEG, rest),
+        None => (NUMERIC_POS, text),
+    };
+    let (int_part, frac_part) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if int_part.is_empty() && frac_part.is_empty() || !is_digits(int_part) || !is_digits(frac_part) {
+        let mut bytes = Vec::with_capacity(8);
+        bytes.extend_from_slice(&0i16.to_be_bytes());
+        bytes.extend_from_slice(&0i16.to_be_bytes());
+        bytes.extend_from_slice(&NUMERIC_NAN.to_be_bytes());
+        bytes.extend_from_slice(&0u16.to_be_bytes());
+        return bytes;
+    }
+
+    // Pads both parts to whole base 10000 digits.
+    let int_pad = (4 - int_part.len() % 4) % 4;
+    let frac_pad = (4 - frac_part.len() % 4) % 4;
+    let padded = format!("{0}{int_part}{frac_part}{1}", "0".repeat(int_pad), "0".repeat(frac_pad));
+    let mut digits: Vec<i16> = padded
+        .as_bytes()
+        .chunks(4)
+        .map(|chunk| std::str::from_utf8(chunk).unwrap().parse().unwrap())
+        .collect();
+    let mut weight = ((int_part.len() + int_pad) / 4) as i16 - 1;
+
+    let leading = digits.iter().take_while(|d| **d == 0).count();
+    digits.drain(..leading);
+    weight -= leading as i16;
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+    if digits.is_empty() {
+        weight = 0;
+    }
+
+    let mut bytes = Vec::with_capacity(8 + digits.len() * 2);
+    bytes.extend_from_slice(&(digits.len() as i16).to_be_bytes());
+    bytes.extend_from_slice(&weight.to_be_bytes());
+    bytes.extend_from_slice(&if digits.is_empty() { NUMERIC_POS } else { sign }.to_be_bytes());
+    bytes.extend_from_slice(&(frac_part.len() as u16).to_be_bytes());
+    for digit in digits {
+        bytes.extend_from_slice(&digit.to_be_bytes());
+    }
+    bytes
+}
+
+/// Tag of the `CommandComplete` message for a result.
+pub fn command_tag(result: &ResultSet) -> String {
+    match result.command {
+        "SELECT" => format!("SELECT {0}", result.rows.len()),
+        // The 0 is the OID of the inserted row, which used to be reported for tables with OIDs.
+        "INSERT" => format!("INSERT 0 {0}", result.affected_rows),
+        "UPDATE" | "DELETE" => format!("{0} {1}", result.command, result.affected_rows),
+        command => command.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_type_mapping() {
+        assert_eq!(type_oid(&DataKind::BigInt(None)), INT8_OID);
+        assert_eq!(type_oid(&DataKind::VarChar(Some(20))), VARCHAR_OID);
+        assert_eq!(type_oid(&DataKind::LongBlob(None)), BYTEA_OID);
+
+        let field = field_description(&Column::new("name", DataKind::VarChar(Some(20))), 0);
+        assert_eq!((field.type_len, field.type_modifier), (-1, 24));
+        let field = field_description(&Column::new("n", DataKind::Integer(None)), 1);
+        assert_eq!((field.type_len, field.type_modifier, field.format), (4, -1, 1));
+    }
+
+    #[test]
+    fn test_encode_text() {
+        assert_eq!(encode_text(&Value::Bool(true), &DataKind::Bool), Some(b"t".to_vec()));
+        assert_eq!(encode_text(&Value::Int(5), &DataKind::Bit(Some(4))), Some(b"0101".to_vec()));
+        assert_eq!(
+            encode_text(&Value::Bytes(vec![0xff, 0x01]), &DataKind::Blob(None)),
+            Some(b"\\xff01".to_vec())
+        );
+        assert_eq!(encode_text(&Value::Null, &DataKind::Bool), None);
+    }
+
+    #[test]
+    fn test_encode_binary() {
+        let encode = |value, data_type| encode_binary(&value, &data_type).unwrap();
+
+        assert_eq!(encode(Value::Int(-2), DataKind::SmallInt(None)), vec![0xff, 0xfe]);
+        assert_eq!(encode(Value::Int(1), DataKind::BigInt(None)), vec![0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(encode(Value::Float(1.5), DataKind::Float(None, None)), 1.5f32.to_be_bytes());
+        assert_eq!(encode(Value::Text("hé".to_string()), DataKind::Text(None)), "hé".as_bytes());
+        assert_eq!(
+            encode(Value::Int(5), DataKind::Bit(Some(10))),
+            vec![0, 0, 0, 10, 0b0000_0001, 0b0100_0000]
+        );
+    }
+
+    #[test]
+    fn test_encode_numeric() {
+        let numeric = |text| encode_binary(&Value::Text(text), &DataKind::Decimal(Some(10), Some(2))).unwrap();
+
+        // 12345.678 is 1 2345 . 6780 with a weight of 1 and 3 digits after the point.
+        assert_eq!(
+            numeric("12345.678".to_string()),
+            vec![0, 3, 0, 1, 0, 0, 0, 3, 0, 1, 0x09, 0x29, 0x1a, 0x7c]
+        );
+        assert_eq!(numeric("-0.0001".to_string()), vec![0, 1, 0xff, 0xff, 0x40, 0, 0, 4, 0, 1]);
+        assert_eq!(numeric("0.00".to_string()), vec![0, 0, 0, 0, 0, 0, 0, 2]);
+        assert_eq!(numeric("NaN".to_string()), vec![0, 0, 0, 0, 0xc0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_command_tag() {
+        let mut result = ResultSet::command("INSERT");
+        result.affected_rows = 3;
+        assert_eq!(command_tag(&result), "INSERT 0 3");
+        assert_eq!(command_tag(&ResultSet::query(vec![], vec![vec![], vec![]])), "SELECT 2");
+        assert_eq!(command_tag(&ResultSet::command("CREATE TABLE")), "CREATE TABLE");
+    }
+}
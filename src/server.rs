@@ -0,0 +1,533 @@
+//! Accepts connections and hands them to a session of their protocol.
+//!
+//! At most `server.max_connections` sessions run at once. Once they are all taken the server keeps accepting, but
+//! turns further clients away at startup with `53300`, except for cancel requests. As many connections at most may be
+//! starting up, the server stops accepting while they are, and those still at it after `timeouts.authentication`
+//! are closed. On shutdown the listeners are closed, running statements are canceled and every session ends after the
+//! message it is working on. Sessions still running after `timeouts.shutdown` are aborted.
+
+use crate::config::Config;
+use crate::executor::Executor;
+use crate::protocol::codec::ProtocolError;
+use crate::protocol::{mysql, postgres};
+use crate::{debug, info, warn};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, watch};
+use tokio::task::JoinSet;
+use tokio_rustls::TlsAcceptor;
+
+/// Tells sessions that the server is shutting down.
+#[derive(Clone, Debug)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// A shutdown that is never requested.
+    pub fn never() -> Self {
+        let (_, receiver) = watch::channel(false);
+        Shutdown(receiver)
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Completes once shutdown is requested.
+    pub async fn requested(&mut self) {
+        while !self.is_requested() {
+            if self.0.changed().await.is_err() {
+                // Nobody is left to request it.
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+/// Why a session stopped waiting for its client.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interrupt {
+    Shutdown,
+    IdleTimeout,
+}
+
+/// Waits for `read`, unless the server shuts down or the client stays quiet for longer than `idle_timeout` first.
+pub async fn read_or_interrupt<F: Future>(
+    read: F,
+    shutdown: &mut Shutdown,
+    idle_timeout: Option<Duration>,
+) -> Result<F::Output, Interrupt> {
+    let idle = async {
+        match idle_timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    tokio::select! {
+        output = read => Ok(output),
+        _ = shutdown.requested() => Err(Interrupt::Shutdown),
+        _ = idle => Err(Interrupt::IdleTimeout),
+    }
+}
+
+/// Runs the startup of a session, which fails once it took longer than `timeout`.
+pub async fn startup_within<F: Future<Output = Result<bool, ProtocolError>>>(
+    startup: F,
+    timeout: Option<Duration>,
+) -> Result<bool, ProtocolError> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, startup).await.unwrap_or_else(|_| {
+            Err(ProtocolError::Malformed(
+                "canceling authentication due to timeout".to_string(),
+            ))
+        }),
+        None => startup.await,
+    }
+}
+
+pub struct Server {
+    config: Arc<Config>,
+    executor: Arc<Executor>,
+    postgres: TcpListener,
+    mysql: Option<TcpListener>,
+    tls: Option<TlsAcceptor>,
+}
+
+impl Server {
+    /// Opens the listeners of the configured protocols.
+    pub async fn bind(config: Arc<Config>, executor: Arc<Executor>) -> std::io::Result<Self> {
+        let postgres = TcpListener::bind((config.server.listen_address, config.server.port)).await?;
+        let mysql = match config.server.mysql_port {
+            Some(port) => Some(TcpListener::bind((config.server.listen_address, port)).await?),
+            None => None,
+        };
+
+        Ok(Server {
+            config,
+            executor,
+            postgres,
+            mysql,
+            tls: None,
+        })
+    }
+
+    /// Offers TLS to clients of both protocols.
+    pub fn with_tls(mut self, tls: Option<TlsAcceptor>) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    pub fn postgres_addr(&self) -> std::io::Result<SocketAddr> {
+        self.postgres.local_addr()
+    }
+
+    pub fn mysql_addr(&self) -> Option<std::io::Result<SocketAddr>> {
+        self.mysql.as_ref().map(TcpListener::local_addr)
+    }
+
+    /// Serves clients until `signal` completes, then shuts down.
+    pub async fn run<F: Future<Output = ()>>(self, signal: F) {
+        let permits = Arc::new(Semaphore::new(self.config.server.max_connections as usize));
+        let starting = Arc::new(Semaphore::new(self.config.server.max_connections as usize));
+        let (shutdown_sender, shutdown) = watch::channel(false);
+        let shutdown = Shutdown(shutdown);
+        let mut sessions = JoinSet::new();
+        tokio::pin!(signal);
+
+        loop {
+            // Sessions that ended on their own.
+            while sessions.try_join_next().is_some() {}
+
+            // Clients that never get through startup hold their slot until they time out.
+            let startup = tokio::select! {
+                permit = starting.clone().acquire_owned() => permit.expect("the semaphore is never closed"),
+                _ = &mut signal => break,
+            };
+            let accepted = tokio::select! {
+                accepted = self.postgres.accept() => accepted.map(|(socket, addr)| (socket, addr, Protocol::Postgres)),
+                accepted = accept(self.mysql.as_ref()) => accepted.map(|(socket, addr)| (socket, addr, Protocol::MySql)),
+                _ = &mut signal => break,
+            };
+
+            let (socket, addr, protocol) = match accepted {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    // Usually out of file descriptors, give sessions a moment to close theirs.
+                    warn!("could not accept a connection: {err}");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+
+            debug!("{protocol:?} connection from {addr}");
+            let _ = socket.set_nodelay(true);
+            let executor = self.executor.clone();
+            let config = self.config.clone();
+            let shutdown = shutdown.clone();
+            let tls = self.tls.clone();
+            let permits = permits.clone();
+            sessions.spawn(async move {
+                let permits = (permits, startup);
+                let result = serve(protocol, socket, &executor, &config, tls, shutdown, permits).await;
+                match result {
+                    Ok(()) => debug!("connection from {addr} closed"),
+                    Err(ProtocolError::Io(err)) => debug!("connection from {addr} failed: {err}"),
+                    Err(err @ ProtocolError::Malformed(_)) => warn!("connection from {addr} closed: {err}"),
+                }
+            });
+        }
+
+        drop(self.postgres);
+        drop(self.mysql);
+        while sessions.try_join_next().is_some() {}
+        info!("shutting down with {0} open sessions", sessions.len());
+        let _ = shutdown_sender.send(true);
+        // Statements may run for long, and block their task until they reach a checkpoint.
+        self.executor.terminate_sessions();
+
+        let drained = tokio::time::timeout(self.config.timeouts.shutdown, async {
+            while sessions.join_next().await.is_some() {}
+        })
+        .await;
+        if drained.is_err() {
+            warn!("aborting {0} sessions that did not finish in time", sessions.len());
+            sessions.shutdown().await;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Protocol {
+    Postgres,
+    MySql,
+}
+
+/// Accepts on the listener if there is one, never completes otherwise.
+async fn accept(listener: Option<&TcpListener>) -> std::io::Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn serve(
+    protocol: Protocol,
+    socket: TcpStream,
+    executor: &Executor,
+    config: &Config,
+    tls: Option<TlsAcceptor>,
+    shutdown: Shutdown,
+    (permits, startup): (Arc<Semaphore>, OwnedSemaphorePermit),
+) -> Result<(), ProtocolError> {
+    match protocol {
+        Protocol::Postgres => {
+            postgres::session::Session::new(socket, executor, config)
+                .with_tls(tls)
+                .with_shutdown(shutdown)
+                .with_permits(permits)
+                .with_startup_permit(startup)
+                .run()
+                .await
+        }
+        Protocol::MySql => {
+            mysql::session::Session::new(socket, executor, config)
+                .with_tls(tls)
+                .with_shutdown(shutdown)
+                .with_permits(permits)
+                .with_startup_permit(startup)
+                .run()
+                .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::catalog::Catalog;
+    use crate::executor::TransactionStatus;
+    use crate::protocol::codec::{MAX_FRAME_LEN, read_frame, write_frame, write_untyped_frame};
+    use crate::protocol::postgres::FrontendMessage;
+    use crate::protocol::postgres::{BackendMessage, PROTOCOL_VERSION, StartupMessage};
+    use crate::stats::StatementStats;
+    use crate::storage::lock::{LockMode, LockTarget};
+    use crate::tls::testing;
+    use crate::value::Value;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+    use tokio::sync::oneshot;
+
+    async fn start(config: Config) -> (SocketAddr, oneshot::Sender<()>, tokio::task::JoinHandle<()>) {
+        let executor = Executor::new(Arc::new(StatementStats::new()), Catalog::bootstrap("rdb", None));
+        start_with(config, Arc::new(executor)).await
+    }
+
+    async fn start_with(
+        config: Config,
+        executor: Arc<Executor>,
+    ) -> (SocketAddr, oneshot::Sender<()>, tokio::task::JoinHandle<()>) {
+        let config = Config {
+            server: crate::config::ServerConfig {
+                port: 0,
+                ..config.server
+            },
+            auth: crate::config::AuthConfig {
+                method: crate::config::AuthMethod::Trust,
+                ..config.auth
+            },
+            ..config
+        };
+        let tls = crate::tls::acceptor(&config.tls).unwrap();
+        let server = Server::bind(Arc::new(config), executor).await.unwrap().with_tls(tls);
+        let addr = server.postgres_addr().unwrap();
+
+        let (stop, stopped) = oneshot::channel();
+        let handle = tokio::spawn(server.run(async {
+            let _ = stopped.await;
+        }));
+        (addr, stop, handle)
+    }
+
+    /// Connects and sends the startup message.
+    async fn connect(addr: SocketAddr) -> TcpStream {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        send_startup(&mut stream).await;
+        stream
+    }
+
+    async fn send_startup<S: AsyncWrite + Unpin>(stream: &mut S) {
+        let startup = StartupMessage::Startup {
+            version: PROTOCOL_VERSION,
+            params: vec![("user".to_string(), "rdb".to_string())],
+        };
+        write_untyped_frame(stream, &startup.encode()).await.unwrap();
+        stream.flush().await.unwrap();
+    }
+
+    /// Sends an `SSLRequest`, and returns the answer of the server.
+    async fn request_tls(stream: &mut TcpStream) -> u8 {
+        write_untyped_frame(stream, &StartupMessage::SslRequest.encode())
+            .await
+            .unwrap();
+        stream.flush().await.unwrap();
+        stream.read_u8().await.unwrap()
+    }
+
+    async fn receive<S: AsyncRead + Unpin>(stream: &mut S) -> Option<BackendMessage> {
+        loop {
+            let frame = read_frame(stream, MAX_FRAME_LEN).await.ok()??;
+            match BackendMessage::decode(&frame) {
+                // Startup noise.
+                Ok(
+                    BackendMessage::AuthenticationOk
+                    | BackendMessage::ParameterStatus { .. }
+                    | BackendMessage::BackendKeyData { .. },
+                )
+                | Err(_) => continue,
+                Ok(msg) => return Some(msg),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connection_limit() {
+        let mut config = Config::default();
+        config.server.max_connections = 1;
+        let (addr, stop, server) = start(config).await;
+
+        let mut first = connect(addr).await;
+        assert_eq!(
+            receive(&mut first).await,
+            Some(BackendMessage::ReadyForQuery(TransactionStatus::Idle))
+        );
+
+        // Turned away while the first connection is open.
+        let mut second = connect(addr).await;
+        match receive(&mut second).await {
+            Some(BackendMessage::ErrorResponse(err)) => {
+                assert_eq!(err.code.as_str(), "53300");
+                assert_eq!(err.message, "sorry, too many clients already");
+            }
+            msg => panic!("expected an error, got {msg:?}"),
+        }
+
+        // Cancel requests are still served, the connection closes without an answer.
+        let mut cancel = TcpStream::connect(addr).await.unwrap();
+        let request = StartupMessage::Cancel {
+            process_id: 0,
+            secret_key: 0,
+        };
+        write_untyped_frame(&mut cancel, &request.encode()).await.unwrap();
+        cancel.flush().await.unwrap();
+        assert_eq!(receive(&mut cancel).await, None);
+
+        drop(first);
+        let start = tokio::time::Instant::now();
+        let third = loop {
+            // The first session ends once the server notices the closed connection.
+            let mut third = connect(addr).await;
+            match receive(&mut third).await {
+                Some(BackendMessage::ReadyForQuery(TransactionStatus::Idle)) => break third,
+                _ => assert!(start.elapsed() < Duration::from_secs(10)),
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        drop(third);
+
+        stop.send(()).unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_startup_timeout() {
+        let mut config = Config::default();
+        config.server.max_connections = 1;
+        config.timeouts.authentication = Some(Duration::from_millis(100));
+        let (addr, stop, server) = start(config).await;
+
+        // A client that never starts up keeps others from doing so, until it is closed.
+        let mut silent = TcpStream::connect(addr).await.unwrap();
+        let start = tokio::time::Instant::now();
+        let mut client = connect(addr).await;
+        assert!(matches!(receive(&mut client).await, Some(BackendMessage::ReadyForQuery(_))));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(silent.read(&mut [0; 1]).await.unwrap(), 0);
+
+        stop.send(()).unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let (addr, stop, server) = start(Config::default()).await;
+
+        let mut client = connect(addr).await;
+        assert!(matches!(receive(&mut client).await, Some(BackendMessage::ReadyForQuery(_))));
+
+        stop.send(()).unwrap();
+        match receive(&mut client).await {
+            Some(BackendMessage::ErrorResponse(err)) => assert_eq!(err.code.as_str(), "57P01"),
+            msg => panic!("expected an error, got {msg:?}"),
+        }
+        assert_eq!(receive(&mut client).await, None);
+
+        server.await.unwrap();
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_shutdown_cancels_statements() {
+        let executor = Arc::new(Executor::new(
+            Arc::new(StatementStats::new()),
+            Catalog::bootstrap("rdb", None),
+        ));
+        // Held by no session, so nothing releases it.
+        let target = LockTarget::Table(crate::stats::STAT_STATEMENTS_TABLE.to_string());
+        executor
+            .locks()
+            .lock(u32::MAX, &target, LockMode::Exclusive, None, &|| false)
+            .unwrap();
+        let (addr, stop, server) = start_with(Config::default(), executor.clone()).await;
+
+        let mut client = connect(addr).await;
+        assert!(matches!(receive(&mut client).await, Some(BackendMessage::ReadyForQuery(_))));
+        let query = FrontendMessage::Query("SELECT * FROM rdb_stat_statements".to_string()).encode();
+        write_frame(&mut client, query.tag, &query.body).await.unwrap();
+        client.flush().await.unwrap();
+        while executor.locks().rows().iter().all(|row| row[5] == Value::Bool(true)) {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        // The statement waits for as long as it takes, yet the server stops well before `timeouts.shutdown`.
+        let start = tokio::time::Instant::now();
+        stop.send(()).unwrap();
+        match receive(&mut client).await {
+            Some(BackendMessage::ErrorResponse(err)) => {
+                assert_eq!(err.code.as_str(), "57P01");
+                assert_eq!(err.message, "terminating connection due to administrator command");
+            }
+            msg => panic!("expected an error, got {msg:?}"),
+        }
+        server.await.unwrap();
+        assert!(start.elapsed() < Config::default().timeouts.shutdown);
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let mut config = Config::default();
+        config.timeouts.idle_session = Some(Duration::from_millis(50));
+        let (addr, stop, server) = start(config).await;
+
+        let mut client = connect(addr).await;
+        assert!(matches!(receive(&mut client).await, Some(BackendMessage::ReadyForQuery(_))));
+        match receive(&mut client).await {
+            Some(BackendMessage::ErrorResponse(err)) => assert_eq!(err.code.as_str(), "57P05"),
+            msg => panic!("expected an error, got {msg:?}"),
+        }
+
+        stop.send(()).unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tls() {
+        let (tls, certificate) = testing::config();
+        let config = Config {
+            tls: crate::config::TlsConfig {
+                required: true,
+                ..tls.clone()
+            },
+            ..Config::default()
+        };
+        let (addr, stop, server) = start(config).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        assert_eq!(request_tls(&mut stream).await, b'S');
+        let mut client = testing::connect(stream, &certificate).await.unwrap();
+        send_startup(&mut client).await;
+        assert!(matches!(receive(&mut client).await, Some(BackendMessage::ReadyForQuery(_))));
+
+        let query = FrontendMessage::Query("SELECT query FROM rdb_stat_statements".to_string()).encode();
+        write_frame(&mut client, query.tag, &query.body).await.unwrap();
+        client.flush().await.unwrap();
+        assert!(matches!(receive(&mut client).await, Some(BackendMessage::RowDescription(_))));
+
+        // Plain text is turned away.
+        let mut plain = connect(addr).await;
+        match receive(&mut plain).await {
+            Some(BackendMessage::ErrorResponse(err)) => {
+                assert_eq!(err.code.as_str(), "28000");
+                assert_eq!(err.message, "connection requires TLS, none was requested");
+            }
+            msg => panic!("expected an error, got {msg:?}"),
+        }
+
+        // A client that does not trust the certificate gives up during the handshake.
+        let (other, _) = testing::self_signed();
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        assert_eq!(request_tls(&mut stream).await, b'S');
+        assert!(testing::connect(stream, &other).await.is_err());
+
+        stop.send(()).unwrap();
+        server.await.unwrap();
+        std::fs::remove_file(tls.certificate.unwrap()).unwrap();
+        std::fs::remove_file(tls.key.unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tls_not_configured() {
+        let (addr, stop, server) = start(Config::default()).await;
+
+        // The client may go on in plain text.
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        assert_eq!(request_tls(&mut stream).await, b'N');
+        send_startup(&mut stream).await;
+        assert!(matches!(receive(&mut stream).await, Some(BackendMessage::ReadyForQuery(_))));
+
+        stop.send(()).unwrap();
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+//! Per query shape execution statistics, in the spirit of `pg_stat_statements`.
+//!
+//! At most a configured number of query shapes are tracked. A new one that does not fit makes room by dropping the
+//! least called twentieth of those tracked.
+
+use crate::parser::fingerprint::Fingerprint;
+use crate::parser::token::DataKind;
+use crate::value::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Name of the system table the statistics are exposed through.
+pub const STAT_STATEMENTS_TABLE: &str = "rdb_stat_statements";
+
+pub const STAT_STATEMENTS_COLUMNS: [(&str, DataKind); 5] = [
+    ("queryid", DataKind::BigInt(None)),
+    ("query", DataKind::Text(None)),
+    ("calls", DataKind::BigInt(None)),
+    ("total_time_ms", DataKind::Double(None, None)),
+    ("rows", DataKind::BigInt(None)),
+];
+
+/// Query shapes tracked unless configured otherwise.
+pub const STAT_STATEMENTS_MAX: usize = 5000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatementEntry {
+    pub id: u64,
+    pub query: String,
+    pub calls: u64,
+    pub total_time: Duration,
+    pub rows: u64,
+}
+
+#[derive(Debug)]
+pub struct StatementStats {
+    /// Query shapes tracked at most.
+    max: usize,
+    entries: Mutex<HashMap<u64, StatementEntry>>,
+}
+
+impl Default for StatementStats {
+    fn default() -> Self {
+        StatementStats::with_max(STAT_STATEMENTS_MAX)
+    }
+}
+
+impl StatementStats {
+    pub fn new() -> Self {
+        StatementStats::default()
+    }
+
+    /// Tracks at most `max` query shapes, which must be positive.
+    pub fn with_max(max: usize) -> Self {
+        StatementStats {
+            max,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, fingerprint: &Fingerprint, elapsed: Duration, rows: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.max && !entries.contains_key(&fingerprint.id) {
+            // The least called go, ties broken by id so that the choice does not depend on the map.
+            let mut called: Vec<(u64, u64)> = entries.values().map(|entry| (entry.calls, entry.id)).collect();
+            called.sort_unstable();
+            let dropped = (entries.len() / 20).max(entries.len() + 1 - self.max);
+            for (_, id) in &called[..dropped] {
+                entries.remove(id);
+            }
+        }
+        let entry = entries.entry(fingerprint.id).or_insert_with(|| StatementEntry {
+            id: fingerprint.id,
+            query: fingerprint.text.clone(),
+            calls: 0,
+            total_time: Duration::ZERO,
+            rows: 0,
+        });
+
+        entry.calls += 1;
+        entry.total_time += elapsed;
+        entry.rows += rows;
+    }
+
+    /// Snapshot of every entry, most called first.
+    pub fn entries(&self) -> Vec<StatementEntry> {
+        let mut entries: Vec<StatementEntry> = self.entries.lock().unwrap().values().cloned().collect();
+        entries.sort_by(|a, b| b.calls.cmp(&a.calls).then(a.id.cmp(&b.id)));
+        entries
+    }
+
+    pub fn reset(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Rows of the system table, in the order of [`STAT_STATEMENTS_COLUMNS`].
+    pub fn rows(&self) -> Vec<Vec<Value>> {
+        self.entries()
+            .into_iter()
+            .map(|e| {
+                vec![
+                    // Reported as signed like Postgres so clients can store it in a BIGINT.
+                    Value::Int(e.id as i64),
+                    Value::Text(e.query),
+                    Value::Int(e.calls as i64),
+                    Value::Float(e.total_time.as_secs_f64() * 1000.0),
+                    Value::Int(e.rows as i64),
+                ]
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn fingerprint(id: u64, text: &str) -> Fingerprint {
+        Fingerprint {
+            id,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_aggregates_by_fingerprint() {
+        let stats = StatementStats::new();
+        let select = fingerprint(1, "SELECT * FROM cats WHERE age > $1");
+        let commit = fingerprint(2, "COMMIT");
+
+        stats.record(&select, Duration::from_millis(2), 3);
+        stats.record(&select, Duration::from_millis(5), 1);
+        stats.record(&commit, Duration::from_millis(1), 0);
+
+        let entries = stats.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0],
+            StatementEntry {
+                id: 1,
+                query: "SELECT * FROM cats WHERE age > $1".to_string(),
+                calls: 2,
+                total_time: Duration::from_millis(7),
+                rows: 4,
+            }
+        );
+        assert_eq!(stats.rows()[0][3], Value::Float(7.0));
+
+        stats.reset();
+        assert!(stats.entries().is_empty());
+    }
+
+    #[test]
+    fn test_drops_least_called() {
+        let stats = StatementStats::with_max(40);
+        for id in 0..40 {
+            for _ in 0..=id {
+                stats.record(&fingerprint(id, "SELECT $1"), Duration::ZERO, 0);
+            }
+        }
+        assert_eq!(stats.entries().len(), 40);
+
+        // The two least called make room, calls to those tracked do not.
+        stats.record(&fingerprint(40, "COMMIT"), Duration::ZERO, 0);
+        stats.record(&fingerprint(2, "SELECT $1"), Duration::ZERO, 0);
+        let entries = stats.entries();
+        assert_eq!(entries.len(), 39);
+        assert!(entries.iter().all(|entry| entry.id >= 2));
+        assert_eq!(entries.iter().find(|entry| entry.id == 2).unwrap().calls, 4);
+    }
+}
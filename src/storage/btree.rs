@@ -0,0 +1,877 @@
+//! B+tree indexes, the ids of rows ordered by a key of some of their values.
+//!
+//! The first page of the file holds the root page, the height of the tree, the number of key columns and whether
+//! keys are unique. The other pages are nodes, which after the common page header hold:
+//!
+//! | bytes    | field                                                           |
+//! |----------|-----------------------------------------------------------------|
+//! | 16..18   | level, zero for leaves                                          |
+//! | 18..20   | number of cells                                                 |
+//! | 20..22   | offset of the first byte of the cells                           |
+//! | 22..26   | previous leaf, or the first child of an inner node              |
+//! | 26..30   | next leaf                                                       |
+//! | 30..     | offsets of the cells in key order                               |
+//!
+//! A cell is the length of its key as a `u16`, the key, and the row it points to in a leaf or the child holding
+//! the keys from it on in an inner node. Cells are packed at the end of the page like the records of a heap page.
+//!
+//! Keys are the values encoded by [`encode_key`], followed by the row id when the index is not unique or the key
+//! has a NULL, which is never equal to another one. Every entry of the tree is then different, and an insert into
+//! a unique index finds the key it duplicates in the leaf it goes to.
+//!
+//! Readers latch their way down holding a node until its child is latched. Writers do the same with the leaf
+//! latched for writing, and when it has no room start over latching every node on the way for writing, letting
+//! go of the ones above a node that has room for another key. Scans latch one leaf at a time. Emptied nodes are
+//! kept rather than merged.
+
+use crate::storage::buffer::{BufferPool, PageReadGuard, PageWriteGuard};
+use crate::storage::key::{decode_key, encode_key, prefix_end};
+use crate::storage::page::{PAGE_HEADER_LEN, PAGE_SIZE, Page, PageKind};
+use crate::storage::row::RowError;
+use crate::storage::{FileId, PageId, RowId, StorageError};
+use crate::value::Value;
+use std::collections::VecDeque;
+use std::ops::{Bound, Deref, DerefMut};
+
+const META_PAGE: u32 = 0;
+const ROOT: usize = PAGE_HEADER_LEN;
+const HEIGHT: usize = PAGE_HEADER_LEN + 4;
+const COLUMNS: usize = PAGE_HEADER_LEN + 6;
+const UNIQUE: usize = PAGE_HEADER_LEN + 8;
+
+const LEVEL: usize = PAGE_HEADER_LEN;
+const CELL_COUNT: usize = PAGE_HEADER_LEN + 2;
+const CELLS_START: usize = PAGE_HEADER_LEN + 4;
+const LEFT: usize = PAGE_HEADER_LEN + 6;
+const RIGHT: usize = PAGE_HEADER_LEN + 10;
+const POINTERS: usize = PAGE_HEADER_LEN + 14;
+const POINTER_LEN: usize = 2;
+const NO_PAGE: u32 = u32::MAX;
+
+const ROW_ID_LEN: usize = 6;
+/// Space a leaf cell takes besides its key.
+const CELL_OVERHEAD: usize = POINTER_LEN + 2 + ROW_ID_LEN;
+
+/// Longest key, row id included, so that a node always holds at least four.
+pub const MAX_KEY_LEN: usize = (PAGE_SIZE - POINTERS) / 4 - CELL_OVERHEAD;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScanDirection {
+    Forward,
+    Backward,
+}
+
+/// Where a search ends up in the tree.
+#[derive(Clone, Copy)]
+enum Target<'a> {
+    /// The leaf that holds the key.
+    At(&'a [u8]),
+    /// The leaf that holds the last key before this one.
+    Before(&'a [u8]),
+    Last,
+}
+
+/// The cells of a node page.
+struct Node<P> {
+    page: P,
+    id: PageId,
+}
+
+impl<P: Deref<Target = Page>> Node<P> {
+    /// Checks that the page is a node whose cells stay inside of it.
+    fn open(page: P, id: PageId) -> Result<Self, StorageError> {
+        let corrupt = |message: &str| Err(StorageError::Corrupt(id, message.to_string()));
+        if page.kind() != Some(PageKind::BTreeNode) {
+            return corrupt("not a b-tree node");
+        }
+
+        let node = Node { page, id };
+        let pointers_end = POINTERS + POINTER_LEN * node.count();
+        if pointers_end > node.cells_start() || node.cells_start() > PAGE_SIZE {
+            return corrupt("cell offsets overlap the cells");
+        }
+        for i in 0..node.count() {
+            let offset = node.offset(i);
+            if offset < node.cells_start()
+                || offset + 2 > PAGE_SIZE
+                || offset + 2 + node.page.get_u16(offset) as usize + node.value_len() > PAGE_SIZE
+            {
+                return corrupt("cell outside of the page");
+            }
+        }
+        Ok(node)
+    }
+
+    fn level(&self) -> u16 {
+        self.page.get_u16(LEVEL)
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.level() == 0
+    }
+
+    fn count(&self) -> usize {
+        self.page.get_u16(CELL_COUNT) as usize
+    }
+
+    fn cells_start(&self) -> usize {
+        self.page.get_u16(CELLS_START) as usize
+    }
+
+    fn left(&self) -> u32 {
+        self.page.get_u32(LEFT)
+    }
+
+    fn right(&self) -> u32 {
+        self.page.get_u32(RIGHT)
+    }
+
+    fn value_len(&self) -> usize {
+        if self.is_leaf() { ROW_ID_LEN } else { 4 }
+    }
+
+    fn offset(&self, i: usize) -> usize {
+        self.page.get_u16(POINTERS + POINTER_LEN * i) as usize
+    }
+
+    fn cell(&self, i: usize) -> &[u8] {
+        let offset = self.offset(i);
+        let len = 2 + self.page.get_u16(offset) as usize + self.value_len();
+        &self.page.data()[offset..offset + len]
+    }
+
+    fn key(&self, i: usize) -> &[u8] {
+        let cell = self.cell(i);
+        &cell[2..cell.len() - self.value_len()]
+    }
+
+    fn row(&self, i: usize) -> RowId {
+        let cell = self.cell(i);
+        let row = &cell[cell.len() - ROW_ID_LEN..];
+        RowId::new(
+            u32::from_le_bytes(row[..4].try_into().unwrap()),
+            u16::from_le_bytes(row[4..].try_into().unwrap()),
+        )
+    }
+
+    fn child(&self, i: usize) -> u32 {
+        let cell = self.cell(i);
+        u32::from_le_bytes(cell[cell.len() - 4..].try_into().unwrap())
+    }
+
+    /// Position of the key, or where it would go.
+    fn search(&self, key: &[u8]) -> Result<usize, usize> {
+        let (mut low, mut high) = (0, self.count());
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.key(mid).cmp(key) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Ok(mid),
+            }
+        }
+        Err(low)
+    }
+
+    /// Child of an inner node to go down to.
+    fn child_for(&self, target: Target) -> u32 {
+        // Number of cells whose key is before the target, the child of the last one holds it.
+        let before = match target {
+            Target::At(key) => self.search(key).map_or_else(|i| i, |i| i + 1),
+            Target::Before(key) => self.search(key).unwrap_or_else(|i| i),
+            Target::Last => self.count(),
+        };
+        match before {
+            0 => self.left(),
+            i => self.child(i - 1),
+        }
+    }
+
+    /// Room left for cells and their offsets, whether contiguous or not.
+    fn free_space(&self) -> usize {
+        let cells: usize = (0..self.count()).map(|i| self.cell(i).len()).sum();
+        PAGE_SIZE - POINTERS - POINTER_LEN * self.count() - cells
+    }
+
+    /// Whether another key of any length fits, so a split below cannot make this node split.
+    fn has_room(&self) -> bool {
+        self.free_space() >= MAX_KEY_LEN + CELL_OVERHEAD
+    }
+}
+
+impl<P: DerefMut<Target = Page>> Node<P> {
+    fn init(mut page: P, id: PageId, level: u16, left: u32, right: u32) -> Self {
+        page.set_kind(PageKind::BTreeNode);
+        page.put_u16(LEVEL, level);
+        page.put_u16(CELL_COUNT, 0);
+        page.put_u16(CELLS_START, PAGE_SIZE as u16);
+        page.put_u32(LEFT, left);
+        page.put_u32(RIGHT, right);
+        Node { page, id }
+    }
+
+    fn set_left(&mut self, page: u32) {
+        self.page.put_u32(LEFT, page);
+    }
+
+    fn set_right(&mut self, page: u32) {
+        self.page.put_u32(RIGHT, page);
+    }
+
+    /// Inserts the cell at position `i`, `false` when it does not fit.
+    fn insert(&mut self, i: usize, cell: &[u8]) -> bool {
+        if cell.len() + POINTER_LEN > self.free_space() {
+            return false;
+        }
+        let count = self.count();
+        if POINTERS + POINTER_LEN * (count + 1) + cell.len() > self.cells_start() {
+            self.compact();
+        }
+
+        let offset = self.cells_start() - cell.len();
+        self.page.data_mut()[offset..offset + cell.len()].copy_from_slice(cell);
+        self.page.put_u16(CELLS_START, offset as u16);
+        let pointer = POINTERS + POINTER_LEN * i;
+        self.page
+            .data_mut()
+            .copy_within(pointer..POINTERS + POINTER_LEN * count, pointer + POINTER_LEN);
+        self.page.put_u16(pointer, offset as u16);
+        self.page.put_u16(CELL_COUNT, count as u16 + 1);
+        true
+    }
+
+    fn remove(&mut self, i: usize) {
+        let count = self.count();
+        let pointer = POINTERS + POINTER_LEN * i;
+        self.page
+            .data_mut()
+            .copy_within(pointer + POINTER_LEN..POINTERS + POINTER_LEN * count, pointer);
+        self.page.put_u16(CELL_COUNT, count as u16 - 1);
+    }
+
+    /// Replaces every cell, which must fit.
+    fn set_cells(&mut self, cells: &[Vec<u8>]) {
+        self.page.put_u16(CELL_COUNT, 0);
+        self.page.put_u16(CELLS_START, PAGE_SIZE as u16);
+        for (i, cell) in cells.iter().enumerate() {
+            assert!(self.insert(i, cell), "cells of a split fit in a node");
+        }
+    }
+
+    /// Packs the cells at the end of the page, so that all free space is in one piece.
+    fn compact(&mut self) {
+        let cells: Vec<Vec<u8>> = (0..self.count()).map(|i| self.cell(i).to_vec()).collect();
+        self.set_cells(&cells);
+    }
+}
+
+fn leaf_cell(key: &[u8], row: RowId) -> Vec<u8> {
+    let mut cell = Vec::with_capacity(2 + key.len() + ROW_ID_LEN);
+    cell.extend_from_slice(&(key.len() as u16).to_le_bytes());
+    cell.extend_from_slice(key);
+    cell.extend_from_slice(&row.page.to_le_bytes());
+    cell.extend_from_slice(&row.slot.to_le_bytes());
+    cell
+}
+
+fn inner_cell(key: &[u8], child: u32) -> Vec<u8> {
+    let mut cell = Vec::with_capacity(2 + key.len() + 4);
+    cell.extend_from_slice(&(key.len() as u16).to_le_bytes());
+    cell.extend_from_slice(key);
+    cell.extend_from_slice(&child.to_le_bytes());
+    cell
+}
+
+/// An index stored in its own file.
+pub struct BTree {
+    pool: BufferPool,
+    file: FileId,
+    columns: usize,
+    unique: bool,
+}
+
+impl BTree {
+    /// A new empty index of keys of `columns` values.
+    pub fn create(pool: BufferPool, columns: usize, unique: bool) -> Result<Self, StorageError> {
+        let file = pool.files().create_file()?;
+        let (_, mut meta) = pool.new_page(file)?;
+        let (root, guard) = pool.new_page(file)?;
+        Node::init(guard, PageId::new(file, root), 0, NO_PAGE, NO_PAGE);
+
+        meta.set_kind(PageKind::BTreeMeta);
+        meta.put_u32(ROOT, root);
+        meta.put_u16(HEIGHT, 0);
+        meta.put_u16(COLUMNS, columns as u16);
+        meta.data_mut()[UNIQUE] = unique as u8;
+        Ok(BTree {
+            pool,
+            file,
+            columns,
+            unique,
+        })
+    }
+
+    pub fn open(pool: BufferPool, file: FileId) -> Result<Self, StorageError> {
+        let id = PageId::new(file, META_PAGE);
+        let meta = pool.fetch_read(id)?;
+        if meta.kind() != Some(PageKind::BTreeMeta) {
+            return Err(StorageError::Corrupt(id, "not a b-tree".to_string()));
+        }
+        let (columns, unique) = (meta.get_u16(COLUMNS) as usize, meta.data()[UNIQUE] != 0);
+        drop(meta);
+
+        Ok(BTree {
+            pool,
+            file,
+            columns,
+            unique,
+        })
+    }
+
+    pub fn file(&self) -> FileId {
+        self.file
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn is_unique(&self) -> bool {
+        self.unique
+    }
+
+    /// Adds the key of a row. Fails on a unique index that already has the key, unless it has a NULL.
+    pub fn insert(&self, key: &[Value], row: RowId) -> Result<(), StorageError> {
+        let entry = self.entry(key, row)?;
+        let cell = leaf_cell(&entry, row);
+        let duplicate = || StorageError::DuplicateKey(key.to_vec());
+
+        {
+            let mut leaf = self.leaf_for_write(&entry)?;
+            match leaf.search(&entry) {
+                Ok(_) => return Err(duplicate()),
+                Err(i) if leaf.insert(i, &cell) => return Ok(()),
+                Err(_) => {}
+            }
+        }
+
+        // The leaf is full, the nodes it splits into are latched from the top.
+        let mut meta = Some(self.pool.fetch_write(self.page_id(META_PAGE))?);
+        let mut page = meta.as_ref().unwrap().get_u32(ROOT);
+        let mut path = Vec::new();
+        loop {
+            let node = self.write(page)?;
+            if node.has_room() {
+                meta = None;
+                path.clear();
+            }
+            let next = (!node.is_leaf()).then(|| node.child_for(Target::At(&entry)));
+            self.check_child(path.last(), &node)?;
+            path.push(node);
+            match next {
+                Some(child) => page = child,
+                None => break,
+            }
+        }
+
+        let mut node = path.pop().unwrap();
+        let i = match node.search(&entry) {
+            Ok(_) => return Err(duplicate()),
+            Err(i) => i,
+        };
+        if node.insert(i, &cell) {
+            return Ok(());
+        }
+        let (mut separator, mut right) = self.split(&mut node, i, cell)?;
+        while let Some(mut parent) = path.pop() {
+            let cell = inner_cell(&separator, right);
+            let i = parent.search(&separator).unwrap_or_else(|i| i);
+            if parent.insert(i, &cell) {
+                return Ok(());
+            }
+            (separator, right) = self.split(&mut parent, i, cell)?;
+        }
+
+        // The root split, the tree grows a level.
+        let mut meta = meta.expect("the meta page stays latched until a node with room");
+        let height = meta.get_u16(HEIGHT) + 1;
+        let (root, guard) = self.pool.new_page(self.file)?;
+        let mut node = Node::init(guard, self.page_id(root), height, meta.get_u32(ROOT), NO_PAGE);
+        node.insert(0, &inner_cell(&separator, right));
+        meta.put_u32(ROOT, root);
+        meta.put_u16(HEIGHT, height);
+        Ok(())
+    }
+
+    /// Removes the key of a row, returns whether it was there.
+    pub fn delete(&self, key: &[Value], row: RowId) -> Result<bool, StorageError> {
+        let entry = self.entry(key, row)?;
+        let mut leaf = self.leaf_for_write(&entry)?;
+        match leaf.search(&entry) {
+            Ok(i) if leaf.row(i) == row => {
+                leaf.remove(i);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Rows with the key.
+    pub fn get(&self, key: &[Value]) -> Result<Vec<RowId>, StorageError> {
+        self.check_columns(key, self.columns)?;
+        self.prefix_scan(key, ScanDirection::Forward)
+            .map(|entry| entry.map(|(_, row)| row))
+            .collect()
+    }
+
+    /// Keys and rows between the bounds, which may be prefixes of the keys: with an index on `(a, b)`, the
+    /// lower bound `(1)` excluded starts after every key where `a` is 1.
+    pub fn scan(&self, lower: Bound<&[Value]>, upper: Bound<&[Value]>, direction: ScanDirection) -> BTreeScan<'_> {
+        let lower = match lower {
+            Bound::Included(key) => Some(encode_key(key)),
+            Bound::Excluded(key) => prefix_end(&encode_key(key)),
+            Bound::Unbounded => Some(Vec::new()),
+        };
+        let upper = match upper {
+            Bound::Included(key) => prefix_end(&encode_key(key)),
+            Bound::Excluded(key) => Some(encode_key(key)),
+            Bound::Unbounded => None,
+        };
+        // A lower bound past every key leaves nothing.
+        let done = lower.is_none();
+        BTreeScan::new(self, lower.unwrap_or_default(), upper, direction, done)
+    }
+
+    /// Keys that start with the values of `prefix`, and their rows.
+    pub fn prefix_scan(&self, prefix: &[Value], direction: ScanDirection) -> BTreeScan<'_> {
+        self.scan(Bound::Included(prefix), Bound::Included(prefix), direction)
+    }
+
+    /// The key of the row as stored in the tree.
+    fn entry(&self, key: &[Value], row: RowId) -> Result<Vec<u8>, StorageError> {
+        self.check_columns(key, self.columns)?;
+        let mut entry = encode_key(key);
+        if !self.unique || key.iter().any(Value::is_null) {
+            entry.extend_from_slice(&row.page.to_be_bytes());
+            entry.extend_from_slice(&row.slot.to_be_bytes());
+        }
+        if entry.len() > MAX_KEY_LEN {
+            return Err(StorageError::KeyTooLarge(entry.len()));
+        }
+        Ok(entry)
+    }
+
+    fn check_columns(&self, key: &[Value], expected: usize) -> Result<(), StorageError> {
+        match key.len() == expected {
+            true => Ok(()),
+            false => Err(RowError::ColumnCount {
+                expected,
+                actual: key.len(),
+            }
+            .into()),
+        }
+    }
+
+    /// The leaf the key goes to, latched for writing.
+    fn leaf_for_write(&self, key: &[u8]) -> Result<Node<PageWriteGuard>, StorageError> {
+        let meta = self.pool.fetch_read(self.page_id(META_PAGE))?;
+        let root = meta.get_u32(ROOT);
+        if meta.get_u16(HEIGHT) == 0 {
+            return self.write(root);
+        }
+
+        let mut node = self.read(root)?;
+        drop(meta);
+        loop {
+            let child = node.child_for(Target::At(key));
+            if node.level() == 1 {
+                let leaf = self.write(child)?;
+                self.check_child(Some(&node), &leaf)?;
+                return Ok(leaf);
+            }
+            let next = self.read(child)?;
+            self.check_child(Some(&node), &next)?;
+            node = next;
+        }
+    }
+
+    /// The leaf a search ends up in, latched for reading.
+    fn leaf_for_read(&self, target: Target) -> Result<Node<PageReadGuard>, StorageError> {
+        let meta = self.pool.fetch_read(self.page_id(META_PAGE))?;
+        let mut node = self.read(meta.get_u32(ROOT))?;
+        drop(meta);
+        while !node.is_leaf() {
+            let next = self.read(node.child_for(target))?;
+            self.check_child(Some(&node), &next)?;
+            node = next;
+        }
+        Ok(node)
+    }
+
+    /// Fails unless the child is one level below its parent, which keeps a corrupt tree from sending a search
+    /// around in circles.
+    fn check_child<P, C>(&self, parent: Option<&Node<P>>, child: &Node<C>) -> Result<(), StorageError>
+    where
+        P: Deref<Target = Page>,
+        C: Deref<Target = Page>,
+    {
+        match parent {
+            Some(parent) if parent.level() != child.level() + 1 => Err(StorageError::Corrupt(
+                child.id,
+                format!("node at level {0} under one at level {1}", child.level(), parent.level()),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Moves the upper half of a full node and the cell that did not fit to a new node to its right, returns the
+    /// key that separates them and the new node.
+    fn split(&self, node: &mut Node<PageWriteGuard>, i: usize, cell: Vec<u8>) -> Result<(Vec<u8>, u32), StorageError> {
+        let mut cells: Vec<Vec<u8>> = (0..node.count()).map(|i| node.cell(i).to_vec()).collect();
+        cells.insert(i, cell);
+        let total: usize = cells.iter().map(Vec::len).sum();
+        let mut half = 0;
+        let mut mid = cells.len() - 1;
+        for (i, cell) in cells.iter().enumerate() {
+            half += cell.len();
+            if half >= total / 2 {
+                mid = (i + 1).clamp(1, cells.len() - 1);
+                break;
+            }
+        }
+        let key = |cell: &[u8]| cell[2..2 + u16::from_le_bytes([cell[0], cell[1]]) as usize].to_vec();
+
+        let (page, guard) = self.pool.new_page(self.file)?;
+        let id = self.page_id(page);
+        if node.is_leaf() {
+            let mut right = Node::init(guard, id, 0, node.id.page, node.right());
+            right.set_cells(&cells[mid..]);
+            if node.right() != NO_PAGE {
+                let mut next = self.write(node.right())?;
+                next.set_left(page);
+            }
+            node.set_right(page);
+            node.set_cells(&cells[..mid]);
+            Ok((key(&cells[mid]), page))
+        } else {
+            // The middle cell moves up, its child becomes the first child of the new node.
+            let first = u32::from_le_bytes(cells[mid][cells[mid].len() - 4..].try_into().unwrap());
+            let mut right = Node::init(guard, id, node.level(), first, NO_PAGE);
+            right.set_cells(&cells[mid + 1..]);
+            node.set_cells(&cells[..mid]);
+            Ok((key(&cells[mid]), page))
+        }
+    }
+
+    fn page_id(&self, page: u32) -> PageId {
+        PageId::new(self.file, page)
+    }
+
+    fn read(&self, page: u32) -> Result<Node<PageReadGuard>, StorageError> {
+        Node::open(self.pool.fetch_read(self.page_id(page))?, self.page_id(page))
+    }
+
+    fn write(&self, page: u32) -> Result<Node<PageWriteGuard>, StorageError> {
+        Node::open(self.pool.fetch_write(self.page_id(page))?, self.page_id(page))
+    }
+}
+
+/// Reads the entries of a range one leaf at a time, without holding on to any latch in between. Entries are
+/// tracked by the last one returned, so leaves that split in the meantime are neither skipped nor read twice.
+pub struct BTreeScan<'a> {
+    tree: &'a BTree,
+    lower: Vec<u8>,
+    /// `None` when there is no upper bound.
+    upper: Option<Vec<u8>>,
+    direction: ScanDirection,
+    entries: VecDeque<(Vec<u8>, RowId)>,
+    /// Last entry returned.
+    last: Option<Vec<u8>>,
+    /// The leaf read last, and the one to read next.
+    leaf: u32,
+    next: Option<u32>,
+    done: bool,
+}
+
+impl<'a> BTreeScan<'a> {
+    fn new(tree: &'a BTree, lower: Vec<u8>, upper: Option<Vec<u8>>, direction: ScanDirection, done: bool) -> Self {
+        BTreeScan {
+            tree,
+            lower,
+            upper,
+            direction,
+            entries: VecDeque::new(),
+            last: None,
+            leaf: NO_PAGE,
+            next: None,
+            done,
+        }
+    }
+
+    /// Reads the next leaf with entries in the range, `false` once there is none.
+    fn fill(&mut self) -> Result<bool, StorageError> {
+        while self.entries.is_empty() && !self.done {
+            let leaf = match (self.next, self.direction) {
+                (None, ScanDirection::Forward) => {
+                    let from = self.last.as_deref().unwrap_or(&self.lower);
+                    self.tree.leaf_for_read(Target::At(from))?
+                }
+                (None, ScanDirection::Backward) => {
+                    let target = match self.last.as_deref().or(self.upper.as_deref()) {
+                        Some(key) => Target::Before(key),
+                        None => Target::Last,
+                    };
+                    self.tree.leaf_for_read(target)?
+                }
+                (Some(NO_PAGE), _) => break,
+                (Some(page), direction) => {
+                    let leaf = self.tree.read(page)?;
+                    // A leaf to the left may have split since, the leaf after it is then a new one.
+                    if direction == ScanDirection::Backward && leaf.right() != self.leaf {
+                        self.next = None;
+                        continue;
+                    }
+                    leaf
+                }
+            };
+            if !leaf.is_leaf() {
+                return Err(StorageError::Corrupt(leaf.id, "sibling of a leaf is not a leaf".to_string()));
+            }
+            self.read_leaf(&leaf);
+        }
+        Ok(!self.entries.is_empty())
+    }
+
+    fn read_leaf(&mut self, leaf: &Node<PageReadGuard>) {
+        let in_range =
+            |key: &[u8]| key >= self.lower.as_slice() && self.upper.as_ref().is_none_or(|upper| key < upper.as_slice());
+        let after_last = |key: &[u8]| match (&self.last, self.direction) {
+            (None, _) => true,
+            (Some(last), ScanDirection::Forward) => key > last.as_slice(),
+            (Some(last), ScanDirection::Backward) => key < last.as_slice(),
+        };
+
+        let mut entries = VecDeque::new();
+        let mut past_range = false;
+        for i in 0..leaf.count() {
+            let key = leaf.key(i);
+            if in_range(key) && after_last(key) {
+                entries.push_back((key.to_vec(), leaf.row(i)));
+            }
+            past_range |= match self.direction {
+                ScanDirection::Forward => self.upper.as_ref().is_some_and(|upper| key >= upper.as_slice()),
+                ScanDirection::Backward => key < self.lower.as_slice(),
+            };
+        }
+
+        self.leaf = leaf.id.page;
+        self.next = Some(match self.direction {
+            ScanDirection::Forward => leaf.right(),
+            ScanDirection::Backward => {
+                entries = entries.into_iter().rev().collect();
+                leaf.left()
+            }
+        });
+        self.done = past_range;
+        self.entries = entries;
+    }
+}
+
+impl Iterator for BTreeScan<'_> {
+    type Item = Result<(Vec<Value>, RowId), StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.fill() {
+            Ok(true) => {}
+            Ok(false) => return None,
+            Err(err) => {
+                self.done = true;
+                self.entries.clear();
+                return Some(Err(err));
+            }
+        }
+
+        let (entry, row) = self.entries.pop_front().unwrap();
+        let key = decode_key(&entry, self.tree.columns).map(|(key, _)| key);
+        self.last = Some(entry);
+        Some(
+            key.map(|key| (key, row)).ok_or_else(|| {
+                StorageError::Corrupt(self.tree.page_id(self.leaf), "invalid key in a b-tree leaf".to_string())
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::storage::buffer::EvictionPolicyKind;
+    use crate::storage::file::FileManager;
+    use crate::storage::testing::TempDir;
+    use std::sync::Arc;
+
+    fn pool(dir: &TempDir) -> BufferPool {
+        BufferPool::new(
+            Arc::new(FileManager::open(dir.path()).unwrap()),
+            32,
+            EvictionPolicyKind::Clock,
+        )
+    }
+
+    fn row(n: i64) -> RowId {
+        RowId::new(n as u32 / 100, n as u16 % 100)
+    }
+
+    fn rows(scan: BTreeScan) -> Vec<RowId> {
+        scan.map(|entry| entry.unwrap().1).collect()
+    }
+
+    #[test]
+    fn test_insert_and_get() {
+        let dir = TempDir::new();
+        let tree = BTree::create(pool(&dir), 1, false).unwrap();
+
+        // Shuffled, with long keys so that the tree grows a few levels.
+        let key = |n: i64| vec![Value::Text(format!("{n:05}{0}", "x".repeat(200)))];
+        let numbers: Vec<i64> = (0..5000).map(|n| n * 7919 % 5000).collect();
+        for n in &numbers {
+            tree.insert(&key(*n), row(*n)).unwrap();
+        }
+        tree.insert(&key(42), row(9999)).unwrap();
+        assert_eq!(tree.get(&key(42)).unwrap(), vec![row(42), row(9999)]);
+        assert_eq!(tree.get(&key(4999)).unwrap(), vec![row(4999)]);
+        assert_eq!(tree.get(&key(5000)).unwrap(), vec![]);
+        let meta = tree.pool.fetch_read(tree.page_id(META_PAGE)).unwrap();
+        assert!(meta.get_u16(HEIGHT) >= 2);
+        drop(meta);
+
+        assert!(tree.delete(&key(42), row(42)).unwrap());
+        assert!(!tree.delete(&key(42), row(42)).unwrap());
+        assert_eq!(tree.get(&key(42)).unwrap(), vec![row(9999)]);
+
+        let all = rows(tree.scan(Bound::Unbounded, Bound::Unbounded, ScanDirection::Forward));
+        assert_eq!(all.len(), 5000);
+        assert_eq!(all[..3], [row(0), row(1), row(2)]);
+
+        // Everything survives flushing and reopening.
+        tree.pool.flush_all().unwrap();
+        let file = tree.file();
+        drop(tree);
+        let tree = BTree::open(pool(&dir), file).unwrap();
+        assert_eq!((tree.columns(), tree.is_unique()), (1, false));
+        assert_eq!(tree.get(&key(1234)).unwrap(), vec![row(1234)]);
+
+        assert!(matches!(
+            tree.insert(&[Value::Text("x".repeat(MAX_KEY_LEN))], row(0)),
+            Err(StorageError::KeyTooLarge(_))
+        ));
+        assert!(matches!(tree.get(&[]), Err(StorageError::Row(RowError::ColumnCount { .. }))));
+    }
+
+    #[test]
+    fn test_unique() {
+        let dir = TempDir::new();
+        let tree = BTree::create(pool(&dir), 2, true).unwrap();
+        let key = |a: i64, b: Value| vec![Value::Int(a), b];
+
+        tree.insert(&key(1, Value::Int(1)), row(1)).unwrap();
+        tree.insert(&key(1, Value::Int(2)), row(2)).unwrap();
+        let err = tree.insert(&key(1, Value::Int(1)), row(3)).unwrap_err();
+        assert!(matches!(&err, StorageError::DuplicateKey(key) if key == &[Value::Int(1), Value::Int(1)]));
+
+        // NULL equals nothing, not even another NULL.
+        tree.insert(&key(1, Value::Null), row(4)).unwrap();
+        tree.insert(&key(1, Value::Null), row(5)).unwrap();
+        assert_eq!(tree.get(&key(1, Value::Null)).unwrap(), vec![row(4), row(5)]);
+
+        // A deleted key may come back, only the row it was for deletes it.
+        assert!(!tree.delete(&key(1, Value::Int(1)), row(3)).unwrap());
+        assert!(tree.delete(&key(1, Value::Int(1)), row(1)).unwrap());
+        tree.insert(&key(1, Value::Int(1)), row(3)).unwrap();
+        assert_eq!(tree.get(&key(1, Value::Int(1))).unwrap(), vec![row(3)]);
+    }
+
+    #[test]
+    fn test_scans() {
+        let dir = TempDir::new();
+        let tree = BTree::create(pool(&dir), 2, false).unwrap();
+        for n in 0..3000 {
+            tree.insert(&[Value::Int(n / 100), Value::Int(n % 100)], row(n)).unwrap();
+        }
+        let int = |n: i64| [Value::Int(n)];
+        let pair = |a: i64, b: i64| [Value::Int(a), Value::Int(b)];
+
+        let forward = rows(tree.scan(
+            Bound::Included(&pair(5, 50)),
+            Bound::Excluded(&pair(7, 10)),
+            ScanDirection::Forward,
+        ));
+        assert_eq!(forward, (550..710).map(row).collect::<Vec<_>>());
+        let backward = rows(tree.scan(
+            Bound::Included(&pair(5, 50)),
+            Bound::Excluded(&pair(7, 10)),
+            ScanDirection::Backward,
+        ));
+        assert_eq!(backward, (550..710).rev().map(row).collect::<Vec<_>>());
+
+        // Bounds on a prefix of the key cover every key that starts with it.
+        let scan = tree.scan(Bound::Excluded(&int(27)), Bound::Included(&int(28)), ScanDirection::Forward);
+        assert_eq!(rows(scan), (2800..2900).map(row).collect::<Vec<_>>());
+        let scan = tree.prefix_scan(&int(3), ScanDirection::Backward);
+        let entries: Vec<(Vec<Value>, RowId)> = scan.map(Result::unwrap).collect();
+        assert_eq!(entries.len(), 100);
+        assert_eq!(entries[0], (pair(3, 99).to_vec(), row(399)));
+        assert_eq!(rows(tree.prefix_scan(&int(30), ScanDirection::Forward)), vec![]);
+
+        let last = tree.scan(Bound::Unbounded, Bound::Unbounded, ScanDirection::Backward).next();
+        assert_eq!(last.unwrap().unwrap().1, row(2999));
+        let scan = tree.scan(Bound::Included(&int(10)), Bound::Excluded(&int(10)), ScanDirection::Forward);
+        assert_eq!(scan.count(), 0);
+
+        // Emptied leaves are skipped over.
+        for n in 1000..2000 {
+            assert!(tree.delete(&pair(n / 100, n % 100), row(n)).unwrap());
+        }
+        let scan = tree.scan(Bound::Included(&int(9)), Bound::Included(&int(20)), ScanDirection::Backward);
+        assert_eq!(rows(scan), (900..1000).chain(2000..2100).rev().map(row).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_concurrent() {
+        let dir = TempDir::new();
+        let tree = BTree::create(pool(&dir), 1, true).unwrap();
+        let key = |n: i64| [Value::Text(format!("{n:06}{0}", "y".repeat(100)))];
+
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let tree = &tree;
+                scope.spawn(move || {
+                    for n in (0..2000).filter(|n| n % 4 == thread) {
+                        tree.insert(&key(n), row(n)).unwrap();
+                    }
+                });
+            }
+            // Scans see keys in order while the tree splits under them.
+            for direction in [ScanDirection::Forward, ScanDirection::Backward] {
+                let tree = &tree;
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        let keys: Vec<Vec<u8>> = tree
+                            .scan(Bound::Unbounded, Bound::Unbounded, direction)
+                            .map(|entry| encode_key(&entry.unwrap().0))
+                            .collect();
+                        let ordered = keys.windows(2).all(|pair| match direction {
+                            ScanDirection::Forward => pair[0] < pair[1],
+                            ScanDirection::Backward => pair[0] > pair[1],
+                        });
+                        assert!(ordered);
+                    }
+                });
+            }
+        });
+
+        let all = rows(tree.scan(Bound::Unbounded, Bound::Unbounded, ScanDirection::Forward));
+        assert_eq!(all, (0..2000).map(row).collect::<Vec<_>>());
+    }
+}
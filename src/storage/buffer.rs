@@ -0,0 +1,621 @@
+//! The shared cache every page access goes through.
+//!
+//! The pool holds a fixed number of frames, each caching one page. Fetching a page pins its frame, and the frame
+//! is only reused for another page once every guard on it is gone. Guards also hold the latch of the frame, any
+//! number of [`PageReadGuard`]s or a single [`PageWriteGuard`] at a time. They own their pin and latch rather than
+//! borrowing the pool, so they are `Send` and can live in the tasks that serve sessions, but latches are meant to be
+//! held for the duration of one page access, not across an `.await` that waits on another session.
+//!
+//! Written pages are marked dirty and reach their file when their frame is evicted or the pool is flushed. With a
+//! [`Wal`] set, the changes made under a [`PageWriteGuard`] are logged when it goes away, unless whoever made them
+//! logged them already, and a page is written only once the log is on disk up to the last record that changed it.
+
+use crate::storage::eviction::{Clock, EvictionPolicy, FrameId, LruK};
+use crate::storage::file::FileManager;
+use crate::storage::page::Page;
+use crate::storage::wal::{Lsn, Wal};
+use crate::storage::{FileId, PageId, StorageError};
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+
+/// How the pool picks the page to evict.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EvictionPolicyKind {
+    Clock,
+    /// Least recently used by the k-th most recent access.
+    LruK(usize),
+}
+
+impl Display for EvictionPolicyKind {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            EvictionPolicyKind::Clock => write!(f, "clock"),
+            EvictionPolicyKind::LruK(k) => write!(f, "lru-{k}"),
+        }
+    }
+}
+
+impl EvictionPolicyKind {
+    fn build(&self, frames: usize) -> Box<dyn EvictionPolicy> {
+        match self {
+            EvictionPolicyKind::Clock => Box::new(Clock::new(frames)),
+            EvictionPolicyKind::LruK(k) => Box::new(LruK::new(frames, *k)),
+        }
+    }
+}
+
+/// Counters since the pool was created.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BufferStats {
+    /// Fetches of a page that was in the pool.
+    pub hits: u64,
+    /// Fetches that read the page from its file.
+    pub misses: u64,
+    pub evictions: u64,
+    /// Dirty pages written to their file.
+    pub writes: u64,
+}
+
+/// Readers and a writer of a frame, released by whoever holds the guard rather than the thread that took it.
+#[derive(Default)]
+struct Latch {
+    /// Number of readers, or -1 while written.
+    state: Mutex<isize>,
+    released: Condvar,
+}
+
+impl Latch {
+    fn read(&self) {
+        let mut state = self.state.lock().unwrap();
+        while *state < 0 {
+            state = self.released.wait(state).unwrap();
+        }
+        *state += 1;
+    }
+
+    fn write(&self) {
+        let mut state = self.state.lock().unwrap();
+        while *state != 0 {
+            state = self.released.wait(state).unwrap();
+        }
+        *state = -1;
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        *state = if *state < 0 { 0 } else { *state - 1 };
+        if *state == 0 {
+            self.released.notify_all();
+        }
+    }
+}
+
+struct Frame {
+    latch: Latch,
+    page: UnsafeCell<Page>,
+    pins: AtomicU32,
+    dirty: AtomicBool,
+    /// End of the log when the page was last made dirty, the first record it may have that its file does not.
+    rec_lsn: AtomicU64,
+}
+
+// The page is only touched under the latch of its frame, or by the pool while nobody has the frame pinned.
+unsafe impl Sync for Frame {}
+
+struct State {
+    /// Frame of every page in the pool.
+    pages: HashMap<PageId, FrameId>,
+    /// Page in every frame.
+    frames: Vec<Option<PageId>>,
+    free: Vec<FrameId>,
+    policy: Box<dyn EvictionPolicy>,
+}
+
+struct Inner {
+    files: Arc<FileManager>,
+    frames: Vec<Frame>,
+    /// Held while pages come and go. Reads and writes of evicted pages happen under it, so a page is never in two
+    /// frames at once.
+    state: Mutex<State>,
+    wal: OnceLock<Arc<Wal>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    writes: AtomicU64,
+}
+
+/// A handle on the pool, clones share it.
+#[derive(Clone)]
+pub struct BufferPool(Arc<Inner>);
+
+impl BufferPool {
+    pub fn new(files: Arc<FileManager>, frames: usize, policy: EvictionPolicyKind) -> Self {
+        BufferPool(Arc::new(Inner {
+            files,
+            frames: (0..frames)
+                .map(|_| Frame {
+                    latch: Latch::default(),
+                    page: UnsafeCell::new(Page::new()),
+                    pins: AtomicU32::new(0),
+                    dirty: AtomicBool::new(false),
+                    rec_lsn: AtomicU64::new(0),
+                })
+                .collect(),
+            state: Mutex::new(State {
+                pages: HashMap::new(),
+                frames: vec![None; frames],
+                free: (0..frames).rev().collect(),
+                policy: policy.build(frames),
+            }),
+            wal: OnceLock::new(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            writes: AtomicU64::new(0),
+        }))
+    }
+
+    pub fn files(&self) -> &Arc<FileManager> {
+        &self.0.files
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.0.frames.len()
+    }
+
+    /// Makes pages wait for the log before they are written. Only the first log set is kept.
+    pub fn set_wal(&self, wal: Arc<Wal>) {
+        let _ = self.0.wal.set(wal);
+    }
+
+    pub fn fetch_read(&self, id: PageId) -> Result<PageReadGuard, StorageError> {
+        let frame = self.pin(id, false)?;
+        self.0.frames[frame].latch.read();
+        Ok(PageReadGuard(Pinned::new(self, frame, id, true)))
+    }
+
+    pub fn fetch_write(&self, id: PageId) -> Result<PageWriteGuard, StorageError> {
+        let frame = self.pin(id, false)?;
+        self.0.frames[frame].latch.write();
+        Ok(PageWriteGuard::new(Pinned::new(self, frame, id, true)))
+    }
+
+    /// Appends a page to the file, and returns it zeroed and dirty without reading it.
+    pub fn new_page(&self, file: FileId) -> Result<(u32, PageWriteGuard), StorageError> {
+        let page = self.0.files.allocate_page(file)?;
+        let id = PageId::new(file, page);
+        let frame = self.pin(id, true)?;
+        self.0.frames[frame].latch.write();
+        self.mark_dirty(frame);
+        Ok((page, PageWriteGuard::new(Pinned::new(self, frame, id, true))))
+    }
+
+    /// Writes the page if it is in the pool and dirty.
+    pub fn flush_page(&self, id: PageId) -> Result<(), StorageError> {
+        let frame = self.0.state.lock().unwrap().pages.get(&id).copied();
+        match frame {
+            Some(frame) => self.flush_frame(frame),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes every dirty page and syncs their files.
+    pub fn flush_all(&self) -> Result<(), StorageError> {
+        let pages: Vec<(PageId, FrameId)> = {
+            let state = self.0.state.lock().unwrap();
+            state.pages.iter().map(|(page, frame)| (*page, *frame)).collect()
+        };
+
+        let mut files = Vec::new();
+        for (page, frame) in pages {
+            if self.0.frames[frame].dirty.load(Ordering::Acquire) {
+                self.flush_frame(frame)?;
+                files.push(page.file);
+            }
+        }
+        files.sort_unstable();
+        files.dedup();
+        for file in files {
+            self.0.files.sync(file)?;
+        }
+        Ok(())
+    }
+
+    /// Forgets the pages of a file that is about to be removed, without writing them. None of them may be pinned.
+    pub fn discard_file(&self, file: FileId) {
+        let mut state = self.0.state.lock().unwrap();
+        let frames: Vec<FrameId> = state
+            .pages
+            .iter()
+            .filter(|(page, _)| page.file == file)
+            .map(|(_, frame)| *frame)
+            .collect();
+
+        for frame in frames {
+            debug_assert_eq!(self.0.frames[frame].pins.load(Ordering::Acquire), 0);
+            if let Some(page) = state.frames[frame].take() {
+                state.pages.remove(&page);
+            }
+            state.policy.remove(frame);
+            self.0.frames[frame].dirty.store(false, Ordering::Release);
+            state.free.push(frame);
+        }
+    }
+
+    /// Pages changed since they were last written, with the first log record each may miss.
+    pub fn dirty_pages(&self) -> Vec<(PageId, Lsn)> {
+        let state = self.0.state.lock().unwrap();
+        let mut pages: Vec<(PageId, Lsn)> = state
+            .pages
+            .iter()
+            .filter(|(_, frame)| self.0.frames[**frame].dirty.load(Ordering::Acquire))
+            .map(|(page, frame)| (*page, self.0.frames[*frame].rec_lsn.load(Ordering::Acquire)))
+            .collect();
+        pages.sort_unstable();
+        pages
+    }
+
+    pub fn stats(&self) -> BufferStats {
+        BufferStats {
+            hits: self.0.hits.load(Ordering::Relaxed),
+            misses: self.0.misses.load(Ordering::Relaxed),
+            evictions: self.0.evictions.load(Ordering::Relaxed),
+            writes: self.0.writes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Pins the frame of the page, bringing the page in first if needed. A `new` page is zeroed instead of read.
+    fn pin(&self, id: PageId, new: bool) -> Result<FrameId, StorageError> {
+        let mut state = self.0.state.lock().unwrap();
+        if let Some(&frame) = state.pages.get(&id) {
+            self.0.frames[frame].pins.fetch_add(1, Ordering::AcqRel);
+            state.policy.record_access(frame);
+            state.policy.set_evictable(frame, false);
+            self.0.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(frame);
+        }
+
+        let frame = match state.free.pop() {
+            Some(frame) => frame,
+            None => {
+                let frame = state.policy.evict().ok_or(StorageError::NoFreeFrames)?;
+                if let Err(err) = self.evict(&mut state, frame) {
+                    state.policy.set_evictable(frame, true);
+                    return Err(err);
+                }
+                frame
+            }
+        };
+
+        // Nobody else can reach the frame: it is in no page table entry and unpinned.
+        let page = unsafe { &mut *self.0.frames[frame].page.get() };
+        let loaded = match new {
+            true => {
+                *page = Page::new();
+                Ok(())
+            }
+            false => self.0.files.read_page(id, page),
+        };
+        if let Err(err) = loaded {
+            state.free.push(frame);
+            return Err(err);
+        }
+
+        self.0.misses.fetch_add(1, Ordering::Relaxed);
+        self.0.frames[frame].pins.store(1, Ordering::Release);
+        state.pages.insert(id, frame);
+        state.frames[frame] = Some(id);
+        state.policy.record_access(frame);
+        Ok(frame)
+    }
+
+    /// Empties a frame the policy picked, writing its page first if it is dirty.
+    fn evict(&self, state: &mut State, frame: FrameId) -> Result<(), StorageError> {
+        let Some(page_id) = state.frames[frame] else {
+            return Ok(());
+        };
+        if self.0.frames[frame].dirty.load(Ordering::Acquire) {
+            // Unpinned, so nobody holds the latch.
+            let page = unsafe { &mut *self.0.frames[frame].page.get() };
+            self.write_page(page_id, page)?;
+            self.0.frames[frame].dirty.store(false, Ordering::Release);
+        }
+        state.pages.remove(&page_id);
+        state.frames[frame] = None;
+        self.0.evictions.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn flush_frame(&self, frame: FrameId) -> Result<(), StorageError> {
+        let Some(guard) = self.pin_frame(frame) else {
+            return Ok(());
+        };
+        let id = guard.id;
+        // A writer cannot change the page while it is read, so the copy is what becomes clean.
+        self.0.frames[frame].latch.read();
+        let mut copy = match self.0.frames[frame].dirty.swap(false, Ordering::AcqRel) {
+            true => Some(unsafe { (*self.0.frames[frame].page.get()).clone() }),
+            false => None,
+        };
+        self.0.frames[frame].latch.release();
+
+        if let Some(page) = &mut copy
+            && let Err(err) = self.write_page(id, page)
+        {
+            self.0.frames[frame].dirty.store(true, Ordering::Release);
+            return Err(err);
+        }
+        drop(guard);
+        Ok(())
+    }
+
+    /// Writes a page to its file once the log is on disk up to the page.
+    fn write_page(&self, id: PageId, page: &mut Page) -> Result<(), StorageError> {
+        if let Some(wal) = self.0.wal.get()
+            && page.lsn() != 0
+        {
+            wal.flush(page.lsn())?;
+        }
+        self.0.files.write_page(id, page)?;
+        self.0.writes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Marks the page of a write latched frame dirty, noting where the log was if it was clean.
+    fn mark_dirty(&self, frame: FrameId) {
+        let frame = &self.0.frames[frame];
+        if !frame.dirty.swap(true, Ordering::AcqRel) {
+            let lsn = self.0.wal.get().map_or(0, |wal| wal.end());
+            frame.rec_lsn.store(lsn, Ordering::Release);
+        }
+    }
+
+    /// Pins the frame while it still holds a page, without counting an access.
+    fn pin_frame(&self, frame: FrameId) -> Option<Pinned> {
+        let mut state = self.0.state.lock().unwrap();
+        let id = state.frames[frame]?;
+        self.0.frames[frame].pins.fetch_add(1, Ordering::AcqRel);
+        state.policy.set_evictable(frame, false);
+        Some(Pinned::new(self, frame, id, false))
+    }
+
+    fn unpin(&self, frame: FrameId) {
+        let mut state = self.0.state.lock().unwrap();
+        if self.0.frames[frame].pins.fetch_sub(1, Ordering::AcqRel) == 1 {
+            state.policy.set_evictable(frame, true);
+        }
+    }
+}
+
+/// A pinned frame, latched unless only pinned by the pool itself.
+struct Pinned {
+    pool: BufferPool,
+    frame: FrameId,
+    id: PageId,
+    latched: bool,
+}
+
+impl Pinned {
+    fn new(pool: &BufferPool, frame: FrameId, id: PageId, latched: bool) -> Self {
+        Pinned {
+            pool: pool.clone(),
+            frame,
+            id,
+            latched,
+        }
+    }
+
+    fn frame(&self) -> &Frame {
+        &self.pool.0.frames[self.frame]
+    }
+}
+
+impl Drop for Pinned {
+    fn drop(&mut self) {
+        if self.latched {
+            self.frame().latch.release();
+        }
+        self.pool.unpin(self.frame);
+    }
+}
+
+/// Shared access to a page in the pool.
+pub struct PageReadGuard(Pinned);
+
+impl PageReadGuard {
+    pub fn id(&self) -> PageId {
+        self.0.id
+    }
+}
+
+impl Deref for PageReadGuard {
+    type Target = Page;
+
+    fn deref(&self) -> &Page {
+        // Read latched.
+        unsafe { &*self.0.frame().page.get() }
+    }
+}
+
+/// Exclusive access to a page in the pool, which is marked dirty when changed.
+pub struct PageWriteGuard {
+    pinned: Pinned,
+    /// The page before it was first changed, while the pool logs changes. The changes are not logged again if
+    /// the LSN of the page moved since, which only logging them does.
+    before: Option<Page>,
+}
+
+impl PageWriteGuard {
+    fn new(pinned: Pinned) -> Self {
+        PageWriteGuard { pinned, before: None }
+    }
+
+    pub fn id(&self) -> PageId {
+        self.pinned.id
+    }
+}
+
+impl Deref for PageWriteGuard {
+    type Target = Page;
+
+    fn deref(&self) -> &Page {
+        // Write latched.
+        unsafe { &*self.pinned.frame().page.get() }
+    }
+}
+
+impl DerefMut for PageWriteGuard {
+    fn deref_mut(&mut self) -> &mut Page {
+        self.pinned.pool.mark_dirty(self.pinned.frame);
+        // Write latched.
+        let page = unsafe { &mut *self.pinned.frame().page.get() };
+        if self.before.is_none() && self.pinned.pool.0.wal.get().is_some() {
+            self.before = Some(page.clone());
+        }
+        page
+    }
+}
+
+impl Drop for PageWriteGuard {
+    fn drop(&mut self) {
+        if let Some(before) = self.before.take()
+            && let Some(wal) = self.pinned.pool.0.wal.get()
+        {
+            // Still write latched, the pin goes after.
+            let page = unsafe { &mut *self.pinned.frame().page.get() };
+            if page.lsn() == before.lsn() {
+                wal.log_changes(self.pinned.id, &before, page);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::storage::testing::TempDir;
+
+    fn pool(dir: &TempDir, frames: usize, policy: EvictionPolicyKind) -> (BufferPool, FileId) {
+        let files = Arc::new(FileManager::open(dir.path()).unwrap());
+        let file = files.create_file().unwrap();
+        (BufferPool::new(files, frames, policy), file)
+    }
+
+    #[test]
+    fn test_pin_and_evict() {
+        let dir = TempDir::new();
+        let (pool, file) = pool(&dir, 2, EvictionPolicyKind::Clock);
+
+        for n in 0..3u8 {
+            let (page, mut guard) = pool.new_page(file).unwrap();
+            assert_eq!(page, n as u32);
+            guard.data_mut()[100] = n;
+        }
+        // Writing the third page evicted the first, which went to its file.
+        assert_eq!(pool.stats().evictions, 1);
+        assert_eq!(pool.stats().writes, 1);
+
+        let first = pool.fetch_read(PageId::new(file, 0)).unwrap();
+        assert_eq!(first.data()[100], 0);
+        let second = pool.fetch_read(PageId::new(file, 0)).unwrap();
+        assert_eq!(second.id(), PageId::new(file, 0));
+        let third = pool.fetch_read(PageId::new(file, 2)).unwrap();
+        assert_eq!(third.data()[100], 2);
+        assert_eq!(pool.stats().hits, 2);
+
+        // Both frames are pinned.
+        assert!(matches!(
+            pool.fetch_read(PageId::new(file, 1)),
+            Err(StorageError::NoFreeFrames)
+        ));
+        drop((first, second));
+        assert_eq!(pool.fetch_read(PageId::new(file, 1)).unwrap().data()[100], 1);
+        assert!(matches!(
+            pool.fetch_read(PageId::new(file, 7)),
+            Err(StorageError::UnknownPage(_))
+        ));
+        drop(third);
+
+        pool.flush_all().unwrap();
+        let mut page = Page::new();
+        pool.files().read_page(PageId::new(file, 2), &mut page).unwrap();
+        assert_eq!(page.data()[100], 2);
+    }
+
+    #[test]
+    fn test_dirty_pages() {
+        let dir = TempDir::new();
+        let (pool, file) = pool(&dir, 4, EvictionPolicyKind::LruK(2));
+        let (_, guard) = pool.new_page(file).unwrap();
+        drop(guard);
+        pool.flush_all().unwrap();
+        let writes = pool.stats().writes;
+
+        // Reading does not dirty a page, writing through the guard does.
+        drop(pool.fetch_write(PageId::new(file, 0)).unwrap());
+        pool.flush_all().unwrap();
+        assert_eq!(pool.stats().writes, writes);
+
+        pool.fetch_write(PageId::new(file, 0)).unwrap().set_lsn(9);
+        pool.flush_page(PageId::new(file, 0)).unwrap();
+        assert_eq!(pool.stats().writes, writes + 1);
+        let mut page = Page::new();
+        pool.files().read_page(PageId::new(file, 0), &mut page).unwrap();
+        assert_eq!(page.lsn(), 9);
+
+        // Discarded pages are not written.
+        pool.fetch_write(PageId::new(file, 0)).unwrap().set_lsn(10);
+        pool.discard_file(file);
+        pool.flush_all().unwrap();
+        assert_eq!(pool.stats().writes, writes + 1);
+    }
+
+    #[test]
+    fn test_latches() {
+        let dir = TempDir::new();
+        let (pool, file) = pool(&dir, 4, EvictionPolicyKind::Clock);
+        drop(pool.new_page(file).unwrap());
+        let id = PageId::new(file, 0);
+
+        // Writers take turns with each other and with readers, guards move between threads.
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let pool = pool.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        let mut guard = pool.fetch_write(id).unwrap();
+                        let count = guard.get_u32(100);
+                        guard.put_u32(100, count + 1);
+                        drop(guard);
+                        assert!(pool.fetch_read(id).unwrap().get_u32(100) > 0);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(pool.fetch_read(id).unwrap().get_u32(100), 400);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_guards_in_tasks() {
+        let dir = TempDir::new();
+        let (pool, file) = pool(&dir, 4, EvictionPolicyKind::Clock);
+        drop(pool.new_page(file).unwrap());
+
+        let task = tokio::spawn(async move {
+            let mut guard = pool.fetch_write(PageId::new(file, 0)).unwrap();
+            tokio::task::yield_now().await;
+            guard.set_lsn(5);
+            drop(guard);
+            pool.fetch_read(PageId::new(file, 0)).unwrap().lsn()
+        });
+        assert_eq!(task.await.unwrap(), 5);
+    }
+}
@@ -0,0 +1,172 @@
+//! Fast compression of large values, in the LZ4 block format.
+//!
+//! The input is a sequence of literals and matches, each one starting with a token byte whose high four bits
+//! are the number of literals and low four bits the length of the match minus 4. A count of 15 continues in the
+//! following bytes, which are added up until one is below 255. The literals follow, then the offset of the match
+//! as a little endian `u16`. The last sequence has only literals.
+
+const MIN_MATCH: usize = 4;
+const MAX_OFFSET: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 12;
+
+fn hash(bytes: &[u8]) -> usize {
+    let n = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+    (n.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+/// Writes a count that did not fit in its four bits of the token.
+fn put_count(out: &mut Vec<u8>, count: usize) {
+    if count < 15 {
+        return;
+    }
+    let mut rest = count - 15;
+    while rest >= 255 {
+        out.push(255);
+        rest -= 255;
+    }
+    out.push(rest as u8);
+}
+
+fn put_sequence(out: &mut Vec<u8>, literals: &[u8], offset: usize, match_len: usize) {
+    out.push(((literals.len().min(15) as u8) << 4) | (match_len - MIN_MATCH).min(15) as u8);
+    put_count(out, literals.len());
+    out.extend_from_slice(literals);
+    out.extend_from_slice(&(offset as u16).to_le_bytes());
+    put_count(out, match_len - MIN_MATCH);
+}
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2);
+    // Position plus one of the last 4 bytes with each hash.
+    let mut table = vec![0usize; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut i = 0;
+
+    while i + MIN_MATCH <= input.len() {
+        let h = hash(&input[i..]);
+        let candidate = table[h];
+        table[h] = i + 1;
+
+        if let Some(start) = candidate.checked_sub(1)
+            && i - start <= MAX_OFFSET
+            && input[start..start + MIN_MATCH] == input[i..i + MIN_MATCH]
+        {
+            let len = MIN_MATCH
+                + input[i + MIN_MATCH..]
+                    .iter()
+                    .zip(&input[start + MIN_MATCH..])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+            put_sequence(&mut out, &input[anchor..i], i - start, len);
+            i += len;
+            anchor = i;
+            continue;
+        }
+        i += 1;
+    }
+
+    let literals = &input[anchor..];
+    out.push((literals.len().min(15) as u8) << 4);
+    put_count(&mut out, literals.len());
+    out.extend_from_slice(literals);
+    out
+}
+
+/// Decompresses `input`, which must expand to exactly `len` bytes.
+pub fn decompress(input: &[u8], len: usize) -> Result<Vec<u8>, String> {
+    let truncated = || "compressed data is truncated".to_string();
+    let mut out = Vec::with_capacity(len);
+    let mut i = 0;
+
+    let count = |i: &mut usize, count: usize| -> Result<usize, String> {
+        let mut count = count;
+        if count == 15 {
+            loop {
+                let byte = *input.get(*i).ok_or_else(truncated)?;
+                *i += 1;
+                count += byte as usize;
+                if byte != 255 {
+                    break;
+                }
+            }
+        }
+        Ok(count)
+    };
+
+    loop {
+        let token = *input.get(i).ok_or_else(truncated)?;
+        i += 1;
+        let literals = count(&mut i, (token >> 4) as usize)?;
+        let literals = input.get(i..i + literals).ok_or_else(truncated)?;
+        if out.len() + literals.len() > len {
+            return Err("compressed data expands past its length".to_string());
+        }
+        out.extend_from_slice(literals);
+        i += literals.len();
+        if i == input.len() {
+            break;
+        }
+
+        let offset = input.get(i..i + 2).ok_or_else(truncated)?;
+        let offset = u16::from_le_bytes(offset.try_into().unwrap()) as usize;
+        i += 2;
+        let match_len = count(&mut i, (token & 0x0f) as usize)? + MIN_MATCH;
+        if offset == 0 || offset > out.len() {
+            return Err(format!("match offset {offset} is out of range"));
+        }
+        if out.len() + match_len > len {
+            return Err("compressed data expands past its length".to_string());
+        }
+        // Matches may overlap the bytes they produce, so they are copied one at a time.
+        let start = out.len() - offset;
+        for j in 0..match_len {
+            out.push(out[start + j]);
+        }
+    }
+
+    if out.len() != len {
+        return Err(format!("compressed data expands to {0} bytes instead of {len}", out.len()));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let text = "the quick brown fox jumps over the lazy dog. ".repeat(200);
+        let mut noise = Vec::new();
+        let mut state = 1u32;
+        for _ in 0..5000 {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            noise.push((state >> 16) as u8);
+        }
+        let inputs: Vec<Vec<u8>> = vec![
+            vec![],
+            b"abc".to_vec(),
+            vec![0; 100_000],
+            text.into_bytes(),
+            noise,
+            [&b"x".repeat(20)[..], &[1, 2, 3]].concat(),
+        ];
+
+        for input in inputs {
+            let compressed = compress(&input);
+            assert_eq!(decompress(&compressed, input.len()).unwrap(), input);
+        }
+        assert!(compress(&[0; 100_000]).len() < 500);
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let compressed = compress(&b"abcd".repeat(100));
+        assert!(decompress(&compressed, 399).is_err());
+        assert!(decompress(&compressed[..compressed.len() - 1], 400).is_err());
+        assert!(decompress(&[], 0).is_err());
+        // A match before the start of the output.
+        assert!(decompress(&[0x10, b'a', 5, 0, 0x00], 5).is_err());
+    }
+}
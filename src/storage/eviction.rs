@@ -0,0 +1,173 @@
+//! Which page the buffer pool gives up when it needs a frame.
+
+use std::collections::VecDeque;
+
+/// Index of a frame in the buffer pool.
+pub type FrameId = usize;
+
+/// Tracks how frames are used, and picks one to evict among those that are not pinned.
+pub trait EvictionPolicy: Send {
+    /// The page in the frame was accessed.
+    fn record_access(&mut self, frame: FrameId);
+
+    /// Whether the frame may be evicted, frames start out not evictable.
+    fn set_evictable(&mut self, frame: FrameId, evictable: bool);
+
+    /// Picks an evictable frame and forgets what it knew about it.
+    fn evict(&mut self) -> Option<FrameId>;
+
+    /// Forgets a frame that was emptied without being evicted.
+    fn remove(&mut self, frame: FrameId);
+}
+
+/// Second chance: the hand sweeps over the frames and takes the first one not accessed since its last pass.
+pub struct Clock {
+    referenced: Vec<bool>,
+    evictable: Vec<bool>,
+    hand: usize,
+}
+
+impl Clock {
+    pub fn new(frames: usize) -> Self {
+        Clock {
+            referenced: vec![false; frames],
+            evictable: vec![false; frames],
+            hand: 0,
+        }
+    }
+}
+
+impl EvictionPolicy for Clock {
+    fn record_access(&mut self, frame: FrameId) {
+        self.referenced[frame] = true;
+    }
+
+    fn set_evictable(&mut self, frame: FrameId, evictable: bool) {
+        self.evictable[frame] = evictable;
+    }
+
+    fn evict(&mut self) -> Option<FrameId> {
+        // The first pass clears the reference bits, the second one finds a frame if there is any.
+        for _ in 0..2 * self.evictable.len() {
+            let frame = self.hand;
+            self.hand = (self.hand + 1) % self.evictable.len();
+            if !self.evictable[frame] {
+                continue;
+            }
+            if self.referenced[frame] {
+                self.referenced[frame] = false;
+                continue;
+            }
+            self.evictable[frame] = false;
+            return Some(frame);
+        }
+        None
+    }
+
+    fn remove(&mut self, frame: FrameId) {
+        self.referenced[frame] = false;
+        self.evictable[frame] = false;
+    }
+}
+
+/// Evicts the frame whose k-th most recent access is the oldest. Frames accessed fewer than k times go first, the
+/// least recently accessed of them first, so pages read once by a scan do not push out the ones used over and
+/// over.
+pub struct LruK {
+    k: usize,
+    /// Logical time of the last k accesses to each frame, oldest first.
+    history: Vec<VecDeque<u64>>,
+    evictable: Vec<bool>,
+    now: u64,
+}
+
+impl LruK {
+    pub fn new(frames: usize, k: usize) -> Self {
+        LruK {
+            k: k.max(1),
+            history: vec![VecDeque::new(); frames],
+            evictable: vec![false; frames],
+            now: 0,
+        }
+    }
+}
+
+impl EvictionPolicy for LruK {
+    fn record_access(&mut self, frame: FrameId) {
+        self.now += 1;
+        let history = &mut self.history[frame];
+        if history.len() == self.k {
+            history.pop_front();
+        }
+        history.push_back(self.now);
+    }
+
+    fn set_evictable(&mut self, frame: FrameId, evictable: bool) {
+        self.evictable[frame] = evictable;
+    }
+
+    fn evict(&mut self) -> Option<FrameId> {
+        // Fewer than k accesses sort before k, then by the oldest access remembered.
+        let frame = (0..self.evictable.len())
+            .filter(|frame| self.evictable[*frame])
+            .min_by_key(|frame| {
+                let history = &self.history[*frame];
+                (history.len() >= self.k, history.front().copied().unwrap_or(0))
+            })?;
+
+        self.remove(frame);
+        Some(frame)
+    }
+
+    fn remove(&mut self, frame: FrameId) {
+        self.evictable[frame] = false;
+        self.history[frame].clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_clock() {
+        let mut clock = Clock::new(3);
+        assert_eq!(clock.evict(), None);
+        for frame in 0..3 {
+            clock.record_access(frame);
+            clock.set_evictable(frame, true);
+        }
+
+        // Everything was referenced, so the hand goes around once.
+        assert_eq!(clock.evict(), Some(0));
+        clock.record_access(1);
+        assert_eq!(clock.evict(), Some(2));
+        clock.set_evictable(1, false);
+        assert_eq!(clock.evict(), None);
+    }
+
+    #[test]
+    fn test_lru_k() {
+        let mut lru = LruK::new(4, 2);
+        for frame in [0, 1, 2, 0, 1, 3, 0] {
+            lru.record_access(frame);
+        }
+        for frame in 0..4 {
+            lru.set_evictable(frame, true);
+        }
+
+        // 2 and 3 were accessed once, 2 longer ago. Then 0, whose second to last access is older than 1's.
+        assert_eq!(lru.evict(), Some(2));
+        assert_eq!(lru.evict(), Some(3));
+        assert_eq!(lru.evict(), Some(1));
+        lru.set_evictable(0, false);
+        assert_eq!(lru.evict(), None);
+
+        // An evicted frame starts over.
+        lru.record_access(1);
+        lru.set_evictable(0, true);
+        lru.set_evictable(1, true);
+        assert_eq!(lru.evict(), Some(1));
+    }
+}
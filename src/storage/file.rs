@@ -0,0 +1,228 @@
+//! Files of pages in the data directory.
+//!
+//! A file is named after its number and holds nothing but pages, page `n` starts at byte `n * PAGE_SIZE`. Files
+//! only grow at the end, a partial page left behind by a crash while extending one is ignored and overwritten by
+//! the next allocation.
+
+use crate::storage::page::{PAGE_SIZE, Page};
+use crate::storage::{FileId, PageId, StorageError};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub struct FileManager {
+    directory: PathBuf,
+    files: Mutex<Files>,
+}
+
+struct Files {
+    open: HashMap<FileId, Arc<Mutex<OpenFile>>>,
+    /// Number of the next file created, above every file in the directory.
+    next: FileId,
+}
+
+struct OpenFile {
+    file: File,
+    pages: u32,
+}
+
+impl FileManager {
+    /// Manages the files in `directory`, which is created if it does not exist.
+    pub fn open(directory: impl Into<PathBuf>) -> Result<Self, StorageError> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+
+        let mut last = 0;
+        for entry in std::fs::read_dir(&directory)? {
+            if let Some(file) = entry?.file_name().to_str().and_then(|name| name.parse::<FileId>().ok()) {
+                last = last.max(file);
+            }
+        }
+
+        Ok(FileManager {
+            directory,
+            files: Mutex::new(Files {
+                open: HashMap::new(),
+                next: last + 1,
+            }),
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Creates an empty file.
+    pub fn create_file(&self) -> Result<FileId, StorageError> {
+        let mut files = self.files.lock().unwrap();
+        let id = files.next;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(self.path(id))?;
+
+        files.next += 1;
+        files.open.insert(id, Arc::new(Mutex::new(OpenFile { file, pages: 0 })));
+        Ok(id)
+    }
+
+    /// Deletes the file and its pages.
+    pub fn remove_file(&self, id: FileId) -> Result<(), StorageError> {
+        let mut files = self.files.lock().unwrap();
+        files.open.remove(&id);
+        match std::fs::remove_file(self.path(id)) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(StorageError::UnknownFile(id)),
+            result => Ok(result?),
+        }
+    }
+
+    pub fn page_count(&self, id: FileId) -> Result<u32, StorageError> {
+        Ok(self.file(id)?.lock().unwrap().pages)
+    }
+
+    /// Appends a page of zeros to the file, and returns its number.
+    pub fn allocate_page(&self, id: FileId) -> Result<u32, StorageError> {
+        let file = self.file(id)?;
+        let mut file = file.lock().unwrap();
+        let page = file.pages;
+        file.file.seek(SeekFrom::Start(page as u64 * PAGE_SIZE as u64))?;
+        file.file.write_all(Page::new().data())?;
+        file.pages += 1;
+        Ok(page)
+    }
+
+    /// Reads a page and checks its checksum.
+    pub fn read_page(&self, id: PageId, page: &mut Page) -> Result<(), StorageError> {
+        let file = self.file(id.file)?;
+        let mut file = file.lock().unwrap();
+        if id.page >= file.pages {
+            return Err(StorageError::UnknownPage(id));
+        }
+        file.file.seek(SeekFrom::Start(id.page as u64 * PAGE_SIZE as u64))?;
+        file.file.read_exact(page.data_mut())?;
+        drop(file);
+
+        if !page.verify_checksum() {
+            return Err(StorageError::Corrupt(id, "checksum mismatch".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Stores the checksum in the page and writes it. The page is in the file once [`FileManager::sync`] returns.
+    pub fn write_page(&self, id: PageId, page: &mut Page) -> Result<(), StorageError> {
+        let file = self.file(id.file)?;
+        let mut file = file.lock().unwrap();
+        if id.page >= file.pages {
+            return Err(StorageError::UnknownPage(id));
+        }
+        page.update_checksum();
+        file.file.seek(SeekFrom::Start(id.page as u64 * PAGE_SIZE as u64))?;
+        file.file.write_all(page.data())?;
+        Ok(())
+    }
+
+    /// Waits until every page written to the file is on disk.
+    pub fn sync(&self, id: FileId) -> Result<(), StorageError> {
+        let file = self.file(id)?;
+        let file = file.lock().unwrap();
+        file.file.sync_data()?;
+        Ok(())
+    }
+
+    fn path(&self, id: FileId) -> PathBuf {
+        self.directory.join(id.to_string())
+    }
+
+    /// The open file, opened on first use.
+    fn file(&self, id: FileId) -> Result<Arc<Mutex<OpenFile>>, StorageError> {
+        let mut files = self.files.lock().unwrap();
+        if let Some(file) = files.open.get(&id) {
+            return Ok(file.clone());
+        }
+
+        let file = match OpenOptions::new().read(true).write(true).open(self.path(id)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Err(StorageError::UnknownFile(id)),
+            Err(err) => return Err(err.into()),
+        };
+        let pages = (file.metadata()?.len() / PAGE_SIZE as u64) as u32;
+        let file = Arc::new(Mutex::new(OpenFile { file, pages }));
+        files.open.insert(id, file.clone());
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::storage::page::PageKind;
+    use crate::storage::testing::TempDir;
+
+    #[test]
+    fn test_pages() {
+        let dir = TempDir::new();
+        let files = FileManager::open(dir.path()).unwrap();
+        let file = files.create_file().unwrap();
+        assert_eq!(files.page_count(file).unwrap(), 0);
+        assert_eq!(files.allocate_page(file).unwrap(), 0);
+        assert_eq!(files.allocate_page(file).unwrap(), 1);
+
+        let mut page = Page::new();
+        page.set_kind(PageKind::Heap);
+        page.data_mut()[100] = 7;
+        files.write_page(PageId::new(file, 1), &mut page).unwrap();
+        files.sync(file).unwrap();
+
+        let mut read = Page::new();
+        files.read_page(PageId::new(file, 0), &mut read).unwrap();
+        assert!(read.is_new());
+        files.read_page(PageId::new(file, 1), &mut read).unwrap();
+        assert_eq!(read, page);
+
+        assert!(matches!(
+            files.read_page(PageId::new(file, 2), &mut read),
+            Err(StorageError::UnknownPage(_))
+        ));
+        assert!(matches!(files.page_count(file + 1), Err(StorageError::UnknownFile(_))));
+
+        // Numbers continue after the files already there, and pages survive reopening.
+        drop(files);
+        let files = FileManager::open(dir.path()).unwrap();
+        assert_eq!(files.create_file().unwrap(), file + 1);
+        assert_eq!(files.page_count(file).unwrap(), 2);
+        files.read_page(PageId::new(file, 1), &mut read).unwrap();
+        assert_eq!(read.data()[100], 7);
+
+        files.remove_file(file).unwrap();
+        assert!(matches!(files.page_count(file), Err(StorageError::UnknownFile(_))));
+        assert!(matches!(files.remove_file(file), Err(StorageError::UnknownFile(_))));
+    }
+
+    #[test]
+    fn test_corruption() {
+        let dir = TempDir::new();
+        let files = FileManager::open(dir.path()).unwrap();
+        let file = files.create_file().unwrap();
+        files.allocate_page(file).unwrap();
+        let mut page = Page::new();
+        page.set_kind(PageKind::Heap);
+        files.write_page(PageId::new(file, 0), &mut page).unwrap();
+
+        // Flip a bit behind the file manager's back, and leave half a page at the end.
+        let path = dir.path().join(file.to_string());
+        let mut data = std::fs::read(&path).unwrap();
+        data[PAGE_SIZE / 2] ^= 1;
+        data.extend_from_slice(&[1; PAGE_SIZE / 2]);
+        std::fs::write(&path, data).unwrap();
+
+        let files = FileManager::open(dir.path()).unwrap();
+        assert_eq!(files.page_count(file).unwrap(), 1);
+        let err = files.read_page(PageId::new(file, 0), &mut page).unwrap_err();
+        assert_eq!(err.to_string(), format!("page {file}:0 is corrupt: checksum mismatch"));
+        assert_eq!(files.allocate_page(file).unwrap(), 1);
+    }
+}
@@ -0,0 +1,638 @@
+//! Hash indexes, the ids of rows found by equality on a key of some of their values.
+//!
+//! Keys are hashed with extendible hashing. The first page of the file holds the global depth of the directory,
+//! the number of key columns, whether keys are unique, and the directory: the bucket of each hash, by its lowest
+//! global depth bits. A bucket is a chain of pages, which after the common page header hold:
+//!
+//! | bytes    | field                                                           |
+//! |----------|-----------------------------------------------------------------|
+//! | 16..18   | local depth, the number of hash bits its entries share          |
+//! | 18..20   | number of entries                                               |
+//! | 20..22   | offset of the end of the entries                                |
+//! | 22..26   | next page of the bucket                                         |
+//! | 26..     | entries                                                         |
+//!
+//! An entry is the hash of its key as a `u32`, the row, the length of the key as a `u16`, and the key encoded by
+//! [`encode_key`]. A full bucket splits in two by one more bit of the hash, doubling the directory when its local
+//! depth is the global one. Buckets whose entries all have the same hash, or that are as deep as the directory
+//! gets, grow another page instead.
+//!
+//! Lookups and inserts latch the directory for reading until they have latched the first page of the bucket, and
+//! hold that while they go through the rest of it. Splits latch the directory for writing. Emptied pages stay in
+//! their bucket, and buckets are never merged.
+
+use crate::storage::btree::MAX_KEY_LEN;
+use crate::storage::buffer::{BufferPool, PageReadGuard, PageWriteGuard};
+use crate::storage::key::encode_key;
+use crate::storage::page::{Crc32, PAGE_HEADER_LEN, PAGE_SIZE, Page, PageKind};
+use crate::storage::row::RowError;
+use crate::storage::{FileId, PageId, RowId, StorageError};
+use crate::value::Value;
+use std::ops::{Deref, DerefMut};
+
+const META_PAGE: u32 = 0;
+const GLOBAL_DEPTH: usize = PAGE_HEADER_LEN;
+const COLUMNS: usize = PAGE_HEADER_LEN + 2;
+const UNIQUE: usize = PAGE_HEADER_LEN + 4;
+const DIRECTORY: usize = PAGE_HEADER_LEN + 8;
+
+const LOCAL_DEPTH: usize = PAGE_HEADER_LEN;
+const ENTRY_COUNT: usize = PAGE_HEADER_LEN + 2;
+const ENTRIES_END: usize = PAGE_HEADER_LEN + 4;
+const NEXT: usize = PAGE_HEADER_LEN + 6;
+const ENTRIES: usize = PAGE_HEADER_LEN + 10;
+const NO_PAGE: u32 = u32::MAX;
+
+/// Space an entry takes besides its key.
+const ENTRY_OVERHEAD: usize = 4 + 6 + 2;
+
+/// Deepest directory, the largest that fits in the first page.
+const MAX_DEPTH: u16 = ((PAGE_SIZE - DIRECTORY) / 4).ilog2() as u16;
+
+fn hash_of(key: &[u8]) -> u32 {
+    Crc32::checksum(key)
+}
+
+/// An entry of a bucket page.
+struct Entry<'a> {
+    offset: usize,
+    hash: u32,
+    row: RowId,
+    key: &'a [u8],
+}
+
+impl Entry<'_> {
+    fn len(&self) -> usize {
+        ENTRY_OVERHEAD + self.key.len()
+    }
+}
+
+/// A page of a bucket.
+struct Bucket<P> {
+    page: P,
+    id: PageId,
+}
+
+impl<P: Deref<Target = Page>> Bucket<P> {
+    /// Checks that the page is a bucket whose entries stay inside of it.
+    fn open(page: P, id: PageId) -> Result<Self, StorageError> {
+        let corrupt = |message: &str| Err(StorageError::Corrupt(id, message.to_string()));
+        if page.kind() != Some(PageKind::HashBucket) {
+            return corrupt("not a hash bucket");
+        }
+
+        let bucket = Bucket { page, id };
+        let end = bucket.end();
+        if !(ENTRIES..=PAGE_SIZE).contains(&end) {
+            return corrupt("entries end outside of the page");
+        }
+        let (mut offset, mut count) = (ENTRIES, 0);
+        while offset < end {
+            if offset + ENTRY_OVERHEAD > end || offset + ENTRY_OVERHEAD + bucket.page.get_u16(offset + 10) as usize > end {
+                return corrupt("entry outside of the page");
+            }
+            offset += bucket.entry_at(offset).len();
+            count += 1;
+        }
+        if count != bucket.count() {
+            return corrupt("wrong number of entries");
+        }
+        Ok(bucket)
+    }
+
+    fn depth(&self) -> u16 {
+        self.page.get_u16(LOCAL_DEPTH)
+    }
+
+    fn count(&self) -> usize {
+        self.page.get_u16(ENTRY_COUNT) as usize
+    }
+
+    fn end(&self) -> usize {
+        self.page.get_u16(ENTRIES_END) as usize
+    }
+
+    fn next(&self) -> u32 {
+        self.page.get_u32(NEXT)
+    }
+
+    fn free_space(&self) -> usize {
+        PAGE_SIZE - self.end()
+    }
+
+    fn entry_at(&self, offset: usize) -> Entry<'_> {
+        let key_len = self.page.get_u16(offset + 10) as usize;
+        Entry {
+            offset,
+            hash: self.page.get_u32(offset),
+            row: RowId::new(self.page.get_u32(offset + 4), self.page.get_u16(offset + 8)),
+            key: &self.page.data()[offset + ENTRY_OVERHEAD..offset + ENTRY_OVERHEAD + key_len],
+        }
+    }
+
+    /// Fails when the new entry duplicates a unique key, returns whether other keys have other hashes.
+    fn check(&self, new: &NewEntry) -> Result<bool, StorageError> {
+        let mut other_hashes = false;
+        for e in self.entries() {
+            if new.unique && e.hash == new.hash && e.key == new.entry {
+                return Err(StorageError::DuplicateKey(new.key.to_vec()));
+            }
+            other_hashes |= e.hash != new.hash;
+        }
+        Ok(other_hashes)
+    }
+
+    fn owned_entries(&self) -> Vec<(u32, RowId, Vec<u8>)> {
+        self.entries().map(|e| (e.hash, e.row, e.key.to_vec())).collect()
+    }
+
+    fn entries(&self) -> impl Iterator<Item = Entry<'_>> {
+        let mut offset = ENTRIES;
+        std::iter::from_fn(move || {
+            if offset >= self.end() {
+                return None;
+            }
+            let entry = self.entry_at(offset);
+            offset += entry.len();
+            Some(entry)
+        })
+    }
+}
+
+impl<P: DerefMut<Target = Page>> Bucket<P> {
+    fn init(mut page: P, id: PageId, depth: u16) -> Self {
+        page.set_kind(PageKind::HashBucket);
+        page.put_u32(NEXT, NO_PAGE);
+        let mut bucket = Bucket { page, id };
+        bucket.clear(depth);
+        bucket
+    }
+
+    /// Removes every entry, keeping the link to the next page.
+    fn clear(&mut self, depth: u16) {
+        self.page.put_u16(LOCAL_DEPTH, depth);
+        self.page.put_u16(ENTRY_COUNT, 0);
+        self.page.put_u16(ENTRIES_END, ENTRIES as u16);
+    }
+
+    fn set_next(&mut self, page: u32) {
+        self.page.put_u32(NEXT, page);
+    }
+
+    /// Appends an entry, `false` when it does not fit.
+    fn push(&mut self, hash: u32, row: RowId, key: &[u8]) -> bool {
+        let offset = self.end();
+        if ENTRY_OVERHEAD + key.len() > self.free_space() {
+            return false;
+        }
+        self.page.put_u32(offset, hash);
+        self.page.put_u32(offset + 4, row.page);
+        self.page.put_u16(offset + 8, row.slot);
+        self.page.put_u16(offset + 10, key.len() as u16);
+        self.page.data_mut()[offset + ENTRY_OVERHEAD..offset + ENTRY_OVERHEAD + key.len()].copy_from_slice(key);
+        let count = self.count() as u16;
+        self.page.put_u16(ENTRY_COUNT, count + 1);
+        self.page.put_u16(ENTRIES_END, (offset + ENTRY_OVERHEAD + key.len()) as u16);
+        true
+    }
+
+    fn remove(&mut self, offset: usize) {
+        let (len, end, count) = (self.entry_at(offset).len(), self.end(), self.count() as u16);
+        self.page.data_mut().copy_within(offset + len..end, offset);
+        self.page.put_u16(ENTRY_COUNT, count - 1);
+        self.page.put_u16(ENTRIES_END, (end - len) as u16);
+    }
+}
+
+/// A key being inserted.
+struct NewEntry<'a> {
+    key: &'a [Value],
+    entry: Vec<u8>,
+    hash: u32,
+    row: RowId,
+    /// Whether the key may not be there already.
+    unique: bool,
+}
+
+/// What an insert into a bucket did.
+enum Added {
+    Done,
+    /// The bucket is full and splitting it would make room.
+    Full,
+}
+
+/// An index stored in its own file.
+pub struct HashIndex {
+    pool: BufferPool,
+    file: FileId,
+    columns: usize,
+    unique: bool,
+}
+
+impl HashIndex {
+    /// A new empty index of keys of `columns` values.
+    pub fn create(pool: BufferPool, columns: usize, unique: bool) -> Result<Self, StorageError> {
+        let file = pool.files().create_file()?;
+        let (_, mut meta) = pool.new_page(file)?;
+        let (bucket, guard) = pool.new_page(file)?;
+        Bucket::init(guard, PageId::new(file, bucket), 0);
+
+        meta.set_kind(PageKind::HashMeta);
+        meta.put_u16(GLOBAL_DEPTH, 0);
+        meta.put_u16(COLUMNS, columns as u16);
+        meta.data_mut()[UNIQUE] = unique as u8;
+        meta.put_u32(DIRECTORY, bucket);
+        Ok(HashIndex {
+            pool,
+            file,
+            columns,
+            unique,
+        })
+    }
+
+    pub fn open(pool: BufferPool, file: FileId) -> Result<Self, StorageError> {
+        let id = PageId::new(file, META_PAGE);
+        let meta = pool.fetch_read(id)?;
+        if meta.kind() != Some(PageKind::HashMeta) || meta.get_u16(GLOBAL_DEPTH) > MAX_DEPTH {
+            return Err(StorageError::Corrupt(id, "not a hash index".to_string()));
+        }
+        let (columns, unique) = (meta.get_u16(COLUMNS) as usize, meta.data()[UNIQUE] != 0);
+        drop(meta);
+
+        Ok(HashIndex {
+            pool,
+            file,
+            columns,
+            unique,
+        })
+    }
+
+    pub fn file(&self) -> FileId {
+        self.file
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn is_unique(&self) -> bool {
+        self.unique
+    }
+
+    /// Adds the key of a row. Fails on a unique index that already has the key, unless it has a NULL.
+    pub fn insert(&self, key: &[Value], row: RowId) -> Result<(), StorageError> {
+        let entry = self.entry(key)?;
+        let new = NewEntry {
+            key,
+            hash: hash_of(&entry),
+            entry,
+            row,
+            unique: self.unique && !key.iter().any(Value::is_null),
+        };
+
+        let meta = self.pool.fetch_read(self.page_id(META_PAGE))?;
+        let head = self.write(directory(&meta, new.hash))?;
+        drop(meta);
+        if let Added::Done = self.add(head, &new)? {
+            return Ok(());
+        }
+
+        // The bucket splits, until the one the key goes to has room.
+        let mut meta = self.pool.fetch_write(self.page_id(META_PAGE))?;
+        loop {
+            let head = self.write(directory(&meta, new.hash))?;
+            match self.add(head, &new)? {
+                Added::Done => return Ok(()),
+                Added::Full => self.split(&mut meta, new.hash)?,
+            }
+        }
+    }
+
+    /// Removes the key of a row, returns whether it was there.
+    pub fn delete(&self, key: &[Value], row: RowId) -> Result<bool, StorageError> {
+        let entry = self.entry(key)?;
+        let hash = hash_of(&entry);
+        let meta = self.pool.fetch_read(self.page_id(META_PAGE))?;
+        let mut head = self.write(directory(&meta, hash))?;
+        drop(meta);
+
+        let found = |bucket: &Bucket<PageWriteGuard>| {
+            bucket
+                .entries()
+                .find(|e| e.hash == hash && e.row == row && e.key == entry)
+                .map(|e| e.offset)
+        };
+        if let Some(offset) = found(&head) {
+            head.remove(offset);
+            return Ok(true);
+        }
+        for page in self.chain(&head)? {
+            let mut bucket = self.write(page)?;
+            if let Some(offset) = found(&bucket) {
+                bucket.remove(offset);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Rows with the key.
+    pub fn get(&self, key: &[Value]) -> Result<Vec<RowId>, StorageError> {
+        let entry = self.entry(key)?;
+        let hash = hash_of(&entry);
+        let meta = self.pool.fetch_read(self.page_id(META_PAGE))?;
+        let head = self.read(directory(&meta, hash))?;
+        drop(meta);
+
+        let matching = |bucket: &Bucket<PageReadGuard>| -> Vec<RowId> {
+            bucket
+                .entries()
+                .filter(|e| e.hash == hash && e.key == entry)
+                .map(|e| e.row)
+                .collect()
+        };
+        let mut rows = matching(&head);
+        for page in self.chain(&head)? {
+            rows.extend(matching(&self.read(page)?));
+        }
+        Ok(rows)
+    }
+
+    /// The key of the row as stored in the index.
+    fn entry(&self, key: &[Value]) -> Result<Vec<u8>, StorageError> {
+        if key.len() != self.columns {
+            return Err(RowError::ColumnCount {
+                expected: self.columns,
+                actual: key.len(),
+            }
+            .into());
+        }
+        let entry = encode_key(key);
+        if entry.len() > MAX_KEY_LEN {
+            return Err(StorageError::KeyTooLarge(entry.len()));
+        }
+        Ok(entry)
+    }
+
+    /// Pages of the bucket after its first one.
+    fn chain<P: Deref<Target = Page>>(&self, head: &Bucket<P>) -> Result<Vec<u32>, StorageError> {
+        let mut pages = Vec::new();
+        let mut next = head.next();
+        while next != NO_PAGE {
+            if next == head.id.page || pages.contains(&next) {
+                return Err(StorageError::Corrupt(head.id, "bucket pages form a cycle".to_string()));
+            }
+            pages.push(next);
+            next = self.read(next)?.next();
+        }
+        Ok(pages)
+    }
+
+    /// Adds an entry to the bucket. When it is full, it grows another page unless a split would make room.
+    fn add(&self, mut head: Bucket<PageWriteGuard>, new: &NewEntry) -> Result<Added, StorageError> {
+        let chain = self.chain(&head)?;
+        let len = ENTRY_OVERHEAD + new.entry.len();
+        let mut other_hashes = head.check(new)?;
+        let mut room = (head.free_space() >= len).then_some(head.id.page);
+        for page in &chain {
+            let bucket = self.read(*page)?;
+            other_hashes |= bucket.check(new)?;
+            if room.is_none() && bucket.free_space() >= len {
+                room = Some(*page);
+            }
+        }
+
+        let (hash, row, entry) = (new.hash, new.row, &new.entry);
+        match room {
+            Some(page) if page == head.id.page => assert!(head.push(hash, row, entry)),
+            Some(page) => assert!(self.write(page)?.push(hash, row, entry)),
+            None if other_hashes && head.depth() < MAX_DEPTH => return Ok(Added::Full),
+            None => {
+                let (page, guard) = self.pool.new_page(self.file)?;
+                let mut bucket = Bucket::init(guard, self.page_id(page), head.depth());
+                assert!(bucket.push(hash, row, entry), "an entry fits in an empty page");
+                match chain.last() {
+                    Some(last) => self.write(*last)?.set_next(page),
+                    None => head.set_next(page),
+                }
+            }
+        }
+        Ok(Added::Done)
+    }
+
+    /// Splits the bucket of the hash by one more bit, doubling the directory if it has to.
+    fn split(&self, meta: &mut PageWriteGuard, hash: u32) -> Result<(), StorageError> {
+        let head_page = directory(meta, hash);
+        let mut head = self.write(head_page)?;
+        let depth = head.depth();
+        let global = meta.get_u16(GLOBAL_DEPTH);
+        if depth >= global {
+            for i in 0..1 << global {
+                let bucket = meta.get_u32(DIRECTORY + 4 * i);
+                meta.put_u32(DIRECTORY + 4 * ((1 << global) + i), bucket);
+            }
+            meta.put_u16(GLOBAL_DEPTH, global + 1);
+        }
+
+        let chain = self.chain(&head)?;
+        let mut entries = head.owned_entries();
+        for page in &chain {
+            entries.extend(self.read(*page)?.owned_entries());
+        }
+        let (high, low): (Vec<_>, Vec<_>) = entries.into_iter().partition(|(hash, _, _)| hash >> depth & 1 == 1);
+
+        let (page, guard) = self.pool.new_page(self.file)?;
+        let new = Bucket::init(guard, self.page_id(page), depth + 1);
+        self.fill(new, &[], &high)?;
+        head.clear(depth + 1);
+        self.fill(head, &chain, &low)?;
+
+        for i in 0..1usize << meta.get_u16(GLOBAL_DEPTH) {
+            let at = DIRECTORY + 4 * i;
+            if meta.get_u32(at) == head_page && i >> depth & 1 == 1 {
+                meta.put_u32(at, page);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the entries to an emptied bucket, reusing its pages in order and adding more as needed.
+    fn fill(
+        &self,
+        head: Bucket<PageWriteGuard>,
+        chain: &[u32],
+        entries: &[(u32, RowId, Vec<u8>)],
+    ) -> Result<(), StorageError> {
+        let depth = head.depth();
+        let mut pages = chain.iter();
+        let mut current = head;
+        for (hash, row, key) in entries {
+            if current.push(*hash, *row, key) {
+                continue;
+            }
+            current = match pages.next() {
+                Some(page) => {
+                    let mut bucket = self.write(*page)?;
+                    bucket.clear(depth);
+                    bucket
+                }
+                None => {
+                    let (page, guard) = self.pool.new_page(self.file)?;
+                    current.set_next(page);
+                    Bucket::init(guard, self.page_id(page), depth)
+                }
+            };
+            assert!(current.push(*hash, *row, key), "an entry fits in an empty page");
+        }
+        drop(current);
+        for page in pages {
+            self.write(*page)?.clear(depth);
+        }
+        Ok(())
+    }
+
+    fn page_id(&self, page: u32) -> PageId {
+        PageId::new(self.file, page)
+    }
+
+    fn read(&self, page: u32) -> Result<Bucket<PageReadGuard>, StorageError> {
+        Bucket::open(self.pool.fetch_read(self.page_id(page))?, self.page_id(page))
+    }
+
+    fn write(&self, page: u32) -> Result<Bucket<PageWriteGuard>, StorageError> {
+        Bucket::open(self.pool.fetch_write(self.page_id(page))?, self.page_id(page))
+    }
+}
+
+/// First page of the bucket of the hash.
+fn directory(meta: &Page, hash: u32) -> u32 {
+    let mask = (1u32 << meta.get_u16(GLOBAL_DEPTH)) - 1;
+    meta.get_u32(DIRECTORY + 4 * (hash & mask) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::storage::buffer::EvictionPolicyKind;
+    use crate::storage::file::FileManager;
+    use crate::storage::testing::TempDir;
+    use std::sync::Arc;
+
+    fn pool(dir: &TempDir) -> BufferPool {
+        BufferPool::new(
+            Arc::new(FileManager::open(dir.path()).unwrap()),
+            32,
+            EvictionPolicyKind::Clock,
+        )
+    }
+
+    fn row(n: i64) -> RowId {
+        RowId::new(n as u32 / 100, n as u16 % 100)
+    }
+
+    fn global_depth(index: &HashIndex) -> u16 {
+        index.pool.fetch_read(index.page_id(META_PAGE)).unwrap().get_u16(GLOBAL_DEPTH)
+    }
+
+    #[test]
+    fn test_insert_and_get() {
+        let dir = TempDir::new();
+        let index = HashIndex::create(pool(&dir), 1, false).unwrap();
+
+        let key = |n: i64| vec![Value::Text(format!("{n}{0}", "x".repeat(100)))];
+        for n in 0..5000 {
+            index.insert(&key(n), row(n)).unwrap();
+        }
+        assert!(global_depth(&index) >= 5);
+        index.insert(&key(42), row(9999)).unwrap();
+        assert_eq!(index.get(&key(42)).unwrap(), vec![row(42), row(9999)]);
+        assert_eq!(index.get(&key(4999)).unwrap(), vec![row(4999)]);
+        assert_eq!(index.get(&key(5000)).unwrap(), vec![]);
+
+        assert!(index.delete(&key(42), row(42)).unwrap());
+        assert!(!index.delete(&key(42), row(42)).unwrap());
+        assert_eq!(index.get(&key(42)).unwrap(), vec![row(9999)]);
+
+        // Everything survives flushing and reopening.
+        index.pool.flush_all().unwrap();
+        let file = index.file();
+        drop(index);
+        let index = HashIndex::open(pool(&dir), file).unwrap();
+        assert_eq!((index.columns(), index.is_unique()), (1, false));
+        assert!((0..5000).all(|n| n == 42 || index.get(&key(n)).unwrap() == vec![row(n)]));
+
+        assert!(matches!(
+            index.insert(&[Value::Text("x".repeat(MAX_KEY_LEN))], row(0)),
+            Err(StorageError::KeyTooLarge(_))
+        ));
+        assert!(matches!(index.get(&[]), Err(StorageError::Row(RowError::ColumnCount { .. }))));
+    }
+
+    #[test]
+    fn test_overflow_pages() {
+        let dir = TempDir::new();
+        let index = HashIndex::create(pool(&dir), 1, false).unwrap();
+
+        // Entries of one key never split apart, their bucket grows pages instead.
+        let same = [Value::Text("y".repeat(500))];
+        for n in 0..200 {
+            index.insert(&same, row(n)).unwrap();
+        }
+        assert_eq!(global_depth(&index), 0);
+        assert_eq!(index.get(&same).unwrap(), (0..200).map(row).collect::<Vec<_>>());
+
+        // Other keys split the bucket, whose pages then hold the entries that stay.
+        for n in 0..2000 {
+            index.insert(&[Value::Int(n)], row(n)).unwrap();
+        }
+        assert!(global_depth(&index) > 0);
+        assert_eq!(index.get(&same).unwrap().len(), 200);
+        assert!((0..2000).all(|n| index.get(&[Value::Int(n)]).unwrap() == vec![row(n)]));
+        for n in 0..200 {
+            assert!(index.delete(&same, row(n)).unwrap());
+        }
+        assert_eq!(index.get(&same).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_unique() {
+        let dir = TempDir::new();
+        let index = HashIndex::create(pool(&dir), 2, true).unwrap();
+        let key = |a: i64, b: Value| vec![Value::Int(a), b];
+
+        index.insert(&key(1, Value::Int(1)), row(1)).unwrap();
+        index.insert(&key(1, Value::Int(2)), row(2)).unwrap();
+        let err = index.insert(&key(1, Value::Int(1)), row(3)).unwrap_err();
+        assert!(matches!(&err, StorageError::DuplicateKey(key) if key == &[Value::Int(1), Value::Int(1)]));
+
+        index.insert(&key(1, Value::Null), row(4)).unwrap();
+        index.insert(&key(1, Value::Null), row(5)).unwrap();
+        assert_eq!(index.get(&key(1, Value::Null)).unwrap(), vec![row(4), row(5)]);
+
+        assert!(index.delete(&key(1, Value::Int(1)), row(1)).unwrap());
+        index.insert(&key(1, Value::Int(1)), row(3)).unwrap();
+        assert_eq!(index.get(&key(1, Value::Int(1))).unwrap(), vec![row(3)]);
+    }
+
+    #[test]
+    fn test_concurrent() {
+        let dir = TempDir::new();
+        let index = HashIndex::create(pool(&dir), 1, true).unwrap();
+        let key = |n: i64| [Value::Text(format!("{n:06}{0}", "z".repeat(100)))];
+
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let index = &index;
+                scope.spawn(move || {
+                    for n in (0..3000).filter(|n| n % 4 == thread) {
+                        index.insert(&key(n), row(n)).unwrap();
+                        assert_eq!(index.get(&key(n)).unwrap(), vec![row(n)]);
+                    }
+                });
+            }
+        });
+
+        assert!((0..3000).all(|n| index.get(&key(n)).unwrap() == vec![row(n)]));
+    }
+}
@@ -0,0 +1,700 @@
+//! Heap files, the unordered rows of a table in slotted pages.
+//!
+//! After the common page header a heap page holds the number of slots and where the record area starts, followed
+//! by the slot array. Each slot is the offset and length of a record, records are packed at the end of the page
+//! and grow towards the slots:
+//!
+//! | bytes    | field                                    |
+//! |----------|------------------------------------------|
+//! | 16..18   | number of slots                          |
+//! | 18..20   | offset of the first byte of the records  |
+//! | 20..     | slots, a `u16` offset and `u16` length   |
+//!
+//! A free slot has offset zero, inserts reuse it. Rows are addressed by page and slot, which is how indexes will
+//! refer to them, so a row keeps its [`RowId`] for as long as it exists: when an update no longer fits in the page
+//! the row moves to another one and leaves a forwarding record behind in its slot.
+
+use crate::storage::buffer::{BufferPool, PageReadGuard, PageWriteGuard};
+use crate::storage::page::{PAGE_HEADER_LEN, PAGE_SIZE, Page, PageKind};
+use crate::storage::{FileId, PageId, RowId, StorageError};
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+const SLOT_COUNT: usize = PAGE_HEADER_LEN;
+const RECORDS_START: usize = PAGE_HEADER_LEN + 2;
+const SLOTS: usize = PAGE_HEADER_LEN + 4;
+const SLOT_LEN: usize = 4;
+
+const ROW: u8 = 0;
+const FORWARD: u8 = 1;
+const MOVED: u8 = 2;
+
+const ROW_ID_LEN: usize = 6;
+
+/// Every record takes at least this much space, so a forwarding record always fits in place of the row.
+const MIN_RECORD_LEN: usize = 1 + ROW_ID_LEN;
+
+/// Longest row that fits in a page, with room for it to be moved.
+pub const MAX_ROW_LEN: usize = PAGE_SIZE - SLOTS - SLOT_LEN - 1 - ROW_ID_LEN;
+
+fn stored_len(len: usize) -> usize {
+    len.max(MIN_RECORD_LEN)
+}
+
+/// What a slot holds, the first byte of a record tells which.
+#[derive(Clone, Debug, PartialEq)]
+enum Record<'a> {
+    Row(&'a [u8]),
+    /// The row moved to another page.
+    Forward(RowId),
+    /// A row stored here on behalf of the slot at `home`, which forwards to it.
+    Moved {
+        home: RowId,
+        row: &'a [u8],
+    },
+}
+
+impl<'a> Record<'a> {
+    fn decode(bytes: &'a [u8], page: PageId) -> Result<Self, StorageError> {
+        let row_id = |bytes: &[u8]| {
+            RowId::new(
+                u32::from_le_bytes(bytes[..4].try_into().unwrap()),
+                u16::from_le_bytes(bytes[4..6].try_into().unwrap()),
+            )
+        };
+        match bytes.split_first() {
+            Some((&ROW, row)) => Ok(Record::Row(row)),
+            Some((&FORWARD, target)) if target.len() == ROW_ID_LEN => Ok(Record::Forward(row_id(target))),
+            Some((&MOVED, moved)) if moved.len() >= ROW_ID_LEN => Ok(Record::Moved {
+                home: row_id(moved),
+                row: &moved[ROW_ID_LEN..],
+            }),
+            _ => Err(StorageError::Corrupt(page, "invalid heap record".to_string())),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let put_row_id = |record: &mut Vec<u8>, id: &RowId| {
+            record.extend_from_slice(&id.page.to_le_bytes());
+            record.extend_from_slice(&id.slot.to_le_bytes());
+        };
+        match self {
+            Record::Row(row) => [&[ROW], *row].concat(),
+            Record::Forward(target) => {
+                let mut record = vec![FORWARD];
+                put_row_id(&mut record, target);
+                record
+            }
+            Record::Moved { home, row } => {
+                let mut record = vec![MOVED];
+                put_row_id(&mut record, home);
+                record.extend_from_slice(row);
+                record
+            }
+        }
+    }
+}
+
+/// The slotted layout of a heap page.
+struct HeapPage<P> {
+    page: P,
+    id: PageId,
+}
+
+impl<P: Deref<Target = Page>> HeapPage<P> {
+    /// Checks that the page is a heap page whose slots stay inside of it.
+    fn open(page: P, id: PageId) -> Result<Self, StorageError> {
+        let corrupt = |message: &str| Err(StorageError::Corrupt(id, message.to_string()));
+        if page.kind() != Some(PageKind::Heap) {
+            return corrupt("not a heap page");
+        }
+
+        let heap = HeapPage { page, id };
+        let slots_end = SLOTS + SLOT_LEN * heap.slot_count() as usize;
+        if slots_end > heap.records_start() || heap.records_start() > PAGE_SIZE {
+            return corrupt("slots overlap the records");
+        }
+        for slot in 0..heap.slot_count() {
+            if let Some((offset, len)) = heap.slot(slot)
+                && (offset < heap.records_start() || offset + stored_len(len) > PAGE_SIZE)
+            {
+                return corrupt("slot points outside of the records");
+            }
+        }
+        Ok(heap)
+    }
+
+    fn slot_count(&self) -> u16 {
+        self.page.get_u16(SLOT_COUNT)
+    }
+
+    fn records_start(&self) -> usize {
+        self.page.get_u16(RECORDS_START) as usize
+    }
+
+    /// Offset and length of the record in the slot, `None` for a free slot.
+    fn slot(&self, slot: u16) -> Option<(usize, usize)> {
+        if slot >= self.slot_count() {
+            return None;
+        }
+        let entry = SLOTS + SLOT_LEN * slot as usize;
+        match self.page.get_u16(entry) as usize {
+            0 => None,
+            offset => Some((offset, self.page.get_u16(entry + 2) as usize)),
+        }
+    }
+
+    fn record(&self, slot: u16) -> Result<Option<Record<'_>>, StorageError> {
+        match self.slot(slot) {
+            Some((offset, len)) => Record::decode(&self.page.data()[offset..offset + len], self.id).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Space taken by neither slots nor records, whether contiguous or not.
+    fn unused(&self) -> usize {
+        let records: usize = (0..self.slot_count())
+            .filter_map(|slot| self.slot(slot))
+            .map(|(_, len)| stored_len(len))
+            .sum();
+        PAGE_SIZE - SLOTS - SLOT_LEN * self.slot_count() as usize - records
+    }
+
+    /// Longest record an insert can take.
+    fn free_space(&self) -> usize {
+        let has_free_slot = (0..self.slot_count()).any(|slot| self.slot(slot).is_none());
+        match has_free_slot {
+            true => self.unused(),
+            false => self.unused().saturating_sub(SLOT_LEN),
+        }
+    }
+}
+
+impl<P: DerefMut<Target = Page>> HeapPage<P> {
+    /// Lays out an empty heap page.
+    fn init(mut page: P, id: PageId) -> Self {
+        page.set_kind(PageKind::Heap);
+        page.put_u16(SLOT_COUNT, 0);
+        page.put_u16(RECORDS_START, PAGE_SIZE as u16);
+        HeapPage { page, id }
+    }
+
+    fn set_slot(&mut self, slot: u16, offset: usize, len: usize) {
+        let entry = SLOTS + SLOT_LEN * slot as usize;
+        self.page.put_u16(entry, offset as u16);
+        self.page.put_u16(entry + 2, len as u16);
+    }
+
+    /// Stores the record in a free slot, `None` when it does not fit.
+    fn insert(&mut self, record: &[u8]) -> Option<u16> {
+        let len = stored_len(record.len());
+        if len > self.free_space() {
+            return None;
+        }
+
+        let count = self.slot_count();
+        let slot = (0..count).find(|slot| self.slot(*slot).is_none()).unwrap_or(count);
+        let slots_end = SLOTS + SLOT_LEN * (count.max(slot + 1) as usize);
+        if slots_end + len > self.records_start() {
+            self.compact();
+        }
+        if slot == count {
+            self.page.put_u16(SLOT_COUNT, count + 1);
+        }
+        self.place(slot, record);
+        Some(slot)
+    }
+
+    /// Replaces the record in a slot, `false` when the new one does not fit and the page is left unchanged.
+    fn replace(&mut self, slot: u16, record: &[u8]) -> bool {
+        let Some((offset, old_len)) = self.slot(slot) else {
+            return false;
+        };
+        let len = stored_len(record.len());
+        if len <= stored_len(old_len) {
+            self.page.data_mut()[offset..offset + record.len()].copy_from_slice(record);
+            self.set_slot(slot, offset, record.len());
+            return true;
+        }
+
+        self.set_slot(slot, 0, 0);
+        if len > self.unused() {
+            self.set_slot(slot, offset, old_len);
+            return false;
+        }
+        if SLOTS + SLOT_LEN * self.slot_count() as usize + len > self.records_start() {
+            self.compact();
+        }
+        self.place(slot, record);
+        true
+    }
+
+    /// Copies the record in front of the others, there must be room for it.
+    fn place(&mut self, slot: u16, record: &[u8]) {
+        let offset = self.records_start() - stored_len(record.len());
+        self.page.data_mut()[offset..offset + record.len()].copy_from_slice(record);
+        self.set_slot(slot, offset, record.len());
+        self.page.put_u16(RECORDS_START, offset as u16);
+    }
+
+    /// The record in a used slot, without its first `skip` bytes.
+    fn record_mut(&mut self, slot: u16, skip: usize) -> &mut [u8] {
+        let (offset, len) = self.slot(slot).unwrap();
+        &mut self.page.data_mut()[offset + skip..offset + len]
+    }
+
+    /// Frees the slot, free slots at the end of the array are dropped.
+    fn remove(&mut self, slot: u16) {
+        self.set_slot(slot, 0, 0);
+        let mut count = self.slot_count();
+        while count > 0 && self.slot(count - 1).is_none() {
+            count -= 1;
+        }
+        self.page.put_u16(SLOT_COUNT, count);
+    }
+
+    /// Packs the records at the end of the page, so that all unused space is in one piece.
+    fn compact(&mut self) {
+        let records: Vec<(u16, Vec<u8>)> = (0..self.slot_count())
+            .filter_map(|slot| {
+                self.slot(slot)
+                    .map(|(offset, len)| (slot, self.page.data()[offset..offset + len].to_vec()))
+            })
+            .collect();
+
+        self.page.put_u16(RECORDS_START, PAGE_SIZE as u16);
+        for (slot, record) in records {
+            self.place(slot, &record);
+        }
+    }
+}
+
+/// The rows of a table, in no particular order.
+pub struct HeapFile {
+    pool: BufferPool,
+    file: FileId,
+    /// Longest record an insert can put in each page. Held for the duration of every change, which serializes
+    /// them.
+    free: Mutex<Vec<u16>>,
+}
+
+impl HeapFile {
+    /// Creates an empty heap in a new file.
+    pub fn create(pool: BufferPool) -> Result<Self, StorageError> {
+        let file = pool.files().create_file()?;
+        Ok(HeapFile {
+            pool,
+            file,
+            free: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Opens the heap stored in `file`.
+    pub fn open(pool: BufferPool, file: FileId) -> Result<Self, StorageError> {
+        let mut free = Vec::new();
+        for page in 0..pool.files().page_count(file)? {
+            let id = PageId::new(file, page);
+            let guard = pool.fetch_write(id)?;
+            // A page allocated right before a crash was never written.
+            let heap = match guard.is_new() {
+                true => HeapPage::init(guard, id),
+                false => HeapPage::open(guard, id)?,
+            };
+            free.push(heap.free_space() as u16);
+        }
+
+        Ok(HeapFile {
+            pool,
+            file,
+            free: Mutex::new(free),
+        })
+    }
+
+    pub fn file(&self) -> FileId {
+        self.file
+    }
+
+    pub fn insert(&self, row: &[u8]) -> Result<RowId, StorageError> {
+        if row.len() > MAX_ROW_LEN {
+            return Err(StorageError::RowTooLarge(row.len()));
+        }
+        let mut free = self.free.lock().unwrap();
+        self.insert_record(&mut free, &Record::Row(row).encode())
+    }
+
+    pub fn get(&self, id: RowId) -> Result<Vec<u8>, StorageError> {
+        let mut followed = None;
+        loop {
+            let target = {
+                let heap = self.read(id.page).map_err(row_error(id))?;
+                match heap.record(id.slot)? {
+                    Some(Record::Row(row)) => return Ok(row.to_vec()),
+                    Some(Record::Forward(target)) => target,
+                    Some(Record::Moved { .. }) | None => return Err(StorageError::UnknownRow(id)),
+                }
+            };
+
+            // The home page is released before following the row, since writers latch pages in any order. If
+            // the row moved again in the meantime, it is followed again from home.
+            if let Some(row) = self.moved(target, id)? {
+                return Ok(row);
+            }
+            if followed == Some(target) {
+                return Err(StorageError::Corrupt(
+                    self.page_id(id.page),
+                    format!("row {id} forwards to {target}, which is not there"),
+                ));
+            }
+            followed = Some(target);
+        }
+    }
+
+    /// Replaces the row, which keeps its id, and returns the row it replaced.
+    pub fn update(&self, id: RowId, row: &[u8]) -> Result<Vec<u8>, StorageError> {
+        if row.len() > MAX_ROW_LEN {
+            return Err(StorageError::RowTooLarge(row.len()));
+        }
+        let mut free = self.free.lock().unwrap();
+        let mut heap = self.write(id.page).map_err(row_error(id))?;
+        let (old, target) = match heap.record(id.slot)? {
+            Some(Record::Row(old)) => (old.to_vec(), None),
+            Some(Record::Forward(target)) => (Vec::new(), Some(target)),
+            Some(Record::Moved { .. }) | None => return Err(StorageError::UnknownRow(id)),
+        };
+        let moved = Record::Moved { home: id, row }.encode();
+
+        let Some(target) = target else {
+            if !heap.replace(id.slot, &Record::Row(row).encode()) {
+                // Neither this page nor the ones in the free space map are the page the row moves to.
+                let target = self.insert_record(&mut free, &moved)?;
+                heap.replace(id.slot, &Record::Forward(target).encode());
+            }
+            free[id.page as usize] = heap.free_space() as u16;
+            return Ok(old);
+        };
+
+        let mut target_heap = self.write(target.page)?;
+        let old = self.moved_row(&target_heap, target, id)?;
+        if target_heap.replace(target.slot, &moved) {
+            free[target.page as usize] = target_heap.free_space() as u16;
+            return Ok(old);
+        }
+
+        // Back home if there is room now, somewhere else otherwise.
+        if !heap.replace(id.slot, &Record::Row(row).encode()) {
+            let new_target = self.insert_record(&mut free, &moved)?;
+            heap.replace(id.slot, &Record::Forward(new_target).encode());
+        }
+        free[id.page as usize] = heap.free_space() as u16;
+        target_heap.remove(target.slot);
+        free[target.page as usize] = target_heap.free_space() as u16;
+        Ok(old)
+    }
+
+    /// Changes the row in place through `f`, which cannot change its length.
+    pub fn modify<T>(&self, id: RowId, f: impl FnOnce(&mut [u8]) -> Result<T, StorageError>) -> Result<T, StorageError> {
+        let _free = self.free.lock().unwrap();
+        let mut heap = self.write(id.page).map_err(row_error(id))?;
+        let target = match heap.record(id.slot)? {
+            Some(Record::Row(_)) => return f(heap.record_mut(id.slot, 1)),
+            Some(Record::Forward(target)) => target,
+            Some(Record::Moved { .. }) | None => return Err(StorageError::UnknownRow(id)),
+        };
+
+        let mut target_heap = self.write(target.page)?;
+        self.moved_row(&target_heap, target, id)?;
+        f(target_heap.record_mut(target.slot, 1 + ROW_ID_LEN))
+    }
+
+    /// Removes the row and returns it.
+    pub fn delete(&self, id: RowId) -> Result<Vec<u8>, StorageError> {
+        let mut free = self.free.lock().unwrap();
+        let mut heap = self.write(id.page).map_err(row_error(id))?;
+        let old = match heap.record(id.slot)? {
+            Some(Record::Row(old)) => old.to_vec(),
+            Some(Record::Forward(target)) => {
+                let mut target_heap = self.write(target.page)?;
+                let old = self.moved_row(&target_heap, target, id)?;
+                target_heap.remove(target.slot);
+                free[target.page as usize] = target_heap.free_space() as u16;
+                old
+            }
+            Some(Record::Moved { .. }) | None => return Err(StorageError::UnknownRow(id)),
+        };
+
+        heap.remove(id.slot);
+        free[id.page as usize] = heap.free_space() as u16;
+        Ok(old)
+    }
+
+    pub fn page_count(&self) -> u32 {
+        self.free.lock().unwrap().len() as u32
+    }
+
+    /// Packs the records of the page together, so that its free space is in one piece.
+    pub fn compact(&self, page: u32) -> Result<(), StorageError> {
+        let mut free = self.free.lock().unwrap();
+        let mut heap = self.write(page)?;
+        heap.compact();
+        free[page as usize] = heap.free_space() as u16;
+        Ok(())
+    }
+
+    /// Every row with its id, moved rows under the id of their slot.
+    pub fn scan(&self) -> HeapScan<'_> {
+        HeapScan {
+            heap: self,
+            next_page: 0,
+            rows: VecDeque::new(),
+        }
+    }
+
+    /// Stores the record in the first page with room for it, or in a new page.
+    fn insert_record(&self, free: &mut Vec<u16>, record: &[u8]) -> Result<RowId, StorageError> {
+        let len = stored_len(record.len());
+        let (page_number, mut heap) = match free.iter().position(|space| *space as usize >= len) {
+            Some(page) => (page as u32, self.write(page as u32)?),
+            None => {
+                let (page, guard) = self.pool.new_page(self.file)?;
+                free.push(0);
+                (page, HeapPage::init(guard, self.page_id(page)))
+            }
+        };
+
+        let Some(slot) = heap.insert(record) else {
+            let message = "less free space than the free space map says".to_string();
+            return Err(StorageError::Corrupt(self.page_id(page_number), message));
+        };
+        free[page_number as usize] = heap.free_space() as u16;
+        Ok(RowId::new(page_number, slot))
+    }
+
+    /// The row a forwarding record in `home` points to, `None` when it is not there.
+    fn moved(&self, target: RowId, home: RowId) -> Result<Option<Vec<u8>>, StorageError> {
+        let heap = self.read(target.page)?;
+        match heap.record(target.slot)? {
+            Some(Record::Moved { home: moved_from, row }) if moved_from == home => Ok(Some(row.to_vec())),
+            _ => Ok(None),
+        }
+    }
+
+    /// The row at `target` of a latched page, which a forwarding record in `home` points to.
+    fn moved_row(&self, heap: &HeapPage<PageWriteGuard>, target: RowId, home: RowId) -> Result<Vec<u8>, StorageError> {
+        match heap.record(target.slot)? {
+            Some(Record::Moved { home: moved_from, row }) if moved_from == home => Ok(row.to_vec()),
+            _ => Err(StorageError::Corrupt(
+                self.page_id(home.page),
+                format!("row {home} forwards to {target}, which is not there"),
+            )),
+        }
+    }
+
+    fn page_id(&self, page: u32) -> PageId {
+        PageId::new(self.file, page)
+    }
+
+    fn read(&self, page: u32) -> Result<HeapPage<PageReadGuard>, StorageError> {
+        HeapPage::open(self.pool.fetch_read(self.page_id(page))?, self.page_id(page))
+    }
+
+    fn write(&self, page: u32) -> Result<HeapPage<PageWriteGuard>, StorageError> {
+        HeapPage::open(self.pool.fetch_write(self.page_id(page))?, self.page_id(page))
+    }
+}
+
+/// Turns a page past the end of the file into an unknown row.
+fn row_error(id: RowId) -> impl Fn(StorageError) -> StorageError {
+    move |err| match err {
+        StorageError::UnknownPage(_) => StorageError::UnknownRow(id),
+        err => err,
+    }
+}
+
+/// Reads a heap one page at a time.
+pub struct HeapScan<'a> {
+    heap: &'a HeapFile,
+    next_page: u32,
+    rows: VecDeque<(RowId, Vec<u8>)>,
+}
+
+impl HeapScan<'_> {
+    fn read_page(&mut self, page_number: u32) -> Result<(), StorageError> {
+        let mut forwarded = Vec::new();
+        let heap = self.heap.read(page_number)?;
+        for slot in 0..heap.slot_count() {
+            let id = RowId::new(page_number, slot);
+            match heap.record(slot)? {
+                Some(Record::Row(row)) => self.rows.push_back((id, row.to_vec())),
+                Some(Record::Forward(_)) => forwarded.push(id),
+                // Returned with the slot that forwards to it.
+                Some(Record::Moved { .. }) | None => {}
+            }
+        }
+        drop(heap);
+
+        // Followed once the page is released, like in `get`. Rows deleted in the meantime are skipped.
+        for id in forwarded {
+            match self.heap.get(id) {
+                Ok(row) => self.rows.push_back((id, row)),
+                Err(StorageError::UnknownRow(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for HeapScan<'_> {
+    type Item = Result<(RowId, Vec<u8>), StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(row) = self.rows.pop_front() {
+                return Some(Ok(row));
+            }
+            let pages = match self.heap.pool.files().page_count(self.heap.file) {
+                Ok(pages) => pages,
+                Err(err) => return Some(Err(err)),
+            };
+            if self.next_page >= pages {
+                return None;
+            }
+
+            let page = self.next_page;
+            self.next_page += 1;
+            if let Err(err) = self.read_page(page) {
+                self.next_page = pages;
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::storage::buffer::EvictionPolicyKind;
+    use crate::storage::file::FileManager;
+    use crate::storage::testing::TempDir;
+    use std::sync::Arc;
+
+    fn heap_page() -> Page {
+        let mut page = Page::new();
+        HeapPage::init(&mut page, PageId::new(1, 0));
+        page
+    }
+
+    #[test]
+    fn test_slotted_page() {
+        let mut page = heap_page();
+        let mut heap = HeapPage::open(&mut page, PageId::new(1, 0)).unwrap();
+        let empty = heap.free_space();
+        assert_eq!(empty, PAGE_SIZE - SLOTS - SLOT_LEN);
+
+        let a = heap.insert(b"\x00first").unwrap();
+        let b = heap.insert(b"\x00second").unwrap();
+        let c = heap.insert(b"\x00").unwrap();
+        assert_eq!((a, b, c), (0, 1, 2));
+        assert_eq!(heap.record(b).unwrap(), Some(Record::Row(b"second")));
+        assert_eq!(heap.free_space(), empty - 3 * MIN_RECORD_LEN - 3 * SLOT_LEN);
+
+        // The slot of a removed record is reused, trailing free slots are dropped.
+        heap.remove(a);
+        assert_eq!(heap.record(a).unwrap(), None);
+        assert_eq!(heap.insert(b"\x00third").unwrap(), a);
+        heap.remove(c);
+        assert_eq!(heap.slot_count(), 2);
+
+        // Growing moves the record to the front, the space it leaves behind is reclaimed by compaction.
+        assert!(heap.replace(b, b"\x00second, but longer"));
+        assert!(heap.replace(a, b"\x00t"));
+        assert_eq!(heap.record(a).unwrap(), Some(Record::Row(b"t")));
+        let rest = heap.free_space();
+        assert_eq!(heap.insert(&vec![0; rest + 1]), None);
+        assert!(heap.insert(&vec![0; rest]).is_some());
+        assert_eq!(heap.free_space(), 0);
+        assert_eq!(heap.record(b).unwrap(), Some(Record::Row(b"second, but longer")));
+        assert!(!heap.replace(a, b"\x00tt too long"));
+        assert_eq!(heap.record(a).unwrap(), Some(Record::Row(b"t")));
+    }
+
+    #[test]
+    fn test_corrupt_page() {
+        let id = PageId::new(1, 0);
+        assert!(matches!(HeapPage::open(&Page::new(), id), Err(StorageError::Corrupt(..))));
+
+        let mut page = heap_page();
+        HeapPage::open(&mut page, id).unwrap().insert(b"\x00row").unwrap();
+        page.put_u16(SLOTS, 10);
+        assert!(matches!(HeapPage::open(&page, id), Err(StorageError::Corrupt(..))));
+    }
+
+    #[test]
+    fn test_heap_file() {
+        let dir = TempDir::new();
+        let pool = |frames| {
+            BufferPool::new(
+                Arc::new(FileManager::open(dir.path()).unwrap()),
+                frames,
+                EvictionPolicyKind::Clock,
+            )
+        };
+        let heap = HeapFile::create(pool(4)).unwrap();
+
+        let rows: Vec<Vec<u8>> = (0..1000u32).map(|n| format!("row {n}").into_bytes()).collect();
+        let ids: Vec<RowId> = rows.iter().map(|row| heap.insert(row).unwrap()).collect();
+        assert!(heap.pool.files().page_count(heap.file()).unwrap() > 1);
+        assert_eq!(heap.get(ids[500]).unwrap(), rows[500]);
+
+        assert_eq!(heap.delete(ids[1]).unwrap(), rows[1]);
+        assert!(matches!(heap.get(ids[1]), Err(StorageError::UnknownRow(_))));
+        assert!(matches!(heap.delete(ids[1]), Err(StorageError::UnknownRow(_))));
+        assert!(matches!(heap.get(RowId::new(1000, 0)), Err(StorageError::UnknownRow(_))));
+        assert!(matches!(
+            heap.insert(&[0; MAX_ROW_LEN + 1]),
+            Err(StorageError::RowTooLarge(_))
+        ));
+
+        assert_eq!(heap.update(ids[2], b"short").unwrap(), rows[2]);
+        assert_eq!(heap.get(ids[2]).unwrap(), b"short");
+
+        // The first page is full, so growing a row moves it while it keeps its id.
+        let long = vec![b'x'; 2000];
+        heap.update(ids[3], &long).unwrap();
+        assert_eq!(heap.get(ids[3]).unwrap(), long);
+        let longer = vec![b'y'; 4000];
+        assert_eq!(heap.update(ids[3], &longer).unwrap(), long);
+        assert_eq!(heap.get(ids[3]).unwrap(), longer);
+
+        let scanned: Vec<(RowId, Vec<u8>)> = heap.scan().map(Result::unwrap).collect();
+        assert_eq!(scanned.len(), 999);
+        assert!(scanned.contains(&(ids[3], longer.clone())));
+        assert!(scanned.contains(&(ids[2], b"short".to_vec())));
+
+        // Shrinking a moved row leaves it where it is.
+        heap.update(ids[3], b"small again").unwrap();
+        assert_eq!(heap.get(ids[3]).unwrap(), b"small again");
+        heap.update(ids[4], &long).unwrap();
+        assert_eq!(heap.delete(ids[4]).unwrap(), long);
+        assert_eq!(heap.scan().count(), 998);
+
+        // Compacting a page moves records but not rows.
+        heap.compact(0).unwrap();
+        assert_eq!(heap.get(ids[2]).unwrap(), b"short");
+        assert_eq!(heap.get(ids[3]).unwrap(), b"small again");
+        assert_eq!(heap.page_count(), heap.pool.files().page_count(heap.file()).unwrap());
+
+        // Everything survives flushing and reopening.
+        heap.pool.flush_all().unwrap();
+        let file = heap.file();
+        drop(heap);
+        let heap = HeapFile::open(pool(4), file).unwrap();
+        assert_eq!(heap.get(ids[999]).unwrap(), rows[999]);
+        assert_eq!(heap.get(ids[3]).unwrap(), b"small again");
+        assert_eq!(heap.scan().count(), 998);
+        let id = heap.insert(b"new").unwrap();
+        assert_eq!(heap.get(id).unwrap(), b"new");
+    }
+}
@@ -0,0 +1,233 @@
+//! Index keys, values encoded so that comparing the bytes compares the values.
+//!
+//! Every value starts with a tag byte for its type, so that keys decode without their schema, and NULL sorts
+//! after everything else:
+//!
+//! | type    | after the tag                                                             |
+//! |---------|---------------------------------------------------------------------------|
+//! | BOOL    | 0 or 1                                                                    |
+//! | integer | big endian `i64` with the sign bit flipped                                |
+//! | float   | big endian bits, all flipped when negative and only the sign bit if not   |
+//! | decimal | integer part, then the fraction at scale 38, both like 128 bit integers   |
+//! | string  | the bytes with 0 escaped as `00 ff`, ending with `00 01`                  |
+//!
+//! Values end where the next one starts, so the key of a prefix of the columns is a prefix of the key. Only values
+//! of the same type compare as their values do, which the values of a column are.
+
+use crate::value::{Decimal, Value};
+
+const BOOL: u8 = 0x10;
+const INT: u8 = 0x20;
+const FLOAT: u8 = 0x30;
+const DECIMAL: u8 = 0x40;
+const TEXT: u8 = 0x50;
+const BYTES: u8 = 0x60;
+const NULL: u8 = 0xf0;
+
+const ESCAPE: u8 = 0xff;
+const END: u8 = 0x01;
+
+pub fn encode_key(values: &[Value]) -> Vec<u8> {
+    let mut key = Vec::new();
+    for value in values {
+        match value {
+            Value::Null => key.push(NULL),
+            Value::Bool(b) => key.extend_from_slice(&[BOOL, *b as u8]),
+            Value::Int(n) => {
+                key.push(INT);
+                key.extend_from_slice(&((*n as u64) ^ (1 << 63)).to_be_bytes());
+            }
+            Value::Float(n) => {
+                // Zero is zero whatever its sign.
+                let bits = if *n == 0.0 { 0 } else { n.to_bits() };
+                let bits = if bits >> 63 == 1 { !bits } else { bits ^ (1 << 63) };
+                key.push(FLOAT);
+                key.extend_from_slice(&bits.to_be_bytes());
+            }
+            Value::Decimal(n) => {
+                let unit = 10i128.pow(n.scale() as u32);
+                let fraction = (n.value() % unit) * 10i128.pow((Decimal::MAX_PRECISION - n.scale()) as u32);
+                key.push(DECIMAL);
+                for part in [n.value() / unit, fraction] {
+                    key.extend_from_slice(&((part as u128) ^ (1 << 127)).to_be_bytes());
+                }
+            }
+            Value::Text(s) => encode_bytes(&mut key, TEXT, s.as_bytes()),
+            Value::Bytes(bytes) => encode_bytes(&mut key, BYTES, bytes),
+        }
+    }
+    key
+}
+
+fn encode_bytes(key: &mut Vec<u8>, tag: u8, bytes: &[u8]) {
+    key.push(tag);
+    for byte in bytes {
+        key.push(*byte);
+        if *byte == 0 {
+            key.push(ESCAPE);
+        }
+    }
+    key.extend_from_slice(&[0, END]);
+}
+
+/// Decodes the first `count` values of a key, and returns them with the bytes after them. Decimals come back
+/// with as few fractional digits as they need.
+pub fn decode_key(mut key: &[u8], count: usize) -> Option<(Vec<Value>, &[u8])> {
+    let mut values = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (tag, rest) = key.split_first()?;
+        let (value, rest) = match *tag {
+            NULL => (Value::Null, rest),
+            BOOL => match rest.split_first()? {
+                (0, rest) => (Value::Bool(false), rest),
+                (1, rest) => (Value::Bool(true), rest),
+                _ => return None,
+            },
+            INT => {
+                let (n, rest) = rest.split_first_chunk::<8>()?;
+                (Value::Int((u64::from_be_bytes(*n) ^ (1 << 63)) as i64), rest)
+            }
+            FLOAT => {
+                let (bits, rest) = rest.split_first_chunk::<8>()?;
+                let bits = u64::from_be_bytes(*bits);
+                let bits = if bits >> 63 == 1 { bits ^ (1 << 63) } else { !bits };
+                (Value::Float(f64::from_bits(bits)), rest)
+            }
+            DECIMAL => {
+                let (int_part, rest) = rest.split_first_chunk::<16>()?;
+                let (fraction, rest) = rest.split_first_chunk::<16>()?;
+                let part = |bytes: &[u8; 16]| (u128::from_be_bytes(*bytes) ^ (1 << 127)) as i128;
+                let (int_part, mut fraction) = (part(int_part), part(fraction));
+                let mut scale = Decimal::MAX_PRECISION;
+                while scale > 0 && fraction % 10 == 0 {
+                    fraction /= 10;
+                    scale -= 1;
+                }
+                let value = int_part.checked_mul(10i128.pow(scale as u32))?.checked_add(fraction)?;
+                (Value::Decimal(Decimal::new(value, scale)), rest)
+            }
+            TEXT => {
+                let (bytes, rest) = decode_bytes(rest)?;
+                (Value::Text(String::from_utf8(bytes).ok()?), rest)
+            }
+            BYTES => {
+                let (bytes, rest) = decode_bytes(rest)?;
+                (Value::Bytes(bytes), rest)
+            }
+            _ => return None,
+        };
+        values.push(value);
+        key = rest;
+    }
+    Some((values, key))
+}
+
+fn decode_bytes(key: &[u8]) -> Option<(Vec<u8>, &[u8])> {
+    let mut bytes = Vec::new();
+    let mut i = 0;
+    loop {
+        match *key.get(i)? {
+            0 => match *key.get(i + 1)? {
+                ESCAPE => bytes.push(0),
+                END => return Some((bytes, &key[i + 2..])),
+                _ => return None,
+            },
+            byte => {
+                bytes.push(byte);
+                i += 1;
+                continue;
+            }
+        }
+        i += 2;
+    }
+}
+
+/// The first key after every key that starts with `prefix`, `None` when there is none.
+pub fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last != u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_order() {
+        let decimal = |s: &str| Value::Decimal(s.parse().unwrap());
+        let text = |s: &str| Value::Text(s.to_string());
+        let ordered = [
+            vec![Value::Bool(false), Value::Bool(true), Value::Null],
+            vec![
+                Value::Int(i64::MIN),
+                Value::Int(-1),
+                Value::Int(0),
+                Value::Int(7),
+                Value::Int(i64::MAX),
+            ],
+            vec![
+                Value::Float(f64::NEG_INFINITY),
+                Value::Float(-2.5),
+                Value::Float(-0.0),
+                Value::Float(1e-300),
+                Value::Float(3.0),
+                Value::Float(f64::INFINITY),
+            ],
+            vec![
+                decimal("-12.5"),
+                decimal("-12.25"),
+                decimal("-0.001"),
+                decimal("0"),
+                decimal("0.5"),
+                decimal("1.49999"),
+                decimal("1.5"),
+                decimal(&"9".repeat(38)),
+            ],
+            vec![
+                text(""),
+                text("a"),
+                text("a\0"),
+                text("a\0b"),
+                text("ab"),
+                text("b"),
+                text("é"),
+            ],
+        ];
+        for values in ordered {
+            for pair in values.windows(2) {
+                assert!(encode_key(&pair[..1]) < encode_key(&pair[1..]), "{0} < {1}", pair[0], pair[1]);
+            }
+            for value in values {
+                let key = encode_key(std::slice::from_ref(&value));
+                assert_eq!(decode_key(&key, 1), Some((vec![value], &[][..])));
+            }
+        }
+        assert_eq!(encode_key(&[decimal("1.5")]), encode_key(&[decimal("1.500")]));
+        assert_eq!(encode_key(&[Value::Float(0.0)]), encode_key(&[Value::Float(-0.0)]));
+    }
+
+    #[test]
+    fn test_composite() {
+        let key = |a: &str, b: i64| encode_key(&[Value::Text(a.to_string()), Value::Int(b)]);
+        assert!(key("a", 9) < key("ab", 0));
+        assert!(key("a", -1) < key("a", 0));
+        assert!(key("a", 5).starts_with(&encode_key(&[Value::Text("a".to_string())])));
+
+        let values = vec![Value::Bytes(vec![0, 1, 0]), Value::Null, Value::Int(3)];
+        let mut bytes = encode_key(&values);
+        bytes.extend_from_slice(b"rest");
+        assert_eq!(decode_key(&bytes, 3), Some((values, &b"rest"[..])));
+        assert_eq!(decode_key(&bytes[..5], 1), None);
+        assert_eq!(decode_key(&[0x99], 1), None);
+
+        assert_eq!(prefix_end(&[1, 2, 0xff]), Some(vec![1, 3]));
+        assert_eq!(prefix_end(&[0xff, 0xff]), None);
+    }
+}
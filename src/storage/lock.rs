@@ -0,0 +1,617 @@
+//! Locks sessions take on the database, its tables and their rows, for what versions of rows do not isolate:
+//! changes to the schema, and rows selected `FOR UPDATE` or `FOR SHARE`.
+//!
+//! Locks form a hierarchy. Before locking a table or a row, a session takes an intention lock on what contains it,
+//! so that a lock on a whole table conflicts with locks on its rows without looking for them. Requests are granted
+//! in the order they came in, except that a session upgrading a lock it holds goes first. Sessions keep their locks
+//! until they release all of them at once, at the end of their transaction.
+//!
+//! Sessions waiting for each other form a wait-for graph. A background thread looks for cycles in it periodically
+//! and breaks each one by failing the request of the session in it that started waiting last. A request also
+//! fails once it waited for longer than its timeout.
+
+use crate::parser::token::DataKind;
+use crate::storage::{RowId, StorageError};
+use crate::value::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
+
+/// Name of the system table listing the locks sessions hold and wait for.
+pub const LOCKS_TABLE: &str = "rdb_locks";
+
+pub const LOCKS_COLUMNS: [(&str, DataKind); 6] = [
+    ("session", DataKind::Integer(None)),
+    ("locktype", DataKind::Text(None)),
+    ("relation", DataKind::Text(None)),
+    ("row", DataKind::Text(None)),
+    ("mode", DataKind::Text(None)),
+    ("granted", DataKind::Bool),
+];
+
+/// How often deadlocks are looked for unless configured otherwise.
+pub const DEADLOCK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often waiting sessions check whether they were interrupted.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Id of the session holding a lock, the one it registered for cancellation with.
+pub type SessionId = u32;
+
+/// What can be locked.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LockTarget {
+    Database,
+    /// A table, by name.
+    Table(String),
+    /// A row of a table.
+    Row(String, RowId),
+}
+
+impl LockTarget {
+    /// What contains the target.
+    fn parent(&self) -> Option<LockTarget> {
+        match self {
+            LockTarget::Database => None,
+            LockTarget::Table(_) => Some(LockTarget::Database),
+            LockTarget::Row(table, _) => Some(LockTarget::Table(table.clone())),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            LockTarget::Database => "database",
+            LockTarget::Table(_) => "table",
+            LockTarget::Row(..) => "row",
+        }
+    }
+}
+
+impl Display for LockTarget {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            LockTarget::Database => write!(f, "database"),
+            LockTarget::Table(table) => write!(f, "table \"{table}\""),
+            LockTarget::Row(table, id) => write!(f, "row {id} of table \"{table}\""),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LockMode {
+    /// Shared locks are taken on some of what the target contains.
+    IntentionShared,
+    /// Exclusive locks are taken on some of what the target contains.
+    IntentionExclusive,
+    Shared,
+    /// `Shared` along with `IntentionExclusive`, for reading all of a target while changing some of it.
+    SharedIntentionExclusive,
+    Exclusive,
+}
+
+impl LockMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LockMode::IntentionShared => "IS",
+            LockMode::IntentionExclusive => "IX",
+            LockMode::Shared => "S",
+            LockMode::SharedIntentionExclusive => "SIX",
+            LockMode::Exclusive => "X",
+        }
+    }
+
+    /// Whether two sessions can hold the modes on the same target at once.
+    pub fn compatible(self, other: LockMode) -> bool {
+        use LockMode::*;
+        match (self, other) {
+            (Exclusive, _) | (_, Exclusive) => false,
+            (IntentionShared, _) | (_, IntentionShared) => true,
+            (IntentionExclusive, IntentionExclusive) | (Shared, Shared) => true,
+            _ => false,
+        }
+    }
+
+    /// The weakest mode at least as strong as both.
+    fn join(self, other: LockMode) -> LockMode {
+        use LockMode::*;
+        match (self, other) {
+            (a, b) if a == b => a,
+            (IntentionShared, mode) | (mode, IntentionShared) => mode,
+            (Exclusive, _) | (_, Exclusive) => Exclusive,
+            _ => SharedIntentionExclusive,
+        }
+    }
+
+    /// Mode taken on what contains a target locked in this one.
+    fn intention(self) -> LockMode {
+        match self {
+            LockMode::IntentionShared | LockMode::Shared => LockMode::IntentionShared,
+            _ => LockMode::IntentionExclusive,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Request {
+    session: SessionId,
+    mode: LockMode,
+    since: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Lock {
+    granted: HashMap<SessionId, LockMode>,
+    /// Requests in the order they came in.
+    waiting: Vec<Request>,
+}
+
+impl Lock {
+    /// Whether the waiting request of the session can be granted: no other session holds a conflicting mode, and
+    /// no request that came in before waits unless the session upgrades a lock.
+    fn grantable(&self, session: SessionId, mode: LockMode) -> bool {
+        let compatible = self
+            .granted
+            .iter()
+            .all(|(holder, granted)| *holder == session || granted.compatible(mode));
+        compatible && (self.granted.contains_key(&session) || self.waiting.first().is_some_and(|r| r.session == session))
+    }
+
+    /// Sessions the request waits for.
+    fn blockers(&self, request: &Request) -> Vec<SessionId> {
+        let mut blockers: Vec<SessionId> = self
+            .granted
+            .iter()
+            .filter(|(holder, granted)| **holder != request.session && !granted.compatible(request.mode))
+            .map(|(holder, _)| *holder)
+            .collect();
+        if !self.granted.contains_key(&request.session) {
+            let ahead = self.waiting.iter().take_while(|other| other.session != request.session);
+            blockers.extend(ahead.map(|other| other.session));
+        }
+        blockers
+    }
+
+    fn is_unused(&self) -> bool {
+        self.granted.is_empty() && self.waiting.is_empty()
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    locks: HashMap<LockTarget, Lock>,
+    /// Sessions whose request fails to break a deadlock, with what the sessions in it wait for.
+    victims: HashMap<SessionId, String>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<State>,
+    /// Notified whenever locks are released or requests fail.
+    changed: Condvar,
+}
+
+/// Grants locks to sessions. Clones share the locks.
+#[derive(Clone, Debug)]
+pub struct LockManager(Arc<Shared>);
+
+impl LockManager {
+    /// Looks for deadlocks every `interval` until the last clone is dropped.
+    pub fn new(interval: Duration) -> Self {
+        let shared = Arc::new(Shared::default());
+        let detector = Arc::downgrade(&shared);
+        std::thread::Builder::new()
+            .name("deadlock detector".to_string())
+            .spawn(move || detect_periodically(detector, interval))
+            .expect("could not start the deadlock detector");
+        LockManager(shared)
+    }
+
+    /// Locks `target` in `mode` for the session, and what contains it in the matching intention mode. Waits for at
+    /// most `timeout` in all, or for as long as it takes with `None`, and stops waiting once `interrupted` says so.
+    /// When that fails, the session holds what contains the target in the modes it held it in before.
+    pub fn lock(
+        &self,
+        session: SessionId,
+        target: &LockTarget,
+        mode: LockMode,
+        timeout: Option<Duration>,
+        interrupted: &dyn Fn() -> bool,
+    ) -> Result<(), StorageError> {
+        let held: Vec<(LockTarget, Option<LockMode>)> = std::iter::successors(target.parent(), LockTarget::parent)
+            .map(|parent| {
+                let mode = self.mode(session, &parent);
+                (parent, mode)
+            })
+            .collect();
+        let locked = self.acquire(
+            session,
+            target,
+            mode,
+            timeout.map(|timeout| Instant::now() + timeout),
+            interrupted,
+        );
+        if locked.is_err() {
+            self.restore(session, &held);
+        }
+        locked
+    }
+
+    fn acquire(
+        &self,
+        session: SessionId,
+        target: &LockTarget,
+        mode: LockMode,
+        deadline: Option<Instant>,
+        interrupted: &dyn Fn() -> bool,
+    ) -> Result<(), StorageError> {
+        if let Some(parent) = target.parent() {
+            self.acquire(session, &parent, mode.intention(), deadline, interrupted)?;
+        }
+
+        let mut state = self.state();
+        let lock = state.locks.entry(target.clone()).or_default();
+        let mode = match lock.granted.get(&session) {
+            Some(held) if held.join(mode) == *held => return Ok(()),
+            Some(held) => held.join(mode),
+            None => mode,
+        };
+        lock.waiting.push(Request {
+            session,
+            mode,
+            since: Instant::now(),
+        });
+        loop {
+            let now = Instant::now();
+            let failed = state.victims.remove(&session).map(StorageError::Deadlock);
+            let lock = state.locks.get_mut(target).unwrap();
+            let failed = match failed {
+                None if lock.grantable(session, mode) => {
+                    lock.waiting.retain(|request| request.session != session);
+                    lock.granted.insert(session, mode);
+                    self.0.changed.notify_all();
+                    return Ok(());
+                }
+                None if deadline.is_some_and(|deadline| now >= deadline) || interrupted() => {
+                    Some(StorageError::LockNotAvailable(target.to_string()))
+                }
+                failed => failed,
+            };
+            if let Some(err) = failed {
+                lock.waiting.retain(|request| request.session != session);
+                if lock.is_unused() {
+                    state.locks.remove(target);
+                }
+                // Requests behind this one may go ahead now.
+                self.0.changed.notify_all();
+                return Err(err);
+            }
+
+            let wait = deadline.map_or(POLL_INTERVAL, |deadline| (deadline - now).min(POLL_INTERVAL));
+            state = self.0.changed.wait_timeout(state, wait).unwrap().0;
+        }
+    }
+
+    /// Puts the locks of the session on the targets back to the modes, or releases them with `None`.
+    fn restore(&self, session: SessionId, held: &[(LockTarget, Option<LockMode>)]) {
+        let mut state = self.state();
+        for (target, mode) in held {
+            let Some(lock) = state.locks.get_mut(target) else {
+                continue;
+            };
+            match mode {
+                Some(mode) => lock.granted.insert(session, *mode),
+                None => lock.granted.remove(&session),
+            };
+            if lock.is_unused() {
+                state.locks.remove(target);
+            }
+        }
+        self.0.changed.notify_all();
+    }
+
+    /// Mode the session holds the target in.
+    pub fn mode(&self, session: SessionId, target: &LockTarget) -> Option<LockMode> {
+        let state = self.state();
+        state.locks.get(target)?.granted.get(&session).copied()
+    }
+
+    /// Releases every lock of the session.
+    pub fn release_all(&self, session: SessionId) {
+        let mut state = self.state();
+        state.locks.retain(|_, lock| {
+            lock.granted.remove(&session);
+            !lock.is_unused()
+        });
+        state.victims.remove(&session);
+        self.0.changed.notify_all();
+    }
+
+    /// Looks for sessions waiting for each other, and fails the request of the one in every cycle that started
+    /// waiting last. Returns the sessions of the cycles.
+    pub fn detect_deadlocks(&self) -> Vec<Vec<SessionId>> {
+        let mut state = self.state();
+        let mut requests = HashMap::new();
+        let mut graph = HashMap::new();
+        for (target, lock) in &state.locks {
+            for request in &lock.waiting {
+                if !state.victims.contains_key(&request.session) {
+                    requests.insert(request.session, (target.clone(), *request));
+                    graph.insert(request.session, lock.blockers(request));
+                }
+            }
+        }
+
+        let mut cycles = Vec::new();
+        while let Some(cycle) = find_cycle(&graph) {
+            let waits: Vec<String> = cycle
+                .iter()
+                .zip(cycle.iter().cycle().skip(1))
+                .map(|(session, blocker)| {
+                    let (target, request) = &requests[session];
+                    let mode = request.mode.as_str();
+                    format!("session {session} waits for {mode} lock on {target}, blocked by session {blocker}")
+                })
+                .collect();
+            let victim = *cycle.iter().max_by_key(|session| requests[session].1.since).unwrap();
+            graph.remove(&victim);
+            state.victims.insert(victim, waits.join("; "));
+            cycles.push(cycle);
+        }
+        if !cycles.is_empty() {
+            self.0.changed.notify_all();
+        }
+        cycles
+    }
+
+    /// Rows of the system table, in the order of [`LOCKS_COLUMNS`], held locks before awaited ones.
+    pub fn rows(&self) -> Vec<Vec<Value>> {
+        let state = self.state();
+        let mut locks: Vec<(SessionId, bool, &LockTarget, LockMode)> = Vec::new();
+        for (target, lock) in &state.locks {
+            locks.extend(lock.granted.iter().map(|(session, mode)| (*session, true, target, *mode)));
+            locks.extend(
+                lock.waiting
+                    .iter()
+                    .map(|request| (request.session, false, target, request.mode)),
+            );
+        }
+        locks.sort_by(|a, b| (a.0, !a.1, a.2).cmp(&(b.0, !b.1, b.2)));
+        locks
+            .into_iter()
+            .map(|(session, granted, target, mode)| {
+                let (relation, row) = match target {
+                    LockTarget::Database => (Value::Null, Value::Null),
+                    LockTarget::Table(table) => (Value::Text(table.clone()), Value::Null),
+                    LockTarget::Row(table, id) => (Value::Text(table.clone()), Value::Text(id.to_string())),
+                };
+                vec![
+                    Value::Int(session as i64),
+                    Value::Text(target.kind().to_string()),
+                    relation,
+                    row,
+                    Value::Text(mode.as_str().to_string()),
+                    Value::Bool(granted),
+                ]
+            })
+            .collect()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.0.state.lock().unwrap()
+    }
+}
+
+fn detect_periodically(shared: Weak<Shared>, interval: Duration) {
+    loop {
+        std::thread::sleep(interval);
+        match shared.upgrade() {
+            Some(shared) => LockManager(shared).detect_deadlocks(),
+            None => return,
+        };
+    }
+}
+
+/// A cycle of sessions each waiting for the next one.
+fn find_cycle(graph: &HashMap<SessionId, Vec<SessionId>>) -> Option<Vec<SessionId>> {
+    fn visit(
+        session: SessionId,
+        graph: &HashMap<SessionId, Vec<SessionId>>,
+        path: &mut Vec<SessionId>,
+        done: &mut HashSet<SessionId>,
+    ) -> Option<Vec<SessionId>> {
+        if let Some(start) = path.iter().position(|other| *other == session) {
+            return Some(path[start..].to_vec());
+        }
+        if done.contains(&session) {
+            return None;
+        }
+        path.push(session);
+        for blocker in graph.get(&session).into_iter().flatten() {
+            if let Some(cycle) = visit(*blocker, graph, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        done.insert(session);
+        None
+    }
+
+    let mut sessions: Vec<SessionId> = graph.keys().copied().collect();
+    sessions.sort_unstable();
+    let mut done = HashSet::new();
+    sessions
+        .into_iter()
+        .find_map(|session| visit(session, graph, &mut Vec::new(), &mut done))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::sync::mpsc;
+
+    fn table(name: &str) -> LockTarget {
+        LockTarget::Table(name.to_string())
+    }
+
+    /// Detection only when the tests run it.
+    fn manager() -> LockManager {
+        LockManager::new(Duration::from_secs(3600))
+    }
+
+    fn lock(locks: &LockManager, session: SessionId, target: &LockTarget, mode: LockMode) -> Result<(), StorageError> {
+        locks.lock(session, target, mode, None, &|| false)
+    }
+
+    #[test]
+    fn test_modes() {
+        use LockMode::*;
+        let modes = [
+            IntentionShared,
+            IntentionExclusive,
+            Shared,
+            SharedIntentionExclusive,
+            Exclusive,
+        ];
+        let compatible = [
+            [true, true, true, true, false],
+            [true, true, false, false, false],
+            [true, false, true, false, false],
+            [true, false, false, false, false],
+            [false, false, false, false, false],
+        ];
+        for (i, a) in modes.iter().enumerate() {
+            for (j, b) in modes.iter().enumerate() {
+                assert_eq!(a.compatible(*b), compatible[i][j], "{a:?} {b:?}");
+            }
+        }
+        assert_eq!(Shared.join(IntentionExclusive), SharedIntentionExclusive);
+        assert_eq!(IntentionShared.join(Shared), Shared);
+        assert_eq!(SharedIntentionExclusive.join(Exclusive), Exclusive);
+    }
+
+    #[test]
+    fn test_hierarchy() {
+        let locks = manager();
+        let row = LockTarget::Row("cats".to_string(), RowId::new(0, 1));
+        lock(&locks, 1, &row, LockMode::Exclusive).unwrap();
+        assert_eq!(locks.mode(1, &table("cats")), Some(LockMode::IntentionExclusive));
+        assert_eq!(locks.mode(1, &LockTarget::Database), Some(LockMode::IntentionExclusive));
+
+        // Other rows can be locked, the table cannot.
+        let other = LockTarget::Row("cats".to_string(), RowId::new(0, 2));
+        lock(&locks, 2, &other, LockMode::Exclusive).unwrap();
+        let nowait = locks.lock(2, &table("cats"), LockMode::Shared, Some(Duration::ZERO), &|| false);
+        assert!(matches!(nowait, Err(StorageError::LockNotAvailable(target)) if target == "table \"cats\""));
+        assert_eq!(locks.mode(2, &table("cats")), Some(LockMode::IntentionExclusive));
+        assert_eq!(locks.rows().len(), 6);
+        locks.release_all(2);
+
+        // Reading the whole table while changing a row of it.
+        lock(&locks, 1, &table("cats"), LockMode::Shared).unwrap();
+        assert_eq!(locks.mode(1, &table("cats")), Some(LockMode::SharedIntentionExclusive));
+        assert_eq!(
+            locks.rows()[2],
+            vec![
+                Value::Int(1),
+                Value::Text("row".to_string()),
+                Value::Text("cats".to_string()),
+                Value::Text("(0,1)".to_string()),
+                Value::Text("X".to_string()),
+                Value::Bool(true),
+            ]
+        );
+
+        locks.release_all(1);
+        assert!(locks.rows().is_empty());
+    }
+
+    #[test]
+    fn test_waits_in_order() {
+        let locks = manager();
+        lock(&locks, 1, &table("cats"), LockMode::Exclusive).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        std::thread::scope(|scope| {
+            for session in [2, 3] {
+                let (locks, sender) = (&locks, sender.clone());
+                scope.spawn(move || {
+                    lock(locks, session, &table("cats"), LockMode::Exclusive).unwrap();
+                    sender.send(session).unwrap();
+                    std::thread::sleep(Duration::from_millis(20));
+                    locks.release_all(session);
+                });
+                // Waiting before the next one asks.
+                while locks.rows().iter().filter(|row| row[5] == Value::Bool(false)).count() < session as usize - 1 {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            }
+            let awaited = locks.rows().into_iter().filter(|row| row[5] == Value::Bool(false));
+            assert_eq!(
+                awaited.map(|row| row[0].clone()).collect::<Vec<_>>(),
+                [Value::Int(2), Value::Int(3)]
+            );
+            locks.release_all(1);
+        });
+        assert_eq!(receiver.iter().take(2).collect::<Vec<_>>(), [2, 3]);
+
+        // Waits end with the timeout or an interruption.
+        lock(&locks, 1, &table("cats"), LockMode::Shared).unwrap();
+        let start = Instant::now();
+        let timeout = Some(Duration::from_millis(30));
+        assert!(
+            locks
+                .lock(2, &table("cats"), LockMode::Exclusive, timeout, &|| false)
+                .is_err()
+        );
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert!(locks.lock(2, &table("cats"), LockMode::Exclusive, None, &|| true).is_err());
+        // Neither leaves the intention lock on the database behind.
+        assert_eq!(locks.rows().len(), 2);
+        assert_eq!(locks.mode(2, &LockTarget::Database), None);
+
+        // One held before goes back to its mode.
+        lock(&locks, 2, &table("dogs"), LockMode::Shared).unwrap();
+        assert!(
+            locks
+                .lock(2, &table("cats"), LockMode::Exclusive, Some(Duration::ZERO), &|| false)
+                .is_err()
+        );
+        assert_eq!(locks.mode(2, &LockTarget::Database), Some(LockMode::IntentionShared));
+        assert_eq!(locks.rows().len(), 4);
+    }
+
+    #[test]
+    fn test_deadlock() {
+        let locks = manager();
+        lock(&locks, 1, &table("a"), LockMode::Exclusive).unwrap();
+        lock(&locks, 2, &table("b"), LockMode::Exclusive).unwrap();
+        std::thread::scope(|scope| {
+            let first = scope.spawn(|| lock(&locks, 1, &table("b"), LockMode::Shared));
+            while locks.rows().iter().all(|row| row[5] == Value::Bool(true)) {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            let second = scope.spawn(|| {
+                let locked = lock(&locks, 2, &table("a"), LockMode::Exclusive);
+                locks.release_all(2);
+                locked
+            });
+            let mut cycles = Vec::new();
+            while cycles.is_empty() {
+                std::thread::sleep(Duration::from_millis(1));
+                cycles = locks.detect_deadlocks();
+            }
+            assert_eq!(cycles, vec![vec![1, 2]]);
+
+            // The session that asked last fails, and the other gets its lock.
+            let err = second.join().unwrap().unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "deadlock detected: session 1 waits for S lock on table \"b\", blocked by session 2; \
+                 session 2 waits for X lock on table \"a\", blocked by session 1"
+            );
+            first.join().unwrap().unwrap();
+        });
+        assert!(locks.detect_deadlocks().is_empty());
+    }
+}
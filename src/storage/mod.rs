@@ -0,0 +1,176 @@
+//! On-disk storage.
+//!
+//! Everything is stored in files of fixed-size [`page::Page`]s, which the [`file::FileManager`] reads and writes
+//! in the data directory, and are accessed through the [`buffer::BufferPool`] which caches them. Table rows live
+//! in [`heap::HeapFile`]s, slotted pages that give every row a [`RowId`] which stays the same for as long as the
+//! row exists, encoded by the [`row::Schema`] of their table. A [`table::Table`] keeps values too large for a row
+//! in an [`overflow::OverflowFile`], and finds rows by their values in [`btree::BTree`] and [`hash::HashIndex`]
+//! indexes. Changes to pages are logged in the [`wal::Wal`] first, by a [`wal::Transaction`] or by the pool, from
+//! which they are redone or undone after a crash. Tables keep versions of their rows for the
+//! [`mvcc::Transaction`]s reading them, whose commits the log keeps, sessions take [`lock::LockManager`] locks for
+//! what versions do not isolate, and [`vacuum::Vacuum`] removes the versions no transaction sees any more.
+
+pub mod btree;
+pub mod buffer;
+pub mod compress;
+pub mod eviction;
+pub mod file;
+pub mod hash;
+pub mod heap;
+pub mod key;
+pub mod lock;
+pub mod mvcc;
+pub mod overflow;
+pub mod page;
+pub mod row;
+pub mod table;
+pub mod vacuum;
+pub mod wal;
+
+use crate::storage::btree::MAX_KEY_LEN;
+use crate::storage::row::RowError;
+use crate::value::Value;
+use std::fmt::{Display, Formatter};
+
+/// Number of a file in the data directory.
+pub type FileId = u32;
+
+/// A page of a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PageId {
+    pub file: FileId,
+    pub page: u32,
+}
+
+impl PageId {
+    pub fn new(file: FileId, page: u32) -> Self {
+        PageId { file, page }
+    }
+}
+
+impl Display for PageId {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{0}:{1}", self.file, self.page)
+    }
+}
+
+/// Where a row is stored in its heap file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RowId {
+    pub page: u32,
+    pub slot: u16,
+}
+
+impl RowId {
+    pub fn new(page: u32, slot: u16) -> Self {
+        RowId { page, slot }
+    }
+}
+
+impl Display for RowId {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "({0},{1})", self.page, self.slot)
+    }
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(std::io::Error),
+    /// A page that fails its checksum or whose contents do not add up.
+    Corrupt(PageId, String),
+    UnknownFile(FileId),
+    /// A page past the end of its file.
+    UnknownPage(PageId),
+    /// A row id that points at no row.
+    UnknownRow(RowId),
+    /// A row that does not fit in a page, with its length.
+    RowTooLarge(usize),
+    /// Every frame of the buffer pool is pinned.
+    NoFreeFrames,
+    /// A row that does not match the schema of its table.
+    Row(RowError),
+    /// A key a unique index already has.
+    DuplicateKey(Vec<Value>),
+    /// An index key that does not fit in a node, with its length.
+    KeyTooLarge(usize),
+    /// A log record that fails its checksum or does not add up.
+    CorruptLog(wal::Lsn, String),
+    /// A transaction that has to fail to stay isolated from concurrent ones, with what it conflicted with.
+    SerializationFailure(String),
+    /// A lock request that failed to break a cycle of sessions waiting for each other, with what they wait for.
+    Deadlock(String),
+    /// A lock that was not granted in time, with what it was on.
+    LockNotAvailable(String),
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            StorageError::Io(err) => write!(f, "{err}"),
+            StorageError::Corrupt(page, message) => write!(f, "page {page} is corrupt: {message}"),
+            StorageError::UnknownFile(file) => write!(f, "file {file} does not exist"),
+            StorageError::UnknownPage(page) => write!(f, "page {page} does not exist"),
+            StorageError::UnknownRow(row) => write!(f, "row {row} does not exist"),
+            StorageError::RowTooLarge(len) => write!(f, "row of {len} bytes does not fit in a page"),
+            StorageError::NoFreeFrames => write!(f, "no unpinned buffers available"),
+            StorageError::Row(err) => write!(f, "{err}"),
+            StorageError::DuplicateKey(key) => {
+                let key: Vec<String> = key.iter().map(Value::to_string).collect();
+                write!(f, "duplicate key ({0})", key.join(", "))
+            }
+            StorageError::KeyTooLarge(len) => {
+                write!(f, "index key of {len} bytes exceeds the maximum of {MAX_KEY_LEN}")
+            }
+            StorageError::CorruptLog(lsn, message) => write!(f, "log record at {lsn} is corrupt: {message}"),
+            StorageError::SerializationFailure(reason) => write!(f, "could not serialize access due to {reason}"),
+            StorageError::Deadlock(waits) => write!(f, "deadlock detected: {waits}"),
+            StorageError::LockNotAvailable(target) => write!(f, "could not obtain lock on {target}"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<std::io::Error> for StorageError {
+    fn from(err: std::io::Error) -> Self {
+        StorageError::Io(err)
+    }
+}
+
+impl From<RowError> for StorageError {
+    fn from(err: RowError) -> Self {
+        StorageError::Row(err)
+    }
+}
+
+/// Temporary data directories for tests.
+#[cfg(test)]
+pub(crate) mod testing {
+
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A new empty directory, removed with everything in it on drop.
+    pub struct TempDir(PathBuf);
+
+    impl TempDir {
+        pub fn new() -> Self {
+            static DIRS: AtomicUsize = AtomicUsize::new(0);
+            let name = format!("rdb-data-{0}-{1}", std::process::id(), DIRS.fetch_add(1, Ordering::Relaxed));
+            let path = std::env::temp_dir().join(name);
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+}
@@ -0,0 +1,943 @@
+//! Multi-version concurrency control, so that transactions read a consistent state of the rows while others change
+//! them.
+//!
+//! A [`Table`](crate::storage::table::Table) changed in a [`Transaction`] keeps the versions of its rows side by
+//! side: a new version is written for every change, and the one it replaces is only marked. Every row starts with
+//! a [`Version`] header saying which transaction created it, which one deleted or replaced it, and the version
+//! that replaced it. A transaction sees the versions created by the transactions its [`Snapshot`] has committed,
+//! and not deleted by one of them. Under `READ COMMITTED` every statement takes a new snapshot, at the other levels
+//! the first statement takes the one of the whole transaction.
+//!
+//! A version is only ever replaced by one transaction, the first to commit wins: a transaction changing a version
+//! that another one changed since its snapshot, or is changing, fails. Serializable transactions also remember the
+//! tables and rows they read, and note whenever one of them read what a concurrent one wrote. A transaction that
+//! both read what a concurrent one wrote and wrote what one read fails, since every anomaly snapshots allow has
+//! one like that.
+//!
+//! A version no transaction can see any more is dead, vacuum removes it: once its creator rolled back, or once the
+//! transaction that deleted it committed before the [horizon](TransactionManager::horizon). Versions of
+//! transactions whose status is not known are invisible, but vacuum leaves them alone. Vacuum also
+//! [freezes](TransactionManager::freeze) the versions it keeps, so that once every table was vacuumed the status
+//! of the transactions before the horizon can be [forgotten](TransactionManager::forget).
+//!
+//! Transactions only get an id, and a status, when they first write. Those that only read are told apart by
+//! another id while in progress, which no version ever holds.
+//!
+//! With a [`Wal`] set, transactions log that they are about to write, then their commit or rollback, and a commit
+//! waits for its record to be on disk. The log keeps how they ended across restarts, recovery rolls back the ones
+//! that did not, and new transactions get ids past every one it has seen, so past every version on disk. Without
+//! one the status of transactions is only kept in memory. Versions written without a transaction are frozen,
+//! every transaction sees them.
+
+use crate::parser::ast::IsolationLevel;
+use crate::storage::wal::{LogRecord, Lsn, Wal};
+use crate::storage::{FileId, RowId, StorageError};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+/// Transaction id, in the order transactions began.
+pub type Xid = u64;
+
+/// Creator of the versions written without a transaction.
+pub const FROZEN_XID: Xid = 0;
+
+/// Length of the header of a version.
+pub const VERSION_LEN: usize = 22;
+
+const NO_ROW: u32 = u32::MAX;
+
+/// Header of a version of a row.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Version {
+    /// Transaction that created the version.
+    pub xmin: Xid,
+    /// Transaction that deleted or replaced it, zero for none.
+    pub xmax: Xid,
+    /// The version that replaced it.
+    pub next: Option<RowId>,
+}
+
+impl Version {
+    pub fn new(xmin: Xid) -> Self {
+        Version {
+            xmin,
+            xmax: 0,
+            next: None,
+        }
+    }
+
+    /// The header at the start of `row`, `None` when it is too short to have one.
+    pub fn decode(row: &[u8]) -> Option<Self> {
+        let header = row.get(..VERSION_LEN)?;
+        let next = RowId::new(
+            u32::from_le_bytes(header[16..20].try_into().unwrap()),
+            u16::from_le_bytes(header[20..22].try_into().unwrap()),
+        );
+        Some(Version {
+            xmin: u64::from_le_bytes(header[..8].try_into().unwrap()),
+            xmax: u64::from_le_bytes(header[8..16].try_into().unwrap()),
+            next: (next.page != NO_ROW).then_some(next),
+        })
+    }
+
+    /// Writes the header over the start of `row`.
+    pub fn encode(&self, row: &mut [u8]) {
+        let next = self.next.unwrap_or(RowId::new(NO_ROW, 0));
+        row[..8].copy_from_slice(&self.xmin.to_le_bytes());
+        row[8..16].copy_from_slice(&self.xmax.to_le_bytes());
+        row[16..20].copy_from_slice(&next.page.to_le_bytes());
+        row[20..22].copy_from_slice(&next.slot.to_le_bytes());
+    }
+
+    /// The header followed by `row`.
+    pub fn with_row(&self, row: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0; VERSION_LEN];
+        self.encode(&mut bytes);
+        bytes.extend_from_slice(row);
+        bytes
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TxnStatus {
+    Active,
+    Committed,
+    Aborted,
+}
+
+/// The transactions whose changes a transaction sees, those that committed before it was taken.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    /// Transaction it was taken for, which sees its own changes, [`FROZEN_XID`] until it writes.
+    pub xid: Xid,
+    /// First transaction that had not begun.
+    pub xmax: Xid,
+    /// Transactions in progress, sorted.
+    pub active: Vec<Xid>,
+    /// Number of commits before it was taken.
+    commits: u64,
+}
+
+impl Snapshot {
+    /// Whether the changes of `xid` are in the snapshot, provided that it committed.
+    pub fn includes(&self, xid: Xid) -> bool {
+        xid == FROZEN_XID || xid == self.xid || (xid < self.xmax && self.active.binary_search(&xid).is_err())
+    }
+}
+
+/// Id of a transaction among those in progress, which it has from the start, unlike its [`Xid`].
+type Vxid = u64;
+
+/// What serializable transactions read and wrote of each other.
+struct Conflicts {
+    /// Number of commits before its snapshot.
+    start: u64,
+    /// Number of commits up to its own.
+    commit: Option<u64>,
+    /// Tables read, and rows read of them.
+    reads: HashSet<(FileId, Option<RowId>)>,
+    /// Transactions that read what this one wrote.
+    readers: HashSet<Vxid>,
+    /// Transactions that wrote what this one read.
+    writers: HashSet<Vxid>,
+    /// Fails at its next step.
+    doomed: bool,
+}
+
+struct State {
+    next_xid: Xid,
+    next_vxid: Vxid,
+    commits: u64,
+    status: HashMap<Xid, TxnStatus>,
+    /// Transactions in progress, with the first transaction id that had not ended when each began.
+    running: HashMap<Vxid, Xid>,
+    /// Transactions in progress that wrote.
+    active: BTreeSet<Xid>,
+    /// Serializable transactions in progress, and those that committed while one overlapping them still is.
+    serializable: HashMap<Vxid, Conflicts>,
+    /// Those of them that wrote, by transaction id.
+    writers: HashMap<Xid, Vxid>,
+    wal: Option<Arc<Wal>>,
+}
+
+impl State {
+    /// `None` for a transaction not known to have begun, or whose status was lost along with the log or forgotten.
+    fn known_status(&self, xid: Xid) -> Option<TxnStatus> {
+        match xid {
+            FROZEN_XID => Some(TxnStatus::Committed),
+            xid => self.status.get(&xid).copied(),
+        }
+    }
+
+    fn status(&self, xid: Xid) -> TxnStatus {
+        // Unknown ones did not commit as far as anyone can tell.
+        self.known_status(xid).unwrap_or(TxnStatus::Aborted)
+    }
+
+    /// Whether the snapshot sees the changes of `xid`.
+    fn sees(&self, snapshot: &Snapshot, xid: Xid) -> bool {
+        snapshot.includes(xid) && (xid == snapshot.xid || self.status(xid) == TxnStatus::Committed)
+    }
+
+    fn concurrent(&self, a: Vxid, b: Vxid) -> bool {
+        match (self.serializable.get(&a), self.serializable.get(&b)) {
+            (Some(a), Some(b)) => {
+                a.commit.is_none_or(|commit| commit > b.start) && b.commit.is_none_or(|commit| commit > a.start)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the transaction read what a concurrent one wrote and wrote what one read, counting only those
+    /// that may still commit.
+    fn is_pivot(&self, vxid: Vxid) -> bool {
+        let live = |vxid: &Vxid| self.serializable.get(vxid).is_some_and(|txn| !txn.doomed);
+        let txn = &self.serializable[&vxid];
+        txn.readers.iter().any(live) && txn.writers.iter().any(live)
+    }
+
+    /// Notes that `reader` read what `writer` wrote, and fails `caller`, one of them, if that makes it a pivot or
+    /// one that already committed. The other one is doomed when it becomes a pivot.
+    fn conflict(&mut self, reader: Vxid, writer: Vxid, caller: Vxid) -> Result<(), StorageError> {
+        if reader == writer || !self.concurrent(reader, writer) {
+            return Ok(());
+        }
+        self.serializable.get_mut(&reader).unwrap().writers.insert(writer);
+        self.serializable.get_mut(&writer).unwrap().readers.insert(reader);
+
+        let other = if caller == reader { writer } else { reader };
+        if self.is_pivot(caller) {
+            return self.doom(caller);
+        }
+        if self.is_pivot(other) {
+            match self.serializable[&other].commit {
+                // Too late to fail it.
+                Some(_) => return self.doom(caller),
+                None => self.serializable.get_mut(&other).unwrap().doomed = true,
+            }
+        }
+        Ok(())
+    }
+
+    fn doom(&mut self, vxid: Vxid) -> Result<(), StorageError> {
+        self.serializable.get_mut(&vxid).unwrap().doomed = true;
+        Err(read_write_conflict())
+    }
+
+    /// Forgets the serializable transactions that no transaction in progress overlaps.
+    fn forget_finished(&mut self) {
+        let oldest = self
+            .serializable
+            .values()
+            .filter(|txn| txn.commit.is_none())
+            .map(|txn| txn.start)
+            .min();
+        self.serializable
+            .retain(|_, txn| txn.commit.is_none() || oldest.is_some_and(|oldest| txn.commit.unwrap() > oldest));
+        self.writers.retain(|_, vxid| self.serializable.contains_key(vxid));
+    }
+}
+
+/// Hands out transactions and keeps track of them. Clones share it.
+#[derive(Clone)]
+pub struct TransactionManager(Arc<Mutex<State>>);
+
+impl Default for TransactionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TransactionManager {
+    pub fn new() -> Self {
+        TransactionManager(Arc::new(Mutex::new(State {
+            next_xid: FROZEN_XID + 1,
+            next_vxid: 0,
+            commits: 0,
+            status: HashMap::new(),
+            running: HashMap::new(),
+            active: BTreeSet::new(),
+            serializable: HashMap::new(),
+            writers: HashMap::new(),
+            wal: None,
+        })))
+    }
+
+    /// Logs the transactions that write in `wal`, which was recovered, and takes how those before ended from it.
+    /// Only the first log set is kept.
+    pub fn set_wal(&self, wal: Arc<Wal>) {
+        let mut state = self.state();
+        if state.wal.is_some() {
+            return;
+        }
+        for (xid, committed) in wal.ended() {
+            let status = if committed { TxnStatus::Committed } else { TxnStatus::Aborted };
+            state.status.entry(xid).or_insert(status);
+        }
+        state.next_xid = state.next_xid.max(wal.next_txn());
+        state.wal = Some(wal);
+    }
+
+    pub fn begin(&self, isolation: IsolationLevel) -> Transaction {
+        let mut state = self.state();
+        let vxid = state.next_vxid;
+        state.next_vxid += 1;
+        let oldest = state.active.first().copied().unwrap_or(state.next_xid);
+        state.running.insert(vxid, oldest);
+        Transaction {
+            manager: self.clone(),
+            vxid,
+            xid: OnceLock::new(),
+            isolation,
+            snapshot: Mutex::new(None),
+            finished: AtomicBool::new(false),
+            logged: AtomicU64::new(0),
+        }
+    }
+
+    pub fn status(&self, xid: Xid) -> TxnStatus {
+        self.state().status(xid)
+    }
+
+    /// The status of the transaction, `None` if it is not known.
+    pub fn known_status(&self, xid: Xid) -> Option<TxnStatus> {
+        self.state().known_status(xid)
+    }
+
+    /// Transactions in progress that wrote.
+    pub fn active(&self) -> Vec<Xid> {
+        self.state().active.iter().copied().collect()
+    }
+
+    /// Number of transactions in progress, whether they wrote or not.
+    pub fn running(&self) -> usize {
+        self.state().running.len()
+    }
+
+    /// Transactions before this one that committed did so before every transaction in progress began, so every
+    /// snapshot sees their changes.
+    pub fn horizon(&self) -> Xid {
+        let state = self.state();
+        state.running.values().min().copied().unwrap_or(state.next_xid)
+    }
+
+    /// Whether no transaction sees the version any more, nor ever will: its creator is known to have rolled back,
+    /// or it was deleted by a transaction known to have committed before `horizon`.
+    pub fn is_dead(&self, version: &Version, horizon: Xid) -> bool {
+        let state = self.state();
+        state.known_status(version.xmin) == Some(TxnStatus::Aborted)
+            || (version.xmax != 0
+                && version.xmax < horizon
+                && state.known_status(version.xmax) == Some(TxnStatus::Committed))
+    }
+
+    /// Makes the version need the status of no transaction before `horizon`: a creator known to have committed
+    /// before it becomes [`FROZEN_XID`], and a deleter known to have rolled back is cleared along with the version
+    /// it wrote in place of this one. Returns whether the version changed.
+    pub fn freeze(&self, version: &mut Version, horizon: Xid) -> bool {
+        let state = self.state();
+        let before = *version;
+        if version.xmin < horizon && state.known_status(version.xmin) == Some(TxnStatus::Committed) {
+            version.xmin = FROZEN_XID;
+        }
+        if version.xmax != 0 && state.known_status(version.xmax) == Some(TxnStatus::Aborted) {
+            version.xmax = 0;
+            version.next = None;
+        }
+        *version != before
+    }
+
+    /// Forgets how the transactions before `xid` ended, here and in the log, once no version needs to know.
+    /// Those still in progress are kept.
+    pub fn forget(&self, xid: Xid) {
+        let mut state = self.state();
+        let xid = state.active.first().map_or(xid, |oldest| xid.min(*oldest));
+        state.status.retain(|ended, _| *ended >= xid);
+        if let Some(wal) = &state.wal {
+            wal.forget(xid);
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.0.lock().unwrap()
+    }
+}
+
+/// A transaction, which is rolled back when dropped unless it finished.
+pub struct Transaction {
+    manager: TransactionManager,
+    vxid: Vxid,
+    /// Given when it first writes.
+    xid: OnceLock<Xid>,
+    isolation: IsolationLevel,
+    /// Snapshot of the running statement, taken when first needed.
+    snapshot: Mutex<Option<Arc<Snapshot>>>,
+    finished: AtomicBool,
+    /// Last record of the transaction in the log, none until it writes.
+    logged: AtomicU64,
+}
+
+impl Debug for Transaction {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self.xid() {
+            Some(xid) => write!(f, "Transaction({xid}, {0})", self.isolation.as_str()),
+            None => write!(f, "Transaction(virtual {0}, {1})", self.vxid, self.isolation.as_str()),
+        }
+    }
+}
+
+impl Transaction {
+    /// Id of the transaction, which only those that write get.
+    pub fn xid(&self) -> Option<Xid> {
+        self.xid.get().copied()
+    }
+
+    pub fn isolation(&self) -> IsolationLevel {
+        self.isolation
+    }
+
+    pub fn status(&self, xid: Xid) -> TxnStatus {
+        self.manager.status(xid)
+    }
+
+    /// The snapshot the running statement reads.
+    pub fn snapshot(&self) -> Arc<Snapshot> {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        if let Some(snapshot) = &*snapshot {
+            return snapshot.clone();
+        }
+        self.take_snapshot(&mut snapshot, &mut self.manager.state())
+    }
+
+    fn take_snapshot(&self, snapshot: &mut Option<Arc<Snapshot>>, state: &mut State) -> Arc<Snapshot> {
+        let xid = self.xid().unwrap_or(FROZEN_XID);
+        let taken = Arc::new(Snapshot {
+            xid,
+            xmax: state.next_xid,
+            active: state.active.iter().copied().filter(|active| *active != xid).collect(),
+            commits: state.commits,
+        });
+        if self.isolation == IsolationLevel::Serializable {
+            state.serializable.entry(self.vxid).or_insert_with(|| Conflicts {
+                start: taken.commits,
+                commit: None,
+                reads: HashSet::new(),
+                readers: HashSet::new(),
+                writers: HashSet::new(),
+                doomed: false,
+            });
+        }
+        *snapshot = Some(taken.clone());
+        taken
+    }
+
+    /// Starts the next statement, which takes a snapshot of its own under `READ COMMITTED`.
+    pub fn next_statement(&self) {
+        if self.isolation == IsolationLevel::ReadCommitted {
+            *self.snapshot.lock().unwrap() = None;
+        }
+    }
+
+    /// Whether the transaction sees the version. A serializable one notes the concurrent transactions that
+    /// created or deleted it out of its sight, and fails when that completes a conflict.
+    pub fn sees(&self, version: &Version) -> Result<bool, StorageError> {
+        let snapshot = self.snapshot();
+        let mut state = self.manager.state();
+        let created = state.sees(&snapshot, version.xmin);
+        let deleted = version.xmax != 0 && state.sees(&snapshot, version.xmax);
+        if self.isolation == IsolationLevel::Serializable {
+            self.check(&state)?;
+            if !created
+                && state.status(version.xmin) != TxnStatus::Aborted
+                && let Some(writer) = state.writers.get(&version.xmin).copied()
+            {
+                state.conflict(self.vxid, writer, self.vxid)?;
+            }
+            if created
+                && !deleted
+                && version.xmax != 0
+                && state.status(version.xmax) != TxnStatus::Aborted
+                && let Some(writer) = state.writers.get(&version.xmax).copied()
+            {
+                state.conflict(self.vxid, writer, self.vxid)?;
+            }
+        }
+        Ok(created && !deleted)
+    }
+
+    /// Notes that a serializable transaction read the table in `file`, or a row of it.
+    pub fn read(&self, file: FileId, row: Option<RowId>) {
+        if self.isolation != IsolationLevel::Serializable {
+            return;
+        }
+        self.snapshot();
+        if let Some(txn) = self.manager.state().serializable.get_mut(&self.vxid) {
+            txn.reads.insert((file, row));
+        }
+    }
+
+    /// About to write to the table in `file`, a new row or the version at `row`. Fails when a serializable
+    /// transaction that read it makes that a conflict. Returns the id of the transaction, which it gets at its
+    /// first write.
+    pub fn write(&self, file: FileId, row: Option<RowId>) -> Result<Xid, StorageError> {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        let mut state = self.manager.state();
+        if snapshot.is_none() {
+            self.take_snapshot(&mut snapshot, &mut state);
+        }
+        self.check(&state)?;
+        let xid = match self.xid() {
+            Some(xid) => xid,
+            None => {
+                let xid = state.next_xid;
+                state.next_xid += 1;
+                state.status.insert(xid, TxnStatus::Active);
+                state.active.insert(xid);
+                if self.isolation == IsolationLevel::Serializable {
+                    state.writers.insert(xid, self.vxid);
+                }
+                let _ = self.xid.set(xid);
+                // It sees its own changes from now on.
+                if let Some(taken) = snapshot.as_mut() {
+                    *taken = Arc::new(Snapshot {
+                        xid,
+                        ..(**taken).clone()
+                    });
+                }
+                xid
+            }
+        };
+        drop(snapshot);
+
+        // Before any version with its id can reach the disk.
+        if let Some(wal) = &state.wal
+            && self.logged.load(Ordering::Acquire) == 0
+        {
+            let lsn = wal.append(&LogRecord::Begin { txn: xid });
+            self.logged.store(lsn, Ordering::Release);
+        }
+        let readers: Vec<Vxid> = state
+            .serializable
+            .iter()
+            .filter(|(_, txn)| {
+                txn.reads.contains(&(file, None)) || row.is_some_and(|row| txn.reads.contains(&(file, Some(row))))
+            })
+            .map(|(vxid, _)| *vxid)
+            .collect();
+        if self.isolation == IsolationLevel::Serializable {
+            for reader in readers {
+                state.conflict(reader, self.vxid, self.vxid)?;
+            }
+        }
+        Ok(xid)
+    }
+
+    /// Marks the version at `id` deleted by this transaction, which [wrote](Transaction::write) to its table.
+    /// Fails when another transaction deleted it since the snapshot or is deleting it.
+    pub fn delete(&self, id: RowId, version: &mut Version) -> Result<(), StorageError> {
+        let xid = self.xid().expect("a transaction writes to a table before deleting from it");
+        let snapshot = self.snapshot();
+        let state = self.manager.state();
+        self.check(&state)?;
+        if !state.sees(&snapshot, version.xmin) {
+            return Err(StorageError::UnknownRow(id));
+        }
+        match (version.xmax, state.status(version.xmax)) {
+            (0, _) | (_, TxnStatus::Aborted) => {}
+            (xmax, _) if xmax == xid => return Err(StorageError::UnknownRow(id)),
+            (xmax, TxnStatus::Committed) if snapshot.includes(xmax) => return Err(StorageError::UnknownRow(id)),
+            _ => return Err(StorageError::SerializationFailure("concurrent update".to_string())),
+        }
+        version.xmax = xid;
+        version.next = None;
+        Ok(())
+    }
+
+    /// Makes the changes of the transaction visible to the snapshots taken from now on, and waits for the commit
+    /// to be on disk. A serializable transaction that has to fail is rolled back instead.
+    pub fn commit(&self) -> Result<(), StorageError> {
+        let mut state = self.manager.state();
+        if self.finished.swap(true, Ordering::AcqRel) {
+            return Ok(());
+        }
+        let fails = state.serializable.get(&self.vxid).is_some_and(|txn| txn.doomed)
+            || (state.serializable.contains_key(&self.vxid) && state.is_pivot(self.vxid));
+        if fails {
+            self.finish(&mut state, TxnStatus::Aborted);
+            return Err(read_write_conflict());
+        }
+        state.commits += 1;
+        let commits = state.commits;
+        if let Some(txn) = state.serializable.get_mut(&self.vxid) {
+            txn.commit = Some(commits);
+        }
+        let logged = self.finish(&mut state, TxnStatus::Committed);
+        let wal = state.wal.clone();
+        drop(state);
+        // Others may see the changes already, but whatever they commit depending on them is logged later.
+        if let (Some(wal), Some(lsn)) = (wal, logged) {
+            wal.flush(lsn)?;
+        }
+        Ok(())
+    }
+
+    /// Abandons the changes of the transaction, the versions it wrote are seen by no one.
+    pub fn rollback(&self) {
+        let mut state = self.manager.state();
+        if !self.finished.swap(true, Ordering::AcqRel) {
+            self.finish(&mut state, TxnStatus::Aborted);
+        }
+    }
+
+    /// Records how the transaction ended if it wrote, and logs it. Returns the record.
+    fn finish(&self, state: &mut State, status: TxnStatus) -> Option<Lsn> {
+        state.running.remove(&self.vxid);
+        if status == TxnStatus::Aborted {
+            state.serializable.remove(&self.vxid);
+        }
+        state.forget_finished();
+        let txn = self.xid()?;
+        state.status.insert(txn, status);
+        state.active.remove(&txn);
+
+        let prev = self.logged.load(Ordering::Acquire);
+        let wal = state.wal.as_ref().filter(|_| prev != 0)?;
+        Some(wal.append(&match status {
+            TxnStatus::Committed => LogRecord::Commit { txn, prev },
+            // Nothing to undo, the versions it wrote are seen by no one.
+            _ => LogRecord::End { txn, prev },
+        }))
+    }
+
+    /// Fails a serializable transaction that a conflict doomed.
+    fn check(&self, state: &State) -> Result<(), StorageError> {
+        match state.serializable.get(&self.vxid) {
+            Some(txn) if txn.doomed => Err(read_write_conflict()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        self.rollback();
+    }
+}
+
+fn read_write_conflict() -> StorageError {
+    StorageError::SerializationFailure("read/write dependencies among transactions".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::{Config, WalConfig};
+    use crate::storage::buffer::{BufferPool, EvictionPolicyKind};
+    use crate::storage::file::FileManager;
+    use crate::storage::testing::TempDir;
+
+    fn version(xmin: Xid, xmax: Xid) -> Version {
+        Version { xmin, xmax, next: None }
+    }
+
+    #[test]
+    fn test_version_header() {
+        let header = Version {
+            xmin: 7,
+            xmax: 9,
+            next: Some(RowId::new(3, 4)),
+        };
+        let row = header.with_row(b"row");
+        assert_eq!(row.len(), VERSION_LEN + 3);
+        assert_eq!(Version::decode(&row), Some(header));
+        assert_eq!(Version::decode(&Version::new(1).with_row(b"")).unwrap().next, None);
+        assert_eq!(Version::decode(b"short"), None);
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let manager = TransactionManager::new();
+        let writer = manager.begin(IsolationLevel::ReadCommitted);
+        let committed = version(writer.write(1, None).unwrap(), 0);
+        let read_committed = manager.begin(IsolationLevel::ReadCommitted);
+        let repeatable = manager.begin(IsolationLevel::RepeatableRead);
+
+        // Nobody but the writer sees what is not committed, everybody sees frozen versions.
+        assert!(writer.sees(&committed).unwrap());
+        assert!(!read_committed.sees(&committed).unwrap());
+        assert!(!repeatable.sees(&committed).unwrap());
+        assert!(repeatable.sees(&version(FROZEN_XID, 0)).unwrap());
+        writer.commit().unwrap();
+
+        // Only a new statement under READ COMMITTED sees it once committed.
+        assert!(!read_committed.sees(&committed).unwrap());
+        read_committed.next_statement();
+        repeatable.next_statement();
+        assert!(read_committed.sees(&committed).unwrap());
+        assert!(!repeatable.sees(&committed).unwrap());
+        assert!(manager.begin(IsolationLevel::RepeatableRead).sees(&committed).unwrap());
+
+        // Versions deleted by an aborted transaction are still there.
+        let deleter = manager.begin(IsolationLevel::ReadCommitted);
+        let deleted = version(FROZEN_XID, deleter.write(1, None).unwrap());
+        assert!(!deleter.sees(&deleted).unwrap());
+        drop(deleter);
+        assert!(read_committed.sees(&deleted).unwrap());
+
+        // Those that only read have no id.
+        assert_eq!((read_committed.xid(), repeatable.xid()), (None, None));
+        assert!(manager.active().is_empty());
+        assert_eq!(manager.running(), 2);
+    }
+
+    #[test]
+    fn test_logged_commits() {
+        let dir = TempDir::new();
+        let wal = Arc::new(Wal::open(dir.path(), &Config::default().wal).unwrap());
+        let manager = TransactionManager::new();
+        manager.set_wal(wal.clone());
+
+        // Only transactions that wrote are logged, and commits wait for the disk.
+        let start = wal.end();
+        manager.begin(IsolationLevel::ReadCommitted).commit().unwrap();
+        assert_eq!(wal.end(), start);
+        let writer = manager.begin(IsolationLevel::ReadCommitted);
+        let committed = writer.write(1, None).unwrap();
+        assert_eq!(writer.write(1, None).unwrap(), committed);
+        writer.commit().unwrap();
+        assert_eq!(wal.syncs(), 1);
+        assert_eq!(wal.read(start).unwrap(), LogRecord::Begin { txn: committed });
+
+        let end = wal.end();
+        let rolled_back = manager.begin(IsolationLevel::ReadCommitted);
+        let xid = rolled_back.write(1, None).unwrap();
+        drop(rolled_back);
+        assert_eq!(wal.read(end).unwrap(), LogRecord::Begin { txn: xid });
+        assert_eq!(wal.ended(), [(committed, true), (xid, false)]);
+        assert_eq!(wal.syncs(), 1);
+    }
+
+    #[test]
+    fn test_status_survives_restart() {
+        let dir = TempDir::new();
+        let config = WalConfig {
+            segment_size: 256,
+            ..Config::default().wal
+        };
+        let open = || {
+            let files = Arc::new(FileManager::open(dir.path().join("base")).unwrap());
+            let pool = BufferPool::new(files, 4, EvictionPolicyKind::Clock);
+            let wal = Arc::new(Wal::open(dir.path().join("wal"), &config).unwrap());
+            pool.set_wal(wal.clone());
+            wal.recover(&pool).unwrap();
+            let manager = TransactionManager::new();
+            manager.set_wal(wal.clone());
+            (pool, wal, manager)
+        };
+        let (pool, wal, manager) = open();
+        let write = |committed| {
+            let txn = manager.begin(IsolationLevel::ReadCommitted);
+            let xid = txn.write(1, None).unwrap();
+            match committed {
+                true => txn.commit().unwrap(),
+                false => txn.rollback(),
+            }
+            xid
+        };
+        let (committed, rolled_back) = (write(true), write(false));
+        // Enough to fill segments the checkpoint removes.
+        for _ in 0..20 {
+            write(true);
+        }
+        let unfinished = manager.begin(IsolationLevel::ReadCommitted);
+        let xid = unfinished.write(1, None).unwrap();
+        wal.checkpoint(&pool).unwrap();
+        std::mem::forget(unfinished);
+        let unfinished = xid;
+        drop((pool, wal, manager));
+
+        // The records of the first ones are gone with their segment, recovery rolls back the unfinished one.
+        let (_pool, _wal, manager) = open();
+        assert!(!dir.path().join("wal").join(format!("log.{0:016x}", 0)).exists());
+        assert_eq!(manager.status(committed), TxnStatus::Committed);
+        assert_eq!(manager.status(rolled_back), TxnStatus::Aborted);
+        assert_eq!(manager.status(unfinished), TxnStatus::Aborted);
+        assert!(manager.begin(IsolationLevel::ReadCommitted).write(1, None).unwrap() > unfinished);
+    }
+
+    #[test]
+    fn test_horizon() {
+        let manager = TransactionManager::new();
+        let old = manager.begin(IsolationLevel::ReadCommitted);
+        let old_xid = old.write(1, None).unwrap();
+        let deleter = manager.begin(IsolationLevel::ReadCommitted);
+        let deleted = version(FROZEN_XID, deleter.write(1, None).unwrap());
+        deleter.commit().unwrap();
+        let young = manager.begin(IsolationLevel::ReadCommitted);
+
+        // The oldest transaction may have a snapshot from before the delete committed.
+        assert_eq!(manager.horizon(), old_xid);
+        assert!(!manager.is_dead(&deleted, manager.horizon()));
+        let rolled_back = manager.begin(IsolationLevel::ReadCommitted);
+        let xid = rolled_back.write(1, None).unwrap();
+        rolled_back.rollback();
+        assert!(manager.is_dead(&version(xid, 0), manager.horizon()));
+
+        // So may the young one, which began while the old one was in progress.
+        old.commit().unwrap();
+        assert_eq!(manager.horizon(), old_xid);
+        drop(young);
+        let next = manager.begin(IsolationLevel::ReadCommitted);
+        assert_eq!(manager.horizon(), next.write(1, None).unwrap());
+        assert!(manager.is_dead(&deleted, manager.horizon()));
+        assert!(!manager.is_dead(&version(FROZEN_XID, 0), manager.horizon()));
+
+        // Nor are the versions of transactions whose status is not known, whether they created or deleted them.
+        let unknown = manager.horizon() + 10;
+        assert_eq!(manager.known_status(unknown), None);
+        assert!(!manager.is_dead(&version(unknown, 0), unknown + 1));
+        assert!(!manager.is_dead(&version(FROZEN_XID, unknown), unknown + 1));
+    }
+
+    #[test]
+    fn test_freeze_and_forget() {
+        let dir = TempDir::new();
+        let files = Arc::new(FileManager::open(dir.path().join("base")).unwrap());
+        let pool = BufferPool::new(files, 4, EvictionPolicyKind::Clock);
+        let open = || Arc::new(Wal::open(dir.path().join("wal"), &Config::default().wal).unwrap());
+        let wal = open();
+        let manager = TransactionManager::new();
+        manager.set_wal(wal.clone());
+        let write = |committed| {
+            let txn = manager.begin(IsolationLevel::ReadCommitted);
+            let xid = txn.write(1, None).unwrap();
+            match committed {
+                true => txn.commit().unwrap(),
+                false => txn.rollback(),
+            }
+            xid
+        };
+        let (committed, rolled_back) = (write(true), write(false));
+        let reader = manager.begin(IsolationLevel::ReadCommitted);
+        let later = write(true);
+
+        // Creators that committed before the horizon and deleters that rolled back need no status any more.
+        let horizon = manager.horizon();
+        let mut stored = Version {
+            xmin: committed,
+            xmax: rolled_back,
+            next: Some(RowId::new(0, 1)),
+        };
+        assert!(manager.freeze(&mut stored, horizon));
+        assert_eq!(stored, Version::new(FROZEN_XID));
+        assert!(!manager.freeze(&mut stored, horizon));
+        let mut young = version(later, 0);
+        assert!(!manager.freeze(&mut young, horizon));
+
+        // Nor does a transaction that only reads get one.
+        reader.snapshot();
+        reader.commit().unwrap();
+        assert_eq!(manager.state().status.len(), 3);
+
+        // Those in progress are kept when forgetting.
+        let running = manager.begin(IsolationLevel::ReadCommitted);
+        let xid = running.write(1, None).unwrap();
+        manager.forget(xid + 1);
+        assert_eq!(manager.known_status(committed), None);
+        assert_eq!(manager.known_status(later), None);
+        assert_eq!(manager.known_status(xid), Some(TxnStatus::Active));
+        running.commit().unwrap();
+
+        // So does the status file, once checkpointed.
+        wal.checkpoint(&pool).unwrap();
+        drop(wal);
+        assert_eq!(open().ended(), [(xid, true)]);
+    }
+
+    #[test]
+    fn test_first_committer_wins() {
+        let manager = TransactionManager::new();
+        let row = RowId::new(0, 1);
+        let first = manager.begin(IsolationLevel::RepeatableRead);
+        let second = manager.begin(IsolationLevel::RepeatableRead);
+        second.write(1, Some(row)).unwrap();
+
+        let mut stored = Version::new(FROZEN_XID);
+        let xid = first.write(1, Some(row)).unwrap();
+        first.delete(row, &mut stored).unwrap();
+        assert_eq!(stored.xmax, xid);
+        assert!(matches!(
+            second.delete(row, &mut stored.clone()),
+            Err(StorageError::SerializationFailure(_))
+        ));
+        assert!(matches!(
+            first.delete(row, &mut stored.clone()),
+            Err(StorageError::UnknownRow(_))
+        ));
+
+        // Still fails once the first committed, since it did after the snapshot.
+        first.commit().unwrap();
+        assert!(matches!(
+            second.delete(row, &mut stored.clone()),
+            Err(StorageError::SerializationFailure(_))
+        ));
+        let later = manager.begin(IsolationLevel::RepeatableRead);
+        let xid = later.write(1, Some(row)).unwrap();
+        assert!(matches!(
+            later.delete(row, &mut stored.clone()),
+            Err(StorageError::UnknownRow(_))
+        ));
+
+        // A rolled back delete leaves the version to others.
+        let mut stored = Version::new(FROZEN_XID);
+        let aborted = manager.begin(IsolationLevel::ReadCommitted);
+        aborted.write(1, Some(row)).unwrap();
+        aborted.delete(row, &mut stored).unwrap();
+        aborted.rollback();
+        later.delete(row, &mut stored).unwrap();
+        assert_eq!(stored.xmax, xid);
+    }
+
+    #[test]
+    fn test_serializable_write_skew() {
+        let manager = TransactionManager::new();
+        let (a, b) = (RowId::new(0, 0), RowId::new(0, 1));
+        let first = manager.begin(IsolationLevel::Serializable);
+        let second = manager.begin(IsolationLevel::Serializable);
+
+        // Both read both rows, then each writes the one the other relies on.
+        for txn in [&first, &second] {
+            txn.read(1, Some(a));
+            txn.read(1, Some(b));
+        }
+        first.write(1, Some(a)).unwrap();
+        assert!(matches!(second.write(1, Some(b)), Err(StorageError::SerializationFailure(_))));
+        second.rollback();
+        first.commit().unwrap();
+
+        // Reading a version created out of sight counts too. The writer becomes a pivot and is doomed.
+        let reader = manager.begin(IsolationLevel::Serializable);
+        let writer = manager.begin(IsolationLevel::Serializable);
+        reader.snapshot();
+        writer.read(1, None);
+        let written = version(writer.write(1, None).unwrap(), 0);
+        assert!(!reader.sees(&written).unwrap());
+        let third = manager.begin(IsolationLevel::Serializable);
+        third.write(1, Some(a)).unwrap();
+        third.commit().unwrap();
+        assert!(matches!(writer.commit(), Err(StorageError::SerializationFailure(_))));
+        reader.commit().unwrap();
+
+        // Transactions that do not overlap have no conflicts.
+        let before = manager.begin(IsolationLevel::Serializable);
+        before.read(1, None);
+        before.commit().unwrap();
+        let after = manager.begin(IsolationLevel::Serializable);
+        after.write(1, None).unwrap();
+        after.commit().unwrap();
+        assert!(manager.state().serializable.is_empty());
+    }
+}
@@ -0,0 +1,477 @@
+//! Values too large for a row, stored out of line in chains of pages.
+//!
+//! Every table has an overflow file next to its heap, holding its large values. A value is a chain of pages, each
+//! one holding after the common page header the next page of the chain and how many bytes of the value it holds:
+//!
+//! | bytes    | field                                    |
+//! |----------|------------------------------------------|
+//! | 16..20   | next page, `u32::MAX` at the end         |
+//! | 20..22   | number of bytes used                     |
+//! | 24..     | the bytes                                |
+//!
+//! The bytes are split in chunks of up to 64KiB of the value, each one preceded by whether it is compressed, its
+//! length and the length stored, so that values are written and read a chunk at a time rather than all at once.
+//! The pages of deleted values are marked free and reused.
+
+use crate::storage::buffer::{BufferPool, PageWriteGuard};
+use crate::storage::compress::{compress, decompress};
+use crate::storage::page::{PAGE_HEADER_LEN, PAGE_SIZE, PageKind};
+use crate::storage::{FileId, PageId, StorageError};
+use std::io::{Read, Write};
+use std::sync::Mutex;
+
+const NEXT: usize = PAGE_HEADER_LEN;
+const USED: usize = PAGE_HEADER_LEN + 4;
+const DATA: usize = PAGE_HEADER_LEN + 8;
+const CAPACITY: usize = PAGE_SIZE - DATA;
+const NO_PAGE: u32 = u32::MAX;
+
+const CHUNK_LEN: usize = 64 * 1024;
+const CHUNK_HEADER_LEN: usize = 9;
+const RAW: u8 = 0;
+const COMPRESSED: u8 = 1;
+
+/// Where a value is stored, kept in its row in place of the value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Overflow {
+    /// First page of the chain.
+    pub page: u32,
+    /// Length of the value, before compression.
+    pub len: u64,
+}
+
+/// The large values of a table.
+pub struct OverflowFile {
+    pool: BufferPool,
+    file: FileId,
+    /// Pages of deleted values.
+    free: Mutex<Vec<u32>>,
+}
+
+impl OverflowFile {
+    pub fn create(pool: BufferPool) -> Result<Self, StorageError> {
+        let file = pool.files().create_file()?;
+        Ok(OverflowFile {
+            pool,
+            file,
+            free: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn open(pool: BufferPool, file: FileId) -> Result<Self, StorageError> {
+        let mut free = Vec::new();
+        for page in 0..pool.files().page_count(file)? {
+            let id = PageId::new(file, page);
+            let guard = pool.fetch_read(id)?;
+            match guard.kind() {
+                Some(PageKind::Overflow) => {}
+                _ if guard.is_new() => free.push(page),
+                Some(PageKind::Free) => free.push(page),
+                _ => return Err(StorageError::Corrupt(id, "not an overflow page".to_string())),
+            }
+        }
+
+        Ok(OverflowFile {
+            pool,
+            file,
+            free: Mutex::new(free),
+        })
+    }
+
+    pub fn file(&self) -> FileId {
+        self.file
+    }
+
+    /// Stores a value written in pieces, optionally compressed.
+    pub fn writer(&self, compress: bool) -> OverflowWriter<'_> {
+        OverflowWriter {
+            file: self,
+            compress,
+            chunk: Vec::new(),
+            first: None,
+            page: None,
+            len: 0,
+        }
+    }
+
+    /// Reads a value in pieces.
+    pub fn reader(&self, value: Overflow) -> OverflowReader<'_> {
+        OverflowReader {
+            file: self,
+            next_page: value.page,
+            page: Vec::new(),
+            page_pos: 0,
+            chunk: Vec::new(),
+            chunk_pos: 0,
+            remaining: value.len,
+        }
+    }
+
+    pub fn write(&self, bytes: &[u8], compress: bool) -> Result<Overflow, StorageError> {
+        let mut writer = self.writer(compress);
+        writer.write_bytes(bytes)?;
+        writer.finish()
+    }
+
+    pub fn read(&self, value: Overflow) -> Result<Vec<u8>, StorageError> {
+        let mut bytes = Vec::with_capacity(value.len as usize);
+        self.reader(value).read_to_end(&mut bytes).map_err(storage_error)?;
+        Ok(bytes)
+    }
+
+    /// Frees the pages of a value.
+    pub fn delete(&self, value: Overflow) -> Result<(), StorageError> {
+        self.free_chain(value.page)
+    }
+
+    /// A reused or new page, laid out as the last page of a chain.
+    fn allocate(&self) -> Result<(u32, PageWriteGuard), StorageError> {
+        let reused = self.free.lock().unwrap().pop();
+        let (page, mut guard) = match reused {
+            Some(page) => (page, self.pool.fetch_write(self.page_id(page))?),
+            None => self.pool.new_page(self.file)?,
+        };
+        guard.set_kind(PageKind::Overflow);
+        guard.put_u32(NEXT, NO_PAGE);
+        guard.put_u16(USED, 0);
+        Ok((page, guard))
+    }
+
+    fn free_chain(&self, first: u32) -> Result<(), StorageError> {
+        let pages = self.pool.files().page_count(self.file)?;
+        let mut freed = Vec::new();
+        let mut page = first;
+        let result = loop {
+            if page == NO_PAGE {
+                break Ok(());
+            }
+            // A chain longer than the file is a cycle.
+            if freed.len() as u32 >= pages {
+                break Err(StorageError::Corrupt(self.page_id(first), "overflow chain loops".to_string()));
+            }
+            let mut guard = match self.pool.fetch_write(self.page_id(page)) {
+                Ok(guard) => guard,
+                Err(err) => break Err(err),
+            };
+            if guard.kind() != Some(PageKind::Overflow) {
+                break Err(StorageError::Corrupt(self.page_id(page), "not an overflow page".to_string()));
+            }
+            let next = guard.get_u32(NEXT);
+            guard.set_kind(PageKind::Free);
+            freed.push(page);
+            page = next;
+        };
+
+        self.free.lock().unwrap().extend(freed);
+        result
+    }
+
+    fn page_id(&self, page: u32) -> PageId {
+        PageId::new(self.file, page)
+    }
+}
+
+/// Errors of the pool surface as I/O errors of readers and writers.
+fn io_error(err: StorageError) -> std::io::Error {
+    match err {
+        StorageError::Io(err) => err,
+        err => std::io::Error::other(err),
+    }
+}
+
+/// Takes back a storage error that went through [`io_error`].
+pub(crate) fn storage_error(err: std::io::Error) -> StorageError {
+    match err.get_ref().is_some_and(|inner| inner.is::<StorageError>()) {
+        true => *err.into_inner().unwrap().downcast::<StorageError>().unwrap(),
+        false => StorageError::Io(err),
+    }
+}
+
+/// Writes a value a chunk at a time. Its pages are freed again unless it is finished.
+pub struct OverflowWriter<'a> {
+    file: &'a OverflowFile,
+    compress: bool,
+    /// The part of the value not written yet.
+    chunk: Vec<u8>,
+    first: Option<u32>,
+    /// The page being filled.
+    page: Option<PageWriteGuard>,
+    len: u64,
+}
+
+impl OverflowWriter<'_> {
+    /// Writes what is left, and returns where the value is.
+    pub fn finish(mut self) -> Result<Overflow, StorageError> {
+        if !self.chunk.is_empty() {
+            self.write_chunk()?;
+        }
+        if self.first.is_none() {
+            let (page, _) = self.file.allocate()?;
+            self.first = Some(page);
+        }
+        self.page = None;
+        let page = self.first.take().unwrap();
+        Ok(Overflow { page, len: self.len })
+    }
+
+    fn write_bytes(&mut self, mut bytes: &[u8]) -> Result<(), StorageError> {
+        while !bytes.is_empty() {
+            let len = bytes.len().min(CHUNK_LEN - self.chunk.len());
+            self.chunk.extend_from_slice(&bytes[..len]);
+            self.len += len as u64;
+            bytes = &bytes[len..];
+            if self.chunk.len() == CHUNK_LEN {
+                self.write_chunk()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_chunk(&mut self) -> Result<(), StorageError> {
+        let compressed = match self.compress {
+            true => Some(compress(&self.chunk)).filter(|compressed| compressed.len() < self.chunk.len()),
+            false => None,
+        };
+        let chunk = std::mem::take(&mut self.chunk);
+        let (method, stored) = match &compressed {
+            Some(compressed) => (COMPRESSED, compressed),
+            None => (RAW, &chunk),
+        };
+
+        let mut header = [0; CHUNK_HEADER_LEN];
+        header[0] = method;
+        header[1..5].copy_from_slice(&(chunk.len() as u32).to_le_bytes());
+        header[5..9].copy_from_slice(&(stored.len() as u32).to_le_bytes());
+        self.put(&header)?;
+        self.put(stored)?;
+
+        self.chunk = chunk;
+        self.chunk.clear();
+        Ok(())
+    }
+
+    /// Appends to the chain, adding pages as they fill up.
+    fn put(&mut self, mut bytes: &[u8]) -> Result<(), StorageError> {
+        while !bytes.is_empty() {
+            let full = matches!(&self.page, Some(guard) if guard.get_u16(USED) as usize == CAPACITY);
+            if self.page.is_none() || full {
+                let (next, guard) = self.file.allocate()?;
+                match &mut self.page {
+                    Some(current) => current.put_u32(NEXT, next),
+                    None => self.first = Some(next),
+                }
+                self.page = Some(guard);
+            }
+
+            let guard = self.page.as_mut().unwrap();
+            let used = guard.get_u16(USED) as usize;
+            let len = bytes.len().min(CAPACITY - used);
+            guard.data_mut()[DATA + used..DATA + used + len].copy_from_slice(&bytes[..len]);
+            guard.put_u16(USED, (used + len) as u16);
+            bytes = &bytes[len..];
+        }
+        Ok(())
+    }
+}
+
+impl Write for OverflowWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_bytes(buf).map_err(io_error)?;
+        Ok(buf.len())
+    }
+
+    /// Chunks are only written once full or finished.
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for OverflowWriter<'_> {
+    fn drop(&mut self) {
+        self.page = None;
+        if let Some(first) = self.first {
+            let _ = self.file.free_chain(first);
+        }
+    }
+}
+
+/// Reads a value a chunk at a time.
+pub struct OverflowReader<'a> {
+    file: &'a OverflowFile,
+    next_page: u32,
+    /// Bytes of the current page.
+    page: Vec<u8>,
+    page_pos: usize,
+    /// The current chunk of the value.
+    chunk: Vec<u8>,
+    chunk_pos: usize,
+    /// Bytes of the value past the current chunk.
+    remaining: u64,
+}
+
+impl OverflowReader<'_> {
+    /// Bytes of the value not read yet.
+    pub fn remaining(&self) -> u64 {
+        self.remaining + (self.chunk.len() - self.chunk_pos) as u64
+    }
+
+    fn next_chunk(&mut self) -> Result<(), StorageError> {
+        let mut header = [0; CHUNK_HEADER_LEN];
+        self.take(&mut header)?;
+        let len = u32::from_le_bytes(header[1..5].try_into().unwrap()) as usize;
+        let stored_len = u32::from_le_bytes(header[5..9].try_into().unwrap()) as usize;
+        if len > CHUNK_LEN || len as u64 > self.remaining || stored_len > CHUNK_LEN {
+            return Err(self.corrupt("invalid chunk length".to_string()));
+        }
+
+        let mut stored = vec![0; stored_len];
+        self.take(&mut stored)?;
+        self.chunk = match header[0] {
+            RAW if stored_len == len => stored,
+            COMPRESSED => decompress(&stored, len).map_err(|message| self.corrupt(message))?,
+            _ => return Err(self.corrupt("invalid chunk".to_string())),
+        };
+        self.chunk_pos = 0;
+        self.remaining -= len as u64;
+        Ok(())
+    }
+
+    /// Fills `out` from the chain, reading pages as needed.
+    fn take(&mut self, mut out: &mut [u8]) -> Result<(), StorageError> {
+        while !out.is_empty() {
+            if self.page_pos == self.page.len() {
+                self.read_page()?;
+            }
+            let len = out.len().min(self.page.len() - self.page_pos);
+            out[..len].copy_from_slice(&self.page[self.page_pos..self.page_pos + len]);
+            self.page_pos += len;
+            out = &mut out[len..];
+        }
+        Ok(())
+    }
+
+    fn read_page(&mut self) -> Result<(), StorageError> {
+        if self.next_page == NO_PAGE {
+            return Err(self.corrupt("overflow chain ends early".to_string()));
+        }
+        let id = self.file.page_id(self.next_page);
+        let guard = self.file.pool.fetch_read(id)?;
+        let used = guard.get_u16(USED) as usize;
+        if guard.kind() != Some(PageKind::Overflow) || used > CAPACITY {
+            return Err(StorageError::Corrupt(id, "not an overflow page".to_string()));
+        }
+        self.page.clear();
+        self.page.extend_from_slice(&guard.data()[DATA..DATA + used]);
+        self.page_pos = 0;
+        self.next_page = guard.get_u32(NEXT);
+        Ok(())
+    }
+
+    fn corrupt(&self, message: String) -> StorageError {
+        StorageError::Corrupt(self.file.page_id(self.next_page), message)
+    }
+}
+
+impl Read for OverflowReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.chunk_pos == self.chunk.len() {
+            if self.remaining == 0 {
+                return Ok(0);
+            }
+            self.next_chunk().map_err(io_error)?;
+        }
+        let len = buf.len().min(self.chunk.len() - self.chunk_pos);
+        buf[..len].copy_from_slice(&self.chunk[self.chunk_pos..self.chunk_pos + len]);
+        self.chunk_pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::storage::buffer::EvictionPolicyKind;
+    use crate::storage::file::FileManager;
+    use crate::storage::testing::TempDir;
+    use std::sync::Arc;
+
+    fn pool(dir: &TempDir) -> BufferPool {
+        BufferPool::new(Arc::new(FileManager::open(dir.path()).unwrap()), 8, EvictionPolicyKind::Clock)
+    }
+
+    #[test]
+    fn test_values() {
+        let dir = TempDir::new();
+        let overflow = OverflowFile::create(pool(&dir)).unwrap();
+
+        let text = "all work and no play makes jack a dull boy\n".repeat(5000).into_bytes();
+        let stored = overflow.write(&text, true).unwrap();
+        assert_eq!(stored.len, text.len() as u64);
+        // Compressed, the text fits in a few pages.
+        assert!(overflow.pool.files().page_count(overflow.file()).unwrap() < 5);
+        assert_eq!(overflow.read(stored).unwrap(), text);
+
+        let empty = overflow.write(&[], false).unwrap();
+        assert_eq!(overflow.read(empty).unwrap(), b"");
+
+        // Pages of deleted values are reused.
+        let pages = overflow.pool.files().page_count(overflow.file()).unwrap();
+        overflow.delete(stored).unwrap();
+        let raw = overflow.write(&text[..5000], false).unwrap();
+        assert_eq!(overflow.pool.files().page_count(overflow.file()).unwrap(), pages);
+        assert_eq!(overflow.read(raw).unwrap(), &text[..5000]);
+
+        // Free pages are found again when the file is opened.
+        overflow.delete(empty).unwrap();
+        overflow.pool.flush_all().unwrap();
+        let file = overflow.file();
+        drop(overflow);
+        let overflow = OverflowFile::open(pool(&dir), file).unwrap();
+        assert_eq!(overflow.read(raw).unwrap(), &text[..5000]);
+        assert_eq!(*overflow.free.lock().unwrap(), vec![empty.page]);
+    }
+
+    #[test]
+    fn test_streaming() {
+        let dir = TempDir::new();
+        let overflow = OverflowFile::create(pool(&dir)).unwrap();
+
+        // Far larger than the pool, written and read in pieces.
+        let piece: Vec<u8> = (0..=255).cycle().take(100_000).collect();
+        let mut writer = overflow.writer(true);
+        for _ in 0..50 {
+            writer.write_all(&piece).unwrap();
+        }
+        let stored = writer.finish().unwrap();
+        assert_eq!(stored.len, 5_000_000);
+
+        let mut reader = overflow.reader(stored);
+        let mut buf = vec![0; 100_000];
+        for _ in 0..50 {
+            reader.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, piece);
+        }
+        assert_eq!(reader.remaining(), 0);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+        // A writer dropped before it is finished gives its pages back.
+        let free = overflow.free.lock().unwrap().len();
+        let mut writer = overflow.writer(false);
+        writer.write_all(&piece).unwrap();
+        drop(writer);
+        assert!(overflow.free.lock().unwrap().len() > free);
+    }
+
+    #[test]
+    fn test_corrupt_chain() {
+        let dir = TempDir::new();
+        let overflow = OverflowFile::create(pool(&dir)).unwrap();
+        let stored = overflow.write(&[7; 20_000], false).unwrap();
+
+        let err = overflow.read(Overflow { len: 30_000, ..stored }).unwrap_err();
+        assert!(matches!(err, StorageError::Corrupt(..)));
+        overflow.delete(stored).unwrap();
+        assert!(matches!(overflow.read(stored), Err(StorageError::Corrupt(..))));
+    }
+}
@@ -0,0 +1,238 @@
+//! Fixed-size pages, the unit of every read and write.
+//!
+//! Every page starts with the same header, the rest belongs to the page kind:
+//!
+//! | bytes  | field                                             |
+//! |--------|---------------------------------------------------|
+//! | 0..8   | LSN of the last log record that changed the page  |
+//! | 8..12  | CRC-32 of the page with this field set to zero    |
+//! | 12     | page kind                                         |
+//! | 13..16 | reserved                                          |
+//!
+//! Numbers are little endian. A page of zeros is a page that was allocated but never written, it passes the
+//! checksum so a crash while extending a file does not make the file unreadable.
+
+use std::fmt::{Debug, Formatter};
+
+pub const PAGE_SIZE: usize = 8192;
+
+/// Length of the header every page starts with.
+pub const PAGE_HEADER_LEN: usize = 16;
+
+const LSN: usize = 0;
+const CHECKSUM: usize = 8;
+const KIND: usize = 12;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PageKind {
+    /// Allocated but not in use.
+    Free,
+    Heap,
+    /// Part of a value stored out of line.
+    Overflow,
+    /// First page of a b-tree.
+    BTreeMeta,
+    BTreeNode,
+    /// First page of a hash index.
+    HashMeta,
+    HashBucket,
+}
+
+impl PageKind {
+    fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            0 => Some(PageKind::Free),
+            1 => Some(PageKind::Heap),
+            2 => Some(PageKind::Overflow),
+            3 => Some(PageKind::BTreeMeta),
+            4 => Some(PageKind::BTreeNode),
+            5 => Some(PageKind::HashMeta),
+            6 => Some(PageKind::HashBucket),
+            _ => None,
+        }
+    }
+
+    fn as_u8(&self) -> u8 {
+        match self {
+            PageKind::Free => 0,
+            PageKind::Heap => 1,
+            PageKind::Overflow => 2,
+            PageKind::BTreeMeta => 3,
+            PageKind::BTreeNode => 4,
+            PageKind::HashMeta => 5,
+            PageKind::HashBucket => 6,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Page(Box<[u8; PAGE_SIZE]>);
+
+impl Page {
+    /// A page of zeros.
+    pub fn new() -> Self {
+        Page(Box::new([0; PAGE_SIZE]))
+    }
+
+    pub fn data(&self) -> &[u8; PAGE_SIZE] {
+        &self.0
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8; PAGE_SIZE] {
+        &mut self.0
+    }
+
+    pub fn lsn(&self) -> u64 {
+        u64::from_le_bytes(self.0[LSN..LSN + 8].try_into().unwrap())
+    }
+
+    pub fn set_lsn(&mut self, lsn: u64) {
+        self.0[LSN..LSN + 8].copy_from_slice(&lsn.to_le_bytes());
+    }
+
+    /// `None` for a kind this version does not know.
+    pub fn kind(&self) -> Option<PageKind> {
+        PageKind::from_u8(self.0[KIND])
+    }
+
+    pub fn set_kind(&mut self, kind: PageKind) {
+        self.0[KIND] = kind.as_u8();
+    }
+
+    /// Whether the page was never written.
+    pub fn is_new(&self) -> bool {
+        self.0.iter().all(|b| *b == 0)
+    }
+
+    /// Stores the checksum of the contents, done right before the page is written.
+    pub fn update_checksum(&mut self) {
+        let checksum = self.checksum();
+        self.put_u32(CHECKSUM, checksum);
+    }
+
+    pub fn verify_checksum(&self) -> bool {
+        self.get_u32(CHECKSUM) == self.checksum() || self.is_new()
+    }
+
+    fn checksum(&self) -> u32 {
+        let mut crc = Crc32::new();
+        crc.update(&self.0[..CHECKSUM]);
+        crc.update(&[0; 4]);
+        crc.update(&self.0[CHECKSUM + 4..]);
+        crc.finish()
+    }
+
+    pub fn get_u16(&self, offset: usize) -> u16 {
+        u16::from_le_bytes([self.0[offset], self.0[offset + 1]])
+    }
+
+    pub fn put_u16(&mut self, offset: usize, value: u16) {
+        self.0[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn get_u32(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.0[offset..offset + 4].try_into().unwrap())
+    }
+
+    pub fn put_u32(&mut self, offset: usize, value: u32) {
+        self.0[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Page::new()
+    }
+}
+
+/// Only the header, the contents are rarely worth printing.
+impl Debug for Page {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("Page")
+            .field("lsn", &self.lsn())
+            .field("kind", &self.kind())
+            .finish_non_exhaustive()
+    }
+}
+
+/// CRC-32 with the polynomial of zlib and Ethernet.
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    pub fn new() -> Self {
+        Crc32(!0)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.0 = Crc32::TABLE[((self.0 ^ *byte as u32) & 0xff) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+
+    pub fn checksum(data: &[u8]) -> u32 {
+        let mut crc = Crc32::new();
+        crc.update(data);
+        crc.finish()
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(Crc32::checksum(b""), 0);
+        assert_eq!(Crc32::checksum(b"123456789"), 0xcbf4_3926);
+
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_checksum() {
+        let mut page = Page::new();
+        assert!(page.is_new());
+        assert!(page.verify_checksum());
+
+        page.set_kind(PageKind::Heap);
+        page.set_lsn(42);
+        assert!(!page.verify_checksum());
+        page.update_checksum();
+        assert!(page.verify_checksum());
+        assert_eq!((page.kind(), page.lsn()), (Some(PageKind::Heap), 42));
+
+        page.data_mut()[PAGE_SIZE - 1] ^= 1;
+        assert!(!page.verify_checksum());
+    }
+}
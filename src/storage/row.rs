@@ -0,0 +1,724 @@
+//! Rows of a table as bytes.
+//!
+//! A row is laid out by the schema of its table: a bitmap with a bit set for every NULL column, a fixed-width
+//! field for every column in order, and then the contents of the variable-length columns. The field of a
+//! variable-length column is the `u32` offset where its contents end, counted from the start of the contents of
+//! the first one, so any column can be read without decoding the ones before it. The field of a NULL column is
+//! left zeroed, unless it is variable-length and holds the end of the contents before it. Contents too large for
+//! a page are stored out of line by the table, the end offset then has its high bit set and the contents are the
+//! first page and length of the value in the overflow file.
+//!
+//! | type                                  | field                                                |
+//! |---------------------------------------|------------------------------------------------------|
+//! | BOOL, TINYINT                         | 1 byte                                               |
+//! | SMALLINT                              | 2 bytes                                              |
+//! | MEDIUMINT                             | 3 bytes                                              |
+//! | INTEGER                               | 4 bytes                                              |
+//! | BIGINT                                | 8 bytes                                              |
+//! | FLOAT                                 | `f32`, `f64` above a precision of 24                 |
+//! | DOUBLE                                | `f64`                                                |
+//! | DECIMAL(p, s)                         | the unscaled integer, 8 bytes up to 18 digits, or 16 |
+//! | BIT(n)                                | n bits rounded up to whole bytes                     |
+//! | BINARY(n)                             | n bytes, padded with zeros                           |
+//! | CHAR, VARCHAR, TEXT, VARBINARY, BLOB  | end of the contents                                  |
+//!
+//! Numbers are little endian like the rest of a page.
+
+use crate::parser::ast::{ColumnConstraintKind, ColumnDef};
+use crate::parser::token::DataKind;
+use crate::storage::overflow::Overflow;
+use crate::value::{Decimal, Value};
+use std::fmt::{Display, Formatter};
+
+const OFFSET_LEN: usize = 4;
+/// Marks the end offset of contents stored out of line.
+const EXTERNAL: u32 = 1 << 31;
+const OVERFLOW_LEN: usize = 12;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnSchema {
+    pub name: String,
+    pub data_type: DataKind,
+    pub nullable: bool,
+}
+
+impl ColumnSchema {
+    pub fn new(name: &str, data_type: DataKind, nullable: bool) -> Self {
+        ColumnSchema {
+            name: name.to_string(),
+            data_type,
+            nullable,
+        }
+    }
+
+    /// Longest contents of a string or binary column in bytes, `None` for other types.
+    pub fn max_len(&self) -> Option<u64> {
+        match Field::of(&self.data_type) {
+            Field::Var(max_len) => Some(max_len),
+            Field::Fixed(_) => None,
+        }
+    }
+
+    /// Most characters of a CHAR or VARCHAR column, whose lengths count characters rather than bytes.
+    pub fn max_chars(&self) -> Option<usize> {
+        match self.data_type {
+            DataKind::Char(len) => Some(len.unwrap_or(1) as usize),
+            DataKind::VarChar(Some(len)) => Some(len as usize),
+            _ => None,
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(
+            self.data_type,
+            DataKind::Char(_)
+                | DataKind::VarChar(_)
+                | DataKind::TinyText
+                | DataKind::Text(_)
+                | DataKind::MediumText(_)
+                | DataKind::LongText(_)
+        )
+    }
+}
+
+/// Primary key columns are not nullable either.
+impl From<&ColumnDef<'_>> for ColumnSchema {
+    fn from(def: &ColumnDef<'_>) -> Self {
+        let nullable = !def
+            .constraints
+            .iter()
+            .any(|constraint| matches!(constraint, ColumnConstraintKind::NotNull | ColumnConstraintKind::PrimaryKey));
+        ColumnSchema::new(def.name, def.data_type.clone(), nullable)
+    }
+}
+
+/// How a column is stored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    Fixed(usize),
+    /// Variable length, at most this many bytes.
+    Var(u64),
+}
+
+impl Field {
+    fn of(data_type: &DataKind) -> Field {
+        match *data_type {
+            DataKind::Bool | DataKind::TinyInt(_) => Field::Fixed(1),
+            DataKind::SmallInt(_) => Field::Fixed(2),
+            DataKind::MediumInt(_) => Field::Fixed(3),
+            DataKind::Integer(_) => Field::Fixed(4),
+            DataKind::BigInt(_) => Field::Fixed(8),
+            DataKind::Float(Some(precision), _) if precision > 24 => Field::Fixed(8),
+            DataKind::Float(..) => Field::Fixed(4),
+            DataKind::Double(..) => Field::Fixed(8),
+            DataKind::Decimal(..) if decimal_type(data_type).0 > 18 => Field::Fixed(16),
+            DataKind::Decimal(..) => Field::Fixed(8),
+            DataKind::Bit(len) => Field::Fixed((len.unwrap_or(1) as usize).div_ceil(8)),
+            DataKind::Binary(len) => Field::Fixed(len.unwrap_or(1) as usize),
+            // Characters take up to 4 bytes in UTF-8, their number is checked separately.
+            DataKind::Char(len) => Field::Var(4 * len.unwrap_or(1) as u64),
+            DataKind::VarChar(len) => Field::Var(len.map_or(u32::MAX as u64, |len| 4 * len as u64)),
+            DataKind::VarBinary(len) => Field::Var(len.map_or(u32::MAX as u64, u64::from)),
+            DataKind::TinyText | DataKind::TinyBlob => Field::Var(u8::MAX as u64),
+            DataKind::Text(_) | DataKind::Blob(_) => Field::Var(u16::MAX as u64),
+            DataKind::MediumText(_) | DataKind::MediumBlob(_) => Field::Var((1 << 24) - 1),
+            DataKind::LongText(_) | DataKind::LongBlob(_) => Field::Var(u32::MAX as u64),
+        }
+    }
+
+    fn width(&self) -> usize {
+        match self {
+            Field::Fixed(width) => *width,
+            Field::Var(_) => OFFSET_LEN,
+        }
+    }
+}
+
+/// Precision and scale of a DECIMAL, 10 and 0 when left out.
+fn decimal_type(data_type: &DataKind) -> (u8, u8) {
+    match data_type {
+        DataKind::Decimal(precision, scale) => (precision.unwrap_or(10), scale.unwrap_or(0)),
+        _ => unreachable!("not a decimal"),
+    }
+}
+
+/// The columns of a table, which say how its rows are stored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schema {
+    columns: Vec<ColumnSchema>,
+    /// Where the field of each column starts.
+    offsets: Vec<usize>,
+    /// Where the variable-length contents start.
+    fixed_len: usize,
+}
+
+impl Schema {
+    /// Fails on types whose parameters are out of range, such as `DECIMAL(40)`.
+    pub fn new(columns: Vec<ColumnSchema>) -> Result<Self, RowError> {
+        for column in &columns {
+            check_type(column)?;
+        }
+
+        let mut offsets = Vec::with_capacity(columns.len());
+        let mut offset = columns.len().div_ceil(8);
+        for column in &columns {
+            offsets.push(offset);
+            offset += Field::of(&column.data_type).width();
+        }
+        Ok(Schema {
+            columns,
+            offsets,
+            fixed_len: offset,
+        })
+    }
+
+    pub fn columns(&self) -> &[ColumnSchema] {
+        &self.columns
+    }
+
+    /// Checks the values against their columns and encodes them. Values are converted to the type of their
+    /// column where no information is lost, an integer is a valid DECIMAL, and decimals are rounded to the scale
+    /// of their column.
+    pub fn encode(&self, row: &[Value]) -> Result<Vec<u8>, RowError> {
+        self.encode_with(row, |_, _| Ok(None))
+    }
+
+    /// Encodes a row like [`Schema::encode`], after offering the checked contents of every string and binary
+    /// value to `external` along with its column, which returns where it stored them to keep them out of line.
+    pub fn encode_with<E: From<RowError>>(
+        &self,
+        row: &[Value],
+        mut external: impl FnMut(usize, &[u8]) -> Result<Option<Overflow>, E>,
+    ) -> Result<Vec<u8>, E> {
+        if row.len() != self.columns.len() {
+            return Err(RowError::ColumnCount {
+                expected: self.columns.len(),
+                actual: row.len(),
+            }
+            .into());
+        }
+
+        let mut bytes = vec![0; self.fixed_len];
+        for (i, (column, value)) in self.columns.iter().zip(row).enumerate() {
+            let offset = self.offsets[i];
+            let field = Field::of(&column.data_type);
+            if value.is_null() {
+                if !column.nullable {
+                    return Err(RowError::NotNull(column.name.clone()).into());
+                }
+                bytes[i / 8] |= 1 << (i % 8);
+                if let Field::Var(_) = field {
+                    let end = (bytes.len() - self.fixed_len) as u32;
+                    bytes[offset..offset + OFFSET_LEN].copy_from_slice(&end.to_le_bytes());
+                }
+                continue;
+            }
+
+            match field {
+                Field::Fixed(width) => encode_fixed(column, value, &mut bytes[offset..offset + width])?,
+                Field::Var(max_len) => {
+                    let too_long = || RowError::TooLong(column.name.clone(), column.data_type.clone());
+                    let contents = var_contents(column, value)?;
+                    let too_many_chars = match (column.max_chars(), value) {
+                        (Some(max), Value::Text(s)) => s.chars().count() > max,
+                        _ => false,
+                    };
+                    if contents.len() as u64 > max_len || too_many_chars {
+                        return Err(too_long().into());
+                    }
+
+                    let flag = match external(i, contents)? {
+                        Some(overflow) => {
+                            bytes.extend_from_slice(&overflow.page.to_le_bytes());
+                            bytes.extend_from_slice(&overflow.len.to_le_bytes());
+                            EXTERNAL
+                        }
+                        None => {
+                            bytes.extend_from_slice(contents);
+                            0
+                        }
+                    };
+                    let end = u32::try_from(bytes.len() - self.fixed_len)
+                        .ok()
+                        .filter(|end| end & EXTERNAL == 0)
+                        .ok_or_else(too_long)?;
+                    bytes[offset..offset + OFFSET_LEN].copy_from_slice(&(end | flag).to_le_bytes());
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes a row whose contents are all inline.
+    pub fn decode(&self, row: &[u8]) -> Result<Vec<Value>, RowError> {
+        self.decode_with(row, not_inline)
+    }
+
+    /// Decodes a row, reading the contents stored out of line with `load`.
+    pub fn decode_with<E: From<RowError>>(
+        &self,
+        row: &[u8],
+        mut load: impl FnMut(Overflow) -> Result<Vec<u8>, E>,
+    ) -> Result<Vec<Value>, E> {
+        (0..self.columns.len())
+            .map(|i| self.decode_column_with(row, i, &mut load))
+            .collect()
+    }
+
+    /// Reads a single column of a row whose contents are all inline.
+    pub fn decode_column(&self, row: &[u8], i: usize) -> Result<Value, RowError> {
+        self.decode_column_with(row, i, not_inline)
+    }
+
+    /// Reads a single column of a row, without decoding the ones before it.
+    pub fn decode_column_with<E: From<RowError>>(
+        &self,
+        row: &[u8],
+        i: usize,
+        mut load: impl FnMut(Overflow) -> Result<Vec<u8>, E>,
+    ) -> Result<Value, E> {
+        let column = &self.columns[i];
+        match self.field(row, i)? {
+            Contents::Null => Ok(Value::Null),
+            Contents::Fixed(field) => Ok(decode_fixed(&column.data_type, field)),
+            Contents::Inline(contents) => Ok(decode_var(column, contents)?),
+            Contents::External(overflow) => {
+                let contents = load(overflow)?;
+                if contents.len() as u64 != overflow.len {
+                    return Err(RowError::Corrupt(format!("column {0} has the wrong length", column.name)).into());
+                }
+                Ok(decode_var(column, &contents)?)
+            }
+        }
+    }
+
+    /// Where the column is stored out of line, if it is.
+    pub fn overflow(&self, row: &[u8], i: usize) -> Result<Option<Overflow>, RowError> {
+        match self.field(row, i)? {
+            Contents::External(overflow) => Ok(Some(overflow)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Every value of the row stored out of line.
+    pub fn overflows(&self, row: &[u8]) -> Result<Vec<Overflow>, RowError> {
+        (0..self.columns.len())
+            .filter_map(|i| self.overflow(row, i).transpose())
+            .collect()
+    }
+
+    fn field<'a>(&self, row: &'a [u8], i: usize) -> Result<Contents<'a>, RowError> {
+        if row.len() < self.fixed_len {
+            return Err(RowError::Corrupt(format!(
+                "row of {0} bytes is shorter than its {1} fixed bytes",
+                row.len(),
+                self.fixed_len
+            )));
+        }
+        if row[i / 8] & (1 << (i % 8)) != 0 {
+            return Ok(Contents::Null);
+        }
+
+        let column = &self.columns[i];
+        let offset = self.offsets[i];
+        let width = match Field::of(&column.data_type) {
+            Field::Fixed(width) => return Ok(Contents::Fixed(&row[offset..offset + width])),
+            Field::Var(_) => OFFSET_LEN,
+        };
+
+        let end_of = |offset: usize| u32::from_le_bytes(row[offset..offset + width].try_into().unwrap());
+        let start = (0..i)
+            .rev()
+            .find(|j| matches!(Field::of(&self.columns[*j].data_type), Field::Var(_)))
+            .map_or(0, |j| end_of(self.offsets[j]) & !EXTERNAL);
+        let end = end_of(offset);
+        let Some(contents) = row[self.fixed_len..].get(start as usize..(end & !EXTERNAL) as usize) else {
+            return Err(RowError::Corrupt(format!("column {0} is outside of the row", column.name)));
+        };
+
+        match end & EXTERNAL != 0 {
+            true if contents.len() == OVERFLOW_LEN => Ok(Contents::External(Overflow {
+                page: u32::from_le_bytes(contents[..4].try_into().unwrap()),
+                len: u64::from_le_bytes(contents[4..].try_into().unwrap()),
+            })),
+            true => Err(RowError::Corrupt(format!("column {0} is an invalid reference", column.name))),
+            false => Ok(Contents::Inline(contents)),
+        }
+    }
+}
+
+/// Where the value of a column is.
+enum Contents<'a> {
+    Null,
+    Fixed(&'a [u8]),
+    Inline(&'a [u8]),
+    External(Overflow),
+}
+
+fn not_inline(_: Overflow) -> Result<Vec<u8>, RowError> {
+    Err(RowError::Corrupt("value is stored out of line".to_string()))
+}
+
+fn check_type(column: &ColumnSchema) -> Result<(), RowError> {
+    let invalid = |message: String| Err(RowError::InvalidType(column.name.clone(), message));
+    match column.data_type {
+        DataKind::Decimal(..) => {
+            let (precision, scale) = decimal_type(&column.data_type);
+            if !(1..=Decimal::MAX_PRECISION).contains(&precision) {
+                return invalid(format!("DECIMAL precision must be between 1 and {0}", Decimal::MAX_PRECISION));
+            }
+            if scale > precision {
+                return invalid(format!("DECIMAL scale {scale} must not exceed its precision {precision}"));
+            }
+        }
+        DataKind::Bit(Some(len)) if !(1..=64).contains(&len) => {
+            return invalid("BIT length must be between 1 and 64".to_string());
+        }
+        DataKind::Char(Some(0)) | DataKind::Binary(Some(0)) => {
+            return invalid(format!("{0} length must be at least 1", column.data_type));
+        }
+        DataKind::Binary(Some(len)) if len > 255 => return invalid("BINARY length must be at most 255".to_string()),
+        DataKind::Float(Some(precision), _) if precision > 53 => {
+            return invalid("FLOAT precision must be at most 53".to_string());
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Range of the integer types.
+fn int_range(data_type: &DataKind) -> Option<(i64, i64)> {
+    match data_type {
+        DataKind::TinyInt(_) => Some((i8::MIN as i64, i8::MAX as i64)),
+        DataKind::SmallInt(_) => Some((i16::MIN as i64, i16::MAX as i64)),
+        DataKind::MediumInt(_) => Some((-(1 << 23), (1 << 23) - 1)),
+        DataKind::Integer(_) => Some((i32::MIN as i64, i32::MAX as i64)),
+        DataKind::BigInt(_) => Some((i64::MIN, i64::MAX)),
+        _ => None,
+    }
+}
+
+fn encode_fixed(column: &ColumnSchema, value: &Value, field: &mut [u8]) -> Result<(), RowError> {
+    let data_type = &column.data_type;
+    let mismatch = || RowError::TypeMismatch(column.name.clone(), data_type.clone());
+    let out_of_range = || RowError::OutOfRange(column.name.clone(), data_type.clone());
+    let width = field.len();
+
+    match (data_type, value) {
+        (DataKind::Bool, Value::Bool(b)) => field[0] = *b as u8,
+        (DataKind::Bool, Value::Int(n @ (0 | 1))) => field[0] = *n as u8,
+        (DataKind::Bool, _) => return Err(mismatch()),
+        (DataKind::Float(..) | DataKind::Double(..), value) => {
+            let n = match value {
+                Value::Float(n) => *n,
+                Value::Int(n) => *n as f64,
+                Value::Decimal(n) => n.to_f64(),
+                _ => return Err(mismatch()),
+            };
+            match width {
+                4 if n.is_finite() && n.abs() <= f32::MAX as f64 => field.copy_from_slice(&(n as f32).to_le_bytes()),
+                8 if n.is_finite() => field.copy_from_slice(&n.to_le_bytes()),
+                _ => return Err(out_of_range()),
+            }
+        }
+        (DataKind::Decimal(..), value) => {
+            let (precision, scale) = decimal_type(data_type);
+            let n = match value {
+                Value::Decimal(n) => *n,
+                Value::Int(n) => Decimal::from(*n),
+                Value::Float(n) if n.is_finite() => {
+                    format!("{n:.0$}", scale as usize).parse().map_err(|_| out_of_range())?
+                }
+                Value::Float(_) => return Err(out_of_range()),
+                _ => return Err(mismatch()),
+            };
+            let n = n
+                .rescale(scale)
+                .filter(|n| n.digits() <= precision)
+                .ok_or_else(out_of_range)?;
+            field.copy_from_slice(&n.value().to_le_bytes()[..width]);
+        }
+        (DataKind::Bit(len), Value::Int(n)) => {
+            let len = len.unwrap_or(1);
+            if len < 64 && !(0..1 << len).contains(n) {
+                return Err(out_of_range());
+            }
+            field.copy_from_slice(&n.to_le_bytes()[..width]);
+        }
+        (DataKind::Binary(_), value) => {
+            let bytes = var_contents(column, value)?;
+            if bytes.len() > width {
+                return Err(RowError::TooLong(column.name.clone(), data_type.clone()));
+            }
+            field[..bytes.len()].copy_from_slice(bytes);
+        }
+        (data_type, value) => {
+            let (min, max) = int_range(data_type).ok_or_else(mismatch)?;
+            let n = match value {
+                Value::Int(n) => *n,
+                Value::Bool(b) => *b as i64,
+                _ => return Err(mismatch()),
+            };
+            if !(min..=max).contains(&n) {
+                return Err(out_of_range());
+            }
+            field.copy_from_slice(&n.to_le_bytes()[..width]);
+        }
+    }
+    Ok(())
+}
+
+fn decode_fixed(data_type: &DataKind, field: &[u8]) -> Value {
+    // Sign extends the little endian integer in the field.
+    let signed = |field: &[u8]| {
+        let fill = if field.last().is_some_and(|b| b & 0x80 != 0) {
+            0xff
+        } else {
+            0
+        };
+        let mut bytes = [fill; 16];
+        bytes[..field.len()].copy_from_slice(field);
+        i128::from_le_bytes(bytes)
+    };
+
+    match data_type {
+        DataKind::Bool => Value::Bool(field[0] != 0),
+        DataKind::Float(..) | DataKind::Double(..) => match field.len() {
+            4 => Value::Float(f32::from_le_bytes(field.try_into().unwrap()) as f64),
+            _ => Value::Float(f64::from_le_bytes(field.try_into().unwrap())),
+        },
+        DataKind::Decimal(..) => Value::Decimal(Decimal::new(signed(field), decimal_type(data_type).1)),
+        DataKind::Bit(_) => {
+            let mut bytes = [0; 8];
+            bytes[..field.len()].copy_from_slice(field);
+            Value::Int(i64::from_le_bytes(bytes))
+        }
+        DataKind::Binary(_) => Value::Bytes(field.to_vec()),
+        _ => Value::Int(signed(field) as i64),
+    }
+}
+
+/// The bytes a string or binary value is stored as. Binary columns also take text.
+fn var_contents<'a>(column: &ColumnSchema, value: &'a Value) -> Result<&'a [u8], RowError> {
+    match (value, column.is_text()) {
+        (Value::Text(s), _) => Ok(s.as_bytes()),
+        (Value::Bytes(bytes), false) => Ok(bytes),
+        _ => Err(RowError::TypeMismatch(column.name.clone(), column.data_type.clone())),
+    }
+}
+
+fn decode_var(column: &ColumnSchema, contents: &[u8]) -> Result<Value, RowError> {
+    match column.is_text() {
+        true => match std::str::from_utf8(contents) {
+            Ok(s) => Ok(Value::Text(s.to_string())),
+            Err(_) => Err(RowError::Corrupt(format!("column {0} is not valid UTF-8", column.name))),
+        },
+        false => Ok(Value::Bytes(contents.to_vec())),
+    }
+}
+
+/// Values that do not fit their columns, with the name and type of the column.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RowError {
+    ColumnCount {
+        expected: usize,
+        actual: usize,
+    },
+    NotNull(String),
+    TypeMismatch(String, DataKind),
+    /// A string or binary value longer than its column allows.
+    TooLong(String, DataKind),
+    OutOfRange(String, DataKind),
+    /// A column type that cannot be stored, such as `DECIMAL(40)`.
+    InvalidType(String, String),
+    /// Stored bytes that are not a row of the schema.
+    Corrupt(String),
+}
+
+impl Display for RowError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            RowError::ColumnCount { expected, actual } => write!(f, "expected {expected} values, got {actual}"),
+            RowError::NotNull(column) => write!(f, "null value in column \"{column}\" violates not-null constraint"),
+            RowError::TypeMismatch(column, data_type) => {
+                write!(f, "value does not match type {data_type} of column \"{column}\"")
+            }
+            RowError::TooLong(column, data_type) => {
+                write!(f, "value too long for type {data_type} of column \"{column}\"")
+            }
+            RowError::OutOfRange(column, data_type) => {
+                write!(f, "value out of range for type {data_type} of column \"{column}\"")
+            }
+            RowError::InvalidType(column, message) => write!(f, "invalid type of column \"{column}\": {message}"),
+            RowError::Corrupt(message) => write!(f, "corrupt row: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for RowError {}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn decimal(s: &str) -> Value {
+        Value::Decimal(s.parse().unwrap())
+    }
+
+    fn schema(types: &[DataKind]) -> Schema {
+        let columns = types
+            .iter()
+            .enumerate()
+            .map(|(i, data_type)| ColumnSchema::new(&format!("c{i}"), data_type.clone(), true))
+            .collect();
+        Schema::new(columns).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let row = vec![
+            (DataKind::Bool, Value::Bool(true)),
+            (DataKind::TinyInt(None), Value::Int(-128)),
+            (DataKind::SmallInt(None), Value::Int(-300)),
+            (DataKind::MediumInt(Some(9)), Value::Int(-(1 << 23))),
+            (DataKind::Integer(None), Value::Int(i32::MAX as i64)),
+            (DataKind::BigInt(None), Value::Int(i64::MIN)),
+            (DataKind::Float(None, None), Value::Float(1.5)),
+            (DataKind::Float(Some(30), None), Value::Float(0.1)),
+            (DataKind::Double(None, None), Value::Float(-2.25e300)),
+            (DataKind::Decimal(None, None), decimal("-1234567890")),
+            (
+                DataKind::Decimal(Some(38), Some(10)),
+                decimal("-1234567890123456789012345678.0123456789"),
+            ),
+            (DataKind::Bit(Some(12)), Value::Int(0xabc)),
+            (DataKind::Bit(Some(64)), Value::Int(-1)),
+            (DataKind::Binary(Some(4)), Value::Bytes(vec![1, 2, 3, 4])),
+            (DataKind::Char(Some(3)), Value::Text("日本語".to_string())),
+            (DataKind::VarChar(Some(255)), Value::Text("hello".to_string())),
+            (DataKind::VarBinary(Some(2)), Value::Bytes(vec![])),
+            (DataKind::TinyText, Value::Text("tiny".to_string())),
+            (DataKind::TinyBlob, Value::Bytes(vec![0xff])),
+            (DataKind::Text(None), Value::Text("text".to_string())),
+            (DataKind::Blob(None), Value::Bytes(vec![0; 300])),
+            (DataKind::MediumText(None), Value::Text("medium".to_string())),
+            (DataKind::MediumBlob(None), Value::Bytes(vec![7])),
+            (DataKind::LongText(None), Value::Text(String::new())),
+            (DataKind::LongBlob(None), Value::Bytes(vec![8, 9])),
+        ];
+        let (types, values): (Vec<DataKind>, Vec<Value>) = row.into_iter().unzip();
+        let schema = schema(&types);
+
+        let bytes = schema.encode(&values).unwrap();
+        assert_eq!(schema.decode(&bytes).unwrap(), values);
+        assert_eq!(schema.decode_column(&bytes, 15).unwrap(), values[15]);
+
+        // Fixed fields are as wide as their types, text takes its length and an offset.
+        let schema = self::schema(&[
+            DataKind::Integer(None),
+            DataKind::Decimal(Some(5), Some(2)),
+            DataKind::Text(None),
+        ]);
+        let bytes = schema
+            .encode(&[Value::Int(1), decimal("1.5"), Value::Text("abc".to_string())])
+            .unwrap();
+        assert_eq!(bytes.len(), 1 + 4 + 8 + 4 + 3);
+        assert_eq!(schema.decode_column(&bytes, 1).unwrap().to_string(), "1.50");
+    }
+
+    #[test]
+    fn test_nulls() {
+        let types = vec![DataKind::VarChar(None); 10];
+        let schema = schema(&types);
+        let mut values = vec![Value::Null; 10];
+        values[3] = Value::Text("three".to_string());
+        values[9] = Value::Text("nine".to_string());
+        let bytes = schema.encode(&values).unwrap();
+        assert_eq!(&bytes[..2], &[0b1111_0111, 0b01]);
+        assert_eq!(schema.decode(&bytes).unwrap(), values);
+
+        let schema = Schema::new(vec![ColumnSchema::new("id", DataKind::Integer(None), false)]).unwrap();
+        let err = schema.encode(&[Value::Null]).unwrap_err();
+        assert_eq!(err.to_string(), "null value in column \"id\" violates not-null constraint");
+        assert!(matches!(
+            schema.encode(&[]),
+            Err(RowError::ColumnCount { expected: 1, actual: 0 })
+        ));
+        assert!(matches!(schema.decode(&[]), Err(RowError::Corrupt(_))));
+    }
+
+    #[test]
+    fn test_external() {
+        let schema = schema(&[DataKind::Text(None), DataKind::Integer(None), DataKind::Blob(None)]);
+        let values = vec![Value::Text("inline".to_string()), Value::Int(7), Value::Bytes(vec![1; 100])];
+        let stored = Overflow { page: 3, len: 100 };
+        let external = |i: usize, _: &[u8]| Ok::<_, RowError>((i == 2).then_some(stored));
+        let bytes = schema.encode_with(&values, external).unwrap();
+        assert_eq!(schema.overflows(&bytes).unwrap(), vec![stored]);
+        assert_eq!(schema.overflow(&bytes, 0).unwrap(), None);
+        assert_eq!(schema.decode_column(&bytes, 0).unwrap(), values[0]);
+        assert!(matches!(schema.decode(&bytes), Err(RowError::Corrupt(_))));
+
+        let decoded = schema.decode_with(&bytes, |overflow| Ok::<_, RowError>(vec![1; overflow.len as usize]));
+        assert_eq!(decoded.unwrap(), values);
+        let short = schema.decode_with(&bytes, |_| Ok::<_, RowError>(vec![1; 10]));
+        assert!(matches!(short, Err(RowError::Corrupt(_))));
+    }
+
+    #[test]
+    fn test_validation() {
+        let encode = |data_type: DataKind, value: Value| {
+            let schema = schema(&[data_type]);
+            schema.encode(&[value]).and_then(|bytes| schema.decode_column(&bytes, 0))
+        };
+
+        let err = encode(DataKind::VarChar(Some(3)), Value::Text("four".to_string())).unwrap_err();
+        assert_eq!(err.to_string(), "value too long for type VARCHAR(3) of column \"c0\"");
+        assert!(encode(DataKind::VarChar(Some(3)), Value::Text("été".to_string())).is_ok());
+        assert!(encode(DataKind::Char(None), Value::Text("ab".to_string())).is_err());
+        assert!(encode(DataKind::TinyBlob, Value::Bytes(vec![0; 256])).is_err());
+        assert!(encode(DataKind::Text(None), Value::Int(1)).is_err());
+
+        let err = encode(DataKind::TinyInt(None), Value::Int(128)).unwrap_err();
+        assert_eq!(err.to_string(), "value out of range for type TINYINT of column \"c0\"");
+        assert!(encode(DataKind::MediumInt(None), Value::Int(1 << 23)).is_err());
+        assert!(encode(DataKind::Bit(Some(3)), Value::Int(8)).is_err());
+        assert!(encode(DataKind::Float(None, None), Value::Float(1e300)).is_err());
+        assert!(encode(DataKind::Double(None, None), Value::Float(f64::NAN)).is_err());
+        assert!(matches!(
+            encode(DataKind::Integer(None), Value::Float(1.0)),
+            Err(RowError::TypeMismatch(..))
+        ));
+
+        // Values are converted to the type of their column, and padded or rounded to it.
+        assert_eq!(encode(DataKind::Bool, Value::Int(1)).unwrap(), Value::Bool(true));
+        assert_eq!(
+            encode(DataKind::Double(None, None), Value::Int(3)).unwrap(),
+            Value::Float(3.0)
+        );
+        assert_eq!(
+            encode(DataKind::Binary(Some(3)), Value::Text("a".to_string())).unwrap(),
+            Value::Bytes(b"a\0\0".to_vec())
+        );
+        let money = DataKind::Decimal(Some(5), Some(2));
+        assert_eq!(encode(money.clone(), decimal("123.455")).unwrap().to_string(), "123.46");
+        assert_eq!(encode(money.clone(), Value::Int(-7)).unwrap().to_string(), "-7.00");
+        assert_eq!(encode(money.clone(), Value::Float(0.1)).unwrap().to_string(), "0.10");
+        assert!(encode(money.clone(), decimal("999.995")).is_err());
+        assert!(encode(money, Value::Int(1000)).is_err());
+    }
+
+    #[test]
+    fn test_invalid_types() {
+        let new = |data_type| Schema::new(vec![ColumnSchema::new("c", data_type, true)]);
+        assert_eq!(
+            new(DataKind::Decimal(Some(40), None)).unwrap_err().to_string(),
+            "invalid type of column \"c\": DECIMAL precision must be between 1 and 38"
+        );
+        assert!(new(DataKind::Decimal(Some(4), Some(5))).is_err());
+        assert!(new(DataKind::Bit(Some(65))).is_err());
+        assert!(new(DataKind::Binary(Some(0))).is_err());
+        assert!(new(DataKind::Float(Some(54), None)).is_err());
+    }
+}
@@ -0,0 +1,1302 @@
+//! Tables, rows of values in a heap file with their large values in an overflow file.
+//!
+//! String and binary values longer than [`MAX_INLINE_LEN`] are stored out of line, compressed unless the table
+//! says otherwise, and the row only keeps where they are. They are freed along with the row that owns them, when
+//! it is deleted or its values replaced. Such a value can also be written and read in pieces through
+//! [`Table::insert_from`] and [`Table::read_value`], so that it never has to be in memory all at once.
+//!
+//! Indexes of the table are kept up to date with its rows. A change that a unique index rejects is undone, and
+//! leaves the table as it was.
+//!
+//! Every row is a [`Version`] of it. The methods taking a [`Transaction`] read the versions it sees and replace
+//! them with new ones, as described in [`crate::storage::mvcc`], the others read and change rows as stored. A
+//! version whose key is the one of the version it replaced shares its index entries, lookups follow the versions
+//! from there. Vacuum removes the versions no transaction sees any more, and moves the entries they shared to the
+//! versions that replaced them. Reads do not follow versions while it removes them, since their slots may be reused.
+
+use crate::parser::ast::IndexMethod;
+use crate::storage::btree::BTree;
+use crate::storage::buffer::BufferPool;
+use crate::storage::hash::HashIndex;
+use crate::storage::heap::{HeapFile, MAX_ROW_LEN};
+use crate::storage::mvcc::{FROZEN_XID, Transaction, TransactionManager, TxnStatus, VERSION_LEN, Version, Xid};
+use crate::storage::overflow::{Overflow, OverflowFile, OverflowReader};
+use crate::storage::page::{PAGE_SIZE, PageKind};
+use crate::storage::row::{RowError, Schema};
+use crate::storage::vacuum::{VacuumPhase, VacuumProgress, VacuumStats};
+use crate::storage::{FileId, PageId, RowId, StorageError};
+use crate::value::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{Cursor, Read, Write};
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Longest value kept in its row.
+pub const MAX_INLINE_LEN: usize = PAGE_SIZE / 4;
+
+/// Where an index keeps its keys.
+pub enum IndexAccess {
+    BTree(BTree),
+    Hash(HashIndex),
+}
+
+impl IndexAccess {
+    fn create(pool: BufferPool, method: IndexMethod, columns: usize, unique: bool) -> Result<Self, StorageError> {
+        Ok(match method {
+            IndexMethod::BTree => IndexAccess::BTree(BTree::create(pool, columns, unique)?),
+            IndexMethod::Hash => IndexAccess::Hash(HashIndex::create(pool, columns, unique)?),
+        })
+    }
+
+    /// Opens the index in `file`, of whichever method it was created with.
+    fn open(pool: BufferPool, file: FileId) -> Result<Self, StorageError> {
+        let id = PageId::new(file, 0);
+        let kind = pool.fetch_read(id)?.kind();
+        match kind {
+            Some(PageKind::BTreeMeta) => Ok(IndexAccess::BTree(BTree::open(pool, file)?)),
+            Some(PageKind::HashMeta) => Ok(IndexAccess::Hash(HashIndex::open(pool, file)?)),
+            _ => Err(StorageError::Corrupt(id, "not an index".to_string())),
+        }
+    }
+
+    pub fn method(&self) -> IndexMethod {
+        match self {
+            IndexAccess::BTree(_) => IndexMethod::BTree,
+            IndexAccess::Hash(_) => IndexMethod::Hash,
+        }
+    }
+
+    pub fn file(&self) -> FileId {
+        match self {
+            IndexAccess::BTree(tree) => tree.file(),
+            IndexAccess::Hash(hash) => hash.file(),
+        }
+    }
+
+    pub fn columns(&self) -> usize {
+        match self {
+            IndexAccess::BTree(tree) => tree.columns(),
+            IndexAccess::Hash(hash) => hash.columns(),
+        }
+    }
+
+    pub fn is_unique(&self) -> bool {
+        match self {
+            IndexAccess::BTree(tree) => tree.is_unique(),
+            IndexAccess::Hash(hash) => hash.is_unique(),
+        }
+    }
+
+    pub fn insert(&self, key: &[Value], row: RowId) -> Result<(), StorageError> {
+        match self {
+            IndexAccess::BTree(tree) => tree.insert(key, row),
+            IndexAccess::Hash(hash) => hash.insert(key, row),
+        }
+    }
+
+    pub fn delete(&self, key: &[Value], row: RowId) -> Result<bool, StorageError> {
+        match self {
+            IndexAccess::BTree(tree) => tree.delete(key, row),
+            IndexAccess::Hash(hash) => hash.delete(key, row),
+        }
+    }
+
+    /// Rows with the key.
+    pub fn get(&self, key: &[Value]) -> Result<Vec<RowId>, StorageError> {
+        match self {
+            IndexAccess::BTree(tree) => tree.get(key),
+            IndexAccess::Hash(hash) => hash.get(key),
+        }
+    }
+}
+
+/// An index over some of the columns of a table.
+pub struct Index {
+    name: String,
+    columns: Vec<usize>,
+    access: IndexAccess,
+    /// Lookups of versions through the index.
+    scans: AtomicU64,
+}
+
+impl Index {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Positions of the key columns in the table.
+    pub fn columns(&self) -> &[usize] {
+        &self.columns
+    }
+
+    pub fn access(&self) -> &IndexAccess {
+        &self.access
+    }
+
+    /// How often [`Table::lookup_in`] went through the index.
+    pub fn scans(&self) -> u64 {
+        self.scans.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Table {
+    pool: BufferPool,
+    schema: Schema,
+    heap: HeapFile,
+    overflow: OverflowFile,
+    indexes: Vec<Index>,
+    compression: bool,
+    /// Versions written by transactions since the last vacuum.
+    changes: AtomicU64,
+    /// Held to follow ids of versions, from an index or the version they replaced, and by vacuum to move index
+    /// entries and remove versions, so that none leads to a slot freed and reused in the meantime.
+    chains: RwLock<()>,
+}
+
+impl Table {
+    pub fn create(pool: BufferPool, schema: Schema) -> Result<Self, StorageError> {
+        Ok(Table {
+            schema,
+            heap: HeapFile::create(pool.clone())?,
+            overflow: OverflowFile::create(pool.clone())?,
+            indexes: Vec::new(),
+            compression: true,
+            changes: AtomicU64::new(0),
+            chains: RwLock::new(()),
+            pool,
+        })
+    }
+
+    /// Opens a table without its indexes, which are opened with [`Table::open_index`].
+    pub fn open(pool: BufferPool, schema: Schema, heap: FileId, overflow: FileId) -> Result<Self, StorageError> {
+        Ok(Table {
+            schema,
+            heap: HeapFile::open(pool.clone(), heap)?,
+            overflow: OverflowFile::open(pool.clone(), overflow)?,
+            indexes: Vec::new(),
+            compression: true,
+            changes: AtomicU64::new(0),
+            chains: RwLock::new(()),
+            pool,
+        })
+    }
+
+    /// Whether values stored out of line are compressed, which they are by default.
+    pub fn with_compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn heap_file(&self) -> FileId {
+        self.heap.file()
+    }
+
+    pub fn overflow_file(&self) -> FileId {
+        self.overflow.file()
+    }
+
+    pub fn indexes(&self) -> &[Index] {
+        &self.indexes
+    }
+
+    pub fn index(&self, name: &str) -> Option<&Index> {
+        self.indexes.iter().find(|index| index.name == name)
+    }
+
+    /// The index to find rows by the values of some columns with: one whose key columns are all among them,
+    /// preferring a hash index, then the most key columns.
+    pub fn equality_index(&self, columns: &[usize]) -> Option<&Index> {
+        self.indexes
+            .iter()
+            .filter(|index| index.columns.iter().all(|column| columns.contains(column)))
+            .max_by_key(|index| (index.access.method() == IndexMethod::Hash, index.columns.len()))
+    }
+
+    /// Indexes the rows by the values of `columns`, which fails when the index is unique and they are not.
+    pub fn create_index(
+        &mut self,
+        name: &str,
+        method: IndexMethod,
+        columns: Vec<usize>,
+        unique: bool,
+    ) -> Result<&Index, StorageError> {
+        let access = IndexAccess::create(self.pool.clone(), method, columns.len(), unique)?;
+        let index = Index {
+            name: name.to_string(),
+            columns,
+            access,
+            scans: AtomicU64::new(0),
+        };
+        let filled = self
+            .index_keys(&index)
+            .and_then(|keys| keys.iter().try_for_each(|(id, key)| index.access.insert(key, *id)));
+        if let Err(err) = filled {
+            self.remove_file(index.access.file())?;
+            return Err(err);
+        }
+
+        self.indexes.push(index);
+        Ok(self.indexes.last().unwrap())
+    }
+
+    /// Adds an index created earlier, stored in `file`.
+    pub fn open_index(&mut self, name: &str, columns: Vec<usize>, file: FileId) -> Result<&Index, StorageError> {
+        let access = IndexAccess::open(self.pool.clone(), file)?;
+        if access.columns() != columns.len() {
+            return Err(RowError::ColumnCount {
+                expected: access.columns(),
+                actual: columns.len(),
+            }
+            .into());
+        }
+        self.indexes.push(Index {
+            name: name.to_string(),
+            columns,
+            access,
+            scans: AtomicU64::new(0),
+        });
+        Ok(self.indexes.last().unwrap())
+    }
+
+    /// Drops the index and its file, returns whether it existed.
+    pub fn drop_index(&mut self, name: &str) -> Result<bool, StorageError> {
+        let Some(i) = self.indexes.iter().position(|index| index.name == name) else {
+            return Ok(false);
+        };
+        let index = self.indexes.remove(i);
+        self.remove_file(index.access.file())?;
+        Ok(true)
+    }
+
+    pub fn insert(&self, row: &[Value]) -> Result<RowId, StorageError> {
+        let (bytes, written) = self.encode(row)?;
+        self.insert_encoded(None, &bytes, &written, None)
+    }
+
+    /// Inserts a row whose value of `column`, a string or binary column, is read from `value` rather than taken
+    /// from `row`, and stored out of line as it is read.
+    pub fn insert_from(&self, row: &[Value], column: usize, value: &mut impl Read) -> Result<RowId, StorageError> {
+        let schema = &self.schema.columns()[column];
+        let (Some(max_len), false) = (schema.max_len(), row.len() != self.schema.columns().len()) else {
+            return Err(RowError::TypeMismatch(schema.name.clone(), schema.data_type.clone()).into());
+        };
+
+        let mut writer = self.overflow.writer(self.compression);
+        let mut text = schema.is_text().then(TextCheck::default);
+        let mut buf = vec![0; 64 * 1024];
+        let mut len = 0;
+        loop {
+            let n = match value.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+            len += n as u64;
+            if let Some(text) = &mut text {
+                text.push(&buf[..n])
+                    .ok_or_else(|| RowError::TypeMismatch(schema.name.clone(), schema.data_type.clone()))?;
+            }
+            let too_many_chars = match (&text, schema.max_chars()) {
+                (Some(text), Some(max)) => text.chars > max,
+                _ => false,
+            };
+            if len > max_len || too_many_chars {
+                return Err(RowError::TooLong(schema.name.clone(), schema.data_type.clone()).into());
+            }
+            writer.write_all(&buf[..n])?;
+        }
+        if text.is_some_and(|text| !text.pending.is_empty()) {
+            return Err(RowError::TypeMismatch(schema.name.clone(), schema.data_type.clone()).into());
+        }
+        let stored = writer.finish()?;
+
+        // The value is checked as it is read, the row holds an empty one of the right type in its place.
+        let mut row = row.to_vec();
+        row[column] = match schema.is_text() {
+            true => Value::Text(String::new()),
+            false => Value::Bytes(Vec::new()),
+        };
+        let mut written = vec![stored];
+        let bytes = self
+            .schema
+            .encode_with(&row, |i, contents| match i == column {
+                true => Ok(Some(stored)),
+                false => self.store(contents, MAX_INLINE_LEN, &mut written),
+            })
+            .or_else(|err| self.undo(&written, err))?;
+        self.insert_encoded(None, &bytes, &written, None)
+    }
+
+    pub fn get(&self, id: RowId) -> Result<Vec<Value>, StorageError> {
+        let row = self.heap.get(id)?;
+        self.decode(self.split(id, &row)?.1)
+    }
+
+    /// Reads the value of a column in pieces, `None` when it is NULL. Values other than strings and binary are
+    /// read in their text form.
+    pub fn read_value(&self, id: RowId, column: usize) -> Result<Option<ValueReader<'_>>, StorageError> {
+        let row = self.heap.get(id)?;
+        let (_, row) = self.split(id, &row)?;
+        if let Some(overflow) = self.schema.overflow(row, column)? {
+            return Ok(Some(ValueReader::Overflow(self.overflow.reader(overflow))));
+        }
+        let bytes = match self.schema.decode_column(row, column)? {
+            Value::Null => return Ok(None),
+            Value::Text(s) => s.into_bytes(),
+            Value::Bytes(bytes) => bytes,
+            value => value.to_string().into_bytes(),
+        };
+        Ok(Some(ValueReader::Inline(Cursor::new(bytes))))
+    }
+
+    /// Replaces the row, freeing the values it stored out of line.
+    pub fn update(&self, id: RowId, row: &[Value]) -> Result<(), StorageError> {
+        let stored = self.heap.get(id)?;
+        let (version, old_row) = self.split(id, &stored)?;
+        let old_keys = self.keys(old_row)?;
+        let (bytes, written) = self.encode(row)?;
+        let new_keys = match self.keys(&bytes) {
+            Ok(keys) => keys,
+            Err(err) => return self.undo(&written, err),
+        };
+
+        // New keys go in first, so that a unique index can reject them before anything changed.
+        let changed: Vec<usize> = (0..self.indexes.len()).filter(|i| old_keys[*i] != new_keys[*i]).collect();
+        for (n, i) in changed.iter().enumerate() {
+            if let Err(err) = self.indexes[*i].access.insert(&new_keys[*i], id) {
+                self.remove_keys(changed[..n].iter().map(|i| (*i, &new_keys[*i])), id)?;
+                return self.undo(&written, err);
+            }
+        }
+        let old = match self.heap.update(id, &version.with_row(&bytes)) {
+            Ok(old) => old,
+            Err(err) => {
+                self.remove_keys(changed.iter().map(|i| (*i, &new_keys[*i])), id)?;
+                return self.undo(&written, err);
+            }
+        };
+        self.remove_keys(changed.iter().map(|i| (*i, &old_keys[*i])), id)?;
+        self.free(&self.schema.overflows(self.split(id, &old)?.1)?)
+    }
+
+    /// Deletes the row along with the values it stored out of line and its keys.
+    pub fn delete(&self, id: RowId) -> Result<(), StorageError> {
+        let old = self.heap.delete(id)?;
+        let (_, old) = self.split(id, &old)?;
+        let keys = self.keys(old)?;
+        self.remove_keys(keys.iter().enumerate(), id)?;
+        self.free(&self.schema.overflows(old)?)
+    }
+
+    /// Every version of every row.
+    pub fn scan(&self) -> impl Iterator<Item = Result<(RowId, Vec<Value>), StorageError>> + '_ {
+        self.heap.scan().map(|row| {
+            let (id, row) = row?;
+            Ok((id, self.decode(self.split(id, &row)?.1)?))
+        })
+    }
+
+    /// Inserts a row as a version created by the transaction.
+    pub fn insert_in(&self, txn: &Transaction, row: &[Value]) -> Result<RowId, StorageError> {
+        let xid = txn.write(self.heap_file(), None)?;
+        let (bytes, written) = self.encode(row)?;
+        let id = self.insert_encoded(Some((txn, xid)), &bytes, &written, None)?;
+        self.changes.fetch_add(1, Ordering::Relaxed);
+        Ok(id)
+    }
+
+    /// The version the transaction sees of the row at `id`, which may have been replaced since, with its id.
+    pub fn get_in(&self, txn: &Transaction, id: RowId) -> Result<Option<(RowId, Vec<Value>)>, StorageError> {
+        let _chains = self.chains.read().unwrap();
+        let Some((id, row)) = self.visible(txn, id)? else {
+            return Ok(None);
+        };
+        txn.read(self.heap_file(), Some(id));
+        Ok(Some((id, self.decode(self.split(id, &row)?.1)?)))
+    }
+
+    /// The versions the transaction sees.
+    pub fn scan_in<'t>(
+        &'t self,
+        txn: &'t Transaction,
+    ) -> impl Iterator<Item = Result<(RowId, Vec<Value>), StorageError>> + 't {
+        txn.read(self.heap_file(), None);
+        self.heap.scan().filter_map(move |row| {
+            let visible = row.and_then(|(id, row)| {
+                let (version, row) = self.split(id, &row)?;
+                match txn.sees(&version)? {
+                    true => Ok(Some((id, self.decode(row)?))),
+                    false => Ok(None),
+                }
+            });
+            visible.transpose()
+        })
+    }
+
+    /// The versions the transaction sees with `key` in the index.
+    pub fn lookup_in(
+        &self,
+        txn: &Transaction,
+        index: &Index,
+        key: &[Value],
+    ) -> Result<Vec<(RowId, Vec<Value>)>, StorageError> {
+        txn.read(self.heap_file(), None);
+        index.scans.fetch_add(1, Ordering::Relaxed);
+        let _chains = self.chains.read().unwrap();
+        let mut rows: Vec<(RowId, Vec<Value>)> = Vec::new();
+        for id in index.access.get(key)? {
+            let Some((id, row)) = self.visible(txn, id)? else {
+                continue;
+            };
+            // Versions that replaced the one in the index may have another key.
+            let (_, row) = self.split(id, &row)?;
+            if self.key(index, row)? == key && !rows.iter().any(|(found, _)| *found == id) {
+                rows.push((id, self.decode(row)?));
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Replaces the version at `id`, one the transaction sees, with a new version of the row, and returns where
+    /// that is. The values the old version stored out of line stay with it.
+    pub fn update_in(&self, txn: &Transaction, id: RowId, row: &[Value]) -> Result<RowId, StorageError> {
+        let xid = txn.write(self.heap_file(), Some(id))?;
+        let (before, stored) = self.stamp(txn, id)?;
+        let inserted = self.keys(self.split(id, &stored)?.1).and_then(|old_keys| {
+            let (bytes, written) = self.encode(row)?;
+            self.insert_encoded(Some((txn, xid)), &bytes, &written, Some(&old_keys))
+        });
+        let new = match inserted {
+            Ok(new) => new,
+            Err(err) => {
+                self.set_version(id, before)?;
+                return Err(err);
+            }
+        };
+        self.set_version(
+            id,
+            Version {
+                xmax: xid,
+                next: Some(new),
+                ..before
+            },
+        )?;
+        self.changes.fetch_add(1, Ordering::Relaxed);
+        Ok(new)
+    }
+
+    /// Deletes the version at `id`, one the transaction sees.
+    pub fn delete_in(&self, txn: &Transaction, id: RowId) -> Result<(), StorageError> {
+        txn.write(self.heap_file(), Some(id))?;
+        self.stamp(txn, id)?;
+        self.changes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Versions transactions wrote since the table was last vacuumed, each of which may leave a dead one behind.
+    pub fn changes(&self) -> u64 {
+        self.changes.load(Ordering::Relaxed)
+    }
+
+    /// Removes the versions no transaction can see any more along with the values they stored out of line, and
+    /// compacts the pages they were in. The index entries of a dead version go to the first version that replaced
+    /// it with the same key and is still there, or are removed. The versions left are
+    /// [frozen](TransactionManager::freeze). Tells `progress` how far it got as it goes.
+    pub fn vacuum(
+        &self,
+        transactions: &TransactionManager,
+        progress: &mut dyn FnMut(&VacuumProgress),
+    ) -> Result<VacuumStats, StorageError> {
+        let changes = self.changes();
+        let horizon = transactions.horizon();
+        let mut state = VacuumProgress {
+            phase: VacuumPhase::ScanningHeap,
+            pages_total: self.heap.page_count(),
+            pages_scanned: 0,
+            dead_versions: 0,
+        };
+        progress(&state);
+
+        // Dead versions with their keys, the versions still there that were replaced, and those to freeze.
+        let mut dead: BTreeMap<RowId, (Version, Vec<Vec<Value>>)> = BTreeMap::new();
+        let mut replaced = Vec::new();
+        let mut frozen = Vec::new();
+        for row in self.heap.scan() {
+            let (id, row) = row?;
+            if id.page >= state.pages_total {
+                break;
+            }
+            if id.page > state.pages_scanned {
+                state.pages_scanned = id.page;
+                progress(&state);
+            }
+            let (version, row) = self.split(id, &row)?;
+            if transactions.is_dead(&version, horizon) {
+                dead.insert(id, (version, self.keys(row)?));
+                state.dead_versions += 1;
+                continue;
+            }
+            if let Some(next) = version.next {
+                replaced.push((id, next));
+            }
+            if transactions.freeze(&mut version.clone(), horizon) {
+                frozen.push(id);
+            }
+        }
+        state.pages_scanned = state.pages_total;
+        state.phase = VacuumPhase::VacuumingIndexes;
+        progress(&state);
+
+        let mut stats = VacuumStats {
+            pages: state.pages_total,
+            horizon,
+            ..VacuumStats::default()
+        };
+        // Lookups would miss the entries being moved.
+        let chains = self.chains.write().unwrap();
+        for (id, (version, keys)) in &dead {
+            for (i, index) in self.indexes.iter().enumerate() {
+                if !index.access.delete(&keys[i], *id)? {
+                    continue;
+                }
+                let moved = match self.live_successor(&dead, version, i, &keys[i])? {
+                    Some(successor) => match index.access.insert(&keys[i], successor) {
+                        // Another row took the key over.
+                        Err(StorageError::DuplicateKey(_)) => false,
+                        inserted => inserted.map(|()| true)?,
+                    },
+                    None => false,
+                };
+                if !moved {
+                    stats.index_entries += 1;
+                }
+            }
+        }
+
+        state.phase = VacuumPhase::VacuumingHeap;
+        progress(&state);
+        for (id, next) in replaced.into_iter().filter(|(_, next)| dead.contains_key(next)) {
+            let mut successor = Some(next);
+            while let Some((version, _)) = successor.and_then(|successor| dead.get(&successor)) {
+                successor = version.next;
+            }
+            self.heap.modify(id, |row| {
+                let mut version = self.split(id, row)?.0;
+                // Unless it was replaced again since.
+                if version.next == Some(next) {
+                    version.next = successor;
+                    if successor.is_none() && transactions.known_status(version.xmax) == Some(TxnStatus::Aborted) {
+                        version.xmax = 0;
+                    }
+                    version.encode(row);
+                }
+                Ok(())
+            })?;
+        }
+        for id in frozen {
+            // As it is now, it may have been deleted since.
+            let changed = self.heap.modify(id, |row| {
+                let mut version = self.split(id, row)?.0;
+                let changed = transactions.freeze(&mut version, horizon);
+                version.encode(row);
+                Ok(changed)
+            })?;
+            stats.frozen += changed as u64;
+        }
+        let mut pages = BTreeSet::new();
+        for id in dead.keys() {
+            let row = self.heap.delete(*id)?;
+            self.free(&self.schema.overflows(self.split(*id, &row)?.1)?)?;
+            pages.insert(id.page);
+            stats.versions += 1;
+        }
+        for page in &pages {
+            self.heap.compact(*page)?;
+        }
+        drop(chains);
+        stats.compacted = pages.len() as u32;
+
+        let _ = self
+            .changes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| Some(n.saturating_sub(changes)));
+        Ok(stats)
+    }
+
+    /// Encodes a row, storing its large values out of line. When the row still does not fit in a page, every
+    /// value that is not shorter than the reference to it goes out of line.
+    fn encode(&self, row: &[Value]) -> Result<(Vec<u8>, Vec<Overflow>), StorageError> {
+        for max_inline_len in [MAX_INLINE_LEN, 0] {
+            let mut written = Vec::new();
+            let bytes = self
+                .schema
+                .encode_with(row, |_, contents| self.store(contents, max_inline_len, &mut written))
+                .or_else(|err| self.undo(&written, err))?;
+            if bytes.len() + VERSION_LEN <= MAX_ROW_LEN || max_inline_len == 0 {
+                return Ok((bytes, written));
+            }
+            self.free(&written)?;
+        }
+        unreachable!()
+    }
+
+    fn store(
+        &self,
+        contents: &[u8],
+        max_inline_len: usize,
+        written: &mut Vec<Overflow>,
+    ) -> Result<Option<Overflow>, StorageError> {
+        // A reference takes 12 bytes.
+        if contents.len() <= max_inline_len.max(12) {
+            return Ok(None);
+        }
+        let stored = self.overflow.write(contents, self.compression)?;
+        written.push(stored);
+        Ok(Some(stored))
+    }
+
+    /// Stores an encoded row and its keys, or nothing when an index rejects it, as a version created by the
+    /// transaction with its id. A version replacing another one leaves the keys it has in common with it,
+    /// `replaced`, to the entries of that one.
+    fn insert_encoded(
+        &self,
+        txn: Option<(&Transaction, Xid)>,
+        bytes: &[u8],
+        written: &[Overflow],
+        replaced: Option<&[Vec<Value>]>,
+    ) -> Result<RowId, StorageError> {
+        let keys = match self.keys(bytes) {
+            Ok(keys) => keys,
+            Err(err) => return self.undo(written, err),
+        };
+        let version = Version::new(txn.map_or(FROZEN_XID, |(_, xid)| xid));
+        let id = self
+            .heap
+            .insert(&version.with_row(bytes))
+            .or_else(|err| self.undo(written, err))?;
+        let mut added = Vec::new();
+        for (i, (index, key)) in self.indexes.iter().zip(&keys).enumerate() {
+            if replaced.is_some_and(|replaced| replaced[i] == *key) {
+                continue;
+            }
+            let inserted = match txn {
+                Some((txn, _)) => self.insert_key_in(txn, index, key, id),
+                None => index.access.insert(key, id),
+            };
+            if let Err(err) = inserted {
+                self.remove_keys(added.into_iter().map(|i| (i, &keys[i])), id)?;
+                self.heap.delete(id)?;
+                return self.undo(written, err);
+            }
+            added.push(i);
+        }
+        Ok(id)
+    }
+
+    /// Adds the key of a version written by the transaction. The entry of a unique key goes to the new version
+    /// when no other row still has the key.
+    fn insert_key_in(&self, txn: &Transaction, index: &Index, key: &[Value], id: RowId) -> Result<(), StorageError> {
+        match index.access.insert(key, id) {
+            Err(StorageError::DuplicateKey(_)) => {
+                let _chains = self.chains.read().unwrap();
+                for other in index.access.get(key)? {
+                    if self.holds_key(txn, index, key, other)? {
+                        return Err(StorageError::DuplicateKey(key.to_vec()));
+                    }
+                    index.access.delete(key, other)?;
+                }
+                index.access.insert(key, id)
+            }
+            inserted => inserted,
+        }
+    }
+
+    /// Whether the latest version of the row at `id` has the key. Fails when that depends on a transaction in
+    /// progress. The caller holds `chains`.
+    fn holds_key(&self, txn: &Transaction, index: &Index, key: &[Value], mut id: RowId) -> Result<bool, StorageError> {
+        let (version, row) = loop {
+            let row = self.heap.get(id)?;
+            let version = self.split(id, &row)?.0;
+            match version.next {
+                Some(next) if txn.status(version.xmax) != TxnStatus::Aborted => id = next,
+                _ => break (version, row),
+            }
+        };
+        let status = |xid| match Some(xid) == txn.xid() {
+            true => TxnStatus::Committed,
+            false => txn.status(xid),
+        };
+        let deleted = match version.xmax {
+            0 => TxnStatus::Aborted,
+            xmax => status(xmax),
+        };
+        if status(version.xmin) == TxnStatus::Aborted
+            || deleted == TxnStatus::Committed
+            || self.key(index, self.split(id, &row)?.1)? != key
+        {
+            return Ok(false);
+        }
+        if status(version.xmin) == TxnStatus::Active || deleted == TxnStatus::Active {
+            return Err(StorageError::SerializationFailure("concurrent update".to_string()));
+        }
+        Ok(true)
+    }
+
+    /// The version the transaction sees among the one at `id` and those that replaced it. The caller holds
+    /// `chains`.
+    fn visible(&self, txn: &Transaction, mut id: RowId) -> Result<Option<(RowId, Vec<u8>)>, StorageError> {
+        loop {
+            let row = match self.heap.get(id) {
+                // Vacuumed, so no transaction sees it.
+                Err(StorageError::UnknownRow(_)) => return Ok(None),
+                row => row?,
+            };
+            let (version, _) = self.split(id, &row)?;
+            if txn.sees(&version)? {
+                return Ok(Some((id, row)));
+            }
+            match version.next {
+                Some(next) => id = next,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// The first version still there that replaced a dead one, provided that every version up to it has the key
+    /// of the index at `i`.
+    fn live_successor(
+        &self,
+        dead: &BTreeMap<RowId, (Version, Vec<Vec<Value>>)>,
+        version: &Version,
+        i: usize,
+        key: &[Value],
+    ) -> Result<Option<RowId>, StorageError> {
+        let mut next = version.next;
+        while let Some(id) = next {
+            match dead.get(&id) {
+                Some((version, keys)) if keys[i] == key => next = version.next,
+                Some(_) => return Ok(None),
+                None => {
+                    let row = self.heap.get(id)?;
+                    let held = self.key(&self.indexes[i], self.split(id, &row)?.1)? == key;
+                    return Ok(held.then_some(id));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Marks the version at `id` deleted by the transaction, returns its header from before and the row.
+    fn stamp(&self, txn: &Transaction, id: RowId) -> Result<(Version, Vec<u8>), StorageError> {
+        self.heap.modify(id, |row| {
+            let before = self.split(id, row)?.0;
+            let mut after = before;
+            txn.delete(id, &mut after)?;
+            after.encode(row);
+            Ok((before, row.to_vec()))
+        })
+    }
+
+    fn set_version(&self, id: RowId, version: Version) -> Result<(), StorageError> {
+        self.heap.modify(id, |row| {
+            version.encode(row);
+            Ok(())
+        })
+    }
+
+    /// The version header of a row as stored, and the encoded row after it.
+    fn split<'r>(&self, id: RowId, row: &'r [u8]) -> Result<(Version, &'r [u8]), StorageError> {
+        match Version::decode(row) {
+            Some(version) => Ok((version, &row[VERSION_LEN..])),
+            None => Err(StorageError::Corrupt(
+                PageId::new(self.heap_file(), id.page),
+                format!("row {id} has no version header"),
+            )),
+        }
+    }
+
+    /// The key in the index of every row, but for versions with the key of the one they replaced.
+    fn index_keys(&self, index: &Index) -> Result<Vec<(RowId, Vec<Value>)>, StorageError> {
+        let mut keys = HashMap::new();
+        let mut replaced = Vec::new();
+        for row in self.heap.scan() {
+            let (id, row) = row?;
+            let (version, row) = self.split(id, &row)?;
+            let key = self.key(index, row)?;
+            if let Some(next) = version.next {
+                replaced.push((next, key.clone()));
+            }
+            keys.insert(id, key);
+        }
+        for (next, key) in replaced {
+            if keys.get(&next) == Some(&key) {
+                keys.remove(&next);
+            }
+        }
+        let mut keys: Vec<(RowId, Vec<Value>)> = keys.into_iter().collect();
+        keys.sort_unstable_by_key(|(id, _)| *id);
+        Ok(keys)
+    }
+
+    /// The key of the row in every index.
+    fn keys(&self, row: &[u8]) -> Result<Vec<Vec<Value>>, StorageError> {
+        self.indexes.iter().map(|index| self.key(index, row)).collect()
+    }
+
+    fn key(&self, index: &Index, row: &[u8]) -> Result<Vec<Value>, StorageError> {
+        index
+            .columns
+            .iter()
+            .map(|i| {
+                self.schema
+                    .decode_column_with(row, *i, |overflow| self.overflow.read(overflow))
+            })
+            .collect()
+    }
+
+    /// Removes keys of the row, by position of their index.
+    fn remove_keys<'k>(&self, keys: impl Iterator<Item = (usize, &'k Vec<Value>)>, id: RowId) -> Result<(), StorageError> {
+        for (i, key) in keys {
+            self.indexes[i].access.delete(key, id)?;
+        }
+        Ok(())
+    }
+
+    fn remove_file(&self, file: FileId) -> Result<(), StorageError> {
+        self.pool.discard_file(file);
+        self.pool.files().remove_file(file)
+    }
+
+    fn decode(&self, row: &[u8]) -> Result<Vec<Value>, StorageError> {
+        self.schema.decode_with(row, |overflow| self.overflow.read(overflow))
+    }
+
+    fn free(&self, values: &[Overflow]) -> Result<(), StorageError> {
+        values.iter().try_for_each(|value| self.overflow.delete(*value))
+    }
+
+    /// Frees the values written for a row that was not stored, and fails with `err`.
+    fn undo<T>(&self, written: &[Overflow], err: StorageError) -> Result<T, StorageError> {
+        self.free(written)?;
+        Err(err)
+    }
+}
+
+/// Counts the characters of text that arrives in pieces, which may split a character.
+#[derive(Default)]
+struct TextCheck {
+    pending: Vec<u8>,
+    chars: usize,
+}
+
+impl TextCheck {
+    /// Adds the next piece, `None` when it is not UTF-8.
+    fn push(&mut self, bytes: &[u8]) -> Option<()> {
+        self.pending.extend_from_slice(bytes);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(s) => s,
+            // The end of the piece may be the start of a character.
+            Err(err) if err.error_len().is_none() => std::str::from_utf8(&self.pending[..err.valid_up_to()]).unwrap(),
+            Err(_) => return None,
+        };
+        self.chars += valid.chars().count();
+        let len = valid.len();
+        self.pending.drain(..len);
+        Some(())
+    }
+}
+
+/// A value read in pieces.
+pub enum ValueReader<'a> {
+    Inline(Cursor<Vec<u8>>),
+    Overflow(OverflowReader<'a>),
+}
+
+impl Read for ValueReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            ValueReader::Inline(cursor) => cursor.read(buf),
+            ValueReader::Overflow(reader) => reader.read(buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parser::ast::IsolationLevel;
+    use crate::parser::token::DataKind;
+    use crate::storage::btree::ScanDirection;
+    use crate::storage::buffer::EvictionPolicyKind;
+    use crate::storage::file::FileManager;
+    use crate::storage::mvcc::TransactionManager;
+    use crate::storage::row::ColumnSchema;
+    use crate::storage::testing::TempDir;
+    use std::ops::Bound;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+
+    fn table(dir: &TempDir) -> (BufferPool, Table) {
+        let pool = BufferPool::new(
+            Arc::new(FileManager::open(dir.path()).unwrap()),
+            16,
+            EvictionPolicyKind::Clock,
+        );
+        let schema = Schema::new(vec![
+            ColumnSchema::new("id", DataKind::Integer(None), false),
+            ColumnSchema::new("body", DataKind::LongText(None), true),
+            ColumnSchema::new("data", DataKind::LongBlob(None), true),
+        ])
+        .unwrap();
+        (pool.clone(), Table::create(pool, schema).unwrap())
+    }
+
+    fn overflow_pages(pool: &BufferPool, table: &Table) -> u32 {
+        pool.files().page_count(table.overflow_file()).unwrap()
+    }
+
+    #[test]
+    fn test_large_values() {
+        let dir = TempDir::new();
+        let (pool, table) = table(&dir);
+
+        let body = "lorem ipsum dolor sit amet ".repeat(1000);
+        let data: Vec<u8> = (0..50_000u32).map(|n| (n * 7919 % 251) as u8).collect();
+        let row = vec![Value::Int(1), Value::Text(body.clone()), Value::Bytes(data.clone())];
+        let id = table.insert(&row).unwrap();
+        assert_eq!(table.get(id).unwrap(), row);
+        let small = vec![Value::Int(2), Value::Text("short".to_string()), Value::Null];
+        let small_id = table.insert(&small).unwrap();
+        assert_eq!(table.scan().map(|row| row.unwrap().1).collect::<Vec<_>>(), vec![row, small]);
+
+        let mut read = String::new();
+        table.read_value(id, 1).unwrap().unwrap().read_to_string(&mut read).unwrap();
+        assert_eq!(read, body);
+        let mut read = String::new();
+        table
+            .read_value(small_id, 1)
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, "short");
+        assert!(table.read_value(small_id, 2).unwrap().is_none());
+
+        // The pages of replaced and deleted values are reused.
+        let row = vec![Value::Int(1), Value::Text(body.to_uppercase()), Value::Null];
+        table.update(id, &row).unwrap();
+        assert_eq!(table.get(id).unwrap(), row);
+        let pages = overflow_pages(&pool, &table);
+        table
+            .insert(&[Value::Int(3), Value::Null, Value::Bytes(data.clone())])
+            .unwrap();
+        assert_eq!(overflow_pages(&pool, &table), pages);
+        table.delete(id).unwrap();
+        assert!(matches!(table.get(id), Err(StorageError::UnknownRow(_))));
+        table.insert(&[Value::Int(4), Value::Text(body), Value::Null]).unwrap();
+        assert_eq!(overflow_pages(&pool, &table), pages);
+    }
+
+    #[test]
+    fn test_streaming() {
+        let dir = TempDir::new();
+        let (_, table) = table(&dir);
+        let table = table.with_compression(false);
+
+        // Far larger than the pool, never in memory all at once.
+        let mut value = std::io::repeat(b'z').take(10_000_000);
+        let id = table
+            .insert_from(&[Value::Int(1), Value::Null, Value::Null], 2, &mut value)
+            .unwrap();
+        let mut reader = table.read_value(id, 2).unwrap().unwrap();
+        let mut buf = vec![0; 100_000];
+        let mut len = 0;
+        while let n @ 1.. = reader.read(&mut buf).unwrap() {
+            assert!(buf[..n].iter().all(|b| *b == b'z'));
+            len += n;
+        }
+        assert_eq!(len, 10_000_000);
+        drop(reader);
+        table.delete(id).unwrap();
+
+        // Text is checked as it is read, even when a character is split between reads.
+        let text = "é".repeat(100_000);
+        let id = table
+            .insert_from(&[Value::Int(2), Value::Null, Value::Null], 1, &mut text.as_bytes())
+            .unwrap();
+        assert_eq!(table.get(id).unwrap()[1], Value::Text(text));
+        let mut invalid = &b"abc\xff"[..];
+        assert!(matches!(
+            table.insert_from(&[Value::Int(3), Value::Null, Value::Null], 1, &mut invalid),
+            Err(StorageError::Row(RowError::TypeMismatch(..)))
+        ));
+        assert!(matches!(
+            table.insert_from(&[Value::Int(3), Value::Null, Value::Null], 0, &mut &b"1"[..]),
+            Err(StorageError::Row(RowError::TypeMismatch(..)))
+        ));
+    }
+
+    #[test]
+    fn test_indexes() {
+        let dir = TempDir::new();
+        let (pool, mut table) = table(&dir);
+        let row = |id: i64, body: &str| vec![Value::Int(id), Value::Text(body.to_string()), Value::Null];
+        let a = table.insert(&row(1, "one")).unwrap();
+        table.insert(&row(2, "two")).unwrap();
+        table.insert(&row(3, "two")).unwrap();
+
+        // Existing rows are indexed, a unique index over duplicate values is not created.
+        let files = pool.files().clone();
+        table.create_index("by_id", IndexMethod::Hash, vec![0], true).unwrap();
+        let next_file = files.create_file().unwrap();
+        files.remove_file(next_file).unwrap();
+        assert!(matches!(
+            table.create_index("by_body", IndexMethod::BTree, vec![1], true),
+            Err(StorageError::DuplicateKey(_))
+        ));
+        assert!(files.page_count(next_file).is_err());
+        table.create_index("by_body", IndexMethod::BTree, vec![1, 0], false).unwrap();
+        let IndexAccess::BTree(by_body) = table.index("by_body").unwrap().access() else {
+            panic!("by_body is a b-tree");
+        };
+        let two = by_body.prefix_scan(&[Value::Text("two".to_string())], ScanDirection::Forward);
+        assert_eq!(
+            two.map(|entry| entry.unwrap().0[1].clone()).collect::<Vec<_>>(),
+            [Value::Int(2), Value::Int(3)]
+        );
+
+        // A rejected insert leaves neither the row nor its other keys behind.
+        let large = "x".repeat(10_000);
+        let pages = overflow_pages(&pool, &table);
+        assert!(matches!(
+            table.insert(&row(1, &large)),
+            Err(StorageError::DuplicateKey(key)) if key == [Value::Int(1)]
+        ));
+        assert_eq!(table.scan().count(), 3);
+        assert_eq!(
+            by_body
+                .scan(Bound::Unbounded, Bound::Unbounded, ScanDirection::Forward)
+                .count(),
+            3
+        );
+        table.insert(&row(4, "")).unwrap();
+        assert_eq!(overflow_pages(&pool, &table), pages + 1);
+
+        // Updates move keys, unless a unique index rejects them.
+        // Lookups by the id and the body use the hash index.
+        assert_eq!(table.equality_index(&[1, 0]).map(Index::name), Some("by_id"));
+        assert_eq!(table.equality_index(&[1, 2]).map(Index::name), None);
+        let by_id = table.index("by_id").unwrap().access();
+        table.update(a, &row(10, "ten")).unwrap();
+        assert_eq!(by_id.get(&[Value::Int(1)]).unwrap(), vec![]);
+        assert_eq!(by_id.get(&[Value::Int(10)]).unwrap(), vec![a]);
+        assert!(table.update(a, &row(2, "ten")).is_err());
+        assert_eq!(table.get(a).unwrap(), row(10, "ten"));
+        assert_eq!(by_id.get(&[Value::Int(10)]).unwrap(), vec![a]);
+
+        table.delete(a).unwrap();
+        assert_eq!(by_id.get(&[Value::Int(10)]).unwrap(), vec![]);
+        assert!(table.drop_index("by_body").unwrap());
+        assert!(!table.drop_index("by_body").unwrap());
+        assert_eq!(table.indexes().len(), 1);
+    }
+
+    #[test]
+    fn test_failed_writes() {
+        let dir = TempDir::new();
+        let (pool, table) = table(&dir);
+
+        // Values written for a row that fails to encode are freed, and take the same page every time.
+        let body = Value::Text("x".repeat(100_000));
+        table.insert(&[Value::Int(1), body.clone(), Value::Null]).unwrap();
+        let pages = overflow_pages(&pool, &table);
+        for _ in 0..10 {
+            assert!(matches!(
+                table.insert(&[Value::Int(2), body.clone(), Value::Float(1.0)]),
+                Err(StorageError::Row(RowError::TypeMismatch(..)))
+            ));
+        }
+        assert_eq!(overflow_pages(&pool, &table), pages + 1);
+    }
+
+    #[test]
+    fn test_versions() {
+        let dir = TempDir::new();
+        let (_, mut table) = table(&dir);
+        table.create_index("by_id", IndexMethod::BTree, vec![0], true).unwrap();
+        let row = |id: i64, body: &str| vec![Value::Int(id), Value::Text(body.to_string()), Value::Null];
+        let manager = TransactionManager::new();
+        let setup = manager.begin(IsolationLevel::ReadCommitted);
+        let a = table.insert_in(&setup, &row(1, "one")).unwrap();
+        setup.commit().unwrap();
+
+        // A snapshot keeps seeing the version from before an update, the index leads to both.
+        let reader = manager.begin(IsolationLevel::RepeatableRead);
+        assert_eq!(table.get_in(&reader, a).unwrap(), Some((a, row(1, "one"))));
+        let writer = manager.begin(IsolationLevel::ReadCommitted);
+        let b = table.update_in(&writer, a, &row(1, "uno")).unwrap();
+        assert_eq!(table.get_in(&writer, a).unwrap(), Some((b, row(1, "uno"))));
+        let index = table.index("by_id").unwrap();
+        assert_eq!(index.access().get(&[Value::Int(1)]).unwrap(), vec![a]);
+        writer.commit().unwrap();
+        assert_eq!(
+            table.lookup_in(&reader, index, &[Value::Int(1)]).unwrap(),
+            vec![(a, row(1, "one"))]
+        );
+        assert_eq!(table.scan().count(), 2);
+        assert_eq!(table.scan_in(&reader).count(), 1);
+
+        // The reader cannot update the version replaced after its snapshot.
+        assert!(matches!(
+            table.update_in(&reader, a, &row(1, "eins")),
+            Err(StorageError::SerializationFailure(_))
+        ));
+        drop(reader);
+
+        // A deleted key can be inserted again, but not while the delete is in progress.
+        let deleter = manager.begin(IsolationLevel::ReadCommitted);
+        table.delete_in(&deleter, b).unwrap();
+        let inserter = manager.begin(IsolationLevel::ReadCommitted);
+        assert!(matches!(
+            table.insert_in(&inserter, &row(1, "again")),
+            Err(StorageError::SerializationFailure(_))
+        ));
+        deleter.commit().unwrap();
+        let inserter = manager.begin(IsolationLevel::ReadCommitted);
+        let c = table.insert_in(&inserter, &row(1, "again")).unwrap();
+        assert_eq!(table.index("by_id").unwrap().access().get(&[Value::Int(1)]).unwrap(), vec![c]);
+        assert!(matches!(
+            table.insert_in(&inserter, &row(1, "twice")),
+            Err(StorageError::DuplicateKey(_))
+        ));
+        inserter.commit().unwrap();
+
+        // An index created later leaves out the versions sharing the entry of the one they replaced.
+        let updater = manager.begin(IsolationLevel::ReadCommitted);
+        let d = table.update_in(&updater, c, &row(1, "again")).unwrap();
+        updater.commit().unwrap();
+        table.create_index("by_body", IndexMethod::BTree, vec![1], false).unwrap();
+        let by_body = table.index("by_body").unwrap();
+        assert_eq!(by_body.access().get(&[Value::Text("again".to_string())]).unwrap(), vec![c]);
+        let reader = manager.begin(IsolationLevel::ReadCommitted);
+        assert_eq!(
+            table
+                .lookup_in(&reader, by_body, &[Value::Text("again".to_string())])
+                .unwrap(),
+            vec![(d, row(1, "again"))]
+        );
+    }
+
+    #[test]
+    fn test_vacuum() {
+        let dir = TempDir::new();
+        let (_, mut table) = table(&dir);
+        table.create_index("by_id", IndexMethod::BTree, vec![0], true).unwrap();
+        table.create_index("by_body", IndexMethod::Hash, vec![1], false).unwrap();
+        let row = |id: i64, body: &str| vec![Value::Int(id), Value::Text(body.to_string()), Value::Null];
+        let text = |body: &str| vec![Value::Text(body.to_string())];
+        let manager = TransactionManager::new();
+        let setup = manager.begin(IsolationLevel::ReadCommitted);
+        let a = table.insert_in(&setup, &row(1, "one")).unwrap();
+        let b = table.insert_in(&setup, &row(2, "two")).unwrap();
+        setup.commit().unwrap();
+        assert_eq!(table.changes(), 2);
+
+        // Versions a snapshot may still see stay, those of rolled back transactions go.
+        let reader = manager.begin(IsolationLevel::RepeatableRead);
+        reader.snapshot();
+        let writer = manager.begin(IsolationLevel::ReadCommitted);
+        let a2 = table.update_in(&writer, a, &row(1, "uno")).unwrap();
+        table.delete_in(&writer, b).unwrap();
+        writer.commit().unwrap();
+        let aborted = manager.begin(IsolationLevel::ReadCommitted);
+        table.insert_in(&aborted, &row(3, "three")).unwrap();
+        drop(aborted);
+        let stats = table.vacuum(&manager, &mut |_| {}).unwrap();
+        assert_eq!((stats.versions, stats.index_entries, stats.compacted), (1, 2, 1));
+        let by_id = table.index("by_id").unwrap();
+        assert_eq!(
+            table.lookup_in(&reader, by_id, &[Value::Int(1)]).unwrap(),
+            vec![(a, row(1, "one"))]
+        );
+        assert_eq!(table.scan_in(&reader).count(), 2);
+        assert_eq!(table.changes(), 0);
+        drop(reader);
+
+        // Then they go too. The entry the update shared moves to the version that replaced it.
+        let mut phases = Vec::new();
+        let stats = table.vacuum(&manager, &mut |progress| phases.push(progress.phase)).unwrap();
+        phases.dedup();
+        assert_eq!(
+            phases,
+            [
+                VacuumPhase::ScanningHeap,
+                VacuumPhase::VacuumingIndexes,
+                VacuumPhase::VacuumingHeap
+            ]
+        );
+        assert_eq!((stats.versions, stats.index_entries), (2, 3));
+        assert_eq!(by_id.access().get(&[Value::Int(1)]).unwrap(), vec![a2]);
+        let by_body = table.index("by_body").unwrap();
+        assert!(by_body.access().get(&text("one")).unwrap().is_empty());
+        assert_eq!(by_body.access().get(&text("uno")).unwrap(), vec![a2]);
+        let reader = manager.begin(IsolationLevel::ReadCommitted);
+        assert_eq!(
+            table.lookup_in(&reader, by_id, &[Value::Int(1)]).unwrap(),
+            vec![(a2, row(1, "uno"))]
+        );
+        assert_eq!(table.scan().count(), 1);
+
+        // A version that a rolled back update replaced is the latest one again, and frozen.
+        let aborted = manager.begin(IsolationLevel::ReadCommitted);
+        table.update_in(&aborted, a2, &row(1, "ein")).unwrap();
+        drop(aborted);
+        table.vacuum(&manager, &mut |_| {}).unwrap();
+        let stored = table.heap.get(a2).unwrap();
+        assert_eq!(table.split(a2, &stored).unwrap().0, Version::new(FROZEN_XID));
+        assert_eq!(table.get_in(&reader, a2).unwrap(), Some((a2, row(1, "uno"))));
+    }
+
+    #[test]
+    fn test_vacuum_while_reading() {
+        let dir = TempDir::new();
+        let (_, mut table) = table(&dir);
+        table.create_index("by_id", IndexMethod::BTree, vec![0], true).unwrap();
+        let row = |id: i64, body: String| vec![Value::Int(id), Value::Text(body), Value::Null];
+        let manager = TransactionManager::new();
+        let setup = manager.begin(IsolationLevel::ReadCommitted);
+        let mut latest = table.insert_in(&setup, &row(1, "0".to_string())).unwrap();
+        setup.commit().unwrap();
+
+        // Lookups keep finding the row while its versions are removed and their slots taken by other rows.
+        let done = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            let reader = scope.spawn(|| {
+                let by_id = table.index("by_id").unwrap();
+                while !done.load(Ordering::Relaxed) {
+                    let txn = manager.begin(IsolationLevel::ReadCommitted);
+                    let found = table.lookup_in(&txn, by_id, &[Value::Int(1)]).unwrap();
+                    assert_eq!(found.len(), 1);
+                    assert_eq!(found[0].1[0], Value::Int(1));
+                }
+            });
+            for i in 1..200 {
+                let txn = manager.begin(IsolationLevel::ReadCommitted);
+                latest = table.update_in(&txn, latest, &row(1, i.to_string())).unwrap();
+                let other = table.insert_in(&txn, &row(i + 1, "other".to_string())).unwrap();
+                table.delete_in(&txn, other).unwrap();
+                txn.commit().unwrap();
+                table.vacuum(&manager, &mut |_| {}).unwrap();
+            }
+            done.store(true, Ordering::Relaxed);
+            reader.join().unwrap();
+        });
+        table.vacuum(&manager, &mut |_| {}).unwrap();
+        assert_eq!(table.scan().count(), 1);
+    }
+}
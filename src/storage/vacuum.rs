@@ -0,0 +1,327 @@
+//! Vacuum, which removes the versions of rows no transaction can see any more so that their space is reused.
+//!
+//! Tables register with [`Vacuum`] by name. Its background thread periodically vacuums those that transactions
+//! wrote enough versions to since they were last vacuumed, `VACUUM` does so when asked. Each vacuum goes through
+//! [`Table::vacuum`] in phases: it scans the heap for dead versions, moves or removes the index entries pointing to
+//! them, then removes them and compacts the pages they were in, freezing the versions it keeps. Once every table
+//! was vacuumed, the statuses of the transactions before the oldest horizon they were vacuumed at are forgotten.
+//! Vacuums in progress are the rows of the [`VACUUM_TABLE`] system table.
+
+use crate::parser::token::DataKind;
+use crate::storage::StorageError;
+use crate::storage::mvcc::{FROZEN_XID, TransactionManager, Xid};
+use crate::storage::table::Table;
+use crate::value::Value;
+use crate::warn;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::Duration;
+
+/// Name of the system table listing the vacuums in progress.
+pub const VACUUM_TABLE: &str = "rdb_stat_progress_vacuum";
+
+pub const VACUUM_COLUMNS: [(&str, DataKind); 6] = [
+    ("relation", DataKind::Text(None)),
+    ("phase", DataKind::Text(None)),
+    ("pages_total", DataKind::BigInt(None)),
+    ("pages_scanned", DataKind::BigInt(None)),
+    ("dead_versions", DataKind::BigInt(None)),
+    ("automatic", DataKind::Bool),
+];
+
+/// How often tables are looked at for vacuuming unless configured otherwise.
+pub const VACUUM_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Versions transactions write to a table before it is vacuumed, unless configured otherwise.
+pub const VACUUM_THRESHOLD: u64 = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VacuumPhase {
+    /// Looking for dead versions.
+    ScanningHeap,
+    /// Moving or removing the index entries of dead versions.
+    VacuumingIndexes,
+    /// Removing dead versions and compacting their pages.
+    VacuumingHeap,
+}
+
+impl VacuumPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VacuumPhase::ScanningHeap => "scanning heap",
+            VacuumPhase::VacuumingIndexes => "vacuuming indexes",
+            VacuumPhase::VacuumingHeap => "vacuuming heap",
+        }
+    }
+}
+
+/// How far a vacuum got.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VacuumProgress {
+    pub phase: VacuumPhase,
+    /// Pages of the heap when the vacuum started, later ones only hold newer versions.
+    pub pages_total: u32,
+    pub pages_scanned: u32,
+    /// Dead versions found so far.
+    pub dead_versions: u64,
+}
+
+/// What a vacuum did.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VacuumStats {
+    pub pages: u32,
+    /// Pages that held dead versions, which were compacted.
+    pub compacted: u32,
+    /// Dead versions removed.
+    pub versions: u64,
+    pub index_entries: u64,
+    /// Versions frozen.
+    pub frozen: u64,
+    /// Horizon the versions were frozen at, the table has none left that need the status of a transaction
+    /// before it.
+    pub horizon: Xid,
+}
+
+struct Running {
+    progress: VacuumProgress,
+    automatic: bool,
+}
+
+struct Registered {
+    table: Weak<RwLock<Table>>,
+    /// Horizon of its last vacuum, its versions need the status of no transaction before it.
+    frozen: Xid,
+}
+
+struct Shared {
+    transactions: TransactionManager,
+    threshold: u64,
+    tables: Mutex<BTreeMap<String, Registered>>,
+    running: Mutex<BTreeMap<String, Running>>,
+}
+
+/// Vacuums registered tables, in the background and on request. Clones share the tables.
+#[derive(Clone)]
+pub struct Vacuum(Arc<Shared>);
+
+impl Vacuum {
+    /// Vacuums the tables that had at least `threshold` versions written to them every `interval`, or only on
+    /// request with `None`, until the last clone is dropped.
+    pub fn new(transactions: TransactionManager, interval: Option<Duration>, threshold: u64) -> Self {
+        let shared = Arc::new(Shared {
+            transactions,
+            threshold,
+            tables: Mutex::new(BTreeMap::new()),
+            running: Mutex::new(BTreeMap::new()),
+        });
+        if let Some(interval) = interval {
+            let worker = Arc::downgrade(&shared);
+            std::thread::Builder::new()
+                .name("vacuum".to_string())
+                .spawn(move || vacuum_periodically(worker, interval))
+                .expect("could not start the vacuum worker");
+        }
+        Vacuum(shared)
+    }
+
+    /// Vacuums the table under `name` for as long as it exists. Its indexes change under the write lock, vacuums
+    /// take the read lock.
+    pub fn register(&self, name: &str, table: &Arc<RwLock<Table>>) {
+        let mut tables = self.0.tables.lock().unwrap();
+        let table = Arc::downgrade(table);
+        tables.insert(
+            name.to_string(),
+            Registered {
+                table,
+                frozen: FROZEN_XID,
+            },
+        );
+    }
+
+    pub fn table(&self, name: &str) -> Option<Arc<RwLock<Table>>> {
+        self.0.tables.lock().unwrap().get(name)?.table.upgrade()
+    }
+
+    /// The registered tables that still exist, by name.
+    pub fn tables(&self) -> Vec<(String, Arc<RwLock<Table>>)> {
+        let mut tables = self.0.tables.lock().unwrap();
+        tables.retain(|_, registered| registered.table.strong_count() > 0);
+        tables
+            .iter()
+            .filter_map(|(name, registered)| Some((name.clone(), registered.table.upgrade()?)))
+            .collect()
+    }
+
+    /// Vacuums the table, `None` when it is already being vacuumed.
+    pub fn run(&self, name: &str, table: &Table, automatic: bool) -> Result<Option<VacuumStats>, StorageError> {
+        let progress = VacuumProgress {
+            phase: VacuumPhase::ScanningHeap,
+            pages_total: 0,
+            pages_scanned: 0,
+            dead_versions: 0,
+        };
+        {
+            let mut running = self.0.running.lock().unwrap();
+            if running.contains_key(name) {
+                return Ok(None);
+            }
+            running.insert(name.to_string(), Running { progress, automatic });
+        }
+
+        let stats = table.vacuum(&self.0.transactions, &mut |progress| {
+            if let Some(running) = self.0.running.lock().unwrap().get_mut(name) {
+                running.progress = *progress;
+            }
+        });
+        self.0.running.lock().unwrap().remove(name);
+        if let Ok(stats) = &stats {
+            self.frozen(name, stats.horizon);
+        }
+        stats.map(Some)
+    }
+
+    /// Notes that the table was frozen at `horizon`, and forgets the statuses no registered table needs.
+    fn frozen(&self, name: &str, horizon: Xid) {
+        let mut tables = self.0.tables.lock().unwrap();
+        if let Some(registered) = tables.get_mut(name) {
+            registered.frozen = registered.frozen.max(horizon);
+        }
+        tables.retain(|_, registered| registered.table.strong_count() > 0);
+        if let Some(frozen) = tables.values().map(|registered| registered.frozen).min() {
+            self.0.transactions.forget(frozen);
+        }
+    }
+
+    /// Vacuums every table that had at least the threshold of versions written to it, returns their names.
+    pub fn vacuum_due(&self) -> Vec<String> {
+        let mut vacuumed = Vec::new();
+        for (name, table) in self.tables() {
+            let table = table.read().unwrap();
+            if table.changes() < self.0.threshold {
+                continue;
+            }
+            match self.run(&name, &table, true) {
+                Ok(Some(_)) => vacuumed.push(name),
+                Ok(None) => {}
+                Err(err) => warn!("could not vacuum \"{name}\": {err}"),
+            }
+        }
+        vacuumed
+    }
+
+    /// Rows of the system table, in the order of [`VACUUM_COLUMNS`].
+    pub fn rows(&self) -> Vec<Vec<Value>> {
+        let running = self.0.running.lock().unwrap();
+        running
+            .iter()
+            .map(|(name, running)| {
+                let progress = &running.progress;
+                vec![
+                    Value::Text(name.clone()),
+                    Value::Text(progress.phase.as_str().to_string()),
+                    Value::Int(progress.pages_total as i64),
+                    Value::Int(progress.pages_scanned as i64),
+                    Value::Int(progress.dead_versions as i64),
+                    Value::Bool(running.automatic),
+                ]
+            })
+            .collect()
+    }
+}
+
+fn vacuum_periodically(shared: Weak<Shared>, interval: Duration) {
+    loop {
+        std::thread::sleep(interval);
+        match shared.upgrade() {
+            Some(shared) => Vacuum(shared).vacuum_due(),
+            None => return,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parser::ast::IsolationLevel;
+    use crate::storage::buffer::{BufferPool, EvictionPolicyKind};
+    use crate::storage::file::FileManager;
+    use crate::storage::mvcc::TxnStatus;
+    use crate::storage::row::{ColumnSchema, Schema};
+    use crate::storage::testing::TempDir;
+    use std::time::Instant;
+
+    fn table(dir: &TempDir) -> Arc<RwLock<Table>> {
+        let pool = BufferPool::new(
+            Arc::new(FileManager::open(dir.path()).unwrap()),
+            16,
+            EvictionPolicyKind::Clock,
+        );
+        let schema = Schema::new(vec![ColumnSchema::new("id", DataKind::Integer(None), false)]).unwrap();
+        Arc::new(RwLock::new(Table::create(pool, schema).unwrap()))
+    }
+
+    #[test]
+    fn test_background() {
+        let dir = TempDir::new();
+        let table = table(&dir);
+        let manager = TransactionManager::new();
+        let vacuum = Vacuum::new(manager.clone(), Some(Duration::from_millis(10)), 2);
+        vacuum.register("cats", &table);
+
+        // Below the threshold the table is left alone.
+        let txn = manager.begin(IsolationLevel::ReadCommitted);
+        let id = table.read().unwrap().insert_in(&txn, &[Value::Int(1)]).unwrap();
+        txn.commit().unwrap();
+        assert!(vacuum.vacuum_due().is_empty());
+
+        let txn = manager.begin(IsolationLevel::ReadCommitted);
+        table.read().unwrap().delete_in(&txn, id).unwrap();
+        txn.commit().unwrap();
+        let start = Instant::now();
+        while table.read().unwrap().scan().count() > 0 {
+            assert!(start.elapsed() < Duration::from_secs(10), "the table was not vacuumed");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(table.read().unwrap().changes(), 0);
+        assert!(vacuum.rows().is_empty());
+
+        // Tables that no longer exist are forgotten.
+        drop(table);
+        assert!(vacuum.tables().is_empty());
+        assert!(vacuum.table("cats").is_none());
+    }
+
+    #[test]
+    fn test_forget_statuses() {
+        let (cats_dir, dogs_dir) = (TempDir::new(), TempDir::new());
+        let (cats, dogs) = (table(&cats_dir), table(&dogs_dir));
+        let manager = TransactionManager::new();
+        let vacuum = Vacuum::new(manager.clone(), None, 1);
+        vacuum.register("cats", &cats);
+        vacuum.register("dogs", &dogs);
+        let insert = |table: &Arc<RwLock<Table>>| {
+            let txn = manager.begin(IsolationLevel::ReadCommitted);
+            let id = table.read().unwrap().insert_in(&txn, &[Value::Int(1)]).unwrap();
+            txn.commit().unwrap();
+            (txn.xid().unwrap(), id)
+        };
+        let (cat, cat_id) = insert(&cats);
+        let (dog, _) = insert(&dogs);
+
+        // Until every table froze its versions, they may need the statuses.
+        vacuum.run("cats", &cats.read().unwrap(), false).unwrap().unwrap();
+        assert_eq!(manager.known_status(cat), Some(TxnStatus::Committed));
+        let stats = vacuum.run("dogs", &dogs.read().unwrap(), false).unwrap().unwrap();
+        assert_eq!(stats.frozen, 1);
+        vacuum.run("cats", &cats.read().unwrap(), false).unwrap().unwrap();
+        assert_eq!((manager.known_status(cat), manager.known_status(dog)), (None, None));
+
+        // The versions are seen all the same.
+        let txn = manager.begin(IsolationLevel::ReadCommitted);
+        assert_eq!(
+            cats.read().unwrap().get_in(&txn, cat_id).unwrap(),
+            Some((cat_id, vec![Value::Int(1)]))
+        );
+    }
+}
@@ -0,0 +1,1501 @@
+//! Write-ahead log, every change to a page made by a [`Transaction`] in the order they were made, so that a crash
+//! loses none that were committed and keeps none that were not.
+//!
+//! A record is identified by its LSN, its offset in the log. Pages carry the LSN of the last record that changed
+//! them, and the [`BufferPool`] writes a page only once the log is on disk up to that record. Updates log the
+//! bytes before and after, so that they can be redone or undone. Undoing one logs a compensation record with the
+//! bytes put back, which is only ever redone, and the record to undo next. Pages changed outside of such a
+//! transaction are logged by the pool with the bytes after only, versions of rows need no undoing since
+//! [`crate::storage::mvcc`] tells which ones count. Every record is stored as:
+//!
+//! | bytes  | field                                   |
+//! |--------|-----------------------------------------|
+//! | 0..4   | length of the record after this header  |
+//! | 4..8   | CRC-32 of the record                    |
+//! | 8..    | kind, transaction, previous record of it, and the fields of the kind |
+//!
+//! The log is split into segment files of the same size, which records run across. Every segment starts with a
+//! magic number and the size of segments, which stays the one the log was created with.
+//!
+//! A commit waits until its record is on disk. Commits waiting at the same time share one `fsync`, whoever asks
+//! first syncs everything logged so far while the others wait for it. Without `fsync` a commit only waits for the
+//! log to be written, which a crash of the system rather than the process can lose.
+//!
+//! A checkpoint writes the dirty pages, then logs the transactions in progress and the pages still dirty, and
+//! records where it started in a file of its own, along with how every transaction the log knows of ended in
+//! another one, but for those it was told to forget. Recovery starts there. It reads the log forward to find the
+//! transactions that never finished and the pages that may miss changes, redoes every change those pages miss,
+//! and then undoes the unfinished transactions, latest change first. The segments before all of that are removed.
+//! A crash while writing the log leaves a record that fails its checksum at the end, which is cut off. Pages torn
+//! by a crash are not repaired.
+
+use crate::config::WalConfig;
+use crate::storage::buffer::{BufferPool, PageWriteGuard};
+use crate::storage::page::{Crc32, PAGE_SIZE, Page};
+use crate::storage::{PageId, StorageError};
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Position of a record in the log, zero for none.
+pub type Lsn = u64;
+
+pub type TxnId = u64;
+
+const MAGIC: &[u8; 8] = b"rdb-wal2";
+/// Magic number and size of segments.
+const SEGMENT_HEADER_LEN: u64 = 16;
+/// Where the first record goes, LSN zero being none.
+const FIRST_LSN: Lsn = 8;
+const RECORD_HEADER_LEN: usize = 8;
+
+const LOG_FILE: &str = "log";
+const CHECKPOINT_FILE: &str = "checkpoint";
+const STATUS_FILE: &str = "status";
+
+const UPDATE: u8 = 1;
+const COMPENSATION: u8 = 2;
+const COMMIT: u8 = 3;
+const ABORT: u8 = 4;
+const END: u8 = 5;
+const CHECKPOINT: u8 = 6;
+const WRITE: u8 = 7;
+const BEGIN: u8 = 8;
+
+/// Unchanged bytes between two changes of a page that are logged along with them rather than as another record.
+const MAX_UNCHANGED_RUN: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LogRecord {
+    /// Bytes of a page changed by a transaction.
+    Update {
+        txn: TxnId,
+        prev: Lsn,
+        page: PageId,
+        offset: u16,
+        before: Vec<u8>,
+        after: Vec<u8>,
+    },
+    /// An update undone, the bytes put back and the record of the transaction to undo after it.
+    Compensation {
+        txn: TxnId,
+        prev: Lsn,
+        page: PageId,
+        offset: u16,
+        after: Vec<u8>,
+        undo_next: Lsn,
+    },
+    /// The transaction is about to change pages, for good or not as its commit or end says.
+    Begin {
+        txn: TxnId,
+    },
+    /// Bytes of a page changed outside of a transaction the log undoes, only ever redone.
+    Write {
+        page: PageId,
+        offset: u16,
+        after: Vec<u8>,
+    },
+    Commit {
+        txn: TxnId,
+        prev: Lsn,
+    },
+    /// The transaction started rolling back.
+    Abort {
+        txn: TxnId,
+        prev: Lsn,
+    },
+    /// The transaction finished rolling back.
+    End {
+        txn: TxnId,
+        prev: Lsn,
+    },
+    /// Transactions in progress with their last record, dirty pages with the first record they may miss, and the
+    /// first transaction id not handed out yet.
+    Checkpoint {
+        txns: Vec<(TxnId, Lsn)>,
+        dirty: Vec<(PageId, Lsn)>,
+        next_txn: TxnId,
+    },
+}
+
+impl LogRecord {
+    pub fn txn(&self) -> Option<TxnId> {
+        match self {
+            LogRecord::Update { txn, .. }
+            | LogRecord::Compensation { txn, .. }
+            | LogRecord::Commit { txn, .. }
+            | LogRecord::Abort { txn, .. }
+            | LogRecord::End { txn, .. }
+            | LogRecord::Begin { txn } => Some(*txn),
+            LogRecord::Write { .. } | LogRecord::Checkpoint { .. } => None,
+        }
+    }
+
+    /// The previous record of the transaction.
+    pub fn prev(&self) -> Lsn {
+        match self {
+            LogRecord::Update { prev, .. }
+            | LogRecord::Compensation { prev, .. }
+            | LogRecord::Commit { prev, .. }
+            | LogRecord::Abort { prev, .. }
+            | LogRecord::End { prev, .. } => *prev,
+            LogRecord::Begin { .. } | LogRecord::Write { .. } | LogRecord::Checkpoint { .. } => 0,
+        }
+    }
+
+    /// The page the record changes, where, and the bytes it puts there when redone.
+    fn redo(&self) -> Option<(PageId, u16, &[u8])> {
+        match self {
+            LogRecord::Update { page, offset, after, .. }
+            | LogRecord::Compensation { page, offset, after, .. }
+            | LogRecord::Write { page, offset, after } => Some((*page, *offset, after)),
+            _ => None,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let page_id = |out: &mut Vec<u8>, page: &PageId| {
+            out.extend_from_slice(&page.file.to_le_bytes());
+            out.extend_from_slice(&page.page.to_le_bytes());
+        };
+        let bytes = |out: &mut Vec<u8>, bytes: &[u8]| {
+            out.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+            out.extend_from_slice(bytes);
+        };
+
+        let kind = match self {
+            LogRecord::Update { .. } => UPDATE,
+            LogRecord::Compensation { .. } => COMPENSATION,
+            LogRecord::Write { .. } => WRITE,
+            LogRecord::Begin { .. } => BEGIN,
+            LogRecord::Commit { .. } => COMMIT,
+            LogRecord::Abort { .. } => ABORT,
+            LogRecord::End { .. } => END,
+            LogRecord::Checkpoint { .. } => CHECKPOINT,
+        };
+        out.push(kind);
+        out.extend_from_slice(&self.txn().unwrap_or(0).to_le_bytes());
+        out.extend_from_slice(&self.prev().to_le_bytes());
+        match self {
+            LogRecord::Update {
+                page,
+                offset,
+                before,
+                after,
+                ..
+            } => {
+                page_id(&mut out, page);
+                out.extend_from_slice(&offset.to_le_bytes());
+                bytes(&mut out, before);
+                bytes(&mut out, after);
+            }
+            LogRecord::Compensation {
+                page,
+                offset,
+                after,
+                undo_next,
+                ..
+            } => {
+                page_id(&mut out, page);
+                out.extend_from_slice(&offset.to_le_bytes());
+                bytes(&mut out, after);
+                out.extend_from_slice(&undo_next.to_le_bytes());
+            }
+            LogRecord::Write { page, offset, after } => {
+                page_id(&mut out, page);
+                out.extend_from_slice(&offset.to_le_bytes());
+                bytes(&mut out, after);
+            }
+            LogRecord::Checkpoint { txns, dirty, next_txn } => {
+                out.extend_from_slice(&(txns.len() as u32).to_le_bytes());
+                for (txn, last) in txns {
+                    out.extend_from_slice(&txn.to_le_bytes());
+                    out.extend_from_slice(&last.to_le_bytes());
+                }
+                out.extend_from_slice(&(dirty.len() as u32).to_le_bytes());
+                for (page, rec_lsn) in dirty {
+                    page_id(&mut out, page);
+                    out.extend_from_slice(&rec_lsn.to_le_bytes());
+                }
+                out.extend_from_slice(&next_txn.to_le_bytes());
+            }
+            _ => {}
+        }
+        out
+    }
+
+    fn decode(record: &[u8]) -> Option<Self> {
+        let mut r = Reader(record);
+        let (kind, txn, prev) = (r.u8()?, r.u64()?, r.u64()?);
+        let record = match kind {
+            UPDATE => LogRecord::Update {
+                txn,
+                prev,
+                page: r.page_id()?,
+                offset: r.u16()?,
+                before: r.bytes()?,
+                after: r.bytes()?,
+            },
+            COMPENSATION => LogRecord::Compensation {
+                txn,
+                prev,
+                page: r.page_id()?,
+                offset: r.u16()?,
+                after: r.bytes()?,
+                undo_next: r.u64()?,
+            },
+            WRITE => LogRecord::Write {
+                page: r.page_id()?,
+                offset: r.u16()?,
+                after: r.bytes()?,
+            },
+            BEGIN => LogRecord::Begin { txn },
+            COMMIT => LogRecord::Commit { txn, prev },
+            ABORT => LogRecord::Abort { txn, prev },
+            END => LogRecord::End { txn, prev },
+            CHECKPOINT => {
+                let txns = (0..r.u32()?).map(|_| Some((r.u64()?, r.u64()?))).collect::<Option<_>>()?;
+                let dirty = (0..r.u32()?).map(|_| Some((r.page_id()?, r.u64()?))).collect::<Option<_>>()?;
+                LogRecord::Checkpoint {
+                    txns,
+                    dirty,
+                    next_txn: r.u64()?,
+                }
+            }
+            _ => return None,
+        };
+        r.0.is_empty().then_some(record)
+    }
+}
+
+/// Reads the fields of a record in order.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (bytes, rest) = self.0.split_first_chunk::<N>()?;
+        self.0 = rest;
+        Some(*bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take()?))
+    }
+
+    fn page_id(&mut self) -> Option<PageId> {
+        Some(PageId::new(self.u32()?, self.u32()?))
+    }
+
+    fn bytes(&mut self) -> Option<Vec<u8>> {
+        let len = self.u16()? as usize;
+        let bytes = self.0.get(..len)?.to_vec();
+        self.0 = &self.0[len..];
+        Some(bytes)
+    }
+}
+
+/// How transactions ended, two bits each: committed, rolled back, or neither. Those before `start` were forgotten.
+#[derive(Clone, Default)]
+struct Outcomes {
+    start: TxnId,
+    /// Starting with the byte of `start`.
+    bits: Vec<u8>,
+}
+
+impl Outcomes {
+    fn set(&mut self, txn: TxnId, committed: bool) {
+        if txn < self.start {
+            return;
+        }
+        let (byte, shift) = ((txn / 4 - self.start / 4) as usize, txn % 4 * 2);
+        if byte >= self.bits.len() {
+            self.bits.resize(byte + 1, 0);
+        }
+        self.bits[byte] = self.bits[byte] & !(3 << shift) | (if committed { 1 } else { 2 }) << shift;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (TxnId, bool)> + '_ {
+        let first = self.start / 4 * 4;
+        (self.start..first + self.bits.len() as TxnId * 4).filter_map(move |txn| {
+            match self.bits[((txn - first) / 4) as usize] >> (txn % 4 * 2) & 3 {
+                1 => Some((txn, true)),
+                2 => Some((txn, false)),
+                _ => None,
+            }
+        })
+    }
+
+    fn forget(&mut self, before: TxnId) {
+        if before <= self.start {
+            return;
+        }
+        let bytes = ((before / 4 - self.start / 4) as usize).min(self.bits.len());
+        self.bits.drain(..bytes);
+        self.start = before;
+    }
+}
+
+/// What recovery did.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recovery {
+    /// Changes written again to pages that missed them.
+    pub redone: usize,
+    /// Transactions rolled back because they never finished.
+    pub rolled_back: Vec<TxnId>,
+}
+
+struct Log {
+    /// Segment the log ends in.
+    file: File,
+    segment: u64,
+    /// Records not written to the file yet.
+    buffer: Vec<u8>,
+    /// End of the records in the file.
+    written: Lsn,
+    /// Last record of every transaction in progress.
+    txns: HashMap<TxnId, Lsn>,
+    /// First record of every transaction in progress.
+    first: HashMap<TxnId, Lsn>,
+    outcomes: Outcomes,
+    next_txn: TxnId,
+}
+
+impl Log {
+    fn end(&self) -> Lsn {
+        self.written + self.buffer.len() as Lsn
+    }
+}
+
+struct Flush {
+    /// End of the records on disk.
+    durable: Lsn,
+    syncing: bool,
+}
+
+pub struct Wal {
+    directory: PathBuf,
+    segments: Segments,
+    fsync: bool,
+    checkpoint_interval: Duration,
+    log: Mutex<Log>,
+    flush: Mutex<Flush>,
+    synced: Condvar,
+    syncs: AtomicU64,
+    checkpoint: Mutex<()>,
+}
+
+impl Wal {
+    /// Opens the log in `directory`, which is created if it does not exist, cutting off a partly written record
+    /// at its end. Pages need [`Wal::recover`] before anything else reads them.
+    pub fn open(directory: impl Into<PathBuf>, config: &WalConfig) -> Result<Self, StorageError> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+        let mut segments = Segments {
+            directory: directory.clone(),
+            size: config.segment_size,
+        };
+        match segments.list()?.first() {
+            Some(first) => segments.size = segments.read_size(*first)?,
+            None => drop(segments.create(0)?),
+        }
+
+        // The log before where the last checkpoint started may be gone, the status file says how the transactions
+        // in it ended.
+        let (mut outcomes, mut next_txn) = match read_file(&directory, STATUS_FILE)? {
+            Some(bytes) => {
+                let corrupt = || corrupt_log(0, "status file is corrupt");
+                let (next_txn, rest) = bytes.split_first_chunk::<8>().ok_or_else(corrupt)?;
+                let (start, bits) = rest.split_first_chunk::<8>().ok_or_else(corrupt)?;
+                let outcomes = Outcomes {
+                    start: u64::from_le_bytes(*start),
+                    bits: bits.to_vec(),
+                };
+                (outcomes, u64::from_le_bytes(*next_txn))
+            }
+            None => (Outcomes::default(), 1),
+        };
+        let end = read_log(&segments, checkpoint_start(&directory)?, |_, record| {
+            match record {
+                LogRecord::Checkpoint { next_txn: next, .. } => next_txn = next_txn.max(next),
+                LogRecord::Commit { txn, .. } => outcomes.set(txn, true),
+                LogRecord::End { txn, .. } => outcomes.set(txn, false),
+                _ => {}
+            }
+            next_txn = next_txn.max(record.txn().unwrap_or(0) + 1);
+            Ok(())
+        })?;
+        let segment = end / segments.size;
+        for later in segments.list()?.into_iter().filter(|later| *later > segment) {
+            std::fs::remove_file(segments.path(later))?;
+        }
+        let file = match segments.open(segment)? {
+            Some(file) => file,
+            None => segments.create(segment)?,
+        };
+        file.set_len(segments.offset(end))?;
+
+        Ok(Wal {
+            directory,
+            segments,
+            fsync: config.fsync,
+            checkpoint_interval: config.checkpoint_interval,
+            log: Mutex::new(Log {
+                file,
+                segment,
+                buffer: Vec::new(),
+                written: end,
+                txns: HashMap::new(),
+                first: HashMap::new(),
+                outcomes,
+                next_txn,
+            }),
+            flush: Mutex::new(Flush {
+                durable: end,
+                syncing: false,
+            }),
+            synced: Condvar::new(),
+            syncs: AtomicU64::new(0),
+            checkpoint: Mutex::new(()),
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// LSN the next record gets.
+    pub fn end(&self) -> Lsn {
+        self.log.lock().unwrap().end()
+    }
+
+    /// Number of times the log was synced, one per group of commits.
+    pub fn syncs(&self) -> u64 {
+        self.syncs.load(Ordering::Relaxed)
+    }
+
+    /// Transactions the log knows ended, and whether they committed.
+    pub fn ended(&self) -> Vec<(TxnId, bool)> {
+        self.log.lock().unwrap().outcomes.iter().collect()
+    }
+
+    /// Forgets how the transactions before `txn` ended, the status file drops them at the next checkpoint.
+    pub fn forget(&self, txn: TxnId) {
+        self.log.lock().unwrap().outcomes.forget(txn);
+    }
+
+    /// The first transaction id the log has not seen.
+    pub fn next_txn(&self) -> TxnId {
+        self.log.lock().unwrap().next_txn
+    }
+
+    /// How often the log was configured to be checkpointed.
+    pub fn checkpoint_interval(&self) -> Duration {
+        self.checkpoint_interval
+    }
+
+    pub fn begin(self: &Arc<Self>, pool: &BufferPool) -> Transaction {
+        let mut log = self.log.lock().unwrap();
+        let id = log.next_txn;
+        log.next_txn += 1;
+        Transaction {
+            wal: self.clone(),
+            pool: pool.clone(),
+            id,
+            last: 0,
+            done: false,
+        }
+    }
+
+    /// Adds a record to the end of the log, where it stays in memory until flushed.
+    pub fn append(&self, record: &LogRecord) -> Lsn {
+        let mut log = self.log.lock().unwrap();
+        append(&mut log, record)
+    }
+
+    /// Logs how a page changed since it was `before`, with records that are only ever redone, and moves its LSN
+    /// to the last of them.
+    pub fn log_changes(&self, id: PageId, before: &Page, page: &mut Page) {
+        let (before, after) = (before.data(), page.data());
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        let mut i = 0;
+        while i < PAGE_SIZE {
+            if before[i] == after[i] {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < PAGE_SIZE && before[i] != after[i] {
+                i += 1;
+            }
+            match runs.last_mut() {
+                Some((_, end)) if start - *end <= MAX_UNCHANGED_RUN => *end = i,
+                _ => runs.push((start, i)),
+            }
+        }
+
+        let records: Vec<LogRecord> = runs
+            .into_iter()
+            .map(|(offset, end)| LogRecord::Write {
+                page: id,
+                offset: offset as u16,
+                after: after[offset..end].to_vec(),
+            })
+            .collect();
+        let mut log = self.log.lock().unwrap();
+        for record in records {
+            let lsn = append(&mut log, &record);
+            page.set_lsn(lsn);
+        }
+    }
+
+    /// Waits until the record at `lsn` and every one before it are on disk.
+    pub fn flush(&self, lsn: Lsn) -> Result<(), StorageError> {
+        let mut flush = self.flush.lock().unwrap();
+        loop {
+            if flush.durable > lsn {
+                return Ok(());
+            }
+            if flush.syncing {
+                flush = self.synced.wait(flush).unwrap();
+                continue;
+            }
+
+            flush.syncing = true;
+            drop(flush);
+            let synced = self.write_buffer().and_then(|(end, file)| {
+                crash_point("log_sync");
+                if self.fsync {
+                    file.sync_data()?;
+                    self.syncs.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(end)
+            });
+            flush = self.flush.lock().unwrap();
+            flush.syncing = false;
+            self.synced.notify_all();
+            flush.durable = synced?;
+        }
+    }
+
+    /// Reads the record at `lsn`.
+    pub fn read(&self, lsn: Lsn) -> Result<LogRecord, StorageError> {
+        {
+            let log = self.log.lock().unwrap();
+            if lsn >= log.written {
+                let start = (lsn - log.written) as usize;
+                return log
+                    .buffer
+                    .get(start..)
+                    .and_then(|bytes| parse_record(bytes).map(|(record, _)| record))
+                    .ok_or_else(|| corrupt_log(lsn, "no record there"));
+            }
+        }
+
+        // Written records do not change.
+        let mut header = [0; RECORD_HEADER_LEN];
+        self.segments.read_at(lsn, &mut header)?;
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let mut bytes = header.to_vec();
+        bytes.resize(RECORD_HEADER_LEN + len.min(3 * PAGE_SIZE), 0);
+        self.segments
+            .read_at(lsn + RECORD_HEADER_LEN as Lsn, &mut bytes[RECORD_HEADER_LEN..])?;
+        parse_record(&bytes)
+            .map(|(record, _)| record)
+            .ok_or_else(|| corrupt_log(lsn, "checksum mismatch"))
+    }
+
+    /// Writes the dirty pages and logs what is in progress, so that recovery can start from here, then removes the
+    /// segments it no longer needs.
+    pub fn checkpoint(&self, pool: &BufferPool) -> Result<Lsn, StorageError> {
+        let _checkpoint = self.checkpoint.lock().unwrap();
+        // Recovery reads from before the dirty pages are listed, so that it sees every change they may miss.
+        let start = self.end();
+        pool.flush_all()?;
+        let dirty = pool.dirty_pages();
+        let (lsn, needed, mut status) = {
+            let mut log = self.log.lock().unwrap();
+            let needed = log
+                .first
+                .values()
+                .chain(dirty.iter().map(|(_, lsn)| lsn))
+                .fold(start, |a, b| a.min(*b));
+            let txns = log.txns.iter().map(|(txn, last)| (*txn, *last)).collect();
+            let next_txn = log.next_txn;
+            let lsn = append(&mut log, &LogRecord::Checkpoint { txns, dirty, next_txn });
+            let outcomes = &log.outcomes;
+            let status = [&next_txn.to_le_bytes()[..], &outcomes.start.to_le_bytes(), &outcomes.bits].concat();
+            (lsn, needed, status)
+        };
+        self.flush(lsn)?;
+
+        // The outcomes go first, they may only be newer than where recovery starts.
+        write_file(&self.directory, STATUS_FILE, &mut status)?;
+        write_file(&self.directory, CHECKPOINT_FILE, &mut start.to_le_bytes().to_vec())?;
+        crash_point("checkpoint");
+
+        for segment in self.segments.list()? {
+            if (segment + 1) * self.segments.size <= needed {
+                std::fs::remove_file(self.segments.path(segment))?;
+            }
+        }
+        Ok(lsn)
+    }
+
+    /// Brings the pages to the state the log says they are in: redoes the changes they miss, and rolls back the
+    /// transactions that did not finish.
+    pub fn recover(&self, pool: &BufferPool) -> Result<Recovery, StorageError> {
+        let start = checkpoint_start(&self.directory)?;
+
+        // Transactions in progress and pages that may miss changes, as of the end of the log.
+        let mut txns: HashMap<TxnId, Lsn> = HashMap::new();
+        let mut dirty: HashMap<PageId, Lsn> = HashMap::new();
+        let mut checkpoint_read = false;
+        read_log(&self.segments, start, |lsn, record| {
+            match &record {
+                LogRecord::Checkpoint {
+                    txns: active,
+                    dirty: pages,
+                    ..
+                } if !checkpoint_read => {
+                    checkpoint_read = true;
+                    txns = active.iter().copied().collect();
+                    for (page, rec_lsn) in pages {
+                        let first = dirty.entry(*page).or_insert(*rec_lsn);
+                        *first = (*first).min(*rec_lsn);
+                    }
+                }
+                LogRecord::Commit { txn, .. } | LogRecord::End { txn, .. } => {
+                    txns.remove(txn);
+                }
+                record => {
+                    if let Some(txn) = record.txn() {
+                        txns.insert(txn, lsn);
+                    }
+                }
+            }
+            if let Some((page, _, _)) = record.redo() {
+                dirty.entry(page).or_insert(lsn);
+            }
+            Ok(())
+        })?;
+
+        let mut recovery = Recovery::default();
+        if let Some(from) = dirty.values().min() {
+            read_log(&self.segments, *from, |lsn, record| {
+                if let Some((page, offset, bytes)) = record.redo()
+                    && dirty.get(&page).is_some_and(|first| lsn >= *first)
+                    && let Some(mut guard) = fetch_for_recovery(pool, page)?
+                    && guard.lsn() < lsn
+                {
+                    apply(&mut guard, lsn, offset, bytes)?;
+                    recovery.redone += 1;
+                }
+                Ok(())
+            })?;
+        }
+
+        // Unfinished transactions are undone together, latest change first.
+        let mut last = txns.clone();
+        let mut undo: BinaryHeap<(Lsn, TxnId)> = txns.iter().map(|(txn, lsn)| (*lsn, *txn)).collect();
+        recovery.rolled_back = txns.into_keys().collect();
+        recovery.rolled_back.sort_unstable();
+        while let Some((lsn, txn)) = undo.pop() {
+            let last = last.get_mut(&txn).unwrap();
+            match self.undo(pool, txn, last, lsn)? {
+                0 => {
+                    let end = self.append(&LogRecord::End { txn, prev: *last });
+                    self.flush(end)?;
+                }
+                next => undo.push((next, txn)),
+            }
+        }
+        Ok(recovery)
+    }
+
+    /// Undoes the record at `lsn` of a transaction whose last record is `last`, returns the record to undo next.
+    fn undo(&self, pool: &BufferPool, txn: TxnId, last: &mut Lsn, lsn: Lsn) -> Result<Lsn, StorageError> {
+        match self.read(lsn)? {
+            LogRecord::Update {
+                page,
+                offset,
+                before,
+                prev,
+                ..
+            } => {
+                if let Some(mut guard) = fetch_for_recovery(pool, page)? {
+                    // Dirty before the record goes in, so that the page is not thought to be clean up to it.
+                    guard.data_mut();
+                    let clr = self.append(&LogRecord::Compensation {
+                        txn,
+                        prev: *last,
+                        page,
+                        offset,
+                        after: before.clone(),
+                        undo_next: prev,
+                    });
+                    apply(&mut guard, clr, offset, &before)?;
+                    *last = clr;
+                }
+                crash_point("undo");
+                Ok(prev)
+            }
+            LogRecord::Compensation { undo_next, .. } => Ok(undo_next),
+            LogRecord::Checkpoint { .. } => Err(corrupt_log(lsn, "checkpoint in the records of a transaction")),
+            record => Ok(record.prev()),
+        }
+    }
+
+    /// Writes the buffered records to the segments, returns the end of them and the segment it is in.
+    fn write_buffer(&self) -> Result<(Lsn, File), StorageError> {
+        let mut log = self.log.lock().unwrap();
+        let size = self.segments.size;
+        while !log.buffer.is_empty() {
+            let segment = log.written / size;
+            if segment != log.segment {
+                // Flushes sync the segment the log ends in, the ones before are synced when it starts.
+                if self.fsync {
+                    log.file.sync_data()?;
+                }
+                log.file = self.segments.create(segment)?;
+                log.segment = segment;
+            }
+            let len = log.buffer.len().min((size - log.written % size) as usize);
+            let offset = self.segments.offset(log.written);
+            let Log { file, buffer, .. } = &mut *log;
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(&buffer[..len])?;
+            log.buffer.drain(..len);
+            log.written += len as Lsn;
+        }
+        Ok((log.written, log.file.try_clone()?))
+    }
+}
+
+/// Where the last checkpoint in `directory` started, or the start of the log.
+fn checkpoint_start(directory: &Path) -> Result<Lsn, StorageError> {
+    match read_file(directory, CHECKPOINT_FILE)? {
+        Some(bytes) => match bytes.try_into() {
+            Ok(lsn) => Ok(u64::from_le_bytes(lsn)),
+            Err(_) => Err(corrupt_log(0, "checkpoint file is corrupt")),
+        },
+        None => Ok(FIRST_LSN),
+    }
+}
+
+/// Replaces a file of the directory with `bytes` followed by their CRC-32, so that a crash leaves either the old
+/// file or the new one.
+fn write_file(directory: &Path, name: &str, bytes: &mut Vec<u8>) -> Result<(), StorageError> {
+    bytes.extend_from_slice(&Crc32::checksum(bytes).to_le_bytes());
+    let temp = directory.join(format!("{name}.tmp"));
+    let mut file = File::create(&temp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    std::fs::rename(&temp, directory.join(name))?;
+    sync_directory(directory)
+}
+
+/// The bytes [`write_file`] wrote, `None` if it never did.
+fn read_file(directory: &Path, name: &str) -> Result<Option<Vec<u8>>, StorageError> {
+    let mut bytes = match std::fs::read(directory.join(name)) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        bytes => bytes?,
+    };
+    let corrupt = || corrupt_log(0, &format!("{name} file is corrupt"));
+    let crc = bytes.split_off(bytes.len().checked_sub(4).ok_or_else(corrupt)?);
+    if crc != Crc32::checksum(&bytes).to_le_bytes() {
+        return Err(corrupt());
+    }
+    Ok(Some(bytes))
+}
+
+/// The files of the log, segment `n` holding `size` bytes of it from LSN `n * size` on after its header.
+struct Segments {
+    directory: PathBuf,
+    size: u64,
+}
+
+impl Segments {
+    fn path(&self, segment: u64) -> PathBuf {
+        self.directory.join(format!("{LOG_FILE}.{segment:016x}"))
+    }
+
+    /// Where the byte at `lsn` is in its segment.
+    fn offset(&self, lsn: Lsn) -> u64 {
+        SEGMENT_HEADER_LEN + lsn % self.size
+    }
+
+    /// The segments in the directory, in order.
+    fn list(&self) -> Result<Vec<u64>, StorageError> {
+        let prefix = format!("{LOG_FILE}.");
+        let mut segments = Vec::new();
+        for entry in std::fs::read_dir(&self.directory)? {
+            let name = entry?.file_name();
+            let segment = name.to_str().and_then(|name| name.strip_prefix(&prefix));
+            if let Some(segment) = segment.and_then(|hex| u64::from_str_radix(hex, 16).ok()) {
+                segments.push(segment);
+            }
+        }
+        segments.sort_unstable();
+        Ok(segments)
+    }
+
+    /// Opens a segment, `None` if it does not exist or a crash cut it off before its header was written.
+    fn open(&self, segment: u64) -> Result<Option<File>, StorageError> {
+        let mut file = match OpenOptions::new().read(true).write(true).open(self.path(segment)) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            file => file?,
+        };
+        let mut header = [0; SEGMENT_HEADER_LEN as usize];
+        if file.read_exact(&mut header).is_err() {
+            return Ok(None);
+        }
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(corrupt_log(segment * self.size, "not a log segment"));
+        }
+        Ok(Some(file))
+    }
+
+    /// The size of segments the log was created with, as the header of `segment` says.
+    fn read_size(&self, segment: u64) -> Result<u64, StorageError> {
+        let mut header = [0; SEGMENT_HEADER_LEN as usize];
+        match self.open(segment)? {
+            Some(mut file) => {
+                file.seek(SeekFrom::Start(0))?;
+                file.read_exact(&mut header)?;
+                Ok(u64::from_le_bytes(header[MAGIC.len()..].try_into().unwrap()))
+            }
+            None => Ok(self.size),
+        }
+    }
+
+    /// Creates a segment, replacing what a crash left of it.
+    fn create(&self, segment: u64) -> Result<File, StorageError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self.path(segment))?;
+        file.write_all(MAGIC)?;
+        file.write_all(&self.size.to_le_bytes())?;
+        file.sync_all()?;
+        sync_directory(&self.directory)?;
+        Ok(file)
+    }
+
+    /// Reads the log from `lsn` on into `buf`, from as many segments as it spans.
+    fn read_at(&self, mut lsn: Lsn, mut buf: &mut [u8]) -> Result<(), StorageError> {
+        while !buf.is_empty() {
+            let Some(mut file) = self.open(lsn / self.size)? else {
+                return Err(corrupt_log(lsn, "no record there"));
+            };
+            let len = buf.len().min((self.size - lsn % self.size) as usize);
+            file.seek(SeekFrom::Start(self.offset(lsn)))?;
+            file.read_exact(&mut buf[..len])?;
+            buf = &mut buf[len..];
+            lsn += len as Lsn;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the log from a position on, ending where a segment is missing or ends early.
+struct SegmentReader<'a> {
+    segments: &'a Segments,
+    lsn: Lsn,
+    /// Segment the position is in, positioned there.
+    file: Option<File>,
+}
+
+impl Read for SegmentReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let segments = self.segments;
+        if self.file.is_none() {
+            let opened = segments.open(self.lsn / segments.size).map_err(std::io::Error::other)?;
+            let Some(mut file) = opened else {
+                return Ok(0);
+            };
+            file.seek(SeekFrom::Start(segments.offset(self.lsn)))?;
+            self.file = Some(file);
+        }
+        let left = (segments.size - self.lsn % segments.size) as usize;
+        let len = buf.len().min(left);
+        let read = self.file.as_mut().unwrap().read(&mut buf[..len])?;
+        self.lsn += read as Lsn;
+        if read == left {
+            self.file = None;
+        }
+        Ok(read)
+    }
+}
+
+fn append(log: &mut Log, record: &LogRecord) -> Lsn {
+    let lsn = log.end();
+    let bytes = record.encode();
+    log.buffer.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    log.buffer.extend_from_slice(&Crc32::checksum(&bytes).to_le_bytes());
+    log.buffer.extend_from_slice(&bytes);
+    if let Some(txn) = record.txn() {
+        log.next_txn = log.next_txn.max(txn + 1);
+    }
+    match record {
+        LogRecord::Commit { txn, .. } | LogRecord::End { txn, .. } => {
+            log.txns.remove(txn);
+            log.first.remove(txn);
+            log.outcomes.set(*txn, matches!(record, LogRecord::Commit { .. }));
+        }
+        record => {
+            if let Some(txn) = record.txn() {
+                log.txns.insert(txn, lsn);
+                log.first.entry(txn).or_insert(lsn);
+            }
+        }
+    }
+    lsn
+}
+
+/// The record at the start of `bytes` and its length, `None` unless it is whole and intact.
+fn parse_record(bytes: &[u8]) -> Option<(LogRecord, usize)> {
+    let (header, rest) = bytes.split_first_chunk::<RECORD_HEADER_LEN>()?;
+    let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+    let record = rest.get(..len)?;
+    if Crc32::checksum(record).to_le_bytes() != header[4..] {
+        return None;
+    }
+    Some((LogRecord::decode(record)?, RECORD_HEADER_LEN + len))
+}
+
+/// Reads the records of the log from `from` on, returns where the last intact one ends.
+fn read_log(
+    segments: &Segments,
+    from: Lsn,
+    mut f: impl FnMut(Lsn, LogRecord) -> Result<(), StorageError>,
+) -> Result<Lsn, StorageError> {
+    let mut reader = BufReader::new(SegmentReader {
+        segments,
+        lsn: from,
+        file: None,
+    });
+    let mut lsn = from;
+    let mut bytes = Vec::new();
+    loop {
+        let mut header = [0; RECORD_HEADER_LEN];
+        if reader.read_exact(&mut header).is_err() {
+            return Ok(lsn);
+        }
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        if len > 3 * PAGE_SIZE {
+            return Ok(lsn);
+        }
+        bytes.clear();
+        bytes.extend_from_slice(&header);
+        bytes.resize(RECORD_HEADER_LEN + len, 0);
+        if reader.read_exact(&mut bytes[RECORD_HEADER_LEN..]).is_err() {
+            return Ok(lsn);
+        }
+        let Some((record, len)) = parse_record(&bytes) else {
+            return Ok(lsn);
+        };
+        f(lsn, record)?;
+        lsn += len as Lsn;
+    }
+}
+
+/// The page a record changes, added to its file if the crash lost it, or `None` if its file is gone.
+fn fetch_for_recovery(pool: &BufferPool, page: PageId) -> Result<Option<PageWriteGuard>, StorageError> {
+    let count = match pool.files().page_count(page.file) {
+        Err(StorageError::UnknownFile(_)) => return Ok(None),
+        count => count?,
+    };
+    for _ in count..=page.page {
+        pool.files().allocate_page(page.file)?;
+    }
+    pool.fetch_write(page).map(Some)
+}
+
+fn apply(page: &mut PageWriteGuard, lsn: Lsn, offset: u16, bytes: &[u8]) -> Result<(), StorageError> {
+    let offset = offset as usize;
+    if offset + bytes.len() > PAGE_SIZE {
+        return Err(corrupt_log(lsn, "change past the end of the page"));
+    }
+    page.data_mut()[offset..offset + bytes.len()].copy_from_slice(bytes);
+    page.set_lsn(lsn);
+    Ok(())
+}
+
+fn corrupt_log(lsn: Lsn, message: &str) -> StorageError {
+    StorageError::CorruptLog(lsn, message.to_string())
+}
+
+/// Makes a file created or renamed in the directory survive a crash.
+fn sync_directory(directory: &Path) -> Result<(), StorageError> {
+    #[cfg(unix)]
+    File::open(directory)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = directory;
+    Ok(())
+}
+
+/// Ends the process without cleaning up when the tests ask for a crash here.
+#[cfg(test)]
+fn crash_point(name: &str) {
+    if std::env::var("RDB_CRASH_POINT").is_ok_and(|point| point == name) {
+        std::process::abort();
+    }
+}
+
+#[cfg(not(test))]
+fn crash_point(_name: &str) {}
+
+/// A transaction whose changes to pages are logged. It rolls back when dropped unless committed.
+pub struct Transaction {
+    wal: Arc<Wal>,
+    pool: BufferPool,
+    id: TxnId,
+    /// Last record of the transaction.
+    last: Lsn,
+    done: bool,
+}
+
+impl Transaction {
+    pub fn id(&self) -> TxnId {
+        self.id
+    }
+
+    /// Writes `bytes` at `offset` of the page, logging what was there.
+    pub fn write(&mut self, page: &mut PageWriteGuard, offset: usize, bytes: &[u8]) -> Result<Lsn, StorageError> {
+        if offset + bytes.len() > PAGE_SIZE {
+            return Err(StorageError::Corrupt(
+                page.id(),
+                format!("write of {0} bytes at {offset}", bytes.len()),
+            ));
+        }
+        // Dirty before the record goes in, so that the page is not thought to be clean up to it.
+        let before = page.data_mut()[offset..offset + bytes.len()].to_vec();
+        let lsn = self.wal.append(&LogRecord::Update {
+            txn: self.id,
+            prev: self.last,
+            page: page.id(),
+            offset: offset as u16,
+            before,
+            after: bytes.to_vec(),
+        });
+        apply(page, lsn, offset as u16, bytes)?;
+        self.last = lsn;
+        Ok(lsn)
+    }
+
+    /// Returns once the commit is on disk.
+    pub fn commit(mut self) -> Result<(), StorageError> {
+        self.done = true;
+        if self.last == 0 {
+            return Ok(());
+        }
+        let lsn = self.wal.append(&LogRecord::Commit {
+            txn: self.id,
+            prev: self.last,
+        });
+        crash_point("commit_logged");
+        self.wal.flush(lsn)?;
+        crash_point("commit_flushed");
+        Ok(())
+    }
+
+    /// Puts back what the transaction changed.
+    pub fn rollback(mut self) -> Result<(), StorageError> {
+        self.abort()
+    }
+
+    fn abort(&mut self) -> Result<(), StorageError> {
+        self.done = true;
+        if self.last == 0 {
+            return Ok(());
+        }
+        let mut next = self.wal.append(&LogRecord::Abort {
+            txn: self.id,
+            prev: self.last,
+        });
+        self.last = next;
+        while next != 0 {
+            next = self.wal.undo(&self.pool, self.id, &mut self.last, next)?;
+        }
+        self.wal.append(&LogRecord::End {
+            txn: self.id,
+            prev: self.last,
+        });
+        Ok(())
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = self.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::Config;
+    use crate::storage::FileId;
+    use crate::storage::buffer::EvictionPolicyKind;
+    use crate::storage::file::FileManager;
+    use crate::storage::testing::TempDir;
+
+    fn config(segment_size: u64) -> WalConfig {
+        WalConfig {
+            segment_size,
+            ..Config::default().wal
+        }
+    }
+
+    /// A pool and log in `directory`, recovered.
+    fn open(directory: &Path) -> (BufferPool, Arc<Wal>, Recovery) {
+        open_with(directory, &Config::default().wal)
+    }
+
+    fn open_with(directory: &Path, config: &WalConfig) -> (BufferPool, Arc<Wal>, Recovery) {
+        let files = Arc::new(FileManager::open(directory.join("base")).unwrap());
+        let pool = BufferPool::new(files, 16, EvictionPolicyKind::Clock);
+        let wal = Arc::new(Wal::open(directory.join("wal"), config).unwrap());
+        pool.set_wal(wal.clone());
+        let recovery = wal.recover(&pool).unwrap();
+        (pool, wal, recovery)
+    }
+
+    /// The first file of a new data directory, with `pages` pages.
+    fn data_file(pool: &BufferPool, pages: u32) -> FileId {
+        let file = pool.files().create_file().unwrap();
+        for _ in 0..pages {
+            pool.new_page(file).unwrap();
+        }
+        pool.flush_all().unwrap();
+        file
+    }
+
+    fn bytes_at(pool: &BufferPool, page: PageId, offset: usize, len: usize) -> Vec<u8> {
+        pool.fetch_read(page).unwrap().data()[offset..offset + len].to_vec()
+    }
+
+    #[test]
+    fn test_records() {
+        let dir = TempDir::new();
+        let page = PageId::new(3, 7);
+        let records = [
+            LogRecord::Update {
+                txn: 1,
+                prev: 0,
+                page,
+                offset: 100,
+                before: vec![0; 3],
+                after: b"abc".to_vec(),
+            },
+            LogRecord::Compensation {
+                txn: 1,
+                prev: 8,
+                page,
+                offset: 100,
+                after: vec![0; 3],
+                undo_next: 0,
+            },
+            LogRecord::Abort { txn: 1, prev: 8 },
+            LogRecord::End { txn: 1, prev: 60 },
+            LogRecord::Write {
+                page,
+                offset: 16,
+                after: b"def".to_vec(),
+            },
+            LogRecord::Checkpoint {
+                txns: vec![(2, 90)],
+                dirty: vec![(page, 8)],
+                next_txn: 3,
+            },
+            LogRecord::Commit { txn: 2, prev: 90 },
+        ];
+
+        let wal = Wal::open(dir.path(), &Config::default().wal).unwrap();
+        let lsns: Vec<Lsn> = records.iter().map(|record| wal.append(record)).collect();
+        assert_eq!(lsns[0], FIRST_LSN);
+        assert_eq!(wal.read(lsns[1]).unwrap(), records[1]);
+        wal.flush(lsns[3]).unwrap();
+        assert_eq!(wal.syncs(), 1);
+        assert_eq!(wal.read(lsns[1]).unwrap(), records[1]);
+        let end = wal.end();
+        let path = wal.segments.path(0);
+        drop(wal);
+
+        // A record cut short at the end is dropped, the next one takes its place.
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(&[40, 0, 0, 0, 1, 2]).unwrap();
+        let wal = Wal::open(dir.path(), &Config::default().wal).unwrap();
+        assert_eq!(wal.end(), end);
+        assert_eq!(wal.read(lsns[6]).unwrap(), records[6]);
+        assert!(wal.read(lsns[6] + 1).is_err());
+        let pool = BufferPool::new(
+            Arc::new(FileManager::open(dir.path().join("base")).unwrap()),
+            1,
+            EvictionPolicyKind::Clock,
+        );
+        assert_eq!(Arc::new(wal).begin(&pool).id(), 3);
+    }
+
+    #[test]
+    fn test_rollback() {
+        let dir = TempDir::new();
+        let (pool, wal, _) = open(dir.path());
+        let page = PageId::new(data_file(&pool, 1), 0);
+
+        let mut txn = wal.begin(&pool);
+        let mut guard = pool.fetch_write(page).unwrap();
+        txn.write(&mut guard, 100, b"first").unwrap();
+        txn.write(&mut guard, 102, b"second").unwrap();
+        drop(guard);
+        assert_eq!(bytes_at(&pool, page, 100, 8), b"fisecond");
+        txn.rollback().unwrap();
+        assert_eq!(bytes_at(&pool, page, 100, 8), vec![0; 8]);
+
+        // Dropped without a commit is rolled back too.
+        let mut txn = wal.begin(&pool);
+        txn.write(&mut pool.fetch_write(page).unwrap(), 100, b"dropped").unwrap();
+        drop(txn);
+        assert_eq!(bytes_at(&pool, page, 100, 7), vec![0; 7]);
+        let lsn = pool.fetch_read(page).unwrap().lsn();
+        assert!(matches!(wal.read(lsn).unwrap(), LogRecord::Compensation { undo_next: 0, .. }));
+    }
+
+    #[test]
+    fn test_group_commit() {
+        let dir = TempDir::new();
+        let (pool, wal, _) = open(dir.path());
+        let page = PageId::new(data_file(&pool, 1), 0);
+
+        // Commits logged before a sync share it.
+        let commits: Vec<Lsn> = (0..3)
+            .map(|txn| wal.append(&LogRecord::Commit { txn, prev: FIRST_LSN }))
+            .collect();
+        let syncs = wal.syncs();
+        wal.flush(commits[0]).unwrap();
+        wal.flush(commits[2]).unwrap();
+        assert_eq!(wal.syncs(), syncs + 1);
+
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let (pool, wal) = (&pool, &wal);
+                scope.spawn(move || {
+                    for n in 0..20 {
+                        let mut txn = wal.begin(pool);
+                        txn.write(&mut pool.fetch_write(page).unwrap(), 100 + thread, &[n]).unwrap();
+                        txn.commit().unwrap();
+                    }
+                });
+            }
+        });
+        assert!(wal.syncs() <= syncs + 1 + 160);
+        assert_eq!(bytes_at(&pool, page, 100, 8), vec![19; 8]);
+    }
+
+    #[test]
+    fn test_recovery() {
+        let dir = TempDir::new();
+        let (pool, wal, recovery) = open(dir.path());
+        assert_eq!(recovery, Recovery::default());
+        let file = data_file(&pool, 2);
+        let (first, second) = (PageId::new(file, 0), PageId::new(file, 1));
+
+        let mut committed = wal.begin(&pool);
+        committed.write(&mut pool.fetch_write(first).unwrap(), 100, b"kept").unwrap();
+        committed.commit().unwrap();
+        wal.checkpoint(&pool).unwrap();
+
+        // An unfinished transaction whose change reached the file, and a committed one whose change did not.
+        let mut unfinished = wal.begin(&pool);
+        unfinished.write(&mut pool.fetch_write(first).unwrap(), 200, b"lost").unwrap();
+        pool.flush_all().unwrap();
+        let mut committed = wal.begin(&pool);
+        committed
+            .write(&mut pool.fetch_write(second).unwrap(), 300, b"redone")
+            .unwrap();
+        committed.commit().unwrap();
+        let loser = unfinished.id();
+        std::mem::forget(unfinished);
+        drop((pool, wal));
+
+        let (pool, wal, recovery) = open(dir.path());
+        assert_eq!(recovery.rolled_back, vec![loser]);
+        assert!(recovery.redone >= 1);
+        assert_eq!(bytes_at(&pool, first, 100, 4), b"kept");
+        assert_eq!(bytes_at(&pool, first, 200, 4), vec![0; 4]);
+        assert_eq!(bytes_at(&pool, second, 300, 6), b"redone");
+
+        // Recovering again finds nothing left to do.
+        pool.flush_all().unwrap();
+        drop((pool, wal));
+        let (_, _, recovery) = open(dir.path());
+        assert_eq!(recovery, Recovery::default());
+    }
+
+    #[test]
+    fn test_page_writes() {
+        let dir = TempDir::new();
+        let (pool, wal, _) = open(dir.path());
+        let page = PageId::new(data_file(&pool, 1), 0);
+
+        // Changes close together are logged as one.
+        let start = wal.end();
+        let mut guard = pool.fetch_write(page).unwrap();
+        guard.data_mut()[100..105].copy_from_slice(b"close");
+        guard.data_mut()[120..126].copy_from_slice(b"enough");
+        guard.data_mut()[5000..5003].copy_from_slice(b"far");
+        drop(guard);
+        wal.flush(start).unwrap();
+        let mut records = Vec::new();
+        read_log(&wal.segments, start, |lsn, record| {
+            records.push((lsn, record));
+            Ok(())
+        })
+        .unwrap();
+        let written: Vec<(u16, usize)> = records
+            .iter()
+            .map(|(_, record)| match record {
+                LogRecord::Write { offset, after, .. } => (*offset, after.len()),
+                record => panic!("unexpected {record:?}"),
+            })
+            .collect();
+        assert_eq!(written, [(100, 26), (5000, 3)]);
+        assert_eq!(pool.fetch_read(page).unwrap().lsn(), records[1].0);
+
+        // Nothing is logged for a guard that changed nothing.
+        let end = wal.end();
+        pool.fetch_write(page).unwrap().data_mut();
+        assert_eq!(wal.end(), end);
+
+        // The pages never reached their file, recovery redoes the changes.
+        drop((pool, wal));
+        let (pool, _wal, recovery) = open(dir.path());
+        assert_eq!(recovery.redone, 2);
+        assert_eq!(bytes_at(&pool, page, 100, 26), b"close\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0enough");
+        assert_eq!(bytes_at(&pool, page, 5000, 3), b"far");
+    }
+
+    #[test]
+    fn test_segments() {
+        let dir = TempDir::new();
+        let (pool, wal, _) = open_with(dir.path(), &config(256));
+        let page = PageId::new(data_file(&pool, 1), 0);
+
+        // Records run across segments.
+        let mut last = 0;
+        for n in 0..20 {
+            let mut txn = wal.begin(&pool);
+            txn.write(&mut pool.fetch_write(page).unwrap(), 100, &[n; 100]).unwrap();
+            last = txn.id();
+            txn.commit().unwrap();
+        }
+        assert!(wal.segments.list().unwrap().len() > 10);
+
+        // A checkpoint removes the segments recovery no longer needs.
+        wal.checkpoint(&pool).unwrap();
+        let segments = wal.segments.list().unwrap();
+        assert!(segments.len() <= 2, "{segments:?}");
+        assert!(segments[0] > 0);
+
+        let mut txn = wal.begin(&pool);
+        txn.write(&mut pool.fetch_write(page).unwrap(), 300, b"after").unwrap();
+        txn.commit().unwrap();
+        drop((pool, wal));
+
+        // The segments keep the size the log was created with, and transaction ids go on from the checkpoint.
+        let (pool, wal, recovery) = open_with(dir.path(), &config(1 << 20));
+        assert_eq!(wal.segments.size, 256);
+        assert_eq!(recovery.redone, 1);
+        assert_eq!(bytes_at(&pool, page, 100, 100), vec![19; 100]);
+        assert_eq!(bytes_at(&pool, page, 300, 5), b"after");
+        assert!(wal.begin(&pool).id() > last + 1);
+    }
+
+    /// Runs a few transactions in the data directory the parent test gives, crashing where it says.
+    #[test]
+    fn test_crash_child() {
+        let Ok(dir) = std::env::var("RDB_CRASH_DIR") else {
+            return;
+        };
+        let (pool, wal, _) = open(Path::new(&dir));
+        let file = data_file(&pool, 2);
+        let (first, second) = (PageId::new(file, 0), PageId::new(file, 1));
+
+        let mut txn = wal.begin(&pool);
+        txn.write(&mut pool.fetch_write(first).unwrap(), 100, b"committed").unwrap();
+        txn.commit().unwrap();
+        // Crash points are armed once the first transaction is on disk.
+        unsafe { std::env::set_var("RDB_CRASH_POINT", std::env::var("RDB_CRASH_AT").unwrap()) };
+        wal.checkpoint(&pool).unwrap();
+
+        let mut txn = wal.begin(&pool);
+        txn.write(&mut pool.fetch_write(second).unwrap(), 100, b"rolled back")
+            .unwrap();
+        txn.write(&mut pool.fetch_write(first).unwrap(), 200, b"also").unwrap();
+        pool.flush_all().unwrap();
+        txn.rollback().unwrap();
+
+        let mut txn = wal.begin(&pool);
+        txn.write(&mut pool.fetch_write(second).unwrap(), 300, b"last").unwrap();
+        txn.commit().unwrap();
+        std::process::abort();
+    }
+
+    #[test]
+    fn test_crash_points() {
+        // Whether the last transaction survives a crash there.
+        let points = [
+            ("checkpoint", false),
+            ("undo", false),
+            ("commit_logged", false),
+            ("commit_flushed", true),
+        ];
+        for (point, last_survives) in points {
+            let dir = TempDir::new();
+            let status = std::process::Command::new(std::env::current_exe().unwrap())
+                .args([
+                    "--exact",
+                    "storage::wal::tests::test_crash_child",
+                    "--test-threads=1",
+                    "--nocapture",
+                ])
+                .env("RDB_CRASH_DIR", dir.path())
+                .env("RDB_CRASH_AT", point)
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status()
+                .unwrap();
+            assert!(!status.success(), "{point}");
+
+            let (pool, _wal, _) = open(dir.path());
+            let (first, second) = (PageId::new(1, 0), PageId::new(1, 1));
+            assert_eq!(bytes_at(&pool, first, 100, 9), b"committed", "{point}");
+            assert_eq!(bytes_at(&pool, first, 200, 4), vec![0; 4], "{point}");
+            assert_eq!(bytes_at(&pool, second, 100, 11), vec![0; 11], "{point}");
+            let last = if last_survives { b"last".to_vec() } else { vec![0; 4] };
+            assert_eq!(bytes_at(&pool, second, 300, 4), last, "{point}");
+        }
+    }
+}
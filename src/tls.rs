@@ -0,0 +1,353 @@
+//! TLS for client connections.
+//!
+//! Connections of both protocols start in plain text and switch to TLS when the client asks for it, PostgreSQL
+//! clients with an `SSLRequest` before the startup message and MySQL clients with an `SSLRequest` packet in place
+//! of the handshake response. The server only agrees when a certificate and key are configured.
+
+use crate::config::TlsConfig;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::server::TlsStream;
+
+#[derive(Debug)]
+pub enum TlsError {
+    /// A certificate or key file that cannot be read or holds nothing of its kind.
+    Pem(PathBuf, String),
+    /// The certificate and key do not belong together or are not supported.
+    Config(String),
+}
+
+impl Display for TlsError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            TlsError::Pem(path, message) => write!(f, "could not load {0}: {message}", path.display()),
+            TlsError::Config(message) => write!(f, "invalid certificate or key: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+/// Acceptor for the configured certificate and key, `None` when TLS is not configured.
+pub fn acceptor(config: &TlsConfig) -> Result<Option<TlsAcceptor>, TlsError> {
+    let (Some(certificate), Some(key)) = (&config.certificate, &config.key) else {
+        return Ok(None);
+    };
+
+    let chain = CertificateDer::pem_file_iter(certificate)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| TlsError::Pem(certificate.clone(), err.to_string()))?;
+    if chain.is_empty() {
+        return Err(TlsError::Pem(certificate.clone(), "no certificate found".to_string()));
+    }
+    let key = PrivateKeyDer::from_pem_file(key).map_err(|err| TlsError::Pem(key.clone(), err.to_string()))?;
+
+    let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|err| TlsError::Config(err.to_string()))?
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .map_err(|err| TlsError::Config(err.to_string()))?;
+    Ok(Some(TlsAcceptor::from(Arc::new(config))))
+}
+
+/// A client connection, encrypted once the client asked for it.
+pub enum MaybeTls<S> {
+    Plain(S),
+    Tls(Box<TlsStream<S>>),
+    /// Left behind by a failed upgrade, the connection is gone.
+    Closed,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> MaybeTls<S> {
+    pub fn is_tls(&self) -> bool {
+        matches!(self, MaybeTls::Tls(_))
+    }
+
+    /// Runs the TLS handshake on a plain text connection.
+    pub async fn upgrade(&mut self, acceptor: &TlsAcceptor) -> io::Result<()> {
+        match std::mem::replace(self, MaybeTls::Closed) {
+            MaybeTls::Plain(stream) => {
+                *self = MaybeTls::Tls(Box::new(acceptor.accept(stream).await?));
+                Ok(())
+            }
+            stream => {
+                *self = stream;
+                Err(io::Error::other("the connection is not in plain text"))
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for MaybeTls<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTls::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            MaybeTls::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+            MaybeTls::Closed => Poll::Ready(Err(io::ErrorKind::NotConnected.into())),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for MaybeTls<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            MaybeTls::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            MaybeTls::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+            MaybeTls::Closed => Poll::Ready(Err(io::ErrorKind::NotConnected.into())),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTls::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            MaybeTls::Tls(stream) => Pin::new(stream).poll_flush(cx),
+            MaybeTls::Closed => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTls::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            MaybeTls::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+            MaybeTls::Closed => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+/// Self-signed certificates for tests.
+#[cfg(test)]
+pub(crate) mod testing {
+
+    use super::*;
+    use crate::auth::crypto::base64_encode;
+    use ::ring::rand::SystemRandom;
+    use ::ring::signature::{ECDSA_P256_SHA256_ASN1_SIGNING, EcdsaKeyPair, KeyPair};
+    use rustls_pki_types::ServerName;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio_rustls::TlsConnector;
+    use tokio_rustls::client::TlsStream;
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+    const SEQUENCE: u8 = 0x30;
+    const SET: u8 = 0x31;
+    const INTEGER: u8 = 0x02;
+    const BIT_STRING: u8 = 0x03;
+    const OCTET_STRING: u8 = 0x04;
+    const OID: u8 = 0x06;
+    const UTF8_STRING: u8 = 0x0c;
+    const UTC_TIME: u8 = 0x17;
+
+    const ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+    const EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+    const PRIME256V1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+    const COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+    const SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+    fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut encoded = vec![tag];
+        match contents.len() {
+            len if len < 0x80 => encoded.push(len as u8),
+            len if len <= 0xff => encoded.extend([0x81, len as u8]),
+            len => encoded.extend([0x82, (len >> 8) as u8, len as u8]),
+        }
+        encoded.extend_from_slice(contents);
+        encoded
+    }
+
+    fn bit_string(bytes: &[u8]) -> Vec<u8> {
+        der(BIT_STRING, &[&[0], bytes].concat())
+    }
+
+    fn pem(label: &str, der: &[u8]) -> String {
+        let base64 = base64_encode(der);
+        let lines: Vec<&str> = base64
+            .as_bytes()
+            .chunks(64)
+            .map(|line| std::str::from_utf8(line).unwrap())
+            .collect();
+        format!("-----BEGIN {label}-----\n{0}\n-----END {label}-----\n", lines.join("\n"))
+    }
+
+    /// A new certificate for `localhost` signed by its own P-256 key, with the key in PKCS#8.
+    pub fn self_signed() -> (CertificateDer<'static>, Vec<u8>) {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+
+        let algorithm = der(SEQUENCE, &der(OID, ECDSA_WITH_SHA256));
+        let name = der(
+            SEQUENCE,
+            &der(
+                SET,
+                &der(SEQUENCE, &[der(OID, COMMON_NAME), der(UTF8_STRING, b"rdb test")].concat()),
+            ),
+        );
+        let validity = der(
+            SEQUENCE,
+            &[der(UTC_TIME, b"200101000000Z"), der(UTC_TIME, b"491231235959Z")].concat(),
+        );
+        let public_key = der(
+            SEQUENCE,
+            &[
+                der(SEQUENCE, &[der(OID, EC_PUBLIC_KEY), der(OID, PRIME256V1)].concat()),
+                bit_string(key.public_key().as_ref()),
+            ]
+            .concat(),
+        );
+        // dNSName is a context specific implicit tag.
+        let alt_names = der(SEQUENCE, &der(0x82, b"localhost"));
+        let extensions = der(
+            0xa3,
+            &der(
+                SEQUENCE,
+                &der(
+                    SEQUENCE,
+                    &[der(OID, SUBJECT_ALT_NAME), der(OCTET_STRING, &alt_names)].concat(),
+                ),
+            ),
+        );
+
+        let tbs = der(
+            SEQUENCE,
+            &[
+                der(0xa0, &der(INTEGER, &[2])),
+                der(INTEGER, &crate::auth::crypto::random_bytes::<8>().map(|b| b & 0x7f)),
+                algorithm.clone(),
+                name.clone(),
+                validity,
+                name,
+                public_key,
+                extensions,
+            ]
+            .concat(),
+        );
+        let signature = key.sign(&rng, &tbs).unwrap();
+        let certificate = der(SEQUENCE, &[tbs, algorithm, bit_string(signature.as_ref())].concat());
+
+        (CertificateDer::from(certificate), pkcs8.as_ref().to_vec())
+    }
+
+    /// Writes a new self-signed certificate and its key to temporary files, the certificate is returned for clients
+    /// to trust.
+    pub fn config() -> (TlsConfig, CertificateDer<'static>) {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let (certificate, key) = self_signed();
+
+        let prefix = format!("rdb-tls-{0}-{1}", std::process::id(), FILES.fetch_add(1, Ordering::Relaxed));
+        let certificate_path = std::env::temp_dir().join(format!("{prefix}.crt"));
+        let key_path = std::env::temp_dir().join(format!("{prefix}.key"));
+        std::fs::write(&certificate_path, pem("CERTIFICATE", &certificate)).unwrap();
+        std::fs::write(&key_path, pem("PRIVATE KEY", &key)).unwrap();
+
+        let config = TlsConfig {
+            certificate: Some(certificate_path),
+            key: Some(key_path),
+            required: false,
+        };
+        (config, certificate)
+    }
+
+    /// Runs the client side of the TLS handshake, trusting only `certificate`.
+    pub async fn connect<S: AsyncRead + AsyncWrite + Unpin>(
+        stream: S,
+        certificate: &CertificateDer<'static>,
+    ) -> io::Result<TlsStream<S>> {
+        let mut roots = RootCertStore::empty();
+        roots.add(certificate.clone()).unwrap();
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let name = ServerName::try_from("localhost").unwrap();
+        TlsConnector::from(Arc::new(config)).connect(name, stream).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_acceptor() {
+        assert!(acceptor(&TlsConfig::default()).unwrap().is_none());
+
+        let (config, _) = testing::config();
+        assert!(acceptor(&config).unwrap().is_some());
+
+        // The key of another certificate.
+        let (other, _) = testing::config();
+        let mismatched = TlsConfig {
+            key: other.key.clone(),
+            ..config.clone()
+        };
+        assert!(matches!(acceptor(&mismatched), Err(TlsError::Config(_))));
+
+        let swapped = TlsConfig {
+            certificate: config.key.clone(),
+            ..config.clone()
+        };
+        assert!(matches!(acceptor(&swapped), Err(TlsError::Pem(..))));
+
+        let missing = TlsConfig {
+            certificate: Some(PathBuf::from("/nonexistent/server.crt")),
+            ..config.clone()
+        };
+        let Err(err) = acceptor(&missing) else {
+            panic!("loaded a missing certificate");
+        };
+        assert!(err.to_string().starts_with("could not load /nonexistent/server.crt"));
+
+        for path in [config.certificate, config.key, other.certificate, other.key]
+            .into_iter()
+            .flatten()
+        {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upgrade() {
+        let (config, certificate) = testing::config();
+        let acceptor = acceptor(&config).unwrap().unwrap();
+        let (client, server) = tokio::io::duplex(4096);
+
+        let server = tokio::spawn(async move {
+            let mut stream = MaybeTls::Plain(server);
+            assert!(!stream.is_tls());
+            stream.upgrade(&acceptor).await.unwrap();
+            assert!(stream.is_tls());
+            assert!(stream.upgrade(&acceptor).await.is_err());
+
+            let mut message = [0; 5];
+            stream.read_exact(&mut message).await.unwrap();
+            stream.write_all(&message).await.unwrap();
+            stream.flush().await.unwrap();
+        });
+
+        let mut client = testing::connect(client, &certificate).await.unwrap();
+        client.write_all(b"hello").await.unwrap();
+        client.flush().await.unwrap();
+        let mut echoed = [0; 5];
+        client.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"hello");
+        server.await.unwrap();
+
+        std::fs::remove_file(config.certificate.unwrap()).unwrap();
+        std::fs::remove_file(config.key.unwrap()).unwrap();
+    }
+}
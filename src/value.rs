@@ -0,0 +1,222 @@
+//! Typed SQL values.
+
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    /// Every integer type up to BIGINT.
+    Int(i64),
+    /// FLOAT and DOUBLE.
+    Float(f64),
+    Decimal(Decimal),
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+}
+
+/// Canonical text form, bytes are rendered as `\x` followed by hex digits.
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Value::Null => write!(f, "NULL"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Int(n) => write!(f, "{n}"),
+            Value::Float(n) => write!(f, "{n}"),
+            Value::Decimal(n) => write!(f, "{n}"),
+            Value::Text(s) => write!(f, "{s}"),
+            Value::Bytes(bytes) => {
+                write!(f, "\\x")?;
+                bytes.iter().try_for_each(|b| write!(f, "{b:02x}"))
+            }
+        }
+    }
+}
+
+/// An exact decimal number, an integer scaled down by a number of fractional digits: 1.50 is 150 at scale 2.
+///
+/// Numbers compare by value, so 1.5 equals 1.50.
+#[derive(Clone, Copy, Debug)]
+pub struct Decimal {
+    value: i128,
+    scale: u8,
+}
+
+impl Decimal {
+    /// Most digits a decimal holds, as many as always fit in an `i128`.
+    pub const MAX_PRECISION: u8 = 38;
+
+    pub fn new(value: i128, scale: u8) -> Self {
+        debug_assert!(scale <= Self::MAX_PRECISION);
+        Decimal { value, scale }
+    }
+
+    /// The unscaled integer.
+    pub fn value(&self) -> i128 {
+        self.value
+    }
+
+    pub fn scale(&self) -> u8 {
+        self.scale
+    }
+
+    /// Number of digits of the unscaled integer, at least one.
+    pub fn digits(&self) -> u8 {
+        self.value.unsigned_abs().checked_ilog10().map_or(1, |log| log as u8 + 1)
+    }
+
+    /// The same number with `scale` fractional digits, rounded half away from zero. `None` when it does not fit.
+    pub fn rescale(&self, scale: u8) -> Option<Decimal> {
+        if scale > Self::MAX_PRECISION {
+            return None;
+        }
+        let value = match scale.cmp(&self.scale) {
+            Ordering::Equal => self.value,
+            Ordering::Greater => self.value.checked_mul(10i128.pow((scale - self.scale) as u32))?,
+            Ordering::Less => {
+                let divisor = 10i128.pow((self.scale - scale) as u32);
+                let (quotient, remainder) = (self.value / divisor, self.value % divisor);
+                match remainder.unsigned_abs() * 2 >= divisor.unsigned_abs() {
+                    true => quotient + self.value.signum(),
+                    false => quotient,
+                }
+            }
+        };
+        let decimal = Decimal { value, scale };
+        (decimal.digits() <= Self::MAX_PRECISION).then_some(decimal)
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.value as f64 / 10f64.powi(self.scale as i32)
+    }
+
+    /// The integer and fractional parts, the fraction scaled up to `scale` digits.
+    fn parts(&self, scale: u8) -> (i128, i128) {
+        let unit = 10i128.pow(self.scale as u32);
+        (
+            self.value / unit,
+            (self.value % unit) * 10i128.pow((scale - self.scale) as u32),
+        )
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(n: i64) -> Self {
+        Decimal::new(n as i128, 0)
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compares the integer parts, then the fractions at the larger of both scales, which cannot overflow.
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        self.parts(scale).cmp(&other.parts(scale))
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let digits = self.value.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        let digits = format!("{digits:0>0$}", scale + 1);
+        let (int_part, frac_part) = digits.split_at(digits.len() - scale);
+        let sign = if self.value < 0 { "-" } else { "" };
+        match scale {
+            0 => write!(f, "{sign}{int_part}"),
+            _ => write!(f, "{sign}{int_part}.{frac_part}"),
+        }
+    }
+}
+
+/// Parses `-12.50`, keeping every fractional digit given.
+impl FromStr for Decimal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid decimal: {s}");
+        let (negative, unsigned) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (int_part, frac_part) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if int_part.is_empty() && frac_part.is_empty() || !is_digits(int_part) || !is_digits(frac_part) {
+            return Err(invalid());
+        }
+
+        let digits = format!("{int_part}{frac_part}");
+        let digits = digits.trim_start_matches('0');
+        if digits.len() > Self::MAX_PRECISION as usize || frac_part.len() > Self::MAX_PRECISION as usize {
+            return Err(format!("decimal has more than {0} digits: {s}", Self::MAX_PRECISION));
+        }
+        let value: i128 = match digits {
+            "" => 0,
+            digits => digits.parse().map_err(|_| invalid())?,
+        };
+        Ok(Decimal::new(if negative { -value } else { value }, frac_part.len() as u8))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Value::Int(-3).to_string(), "-3");
+        assert_eq!(Value::Float(2.5).to_string(), "2.5");
+        assert_eq!(Value::Bytes(vec![0xde, 0x0a]).to_string(), "\\xde0a");
+        assert_eq!(Value::Null.to_string(), "NULL");
+        assert_eq!(Value::Decimal(Decimal::new(-5, 2)).to_string(), "-0.05");
+    }
+
+    #[test]
+    fn test_decimal() {
+        let parse = |s: &str| s.parse::<Decimal>().unwrap();
+        assert_eq!(parse("12.50").to_string(), "12.50");
+        assert_eq!(parse("-.5").to_string(), "-0.5");
+        assert_eq!(parse("+007").to_string(), "7");
+        assert_eq!(parse("0.000").digits(), 1);
+        assert_eq!(parse("-123.45").digits(), 5);
+        assert!("1.2.3".parse::<Decimal>().is_err());
+        assert!("-".parse::<Decimal>().is_err());
+        assert!("1".repeat(39).parse::<Decimal>().is_err());
+        assert_eq!(parse(&"9".repeat(38)).value(), 10i128.pow(38) - 1);
+
+        // Rounding is half away from zero.
+        assert_eq!(parse("2.345").rescale(2).unwrap().to_string(), "2.35");
+        assert_eq!(parse("-2.345").rescale(2).unwrap().to_string(), "-2.35");
+        assert_eq!(parse("2.344").rescale(0).unwrap().to_string(), "2");
+        assert_eq!(parse("1.5").rescale(4).unwrap().to_string(), "1.5000");
+        assert_eq!(parse(&"9".repeat(30)).rescale(9), None);
+
+        assert_eq!(parse("1.5"), parse("1.500"));
+        assert!(parse("-1.5") < parse("-1.49"));
+        assert!(parse("10") > parse("9.999999"));
+        assert_eq!(parse("0.25").to_f64(), 0.25);
+    }
+}